@@ -0,0 +1,122 @@
+/// benchmarks of the ebo obfuscator: parsing, cfg construction, every pass of the default pipeline
+/// on its own, and the whole pipeline, each on a small, a medium, and a large contract.
+///
+/// the contracts are generated: a selector dispatcher over `n` external functions, each storing the
+/// sum of two constants a few times, so they have the shape of compiled solidity and grow with `n`
+/// without depending on a compiler. run with `cargo bench`, or `cargo bench -- substitution` for
+/// the benchmarks of one pass.
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use ebo::cfg::Cfg;
+use ebo::passes::{Ctx, Pipeline};
+use ebo::reloc::Program;
+use ebo::{disassemble, parse_bytecode, Obfuscator};
+
+/// the contracts, by name and number of external functions.
+const FIXTURES: [(&str, usize); 3] = [("small", 4), ("medium", 40), ("large", 300)];
+
+/// number of stores in the body of every function.
+const STORES: usize = 8;
+
+/// a dispatcher over `functions` external functions.
+fn contract(functions: usize) -> Vec<u8> {
+    // PUSH1 0, CALLDATALOAD, PUSH1 0xe0, SHR
+    let mut code = vec![0x60, 0x00, 0x35, 0x60, 0xe0, 0x1c];
+    // every case is DUP1, PUSH4 selector, EQ, PUSH2 body, JUMPI; the fallback PUSH1 0, DUP1, REVERT
+    let bodies = code.len() + functions * 11 + 4;
+    let body = 1 + STORES * 8 + 1;
+    for idx in 0..functions {
+        let selector = (idx as u32).wrapping_mul(0x9e37_79b9).to_be_bytes();
+        let target = ((bodies + idx * body) as u16).to_be_bytes();
+        code.push(0x80);
+        code.push(0x63);
+        code.extend(selector);
+        code.push(0x14);
+        code.push(0x61);
+        code.extend(target);
+        code.push(0x57);
+    }
+    code.extend([0x60, 0x00, 0x80, 0xfd]);
+    for idx in 0..functions {
+        // JUMPDEST, then PUSH1 a, PUSH1 b, ADD, PUSH1 slot, SSTORE per store, then STOP
+        code.push(0x5b);
+        for store in 0..STORES {
+            code.extend([
+                0x60,
+                idx as u8,
+                0x60,
+                store as u8,
+                0x01,
+                0x60,
+                store as u8,
+                0x55,
+            ]);
+        }
+        code.push(0x00);
+    }
+    code
+}
+
+fn parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, functions) in FIXTURES {
+        let bytecode = contract(functions);
+        group.bench_with_input(BenchmarkId::new("blocks", name), &bytecode, |b, code| {
+            b.iter(|| parse_bytecode(code))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("disassemble", name),
+            &bytecode,
+            |b, code| b.iter(|| disassemble(code)),
+        );
+        group.bench_with_input(BenchmarkId::new("lift", name), &bytecode, |b, code| {
+            b.iter(|| Program::lift(code))
+        });
+        group.bench_with_input(BenchmarkId::new("cfg", name), &bytecode, |b, code| {
+            b.iter(|| Cfg::new(code))
+        });
+    }
+    group.finish();
+}
+
+/// every pass of the default pipeline alone, with the size and validity checks the pipeline runs
+/// around it, so a pass added to the pipeline is benchmarked without being listed here.
+fn passes(c: &mut Criterion) {
+    for pass in Pipeline::default().names() {
+        let mut group = c.benchmark_group(pass);
+        for (name, functions) in FIXTURES {
+            let program = Program::lift(&contract(functions));
+            group.bench_function(name, |b| {
+                b.iter_batched(
+                    || {
+                        let others = Pipeline::default().names();
+                        let pipeline = others
+                            .into_iter()
+                            .filter(|other| *other != pass)
+                            .fold(Pipeline::default(), |pipeline, other| {
+                                pipeline.without(other)
+                            });
+                        (pipeline, program.clone(), Ctx::new(42))
+                    },
+                    |(mut pipeline, mut program, mut ctx)| pipeline.run(&mut program, &mut ctx),
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+fn pipeline(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pipeline");
+    group.sample_size(10);
+    for (name, functions) in FIXTURES {
+        let bytecode = contract(functions);
+        group.bench_with_input(BenchmarkId::new("default", name), &bytecode, |b, code| {
+            b.iter(|| Obfuscator::new(code, 42).obfuscate().unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parsing, passes, pipeline);
+criterion_main!(benches);
@@ -0,0 +1,19 @@
+// runs against the package built by `npm run build`
+const test = require("node:test");
+const assert = require("node:assert");
+const { obfuscate } = require("./pkg/ebo.js");
+
+// PUSH1 1, PUSH1 2, ADD, STOP
+const code = "0x600160020100";
+
+test("obfuscates with the seed of the configuration", () => {
+  const obfuscated = obfuscate(code, "seed = 7");
+  assert.match(obfuscated, /^0x([0-9a-f]{2})+$/);
+  assert.notStrictEqual(obfuscated, code);
+  assert.strictEqual(obfuscate(code, "seed = 7"), obfuscated);
+});
+
+test("raises errors as messages", () => {
+  assert.throws(() => obfuscate("0xzz", ""), /hex/i);
+  assert.throws(() => obfuscate(code, "rounds = \"two\""));
+});
@@ -0,0 +1,20 @@
+//! decoding arbitrary bytes: every byte belongs to exactly one instruction, and the basic blocks
+//! and metrics built on top never panic.
+#![no_main]
+
+use ebo::evm::{compute_cfg_complexity, disassemble, parse_bytecode, push_size};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let instructions = disassemble(data);
+    let mut offset = 0;
+    for ins in &instructions {
+        assert_eq!(ins.offset, offset);
+        assert!(ins.immediate.len() <= push_size(ins.opcode));
+        offset += 1 + ins.immediate.len();
+    }
+    assert_eq!(offset, data.len());
+
+    let blocks = parse_bytecode(data);
+    compute_cfg_complexity(&blocks);
+});
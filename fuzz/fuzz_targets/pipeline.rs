@@ -0,0 +1,33 @@
+//! the full default pipeline on arbitrary bytes: it never panics or fails, and the output has no
+//! validation issue the input did not already have.
+#![no_main]
+
+use ebo::evm::{Fork, MAX_CODE_SIZE};
+use ebo::obfuscator::Obfuscator;
+use ebo::validate::{introduced, validate};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // the first byte picks the seed, the rest is the bytecode
+    let Some((&seed, bytecode)) = data.split_first() else {
+        return;
+    };
+    if bytecode.len() > MAX_CODE_SIZE {
+        return;
+    }
+    let output = match Obfuscator::new(bytecode, seed as u64).obfuscate() {
+        Ok(output) => output,
+        Err(err) => panic!("seed {} failed on {:02x?}: {}", seed, bytecode, err),
+    };
+    let issues = introduced(
+        &validate(bytecode, Fork::default()),
+        &validate(&output, Fork::default()),
+    );
+    assert!(
+        issues.is_empty(),
+        "seed {} introduced {:?} into {:02x?}",
+        seed,
+        issues,
+        bytecode
+    );
+});
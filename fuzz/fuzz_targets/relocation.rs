@@ -0,0 +1,10 @@
+//! the relocation layer on arbitrary bytes: a lifted program that is left untouched assembles back
+//! to the input byte for byte.
+#![no_main]
+
+use ebo::reloc::Program;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    assert_eq!(Program::lift(data).assemble().unwrap(), data);
+});
@@ -0,0 +1,63 @@
+/*
+ * c interface of the ebo evm bytecode obfuscator.
+ * build the library with `cargo rustc --release --lib --features ebo-ffi --crate-type cdylib`
+ * (or `--crate-type staticlib`) and link against it.
+ *
+ * functions return EBO_OK on success and otherwise the code the cli exits with for the same
+ * failure; the message of the last failure on the calling thread is available from
+ * ebo_last_error().
+ */
+#ifndef EBO_H
+#define EBO_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define EBO_OK 0
+#define EBO_ERROR 1
+#define EBO_INVALID_ARGUMENT 2
+#define EBO_PARSE_ERROR 3
+#define EBO_UNSUPPORTED_OPCODE 4
+#define EBO_RELOCATION_ERROR 5
+#define EBO_SIZE_LIMIT 6
+#define EBO_INVALID_OUTPUT 7
+#define EBO_VERIFICATION_ERROR 8
+
+/* settings of a run. */
+typedef struct EboConfig {
+    /* seed of the run. */
+    uint64_t seed;
+    /* contents of an ebo.toml (nul-terminated utf-8), or NULL for the defaults. its seed is
+     * ignored in favor of the field above. */
+    const char *toml;
+} EboConfig;
+
+/* bytes owned by the library, released with ebo_buffer_free(). */
+typedef struct EboBuffer {
+    uint8_t *data;
+    size_t len;
+} EboBuffer;
+
+/* obfuscates `len` bytes of runtime code at `bytecode` with the checks `ebo obfuscate` makes.
+ * `config` may be NULL for the defaults with seed 42. on success the obfuscated code is written
+ * to `out`, which must then be released with ebo_buffer_free(). */
+int32_t ebo_obfuscate(const uint8_t *bytecode, size_t len, const EboConfig *config, EboBuffer *out);
+
+/* releases a buffer filled in by the library and empties it. */
+void ebo_buffer_free(EboBuffer *buffer);
+
+/* message of the last failure on the calling thread, or NULL. valid until the next failure. */
+const char *ebo_last_error(void);
+
+/* version of the library. */
+const char *ebo_version(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* EBO_H */
@@ -0,0 +1,322 @@
+/// solidity abi support for the ebo obfuscator.
+/// reads the functions of a contract from its json abi and encodes random arguments for them, so
+/// differential fuzzing can reach code behind the selector dispatcher and the argument decoding.
+use crate::error::EboError;
+use anyhow::{bail, Context};
+use rand::Rng;
+use revm::primitives::alloy_primitives::keccak256;
+use serde::Deserialize;
+
+/// an abi type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind {
+    /// `uint<bits>`.
+    Uint(usize),
+    /// `int<bits>`.
+    Int(usize),
+    Address,
+    Bool,
+    /// `bytes<n>`, with 1 <= n <= 32.
+    FixedBytes(usize),
+    Bytes,
+    String,
+    /// `T[]`.
+    Array(Box<Kind>),
+    /// `T[k]`.
+    FixedArray(Box<Kind>, usize),
+    /// `(T1, T2, ...)`.
+    Tuple(Vec<Kind>),
+}
+
+/// an external function of the abi.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// function name.
+    pub name: String,
+    /// argument types, in order.
+    pub inputs: Vec<Kind>,
+}
+
+/// a parameter as it appears in the json abi.
+#[derive(Deserialize)]
+struct Param {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    components: Vec<Param>,
+}
+
+/// an entry of the json abi. only functions are kept.
+#[derive(Deserialize)]
+struct Entry {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    inputs: Vec<Param>,
+}
+
+/// a json abi: either the bare array, or a compiler artifact (foundry, hardhat) holding it under
+/// `abi`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Document {
+    Abi(Vec<Entry>),
+    Artifact { abi: Vec<Entry> },
+}
+
+impl Kind {
+    /// parses a type name such as `uint256`, `bytes32[]`, or `tuple[2]`; `components` gives the
+    /// members of tuple types.
+    fn parse(name: &str, components: &[Param]) -> anyhow::Result<Self> {
+        if let Some(inner) = name.strip_suffix(']') {
+            let Some((element, size)) = inner.rsplit_once('[') else {
+                bail!("invalid abi type {:?}", name);
+            };
+            let element = Box::new(Kind::parse(element, components)?);
+            return Ok(if size.is_empty() {
+                Kind::Array(element)
+            } else {
+                let size = size
+                    .parse()
+                    .with_context(|| format!("invalid array size in {:?}", name))?;
+                Kind::FixedArray(element, size)
+            });
+        }
+        let bits = |digits: &str, default: usize| -> anyhow::Result<usize> {
+            let bits = if digits.is_empty() {
+                default
+            } else {
+                digits.parse()?
+            };
+            if bits == 0 || bits > 256 || bits % 8 != 0 {
+                bail!("invalid abi type {:?}", name);
+            }
+            Ok(bits)
+        };
+        Ok(match name {
+            "address" => Kind::Address,
+            "bool" => Kind::Bool,
+            "bytes" => Kind::Bytes,
+            "string" => Kind::String,
+            "tuple" => Kind::Tuple(
+                components
+                    .iter()
+                    .map(|c| Kind::parse(&c.kind, &c.components))
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            _ if name.starts_with("uint") => Kind::Uint(bits(&name[4..], 256)?),
+            _ if name.starts_with("int") => Kind::Int(bits(&name[3..], 256)?),
+            _ if name.starts_with("bytes") => match name[5..].parse() {
+                Ok(n @ 1..=32) => Kind::FixedBytes(n),
+                _ => bail!("invalid abi type {:?}", name),
+            },
+            _ => bail!("unsupported abi type {:?}", name),
+        })
+    }
+
+    /// canonical type name, as used in function signatures.
+    pub fn signature(&self) -> String {
+        match self {
+            Kind::Uint(bits) => format!("uint{}", bits),
+            Kind::Int(bits) => format!("int{}", bits),
+            Kind::Address => "address".into(),
+            Kind::Bool => "bool".into(),
+            Kind::FixedBytes(n) => format!("bytes{}", n),
+            Kind::Bytes => "bytes".into(),
+            Kind::String => "string".into(),
+            Kind::Array(element) => format!("{}[]", element.signature()),
+            Kind::FixedArray(element, size) => format!("{}[{}]", element.signature(), size),
+            Kind::Tuple(members) => format!(
+                "({})",
+                members
+                    .iter()
+                    .map(Kind::signature)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        }
+    }
+
+    /// returns true if values of this type are encoded out of place, behind an offset.
+    fn is_dynamic(&self) -> bool {
+        match self {
+            Kind::Bytes | Kind::String | Kind::Array(_) => true,
+            Kind::FixedArray(element, _) => element.is_dynamic(),
+            Kind::Tuple(members) => members.iter().any(Kind::is_dynamic),
+            _ => false,
+        }
+    }
+}
+
+impl Function {
+    /// canonical signature, e.g. `transfer(address,uint256)`.
+    pub fn signature(&self) -> String {
+        format!(
+            "{}{}",
+            self.name,
+            Kind::Tuple(self.inputs.clone()).signature()
+        )
+    }
+
+    /// first four bytes of the keccak-256 hash of the signature.
+    pub fn selector(&self) -> [u8; 4] {
+        let hash = keccak256(self.signature());
+        std::array::from_fn(|i| hash[i])
+    }
+
+    /// calldata calling this function with random arguments.
+    pub fn random_call(&self, rng: &mut impl Rng) -> Vec<u8> {
+        let mut calldata = self.selector().to_vec();
+        calldata.extend(encode_random(&self.inputs, rng));
+        calldata
+    }
+}
+
+/// parses the functions of a json abi, or of a compiler artifact that contains one.
+///
+/// # arguments
+/// * `json` - contents of the abi file.
+///
+/// # returns
+/// the functions, in the order the abi lists them.
+pub fn parse(json: &str) -> anyhow::Result<Vec<Function>> {
+    let document: Document =
+        serde_json::from_str(json).map_err(|err| EboError::parse("json abi", err))?;
+    let entries = match document {
+        Document::Abi(entries) | Document::Artifact { abi: entries } => entries,
+    };
+    entries
+        .into_iter()
+        .filter(|entry| entry.kind == "function")
+        .map(|entry| {
+            let inputs = entry
+                .inputs
+                .iter()
+                .map(|p| Kind::parse(&p.kind, &p.components))
+                .collect::<anyhow::Result<_>>()
+                .with_context(|| format!("in function {}", entry.name))?;
+            Ok(Function {
+                name: entry.name,
+                inputs,
+            })
+        })
+        .collect()
+}
+
+/// finds a function by its signature (`transfer(address,uint256)`) or, if no other function
+/// shares it, its name (`transfer`).
+pub fn find<'a>(functions: &'a [Function], name: &str) -> anyhow::Result<&'a Function> {
+    if let Some(function) = functions.iter().find(|f| f.signature() == name) {
+        return Ok(function);
+    }
+    let named: Vec<_> = functions.iter().filter(|f| f.name == name).collect();
+    match named[..] {
+        [function] => Ok(function),
+        [] => bail!("the abi has no function '{}'", name),
+        _ => bail!(
+            "'{}' is overloaded; name one of {}",
+            name,
+            named
+                .iter()
+                .map(|f| f.signature())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// abi-encodes random values of the types `kinds`, as a tuple: static values in place and dynamic
+/// ones behind offsets into a tail.
+fn encode_random(kinds: &[Kind], rng: &mut impl Rng) -> Vec<u8> {
+    let head_len: usize = kinds.iter().map(head_size).sum();
+    let mut head = Vec::with_capacity(head_len);
+    let mut tail = Vec::new();
+    for kind in kinds {
+        let value = encode_value(kind, rng);
+        if kind.is_dynamic() {
+            head.extend(word(head_len + tail.len()));
+            tail.extend(value);
+        } else {
+            head.extend(value);
+        }
+    }
+    head.extend(tail);
+    head
+}
+
+/// bytes a value of type `kind` takes in the head of a tuple.
+fn head_size(kind: &Kind) -> usize {
+    match kind {
+        _ if kind.is_dynamic() => 32,
+        Kind::FixedArray(element, size) => head_size(element) * size,
+        Kind::Tuple(members) => members.iter().map(head_size).sum(),
+        _ => 32,
+    }
+}
+
+/// `value` as a big-endian 32-byte word.
+fn word(value: usize) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[24..].copy_from_slice(&(value as u64).to_be_bytes());
+    word
+}
+
+/// a random 32-byte word holding an unsigned value of `bits` bits. edge values (zero, one, the
+/// maximum) come up more often than chance, since that is where code tends to branch.
+fn random_uint(bits: usize, rng: &mut impl Rng) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    match rng.gen_range(0..8) {
+        0 => {}
+        1 => word[31] = 1,
+        2 => word[32 - bits / 8..].fill(0xff),
+        3 => word[31] = rng.gen(),
+        _ => rng.fill(&mut word[32 - bits / 8..]),
+    }
+    word
+}
+
+/// encoding of a random value of type `kind`, as it appears in the head (static types) or tail
+/// (dynamic types) of the enclosing tuple.
+fn encode_value(kind: &Kind, rng: &mut impl Rng) -> Vec<u8> {
+    match kind {
+        Kind::Uint(bits) => random_uint(*bits, rng).to_vec(),
+        Kind::Int(bits) => {
+            let mut word = random_uint(*bits, rng);
+            // sign-extend from the top bit of the value
+            if word[32 - bits / 8] & 0x80 != 0 {
+                word[..32 - bits / 8].fill(0xff);
+            }
+            word.to_vec()
+        }
+        Kind::Address => {
+            let mut word = [0u8; 32];
+            rng.fill(&mut word[12..]);
+            word.to_vec()
+        }
+        Kind::Bool => word(rng.gen_range(0..2)).to_vec(),
+        Kind::FixedBytes(n) => {
+            let mut word = [0u8; 32];
+            rng.fill(&mut word[..*n]);
+            word.to_vec()
+        }
+        Kind::Bytes | Kind::String => {
+            let len: usize = rng.gen_range(0..=64);
+            let mut data: Vec<u8> = if *kind == Kind::String {
+                (0..len).map(|_| rng.gen_range(b' '..=b'~')).collect()
+            } else {
+                (0..len).map(|_| rng.gen()).collect()
+            };
+            data.resize(len.div_ceil(32) * 32, 0);
+            [word(len).to_vec(), data].concat()
+        }
+        Kind::Array(element) => {
+            let len = rng.gen_range(0..=4);
+            let elements = vec![(**element).clone(); len];
+            [word(len).to_vec(), encode_random(&elements, rng)].concat()
+        }
+        Kind::FixedArray(element, size) => encode_random(&vec![(**element).clone(); *size], rng),
+        Kind::Tuple(members) => encode_random(members, rng),
+    }
+}
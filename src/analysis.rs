@@ -0,0 +1,459 @@
+/// static analysis of a single bytecode for the ebo obfuscator.
+/// everything ebo measures about a contract, without obfuscating it: the size and complexity
+/// metrics of the report, the external functions of the dispatcher, the static gas of the paths
+/// through the code, and the compiler metadata appended to it. useful before obfuscation to pick
+/// settings, and on third-party contracts to see what they were built with.
+use crate::dispatcher;
+use crate::evm::{disassemble, mnemonic, ngram_similarity, op};
+use crate::gas::Baseline;
+use crate::reloc::Program;
+use crate::summary::{Metrics, NGRAM_SIZES};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Range;
+
+/// an external function found in the dispatcher.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Selector {
+    /// 4-byte function selector, as `0x`-prefixed hex.
+    pub selector: String,
+    /// offset of the jumpdest the dispatcher jumps to for this selector.
+    pub entry: usize,
+    /// number of basic blocks reachable from the entry.
+    pub blocks: usize,
+}
+
+/// static gas of the paths through the code, without dynamic costs (see `gas::Baseline`).
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct Gas {
+    /// number of paths measured.
+    pub paths: usize,
+    /// gas of the cheapest path.
+    pub min: u64,
+    /// gas of the most expensive path.
+    pub max: u64,
+}
+
+/// the cbor metadata solc appends to runtime code.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Metadata {
+    /// byte range of the metadata, including its two-byte length.
+    pub range: Range<usize>,
+    /// keys of the metadata map, e.g. `ipfs` and `solc`.
+    pub keys: Vec<String>,
+    /// version of the compiler, if recorded.
+    pub solc: Option<String>,
+}
+
+/// what `ebo analyze` reports about a bytecode.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Analysis {
+    /// size and complexity metrics.
+    pub metrics: Metrics,
+    /// external functions in order of their entry offset.
+    pub functions: Vec<Selector>,
+    /// static gas of the paths through the code.
+    pub gas: Gas,
+    /// appended compiler metadata, if any.
+    pub metadata: Option<Metadata>,
+}
+
+impl Analysis {
+    /// analyzes `bytecode`.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::analysis::Analysis;
+    ///
+    /// let analysis = Analysis::new(&[0x60, 0x01, 0x60, 0x02, 0x01, 0x00]);
+    /// assert_eq!((analysis.gas.paths, analysis.gas.max), (1, 9));
+    /// assert!(analysis.functions.is_empty() && analysis.metadata.is_none());
+    /// ```
+    pub fn new(bytecode: &[u8]) -> Self {
+        let functions = dispatcher::functions(bytecode)
+            .into_iter()
+            .map(|function| Selector {
+                selector: format!("0x{}", hex::encode(function.selector)),
+                entry: function.entry,
+                blocks: function.blocks.len(),
+            })
+            .collect();
+        let baseline = Baseline::new(&Program::lift(bytecode));
+        let costs = baseline.costs();
+        let gas = Gas {
+            paths: baseline.paths(),
+            min: costs.iter().copied().min().unwrap_or(0),
+            max: costs.iter().copied().max().unwrap_or(0),
+        };
+        Analysis {
+            metrics: Metrics::measure(bytecode),
+            functions,
+            gas,
+            metadata: metadata(bytecode),
+        }
+    }
+
+    /// the analysis as pretty-printed json.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("an analysis always serializes")
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let metrics = &self.metrics;
+        writeln!(f, "size:         {} bytes", metrics.size)?;
+        writeln!(f, "sha256:       {}", metrics.sha256)?;
+        writeln!(
+            f,
+            "cfg:          {} blocks, {} edges, {} conditional, block size {:.1} (max {})",
+            metrics.cfg.nodes,
+            metrics.cfg.edges,
+            metrics.cfg_complexity,
+            metrics.cfg.average_block_size,
+            metrics.cfg.max_block_size
+        )?;
+        writeln!(
+            f,
+            "halstead:     volume {:.0}, difficulty {:.1}, effort {:.0}",
+            metrics.halstead.volume, metrics.halstead.difficulty, metrics.halstead.effort
+        )?;
+        writeln!(
+            f,
+            "entropy:      {:.3} bits per byte, {:.3} bits per opcode, compression ratio {:.3}",
+            metrics.entropy, metrics.opcode_entropy, metrics.compression_ratio
+        )?;
+        writeln!(
+            f,
+            "gas:          {}..{} over {} paths (static costs only)",
+            self.gas.min, self.gas.max, self.gas.paths
+        )?;
+        match &self.metadata {
+            Some(metadata) => writeln!(
+                f,
+                "metadata:     {} bytes at {:#06x} ({}{})",
+                metadata.range.len(),
+                metadata.range.start,
+                metadata.keys.join(", "),
+                metadata
+                    .solc
+                    .as_ref()
+                    .map_or(String::new(), |version| format!("; solc {}", version))
+            )?,
+            None => writeln!(f, "metadata:     none")?,
+        }
+        writeln!(f, "functions:    {}", self.functions.len())?;
+        for function in &self.functions {
+            writeln!(
+                f,
+                "  {}  entry {:#06x}, {} blocks",
+                function.selector, function.entry, function.blocks
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// two bytecodes analyzed side by side, for `ebo compare`. neither has to come from ebo.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Comparison {
+    /// analysis of the first bytecode.
+    pub left: Analysis,
+    /// analysis of the second bytecode.
+    pub right: Analysis,
+    /// jaccard similarity of the opcode n-grams of the two, keyed by n (see `NGRAM_SIZES`).
+    pub ngram_similarity: BTreeMap<usize, f64>,
+    /// jaccard similarity of the selectors their dispatchers match; 1 if neither has any.
+    pub selector_similarity: f64,
+}
+
+impl Comparison {
+    /// analyzes and compares `left` and `right`.
+    pub fn new(left: &[u8], right: &[u8]) -> Self {
+        let ngram_similarity = NGRAM_SIZES
+            .map(|n| (n, ngram_similarity(left, right, n)))
+            .collect();
+        let (left, right) = (Analysis::new(left), Analysis::new(right));
+        let selectors = |analysis: &Analysis| -> BTreeSet<String> {
+            analysis
+                .functions
+                .iter()
+                .map(|f| f.selector.clone())
+                .collect()
+        };
+        let (a, b) = (selectors(&left), selectors(&right));
+        let union = a.union(&b).count();
+        let selector_similarity = match union {
+            0 => 1.0,
+            _ => a.intersection(&b).count() as f64 / union as f64,
+        };
+        Comparison {
+            left,
+            right,
+            ngram_similarity,
+            selector_similarity,
+        }
+    }
+
+    /// the comparison as pretty-printed json.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a comparison always serializes")
+    }
+}
+
+impl fmt::Display for Comparison {
+    /// one line per metric with both values and the change from left to right.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (left, right) = (&self.left, &self.right);
+        let (a, b) = (&left.metrics, &right.metrics);
+        let rows: [(&str, f64, f64); 12] = [
+            ("size", a.size as f64, b.size as f64),
+            ("blocks", a.cfg.nodes as f64, b.cfg.nodes as f64),
+            ("edges", a.cfg.edges as f64, b.cfg.edges as f64),
+            (
+                "conditional",
+                a.cfg_complexity as f64,
+                b.cfg_complexity as f64,
+            ),
+            (
+                "max block size",
+                a.cfg.max_block_size as f64,
+                b.cfg.max_block_size as f64,
+            ),
+            ("halstead volume", a.halstead.volume, b.halstead.volume),
+            ("halstead effort", a.halstead.effort, b.halstead.effort),
+            ("byte entropy", a.entropy, b.entropy),
+            ("opcode entropy", a.opcode_entropy, b.opcode_entropy),
+            (
+                "compression ratio",
+                a.compression_ratio,
+                b.compression_ratio,
+            ),
+            ("max path gas", left.gas.max as f64, right.gas.max as f64),
+            (
+                "functions",
+                left.functions.len() as f64,
+                right.functions.len() as f64,
+            ),
+        ];
+        writeln!(
+            f,
+            "{:<17}  {:>12}  {:>12}  {:>9}",
+            "metric", "left", "right", "change"
+        )?;
+        for (name, a, b) in rows {
+            // counts print as integers, measures with three decimals
+            let value = |v: f64| match v.fract() {
+                0.0 => format!("{:.0}", v),
+                _ => format!("{:.3}", v),
+            };
+            let change = match a {
+                0.0 => "-".to_string(),
+                _ => format!("{:+.1}%", (b / a - 1.0) * 100.0),
+            };
+            writeln!(
+                f,
+                "{:<17}  {:>12}  {:>12}  {:>9}",
+                name,
+                value(a),
+                value(b),
+                change
+            )?;
+        }
+        let compiler = |analysis: &Analysis| match &analysis.metadata {
+            Some(Metadata {
+                solc: Some(version),
+                ..
+            }) => format!("solc {}", version),
+            Some(_) => "metadata without a compiler version".into(),
+            None => "no metadata".into(),
+        };
+        writeln!(f, "compiler: {} / {}", compiler(left), compiler(right))?;
+        writeln!(
+            f,
+            "opcode n-gram similarity: {}",
+            self.ngram_similarity
+                .iter()
+                .map(|(n, similarity)| format!("{:.2} (n={})", similarity, n))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )?;
+        writeln!(f, "selector similarity: {:.2}", self.selector_similarity)
+    }
+}
+
+/// how often each opcode and push width occurs in a bytecode, for `ebo stats`. a profile far from
+/// that of compiler output, such as one dominated by a few opcodes, gives obfuscated code away.
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct Histogram {
+    /// number of decoded instructions.
+    pub instructions: usize,
+    /// occurrences of each opcode, keyed by mnemonic.
+    pub opcodes: BTreeMap<&'static str, usize>,
+    /// occurrences of each push width in bytes, 0 for push0.
+    pub push_widths: BTreeMap<usize, usize>,
+}
+
+impl Histogram {
+    /// counts the instructions of `bytecode`, push data left out.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::analysis::Histogram;
+    ///
+    /// let histogram = Histogram::new(&[0x60, 0x01, 0x60, 0x02, 0x01, 0x00]);
+    /// assert_eq!(histogram.opcodes["PUSH1"], 2);
+    /// assert_eq!(histogram.push_widths[&1], 2);
+    /// ```
+    pub fn new(bytecode: &[u8]) -> Self {
+        let mut histogram = Histogram::default();
+        for ins in disassemble(bytecode) {
+            histogram.instructions += 1;
+            *histogram.opcodes.entry(mnemonic(ins.opcode)).or_default() += 1;
+            if (op::PUSH0..=op::PUSH32).contains(&ins.opcode) {
+                *histogram
+                    .push_widths
+                    .entry((ins.opcode - op::PUSH0) as usize)
+                    .or_default() += 1;
+            }
+        }
+        histogram
+    }
+
+    /// opcodes from most to least frequent, ties by name.
+    pub fn ranked(&self) -> Vec<(&'static str, usize)> {
+        let mut ranked: Vec<_> = self.opcodes.iter().map(|(&name, &n)| (name, n)).collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        ranked
+    }
+
+    /// the histogram as pretty-printed json.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a histogram always serializes")
+    }
+
+    /// the histogram as csv with a `kind,name,count` header: one `opcode` row per opcode, most
+    /// frequent first, then one `push_width` row per width.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("kind,name,count\n");
+        for (name, count) in self.ranked() {
+            csv.push_str(&format!("opcode,{},{}\n", name, count));
+        }
+        for (width, count) in &self.push_widths {
+            csv.push_str(&format!("push_width,{},{}\n", width, count));
+        }
+        csv
+    }
+}
+
+impl fmt::Display for Histogram {
+    /// one bar per opcode, most frequent first, then the push widths.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.instructions.max(1) as f64;
+        let most = self.opcodes.values().copied().max().unwrap_or(1) as f64;
+        let bar = |count: usize| "#".repeat((count as f64 / most * 40.0).ceil() as usize);
+        writeln!(f, "{} instructions", self.instructions)?;
+        for (name, count) in self.ranked() {
+            writeln!(
+                f,
+                "{:<14} {:>6} {:>6.2}%  {}",
+                name,
+                count,
+                count as f64 / total * 100.0,
+                bar(count)
+            )?;
+        }
+        writeln!(f, "push widths:")?;
+        for (&width, &count) in &self.push_widths {
+            writeln!(
+                f,
+                "{:<14} {:>6} {:>6.2}%  {}",
+                format!("{} bytes", width),
+                count,
+                count as f64 / total * 100.0,
+                bar(count)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// finds the cbor metadata solc appends to runtime code: a map of at most 23 entries with text
+/// keys, followed by its length as two big-endian bytes.
+///
+/// # returns
+/// the metadata, or none if the code does not end in well-formed metadata.
+pub fn metadata(bytecode: &[u8]) -> Option<Metadata> {
+    let [.., high, low] = *bytecode else {
+        return None;
+    };
+    let len = u16::from_be_bytes([high, low]) as usize;
+    let start = bytecode.len().checked_sub(len + 2)?;
+    let mut cbor = Cbor {
+        data: &bytecode[start..bytecode.len() - 2],
+    };
+
+    let entries = match cbor.byte()? {
+        header @ 0xA1..=0xB7 => header - 0xA0,
+        _ => return None,
+    };
+    let mut keys = Vec::new();
+    let mut solc = None;
+    for _ in 0..entries {
+        let (0x60..=0x77, key) = cbor.item()? else {
+            return None;
+        };
+        let key = String::from_utf8(key.to_vec()).ok()?;
+        match cbor.item()? {
+            // releases record the version as three bytes, nightlies as a string
+            (0x43, [major, minor, patch]) if key == "solc" => {
+                solc = Some(format!("{}.{}.{}", major, minor, patch))
+            }
+            (0x60..=0x77, version) if key == "solc" => {
+                solc = Some(String::from_utf8_lossy(version).into_owned())
+            }
+            _ => {}
+        }
+        keys.push(key);
+    }
+    cbor.data.is_empty().then_some(Metadata {
+        range: start..bytecode.len(),
+        keys,
+        solc,
+    })
+}
+
+/// reader for the few cbor items solc metadata is made of.
+struct Cbor<'a> {
+    /// bytes not read yet.
+    data: &'a [u8],
+}
+
+impl<'a> Cbor<'a> {
+    /// reads one byte.
+    fn byte(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(byte)
+    }
+
+    /// reads a byte string, a text string, or a simple value, and returns its header and contents.
+    /// lengths of 24 and up are read from the byte after the header, which covers every item
+    /// solc writes.
+    fn item(&mut self) -> Option<(u8, &'a [u8])> {
+        let header = self.byte()?;
+        let len = match header {
+            0x40..=0x57 | 0x60..=0x77 => (header & 0x1F) as usize,
+            0x58 | 0x78 => self.byte()? as usize,
+            0xF4..=0xF6 => 0,
+            _ => return None,
+        };
+        if len > self.data.len() {
+            return None;
+        }
+        let (contents, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some((header, contents))
+    }
+}
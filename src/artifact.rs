@@ -0,0 +1,712 @@
+/// compiler artifacts for the ebo obfuscator.
+/// reads the runtime code out of a foundry (`out/Contract.sol/Contract.json`) or hardhat
+/// (`artifacts/contracts/Contract.sol/Contract.json`) artifact and writes the artifact back with the
+/// obfuscated runtime in place of the original, in the deployed bytecode and inside the creation
+/// bytecode, so the result deploys like the artifact the toolchain produced. every other field is
+/// written back as it was read.
+///
+/// code that links against libraries holds placeholders where the library addresses go. while the
+/// code is decoded each placeholder is replaced by a marker address (see `link`), and when it is
+/// written back the markers are turned into placeholders again, with the link references moved to
+/// wherever the obfuscated code put them, so the obfuscated artifact is linked like the original.
+///
+/// immutables are handled alike. the `push32` of each immutable gets a marker word while the code is
+/// decoded, and comes out of the obfuscator whole, wherever it was moved. the constructor still
+/// writes the values at the offsets of the original runtime code, so the creation code keeps the
+/// original runtime for it to fill in, and when the constructor returns it the values are copied
+/// into the obfuscated runtime code, which is returned instead (see `embed_immutables`).
+use crate::error::EboError;
+use crate::evm::{disassemble, op, push_size};
+use crate::link;
+use crate::pcmap::PcMap;
+use crate::reloc::{Item, Program};
+use crate::srcmap::SourceMap;
+use anyhow::{bail, ensure, Context};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// how an artifact stores its code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// forge: `bytecode` and `deployedBytecode` are objects with the hex in `object` and the link
+    /// references in `linkReferences`.
+    Foundry,
+    /// hardhat: `bytecode` and `deployedBytecode` are hex strings, with the link references in
+    /// `linkReferences` and `deployedLinkReferences`.
+    Hardhat,
+}
+
+/// the two codes of an artifact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Code {
+    Creation,
+    Deployed,
+}
+
+impl Code {
+    /// field holding the code.
+    fn field(self) -> &'static str {
+        match self {
+            Code::Creation => "bytecode",
+            Code::Deployed => "deployedBytecode",
+        }
+    }
+}
+
+/// a library the code links against.
+#[derive(Debug, Clone, PartialEq)]
+struct Library {
+    /// source file the library is defined in.
+    file: String,
+    /// library name.
+    name: String,
+    /// the 40 characters standing in for its address in the hex of the code.
+    placeholder: String,
+    /// address standing in for it while the code is decoded.
+    marker: [u8; 20],
+}
+
+/// the value of an immutable in the runtime code.
+#[derive(Debug, Clone, PartialEq)]
+struct Immutable {
+    /// ast id of the immutable variable.
+    id: String,
+    /// byte offset of the value, right after its `push32`.
+    start: usize,
+    /// word standing in for the value while the code is decoded.
+    marker: [u8; 32],
+}
+
+/// position of a library address in the code, as link references list it.
+#[derive(Deserialize)]
+struct Offset {
+    start: usize,
+    length: usize,
+}
+
+/// a compiler artifact.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artifact {
+    /// the artifact as read, updated in place.
+    json: Value,
+    /// where the code is kept.
+    layout: Layout,
+    /// libraries either code links against.
+    libraries: Vec<Library>,
+    /// values of immutables in the runtime code.
+    immutables: Vec<Immutable>,
+}
+
+impl Artifact {
+    /// parses an artifact from its json source.
+    pub fn parse(source: &[u8]) -> anyhow::Result<Self> {
+        let json =
+            serde_json::from_slice(source).map_err(|err| EboError::parse("artifact", err))?;
+        Self::from_json(json)
+    }
+
+    /// reads an artifact from parsed json. this also takes the `evm` object solc writes for each
+    /// contract in its standard-json output, which stores the code like forge does.
+    pub fn from_json(json: Value) -> anyhow::Result<Self> {
+        let layout = match json.get("deployedBytecode") {
+            Some(Value::Object(_)) => Layout::Foundry,
+            Some(Value::String(_)) => Layout::Hardhat,
+            _ => return Err(EboError::parse("artifact", "it has no deployedBytecode").into()),
+        };
+        let mut artifact = Artifact {
+            json,
+            layout,
+            libraries: Vec::new(),
+            immutables: Vec::new(),
+        };
+        for code in [Code::Creation, Code::Deployed] {
+            let Some(digits) = artifact.digits(code)?.map(str::to_string) else {
+                continue;
+            };
+            for (file, name, start) in artifact.references(code)? {
+                let placeholder = digits
+                    .get(2 * start..2 * start + 40)
+                    .with_context(|| format!("link reference to {} is out of bounds", name))?;
+                if artifact
+                    .libraries
+                    .iter()
+                    .all(|library| library.placeholder != placeholder)
+                {
+                    artifact.libraries.push(Library {
+                        marker: link::marker(&format!("{}:{}", file, name)),
+                        placeholder: placeholder.to_string(),
+                        file,
+                        name,
+                    });
+                }
+            }
+        }
+        artifact.immutables = artifact.immutable_references()?;
+        Ok(artifact)
+    }
+
+    /// where the artifact keeps its code.
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// the deployed (runtime) bytecode, with a marker address in place of every library address.
+    ///
+    /// # returns
+    /// the code, or an error if the contract has none (an interface or abstract contract).
+    pub fn runtime(&self) -> anyhow::Result<Vec<u8>> {
+        match self.code(Code::Deployed)? {
+            Some(code) if !code.is_empty() => Ok(code),
+            _ => {
+                bail!("the artifact has no deployed code; is it an interface or abstract contract?")
+            }
+        }
+    }
+
+    /// the creation bytecode, with marker addresses like `runtime`, or `None` if the artifact has
+    /// none.
+    pub fn creation(&self) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.code(Code::Creation)?.filter(|code| !code.is_empty()))
+    }
+
+    /// replaces the runtime code with `obfuscated`, in the deployed bytecode and in the creation
+    /// bytecode (see `embed` and `embed_immutables`), and updates the link and immutable
+    /// references of both. the source map of the deployed bytecode is translated to the obfuscated
+    /// code with `pc_map`, the map between their program counters, or cleared without one; the one
+    /// of the creation bytecode only covers the constructor, whose instructions are kept.
+    ///
+    /// only foundry artifacts list immutables; a hardhat artifact of a contract with immutables is
+    /// rewritten, but does not deploy correctly.
+    pub fn replace_runtime(
+        &mut self,
+        obfuscated: &[u8],
+        pc_map: Option<&PcMap>,
+    ) -> anyhow::Result<()> {
+        let runtime = self.runtime()?;
+        for library in &self.libraries {
+            if contains(&runtime, &library.marker) && !contains(obfuscated, &library.marker) {
+                bail!(
+                    "the obfuscated code lost the address of library {}:{}",
+                    library.file,
+                    library.name
+                );
+            }
+        }
+
+        // every immutable is found by its marker, which is then cleared again
+        let mut cleared = obfuscated.to_vec();
+        let mut moves = Vec::new();
+        let mut references = Map::new();
+        for immutable in &self.immutables {
+            let starts = find(obfuscated, &immutable.marker);
+            if starts.is_empty() {
+                bail!(
+                    "the obfuscated code lost the value of immutable {}",
+                    immutable.id
+                );
+            }
+            for &start in &starts {
+                cleared[start..start + 32].fill(0);
+                moves.push((immutable.start, start));
+            }
+            let offsets = references
+                .entry(immutable.id.clone())
+                .or_insert_with(|| Value::Array(Vec::new()));
+            if let Value::Array(offsets) = offsets {
+                offsets.extend(
+                    starts
+                        .iter()
+                        .map(|start| json!({"length": 32, "start": start})),
+                );
+            }
+        }
+
+        if let Some(creation) = self.creation()? {
+            let original = self.unmarked(&runtime);
+            let creation = if moves.is_empty() {
+                embed(&creation, &original, &cleared)?
+            } else {
+                embed_immutables(&creation, &original, &cleared, &moves)?
+            };
+            self.set_code(Code::Creation, &creation);
+        }
+        self.set_code(Code::Deployed, &cleared);
+        if !self.immutables.is_empty() {
+            self.json["deployedBytecode"]["immutableReferences"] = Value::Object(references);
+            self.immutables = self.immutable_references()?;
+        }
+        if let Some(map) = self.json["deployedBytecode"].get_mut("sourceMap") {
+            let translated = match (map.as_str(), pc_map) {
+                (Some(source), Some(pc_map)) if !source.is_empty() => SourceMap::parse(source)?
+                    .translate(&runtime, obfuscated, pc_map)
+                    .to_string(),
+                _ => String::new(),
+            };
+            *map = Value::String(translated);
+        }
+        Ok(())
+    }
+
+    /// the artifact as pretty-printed json.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.json).expect("an artifact always serializes")
+    }
+
+    /// the artifact as json.
+    pub fn into_json(self) -> Value {
+        self.json
+    }
+
+    /// the hex digits of `code`, without the `0x` prefix, or `None` if the artifact has no such
+    /// code.
+    fn digits(&self, code: Code) -> anyhow::Result<Option<&str>> {
+        let Some(value) = self.digits_value(code) else {
+            return Ok(None);
+        };
+        let digits = value
+            .as_str()
+            .with_context(|| format!("{} is not a hex string", code.field()))?;
+        Ok(Some(digits.trim_start_matches("0x")))
+    }
+
+    /// the field holding the hex of `code`.
+    fn digits_value(&self, code: Code) -> Option<&Value> {
+        match self.layout {
+            Layout::Foundry => self.json[code.field()].get("object"),
+            Layout::Hardhat => self.json.get(code.field()),
+        }
+    }
+
+    /// the field holding the link references of `code`.
+    fn references_field(&self, code: Code) -> Option<&Value> {
+        match (self.layout, code) {
+            (Layout::Foundry, _) => self.json[code.field()].get("linkReferences"),
+            (Layout::Hardhat, Code::Creation) => self.json.get("linkReferences"),
+            (Layout::Hardhat, Code::Deployed) => self.json.get("deployedLinkReferences"),
+        }
+    }
+
+    /// the library addresses in `code`, as (file, library, byte offset).
+    fn references(&self, code: Code) -> anyhow::Result<Vec<(String, String, usize)>> {
+        let Some(references) = self.references_field(code) else {
+            return Ok(Vec::new());
+        };
+        let references: BTreeMap<String, BTreeMap<String, Vec<Offset>>> =
+            serde_json::from_value(references.clone())
+                .with_context(|| format!("invalid link references of {}", code.field()))?;
+        let mut found = Vec::new();
+        for (file, libraries) in references {
+            for (name, offsets) in libraries {
+                for offset in offsets {
+                    ensure!(
+                        offset.length == 20,
+                        "link reference to {} is {} bytes long, not 20",
+                        name,
+                        offset.length
+                    );
+                    found.push((file.clone(), name.clone(), offset.start));
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// decodes `code`, with the marker of every library in place of its placeholder.
+    fn code(&self, code: Code) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some(digits) = self.digits(code)? else {
+            return Ok(None);
+        };
+        let mut digits = digits.to_string();
+        for library in &self.libraries {
+            digits = digits.replace(&library.placeholder, &hex::encode(library.marker));
+        }
+        if digits.contains("__") {
+            bail!(
+                "{} has a library placeholder its link references do not list",
+                code.field()
+            );
+        }
+        let mut bytes =
+            hex::decode(digits).with_context(|| format!("invalid hex in {}", code.field()))?;
+        if code == Code::Deployed && !self.immutables.is_empty() {
+            let values: Vec<usize> = disassemble(&bytes)
+                .iter()
+                .filter(|ins| ins.opcode == op::PUSH32)
+                .map(|ins| ins.offset + 1)
+                .collect();
+            for immutable in &self.immutables {
+                let at = immutable.start;
+                let value = bytes.get_mut(at..at + 32).filter(|_| values.contains(&at));
+                let Some(value) = value else {
+                    bail!(
+                        "immutable {} at {:#x} is not the value of a PUSH32",
+                        immutable.id,
+                        at
+                    );
+                };
+                value.copy_from_slice(&immutable.marker);
+            }
+        }
+        Ok(Some(bytes))
+    }
+
+    /// `runtime` with the marker of every immutable cleared, as the compiler wrote it.
+    fn unmarked(&self, runtime: &[u8]) -> Vec<u8> {
+        let mut runtime = runtime.to_vec();
+        for immutable in &self.immutables {
+            runtime[immutable.start..immutable.start + 32].fill(0);
+        }
+        runtime
+    }
+
+    /// the immutables listed for the deployed code.
+    fn immutable_references(&self) -> anyhow::Result<Vec<Immutable>> {
+        let Some(references) = self.json["deployedBytecode"].get("immutableReferences") else {
+            return Ok(Vec::new());
+        };
+        let references: BTreeMap<String, Vec<Offset>> = serde_json::from_value(references.clone())
+            .context("invalid immutable references of deployedBytecode")?;
+        let mut immutables = Vec::new();
+        for (id, offsets) in references {
+            for offset in offsets {
+                ensure!(
+                    offset.length == 32,
+                    "immutable reference to {} is {} bytes long, not 32",
+                    id,
+                    offset.length
+                );
+                immutables.push(Immutable {
+                    marker: link::marker(&format!("immutable {}@{}", id, offset.start)),
+                    start: offset.start,
+                    id: id.clone(),
+                });
+            }
+        }
+        Ok(immutables)
+    }
+
+    /// writes `code` into the artifact, turning library markers back into placeholders and listing
+    /// where they are in the link references.
+    fn set_code(&mut self, code: Code, bytes: &[u8]) {
+        let mut digits = hex::encode(bytes);
+        let mut references = Map::new();
+        for library in &self.libraries {
+            let starts: Vec<usize> = bytes
+                .windows(20)
+                .enumerate()
+                .filter(|(_, window)| *window == library.marker)
+                .map(|(start, _)| start)
+                .collect();
+            for &start in &starts {
+                digits.replace_range(2 * start..2 * start + 40, &library.placeholder);
+            }
+            if starts.is_empty() {
+                continue;
+            }
+            let offsets: Vec<Value> = starts
+                .iter()
+                .map(|start| json!({"length": 20, "start": start}))
+                .collect();
+            let file = references
+                .entry(library.file.clone())
+                .or_insert_with(|| Value::Object(Map::new()));
+            file[library.name.as_str()] = Value::Array(offsets);
+        }
+
+        // solc writes the hex without a prefix, forge and hardhat with one
+        let prefix = match self.digits_value(code) {
+            Some(Value::String(old)) if !old.starts_with("0x") => "",
+            _ => "0x",
+        };
+        let digits = Value::String(format!("{}{}", prefix, digits));
+        // artifacts of code without libraries may leave the link references out
+        let keep = self.references_field(code).is_some() || !self.libraries.is_empty();
+        match self.layout {
+            Layout::Foundry => {
+                self.json[code.field()]["object"] = digits;
+                if keep {
+                    self.json[code.field()]["linkReferences"] = Value::Object(references);
+                }
+            }
+            Layout::Hardhat => {
+                self.json[code.field()] = digits;
+                if keep {
+                    let field = match code {
+                        Code::Creation => "linkReferences",
+                        Code::Deployed => "deployedLinkReferences",
+                    };
+                    self.json[field] = Value::Object(references);
+                }
+            }
+        }
+    }
+}
+
+/// offsets at which `needle` occurs in `code`.
+fn find(code: &[u8], needle: &[u8]) -> Vec<usize> {
+    code.windows(needle.len())
+        .enumerate()
+        .filter(|(_, window)| *window == needle)
+        .map(|(start, _)| start)
+        .collect()
+}
+
+/// returns true if `needle` occurs in `code`.
+fn contains(code: &[u8], needle: &[u8]) -> bool {
+    code.windows(needle.len()).any(|window| window == needle)
+}
+
+/// returns true if `source` looks like a json artifact rather than bytecode.
+pub fn is_artifact(source: &[u8]) -> bool {
+    std::str::from_utf8(source).is_ok_and(|text| text.trim_start().starts_with('{'))
+}
+
+/// swaps the runtime code embedded in creation code for `obfuscated`.
+///
+/// solc places the runtime code at the end of the creation code, where the constructor copies it
+/// from with `CODECOPY` and returns it. the runtime is replaced there, and every push of its length
+/// in the constructor is patched to the new length; the offset it is copied from does not change.
+/// constructor arguments are appended to the creation code when it is deployed, and read from the
+/// end of it, so every push of the length of the creation code is patched to the new length too.
+///
+/// # arguments
+/// * `creation` - creation bytecode.
+/// * `runtime` - runtime bytecode embedded in it.
+/// * `obfuscated` - runtime bytecode to embed instead.
+///
+/// # returns
+/// the new creation bytecode, or an error if the runtime is not at its end, the constructor never
+/// pushes its length, or the new length does not fit those pushes.
+pub fn embed(creation: &[u8], runtime: &[u8], obfuscated: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut embedded = Vec::with_capacity(creation.len() - runtime.len().min(creation.len()));
+    embed_into(creation, runtime, obfuscated, &mut embedded)?;
+    Ok(embedded)
+}
+
+/// `embed`, writing the new creation bytecode to `out` as it goes rather than into a copy, for
+/// init code too large to hold twice. only the patched pushes are copied.
+///
+/// # returns
+/// nothing, or the error of `embed` before anything is written, or the error of writing.
+pub fn embed_into(
+    creation: &[u8],
+    runtime: &[u8],
+    obfuscated: &[u8],
+    out: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let Some(offset) = creation.len().checked_sub(runtime.len()) else {
+        bail!("the creation code is shorter than the runtime code");
+    };
+    ensure!(
+        creation[offset..] == *runtime,
+        "the runtime code is not at the end of the creation code"
+    );
+
+    // the pushes are found without disassembling the constructor, which may embed a lot of data
+    let constructor = &creation[..offset];
+    let mut patches = Vec::new();
+    let mut pc = 0;
+    while pc < constructor.len() {
+        let width = push_size(constructor[pc]);
+        let start = pc + 1;
+        pc = start + width;
+        if width == 0 || width > 8 || pc > constructor.len() {
+            continue;
+        }
+        let value = constructor[start..pc]
+            .iter()
+            .fold(0u64, |value, &b| value << 8 | b as u64);
+        let (what, length) = if value == runtime.len() as u64 {
+            ("obfuscated runtime", obfuscated.len())
+        } else if value == creation.len() as u64 {
+            ("creation code", offset + obfuscated.len())
+        } else {
+            continue;
+        };
+        let bytes = (length as u64).to_be_bytes();
+        let (high, low) = bytes.split_at(8 - width);
+        ensure!(
+            high.iter().all(|&b| b == 0),
+            "the {} is {} bytes, too long for the PUSH{} of its length",
+            what,
+            length,
+            width
+        );
+        patches.push((start, low.to_vec(), value == runtime.len() as u64));
+    }
+    ensure!(
+        patches.iter().any(|(_, _, runtime)| *runtime),
+        "the constructor never pushes the runtime length {}",
+        runtime.len()
+    );
+
+    let mut written = 0;
+    for (start, bytes, _) in patches {
+        out.write_all(&constructor[written..start])?;
+        out.write_all(&bytes)?;
+        written = start + bytes.len();
+    }
+    out.write_all(&constructor[written..])?;
+    out.write_all(obfuscated)?;
+    Ok(())
+}
+
+/// swaps the runtime code embedded in creation code for `obfuscated`, for a contract with
+/// immutables.
+///
+/// the constructor writes immutables into its copy of the runtime code at the offsets they have in
+/// the original, so the original stays embedded for it, and `obfuscated` is appended after it.
+/// every `RETURN` of the constructor jumps to a fixup instead, which copies `obfuscated` into memory
+/// next to the filled-in original, copies each immutable value over, and returns the obfuscated
+/// copy. the constructor is relocated to make room, and its pushes of the offset of the runtime and
+/// of the length of the creation code (where constructor arguments start) are patched to match.
+///
+/// # arguments
+/// * `creation` - creation bytecode.
+/// * `runtime` - runtime bytecode embedded in it, with the immutable values left zero.
+/// * `obfuscated` - runtime bytecode to embed instead, with the immutable values left zero.
+/// * `moves` - offset of an immutable value in `runtime` and an offset of it in `obfuscated`.
+///
+/// # returns
+/// the new creation bytecode, or an error if the runtime is not at the end of the creation code or
+/// the patched offsets do not fit the pushes of the constructor.
+pub fn embed_immutables(
+    creation: &[u8],
+    runtime: &[u8],
+    obfuscated: &[u8],
+    moves: &[(usize, usize)],
+) -> anyhow::Result<Vec<u8>> {
+    let Some(offset) = creation.len().checked_sub(runtime.len()) else {
+        bail!("the creation code is shorter than the runtime code");
+    };
+    ensure!(
+        creation[offset..] == *runtime,
+        "the runtime code is not at the end of the creation code"
+    );
+
+    // the constructor is laid out once to learn its new length, which the second layout patches in
+    let mut length = offset;
+    for _ in 0..2 {
+        let constructor = relocate_constructor(
+            &creation[..offset],
+            length,
+            length + runtime.len() + obfuscated.len(),
+            runtime.len(),
+            obfuscated.len(),
+            moves,
+        )?;
+        if constructor.len() == length {
+            return Ok([&constructor[..], runtime, obfuscated].concat());
+        }
+        length = constructor.len();
+    }
+    bail!("the constructor does not settle on one length")
+}
+
+/// the constructor of `embed_immutables`, laid out for a new constructor `length` and a creation
+/// code of `total` bytes.
+fn relocate_constructor(
+    constructor: &[u8],
+    length: usize,
+    total: usize,
+    runtime: usize,
+    obfuscated: usize,
+    moves: &[(usize, usize)],
+) -> anyhow::Result<Vec<u8>> {
+    let push = |value: usize| Item::push(&(value as u32).to_be_bytes());
+    let old_total = constructor.len() + runtime;
+    let mut program = Program::lift(constructor);
+    let (start, fixup) = (program.new_label(), program.new_label());
+
+    // entry: jump over the fixup to the constructor
+    let mut items = vec![
+        Item::PushLabel {
+            label: start,
+            addend: 0,
+            width: 2,
+        },
+        Item::op(op::JUMP),
+        Item::Mark(fixup),
+        Item::op(op::JUMPDEST),
+        // offset and length of the filled-in original: keep the offset
+        Item::op(op::SWAP1),
+        Item::op(op::POP),
+        // codecopy(offset + runtime, length + runtime, obfuscated)
+        push(obfuscated),
+        push(length + runtime),
+        Item::op(op::DUP1 + 2),
+        push(runtime),
+        Item::op(op::ADD),
+        Item::op(op::CODECOPY),
+    ];
+    // mstore(offset + runtime + to, mload(offset + from)) for every immutable value
+    for &(from, to) in moves {
+        items.extend([
+            Item::op(op::DUP1),
+            push(from),
+            Item::op(op::ADD),
+            Item::op(op::MLOAD),
+            Item::op(op::DUP1 + 1),
+            push(runtime + to),
+            Item::op(op::ADD),
+            Item::op(op::MSTORE),
+        ]);
+    }
+    // return(offset + runtime, obfuscated)
+    items.extend([
+        push(obfuscated),
+        Item::op(op::SWAP1),
+        push(runtime),
+        Item::op(op::ADD),
+        Item::op(op::RETURN),
+        Item::Mark(start),
+        Item::op(op::JUMPDEST),
+    ]);
+
+    for item in std::mem::take(&mut program.items) {
+        match item {
+            Item::Op {
+                opcode: op::RETURN, ..
+            } => items.extend([
+                Item::PushLabel {
+                    label: fixup,
+                    addend: 0,
+                    width: 2,
+                },
+                Item::op(op::JUMP),
+            ]),
+            Item::Op {
+                opcode,
+                immediate,
+                origin,
+            } if (1..=8).contains(&immediate.len()) => {
+                let value = immediate
+                    .iter()
+                    .fold(0usize, |value, &b| value << 8 | b as usize);
+                let patched = match value {
+                    value if value == constructor.len() => length,
+                    value if value == old_total => total,
+                    value => value,
+                };
+                let bytes = patched.to_be_bytes();
+                let (high, low) = bytes.split_at(bytes.len() - immediate.len());
+                ensure!(
+                    high.iter().all(|&b| b == 0),
+                    "offset {} does not fit the PUSH{} of the constructor",
+                    patched,
+                    immediate.len()
+                );
+                items.push(Item::Op {
+                    opcode,
+                    immediate: low.to_vec(),
+                    origin,
+                });
+            }
+            item => items.push(item),
+        }
+    }
+    program.items = items;
+    Ok(program.assemble()?)
+}
@@ -0,0 +1,166 @@
+/// batch obfuscation for the ebo obfuscator.
+/// obfuscates every file under a directory that matches a glob pattern in one run, writing each
+/// result to the same relative path under an output directory, so a project's contracts do not
+/// have to be obfuscated one invocation at a time.
+use crate::artifact::Artifact;
+use crate::config::Obfuscation;
+use crate::corpus::{self, Format};
+use crate::link;
+use crate::slotmap;
+use anyhow::Context;
+use glob::{MatchOptions, Pattern};
+use std::path::{Path, PathBuf};
+
+/// `*` and `?` stay within one path component; `**` crosses them.
+const MATCH: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// finds the files under `dir` whose path relative to it matches `pattern` (e.g. `**/*.bin`).
+///
+/// # returns
+/// the relative paths, sorted.
+pub fn find(dir: &Path, pattern: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let pattern =
+        Pattern::new(pattern).with_context(|| format!("invalid glob pattern '{}'", pattern))?;
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        let entries = std::fs::read_dir(dir.join(&relative))
+            .with_context(|| format!("cannot read {:?}", dir.join(&relative)))?;
+        for entry in entries {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else if pattern.matches_path_with(&path, MATCH) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// what happened to one file of a batch.
+#[derive(Debug)]
+pub struct Outcome {
+    /// the file, relative to the input directory.
+    pub path: PathBuf,
+    /// sizes of the code before and after, or why the file was not written.
+    pub result: anyhow::Result<(usize, usize)>,
+}
+
+/// obfuscates `files` of `dir` into `out_dir`, keeping their relative paths. each output keeps the
+/// form of its input: artifacts are written back as artifacts, hex as hex, and raw bytes as raw
+/// bytes unless `hex` is set. a file that fails does not stop the others.
+///
+/// # arguments
+/// * `dir` - input directory.
+/// * `files` - paths relative to `dir`, see `find`.
+/// * `out_dir` - output directory, created as needed.
+/// * `format` - how the inputs are encoded.
+/// * `hex` - write raw inputs as hex.
+/// * `force` - overwrite existing outputs.
+/// * `obfuscate` - obfuscates the runtime code of a file.
+///
+/// # returns
+/// the outcome for every file, in order.
+pub fn run(
+    dir: &Path,
+    files: &[PathBuf],
+    out_dir: &Path,
+    format: Format,
+    hex: bool,
+    force: bool,
+    mut obfuscate: impl FnMut(&Path, &[u8]) -> anyhow::Result<Obfuscation>,
+) -> Vec<Outcome> {
+    files
+        .iter()
+        .map(|path| Outcome {
+            path: path.clone(),
+            result: process(
+                &dir.join(path),
+                &out_dir.join(path),
+                format,
+                hex,
+                force,
+                |code| obfuscate(path, code),
+            ),
+        })
+        .collect()
+}
+
+/// obfuscates the file at `input` into `output`, and writes the storage slots it moved next to it
+/// if it moved any (see `slotmap`).
+fn process(
+    input: &Path,
+    output: &Path,
+    format: Format,
+    hex: bool,
+    force: bool,
+    obfuscate: impl FnOnce(&[u8]) -> anyhow::Result<Obfuscation>,
+) -> anyhow::Result<(usize, usize)> {
+    let slot_map = slotmap::path(output);
+    let links = link::references_path(output);
+    for path in [output, &slot_map, &links] {
+        corpus::refuse_overwrite(path, force)?;
+    }
+    let bytes = std::fs::read(input).with_context(|| format!("cannot read {:?}", input))?;
+    let format = match format {
+        Format::Auto => corpus::detect(&bytes),
+        format => format,
+    };
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent).with_context(|| format!("cannot create {:?}", parent))?;
+    }
+    let (before, after, contents, slots) = match format {
+        Format::Artifact => {
+            let mut artifact = Artifact::parse(&bytes)?;
+            let runtime = artifact.runtime()?;
+            let Obfuscation {
+                bytecode: obfuscated,
+                pc_map,
+                slots,
+                ..
+            } = obfuscate(&runtime)?;
+            artifact.replace_runtime(&obfuscated, pc_map.as_ref())?;
+            (
+                runtime.len(),
+                obfuscated.len(),
+                artifact.to_json().into_bytes(),
+                slots,
+            )
+        }
+        format => {
+            // unlinked hex keeps its library placeholders, with the link references alongside
+            let placeholders = match format {
+                Format::Hex => link::placeholders(&String::from_utf8_lossy(&bytes)),
+                _ => Vec::new(),
+            };
+            let code = corpus::parse_bytes(bytes, format)?;
+            let Obfuscation {
+                bytecode: obfuscated,
+                slots,
+                ..
+            } = obfuscate(&code)?;
+            let contents = if !placeholders.is_empty() {
+                let (digits, references) = link::relink(&obfuscated, &placeholders)?;
+                link::write_references(output, &references, force)?;
+                digits.into_bytes()
+            } else if hex || format == Format::Hex {
+                ::hex::encode(&obfuscated).into_bytes()
+            } else {
+                obfuscated.clone()
+            };
+            (code.len(), obfuscated.len(), contents, slots)
+        }
+    };
+    corpus::write(output, contents, force)?;
+    if !slots.is_empty() {
+        corpus::write(&slot_map, slots.to_json(), force)?;
+    }
+    Ok((before, after))
+}
@@ -0,0 +1,38 @@
+/// foreign-language entry points for the ebo obfuscator.
+/// what a javascript (wasm-bindgen) or other foreign wrapper exposes: plain strings in and out, and
+/// nothing on the way that reads files, the environment, or the network, so the core obfuscation
+/// path runs wherever the wrapper does, such as a browser or a hardhat plugin. with the `wasm`
+/// feature the functions are exported to javascript as they are, see bindings/npm for the package.
+use crate::config::Config;
+use crate::corpus;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// obfuscates runtime code as an `ebo.toml` describes, with the checks `ebo obfuscate` makes.
+///
+/// # arguments
+/// * `bytecode` - the code as hex, with or without `0x`.
+/// * `config` - contents of an `ebo.toml`, or an empty string for the defaults. its `seed` is used,
+///   or 42.
+///
+/// # returns
+/// the obfuscated code as `0x`-prefixed hex, or the error as a message for the wrapper to raise.
+///
+/// # example
+/// ```
+/// use ebo::bindings;
+///
+/// let obfuscated = bindings::obfuscate("0x6001600201 00", "seed = 7").unwrap();
+/// assert!(obfuscated.starts_with("0x"));
+/// assert!(bindings::obfuscate("0xzz", "").is_err());
+/// ```
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn obfuscate(bytecode: &str, config: &str) -> Result<String, String> {
+    let run = || -> anyhow::Result<String> {
+        let bytecode = corpus::decode_hex(bytecode)?;
+        let config = Config::from_toml(config)?;
+        let obfuscation = config.obfuscate(&bytecode, config.seed.unwrap_or(42))?;
+        Ok(format!("0x{}", hex::encode(obfuscation.bytecode)))
+    };
+    run().map_err(|err| format!("{:#}", err))
+}
@@ -0,0 +1,168 @@
+/// block-level caching for the ebo obfuscator.
+/// the passes whose changes stay inside a block (chaotic shuffle, substitution, peephole) draw the
+/// randomness for every block from a stream of its own, derived from the seed of the round, the
+/// pass, and the block's content (see `passes::Ctx::transform_blocks`), so a block comes out of a
+/// pass the same whatever the rest of the program looks like. `ebo obfuscate --cache FILE` keeps
+/// those results between runs: a re-run on slightly changed bytecode with the same seed and
+/// configuration reuses the blocks it has seen and only transforms the changed ones, then lays the
+/// program out again. the passes that move code between blocks always run in full.
+///
+/// a cache written by another version of ebo is ignored, and saving keeps only the entries the
+/// runs since loading used, so the file does not grow with every edit.
+use crate::error::EboError;
+use crate::manifest::VERSION;
+use crate::reloc::Item;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tracing::info;
+
+/// hash of a block in canonical form.
+pub fn digest(items: &[Item]) -> [u8; 32] {
+    let json = serde_json::to_vec(items).expect("items always serialize");
+    Sha256::digest(json).into()
+}
+
+/// the key of the result of `pass` on a block, from everything it depends on: the block, its
+/// stream (see `seed::block_rng`), the settings of the pass, and what the pass knows about the
+/// code around the block.
+pub fn key(
+    base: &[u8; 32],
+    pass: &str,
+    content: &[u8; 32],
+    occurrence: usize,
+    params: &str,
+    context: &str,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(base);
+    hasher.update(content);
+    hasher.update((occurrence as u64).to_le_bytes());
+    for part in [pass, params, context] {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// a block as a pass left it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// the block in canonical form, see `reloc::Canonical`.
+    pub items: Vec<Item>,
+    /// number of transformations the pass applied to it.
+    pub applied: usize,
+}
+
+/// contents of a cache file.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    version: String,
+    entries: BTreeMap<String, Entry>,
+}
+
+/// transformed blocks by the hash of everything their transformation depends on. shared between
+/// the runs of a search or a batch, which may fill it from several threads.
+#[derive(Debug, Default)]
+pub struct BlockCache {
+    entries: Mutex<HashMap<String, Entry>>,
+    used: Mutex<HashSet<String>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl BlockCache {
+    /// an empty cache.
+    pub fn new() -> Self {
+        BlockCache::default()
+    }
+
+    /// the cache saved at `path`, or an empty one if there is none yet or it was written by
+    /// another version of ebo.
+    ///
+    /// # returns
+    /// the cache, or `EboError::Parse` if the file is not a cache.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(BlockCache::new());
+        }
+        let source =
+            std::fs::read_to_string(path).with_context(|| format!("cannot read {:?}", path))?;
+        let file: File =
+            serde_json::from_str(&source).map_err(|err| EboError::parse("block cache", err))?;
+        if file.version != VERSION {
+            info!(
+                "Ignoring the block cache of ebo {} in {:?}",
+                file.version, path
+            );
+            return Ok(BlockCache::new());
+        }
+        Ok(BlockCache {
+            entries: Mutex::new(file.entries.into_iter().collect()),
+            ..BlockCache::default()
+        })
+    }
+
+    /// writes the entries used since the cache was loaded to `path`.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        let used = self.used.lock().unwrap_or_else(|err| err.into_inner());
+        let file = File {
+            version: VERSION.to_string(),
+            entries: entries
+                .iter()
+                .filter(|(key, _)| used.contains(*key))
+                .map(|(key, entry)| (key.clone(), entry.clone()))
+                .collect(),
+        };
+        let json = serde_json::to_string(&file).expect("a block cache always serializes");
+        std::fs::write(path, json).with_context(|| format!("cannot write {:?}", path))
+    }
+
+    /// the entry stored under `key`, counted as a hit or a miss.
+    pub fn get(&self, key: &str) -> Option<Entry> {
+        let entry = self
+            .entries
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(key)
+            .cloned();
+        let counter = match entry {
+            Some(_) => &self.hits,
+            None => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if entry.is_some() {
+            self.used
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .insert(key.to_string());
+        }
+        entry
+    }
+
+    /// stores `entry` under `key`.
+    pub fn insert(&self, key: String, entry: Entry) {
+        self.used
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(key.clone());
+        self.entries
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(key, entry);
+    }
+
+    /// number of blocks found in the cache, and number transformed, so far.
+    pub fn stats(&self) -> (usize, usize) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+}
@@ -0,0 +1,249 @@
+/// control flow graph recovery for the ebo obfuscator.
+/// splits decoded bytecode into basic blocks and links them by fall-through and by the jumpdests
+/// they push, which is how solc-style code reaches both jump targets and internal return
+/// addresses. used to partition code by function and to describe the shape of the code before and
+/// after obfuscation in more detail than a count of conditional jumps.
+use crate::evm::{disassemble, is_terminator, op, Instruction};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::ops::Range;
+
+/// a basic block of decoded bytecode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Block {
+    /// byte range covered by the block.
+    pub range: Range<usize>,
+    /// index of the first instruction of the block.
+    pub first: usize,
+    /// index one past the last instruction of the block.
+    pub last: usize,
+}
+
+/// splits `instructions` into basic blocks, starting a block at every jumpdest and after every jump,
+/// jumpi, or terminator.
+pub fn basic_blocks(instructions: &[Instruction], code_len: usize) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut first = 0;
+    for (idx, ins) in instructions.iter().enumerate() {
+        let starts_here = ins.opcode == op::JUMPDEST && idx > first;
+        if starts_here {
+            blocks.push((first, idx));
+            first = idx;
+        }
+        if is_terminator(ins.opcode) || ins.opcode == op::JUMPI {
+            blocks.push((first, idx + 1));
+            first = idx + 1;
+        }
+    }
+    if first < instructions.len() {
+        blocks.push((first, instructions.len()));
+    }
+    blocks
+        .into_iter()
+        .map(|(first, last)| {
+            let end = instructions.get(last).map_or(code_len, |ins| ins.offset);
+            Block {
+                range: instructions[first].offset..end,
+                first,
+                last,
+            }
+        })
+        .collect()
+}
+
+/// value of a push immediate of at most eight bytes.
+pub fn immediate_value(ins: &Instruction) -> Option<usize> {
+    (!ins.immediate.is_empty() && ins.immediate.len() <= 8).then(|| {
+        ins.immediate
+            .iter()
+            .fold(0usize, |acc, &b| (acc << 8) | b as usize)
+    })
+}
+
+/// an edge of the graph, by block index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Edge {
+    /// block the edge leaves.
+    pub from: usize,
+    /// block the edge enters.
+    pub to: usize,
+}
+
+/// control flow graph over basic blocks, keyed by block start offset.
+///
+/// nodes and edges are kept by index rather than by reference: the blocks are ranges into one
+/// instruction vector, and the edges of all blocks sit in one vector ordered by source, so a block's
+/// successors are a slice of it and walking the graph allocates nothing.
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    /// the decoded bytecode.
+    pub instructions: Vec<Instruction>,
+    /// basic blocks in code order.
+    pub blocks: Vec<Block>,
+    /// block index by start offset.
+    pub by_offset: BTreeMap<usize, usize>,
+    /// jumpdest offsets.
+    pub jumpdests: HashSet<usize>,
+    /// distinct edges, ordered by source and then by target.
+    pub edges: Vec<Edge>,
+    /// range of `edges` leaving each block.
+    outgoing: Vec<Range<usize>>,
+    /// sources of the edges entering each block, grouped by target; see `incoming`.
+    sources: Vec<usize>,
+    /// range of `sources` entering each block.
+    incoming: Vec<Range<usize>>,
+}
+
+impl Cfg {
+    /// recovers the graph of `bytecode`.
+    pub fn new(bytecode: &[u8]) -> Self {
+        let instructions = disassemble(bytecode);
+        let blocks = basic_blocks(&instructions, bytecode.len());
+        let by_offset = blocks
+            .iter()
+            .enumerate()
+            .map(|(idx, block)| (block.range.start, idx))
+            .collect();
+        let jumpdests = instructions
+            .iter()
+            .filter(|ins| ins.opcode == op::JUMPDEST)
+            .map(|ins| ins.offset)
+            .collect();
+        let mut cfg = Cfg {
+            instructions,
+            blocks,
+            by_offset,
+            jumpdests,
+            edges: Vec::new(),
+            outgoing: Vec::new(),
+            sources: Vec::new(),
+            incoming: Vec::new(),
+        };
+        cfg.link();
+        cfg
+    }
+
+    /// fills the edge lists: the successors of a block are the fall-through block and every
+    /// jumpdest pushed in the block. a pushed jumpdest is either a jump target or a return address,
+    /// so counting every one keeps internal function returns inside the caller's reach.
+    fn link(&mut self) {
+        let nodes = self.blocks.len();
+        let mut next = Vec::new();
+        for (idx, block) in self.blocks.iter().enumerate() {
+            let code = &self.instructions[block.first..block.last];
+            next.clear();
+            next.extend(
+                code.iter()
+                    .filter_map(immediate_value)
+                    .filter(|value| self.jumpdests.contains(value))
+                    .filter_map(|value| self.by_offset.get(&value).copied()),
+            );
+            let falls_through = code.last().is_some_and(|ins| !is_terminator(ins.opcode));
+            if falls_through && idx + 1 < nodes {
+                next.push(idx + 1);
+            }
+            next.sort_unstable();
+            next.dedup();
+            let start = self.edges.len();
+            self.edges
+                .extend(next.iter().map(|&to| Edge { from: idx, to }));
+            self.outgoing.push(start..self.edges.len());
+        }
+
+        // counting sort of the edges by target gives every block its predecessors as one range
+        let mut counts = vec![0; nodes + 1];
+        for edge in &self.edges {
+            counts[edge.to + 1] += 1;
+        }
+        for idx in 0..nodes {
+            counts[idx + 1] += counts[idx];
+        }
+        self.incoming = (0..nodes).map(|idx| counts[idx]..counts[idx + 1]).collect();
+        self.sources = vec![0; self.edges.len()];
+        for edge in &self.edges {
+            self.sources[counts[edge.to]] = edge.from;
+            counts[edge.to] += 1;
+        }
+    }
+
+    /// distinct successors of block `idx` in block order, see `link`.
+    pub fn successors(&self, idx: usize) -> impl Iterator<Item = usize> + '_ {
+        self.edges[self.outgoing[idx].clone()]
+            .iter()
+            .map(|edge| edge.to)
+    }
+
+    /// distinct predecessors of block `idx` in block order.
+    pub fn predecessors(&self, idx: usize) -> &[usize] {
+        &self.sources[self.incoming[idx].clone()]
+    }
+
+    /// indices of the blocks reachable from `start` without entering any block in `stop`.
+    pub fn reach(&self, start: usize, stop: &BTreeSet<usize>) -> BTreeSet<usize> {
+        let mut seen = BTreeSet::from([start]);
+        let mut queue = vec![start];
+        while let Some(idx) = queue.pop() {
+            for next in self.successors(idx) {
+                if !stop.contains(&next) && seen.insert(next) {
+                    queue.push(next);
+                }
+            }
+        }
+        seen
+    }
+
+    /// structural statistics of the graph.
+    pub fn stats(&self) -> Stats {
+        let nodes = self.blocks.len();
+        let edges = self.edges.len();
+        let sizes = self.blocks.iter().map(|block| block.last - block.first);
+        let branches = self
+            .blocks
+            .iter()
+            .filter(|block| self.instructions[block.last - 1].opcode == op::JUMPI)
+            .count();
+        Stats {
+            nodes,
+            edges,
+            average_block_size: match nodes {
+                0 => 0.0,
+                _ => self.instructions.len() as f64 / nodes as f64,
+            },
+            max_block_size: sizes.max().unwrap_or(0),
+            branch_density: match nodes {
+                0 => 0.0,
+                _ => branches as f64 / nodes as f64,
+            },
+        }
+    }
+}
+
+/// shape of a control flow graph.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Stats {
+    /// number of basic blocks.
+    pub nodes: usize,
+    /// number of distinct edges between blocks, by fall-through or by a pushed jumpdest. jumps
+    /// whose target is computed at runtime have no edge.
+    pub edges: usize,
+    /// average number of instructions per block.
+    pub average_block_size: f64,
+    /// number of instructions of the largest block.
+    pub max_block_size: usize,
+    /// share of the blocks that end in a conditional jump.
+    pub branch_density: f64,
+}
+
+/// structural statistics of the control flow graph of `bytecode`.
+///
+/// # example
+/// ```
+/// use ebo::cfg;
+///
+/// // PUSH1 4, JUMPI, STOP, JUMPDEST, STOP splits into [PUSH1 4, JUMPI], [STOP], [JUMPDEST, STOP]
+/// let stats = cfg::stats(&[0x60, 0x04, 0x57, 0x00, 0x5B, 0x00]);
+/// assert_eq!((stats.nodes, stats.edges, stats.max_block_size), (3, 2, 2));
+/// ```
+pub fn stats(bytecode: &[u8]) -> Stats {
+    Cfg::new(bytecode).stats()
+}
@@ -0,0 +1,243 @@
+/// chain profiles for the ebo obfuscator.
+/// rollups run the evm with differences of their own: opcodes that revert or are rejected, opcodes
+/// that run but return something other than on ethereum, and gas that is accounted differently. a
+/// profile records those differences for one chain, following the chain's documentation of how it
+/// differs from ethereum, so the validator and the passes can keep to what the chain runs.
+use crate::evm::{disassemble, op, Fork};
+use crate::explorer;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// a layer 2 chain with an opcode profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Chain {
+    /// arbitrum one.
+    Arbitrum,
+    /// op mainnet, and the op stack chains that share its execution (base).
+    Optimism,
+    /// zksync era, running evm bytecode in its evm emulator.
+    ZksyncEra,
+    /// polygon zkevm.
+    PolygonZkevm,
+}
+
+/// how a chain differs from ethereum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+    /// ids of the chains the profile applies to.
+    pub ids: &'static [u64],
+    /// latest fork whose opcodes the chain runs.
+    pub fork: Fork,
+    /// opcodes the fork defines but the chain rejects or reverts on.
+    pub unsupported: &'static [u8],
+    /// opcodes that run but return chain-specific values, so no transformation may depend on them.
+    pub modified: &'static [u8],
+    /// whether opcodes cost what they cost on ethereum, so gas budgets estimated with
+    /// `gas::static_cost` hold on the chain and gas readings match ethereum's.
+    pub mainnet_gas: bool,
+}
+
+// opcodes the profiles name that the passes never emit, so `evm::op` has no name for them
+const COINBASE: u8 = 0x41;
+const PREVRANDAO: u8 = 0x44;
+const NUMBER: u8 = 0x43;
+const BLOCKHASH: u8 = 0x40;
+const TIMESTAMP: u8 = 0x42;
+const GAS: u8 = 0x5A;
+const GASLIMIT: u8 = 0x45;
+const CALLCODE: u8 = 0xF2;
+const EXTCODECOPY: u8 = 0x3C;
+const EXTCODEHASH: u8 = 0x3F;
+const PC: u8 = 0x58;
+const CREATE: u8 = 0xF0;
+const CREATE2: u8 = 0xF5;
+
+const ARBITRUM: Profile = Profile {
+    ids: &[42_161],
+    fork: Fork::Cancun,
+    // there are no blobs on arbitrum
+    unsupported: &[op::BLOBBASEFEE],
+    modified: &[BLOCKHASH, COINBASE, NUMBER, PREVRANDAO, op::BLOBHASH],
+    // costs match, but the gas of a transaction also pays for its l1 data
+    mainnet_gas: true,
+};
+
+const OPTIMISM: Profile = Profile {
+    ids: &[10, 8_453],
+    fork: Fork::Cancun,
+    unsupported: &[],
+    modified: &[COINBASE, PREVRANDAO, op::BLOBHASH, op::BLOBBASEFEE],
+    mainnet_gas: true,
+};
+
+const ZKSYNC_ERA: Profile = Profile {
+    ids: &[324],
+    fork: Fork::Cancun,
+    unsupported: &[
+        op::CODECOPY,
+        EXTCODECOPY,
+        PC,
+        CALLCODE,
+        op::SELFDESTRUCT,
+        op::BLOBHASH,
+        op::BLOBBASEFEE,
+    ],
+    modified: &[
+        COINBASE, TIMESTAMP, NUMBER, PREVRANDAO, GASLIMIT, GAS, CREATE, CREATE2,
+    ],
+    mainnet_gas: false,
+};
+
+const POLYGON_ZKEVM: Profile = Profile {
+    ids: &[1_101],
+    fork: Fork::Shanghai,
+    unsupported: &[],
+    // selfdestruct is sendall: it moves the balance but keeps the code
+    modified: &[
+        BLOCKHASH,
+        COINBASE,
+        NUMBER,
+        PREVRANDAO,
+        EXTCODEHASH,
+        op::SELFDESTRUCT,
+    ],
+    mainnet_gas: true,
+};
+
+impl Chain {
+    /// every chain with a profile.
+    pub const ALL: [Chain; 4] = [
+        Chain::Arbitrum,
+        Chain::Optimism,
+        Chain::ZksyncEra,
+        Chain::PolygonZkevm,
+    ];
+
+    /// the profile of the chain.
+    pub fn profile(self) -> &'static Profile {
+        match self {
+            Chain::Arbitrum => &ARBITRUM,
+            Chain::Optimism => &OPTIMISM,
+            Chain::ZksyncEra => &ZKSYNC_ERA,
+            Chain::PolygonZkevm => &POLYGON_ZKEVM,
+        }
+    }
+
+    /// the chain with the id `id`, if it has a profile.
+    pub fn from_id(id: u64) -> Option<Chain> {
+        Chain::ALL
+            .into_iter()
+            .find(|chain| chain.profile().ids.contains(&id))
+    }
+
+    /// returns true if the chain runs `opcode`.
+    pub fn supports(self, opcode: u8) -> bool {
+        let profile = self.profile();
+        profile.fork.supports(opcode) && !profile.unsupported.contains(&opcode)
+    }
+}
+
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => f.write_str(value.get_name()),
+            None => write!(f, "{:?}", self),
+        }
+    }
+}
+
+/// parses a chain with a profile, given by name (e.g. `zksync-era`) or by name or id as
+/// `explorer::parse_chain` reads it (e.g. `base`, `42161`).
+///
+/// # example
+/// ```
+/// use ebo::chain::{self, Chain};
+///
+/// assert_eq!(chain::parse("arbitrum").unwrap(), Chain::Arbitrum);
+/// assert_eq!(chain::parse("8453").unwrap(), Chain::Optimism);
+/// assert!(chain::parse("mainnet").is_err());
+/// ```
+pub fn parse(source: &str) -> anyhow::Result<Chain> {
+    if let Ok(chain) = Chain::from_str(source, true) {
+        return Ok(chain);
+    }
+    let id = explorer::parse_chain(source)?;
+    Chain::from_id(id).ok_or_else(|| {
+        anyhow::anyhow!(
+            "no opcode profile for chain '{}'; known: {}",
+            source,
+            Chain::ALL.map(|chain| chain.to_string()).join(", ")
+        )
+    })
+}
+
+/// what the output has to run on: a fork, further narrowed by the profile of a chain if one is
+/// given. a fork alone converts into a target, so anything taking a target takes a fork too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Target {
+    /// fork whose opcodes are allowed.
+    pub fork: Fork,
+    /// chain whose profile restricts them further.
+    pub chain: Option<Chain>,
+}
+
+impl Target {
+    /// the target of `fork` on `chain`.
+    pub fn new(fork: Fork, chain: Option<Chain>) -> Self {
+        Target { fork, chain }
+    }
+
+    /// returns true if `opcode` is defined in the fork and runs on the chain.
+    pub fn supports(self, opcode: u8) -> bool {
+        self.fork.supports(opcode) && self.chain.is_none_or(|chain| chain.supports(opcode))
+    }
+
+    /// returns true if every instruction of `code` is supported, see `Fork::admits`.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::chain::{Chain, Target};
+    /// use ebo::Fork;
+    ///
+    /// let code = [0x5F, 0x5C]; // PUSH0, TLOAD
+    /// assert!(Target::from(Fork::Cancun).admits(&code));
+    /// assert!(!Target::new(Fork::Cancun, Some(Chain::PolygonZkevm)).admits(&code));
+    /// ```
+    pub fn admits(self, code: &[u8]) -> bool {
+        disassemble(code)
+            .iter()
+            .all(|ins| self.supports(ins.opcode))
+    }
+
+    /// returns true if `opcode` behaves on the chain as it does on ethereum.
+    pub fn reliable(self, opcode: u8) -> bool {
+        self.chain
+            .is_none_or(|chain| !chain.profile().modified.contains(&opcode))
+    }
+
+    /// returns true if gas is accounted as on ethereum, see `Profile::mainnet_gas`.
+    pub fn mainnet_gas(self) -> bool {
+        self.chain.is_none_or(|chain| chain.profile().mainnet_gas)
+    }
+}
+
+impl From<Fork> for Target {
+    fn from(fork: Fork) -> Self {
+        Target { fork, chain: None }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fork = self.fork.to_possible_value().map_or_else(
+            || format!("{:?}", self.fork),
+            |value| value.get_name().to_string(),
+        );
+        match self.chain {
+            Some(chain) => write!(f, "{} ({})", chain, fork),
+            None => f.write_str(&fork),
+        }
+    }
+}
@@ -0,0 +1,864 @@
+/// configuration file support for ebo.
+/// an `ebo.toml` describes a whole obfuscation run (seed, passes, probabilities, output) so it does
+/// not have to be spelled out in command-line flags every time. flags given on the command line
+/// override the values from the file.
+use crate::abi::{self, Function};
+use crate::cache::BlockCache;
+use crate::chain::{Chain, Target};
+use crate::dispatcher::{self, Selection};
+use crate::eof::{self, EofMode};
+use crate::error::EboError;
+use crate::evm::{self, Fork};
+use crate::interface;
+use crate::obfuscator::Obfuscator;
+use crate::passes::env_predicate::{self, Predicate};
+use crate::passes::revert_strings::ReasonMode;
+use crate::passes::{
+    ChaoticShuffle, ConstantPool, DecoyReads, EnvPredicate, ExceptionFlow, FalseBranch, Flower,
+    GasPredicate, Inline, Loops, Outline, Peephole, Pipeline, Push0Compat, PushWidth, ReturnPath,
+    RevertStrings, ScratchLayout, SelectorMask, Spaghetti, StackShuffle, StorageRemap,
+    Substitution, Trampolines, OPT_IN,
+};
+use crate::pcmap::PcMap;
+use crate::plan::Plan;
+use crate::search::{Objective, Search};
+use crate::seed::{self, Key};
+use crate::slotmap::SlotMap;
+use crate::validate;
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// file picked up from the working directory when no `--config` is given.
+pub const DEFAULT_CONFIG_FILE: &str = "ebo.toml";
+
+/// contents of an `ebo.toml`. every field is optional.
+///
+/// # example
+/// ```toml
+/// seed = 7
+/// rounds = 2
+/// fork = "shanghai"
+/// chain = "arbitrum"
+///
+/// [passes]
+/// disabled = ["spaghetti"]
+///
+/// [probabilities]
+/// chaotic-shuffle = 0.5
+/// false-branch = 0.1
+/// intensity = 1.5
+///
+/// [scope]
+/// only-selectors = ["0xa9059cbb", "0x095ea7b3"]
+/// exclude = ["0x120..0x1a0"]
+///
+/// [budget]
+/// max-gas-overhead = 15
+/// max-size = 24576
+///
+/// [search]
+/// candidates = 32
+/// objective = "cyclomatic"
+/// patience = 8
+///
+/// [output]
+/// path = "out/token.hex"
+/// hex = true
+/// ```
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// random seed for obfuscation.
+    pub seed: Option<u64>,
+    /// number of times the pipeline is applied. 1 when unset.
+    pub rounds: Option<usize>,
+    /// fork the output is validated against and whose opcodes passes may emit; also accepted as
+    /// `evm-version`, the name solc uses. cancun when unset.
+    #[serde(alias = "evm-version")]
+    pub fork: Option<Fork>,
+    /// chain the output is deployed to, whose opcode profile (see `chain::Profile`) restricts the
+    /// fork further. none when unset.
+    pub chain: Option<Chain>,
+    /// id of the chain the output is deployed to, which `env-predicate` compares `chainid` with.
+    /// the id of `chain` when unset and its profile has only one.
+    #[serde(rename = "chain-id")]
+    pub chain_id: Option<u64>,
+    /// whether `revert-strings` encodes the revert reasons it finds or strips them. encoded when
+    /// unset.
+    #[serde(rename = "revert-strings")]
+    pub revert_strings: Option<ReasonMode>,
+    /// whether `storage-remap` runs, first, in front of the enabled passes. the pass moves the
+    /// storage of the contract and is never part of the standard pipeline, see `StorageRemap`. off
+    /// when unset.
+    #[serde(rename = "storage-remap")]
+    pub storage_remap: Option<bool>,
+    /// which passes run.
+    pub passes: PassesConfig,
+    /// per-pass insertion probabilities.
+    pub probabilities: Probabilities,
+    /// which parts of the contract are obfuscated.
+    pub scope: ScopeConfig,
+    /// limits on the cost of the obfuscated code.
+    pub budget: BudgetConfig,
+    /// seed search.
+    pub search: SearchConfig,
+    /// where and how the result is written.
+    pub output: OutputConfig,
+    /// secret the randomness is keyed with, see `Obfuscator::use_key`. never read from or written
+    /// to a file, so it stays out of the fingerprint.
+    #[serde(skip)]
+    pub key: Option<Key>,
+    /// cache of transformed blocks shared by the runs, see `Obfuscator::use_cache`. never read
+    /// from or written to a file.
+    #[serde(skip)]
+    pub cache: Option<Arc<BlockCache>>,
+}
+
+/// result of `Config::obfuscate`.
+#[derive(Debug, Clone)]
+pub struct Obfuscation {
+    /// the obfuscated code.
+    pub bytecode: Vec<u8>,
+    /// seed the code was made with (the kept seed, after a search); passing it as the seed with
+    /// the same configuration reproduces the code.
+    pub seed: u64,
+    /// number of transformations each pass applied, keyed by pass name.
+    pub stats: BTreeMap<&'static str, usize>,
+    /// program counters of the code mapped to those of the input, see `PcMap`; `None` for eof
+    /// containers.
+    pub pc_map: Option<PcMap>,
+    /// storage slots of the input the code reads and writes elsewhere, see `SlotMap`.
+    pub slots: SlotMap,
+}
+
+/// pass selection.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PassesConfig {
+    /// passes to run, in order. the standard pipeline when unset.
+    pub enabled: Option<Vec<String>>,
+    /// passes removed from the enabled list.
+    pub disabled: Vec<String>,
+}
+
+/// probabilities overriding the defaults of the individual passes, keyed by pass name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Probabilities {
+    /// chance that a basic block is shuffled.
+    pub chaotic_shuffle: Option<f64>,
+    /// chance that an add is substituted.
+    pub substitution: Option<f64>,
+    /// chance that the stack layout of a basic block is shuffled.
+    pub stack_shuffle: Option<f64>,
+    /// chance that a jumpi is followed by a false branch.
+    pub false_branch: Option<f64>,
+    /// chance that a group of scratch space temporaries is moved.
+    pub scratch_layout: Option<f64>,
+    /// chance that an internal call is inlined.
+    pub inline: Option<f64>,
+    /// chance that a loop is reshaped.
+    pub loops: Option<f64>,
+    /// chance that a basic block is guarded by a gas predicate.
+    pub gas_predicate: Option<f64>,
+    /// chance that a basic block is guarded by an environment predicate.
+    pub env_predicate: Option<f64>,
+    /// chance that a jump is routed past a trap, or a block through a failing call.
+    pub exception_flow: Option<f64>,
+    /// chance that a return or revert is routed through a shared epilogue.
+    pub return_path: Option<f64>,
+    /// chance that a stop or return is followed by flower instructions.
+    pub flower: Option<f64>,
+    /// chance that a terminator is followed by, or a function block guarded by, a decoy storage
+    /// read.
+    pub decoy_reads: Option<f64>,
+    /// chance that an identity is inserted at a given position.
+    pub peephole: Option<f64>,
+    /// chance that a direct jump is routed through a chain of trampolines.
+    pub trampolines: Option<f64>,
+    /// chance that a push is re-encoded with a wider immediate.
+    pub push_width: Option<f64>,
+    /// chance that a fragment is cut at a given instruction.
+    pub spaghetti: Option<f64>,
+    /// multiplier applied to every probability (capped at 1), trading size and gas for strength.
+    pub intensity: Option<f64>,
+}
+
+impl Probabilities {
+    /// returns the probability `pass` runs with: the configured value, or `default` if none is
+    /// configured, scaled by the intensity.
+    fn resolve(&self, pass: &str, configured: Option<f64>, default: f64) -> anyhow::Result<f64> {
+        let intensity = self.intensity.unwrap_or(1.0);
+        if !(intensity.is_finite() && intensity >= 0.0) {
+            bail!("intensity must be a non-negative number, got {}", intensity);
+        }
+        let p = configured.unwrap_or(default);
+        if !(0.0..=1.0).contains(&p) {
+            bail!(
+                "probability of '{}' must be between 0 and 1, got {}",
+                pass,
+                p
+            );
+        }
+        Ok((p * intensity).min(1.0))
+    }
+}
+
+/// obfuscation scope.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ScopeConfig {
+    /// obfuscate only the external functions with these selectors (hex, e.g. `"0xa9059cbb"`).
+    pub only_selectors: Vec<String>,
+    /// leave the external functions with these selectors untouched.
+    pub skip_selectors: Vec<String>,
+    /// byte ranges of the input passed through verbatim (e.g. `"0x120..0x1a0"`).
+    pub exclude: Vec<String>,
+}
+
+impl ScopeConfig {
+    /// returns the function selection to scope obfuscation to, if any.
+    /// fails on malformed selectors and when both lists are given.
+    pub fn selection(&self) -> anyhow::Result<Option<Selection>> {
+        let parse = |selectors: &[String]| -> anyhow::Result<Vec<[u8; 4]>> {
+            selectors.iter().map(|s| parse_selector(s)).collect()
+        };
+        match (
+            self.only_selectors.is_empty(),
+            self.skip_selectors.is_empty(),
+        ) {
+            (true, true) => Ok(None),
+            (false, true) => Ok(Some(Selection::Only(parse(&self.only_selectors)?))),
+            (true, false) => Ok(Some(Selection::Skip(parse(&self.skip_selectors)?))),
+            (false, false) => bail!("only-selectors and skip-selectors cannot be combined"),
+        }
+    }
+
+    /// replaces the functions given by name or signature in the selector lists (e.g.
+    /// `transfer` or `transfer(address,uint256)`) with their selectors in `functions`.
+    /// fails on names the abi does not have, or shares between overloads.
+    pub fn resolve_names(&mut self, functions: &[Function]) -> anyhow::Result<()> {
+        for selector in self
+            .only_selectors
+            .iter_mut()
+            .chain(self.skip_selectors.iter_mut())
+        {
+            if parse_selector(selector).is_err() {
+                let function = abi::find(functions, selector.trim())?;
+                *selector = format!("0x{}", hex::encode(function.selector()));
+            }
+        }
+        Ok(())
+    }
+
+    /// returns the byte ranges to pass through verbatim.
+    /// fails on malformed or empty ranges.
+    pub fn excluded(&self) -> anyhow::Result<Vec<Range<usize>>> {
+        self.exclude.iter().map(|s| parse_range(s)).collect()
+    }
+}
+
+/// parses a byte range written as `start..end`, with each bound in decimal or `0x`-prefixed hex.
+fn parse_range(source: &str) -> anyhow::Result<Range<usize>> {
+    let parse = |bound: &str| -> anyhow::Result<usize> {
+        let bound = bound.trim();
+        match bound.strip_prefix("0x") {
+            Some(digits) => usize::from_str_radix(digits, 16),
+            None => bound.parse(),
+        }
+        .with_context(|| format!("invalid bound '{}' in range '{}'", bound, source))
+    };
+    let Some((start, end)) = source.split_once("..") else {
+        bail!("invalid range '{}', expected start..end", source);
+    };
+    let range = parse(start)?..parse(end)?;
+    if range.is_empty() {
+        bail!("range '{}' is empty", source);
+    }
+    Ok(range)
+}
+
+/// parses a 4-byte function selector written in hex, with or without a `0x` prefix.
+fn parse_selector(source: &str) -> anyhow::Result<[u8; 4]> {
+    let digits = source.trim().trim_start_matches("0x");
+    let bytes = hex::decode(digits).with_context(|| format!("invalid selector '{}'", source))?;
+    bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("selector '{}' is not 4 bytes long", source))
+}
+
+/// cost limits.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BudgetConfig {
+    /// largest gas overhead, in percent, that obfuscation may add to any path.
+    pub max_gas_overhead: Option<f64>,
+    /// largest size of the obfuscated code in bytes. the eip-170 limit of 24,576 when unset.
+    pub max_size: Option<usize>,
+}
+
+impl BudgetConfig {
+    /// returns the gas overhead limit as a fraction, if any. fails on negative limits.
+    pub fn gas_overhead(&self) -> anyhow::Result<Option<f64>> {
+        match self.max_gas_overhead {
+            Some(percent) if !(percent.is_finite() && percent >= 0.0) => {
+                bail!(
+                    "max-gas-overhead must be a non-negative percentage, got {}",
+                    percent
+                )
+            }
+            limit => Ok(limit.map(|percent| percent / 100.0)),
+        }
+    }
+}
+
+/// parses a percentage such as `15%` or `15`.
+pub fn parse_percent(source: &str) -> Result<f64, String> {
+    source
+        .trim()
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("invalid percentage '{}'", source))
+}
+
+/// seed search options.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    /// number of seeds to try. no search when unset.
+    pub candidates: Option<usize>,
+    /// metric to maximize.
+    pub objective: Objective,
+    /// stop after this many candidates without improvement.
+    pub patience: Option<usize>,
+}
+
+/// output options.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// output file, or `-` for hex on stdout. `obfuscated.bin` when unset.
+    pub path: Option<PathBuf>,
+    /// write the bytecode as a hex string instead of raw bytes.
+    pub hex: bool,
+}
+
+impl Config {
+    /// parses a configuration from toml source.
+    pub fn from_toml(source: &str) -> anyhow::Result<Self> {
+        toml::from_str(source).map_err(|err| EboError::parse("configuration", err).into())
+    }
+
+    /// reads the configuration from `path`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read configuration file {:?}", path))?;
+        Self::from_toml(&source).with_context(|| format!("in {:?}", path))
+    }
+
+    /// reads the configuration from `path` if given, otherwise from `ebo.toml` in the working
+    /// directory if it exists, otherwise returns the default configuration.
+    pub fn discover(path: Option<&Path>) -> anyhow::Result<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::load(Path::new(DEFAULT_CONFIG_FILE))
+            }
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// builds the pass pipeline described by this configuration.
+    /// fails on unknown pass names and on probabilities outside `[0, 1]`.
+    pub fn pipeline(&self) -> anyhow::Result<Pipeline> {
+        let standard = Pipeline::default().names();
+        let mut enabled: Vec<&str> = match &self.passes.enabled {
+            Some(names) => names.iter().map(String::as_str).collect(),
+            None => standard.clone(),
+        };
+        let known: Vec<&str> = standard.iter().chain(OPT_IN).copied().collect();
+        for name in enabled
+            .iter()
+            .copied()
+            .chain(self.passes.disabled.iter().map(String::as_str))
+        {
+            if !known.contains(&name) {
+                bail!(
+                    "unknown pass '{}', expected one of: {}",
+                    name,
+                    known.join(", ")
+                );
+            }
+        }
+        if self.storage_remap == Some(true) && !enabled.contains(&"storage-remap") {
+            enabled.insert(0, "storage-remap");
+        }
+
+        let p = &self.probabilities;
+        let mut pipeline = Pipeline::new();
+        for name in enabled {
+            pipeline = match name {
+                "storage-remap" => pipeline.with(StorageRemap),
+                "push0-compat" => pipeline.with(Push0Compat),
+                "decoy-reads" => pipeline.with(DecoyReads {
+                    probability: p.resolve(
+                        name,
+                        p.decoy_reads,
+                        DecoyReads::default().probability,
+                    )?,
+                }),
+                "selector-mask" => pipeline.with(SelectorMask),
+                "revert-strings" => pipeline.with(RevertStrings {
+                    mode: self.revert_strings.unwrap_or_default(),
+                }),
+                "scratch-layout" => pipeline.with(ScratchLayout {
+                    probability: p.resolve(
+                        name,
+                        p.scratch_layout,
+                        ScratchLayout::default().probability,
+                    )?,
+                }),
+                "inline" => pipeline.with(Inline {
+                    probability: p.resolve(name, p.inline, Inline::default().probability)?,
+                }),
+                "outline" => pipeline.with(Outline),
+                "loops" => pipeline.with(Loops {
+                    probability: p.resolve(name, p.loops, Loops::default().probability)?,
+                }),
+                "chaotic-shuffle" => pipeline.with(ChaoticShuffle {
+                    probability: p.resolve(
+                        name,
+                        p.chaotic_shuffle,
+                        ChaoticShuffle::default().probability,
+                    )?,
+                }),
+                "substitution" => pipeline.with(Substitution {
+                    probability: p.resolve(
+                        name,
+                        p.substitution,
+                        Substitution::default().probability,
+                    )?,
+                }),
+                "stack-shuffle" => pipeline.with(StackShuffle {
+                    probability: p.resolve(
+                        name,
+                        p.stack_shuffle,
+                        StackShuffle::default().probability,
+                    )?,
+                }),
+                "false-branch" => pipeline.with(FalseBranch {
+                    probability: p.resolve(
+                        name,
+                        p.false_branch,
+                        FalseBranch::default().probability,
+                    )?,
+                }),
+                "gas-predicate" => pipeline.with(GasPredicate {
+                    probability: p.resolve(
+                        name,
+                        p.gas_predicate,
+                        GasPredicate::default().probability,
+                    )?,
+                }),
+                "env-predicate" => pipeline.with(EnvPredicate {
+                    probability: p.resolve(
+                        name,
+                        p.env_predicate,
+                        EnvPredicate::default().probability,
+                    )?,
+                    chain_id: self.chain_id()?,
+                }),
+                "exception-flow" => pipeline.with(ExceptionFlow {
+                    probability: p.resolve(
+                        name,
+                        p.exception_flow,
+                        ExceptionFlow::default().probability,
+                    )?,
+                }),
+                "return-path" => pipeline.with(ReturnPath {
+                    probability: p.resolve(
+                        name,
+                        p.return_path,
+                        ReturnPath::default().probability,
+                    )?,
+                    ..ReturnPath::default()
+                }),
+                "flower" => pipeline.with(Flower {
+                    probability: p.resolve(name, p.flower, Flower::default().probability)?,
+                }),
+                "peephole" => pipeline.with(Peephole {
+                    probability: p.resolve(name, p.peephole, Peephole::default().probability)?,
+                }),
+                "trampolines" => pipeline.with(Trampolines {
+                    probability: p.resolve(
+                        name,
+                        p.trampolines,
+                        Trampolines::default().probability,
+                    )?,
+                }),
+                "constant-pool" => pipeline.with(ConstantPool),
+                "push-width" => pipeline.with(PushWidth {
+                    probability: p.resolve(name, p.push_width, PushWidth::default().probability)?,
+                }),
+                "spaghetti" => pipeline.with(Spaghetti {
+                    probability: p.resolve(name, p.spaghetti, Spaghetti::default().probability)?,
+                }),
+                _ => unreachable!("pass names are validated above"),
+            };
+        }
+        for name in &self.passes.disabled {
+            pipeline = pipeline.without(name);
+        }
+        Ok(pipeline)
+    }
+
+    /// the id of the chain the output is deployed to: the configured one, or else the only id of
+    /// the profile of `chain`.
+    /// fails on an id that is not one of the ids of `chain`.
+    pub fn chain_id(&self) -> anyhow::Result<Option<u64>> {
+        let Some(id) = self.chain_id else {
+            return Ok(self.chain.and_then(|chain| match chain.profile().ids {
+                [id] => Some(*id),
+                _ => None,
+            }));
+        };
+        let target = Target::new(self.fork.unwrap_or_default(), self.chain);
+        if let Err(reason) = env_predicate::check(Predicate::ChainId(id), target) {
+            bail!("{}", reason);
+        }
+        Ok(Some(id))
+    }
+
+    /// code ranges of `bytecode` this configuration keeps verbatim: everything outside the selected
+    /// functions, plus the excluded ranges.
+    pub fn frozen(&self, bytecode: &[u8]) -> anyhow::Result<Vec<Range<usize>>> {
+        let mut frozen = match self.scope.selection()? {
+            Some(selection) => dispatcher::frozen_ranges(bytecode, &selection),
+            None => Vec::new(),
+        };
+        frozen.extend(self.scope.excluded()?);
+        Ok(frozen)
+    }
+
+    /// sha-256 of the configuration written back as toml, as hex. two runs with the same
+    /// fingerprint, input, and seed produce the same output.
+    pub fn fingerprint(&self) -> anyhow::Result<String> {
+        let source = toml::to_string(self).context("cannot serialize the configuration")?;
+        Ok(hex::encode(Sha256::digest(source)))
+    }
+
+    /// this configuration with everything left to defaults spelled out, such that obfuscating with
+    /// it and `seed` gives what `obfuscate` gave with the seed it kept: the seed, the passes in the
+    /// order they run, the probability of every pass with the intensity applied, and the chain id
+    /// the environment predicates use. the search and the output settings, which do not change the
+    /// code, are cleared, and so is the key.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::Config;
+    ///
+    /// let source = "[passes]\ndisabled = [\"spaghetti\"]\n[probabilities]\nintensity = 0.5";
+    /// let config = Config::from_toml(source).unwrap();
+    /// let resolved = config.resolved(7).unwrap();
+    /// assert_eq!(resolved.seed, Some(7));
+    /// assert_eq!(resolved.probabilities.substitution, Some(0.25));
+    /// assert_eq!(resolved.pipeline().unwrap().names(), config.pipeline().unwrap().names());
+    /// ```
+    pub fn resolved(&self, seed: u64) -> anyhow::Result<Config> {
+        let names = self.pipeline()?.names();
+        let p = &self.probabilities;
+        let resolve = |name: &str, configured: Option<f64>, default: f64| {
+            p.resolve(name, configured, default).map(Some)
+        };
+        Ok(Config {
+            seed: Some(seed),
+            rounds: Some(self.rounds.unwrap_or(1)),
+            fork: Some(self.fork.unwrap_or_default()),
+            chain: self.chain,
+            chain_id: self.chain_id()?,
+            revert_strings: Some(self.revert_strings.unwrap_or_default()),
+            storage_remap: Some(names.contains(&"storage-remap")),
+            passes: PassesConfig {
+                enabled: Some(names.iter().map(|name| name.to_string()).collect()),
+                disabled: Vec::new(),
+            },
+            probabilities: Probabilities {
+                chaotic_shuffle: resolve(
+                    "chaotic-shuffle",
+                    p.chaotic_shuffle,
+                    ChaoticShuffle::default().probability,
+                )?,
+                substitution: resolve(
+                    "substitution",
+                    p.substitution,
+                    Substitution::default().probability,
+                )?,
+                stack_shuffle: resolve(
+                    "stack-shuffle",
+                    p.stack_shuffle,
+                    StackShuffle::default().probability,
+                )?,
+                false_branch: resolve(
+                    "false-branch",
+                    p.false_branch,
+                    FalseBranch::default().probability,
+                )?,
+                scratch_layout: resolve(
+                    "scratch-layout",
+                    p.scratch_layout,
+                    ScratchLayout::default().probability,
+                )?,
+                inline: resolve("inline", p.inline, Inline::default().probability)?,
+                loops: resolve("loops", p.loops, Loops::default().probability)?,
+                gas_predicate: resolve(
+                    "gas-predicate",
+                    p.gas_predicate,
+                    GasPredicate::default().probability,
+                )?,
+                env_predicate: resolve(
+                    "env-predicate",
+                    p.env_predicate,
+                    EnvPredicate::default().probability,
+                )?,
+                exception_flow: resolve(
+                    "exception-flow",
+                    p.exception_flow,
+                    ExceptionFlow::default().probability,
+                )?,
+                return_path: resolve(
+                    "return-path",
+                    p.return_path,
+                    ReturnPath::default().probability,
+                )?,
+                flower: resolve("flower", p.flower, Flower::default().probability)?,
+                decoy_reads: resolve(
+                    "decoy-reads",
+                    p.decoy_reads,
+                    DecoyReads::default().probability,
+                )?,
+                peephole: resolve("peephole", p.peephole, Peephole::default().probability)?,
+                trampolines: resolve(
+                    "trampolines",
+                    p.trampolines,
+                    Trampolines::default().probability,
+                )?,
+                push_width: resolve("push-width", p.push_width, PushWidth::default().probability)?,
+                spaghetti: resolve("spaghetti", p.spaghetti, Spaghetti::default().probability)?,
+                intensity: None,
+            },
+            scope: ScopeConfig {
+                only_selectors: self.scope.only_selectors.clone(),
+                skip_selectors: self.scope.skip_selectors.clone(),
+                exclude: self.scope.exclude.clone(),
+            },
+            budget: BudgetConfig {
+                max_gas_overhead: self.budget.max_gas_overhead,
+                max_size: self.budget.max_size,
+            },
+            search: SearchConfig::default(),
+            output: OutputConfig::default(),
+            key: None,
+            cache: None,
+        })
+    }
+
+    /// builds an obfuscator for `bytecode` set up as this configuration describes: its pipeline,
+    /// scope, rounds, fork, chain, budgets, key, and block cache. the seed is passed separately,
+    /// so a search can try several.
+    pub fn obfuscator(&self, bytecode: &[u8], seed: u64) -> anyhow::Result<Obfuscator> {
+        let mut builder = Obfuscator::builder()
+            .seed(seed)
+            .pipeline(self.pipeline()?)
+            .fork(self.fork.unwrap_or_default());
+        if let Some(chain) = self.chain {
+            builder = builder.chain(chain);
+        }
+        for range in self.frozen(bytecode)? {
+            builder = builder.freeze(range);
+        }
+        if let Some(key) = &self.key {
+            builder = builder.key(key.clone());
+        }
+        if let Some(cache) = &self.cache {
+            builder = builder.cache(cache.clone());
+        }
+        if let Some(rounds) = self.rounds {
+            anyhow::ensure!(rounds >= 1, "rounds must be at least 1");
+            builder = builder.rounds(rounds);
+        }
+        if let Some(max_size) = self.budget.max_size {
+            builder = builder.max_size(max_size);
+        }
+        if let Some(overhead) = self.budget.gas_overhead()? {
+            builder = builder.gas_budget(overhead);
+        }
+        Ok(builder.build(bytecode))
+    }
+
+    /// obfuscates `bytecode` as this configuration describes, searching for the best seed from
+    /// `seed` on if the configuration asks for a search. output with validation problems the
+    /// input did not have is refused, and so is output whose selectors, event topics, or return
+    /// shapes differ from those of the input (see `Interface`).
+    ///
+    /// # arguments
+    /// * `bytecode` - runtime code to obfuscate.
+    /// * `seed` - seed of the run, or the first seed of the search.
+    ///
+    /// # returns
+    /// the obfuscated code, with the seed it was made with and what every pass did.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::Config;
+    ///
+    /// let config = Config::from_toml("[passes]\ndisabled = [\"spaghetti\"]").unwrap();
+    /// let bytecode = [0x60, 0x01, 0x60, 0x02, 0x01, 0x00]; // PUSH1 1, PUSH1 2, ADD, STOP
+    /// let obfuscation = config.obfuscate(&bytecode, 42).unwrap();
+    /// assert_eq!(obfuscation.seed, 42);
+    /// ```
+    pub fn obfuscate(&self, bytecode: &[u8], seed: u64) -> anyhow::Result<Obfuscation> {
+        if eof::is_container(bytecode) {
+            return self.obfuscate_eof(bytecode, seed);
+        }
+        if let Some(selection) = self.scope.selection()? {
+            info!(
+                "Keeping {} code regions outside the selected functions",
+                dispatcher::frozen_ranges(bytecode, &selection).len()
+            );
+        }
+        let excluded = self.scope.excluded()?;
+        if let Some(range) = excluded.iter().find(|range| range.end > bytecode.len()) {
+            warn!(
+                "Excluded range {:#x}..{:#x} extends past the end of the bytecode",
+                range.start, range.end
+            );
+        }
+        let target = Target::new(self.fork.unwrap_or_default(), self.chain);
+        if !target.mainnet_gas() && self.budget.max_gas_overhead.is_some() {
+            warn!(
+                "Gas overhead is estimated with Ethereum's costs, which {} does not use",
+                target
+            );
+        }
+        let build = |seed: u64| self.obfuscator(bytecode, seed);
+
+        let (obfuscated, seed, stats, pc_map, slots) = match self.search.candidates {
+            Some(candidates) if candidates > 1 => {
+                let search = Search {
+                    objective: self.search.objective,
+                    candidates,
+                    patience: self.search.patience,
+                    max_size: self.budget.max_size.unwrap_or(evm::MAX_CODE_SIZE),
+                };
+                let outcome = search.run(seed, build)?;
+                info!(
+                    "Search kept seed {} ({:?} score {:.1}) after trying {} of {} candidates",
+                    outcome.seed, search.objective, outcome.score, outcome.tried, candidates
+                );
+                (
+                    outcome.bytecode,
+                    outcome.seed,
+                    outcome.stats,
+                    outcome.pc_map,
+                    outcome.slots,
+                )
+            }
+            _ => {
+                let mut obfuscator = build(seed)?;
+                let obfuscated = obfuscator.obfuscate()?;
+                let pc_map = obfuscator.pc_map()?;
+                let slots = obfuscator.slot_map().clone();
+                (obfuscated, seed, obfuscator.stats().clone(), pc_map, slots)
+            }
+        };
+
+        // broken output is never written; problems the input already had are not held against it
+        let introduced = validate::introduced(
+            &validate::validate(bytecode, target),
+            &validate::validate(&obfuscated, target),
+        );
+        if !introduced.is_empty() {
+            return Err(EboError::introduced(introduced, target).into());
+        }
+
+        // nor is output that integrations would notice: other selectors, events, return shapes,
+        // or storage writes, or unknown selectors routed elsewhere
+        interface::check(bytecode, &obfuscated, &slots)?;
+
+        if obfuscated.len() > evm::MAX_CODE_SIZE {
+            warn!(
+                "Output is {} bytes, over the EIP-170 limit of {} bytes; it cannot be deployed",
+                obfuscated.len(),
+                evm::MAX_CODE_SIZE
+            );
+        }
+        Ok(Obfuscation {
+            bytecode: obfuscated,
+            seed,
+            stats,
+            pc_map,
+            slots,
+        })
+    }
+
+    /// decides what `obfuscate` would do to `bytecode` without keeping the output: the run is made
+    /// with the seed `obfuscate` would keep, searching for it first if the configuration asks for a
+    /// search, and its passes are reported block by block, see `Plan`.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::Config;
+    ///
+    /// let bytecode = [0x60, 0x01, 0x60, 0x02, 0x01, 0x00]; // PUSH1 1, PUSH1 2, ADD, STOP
+    /// let plan = Config::default().plan(&bytecode, 42).unwrap();
+    /// assert_eq!(plan.blocks.len(), 1);
+    /// ```
+    pub fn plan(&self, bytecode: &[u8], seed: u64) -> anyhow::Result<Plan> {
+        if eof::is_container(bytecode) {
+            bail!("dry runs are not supported for eof containers");
+        }
+        let seed = match self.search.candidates {
+            Some(candidates) if candidates > 1 => self.obfuscate(bytecode, seed)?.seed,
+            _ => seed,
+        };
+        Ok(self.obfuscator(bytecode, seed)?.plan()?)
+    }
+
+    /// obfuscates the eof container `bytecode` in `EofMode`, with the substitution and false-branch
+    /// probabilities configured for the legacy passes of the same names. the other passes have no
+    /// eof counterpart, and scope, rounds, and budgets do not apply.
+    fn obfuscate_eof(&self, bytecode: &[u8], seed: u64) -> anyhow::Result<Obfuscation> {
+        let enabled = self.pipeline()?.names();
+        let p = &self.probabilities;
+        let resolve = |name: &str, configured: Option<f64>, default: f64| {
+            if enabled.contains(&name) {
+                p.resolve(name, configured, default)
+            } else {
+                Ok(0.0)
+            }
+        };
+        let defaults = EofMode::default();
+        let mode = EofMode {
+            substitution: resolve("substitution", p.substitution, defaults.substitution)?,
+            false_branch: resolve("false-branch", p.false_branch, defaults.false_branch)?,
+        };
+        info!("Obfuscating an EOF container");
+
+        let mut stats = BTreeMap::new();
+        let mut rng = seed::rng(seed, self.key.as_ref());
+        let bytecode = mode.obfuscate(bytecode, &mut rng, &mut stats)?;
+        Ok(Obfuscation {
+            bytecode,
+            seed,
+            stats,
+            pc_map: None,
+            slots: SlotMap::default(),
+        })
+    }
+}
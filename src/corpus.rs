@@ -0,0 +1,306 @@
+/// calldata corpora for the ebo obfuscator.
+/// loads the calldata samples that `ebo verify` replays against the original and the obfuscated
+/// bytecode, either from a json file or from a directory with one sample per file.
+use crate::artifact::{self, Artifact};
+use crate::error::EboError;
+use crate::link;
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::io::{IsTerminal, Read, Write};
+use std::path::Path;
+
+/// one call to replay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// name used when reporting the sample: the file name, or the position in a json corpus.
+    pub name: String,
+    /// input of the call.
+    pub calldata: Vec<u8>,
+}
+
+/// entry of a json corpus: a hex string, or an object with a `calldata` hex string and an optional
+/// `name`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Hex(String),
+    Named {
+        name: Option<String>,
+        calldata: String,
+    },
+}
+
+/// how a bytecode or calldata file is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Format {
+    /// an artifact if the file is a json object, hex if it is nothing but hex digits (with an
+    /// optional `0x` prefix and any whitespace), raw bytes otherwise.
+    #[default]
+    Auto,
+    /// the bytes themselves.
+    Raw,
+    /// a hex string, as written by `solc --bin`, `forge inspect`, `cast code`, or `ebo obfuscate
+    /// --hex`.
+    Hex,
+    /// a compiler artifact, whose deployed bytecode is read (see `artifact::Artifact`).
+    Artifact,
+}
+
+/// decodes `text` as hex, ignoring whitespace (including line breaks inside the string) and a `0x`
+/// prefix. library placeholders of unlinked code decode to their marker addresses (see `link`).
+/// the digits are decoded where they are, so a large file is not copied on the way.
+pub fn decode_hex(text: &str) -> anyhow::Result<Vec<u8>> {
+    let invalid = |err| EboError::parse(&format!("hex {:?}", text.trim()), err);
+    let digits = text.trim_start();
+    let digits = digits.strip_prefix("0x").unwrap_or(digits);
+    let placeholders = link::placeholders(digits);
+    if !placeholders.is_empty() {
+        let digits: String = link::unlink(digits, &placeholders)
+            .split_whitespace()
+            .collect();
+        return hex::decode(digits).map_err(|err| invalid(err).into());
+    }
+    let mut bytes = Vec::with_capacity(digits.len() / 2);
+    let mut nibbles = digits.bytes().filter(|b| !b.is_ascii_whitespace());
+    let mut index = 0;
+    while let Some(high) = nibbles.next() {
+        let Some(low) = nibbles.next() else {
+            return Err(invalid(hex::FromHexError::OddLength).into());
+        };
+        let nibble = |digit: u8, index| {
+            (digit as char)
+                .to_digit(16)
+                .ok_or(hex::FromHexError::InvalidHexCharacter {
+                    c: digit as char,
+                    index,
+                })
+        };
+        let high = nibble(high, index).map_err(invalid)?;
+        let low = nibble(low, index + 1).map_err(invalid)?;
+        bytes.push((high << 4 | low) as u8);
+        index += 2;
+    }
+    Ok(bytes)
+}
+
+/// a writer that writes the hex of the bytes written to it to `inner`, so large code can be written
+/// as hex without encoding all of it first.
+///
+/// # example
+/// ```
+/// use ebo::corpus::HexWriter;
+/// use std::io::Write;
+///
+/// let mut hex = HexWriter::new(Vec::new());
+/// hex.write_all(&[0x60, 0x01]).unwrap();
+/// hex.write_all(&[0x00]).unwrap();
+/// assert_eq!(hex.into_inner(), b"600100");
+/// ```
+pub struct HexWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> HexWriter<W> {
+    /// a writer writing hex to `inner`.
+    pub fn new(inner: W) -> Self {
+        HexWriter { inner }
+    }
+
+    /// the writer the hex was written to.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for HexWriter<W> {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        let mut digits = [0; 8192];
+        for chunk in bytes.chunks(digits.len() / 2) {
+            let digits = &mut digits[..chunk.len() * 2];
+            hex::encode_to_slice(chunk, digits).expect("the buffer fits the chunk");
+            self.inner.write_all(digits)?;
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// returns true if `bytes` reads as a hex string: only hex digits, library placeholders, and
+/// whitespace behind an optional `0x` prefix, and not empty unless the prefix is there (`cast code`
+/// prints `0x` for an account without code).
+fn looks_like_hex(bytes: &[u8]) -> bool {
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return false;
+    };
+    let text = text.trim_start();
+    let (prefixed, digits) = match text.strip_prefix("0x") {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    // placeholders are only replaced where there are any, so plain hex is not copied
+    let placeholders = link::placeholders(digits);
+    let digits = match placeholders.is_empty() {
+        true => Cow::Borrowed(digits),
+        false => Cow::Owned(link::unlink(digits, &placeholders)),
+    };
+    (prefixed || !digits.trim().is_empty())
+        && digits
+            .bytes()
+            .all(|b| b.is_ascii_hexdigit() || b.is_ascii_whitespace())
+}
+
+/// returns the format of the contents of a bytecode or calldata file.
+pub fn detect(bytes: &[u8]) -> Format {
+    if artifact::is_artifact(bytes) {
+        Format::Artifact
+    } else if looks_like_hex(bytes) {
+        Format::Hex
+    } else {
+        Format::Raw
+    }
+}
+
+/// decodes the contents of a bytecode or calldata file in `format`.
+pub fn parse_bytes(bytes: Vec<u8>, format: Format) -> anyhow::Result<Vec<u8>> {
+    match format {
+        Format::Auto => {
+            let format = detect(&bytes);
+            parse_bytes(bytes, format)
+        }
+        Format::Raw => Ok(bytes),
+        Format::Hex => {
+            let text = std::str::from_utf8(&bytes)
+                .map_err(|_| EboError::parse("hex input", "it is not valid text"))?;
+            decode_hex(text)
+        }
+        Format::Artifact => Artifact::parse(&bytes)?.runtime(),
+    }
+}
+
+/// reads a bytecode or calldata file, which holds either raw bytes or the same bytes as a hex string.
+pub fn read_bytes(path: &Path) -> anyhow::Result<Vec<u8>> {
+    read_bytes_as(path, Format::Auto)
+}
+
+/// reads a bytecode or calldata file encoded in `format`.
+pub fn read_bytes_as(path: &Path, format: Format) -> anyhow::Result<Vec<u8>> {
+    let bytes = read_input(path)?;
+    parse_bytes(bytes, format).with_context(|| format!("cannot decode {:?}", path))
+}
+
+/// reads the undecoded contents of a file; a path of `-` reads standard input.
+pub fn read_input(path: &Path) -> anyhow::Result<Vec<u8>> {
+    if path.as_os_str() == "-" {
+        let mut stdin = std::io::stdin();
+        if stdin.is_terminal() {
+            bail!(
+                "expected bytecode on standard input, e.g. `cast code <address> | ebo obfuscate -`"
+            );
+        }
+        let mut bytes = Vec::new();
+        stdin
+            .read_to_end(&mut bytes)
+            .context("cannot read standard input")?;
+        Ok(bytes)
+    } else {
+        std::fs::read(path).with_context(|| format!("cannot read {:?}", path))
+    }
+}
+
+/// fails if `path` already exists unless `force` is set, so a command can refuse before doing any
+/// work rather than only when it writes.
+pub fn refuse_overwrite(path: &Path, force: bool) -> anyhow::Result<()> {
+    if !force && path.exists() {
+        bail!("{:?} already exists; pass --force to overwrite it", path);
+    }
+    Ok(())
+}
+
+/// opens `path` for writing, failing if it already exists unless `force` is set.
+pub fn create(path: &Path, force: bool) -> anyhow::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .create_new(!force)
+        .open(path)
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::AlreadyExists => {
+                anyhow::anyhow!("{:?} already exists; pass --force to overwrite it", path)
+            }
+            _ => anyhow::Error::new(err).context(format!("cannot write {:?}", path)),
+        })
+}
+
+/// writes `contents` to `path`, failing if it already exists unless `force` is set.
+pub fn write(path: &Path, contents: impl AsRef<[u8]>, force: bool) -> anyhow::Result<()> {
+    create(path, force)?
+        .write_all(contents.as_ref())
+        .with_context(|| format!("cannot write {:?}", path))
+}
+
+/// parses a json corpus: an array whose entries are hex strings or `{"name": ..., "calldata": ...}`
+/// objects.
+pub fn from_json(json: &str) -> anyhow::Result<Vec<Sample>> {
+    let entries: Vec<Entry> = serde_json::from_str(json).context("invalid calldata corpus")?;
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(idx, entry)| {
+            let (name, calldata) = match entry {
+                Entry::Hex(calldata) => (None, calldata),
+                Entry::Named { name, calldata } => (name, calldata),
+            };
+            Ok(Sample {
+                name: name.unwrap_or_else(|| format!("#{}", idx)),
+                calldata: decode_hex(&calldata)
+                    .with_context(|| format!("in corpus entry {}", idx))?,
+            })
+        })
+        .collect()
+}
+
+/// loads the samples at `path`: every file of a directory (in name order, each read with
+/// `read_bytes`), or the entries of a json file.
+///
+/// # arguments
+/// * `path` - corpus directory or json file.
+///
+/// # returns
+/// the samples, in replay order.
+pub fn load(path: &Path) -> anyhow::Result<Vec<Sample>> {
+    let samples = if path.is_dir() {
+        let mut files: Vec<_> = std::fs::read_dir(path)
+            .with_context(|| format!("cannot read corpus directory {:?}", path))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?;
+        files.retain(|file| file.is_file());
+        files.sort();
+        files
+            .iter()
+            .map(|file| {
+                Ok(Sample {
+                    name: file
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into(),
+                    calldata: read_bytes(file)?,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?
+    } else {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read calldata corpus {:?}", path))?;
+        from_json(&json).with_context(|| format!("in {:?}", path))?
+    };
+    if samples.is_empty() {
+        bail!("calldata corpus {:?} is empty", path);
+    }
+    Ok(samples)
+}
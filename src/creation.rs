@@ -0,0 +1,206 @@
+/// creation transactions for the ebo obfuscator.
+/// the data of a transaction that deploys a contract is its creation code followed by the
+/// abi-encoded constructor arguments, which are not code and must not be obfuscated as such. the
+/// constructor is run once to learn the runtime code it returns, which is found where it is embedded
+/// in the creation code; everything after it is arguments. only the runtime code is obfuscated: it
+/// is embedded in place of the original (see `artifact::embed`), and the arguments are appended
+/// again untouched. runtime code obfuscated on its own is made deployable by `wrap`, which puts a
+/// constructor in front of it that returns it, and `create2_address` tells where init code will be
+/// deployed by a `create2` factory.
+///
+/// init code of factories and contracts embedding data runs to hundreds of kilobytes. it is held
+/// once: `Creation::split_owned` takes the transaction data apart without copying it, and
+/// `Creation::write_rebuilt` writes the new data straight to its file.
+use crate::artifact;
+use crate::chain::Target;
+use crate::equivalence::{self, Status};
+use crate::evm::op;
+use anyhow::{bail, ensure, Context};
+use revm::primitives::{keccak256, Address};
+use std::io::Write;
+use std::ops::Range;
+
+/// the deterministic deployment proxy, the `create2` factory at the same address on most chains,
+/// which foundry deploys through.
+pub const DEPLOYER: [u8; 20] = [
+    0x4E, 0x59, 0xB4, 0x48, 0x47, 0xB3, 0x79, 0x57, 0x85, 0x88, 0x92, 0x0C, 0xA7, 0x8F, 0xBF, 0x26,
+    0xC0, 0xB4, 0x95, 0x6C,
+];
+
+/// a creation transaction, split into its parts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Creation {
+    /// the transaction data: the creation code, then the arguments.
+    pub data: Vec<u8>,
+    /// where the runtime code is in `data`. the creation code ends with it.
+    pub runtime: Range<usize>,
+}
+
+impl Creation {
+    /// splits the data of a creation transaction by running its constructor.
+    ///
+    /// # returns
+    /// the parts, or an error if the constructor fails, or returns code that is not embedded
+    /// verbatim in front of a whole number of argument words (as for contracts with immutables,
+    /// whose values the constructor writes into the code it returns).
+    ///
+    /// # example
+    /// ```
+    /// use ebo::creation::Creation;
+    ///
+    /// // PUSH1 3, DUP1, PUSH1 9, PUSH0, CODECOPY, PUSH0, RETURN, then the runtime code
+    /// // PUSH0, SLOAD, STOP and one argument word
+    /// let data = hex::decode(format!("6003 80 6009 5f 39 5f f3 5f5400 {}", "2a".repeat(32))
+    ///     .replace(' ', ""))
+    ///     .unwrap();
+    /// let creation = Creation::split(&data).unwrap();
+    /// assert_eq!(creation.runtime(), &[0x5F, 0x54, 0x00]);
+    /// assert_eq!(creation.arguments(), [0x2A; 32]);
+    /// ```
+    pub fn split(data: &[u8]) -> anyhow::Result<Self> {
+        Creation::split_owned(data.to_vec())
+    }
+
+    /// `split`, keeping `data` rather than copying it.
+    pub fn split_owned(data: Vec<u8>) -> anyhow::Result<Self> {
+        let deployment = equivalence::execute(&data, &[])?;
+        ensure!(
+            deployment.effects.status == Status::Success,
+            "the constructor does not return: {:?}",
+            deployment.effects.status
+        );
+        let runtime = deployment.effects.output;
+        ensure!(!runtime.is_empty(), "the constructor returns no code");
+        // only starts a whole number of argument words from the end are compared
+        let last = data.len().checked_sub(runtime.len());
+        let Some(start) = last.and_then(|last| {
+            (last % 32..=last)
+                .step_by(32)
+                .find(|&start| data[start..start + runtime.len()] == *runtime)
+        }) else {
+            bail!(
+                "the {} bytes of runtime code the constructor returns are not embedded in the \
+                 creation code ahead of the arguments; contracts with immutables need their artifact",
+                runtime.len()
+            );
+        };
+        Ok(Creation {
+            data,
+            runtime: start..start + runtime.len(),
+        })
+    }
+
+    /// creation code: the constructor and the runtime code it returns.
+    pub fn code(&self) -> &[u8] {
+        &self.data[..self.runtime.end]
+    }
+
+    /// abi-encoded constructor arguments following the creation code.
+    pub fn arguments(&self) -> &[u8] {
+        &self.data[self.runtime.end..]
+    }
+
+    /// the runtime code the constructor returns.
+    pub fn runtime(&self) -> &[u8] {
+        &self.data[self.runtime.clone()]
+    }
+
+    /// the data of a creation transaction deploying `obfuscated` instead, with the same arguments.
+    ///
+    /// # returns
+    /// the new data, or an error if `obfuscated` cannot be embedded (see `artifact::embed`).
+    pub fn rebuild(&self, obfuscated: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(self.data.len());
+        self.write_rebuilt(obfuscated, &mut data)?;
+        Ok(data)
+    }
+
+    /// writes the data of `rebuild` to `out` as it goes, without a copy of the creation code.
+    pub fn write_rebuilt(&self, obfuscated: &[u8], out: &mut dyn Write) -> anyhow::Result<()> {
+        artifact::embed_into(self.code(), self.runtime(), obfuscated, out)?;
+        out.write_all(self.arguments())?;
+        Ok(())
+    }
+}
+
+/// init code deploying `runtime`: a constructor that copies the code after it into memory and
+/// returns it (`codecopy(0, offset, length)`, `return(0, length)`), followed by `runtime`. zero is
+/// pushed with `push0` where `target` has it.
+///
+/// # example
+/// ```
+/// use ebo::{creation, equivalence, Fork};
+///
+/// let runtime = [0x5F, 0x54, 0x00]; // PUSH0, SLOAD, STOP
+/// let init = creation::wrap(&runtime, Fork::Cancun);
+/// assert_eq!(hex::encode(&init), "6003 80 6009 5f 39 5f f3 5f5400".replace(' ', ""));
+/// let deployed = equivalence::execute(&init, &[]).unwrap().effects.output;
+/// assert_eq!(deployed, runtime);
+/// ```
+pub fn wrap(runtime: &[u8], target: impl Into<Target>) -> Vec<u8> {
+    let zero: &[u8] = if target.into().supports(op::PUSH0) {
+        &[op::PUSH0]
+    } else {
+        &[op::PUSH1, 0]
+    };
+    // both pushes get the width of the larger value, which the constructor length depends on
+    let mut width = 1;
+    let offset = loop {
+        let length = 2 * (1 + width) + 2 * zero.len() + 3;
+        if (runtime.len().max(length) as u64) < 1 << (8 * width) {
+            break length;
+        }
+        width += 1;
+    };
+    let push = |value: usize| {
+        let mut code = vec![op::PUSH1 + (width - 1) as u8];
+        code.extend_from_slice(&(value as u64).to_be_bytes()[8 - width..]);
+        code
+    };
+    let mut init = push(runtime.len());
+    init.push(op::DUP1);
+    init.extend(push(offset));
+    init.extend_from_slice(zero);
+    init.push(op::CODECOPY);
+    init.extend_from_slice(zero);
+    init.push(op::RETURN);
+    debug_assert_eq!(init.len(), offset);
+    init.extend_from_slice(runtime);
+    init
+}
+
+/// the address of the contract that `deployer` creates with `create2` from `init` and `salt`:
+/// the last 20 bytes of `keccak256(0xff ++ deployer ++ salt ++ keccak256(init))` (eip-1014).
+///
+/// # example
+/// ```
+/// use ebo::creation;
+///
+/// let address = creation::create2_address([0; 20], [0; 32], &[0x00]);
+/// assert_eq!(hex::encode(address), "4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38");
+/// ```
+pub fn create2_address(deployer: [u8; 20], salt: [u8; 32], init: &[u8]) -> [u8; 20] {
+    Address::from(deployer)
+        .create2(salt, keccak256(init))
+        .into()
+}
+
+/// parses a `create2` salt given in hex, with or without `0x`. shorter salts are padded on the
+/// left, so `0x1` is the salt one.
+pub fn parse_salt(source: &str) -> anyhow::Result<[u8; 32]> {
+    let digits = source.trim().trim_start_matches("0x");
+    let digits = if digits.len() % 2 == 1 {
+        format!("0{}", digits)
+    } else {
+        digits.to_string()
+    };
+    let bytes = hex::decode(&digits).with_context(|| format!("invalid salt '{}'", source))?;
+    ensure!(
+        bytes.len() <= 32,
+        "salt '{}' is longer than 32 bytes",
+        source
+    );
+    let mut salt = [0; 32];
+    salt[32 - bytes.len()..].copy_from_slice(&bytes);
+    Ok(salt)
+}
@@ -0,0 +1,416 @@
+/// decompiler evaluation for the ebo obfuscator.
+/// makes resistance to reverse engineering measurable instead of assumed: the original and the
+/// obfuscated code are both run through a decompiler, what it recovers from each is read out of its
+/// output (selectors, string literals, functions, branches), and the evaluation reports how much of
+/// what it recovers from the original it still recovers from the obfuscated code. decompilers are
+/// external programs, run only if they are installed; any other decompiler with a command line can
+/// be run as a custom command. a decompiler that fails or times out on the obfuscated code recovers
+/// nothing from it.
+use anyhow::{bail, Context};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// how long a decompiler may run on one bytecode before it is stopped.
+pub const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// runs started by this process, to give each its own directory.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// a decompiler and how to run it. in its arguments `{hex}` stands for the code as `0x` hex,
+/// `{file}` for a file holding that hex, and `{dir}` for an empty directory to write output to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Decompiler {
+    /// name it is reported by.
+    pub name: String,
+    /// program and arguments.
+    pub command: Vec<String>,
+}
+
+impl Decompiler {
+    /// heimdall-rs, writing solidity-like source; selectors are left unresolved, so they can be
+    /// compared.
+    pub fn heimdall() -> Self {
+        Decompiler::new(
+            "heimdall",
+            "heimdall decompile {hex} --include-sol --skip-resolving --default --output {dir}",
+        )
+    }
+
+    /// panoramix, printing python-like source.
+    pub fn panoramix() -> Self {
+        Decompiler::new("panoramix", "panoramix {hex}")
+    }
+
+    /// the decompilers ebo knows how to run.
+    pub fn known() -> Vec<Self> {
+        vec![Decompiler::heimdall(), Decompiler::panoramix()]
+    }
+
+    /// a decompiler run as `command`, split at whitespace.
+    pub fn new(name: &str, command: &str) -> Self {
+        Decompiler {
+            name: name.to_string(),
+            command: command.split_whitespace().map(str::to_string).collect(),
+        }
+    }
+
+    /// the known decompiler called `name`, or `name` as a custom command.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::decompile::Decompiler;
+    ///
+    /// assert_eq!(Decompiler::parse("heimdall"), Decompiler::heimdall());
+    /// assert_eq!(Decompiler::parse("mytool {file}").command, ["mytool", "{file}"]);
+    /// ```
+    pub fn parse(name: &str) -> Self {
+        Decompiler::known()
+            .into_iter()
+            .find(|known| known.name == name)
+            .unwrap_or_else(|| Decompiler::new(name, name))
+    }
+
+    /// returns true if the program of the decompiler can be started.
+    pub fn installed(&self) -> bool {
+        let Some(program) = self.command.first() else {
+            return false;
+        };
+        Command::new(program)
+            .arg("--help")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok()
+    }
+
+    /// decompiles `code`, giving up after `timeout`.
+    ///
+    /// # returns
+    /// everything the decompiler wrote to `{dir}`, or what it printed if it wrote nothing there;
+    /// or an error if it cannot be run, fails, or runs out of time.
+    pub fn run(&self, code: &[u8], timeout: Duration) -> anyhow::Result<String> {
+        let Some((program, args)) = self.command.split_first() else {
+            bail!("decompiler {} has no command", self.name);
+        };
+        // one directory per run, so runs in parallel do not share files
+        let dir = std::env::temp_dir().join(format!(
+            "ebo-decompile-{}-{}",
+            std::process::id(),
+            RUNS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let output = dir.join("output");
+        std::fs::create_dir_all(&output).with_context(|| format!("cannot create {:?}", output))?;
+        let text = format!("0x{}", hex::encode(code));
+        let file = dir.join("code.hex");
+        std::fs::write(&file, &text).with_context(|| format!("cannot write {:?}", file))?;
+        let args: Vec<String> = args
+            .iter()
+            .map(|arg| {
+                arg.replace("{hex}", &text)
+                    .replace("{file}", &file.to_string_lossy())
+                    .replace("{dir}", &output.to_string_lossy())
+            })
+            .collect();
+
+        let result = run(program, &args, timeout).and_then(|stdout| {
+            let written = read_tree(&output)?;
+            Ok(if written.is_empty() { stdout } else { written })
+        });
+        let _ = std::fs::remove_dir_all(&dir);
+        result.with_context(|| format!("{} failed", self.name))
+    }
+}
+
+/// runs `program` with `args`, stopping it after `timeout`, and returns what it printed.
+fn run(program: &str, args: &[String], timeout: Duration) -> anyhow::Result<String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("cannot run {}; is it installed and on the PATH?", program))?;
+    // the output is read on a thread, so a decompiler printing a lot cannot block on a full pipe
+    let mut stdout = child.stdout.take().context("no standard output")?;
+    let reader = std::thread::spawn(move || {
+        let mut text = String::new();
+        stdout.read_to_string(&mut text).map(|_| text)
+    });
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("it ran for more than {}s", timeout.as_secs());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    let text = reader
+        .join()
+        .map_err(|_| anyhow::anyhow!("cannot read its output"))??;
+    if !status.success() {
+        bail!("it exited with {}", status);
+    }
+    Ok(text)
+}
+
+/// the contents of every file under `dir`, in path order.
+fn read_tree(dir: &Path) -> anyhow::Result<String> {
+    let mut paths = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir).with_context(|| format!("cannot read {:?}", dir))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    let mut text = String::new();
+    for path in paths {
+        text.push_str(&String::from_utf8_lossy(&std::fs::read(&path)?));
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// what a decompiler recovered from some code.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Recovered {
+    /// function selectors, as eight lowercase hex digits.
+    pub selectors: BTreeSet<String>,
+    /// string literals.
+    pub strings: BTreeSet<String>,
+    /// functions defined.
+    pub functions: usize,
+    /// conditionals and loops.
+    pub branches: usize,
+}
+
+/// reads what a decompiler recovered out of its output. selectors are eight hex digits after `0x`,
+/// `Unresolved_` (heimdall) or `unknown` (panoramix); strings are quoted with `"` or `'`; functions
+/// are lines starting with `function` or `def`, and branches lines starting with `if`, `while`,
+/// `for`, or `require`.
+///
+/// # example
+/// ```
+/// use ebo::decompile;
+///
+/// let text = "function Unresolved_a9059cbb(address arg0) public {\n    require(arg0, \"zero\");\n}";
+/// let recovered = decompile::parse(text);
+/// assert!(recovered.selectors.contains("a9059cbb"));
+/// assert!(recovered.strings.contains("zero"));
+/// assert_eq!((recovered.functions, recovered.branches), (1, 1));
+/// ```
+pub fn parse(text: &str) -> Recovered {
+    let mut recovered = Recovered::default();
+    for prefix in ["0x", "Unresolved_", "unknown"] {
+        for (at, _) in text.match_indices(prefix) {
+            let digits: String = text[at + prefix.len()..]
+                .chars()
+                .take_while(char::is_ascii_hexdigit)
+                .collect();
+            if digits.len() == 8 {
+                recovered.selectors.insert(digits.to_lowercase());
+            }
+        }
+    }
+    // only strings that open and close on one line, so an apostrophe in a comment does not swallow
+    // the rest of the output
+    for quote in ['"', '\''] {
+        for line in text.lines() {
+            let closed = line.matches(quote).count() / 2;
+            let inside = line.split(quote).skip(1).step_by(2).take(closed);
+            recovered.strings.extend(
+                inside
+                    .filter(|string| !string.is_empty())
+                    .map(str::to_string),
+            );
+        }
+    }
+    for line in text.lines().map(str::trim_start) {
+        let word = line
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .next()
+            .unwrap_or("");
+        match word {
+            "function" | "def" => recovered.functions += 1,
+            "if" | "while" | "for" | "require" => recovered.branches += 1,
+            _ => {}
+        }
+    }
+    recovered
+}
+
+/// a decompiler run on the original and the obfuscated code.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Evaluation {
+    /// name of the decompiler.
+    pub decompiler: String,
+    /// what it recovered from the original.
+    pub original: Recovered,
+    /// what it recovered from the obfuscated code, nothing if it failed on it.
+    pub obfuscated: Recovered,
+    /// why it failed on the obfuscated code, if it did.
+    pub failure: Option<String>,
+    /// how much of what it recovered from the original it recovered from the obfuscated code.
+    pub recovery: Recovery,
+}
+
+/// shares of what a decompiler recovered from the original that it recovered from the obfuscated
+/// code, each from 0 (nothing) to 1 (everything), and 1 if there was nothing to recover. lower is
+/// better for the obfuscation.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Recovery {
+    /// share of the selectors.
+    pub selectors: f64,
+    /// share of the strings.
+    pub strings: f64,
+    /// share of the functions and branches, counting those of the obfuscated code up to as many
+    /// as the original has, since obfuscation adds branches of its own.
+    pub structure: f64,
+    /// mean of the three.
+    pub overall: f64,
+}
+
+impl Evaluation {
+    /// runs `decompiler` on both codes. only failing on the original is an error.
+    pub fn run(
+        decompiler: &Decompiler,
+        original: &[u8],
+        obfuscated: &[u8],
+        timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        let before = parse(&decompiler.run(original, timeout)?);
+        let (after, failure) = match decompiler.run(obfuscated, timeout) {
+            Ok(text) => (parse(&text), None),
+            Err(err) => (Recovered::default(), Some(format!("{:#}", err))),
+        };
+        Ok(Evaluation::new(&decompiler.name, before, after, failure))
+    }
+
+    /// the evaluation of what a decompiler recovered from either code.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::decompile::{self, Evaluation};
+    ///
+    /// let original = decompile::parse("function Unresolved_a9059cbb() {\n  if (x) { }\n}");
+    /// let obfuscated = decompile::parse("function Unresolved_a9059cbb() {\n}");
+    /// let evaluation = Evaluation::new("heimdall", original, obfuscated, None);
+    /// assert_eq!(evaluation.recovery.structure, 0.5);
+    /// assert_eq!(evaluation.recovery.overall, (1.0 + 1.0 + 0.5) / 3.0);
+    /// ```
+    pub fn new(
+        decompiler: &str,
+        original: Recovered,
+        obfuscated: Recovered,
+        failure: Option<String>,
+    ) -> Self {
+        let selectors = share(&original.selectors, &obfuscated.selectors);
+        let strings = share(&original.strings, &obfuscated.strings);
+        let total = original.functions + original.branches;
+        let found = obfuscated.functions.min(original.functions)
+            + obfuscated.branches.min(original.branches);
+        let structure = match total {
+            0 => 1.0,
+            total => found as f64 / total as f64,
+        };
+        Evaluation {
+            decompiler: decompiler.to_string(),
+            original,
+            obfuscated,
+            failure,
+            recovery: Recovery {
+                selectors,
+                strings,
+                structure,
+                overall: (selectors + strings + structure) / 3.0,
+            },
+        }
+    }
+}
+
+/// share of `original` found in `obfuscated`; 1 if `original` is empty.
+fn share(original: &BTreeSet<String>, obfuscated: &BTreeSet<String>) -> f64 {
+    match original.len() {
+        0 => 1.0,
+        total => original.intersection(obfuscated).count() as f64 / total as f64,
+    }
+}
+
+impl fmt::Display for Evaluation {
+    /// one line per recovered item with both counts and the share still recovered.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (a, b) = (&self.original, &self.obfuscated);
+        writeln!(f, "{}", self.decompiler)?;
+        if let Some(failure) = &self.failure {
+            writeln!(f, "  failed on the obfuscated code: {}", failure)?;
+        }
+        writeln!(
+            f,
+            "  {:<10}  {:>8}  {:>10}  {:>9}",
+            "recovered", "original", "obfuscated", "recovery"
+        )?;
+        // counts of the obfuscated code are capped as for `Recovery::structure`
+        let capped = |a: usize, b: usize| match a {
+            0 => 1.0,
+            a => b.min(a) as f64 / a as f64,
+        };
+        let rows = [
+            (
+                "selectors",
+                a.selectors.len(),
+                b.selectors.len(),
+                self.recovery.selectors,
+            ),
+            (
+                "strings",
+                a.strings.len(),
+                b.strings.len(),
+                self.recovery.strings,
+            ),
+            (
+                "functions",
+                a.functions,
+                b.functions,
+                capped(a.functions, b.functions),
+            ),
+            (
+                "branches",
+                a.branches,
+                b.branches,
+                capped(a.branches, b.branches),
+            ),
+        ];
+        for (name, a, b, recovery) in rows {
+            writeln!(
+                f,
+                "  {:<10}  {:>8}  {:>10}  {:>8.0}%",
+                name,
+                a,
+                b,
+                recovery * 100.0
+            )?;
+        }
+        writeln!(
+            f,
+            "  overall recovery {:.0}%",
+            self.recovery.overall * 100.0
+        )
+    }
+}
@@ -0,0 +1,128 @@
+/// a minimal deobfuscator for the ebo obfuscator.
+/// undoes what a pattern-matching analyst would undo first: it lifts the code and deletes every
+/// instruction pair of the peephole identity library, repeatedly, so identities nested inside each
+/// other disappear too. what survives it is the part of an obfuscation that takes real analysis to
+/// remove, which is what the resilience score measures.
+use crate::evm::op;
+use crate::passes::peephole::{Site, IDENTITIES};
+use crate::reloc::{Item, Label, Program};
+use std::collections::HashMap;
+
+/// removes the peephole identities from `bytecode`.
+///
+/// an identity that only holds at a jump condition (`iszero iszero`) is only removed directly
+/// before the push of a jumpi target. obfuscated code may push a jump target well before the jump
+/// that takes it, so every push of a jumpdest offset is relocated as a code pointer, which can
+/// mistake a constant for one but never leaves a real jump target behind.
+///
+/// # returns
+/// the simplified bytecode, and the number of identities removed. bytecode that cannot be
+/// reassembled is returned unchanged.
+///
+/// # example
+/// ```
+/// use ebo::deobfuscator;
+///
+/// // PUSH1 1, NOT, DUP1, POP, NOT, STOP: dup1 pop goes first, which exposes not not
+/// let (code, removed) = deobfuscator::deobfuscate(&[0x60, 0x01, 0x19, 0x80, 0x50, 0x19, 0x00]);
+/// assert_eq!((code, removed), (vec![0x60, 0x01, 0x00], 2));
+/// ```
+pub fn deobfuscate(bytecode: &[u8]) -> (Vec<u8>, usize) {
+    let mut program = Program::lift(bytecode);
+    program.items = relocate_pushes(std::mem::take(&mut program.items));
+    let mut removed = 0;
+    // an identity between a jump target and its jumpi hides the jump condition until it is gone
+    loop {
+        let (items, count) = strip(&program.items);
+        program.items = items;
+        removed += count;
+        if count == 0 {
+            break;
+        }
+    }
+    match program.assemble() {
+        Ok(code) => (code, removed),
+        Err(_) => (bytecode.to_vec(), 0),
+    }
+}
+
+/// removes the identities of one sweep over `original`.
+///
+/// # returns
+/// the remaining items, and the number of identities removed.
+fn strip(original: &[Item]) -> (Vec<Item>, usize) {
+    let mut items: Vec<Item> = Vec::with_capacity(original.len());
+    let mut removed = 0;
+    for (idx, item) in original.iter().enumerate() {
+        items.push(item.clone());
+        let feeds_jumpi = is_push(original.get(idx + 1))
+            && matches!(
+                original.get(idx + 2),
+                Some(Item::Op {
+                    opcode: op::JUMPI,
+                    ..
+                })
+            );
+        // removing one identity can expose another that ends at the same place
+        while let Some(identity) = IDENTITIES.iter().find(|identity| {
+            (identity.site == Site::Anywhere || feeds_jumpi) && ends_with(&items, identity.code)
+        }) {
+            items.truncate(items.len() - identity.code.len());
+            removed += 1;
+        }
+    }
+    (items, removed)
+}
+
+/// turns every push of the offset of a marked jumpdest into a label push.
+fn relocate_pushes(mut items: Vec<Item>) -> Vec<Item> {
+    let labels: HashMap<usize, Label> = items
+        .windows(2)
+        .filter_map(|pair| match pair {
+            [Item::Mark(label), Item::Op {
+                origin: Some(offset),
+                ..
+            }] => Some((*offset, *label)),
+            _ => None,
+        })
+        .collect();
+    for item in &mut items {
+        let Item::Op { immediate, .. } = item else {
+            continue;
+        };
+        let value = (!immediate.is_empty() && immediate.len() <= 8).then(|| {
+            immediate
+                .iter()
+                .fold(0usize, |acc, &b| (acc << 8) | b as usize)
+        });
+        if let Some(&label) = value.and_then(|value| labels.get(&value)) {
+            *item = Item::PushLabel {
+                label,
+                addend: 0,
+                width: immediate.len(),
+            };
+        }
+    }
+    items
+}
+
+/// returns true if `item` pushes a value: a push instruction or a label push.
+fn is_push(item: Option<&Item>) -> bool {
+    match item {
+        Some(Item::Op { opcode, .. }) => (op::PUSH0..=op::PUSH32).contains(opcode),
+        Some(Item::PushLabel { .. }) => true,
+        _ => false,
+    }
+}
+
+/// returns true if `items` ends with the immediate-free instructions `code`.
+fn ends_with(items: &[Item], code: &[u8]) -> bool {
+    items.len() >= code.len()
+        && items[items.len() - code.len()..]
+            .iter()
+            .zip(code)
+            .all(|(item, &expected)| {
+                matches!(item, Item::Op { opcode, immediate, .. }
+                    if *opcode == expected && immediate.is_empty())
+            })
+}
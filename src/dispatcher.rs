@@ -0,0 +1,342 @@
+/// function dispatcher recognition for the ebo obfuscator.
+/// finds the external functions of a solidity-style contract by matching the selector comparisons of
+/// its dispatcher, and partitions the code into the blocks each function can reach, so obfuscation can
+/// be scoped to chosen functions. once passes rewrite the dispatcher, `Routes` checks that every
+/// selector still reaches its function and that no comparison added to the dispatcher claims a
+/// selector of the contract.
+use crate::cfg::{immediate_value, Cfg};
+use crate::evm::{op, push_size, Instruction};
+use crate::pcmap::PcMap;
+use crate::trace;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Range;
+use tracing::warn;
+
+/// eq opcode, compared against the calldata selector by the dispatcher.
+const EQ: u8 = 0x14;
+/// dup1 opcode.
+const DUP1: u8 = 0x80;
+/// dup2 opcode.
+const DUP2: u8 = 0x81;
+
+/// an external function found in the dispatcher.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// 4-byte function selector.
+    pub selector: [u8; 4],
+    /// offset of the jumpdest the dispatcher jumps to for this selector.
+    pub entry: usize,
+    /// byte ranges of the blocks reachable from `entry` without entering another function's entry.
+    pub blocks: Vec<Range<usize>>,
+}
+
+/// a way the dispatcher of the obfuscated code disagrees with the dispatcher of the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// a call with the selector never reaches a copy of the entry of its function.
+    Unrouted {
+        /// the selector.
+        selector: [u8; 4],
+        /// offset of the entry of its function in the input.
+        entry: usize,
+    },
+    /// a comparison of the obfuscated dispatcher matches the selector of a function of the input
+    /// but jumps somewhere other than a copy of its entry, like a decoy colliding with it.
+    Collision {
+        /// the selector.
+        selector: [u8; 4],
+        /// offset in the obfuscated code the comparison jumps to.
+        target: usize,
+    },
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Inconsistency::Unrouted { selector, entry } => write!(
+                f,
+                "selector 0x{} no longer reaches its entry at {:#06x}",
+                hex::encode(selector),
+                entry
+            ),
+            Inconsistency::Collision { selector, target } => write!(
+                f,
+                "a comparison against selector 0x{} jumps to {:#06x}, not to its function",
+                hex::encode(selector),
+                target
+            ),
+        }
+    }
+}
+
+/// which external functions obfuscation applies to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selection {
+    /// obfuscate only code that belongs exclusively to these functions.
+    Only(Vec<[u8; 4]>),
+    /// obfuscate everything except the code of these functions.
+    Skip(Vec<[u8; 4]>),
+}
+
+/// matches a selector comparison ending at instruction `idx`: `push<n> selector, eq, push target,
+/// jumpi` or `push<n> selector, dup2, eq, push target, jumpi`, optionally preceded by `dup1`.
+fn selector_jump(instructions: &[Instruction], idx: usize) -> Option<([u8; 4], usize)> {
+    let ins = instructions.get(idx)?;
+    if !(op::PUSH1..op::PUSH1 + 4).contains(&ins.opcode)
+        || ins.immediate.len() != push_size(ins.opcode)
+    {
+        return None;
+    }
+    let mut next = idx + 1;
+    if instructions.get(next)?.opcode == DUP2 {
+        next += 1;
+    } else if idx == 0 || instructions[idx - 1].opcode != DUP1 {
+        return None;
+    }
+    if instructions.get(next)?.opcode != EQ {
+        return None;
+    }
+    let target = immediate_value(instructions.get(next + 1)?)?;
+    if instructions.get(next + 2)?.opcode != op::JUMPI {
+        return None;
+    }
+    let mut selector = [0u8; 4];
+    selector[4 - ins.immediate.len()..].copy_from_slice(&ins.immediate);
+    Some((selector, target))
+}
+
+/// finds the external functions of `bytecode`.
+///
+/// the dispatcher is explored from offset 0; every selector comparison inside it (see
+/// `selector_jump`) marks a function entry, which is not explored as part of the dispatcher. each
+/// function then owns the blocks reachable from its entry without entering another function's entry.
+/// blocks shared by several functions (revert paths, abi decoding helpers) appear in all of them.
+///
+/// # arguments
+/// * `bytecode` - runtime bytecode of the contract.
+///
+/// # returns
+/// the functions in order of their entry offset.
+pub fn functions(bytecode: &[u8]) -> Vec<Function> {
+    let graph = Cfg::new(bytecode);
+    let instructions = &graph.instructions;
+    if instructions.is_empty() {
+        return Vec::new();
+    }
+
+    let mut found: Vec<([u8; 4], usize)> = Vec::new();
+    let mut entries = BTreeSet::new();
+    let mut seen = BTreeSet::from([0]);
+    let mut queue = vec![0];
+    while let Some(idx) = queue.pop() {
+        let block = &graph.blocks[idx];
+        for i in block.first..block.last {
+            if let Some((selector, target)) = selector_jump(instructions, i) {
+                if let Some(&entry) = graph.by_offset.get(&target) {
+                    if graph.jumpdests.contains(&target) && entries.insert(entry) {
+                        found.push((selector, target));
+                    }
+                }
+            }
+        }
+        for next in graph.successors(idx) {
+            if !entries.contains(&next) && seen.insert(next) {
+                queue.push(next);
+            }
+        }
+    }
+    found.sort_by_key(|&(_, entry)| entry);
+
+    found
+        .into_iter()
+        .map(|(selector, entry)| {
+            let start = graph.by_offset[&entry];
+            let mut stop = entries.clone();
+            stop.remove(&start);
+            let blocks = graph
+                .reach(start, &stop)
+                .into_iter()
+                .map(|idx| graph.blocks[idx].range.clone())
+                .collect();
+            Function {
+                selector,
+                entry,
+                blocks,
+            }
+        })
+        .collect()
+}
+
+/// byte ranges of `bytecode` to keep verbatim so that only the code chosen by `selection` is
+/// obfuscated. with `Selection::Only`, everything outside the blocks owned exclusively by the chosen
+/// functions is kept, including the dispatcher; with `Selection::Skip`, every block reachable from a
+/// skipped function is kept.
+///
+/// # arguments
+/// * `bytecode` - runtime bytecode of the contract.
+/// * `selection` - functions to obfuscate or to leave alone.
+///
+/// # returns
+/// sorted, non-overlapping ranges to freeze.
+pub fn frozen_ranges(bytecode: &[u8], selection: &Selection) -> Vec<Range<usize>> {
+    let functions = functions(bytecode);
+    let wanted = match selection {
+        Selection::Only(selectors) | Selection::Skip(selectors) => selectors,
+    };
+    for selector in wanted {
+        if !functions.iter().any(|f| f.selector == *selector) {
+            warn!(
+                "Selector 0x{} not found in the dispatcher",
+                hex::encode(selector)
+            );
+        }
+    }
+
+    // blocks are identified by their start offset
+    let starts_of = |chosen: bool| -> BTreeSet<usize> {
+        functions
+            .iter()
+            .filter(|f| wanted.contains(&f.selector) == chosen)
+            .flat_map(|f| f.blocks.iter().map(|block| block.start))
+            .collect()
+    };
+    let blocks = Cfg::new(bytecode).blocks;
+    let frozen: BTreeSet<usize> = match selection {
+        Selection::Only(_) => {
+            let owned = &starts_of(true) - &starts_of(false);
+            blocks
+                .iter()
+                .map(|block| block.range.start)
+                .filter(|start| !owned.contains(start))
+                .collect()
+        }
+        Selection::Skip(_) => starts_of(true),
+    };
+
+    // blocks are contiguous, so neighbouring frozen blocks merge into one range
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for block in blocks
+        .iter()
+        .filter(|block| frozen.contains(&block.range.start))
+    {
+        match merged.last_mut() {
+            Some(last) if last.end == block.range.start => last.end = block.range.end,
+            _ => merged.push(block.range.clone()),
+        }
+    }
+    merged
+}
+
+/// number of zero words passed as the arguments of the calls `Routes` traces.
+const ARGUMENT_WORDS: usize = 8;
+
+/// the way a call with each selector of the input takes through its dispatcher, to check the
+/// dispatcher of code obfuscated from it against.
+#[derive(Debug, Clone)]
+pub struct Routes {
+    /// the functions of the input.
+    functions: Vec<Function>,
+    /// the calldata of the call of every function that reaches its entry, with the offsets the
+    /// call executes from the entry on.
+    calls: Vec<(usize, Vec<u8>, Vec<usize>)>,
+}
+
+impl Routes {
+    /// traces a call with the selector of every function of `original`, followed by zeroed
+    /// arguments. calls that do not reach the entry of their function are not checked.
+    ///
+    /// # returns
+    /// the routes, or an error if the evm rejects a call.
+    pub fn new(original: &[u8]) -> anyhow::Result<Routes> {
+        let functions = functions(original);
+        let mut calls = Vec::new();
+        for (idx, function) in functions.iter().enumerate() {
+            let mut calldata = function.selector.to_vec();
+            calldata.resize(4 + ARGUMENT_WORDS * 32, 0);
+            let (_, steps) = trace::record(original, &calldata)?;
+            if let Some(start) = steps.iter().position(|step| step.pc == function.entry) {
+                let path = steps[start..].iter().map(|step| step.pc).collect();
+                calls.push((idx, calldata, path));
+            }
+        }
+        Ok(Routes { functions, calls })
+    }
+
+    /// checks the dispatcher of `obfuscated`, with `map` to the input of the routes: every call has
+    /// to pass through a copy of the entry of its function, or of the first instruction after it
+    /// that has a copy, and every selector comparison still recognized in `obfuscated` that matches
+    /// a selector of the input has to jump to a copy of the entry block of its function.
+    ///
+    /// # returns
+    /// the inconsistencies found, empty if the dispatchers agree, or an error if the evm rejects a
+    /// call.
+    pub fn check(&self, obfuscated: &[u8], map: &PcMap) -> anyhow::Result<Vec<Inconsistency>> {
+        let mut found = Vec::new();
+        for (idx, calldata, path) in &self.calls {
+            let function = &self.functions[*idx];
+            let Some(copies) = path
+                .iter()
+                .map(|&pc| map.obfuscated(pc))
+                .find(|copies| !copies.is_empty())
+            else {
+                continue;
+            };
+            let (_, steps) = trace::record(obfuscated, calldata)?;
+            if !steps.iter().any(|step| copies.contains(&step.pc)) {
+                found.push(Inconsistency::Unrouted {
+                    selector: function.selector,
+                    entry: function.entry,
+                });
+            }
+        }
+
+        for claimed in functions(obfuscated) {
+            let Some(function) = self
+                .functions
+                .iter()
+                .find(|function| function.selector == claimed.selector)
+            else {
+                continue;
+            };
+            // a copy of the entry block, wherever its jumpdest went
+            let entered = map.original(claimed.entry).is_some_and(|origin| {
+                origin.exact
+                    && function
+                        .blocks
+                        .iter()
+                        .any(|block| block.start == function.entry && block.contains(&origin.pc))
+            });
+            if !entered {
+                found.push(Inconsistency::Collision {
+                    selector: claimed.selector,
+                    target: claimed.entry,
+                });
+            }
+        }
+        Ok(found)
+    }
+}
+
+/// checks the dispatcher of `obfuscated` against the dispatcher of `original`, the input it was
+/// obfuscated from with `map` between the two, see `Routes::check`.
+///
+/// # example
+/// ```
+/// use ebo::dispatcher;
+/// use ebo::pcmap::PcMap;
+/// use ebo::reloc::Program;
+///
+/// // PUSH0, CALLDATALOAD, PUSH1 224, SHR, DUP1, PUSH4 0xa9059cbb, EQ, PUSH1 16, JUMPI, STOP,
+/// // JUMPDEST, STOP
+/// let code = hex::decode("5f3560e01c8063a9059cbb14601057005b00").unwrap();
+/// let map = PcMap::new(&Program::lift(&code)).unwrap();
+/// assert!(dispatcher::inconsistencies(&code, &code, &map).unwrap().is_empty());
+/// ```
+pub fn inconsistencies(
+    original: &[u8],
+    obfuscated: &[u8],
+    map: &PcMap,
+) -> anyhow::Result<Vec<Inconsistency>> {
+    Routes::new(original)?.check(obfuscated, map)
+}
@@ -0,0 +1,381 @@
+/// eof obfuscation mode.
+/// eof containers (eip-3540) hold their code in sections that forbid `jump`, `jumpi`, `pc`, and
+/// the other opcodes the legacy passes lean on, and are validated on deployment (eip-3670,
+/// eip-5450): every relative jump has to land on an instruction, and the type section has to state
+/// the exact stack height each section reaches. so eof code gets its own mode: each code section is
+/// lifted with labels for its `rjump`, `rjumpi`, and `rjumpv` targets, transformed, reassembled with
+/// the relative offsets recomputed, and given its new maximum stack height in the type section.
+/// a section whose result does not pass eof validation is kept as it was. nested containers and the
+/// data section are kept as they are.
+use crate::error::EboError;
+use crate::evm::{op, Fork};
+use crate::passes::push0;
+use crate::passes::substitution::TEMPLATES;
+use crate::symbolic;
+use rand::{Rng, RngCore};
+use revm::interpreter::analysis::validate_eof;
+use revm::interpreter::opcode::{self, OpCode};
+use revm::primitives::eof::TypesSection;
+use revm::primitives::{Bytes, Eof};
+use std::collections::{BTreeMap, HashMap};
+use tracing::warn;
+
+/// largest number of stack items the evm allows.
+const STACK_LIMIT: i32 = 1024;
+
+/// returns true if `bytecode` starts with the eof magic `0xef00`. legacy code never does, since
+/// eip-3541 rejects new code starting with `0xef`.
+///
+/// # example
+/// ```
+/// use ebo::eof;
+///
+/// assert!(eof::is_container(&[0xEF, 0x00, 0x01]));
+/// assert!(!eof::is_container(&[0x60, 0x00]));
+/// ```
+pub fn is_container(bytecode: &[u8]) -> bool {
+    bytecode.starts_with(&[0xEF, 0x00])
+}
+
+/// decodes and validates an eof container.
+pub fn decode(bytecode: &[u8]) -> Result<Eof, EboError> {
+    let eof = Eof::decode(Bytes::copy_from_slice(bytecode))
+        .map_err(|err| EboError::parse("eof container", format!("{:?}", err)))?;
+    validate_eof(&eof).map_err(|err| EboError::parse("eof container", format!("{:?}", err)))?;
+    Ok(eof)
+}
+
+/// an instruction of an eof code section, with jump targets as labels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    /// position a relative jump can target.
+    Label(usize),
+    /// instruction with its immediate bytes.
+    Op { opcode: u8, immediate: Vec<u8> },
+    /// `rjump`, `rjumpi`, or `rjumpv` with the labels it jumps to.
+    Jump { opcode: u8, targets: Vec<usize> },
+}
+
+impl Item {
+    fn op(opcode: u8) -> Self {
+        Item::Op {
+            opcode,
+            immediate: Vec::new(),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Item::Label(_) => 0,
+            Item::Op { immediate, .. } => 1 + immediate.len(),
+            Item::Jump { opcode, targets } if *opcode == opcode::RJUMPV => 2 + 2 * targets.len(),
+            Item::Jump { .. } => 3,
+        }
+    }
+}
+
+/// length of the instruction at `pc`, immediates included.
+fn instruction_len(code: &[u8], pc: usize) -> usize {
+    match code[pc] {
+        opcode::RJUMPV => 2 + 2 * (code.get(pc + 1).copied().unwrap_or(0) as usize + 1),
+        opcode => {
+            1 + OpCode::new(opcode).map_or(0, |opcode| opcode.info().immediate_size() as usize)
+        }
+    }
+}
+
+fn read_i16(code: &[u8], at: usize) -> isize {
+    i16::from_be_bytes([code[at], code[at + 1]]) as isize
+}
+
+/// lifts a validated code section, labelling every relative jump target by its offset.
+fn lift(code: &[u8]) -> Vec<Item> {
+    let mut instructions = Vec::new();
+    let mut targets = Vec::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        let len = instruction_len(code, pc);
+        let end = (pc + len) as isize;
+        let jumps: Vec<usize> = match code[pc] {
+            opcode::RJUMP | opcode::RJUMPI => vec![(end + read_i16(code, pc + 1)) as usize],
+            opcode::RJUMPV => (pc + 2..pc + len)
+                .step_by(2)
+                .map(|at| (end + read_i16(code, at)) as usize)
+                .collect(),
+            _ => Vec::new(),
+        };
+        targets.extend(&jumps);
+        instructions.push((pc, len, jumps));
+        pc += len;
+    }
+
+    let mut items = Vec::with_capacity(instructions.len() + targets.len());
+    for (pc, len, jumps) in instructions {
+        if targets.contains(&pc) {
+            items.push(Item::Label(pc));
+        }
+        let opcode = code[pc];
+        items.push(if jumps.is_empty() {
+            Item::Op {
+                opcode,
+                immediate: code[pc + 1..pc + len].to_vec(),
+            }
+        } else {
+            Item::Jump {
+                opcode,
+                targets: jumps,
+            }
+        });
+    }
+    items
+}
+
+/// assembles items back into a code section, resolving labels to relative offsets.
+fn assemble(items: &[Item]) -> Result<Vec<u8>, EboError> {
+    let mut labels = HashMap::new();
+    let mut offset = 0;
+    for item in items {
+        if let Item::Label(label) = item {
+            labels.insert(*label, offset);
+        }
+        offset += item.size();
+    }
+
+    let mut code = Vec::with_capacity(offset);
+    for item in items {
+        match item {
+            Item::Label(_) => {}
+            Item::Op { opcode, immediate } => {
+                code.push(*opcode);
+                code.extend_from_slice(immediate);
+            }
+            Item::Jump { opcode, targets } => {
+                let end = (code.len() + item.size()) as isize;
+                code.push(*opcode);
+                if *opcode == opcode::RJUMPV {
+                    code.push((targets.len() - 1) as u8);
+                }
+                for target in targets {
+                    let relative = labels[target] as isize - end;
+                    let relative = i16::try_from(relative).map_err(|_| {
+                        EboError::parse("eof code section", "relative jump out of range")
+                    })?;
+                    code.extend(relative.to_be_bytes());
+                }
+            }
+        }
+    }
+    Ok(code)
+}
+
+/// the maximum stack height section `index` reaches, as eip-5450 defines it for the type section.
+/// the code has to be valid apart from its stated maximum.
+fn max_stack_height(code: &[u8], index: usize, types: &[TypesSection]) -> i32 {
+    let mut biggest = vec![i32::MIN; code.len()];
+    let mut next = types[index].inputs as i32;
+    let mut after_termination = false;
+    let mut pc = 0;
+    while pc < code.len() {
+        let opcode = code[pc];
+        let Some(info) = OpCode::new(opcode).map(|opcode| opcode.info()) else {
+            break;
+        };
+        if !after_termination {
+            biggest[pc] = biggest[pc].max(next);
+        }
+        after_termination = info.is_terminating();
+
+        let len = instruction_len(code, pc);
+        let end = (pc + len) as isize;
+        let diff = match opcode {
+            opcode::CALLF => {
+                let section = u16::from_be_bytes([code[pc + 1], code[pc + 2]]) as usize;
+                types[section].io_diff()
+            }
+            _ => info.io_diff() as i32,
+        };
+        next = biggest[pc].saturating_add(diff);
+
+        let jumps: Vec<isize> = match opcode {
+            opcode::RJUMP | opcode::RJUMPI => vec![end + read_i16(code, pc + 1)],
+            opcode::RJUMPV => (pc + 2..pc + len)
+                .step_by(2)
+                .map(|at| end + read_i16(code, at))
+                .collect(),
+            _ => Vec::new(),
+        };
+        for target in jumps {
+            // backward jumps only reach heights already recorded
+            if target as usize > pc {
+                biggest[target as usize] = biggest[target as usize].max(next);
+            }
+        }
+        pc += len;
+    }
+    biggest.into_iter().max().unwrap_or(0).max(0)
+}
+
+/// transformations applied to the code sections of an eof container.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EofMode {
+    /// chance that a given add is replaced by a substitution template.
+    pub substitution: f64,
+    /// chance that a decoy block, skipped by an always-taken `rjumpi`, is inserted before a given
+    /// instruction.
+    pub false_branch: f64,
+}
+
+impl Default for EofMode {
+    fn default() -> Self {
+        EofMode {
+            substitution: 0.5,
+            false_branch: 0.2,
+        }
+    }
+}
+
+impl EofMode {
+    /// obfuscates every code section of the eof container `bytecode`, counting the
+    /// transformations kept in `stats` under the names of the matching legacy passes.
+    ///
+    /// # arguments
+    /// * `bytecode` - a valid eof container.
+    /// * `rng` - source of the random choices.
+    /// * `stats` - transformation counts, keyed by pass name.
+    ///
+    /// # returns
+    /// the obfuscated container, which passes eof validation, or an error if `bytecode` is not a
+    /// valid container.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::eof::{self, EofMode};
+    /// use rand::SeedableRng;
+    /// use std::collections::BTreeMap;
+    ///
+    /// // one section: PUSH1 1, PUSH1 2, ADD, POP, STOP
+    /// let container = hex::decode("ef00010100040200010007040000000080000260016002015000").unwrap();
+    /// let mode = EofMode { substitution: 1.0, false_branch: 1.0 };
+    /// let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+    /// let mut stats = BTreeMap::new();
+    /// let obfuscated = mode.obfuscate(&container, &mut rng, &mut stats).unwrap();
+    /// assert!(obfuscated.len() > container.len());
+    /// assert!(eof::decode(&obfuscated).is_ok());
+    /// ```
+    pub fn obfuscate(
+        &self,
+        bytecode: &[u8],
+        rng: &mut dyn RngCore,
+        stats: &mut BTreeMap<&'static str, usize>,
+    ) -> Result<Vec<u8>, EboError> {
+        let mut eof = decode(bytecode)?;
+        for index in 0..eof.body.code_section.len() {
+            let mut applied = BTreeMap::new();
+            let items = self.transform(lift(&eof.body.code_section[index]), rng, &mut applied);
+            let code = match assemble(&items) {
+                Ok(code) if code.len() <= u16::MAX as usize => code,
+                _ => {
+                    warn!("Keeping EOF code section {}: it no longer fits", index);
+                    continue;
+                }
+            };
+
+            let mut body = eof.body.clone();
+            let height = max_stack_height(&code, index, &body.types_section);
+            if height >= STACK_LIMIT {
+                warn!(
+                    "Keeping EOF code section {}: it would overflow the stack",
+                    index
+                );
+                continue;
+            }
+            body.types_section[index].max_stack_size = height as u16;
+            body.code_section[index] = code.into();
+            let candidate = body.into_eof();
+            match validate_eof(&candidate) {
+                Ok(()) => {
+                    eof = candidate;
+                    for (name, count) in applied {
+                        *stats.entry(name).or_default() += count;
+                    }
+                }
+                Err(err) => warn!(
+                    "Keeping EOF code section {}: the result fails validation ({:?})",
+                    index, err
+                ),
+            }
+        }
+        Ok(eof.raw.to_vec())
+    }
+
+    /// applies the transformations to the items of one code section.
+    fn transform(
+        &self,
+        items: Vec<Item>,
+        rng: &mut dyn RngCore,
+        applied: &mut BTreeMap<&'static str, usize>,
+    ) -> Vec<Item> {
+        let mut next_label = usize::MAX / 2;
+        let mut transformed = Vec::with_capacity(items.len());
+        let mut reachable = true;
+        for item in items {
+            let opcode = match &item {
+                Item::Label(_) => {
+                    reachable = true;
+                    transformed.push(item);
+                    continue;
+                }
+                Item::Op { opcode, .. } | Item::Jump { opcode, .. } => *opcode,
+            };
+
+            // code after a terminating instruction is only valid where a forward jump lands, so
+            // decoys go in front of reachable instructions only
+            if reachable && rng.gen_bool(self.false_branch) {
+                // push1 k, rjumpi over, push1 a, push1 b, sstore, stop, over: with k never zero
+                // (bosc, section 2.2)
+                let over = next_label;
+                next_label += 1;
+                transformed.push(Item::Op {
+                    opcode: op::PUSH1,
+                    immediate: vec![rng.gen_range(1..=u8::MAX)],
+                });
+                transformed.push(Item::Jump {
+                    opcode: opcode::RJUMPI,
+                    targets: vec![over],
+                });
+                for byte in [rng.gen(), rng.gen()] {
+                    transformed.push(Item::Op {
+                        opcode: op::PUSH1,
+                        immediate: vec![byte],
+                    });
+                }
+                transformed.push(Item::op(opcode::SSTORE));
+                transformed.push(Item::op(op::STOP));
+                transformed.push(Item::Label(over));
+                *applied.entry("false-branch").or_default() += 1;
+            }
+            reachable = !OpCode::new(opcode).is_some_and(|opcode| opcode.info().is_terminating());
+
+            if opcode == op::ADD && rng.gen_bool(self.substitution) {
+                let templates: Vec<_> = TEMPLATES
+                    .iter()
+                    .filter(|template| template.replaces == op::ADD)
+                    .collect();
+                let template = templates[rng.gen_range(0..templates.len())];
+                let code = push0::fit(&(template.code)(rng.gen()), Fork::Cancun);
+                if symbolic::equivalent(&[op::ADD], &code) {
+                    for ins in crate::evm::disassemble(&code) {
+                        transformed.push(Item::Op {
+                            opcode: ins.opcode,
+                            immediate: ins.immediate,
+                        });
+                    }
+                    *applied.entry("substitution").or_default() += 1;
+                    continue;
+                }
+            }
+            transformed.push(item);
+        }
+        transformed
+    }
+}
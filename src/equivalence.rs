@@ -0,0 +1,209 @@
+/// differential execution harness for the ebo obfuscator.
+/// runs the original and the obfuscated bytecode in an embedded evm (revm) with the same calldata and
+/// the same starting state, and compares everything a caller can observe: whether the call succeeded,
+/// the return or revert data, the storage it wrote, and the logs it emitted. gas is measured but not
+/// compared, since obfuscation is expected to change it.
+use anyhow::anyhow;
+use revm::db::{CacheDB, EmptyDB};
+use revm::primitives::{
+    AccountInfo, Address, Bytecode, Bytes, ExecutionResult, Log, Output, ResultAndState, SpecId,
+    TxEnv, TxKind, U256,
+};
+use revm::Evm;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// address the code under test is deployed at.
+const CONTRACT: Address = Address::repeat_byte(0xeb);
+/// address the call is made from.
+const CALLER: Address = Address::repeat_byte(0xca);
+/// gas available to every call; large enough for any code that fits the size limit, small enough
+/// to stop code that never terminates.
+const GAS_LIMIT: u64 = 30_000_000;
+/// fork the code is executed under.
+pub const SPEC: SpecId = SpecId::CANCUN;
+
+/// how a call ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// stopped or returned normally.
+    Success,
+    /// reverted with `REVERT`, keeping the remaining gas.
+    Revert,
+    /// failed exceptionally (invalid opcode, bad jump, stack error, out of gas), spending all gas.
+    /// the reason is not part of the observable result, so it is not compared.
+    Halt,
+}
+
+/// everything a caller can observe about a call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Effects {
+    /// how the call ended.
+    pub status: Status,
+    /// return data, or revert data when the call reverted.
+    pub output: Vec<u8>,
+    /// storage slots of the contract whose value changed, with their final value.
+    pub storage: BTreeMap<U256, U256>,
+    /// logs emitted, in order.
+    pub logs: Vec<Log>,
+}
+
+/// result of executing bytecode once.
+#[derive(Debug, Clone)]
+pub struct Execution {
+    /// observable effects of the call.
+    pub effects: Effects,
+    /// gas used by the call, including the intrinsic transaction cost.
+    pub gas_used: u64,
+}
+
+/// a call on which the original and the obfuscated bytecode behave differently.
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// calldata that reproduces the divergence.
+    pub calldata: Vec<u8>,
+    /// effects of the original bytecode.
+    pub original: Effects,
+    /// effects of the obfuscated bytecode.
+    pub obfuscated: Effects,
+}
+
+/// the original and the obfuscated bytecode run on the same call.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    /// input of the call.
+    pub calldata: Vec<u8>,
+    /// run of the original bytecode.
+    pub original: Execution,
+    /// run of the obfuscated bytecode.
+    pub obfuscated: Execution,
+}
+
+impl Comparison {
+    /// returns how the two runs differ, or `None` if a caller could not tell them apart.
+    pub fn divergence(&self) -> Option<Divergence> {
+        (self.original.effects != self.obfuscated.effects).then(|| Divergence {
+            calldata: self.calldata.clone(),
+            original: self.original.effects.clone(),
+            obfuscated: self.obfuscated.effects.clone(),
+        })
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (a, b) = (&self.original, &self.obfuscated);
+        writeln!(f, "calldata: 0x{}", hex::encode(&self.calldata))?;
+        if a.status != b.status {
+            writeln!(f, "status: {:?} != {:?}", a.status, b.status)?;
+        }
+        if a.output != b.output {
+            writeln!(
+                f,
+                "output: 0x{} != 0x{}",
+                hex::encode(&a.output),
+                hex::encode(&b.output)
+            )?;
+        }
+        if a.storage != b.storage {
+            writeln!(f, "storage: {:?} != {:?}", a.storage, b.storage)?;
+        }
+        if a.logs != b.logs {
+            writeln!(f, "logs: {:?} != {:?}", a.logs, b.logs)?;
+        }
+        Ok(())
+    }
+}
+
+/// a fresh state in which `code` is deployed as the contract under test, with empty storage.
+pub fn deploy(code: &[u8]) -> CacheDB<EmptyDB> {
+    let bytecode = Bytecode::new_raw(Bytes::copy_from_slice(code));
+    let mut db = CacheDB::new(EmptyDB::default());
+    db.insert_account_info(
+        CONTRACT,
+        AccountInfo::new(U256::ZERO, 1, bytecode.hash_slow(), bytecode),
+    );
+    db
+}
+
+/// sets up `tx` as a call of the contract under test with `calldata`.
+pub fn call(tx: &mut TxEnv, calldata: &[u8]) {
+    tx.caller = CALLER;
+    tx.transact_to = TxKind::Call(CONTRACT);
+    tx.data = Bytes::copy_from_slice(calldata);
+    tx.gas_limit = GAS_LIMIT;
+}
+
+/// what a caller can observe of a finished transaction, and the gas it used.
+pub fn observe(outcome: ResultAndState) -> Execution {
+    let storage = outcome
+        .state
+        .get(&CONTRACT)
+        .map(|account| {
+            account
+                .storage
+                .iter()
+                .filter(|(_, slot)| slot.is_changed())
+                .map(|(key, slot)| (*key, slot.present_value))
+                .collect()
+        })
+        .unwrap_or_default();
+    let (status, output, logs, gas_used) = match outcome.result {
+        ExecutionResult::Success {
+            output,
+            logs,
+            gas_used,
+            ..
+        } => {
+            let output = match output {
+                Output::Call(data) => data.to_vec(),
+                Output::Create(data, _) => data.to_vec(),
+            };
+            (Status::Success, output, logs, gas_used)
+        }
+        ExecutionResult::Revert { output, gas_used } => {
+            (Status::Revert, output.to_vec(), Vec::new(), gas_used)
+        }
+        ExecutionResult::Halt { gas_used, .. } => (Status::Halt, Vec::new(), Vec::new(), gas_used),
+    };
+
+    Execution {
+        effects: Effects {
+            status,
+            output,
+            storage,
+            logs,
+        },
+        gas_used,
+    }
+}
+
+/// executes `code` as the runtime code of a fresh contract with empty storage, calling it with
+/// `calldata` from an externally owned account.
+///
+/// # arguments
+/// * `code` - runtime bytecode to run.
+/// * `calldata` - input of the call.
+///
+/// # returns
+/// the observable effects of the call and the gas it used.
+pub fn execute(code: &[u8], calldata: &[u8]) -> anyhow::Result<Execution> {
+    let mut evm = Evm::builder()
+        .with_db(deploy(code))
+        .with_spec_id(SPEC)
+        .modify_tx_env(|tx| call(tx, calldata))
+        .build();
+    let outcome = evm
+        .transact()
+        .map_err(|err| anyhow!("evm rejected the call: {}", err))?;
+    Ok(observe(outcome))
+}
+
+/// runs `original` and `obfuscated` with the same `calldata`, each on a fresh contract.
+pub fn compare(original: &[u8], obfuscated: &[u8], calldata: &[u8]) -> anyhow::Result<Comparison> {
+    Ok(Comparison {
+        calldata: calldata.to_vec(),
+        original: execute(original, calldata)?,
+        obfuscated: execute(obfuscated, calldata)?,
+    })
+}
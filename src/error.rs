@@ -0,0 +1,111 @@
+/// error types for the ebo obfuscator.
+/// the failures a caller may want to tell apart: input that cannot be parsed, obfuscated code that
+/// uses opcodes the target fork lacks or fails validation, jumps that cannot be relocated, code over
+/// the size limit, and obfuscated code that behaves differently from the original. the library
+/// returns them (directly, or at the root of an `anyhow::Error`) and the cli maps each to its own
+/// exit code.
+use crate::chain::Target;
+use crate::validate::{Issue, Problem};
+use thiserror::Error;
+
+/// an error of the obfuscator.
+#[derive(Debug, Error)]
+pub enum EboError {
+    /// input that cannot be read as what it is supposed to be.
+    #[error("cannot parse {what}: {reason}")]
+    Parse {
+        /// what was being parsed, e.g. `hex bytecode` or `artifact`.
+        what: String,
+        /// why it could not be.
+        reason: String,
+    },
+    /// obfuscation produced an opcode the target fork does not define or the target chain does not
+    /// run.
+    #[error("obfuscation produced opcode {opcode:#04x} at {offset:#06x}, which {target} does not support")]
+    UnsupportedOpcode {
+        /// the opcode.
+        opcode: u8,
+        /// its offset in the obfuscated code.
+        offset: usize,
+        /// the target fork and chain.
+        target: Target,
+    },
+    /// a jump target or code pointer that cannot be resolved to an offset.
+    #[error("cannot relocate {0}")]
+    Relocation(String),
+    /// code that cannot be made to fit the size limit.
+    #[error("{size} bytes exceeds the size limit of {limit} bytes")]
+    SizeLimit {
+        /// size of the smallest code produced.
+        size: usize,
+        /// the limit.
+        limit: usize,
+    },
+    /// obfuscation produced code with validation issues the input did not have.
+    #[error("obfuscation produced invalid bytecode:\n{}", lines(.0))]
+    Invalid(Vec<Issue>),
+    /// obfuscated code that behaves differently from the original.
+    #[error("{0}")]
+    Verification(String),
+}
+
+impl EboError {
+    /// a parse error for `what`.
+    pub fn parse(what: &str, reason: impl ToString) -> Self {
+        EboError::Parse {
+            what: what.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    /// the error for `issues` introduced by obfuscation: the first undefined opcode if there is
+    /// one, since that is a matter of the target fork rather than a broken transformation.
+    pub fn introduced(issues: Vec<Issue>, target: impl Into<Target>) -> Self {
+        let undefined = issues.iter().find_map(|issue| match issue.problem {
+            Problem::UndefinedOpcode(opcode) => Some((opcode, issue.offset)),
+            _ => None,
+        });
+        match undefined {
+            Some((opcode, offset)) => EboError::UnsupportedOpcode {
+                opcode,
+                offset,
+                target: target.into(),
+            },
+            None => EboError::Invalid(issues),
+        }
+    }
+
+    /// name of the kind of error, as `--json` reports it next to the exit code.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EboError::Parse { .. } => "parse",
+            EboError::UnsupportedOpcode { .. } => "unsupported-opcode",
+            EboError::Relocation(_) => "relocation",
+            EboError::SizeLimit { .. } => "size-limit",
+            EboError::Invalid(_) => "invalid-output",
+            EboError::Verification(_) => "verification",
+        }
+    }
+
+    /// exit code of the cli when a command fails with this error. 1 is left for other failures
+    /// and 2 for usage errors.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            EboError::Parse { .. } => 3,
+            EboError::UnsupportedOpcode { .. } => 4,
+            EboError::Relocation(_) => 5,
+            EboError::SizeLimit { .. } => 6,
+            EboError::Invalid(_) => 7,
+            EboError::Verification(_) => 8,
+        }
+    }
+}
+
+/// one issue per line.
+fn lines(issues: &[Issue]) -> String {
+    issues
+        .iter()
+        .map(|issue| issue.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -1,12 +1,73 @@
 /// module for parsing and analyzing evm bytecode in the ebo obfuscator.
 /// provides functionality to split bytecode into basic blocks and compute control flow graph (cfg)
 /// complexity, supporting obfuscation techniques and reverse engineering resistance tests.
+use clap::ValueEnum;
+use flate2::{write::DeflateEncoder, Compression};
+use revm::interpreter::OpCode;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashSet;
+use std::io::Write;
+use std::ops::Range;
+
+/// maximum size of deployed contract code in bytes (eip-170). anything larger cannot be deployed.
+pub const MAX_CODE_SIZE: usize = 24_576;
+
+/// raw byte values of the opcodes the obfuscator emits or inspects directly.
+pub mod op {
+    pub const STOP: u8 = 0x00;
+    pub const ADD: u8 = 0x01;
+    pub const MUL: u8 = 0x02;
+    pub const SUB: u8 = 0x03;
+    pub const DIV: u8 = 0x04;
+    pub const MOD: u8 = 0x06;
+    pub const ADDMOD: u8 = 0x08;
+    pub const MULMOD: u8 = 0x09;
+    pub const LT: u8 = 0x10;
+    pub const GT: u8 = 0x11;
+    pub const SLT: u8 = 0x12;
+    pub const SGT: u8 = 0x13;
+    pub const EQ: u8 = 0x14;
+    pub const ISZERO: u8 = 0x15;
+    pub const AND: u8 = 0x16;
+    pub const OR: u8 = 0x17;
+    pub const XOR: u8 = 0x18;
+    pub const NOT: u8 = 0x19;
+    pub const SHL: u8 = 0x1B;
+    pub const SHR: u8 = 0x1C;
+    pub const CODECOPY: u8 = 0x39;
+    pub const BLOBHASH: u8 = 0x49;
+    pub const BLOBBASEFEE: u8 = 0x4A;
+    pub const POP: u8 = 0x50;
+    pub const MLOAD: u8 = 0x51;
+    pub const MSTORE: u8 = 0x52;
+    pub const SLOAD: u8 = 0x54;
+    pub const SSTORE: u8 = 0x55;
+    pub const JUMP: u8 = 0x56;
+    pub const JUMPI: u8 = 0x57;
+    pub const GAS: u8 = 0x5A;
+    pub const JUMPDEST: u8 = 0x5B;
+    pub const TLOAD: u8 = 0x5C;
+    pub const TSTORE: u8 = 0x5D;
+    pub const MCOPY: u8 = 0x5E;
+    pub const PUSH0: u8 = 0x5F;
+    pub const PUSH1: u8 = 0x60;
+    pub const PUSH32: u8 = 0x7F;
+    pub const DUP1: u8 = 0x80;
+    pub const DUP2: u8 = 0x81;
+    pub const DUP16: u8 = 0x8F;
+    pub const SWAP1: u8 = 0x90;
+    pub const SWAP16: u8 = 0x9F;
+    pub const RETURN: u8 = 0xF3;
+    pub const REVERT: u8 = 0xFD;
+    pub const INVALID: u8 = 0xFE;
+    pub const SELFDESTRUCT: u8 = 0xFF;
+}
 
 /// represents an evm opcode, used to categorize instructions during bytecode parsing.
 /// variants cover key control-flow and arithmetic opcodes relevant to obfuscation, with a fallback
 /// for unrecognized instructions.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[allow(clippy::upper_case_acronyms, dead_code)]
 /// draws on research from eveilm (page 47) and bosc (table i) for cfg complexity metrics.
 pub enum Opcode {
     /// addition operation (0x01), targeted for substitution in obfuscation (eveilm, page 59).
@@ -19,13 +80,75 @@ pub enum Opcode {
     STOP,
     /// return from execution (0xf3), marks unreachable code regions (bosc, section 2.4).
     RETURN,
+    /// transient storage read (0x5c, cancun, eip-1153). like the write and mcopy, never moved by the
+    /// chaotic shuffle, so transient state is accessed in its original order.
+    TLOAD,
+    /// transient storage write (0x5d, cancun, eip-1153).
+    TSTORE,
+    /// memory to memory copy (0x5e, cancun, eip-5656).
+    MCOPY,
+    /// versioned hash of a blob of the transaction (0x49, cancun, eip-4844).
+    BLOBHASH,
+    /// blob base fee of the block (0x4a, cancun, eip-7516).
+    BLOBBASEFEE,
     /// unrecognized or other opcode, stored as its byte value.
     Other(u8),
 }
 
+impl Opcode {
+    /// returns the raw byte of the opcode.
+    pub fn byte(&self) -> u8 {
+        match self {
+            Opcode::ADD => op::ADD,
+            Opcode::JUMPI => op::JUMPI,
+            Opcode::JUMPDEST => op::JUMPDEST,
+            Opcode::STOP => op::STOP,
+            Opcode::RETURN => op::RETURN,
+            Opcode::TLOAD => op::TLOAD,
+            Opcode::TSTORE => op::TSTORE,
+            Opcode::MCOPY => op::MCOPY,
+            Opcode::BLOBHASH => op::BLOBHASH,
+            Opcode::BLOBBASEFEE => op::BLOBBASEFEE,
+            Opcode::Other(byte) => *byte,
+        }
+    }
+
+    /// returns the stack effect of the opcode as `(items popped, items pushed)`, see `stack_io`.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::evm::Opcode;
+    ///
+    /// assert_eq!(Opcode::from(0x5D), Opcode::TSTORE);
+    /// assert_eq!(Opcode::TSTORE.stack_io(), Some((2, 0)));
+    /// assert_eq!(Opcode::MCOPY.stack_io(), Some((3, 0)));
+    /// ```
+    pub fn stack_io(&self) -> Option<(usize, usize)> {
+        stack_io(self.byte())
+    }
+}
+
+impl From<u8> for Opcode {
+    fn from(byte: u8) -> Self {
+        match byte {
+            op::ADD => Opcode::ADD,
+            op::JUMPI => Opcode::JUMPI,
+            op::JUMPDEST => Opcode::JUMPDEST,
+            op::STOP => Opcode::STOP,
+            op::RETURN => Opcode::RETURN,
+            op::TLOAD => Opcode::TLOAD,
+            op::TSTORE => Opcode::TSTORE,
+            op::MCOPY => Opcode::MCOPY,
+            op::BLOBHASH => Opcode::BLOBHASH,
+            op::BLOBBASEFEE => Opcode::BLOBBASEFEE,
+            b => Opcode::Other(b),
+        }
+    }
+}
+
 /// represents a basic block of evm bytecode, a sequence of opcodes executed sequentially.
 /// used to isolate code segments for chaotic shuffle and other obfuscation techniques (bian, section iii.b).
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct BasicBlock {
     /// sequence of opcodes within the block.
     pub opcodes: Vec<Opcode>,
@@ -43,53 +166,347 @@ pub struct BasicBlock {
 ///
 /// # example
 /// ```
+/// use ebo::parse_bytecode;
+///
 /// let bytecode = vec![0x60, 0x01, 0x01, 0x57, 0x00]; // PUSH1 1, ADD, JUMPI, STOP
 /// let blocks = parse_bytecode(&bytecode);
 /// assert_eq!(blocks.len(), 2); // Two blocks: [PUSH1, ADD, JUMPI], [STOP]
 /// ```
 pub fn parse_bytecode(bytecode: &[u8]) -> Vec<BasicBlock> {
-    let mut blocks = Vec::new();
-    let mut current_block = BasicBlock {
-        opcodes: Vec::new(),
-    };
-    let mut i = 0;
+    block_ranges(bytecode)
+        .map(|range| BasicBlock {
+            opcodes: bytecode[range]
+                .iter()
+                .map(|&byte| Opcode::from(byte))
+                .collect(),
+        })
+        .collect()
+}
 
-    while i < bytecode.len() {
-        let op = match bytecode[i] {
-            0x01 => Opcode::ADD,
-            0x57 => Opcode::JUMPI,
-            0x5B => Opcode::JUMPDEST,
-            0x00 => Opcode::STOP,
-            0xF3 => Opcode::RETURN,
-            b => Opcode::Other(b),
+/// the byte ranges of the blocks `parse_bytecode` splits `bytecode` into, without decoding or
+/// copying any of it, for callers that only look at the bytes of each block.
+///
+/// # example
+/// ```
+/// use ebo::evm::block_ranges;
+///
+/// let bytecode = vec![0x60, 0x01, 0x01, 0x57, 0x00]; // PUSH1 1, ADD, JUMPI, STOP
+/// assert_eq!(block_ranges(&bytecode).collect::<Vec<_>>(), [0..4, 4..5]);
+/// ```
+pub fn block_ranges(bytecode: &[u8]) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut start = 0;
+    std::iter::from_fn(move || {
+        if start >= bytecode.len() {
+            return None;
+        }
+        // a block ends after a control-flow opcode (JUMPI, JUMPDEST, STOP, or RETURN)
+        let end = bytecode[start..]
+            .iter()
+            .position(|&byte| OPCODES[byte as usize].ends_block)
+            .map_or(bytecode.len(), |idx| start + idx + 1);
+        let range = start..end;
+        start = end;
+        Some(range)
+    })
+}
+
+/// a single decoded evm instruction together with its immediate operand.
+/// unlike `Opcode`, which treats every byte as an instruction, this keeps push data attached to the
+/// push that owns it, so transformations never mistake constants for code.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Instruction {
+    /// byte offset of the instruction in the decoded bytecode.
+    pub offset: usize,
+    /// raw opcode byte.
+    pub opcode: u8,
+    /// immediate bytes of a push instruction, empty for every other opcode. may be shorter than the
+    /// push width when the bytecode ends in a truncated push.
+    #[serde(with = "hex_bytes")]
+    pub immediate: Vec<u8>,
+}
+
+/// (de)serializes bytes as a `0x`-prefixed hex string rather than a list of numbers, for
+/// `#[serde(with = "hex_bytes")]`. the prefix is optional when reading.
+pub mod hex_bytes {
+    use super::*;
+
+    /// writes `bytes` as hex.
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+    }
+
+    /// reads bytes from hex.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        hex::decode(source.strip_prefix("0x").unwrap_or(&source)).map_err(serde::de::Error::custom)
+    }
+}
+
+/// what is known about a byte value as an opcode, see `OPCODES`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpInfo {
+    /// name, e.g. `PUSH1`, or `UNKNOWN` for an undefined opcode.
+    pub name: &'static str,
+    /// number of immediate bytes that follow it (1..=32 for PUSH1..PUSH32, 0 otherwise).
+    pub immediate: usize,
+    /// stack effect as `(items popped, items pushed)`, or `None` for an undefined opcode.
+    pub stack: Option<(usize, usize)>,
+    /// static gas cost on cancun, with warm costs for account and storage access; see
+    /// `gas::static_cost`.
+    pub gas: u64,
+    /// fork that introduced it, or `None` for an undefined opcode. london is the oldest fork ebo
+    /// targets, so everything older counts as london.
+    pub fork: Option<Fork>,
+    /// whether execution never falls through past it.
+    pub terminator: bool,
+    /// whether it ends a block of `parse_bytecode` (jumpi, jumpdest, stop, or return).
+    pub ends_block: bool,
+}
+
+/// the metadata of every byte value as an opcode, indexed by it, built at compile time so that
+/// the parser, the validator, and the gas model look an opcode up instead of matching on it.
+///
+/// # example
+/// ```
+/// use ebo::evm::{op, OPCODES};
+/// use ebo::Fork;
+///
+/// let push0 = OPCODES[op::PUSH0 as usize];
+/// assert_eq!((push0.name, push0.stack, push0.gas), ("PUSH0", Some((0, 1)), 2));
+/// assert_eq!(push0.fork, Some(Fork::Shanghai));
+/// assert_eq!(OPCODES[op::PUSH32 as usize].immediate, 32);
+/// assert_eq!(OPCODES[0x0C].stack, None);
+/// ```
+pub const OPCODES: [OpInfo; 256] = {
+    let mut table = [OpInfo {
+        name: "UNKNOWN",
+        immediate: 0,
+        stack: None,
+        gas: 0,
+        fork: None,
+        terminator: false,
+        ends_block: false,
+    }; 256];
+    let mut idx = 0;
+    while idx < 256 {
+        let opcode = idx as u8;
+        let stack = table_stack_io(opcode);
+        table[idx] = OpInfo {
+            name: match OpCode::new(opcode) {
+                Some(code) => code.as_str(),
+                None => "UNKNOWN",
+            },
+            immediate: match opcode {
+                op::PUSH1..=op::PUSH32 => (opcode - op::PUSH1) as usize + 1,
+                _ => 0,
+            },
+            stack,
+            gas: table_gas(opcode),
+            fork: match (stack, opcode) {
+                (None, _) => None,
+                (_, 0x5F) => Some(Fork::Shanghai),
+                (_, 0x49 | 0x4A | 0x5C..=0x5E) => Some(Fork::Cancun),
+                _ => Some(Fork::London),
+            },
+            terminator: matches!(
+                opcode,
+                op::STOP | op::JUMP | op::RETURN | op::REVERT | op::INVALID | op::SELFDESTRUCT
+            ),
+            ends_block: matches!(opcode, op::JUMPI | op::JUMPDEST | op::STOP | op::RETURN),
         };
+        idx += 1;
+    }
+    table
+};
 
-        current_block.opcodes.push(op.clone());
-
-        // after a control-flow opcode (JUMPI, JUMPDEST, STOP, or RETURN) is encountered, the current
-        // BasicBlock (stored in current_block) needs to be moved into the blocks vector, and a new empty
-        // BasicBlock needs to be prepared for the next segment
-        if matches!(
-            op,
-            Opcode::JUMPI | Opcode::STOP | Opcode::RETURN | Opcode::JUMPDEST
-        ) {
-            blocks.push(std::mem::take(&mut current_block)); // to avoid unnecessary cloning and reallocations
-
-            // since loop will keep appending new opcodes to `current_block.opcodes` for the next segment, we
-            // need to ensure `current_block` is properly initialized for the next iteration, else we might
-            // end up with unexpected behavior (e.g., reusing a partially filled or uninitialized state), hence
-            // why we have another assignment below.
-            current_block = BasicBlock::default();
+/// the stack effect of `opcode` for `OPCODES`. covers every opcode up to and including cancun.
+const fn table_stack_io(opcode: u8) -> Option<(usize, usize)> {
+    let io = match opcode {
+        0x00 | 0x5B => (0, 0),
+        0x01..=0x07 | 0x0A | 0x0B | 0x10..=0x14 | 0x16..=0x18 | 0x1A..=0x1D | 0x20 => (2, 1),
+        0x08 | 0x09 => (3, 1),
+        0x15 | 0x19 => (1, 1),
+        0x30 | 0x32..=0x34 | 0x36 | 0x38 | 0x3A | 0x3D | 0x41..=0x48 | 0x4A => (0, 1),
+        0x31 | 0x35 | 0x3B | 0x3F | 0x40 | 0x49 | 0x51 | 0x54 | 0x5C => (1, 1),
+        0x37 | 0x39 | 0x3E | 0x5E => (3, 0),
+        0x3C => (4, 0),
+        0x50 | 0x56 => (1, 0),
+        0x52 | 0x53 | 0x55 | 0x57 | 0x5D => (2, 0),
+        0x58..=0x5A | 0x5F..=0x7F => (0, 1),
+        0x80..=0x8F => {
+            let n = (opcode - 0x80) as usize + 1;
+            (n, n + 1)
+        }
+        0x90..=0x9F => {
+            let n = (opcode - 0x90) as usize + 2;
+            (n, n)
         }
+        0xA0..=0xA4 => ((opcode - 0xA0) as usize + 2, 0),
+        0xF0 => (3, 1),
+        0xF1 | 0xF2 => (7, 1),
+        0xF3 | 0xFD => (2, 0),
+        0xF4 | 0xFA => (6, 1),
+        0xF5 => (4, 1),
+        0xFE => (0, 0),
+        0xFF => (1, 0),
+        _ => return None,
+    };
+    Some(io)
+}
 
-        i += 1;
+/// the static gas cost of `opcode` for `OPCODES`. terminators that end execution without running
+/// out of gas cost nothing.
+const fn table_gas(opcode: u8) -> u64 {
+    match opcode {
+        0x00 | 0xF3 | 0xFD | 0xFE => 0,
+        0x5B => 1,
+        0x30 | 0x32..=0x34 | 0x36 | 0x38 | 0x3A | 0x3D | 0x41..=0x46 | 0x48 | 0x4A => 2,
+        0x50 | 0x58..=0x5A | 0x5F => 2,
+        0x01 | 0x03 | 0x10..=0x1D | 0x35 | 0x37 | 0x39 | 0x3E | 0x49 | 0x51..=0x53 | 0x5E => 3,
+        0x60..=0x9F => 3,
+        0x02 | 0x04..=0x07 | 0x0B | 0x47 => 5,
+        0x08 | 0x09 | 0x56 => 8,
+        0x0A | 0x57 => 10,
+        0x40 => 20,
+        0x20 => 30,
+        0x31 | 0x3B | 0x3C | 0x3F | 0x54 | 0x55 | 0x5C | 0x5D | 0xF1 | 0xF2 | 0xF4 | 0xFA => 100,
+        0xA0..=0xA4 => 375 * (opcode - 0xA0 + 1) as u64,
+        0xFF => 5000,
+        0xF0 | 0xF5 => 32000,
+        _ => 0,
     }
+}
+
+/// returns the number of immediate bytes that follow `opcode` (1..=32 for PUSH1..PUSH32, 0 otherwise).
+pub fn push_size(opcode: u8) -> usize {
+    OPCODES[opcode as usize].immediate
+}
 
-    if !current_block.opcodes.is_empty() {
-        blocks.push(current_block);
+/// returns the name of `opcode`, e.g. `PUSH1`, or `UNKNOWN` for an undefined opcode.
+pub fn mnemonic(opcode: u8) -> &'static str {
+    OPCODES[opcode as usize].name
+}
+
+/// returns true if execution never falls through past `opcode`, i.e. the bytes after it are only
+/// reachable through a jump to a later jumpdest.
+pub fn is_terminator(opcode: u8) -> bool {
+    OPCODES[opcode as usize].terminator
+}
+
+/// returns the stack effect of `opcode` as `(items popped, items pushed)`, or `None` for undefined
+/// opcodes. covers every opcode up to and including cancun; see `Fork::supports` for when each was
+/// introduced.
+pub fn stack_io(opcode: u8) -> Option<(usize, usize)> {
+    OPCODES[opcode as usize].stack
+}
+
+/// an ethereum hard fork, deciding which opcodes are defined.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize, ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fork {
+    /// basefee.
+    London,
+    /// the merge; difficulty becomes prevrandao, with no new opcodes.
+    Paris,
+    /// push0.
+    Shanghai,
+    /// transient storage, mcopy, blobhash, and blobbasefee.
+    #[default]
+    Cancun,
+}
+
+impl Fork {
+    /// returns true if `opcode` is defined in this fork. the designated invalid instruction (0xfe)
+    /// counts as defined, since reaching it is deliberate.
+    pub fn supports(self, opcode: u8) -> bool {
+        OPCODES[opcode as usize]
+            .fork
+            .is_some_and(|introduced| self >= introduced)
     }
 
-    blocks
+    /// returns true if every instruction of `code` is defined in this fork, so passes can leave out
+    /// sequences the target chain would reject.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::Fork;
+    ///
+    /// let code = [0x5F, 0x60, 0x5F, 0x01]; // PUSH0, PUSH1 0x5f, ADD
+    /// assert!(Fork::Shanghai.admits(&code));
+    /// assert!(!Fork::London.admits(&code));
+    /// ```
+    pub fn admits(self, code: &[u8]) -> bool {
+        disassemble(code)
+            .iter()
+            .all(|ins| self.supports(ins.opcode))
+    }
+}
+
+/// computes the net stack effect of a straight-line instruction sequence.
+///
+/// # arguments
+/// * `code` - raw bytes of the sequence; push immediates are skipped.
+///
+/// # returns
+/// `(depth, produced)`, where `depth` is how many pre-existing stack items the sequence reads and
+/// `produced` is how many items it leaves in their place, or `None` if it contains undefined opcodes.
+///
+/// # example
+/// ```
+/// use ebo::evm::stack_effect;
+///
+/// let effect = stack_effect(&[0x80, 0x50]); // DUP1, POP
+/// assert_eq!(effect, Some((1, 1)));
+/// ```
+pub fn stack_effect(code: &[u8]) -> Option<(usize, usize)> {
+    let mut height: isize = 0;
+    let mut lowest: isize = 0;
+    for ins in disassemble(code) {
+        let (popped, pushed) = stack_io(ins.opcode)?;
+        height -= popped as isize;
+        lowest = lowest.min(height);
+        height += pushed as isize;
+    }
+    let depth = (-lowest) as usize;
+    Some((depth, (height - lowest) as usize))
+}
+
+/// decodes evm bytecode into a linear sequence of instructions, keeping push immediates attached to
+/// their push opcode.
+///
+/// # arguments
+/// * `bytecode` - slice of raw evm bytecode bytes.
+///
+/// # returns
+/// vector of `Instruction` values in bytecode order.
+///
+/// # example
+/// ```
+/// use ebo::disassemble;
+///
+/// let bytecode = vec![0x60, 0x01, 0x01]; // PUSH1 1, ADD
+/// let instructions = disassemble(&bytecode);
+/// assert_eq!(instructions.len(), 2);
+/// assert_eq!(instructions[0].immediate, vec![0x01]);
+/// ```
+pub fn disassemble(bytecode: &[u8]) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut i = 0;
+
+    while i < bytecode.len() {
+        let opcode = bytecode[i];
+        let end = (i + 1 + push_size(opcode)).min(bytecode.len());
+        instructions.push(Instruction {
+            offset: i,
+            opcode,
+            immediate: bytecode[i + 1..end].to_vec(),
+        });
+        i = end;
+    }
+
+    instructions
 }
 
 /// computes a simple control flow graph (cfg) complexity metric for a set of basic blocks.
@@ -104,12 +521,13 @@ pub fn parse_bytecode(bytecode: &[u8]) -> Vec<BasicBlock> {
 ///
 /// # example
 /// ```
+/// use ebo::{compute_cfg_complexity, parse_bytecode};
+///
 /// let bytecode = vec![0x01, 0x57, 0x00]; // ADD, JUMPI, STOP
 /// let blocks = parse_bytecode(&bytecode);
 /// let complexity = compute_cfg_complexity(&blocks);
 /// assert_eq!(complexity, 1); // One block with JUMPI
 /// ```
-#[allow(dead_code)]
 pub fn compute_cfg_complexity(blocks: &[BasicBlock]) -> usize {
     blocks
         .iter()
@@ -117,6 +535,24 @@ pub fn compute_cfg_complexity(blocks: &[BasicBlock]) -> usize {
         .count()
 }
 
+/// `compute_cfg_complexity` of the blocks of `bytecode`, counted over its bytes in place rather than
+/// over parsed blocks, for metrics computed on every candidate of a search.
+///
+/// # example
+/// ```
+/// use ebo::evm::cfg_complexity;
+/// use ebo::{compute_cfg_complexity, parse_bytecode};
+///
+/// let bytecode = vec![0x01, 0x57, 0x5B, 0x57, 0x00]; // ADD, JUMPI, JUMPDEST, JUMPI, STOP
+/// assert_eq!(cfg_complexity(&bytecode), 2);
+/// assert_eq!(cfg_complexity(&bytecode), compute_cfg_complexity(&parse_bytecode(&bytecode)));
+/// ```
+pub fn cfg_complexity(bytecode: &[u8]) -> usize {
+    block_ranges(bytecode)
+        .filter(|range| bytecode[range.clone()].contains(&op::JUMPI))
+        .count()
+}
+
 /// counts the number of unique opcodes in a bytecode slice.
 /// used as a readability metric to assess obfuscation’s impact on reverse engineering difficulty,
 /// where more unique opcodes indicate increased complexity (eveilm, page 59).
@@ -129,11 +565,12 @@ pub fn compute_cfg_complexity(blocks: &[BasicBlock]) -> usize {
 ///
 /// # example
 /// ```
+/// use ebo::count_unique_opcodes;
+///
 /// let bytecode = vec![0x60, 0x01, 0x01, 0x57]; // PUSH1, ADD, ADD, JUMPI
 /// let unique_count = count_unique_opcodes(&bytecode);
 /// assert_eq!(unique_count, 3); // PUSH1, ADD, JUMPI
 /// ```
-#[allow(unused)]
 pub fn count_unique_opcodes(bytecode: &[u8]) -> usize {
     let mut unique = HashSet::new();
     for &b in bytecode {
@@ -142,27 +579,212 @@ pub fn count_unique_opcodes(bytecode: &[u8]) -> usize {
     unique.len()
 }
 
-/// computes a simplified halstead’s effort proxy for bytecode analysis complexity.
-/// estimates the effort required to understand bytecode by combining unique opcodes (operators)
-/// and total instructions (operands), inspired by eveilm’s halstead metrics (page 59). higher effort
-/// indicates greater difficulty for reverse engineering.
+/// halstead's software science metrics of bytecode (eveilm, page 59), treating every opcode as an
+/// operator and every push immediate as an operand.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Halstead {
+    /// number of distinct operators (opcodes), n1.
+    pub distinct_operators: usize,
+    /// number of distinct operands (push immediates, by value), n2.
+    pub distinct_operands: usize,
+    /// total number of operators, n1 big.
+    pub operators: usize,
+    /// total number of operands, n2 big.
+    pub operands: usize,
+    /// vocabulary, n1 + n2.
+    pub vocabulary: usize,
+    /// length, the total of operators and operands.
+    pub length: usize,
+    /// volume in bits, length * log2(vocabulary).
+    pub volume: f64,
+    /// difficulty, (n1 / 2) * (operands / n2): how hard the code is to understand.
+    pub difficulty: f64,
+    /// effort, difficulty * volume: the work needed to understand the code. higher effort indicates
+    /// greater difficulty for reverse engineering.
+    pub effort: f64,
+}
+
+/// computes halstead's metrics for bytecode.
+/// the code is decoded first, so push data counts as the operand of its push and not as
+/// instructions of its own.
 ///
 /// # arguments
 /// * `bytecode` - slice of raw evm bytecode bytes.
 ///
 /// # returns
-/// floating-point value representing the estimated analysis effort (n1 * n2 * log2(n2)).
+/// the metrics; all zero for empty code.
 ///
 /// # example
 /// ```
-/// let bytecode = vec![0x60, 0x01, 0x01]; // PUSH1, ADD, ADD
-/// let effort = halstead_effort_proxy(&bytecode);
-/// assert!(effort > 0.0); // Effort scales with opcode count and variety
+/// use ebo::halstead;
+///
+/// let bytecode = vec![0x60, 0x01, 0x60, 0x01, 0x01]; // PUSH1 1, PUSH1 1, ADD
+/// let metrics = halstead(&bytecode);
+/// assert_eq!((metrics.distinct_operators, metrics.operators), (2, 3));
+/// assert_eq!((metrics.distinct_operands, metrics.operands), (1, 2));
+/// assert_eq!(metrics.volume, 5.0 * 3f64.log2());
+/// assert_eq!(metrics.difficulty, 2.0);
+/// ```
+pub fn halstead(bytecode: &[u8]) -> Halstead {
+    let instructions = disassemble(bytecode);
+    let operators: HashSet<u8> = instructions.iter().map(|ins| ins.opcode).collect();
+    let operands: Vec<&[u8]> = instructions
+        .iter()
+        .filter(|ins| !ins.immediate.is_empty())
+        .map(|ins| ins.immediate.as_slice())
+        .collect();
+    let distinct_operands = operands.iter().collect::<HashSet<_>>().len();
+
+    let vocabulary = operators.len() + distinct_operands;
+    let length = instructions.len() + operands.len();
+    let volume = match vocabulary {
+        0 => 0.0,
+        _ => length as f64 * (vocabulary as f64).log2(),
+    };
+    let difficulty = match distinct_operands {
+        0 => 0.0,
+        _ => operators.len() as f64 / 2.0 * (operands.len() as f64 / distinct_operands as f64),
+    };
+    Halstead {
+        distinct_operators: operators.len(),
+        distinct_operands,
+        operators: instructions.len(),
+        operands: operands.len(),
+        vocabulary,
+        length,
+        volume,
+        difficulty,
+        effort: difficulty * volume,
+    }
+}
+
+/// computes the shannon entropy of the byte distribution of bytecode, in bits per byte.
+/// compiled code reuses a small set of opcodes and scores well below the maximum of 8; inserted
+/// random constants and junk push it up, so it is a rough measure of how much noise obfuscation
+/// has added.
+///
+/// # arguments
+/// * `bytecode` - slice of raw evm bytecode bytes.
+///
+/// # returns
+/// entropy between 0 (empty, or a single repeated byte) and 8.
+///
+/// # example
+/// ```
+/// use ebo::byte_entropy;
+///
+/// let bytecode = vec![0x60, 0x01, 0x60, 0x02]; // PUSH1 1, PUSH1 2
+/// assert_eq!(byte_entropy(&bytecode), 1.5);
 /// ```
-#[allow(unused)]
-pub fn halstead_effort_proxy(bytecode: &[u8]) -> f64 {
-    let n1 = count_unique_opcodes(bytecode) as f64; // Unique operators
-    let n2 = bytecode.len() as f64; // Total operands
-    
-    n1 * n2 * n2.log2() // Simplified effort
+pub fn byte_entropy(bytecode: &[u8]) -> f64 {
+    entropy(bytecode.iter().copied())
+}
+
+/// computes the shannon entropy of the opcode distribution of bytecode, in bits per instruction.
+/// unlike `byte_entropy`, push data does not count, so it measures how varied the instructions are
+/// rather than the constants they carry: substitutions and inserted identities raise it, random
+/// immediates do not.
+///
+/// # arguments
+/// * `bytecode` - slice of raw evm bytecode bytes.
+///
+/// # returns
+/// entropy between 0 (no instructions, or a single repeated opcode) and 8.
+///
+/// # example
+/// ```
+/// use ebo::opcode_entropy;
+///
+/// let bytecode = vec![0x60, 0x01, 0x60, 0x02]; // PUSH1 1, PUSH1 2
+/// assert_eq!(opcode_entropy(&bytecode), 0.0);
+/// ```
+pub fn opcode_entropy(bytecode: &[u8]) -> f64 {
+    entropy(disassemble(bytecode).into_iter().map(|ins| ins.opcode))
+}
+
+/// computes the deflate compression ratio of bytecode: compressed size over original size, a
+/// practical stand-in for kolmogorov complexity. code that compresses well is repetitive, so
+/// obfuscation that only inserts cheap, repetitive junk shows up as a low ratio even when the size
+/// and the other metrics grow.
+///
+/// # arguments
+/// * `bytecode` - slice of raw evm bytecode bytes.
+///
+/// # returns
+/// the ratio, usually between 0 and a little over 1 for short or random code; 0 for empty code.
+///
+/// # example
+/// ```
+/// use ebo::compression_ratio;
+///
+/// let repetitive = [0x80, 0x50].repeat(100); // DUP1, POP, ...
+/// assert!(compression_ratio(&repetitive) < 0.1);
+/// ```
+pub fn compression_ratio(bytecode: &[u8]) -> f64 {
+    if bytecode.is_empty() {
+        return 0.0;
+    }
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder
+        .write_all(bytecode)
+        .expect("writing to a vector cannot fail");
+    let compressed = encoder.finish().expect("writing to a vector cannot fail");
+    compressed.len() as f64 / bytecode.len() as f64
+}
+
+/// computes the jaccard similarity of the opcode n-grams of two bytecodes: the share of distinct
+/// sequences of `n` consecutive opcodes (push data left out) found in both, out of those found in
+/// either. clone detectors match code on exactly these features, so the lower the similarity
+/// between original and obfuscated code, the less the obfuscated code gives away what it was made
+/// from.
+///
+/// # arguments
+/// * `a` - first bytecode.
+/// * `b` - second bytecode.
+/// * `n` - length of the n-grams, at least 1.
+///
+/// # returns
+/// similarity between 0 (no n-gram in common) and 1 (the same n-grams); 1 if neither has any.
+///
+/// # example
+/// ```
+/// use ebo::ngram_similarity;
+///
+/// let original = [0x60, 0x01, 0x60, 0x02, 0x01, 0x00]; // PUSH1 1, PUSH1 2, ADD, STOP
+/// let reordered = [0x60, 0x02, 0x60, 0x01, 0x01, 0x00]; // PUSH1 2, PUSH1 1, ADD, STOP
+/// assert_eq!(ngram_similarity(&original, &reordered, 2), 1.0);
+/// assert_eq!(ngram_similarity(&original, &[0x00], 2), 0.0);
+/// ```
+pub fn ngram_similarity(a: &[u8], b: &[u8], n: usize) -> f64 {
+    let ngrams = |bytecode: &[u8]| -> HashSet<Vec<u8>> {
+        let opcodes: Vec<u8> = disassemble(bytecode)
+            .into_iter()
+            .map(|ins| ins.opcode)
+            .collect();
+        opcodes.windows(n.max(1)).map(<[u8]>::to_vec).collect()
+    };
+    let (a, b) = (ngrams(a), ngrams(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+/// shannon entropy of a sequence of bytes, in bits per byte.
+fn entropy(values: impl Iterator<Item = u8>) -> f64 {
+    let mut counts = [0usize; 256];
+    let mut total = 0;
+    for value in values {
+        counts[value as usize] += 1;
+        total += 1;
+    }
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
 }
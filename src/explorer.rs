@@ -0,0 +1,179 @@
+/// block explorer access for the ebo obfuscator.
+/// fetches a deployed contract and, when its source is verified, its abi and compiler settings from
+/// an etherscan-compatible api (etherscan and its v2 multichain endpoint, blockscout), so the
+/// function-aware passes can be scoped by function name and the build it came from is on record.
+use crate::abi::{self, Function};
+use crate::rpc;
+use anyhow::{bail, Context};
+use serde_json::Value;
+
+/// environment variable holding the api key, as in foundry.
+pub const API_KEY_VAR: &str = "ETHERSCAN_API_KEY";
+
+/// etherscan's multichain api, which picks the chain from a `chainid` parameter.
+pub const ETHERSCAN_API: &str = "https://api.etherscan.io/v2/api";
+
+/// chains known by name, with their ids.
+const CHAINS: [(&str, u64); 15] = [
+    ("mainnet", 1),
+    ("ethereum", 1),
+    ("sepolia", 11_155_111),
+    ("holesky", 17_000),
+    ("optimism", 10),
+    ("bsc", 56),
+    ("gnosis", 100),
+    ("polygon", 137),
+    ("zksync", 324),
+    ("base", 8_453),
+    ("arbitrum", 42_161),
+    ("polygon-zkevm", 1_101),
+    ("avalanche", 43_114),
+    ("linea", 59_144),
+    ("scroll", 534_352),
+];
+
+/// parses a chain given by name (e.g. `base`) or id (e.g. `8453`).
+pub fn parse_chain(source: &str) -> anyhow::Result<u64> {
+    if let Ok(id) = source.parse() {
+        return Ok(id);
+    }
+    let name = source.to_lowercase();
+    match CHAINS.iter().find(|(known, _)| *known == name) {
+        Some(&(_, id)) => Ok(id),
+        None => bail!(
+            "unknown chain '{}'; give its id or one of: {}",
+            source,
+            CHAINS.map(|(name, _)| name).join(", ")
+        ),
+    }
+}
+
+/// what the explorer knows about a verified contract.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verified {
+    /// contract name.
+    pub name: String,
+    /// compiler version, e.g. `v0.8.26+commit.8a97fa7a`.
+    pub compiler: String,
+    /// optimizer runs, if the optimizer was on.
+    pub optimizer_runs: Option<u64>,
+    /// external functions of the abi.
+    pub functions: Vec<Function>,
+}
+
+/// a contract fetched from an explorer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contract {
+    /// deployed code.
+    pub code: Vec<u8>,
+    /// source metadata, if the contract is verified.
+    pub verified: Option<Verified>,
+}
+
+/// an etherscan-compatible api.
+pub struct Explorer {
+    url: String,
+    chain: u64,
+    key: Option<String>,
+}
+
+impl Explorer {
+    /// an explorer at `url` for `chain`, authenticating with `key` if given.
+    pub fn new(url: &str, chain: u64, key: Option<String>) -> Self {
+        Explorer {
+            url: url.to_string(),
+            chain,
+            key,
+        }
+    }
+
+    /// fetches the code deployed at `address` and, if the contract is verified, its metadata.
+    ///
+    /// # arguments
+    /// * `address` - the contract.
+    /// * `block` - block number (decimal or hex) or tag such as `latest` to read the code at.
+    ///
+    /// # returns
+    /// the contract, or an error if the api cannot be reached, refuses the request, or the
+    /// account has no code.
+    pub fn fetch(&self, address: &[u8; 20], block: &str) -> anyhow::Result<Contract> {
+        let address = format!("0x{}", hex::encode(address));
+        let block = rpc::block_tag(block);
+        let response = self.get(&[
+            ("module", "proxy"),
+            ("action", "eth_getCode"),
+            ("address", &address),
+            ("tag", &block),
+        ])?;
+        let code = rpc::decode(&response)?;
+        if code.is_empty() {
+            bail!(
+                "{} has no code at block {}; is it an externally owned account?",
+                address,
+                block
+            );
+        }
+        let response = self.get(&[
+            ("module", "contract"),
+            ("action", "getsourcecode"),
+            ("address", &address),
+        ])?;
+        Ok(Contract {
+            code,
+            verified: verified(&response)?,
+        })
+    }
+
+    /// sends one api request and returns the response, failing on the errors the api reports.
+    fn get(&self, params: &[(&str, &str)]) -> anyhow::Result<Value> {
+        let chain = self.chain.to_string();
+        let mut request = ureq::get(&self.url).query("chainid", &chain);
+        for (name, value) in params {
+            request = request.query(name, value);
+        }
+        if let Some(key) = &self.key {
+            request = request.query("apikey", key);
+        }
+        let response: Value = request
+            .call()
+            .with_context(|| format!("cannot reach {}", self.url))?
+            .into_json()
+            .with_context(|| format!("invalid response from {}", self.url))?;
+        // errors come as {"status": "0", "message": "NOTOK", "result": "<reason>"}
+        if response["status"] == "0" {
+            bail!(
+                "{} refused the request: {}",
+                self.url,
+                response["result"]
+                    .as_str()
+                    .or(response["message"].as_str())
+                    .unwrap_or("unknown error")
+            );
+        }
+        Ok(response)
+    }
+}
+
+/// the metadata in a `getsourcecode` response, or none if the contract is not verified.
+pub fn verified(response: &Value) -> anyhow::Result<Option<Verified>> {
+    let entry = &response["result"][0];
+    let source = entry["SourceCode"].as_str().unwrap_or_default();
+    let abi = entry["ABI"].as_str().unwrap_or_default();
+    if source.is_empty() || !abi.starts_with('[') {
+        return Ok(None);
+    }
+    let name = entry["ContractName"].as_str().unwrap_or_default();
+    let optimized = entry["OptimizationUsed"] == "1" || entry["OptimizationUsed"] == "true";
+    Ok(Some(Verified {
+        name: name.to_string(),
+        compiler: entry["CompilerVersion"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        optimizer_runs: entry["Runs"]
+            .as_str()
+            .and_then(|runs| runs.parse().ok())
+            .filter(|_| optimized),
+        functions: abi::parse(abi).with_context(|| format!("in the abi of {}", name))?,
+    }))
+}
@@ -0,0 +1,163 @@
+/// c interface of the ebo obfuscator, built with the `ebo-ffi` feature.
+/// lets c, c++, go (cgo), and anything else that can call c link the obfuscator in-process rather
+/// than spawn the cli. every function is declared in `include/ebo.h`; build the library with
+/// `cargo rustc --release --lib --features ebo-ffi --crate-type cdylib` (or `staticlib`).
+///
+/// functions return 0 on success and otherwise the code the cli exits with for the same failure
+/// (see `EboError::exit_code`), with 2 for invalid arguments. the message of the last failure on
+/// the calling thread is available from `ebo_last_error`.
+use crate::config::Config;
+use crate::error::EboError;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+
+/// returned when a call succeeds.
+pub const EBO_OK: i32 = 0;
+/// returned for a failure without a more specific code, including a panic.
+pub const EBO_ERROR: i32 = 1;
+/// returned for a null pointer or an argument that is not valid utf-8.
+pub const EBO_INVALID_ARGUMENT: i32 = 2;
+
+thread_local! {
+    /// message of the last failure on this thread.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// settings of a run, as an `ebo.toml` and a seed.
+#[repr(C)]
+pub struct EboConfig {
+    /// seed of the run.
+    pub seed: u64,
+    /// contents of an `ebo.toml` as a nul-terminated utf-8 string, or null for the defaults. its
+    /// `seed` is ignored in favor of the field above.
+    pub toml: *const c_char,
+}
+
+/// bytes owned by the library, to be released with `ebo_buffer_free`.
+#[repr(C)]
+pub struct EboBuffer {
+    /// the bytes, or null when empty.
+    pub data: *mut u8,
+    /// number of bytes.
+    pub len: usize,
+}
+
+impl EboBuffer {
+    /// hands `bytes` over to the caller.
+    fn new(bytes: Vec<u8>) -> Self {
+        let len = bytes.len();
+        if len == 0 {
+            return EboBuffer {
+                data: std::ptr::null_mut(),
+                len,
+            };
+        }
+        EboBuffer {
+            data: Box::into_raw(bytes.into_boxed_slice()).cast(),
+            len,
+        }
+    }
+}
+
+/// obfuscates runtime code, with the checks `ebo obfuscate` makes.
+///
+/// # arguments
+/// * `bytecode` - the raw code.
+/// * `len` - its length in bytes.
+/// * `config` - settings of the run, or null for the defaults with seed 42.
+/// * `out` - receives the obfuscated code on success; left untouched otherwise.
+///
+/// # returns
+/// `EBO_OK`, or the code of the failure.
+///
+/// # Safety
+/// `bytecode` must point to `len` readable bytes (it may be null if `len` is 0), `config` must be
+/// null or point to a valid `EboConfig` whose `toml` is null or a nul-terminated string, and `out`
+/// must point to writable memory for an `EboBuffer`.
+#[no_mangle]
+pub unsafe extern "C" fn ebo_obfuscate(
+    bytecode: *const u8,
+    len: usize,
+    config: *const EboConfig,
+    out: *mut EboBuffer,
+) -> i32 {
+    if (bytecode.is_null() && len > 0) || out.is_null() {
+        return fail(EBO_INVALID_ARGUMENT, "bytecode and out must not be null");
+    }
+    let bytecode = match len {
+        0 => &[][..],
+        _ => std::slice::from_raw_parts(bytecode, len),
+    };
+    let (seed, toml) = match config.as_ref() {
+        Some(config) if config.toml.is_null() => (config.seed, ""),
+        Some(config) => match CStr::from_ptr(config.toml).to_str() {
+            Ok(toml) => (config.seed, toml),
+            Err(_) => return fail(EBO_INVALID_ARGUMENT, "config toml is not valid utf-8"),
+        },
+        None => (42, ""),
+    };
+    let run = panic::catch_unwind(AssertUnwindSafe(|| {
+        Config::from_toml(toml)?.obfuscate(bytecode, seed)
+    }));
+    match run {
+        Ok(Ok(obfuscation)) => {
+            out.write(EboBuffer::new(obfuscation.bytecode));
+            EBO_OK
+        }
+        Ok(Err(err)) => {
+            let code = err
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<EboError>())
+                .map_or(EBO_ERROR, |err| err.exit_code() as i32);
+            fail(code, &format!("{:#}", err))
+        }
+        Err(_) => fail(EBO_ERROR, "the obfuscator panicked"),
+    }
+}
+
+/// releases a buffer returned by the library and empties it. a buffer that is already empty is
+/// left alone.
+///
+/// # Safety
+/// `buffer` must be null or point to an `EboBuffer` filled in by this library and not modified since.
+#[no_mangle]
+pub unsafe extern "C" fn ebo_buffer_free(buffer: *mut EboBuffer) {
+    let Some(buffer) = buffer.as_mut() else {
+        return;
+    };
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            buffer.data,
+            buffer.len,
+        )));
+    }
+    buffer.data = std::ptr::null_mut();
+    buffer.len = 0;
+}
+
+/// message of the last failure on the calling thread, as a nul-terminated utf-8 string, or null if
+/// no call has failed on it. the string stays valid until the next failing call on the thread.
+#[no_mangle]
+pub extern "C" fn ebo_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// version of the library, as a nul-terminated string with static lifetime.
+#[no_mangle]
+pub extern "C" fn ebo_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// records `message` as the last failure and returns `code`.
+fn fail(code: i32, message: &str) -> i32 {
+    // a message cannot hold a nul byte, so one from the input is cut off there
+    let message = message.split('\0').next().unwrap_or_default();
+    let message = CString::new(message).expect("nul bytes are cut off");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    code
+}
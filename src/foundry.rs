@@ -0,0 +1,273 @@
+/// foundry integration for the ebo obfuscator.
+/// reads the contracts a foundry project builds, and generates a solidity fixture that lets the
+/// project's own tests swap the deployed code of those contracts for the obfuscated build with
+/// `vm.etch`. `ebo test` runs `forge test` once as is and once with the swap enabled, and reports the
+/// tests that only fail on obfuscated code.
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// environment variable that turns the swap in the generated fixture on.
+pub const SWITCH: &str = "EBO_OBFUSCATED";
+
+/// a deployable contract of the project.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artifact {
+    /// contract name.
+    pub name: String,
+    /// source file the contract is defined in, relative to the project root.
+    pub source: String,
+    /// deployed (runtime) bytecode.
+    pub runtime: Vec<u8>,
+}
+
+/// a contract that cannot be swapped, with the reason.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skipped {
+    /// contract name.
+    pub name: String,
+    /// why the contract is left alone.
+    pub reason: &'static str,
+}
+
+/// the parts of a forge artifact ebo reads.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArtifactJson {
+    #[serde(default)]
+    deployed_bytecode: Option<BytecodeJson>,
+    #[serde(default)]
+    metadata: Option<MetadataJson>,
+}
+
+/// code of a contract, as forge writes it.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BytecodeJson {
+    #[serde(default)]
+    object: String,
+    #[serde(default)]
+    immutable_references: BTreeMap<String, serde_json::Value>,
+}
+
+/// compiler metadata, of which only the compilation target (source file and contract) is read.
+#[derive(Deserialize)]
+struct MetadataJson {
+    settings: SettingsJson,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SettingsJson {
+    compilation_target: BTreeMap<String, String>,
+}
+
+/// reads the artifacts of the contracts defined under `sources` (the project's `src` directory)
+/// from a forge output directory. test and script contracts, dependencies, and contracts without
+/// code (interfaces, abstract contracts) are not returned.
+///
+/// # arguments
+/// * `out` - forge output directory, `out/` by default.
+/// * `sources` - source directory of the contracts to swap, relative to the project root.
+///
+/// # returns
+/// the swappable contracts in name order, and the contracts under `sources` that cannot be
+/// swapped: ones with immutables, whose deployed code depends on the constructor arguments, and
+/// ones with unlinked libraries.
+pub fn artifacts(out: &Path, sources: &str) -> anyhow::Result<(Vec<Artifact>, Vec<Skipped>)> {
+    let prefix = format!("{}/", sources.trim_end_matches('/'));
+    let mut files: Vec<PathBuf> = Vec::new();
+    for dir in std::fs::read_dir(out).with_context(|| format!("cannot read {:?}", out))? {
+        let dir = dir?.path();
+        // one directory per source file; build-info holds compiler inputs, not artifacts
+        if !dir.is_dir() || dir.file_name().is_some_and(|name| name == "build-info") {
+            continue;
+        }
+        for file in std::fs::read_dir(&dir)? {
+            let file = file?.path();
+            if file.extension().is_some_and(|ext| ext == "json") {
+                files.push(file);
+            }
+        }
+    }
+    files.sort();
+
+    let mut artifacts = Vec::new();
+    let mut skipped = Vec::new();
+    for file in files {
+        let json = std::fs::read_to_string(&file)?;
+        let artifact: ArtifactJson =
+            serde_json::from_str(&json).with_context(|| format!("invalid artifact {:?}", file))?;
+        let Some((source, name)) = artifact
+            .metadata
+            .and_then(|metadata| metadata.settings.compilation_target.into_iter().next())
+        else {
+            continue;
+        };
+        if !source.starts_with(&prefix) {
+            continue;
+        }
+        let Some(code) = artifact.deployed_bytecode else {
+            continue;
+        };
+        let digits = code.object.trim_start_matches("0x");
+        if digits.is_empty() {
+            continue;
+        }
+        let reason = if !code.immutable_references.is_empty() {
+            Some("immutables are only known once it is deployed")
+        } else if digits.contains("__$") {
+            Some("it links against libraries")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            skipped.push(Skipped { name, reason });
+            continue;
+        }
+        artifacts.push(Artifact {
+            name,
+            source,
+            runtime: hex::decode(digits)
+                .with_context(|| format!("invalid bytecode in {:?}", file))?,
+        });
+    }
+    artifacts.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok((artifacts, skipped))
+}
+
+/// generates the solidity fixture for `contracts` (name and obfuscated runtime code).
+///
+/// the fixture defines `EboFixtures.obfuscate(address target, string name)`, which a test calls in
+/// its `setUp` after deploying contract `name` at `target`. when `EBO_OBFUSCATED` is set, it
+/// replaces the code at `target` with the obfuscated build; otherwise it does nothing, so the same
+/// tests run on both builds.
+pub fn fixture(contracts: &[(String, Vec<u8>)]) -> String {
+    let mut source = String::from(
+        "// SPDX-License-Identifier: UNLICENSED\n\
+         // generated by `ebo test`; do not edit, it is rewritten on every run\n\
+         pragma solidity >=0.8.0;\n\
+         \n\
+         interface EboVm {\n\
+         \x20   function etch(address target, bytes calldata code) external;\n\
+         \x20   function envOr(string calldata name, bool defaultValue) external view returns (bool);\n\
+         }\n\
+         \n\
+         library EboFixtures {\n\
+         \x20   EboVm private constant VM = EboVm(address(uint160(uint256(keccak256(\"hevm cheat code\")))));\n\
+         \n\
+         \x20   /// replaces the code at `target` with the obfuscated build of contract `name` when\n\
+         \x20   /// EBO_OBFUSCATED is set.\n\
+         \x20   function obfuscate(address target, string memory name) internal {\n\
+         \x20       if (!VM.envOr(\"EBO_OBFUSCATED\", false)) return;\n\
+         \x20       bytes32 id = keccak256(bytes(name));\n",
+    );
+    for (name, code) in contracts {
+        source.push_str(&format!(
+            "        if (id == keccak256(\"{}\")) {{\n            VM.etch(target, hex\"{}\");\n            return;\n        }}\n",
+            name,
+            hex::encode(code)
+        ));
+    }
+    source.push_str("        revert(\"ebo: no obfuscated build of this contract\");\n    }\n}\n");
+    source
+}
+
+/// outcome of one test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// the test passed.
+    Passed,
+    /// the test failed or reverted unexpectedly.
+    Failed,
+    /// the test skipped itself.
+    Skipped,
+}
+
+/// a test contract in the output of `forge test --json`.
+#[derive(Deserialize)]
+struct SuiteJson {
+    test_results: BTreeMap<String, TestJson>,
+}
+
+/// a test function in the output of `forge test --json`.
+#[derive(Deserialize)]
+struct TestJson {
+    status: String,
+}
+
+/// parses the output of `forge test --json` into the status of every test, keyed by
+/// `path:Contract::test()`.
+pub fn results(json: &str) -> anyhow::Result<BTreeMap<String, Status>> {
+    let suites: BTreeMap<String, SuiteJson> =
+        serde_json::from_str(json).context("invalid `forge test --json` output")?;
+    let mut results = BTreeMap::new();
+    for (suite, tests) in suites {
+        for (test, result) in tests.test_results {
+            let status = match result.status.as_str() {
+                "Success" => Status::Passed,
+                "Skipped" => Status::Skipped,
+                _ => Status::Failed,
+            };
+            results.insert(format!("{}::{}", suite, test), status);
+        }
+    }
+    Ok(results)
+}
+
+/// tests that pass on the original build and fail on the obfuscated one.
+pub fn regressions(
+    original: &BTreeMap<String, Status>,
+    obfuscated: &BTreeMap<String, Status>,
+) -> Vec<String> {
+    original
+        .iter()
+        .filter(|(_, &status)| status == Status::Passed)
+        .filter(|(test, _)| obfuscated.get(*test) != Some(&Status::Passed))
+        .map(|(test, _)| test.clone())
+        .collect()
+}
+
+/// runs `forge test --json` in `project`, with the fixture switch on or off.
+///
+/// # returns
+/// the status of every test; forge's exit status is not checked, since failing tests make it
+/// non-zero.
+pub fn test(project: &Path, obfuscated: bool) -> anyhow::Result<BTreeMap<String, Status>> {
+    let output = forge(project, &["test", "--json"], obfuscated)?;
+    if output.stdout.is_empty() {
+        bail!(
+            "forge test failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    results(&String::from_utf8(output.stdout)?)
+}
+
+/// runs `forge build` in `project`.
+pub fn build(project: &Path) -> anyhow::Result<()> {
+    let output = forge(project, &["build"], false)?;
+    if !output.status.success() {
+        bail!(
+            "forge build failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+/// runs `forge` with `args` in `project`, with the fixture switch on or off.
+fn forge(project: &Path, args: &[&str], obfuscated: bool) -> anyhow::Result<Output> {
+    let mut command = Command::new("forge");
+    command.args(args).current_dir(project);
+    if obfuscated {
+        command.env(SWITCH, "true");
+    } else {
+        command.env_remove(SWITCH);
+    }
+    command
+        .output()
+        .context("cannot run forge; is foundry installed and on the PATH?")
+}
@@ -0,0 +1,108 @@
+/// differential fuzzing for the ebo obfuscator.
+/// generates calldata, runs the original and the obfuscated bytecode on it, and stops at the first
+/// input on which they behave differently. calldata follows the abi when one is given; otherwise it
+/// starts with the selectors the dispatcher compares against, so runs get past the dispatcher.
+use crate::abi;
+use crate::dispatcher;
+use crate::equivalence::{self, Divergence};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tracing::debug;
+
+/// how the calldata of a run was made, reported with a divergence.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    /// a call of this abi function with random arguments.
+    Call(String),
+    /// a known selector followed by random words.
+    Selector([u8; 4]),
+    /// random bytes.
+    Random,
+}
+
+/// result of a fuzzing campaign.
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// number of inputs run, including the diverging one.
+    pub runs: usize,
+    /// first diverging input, if any, with how it was made.
+    pub divergence: Option<(Input, Divergence)>,
+}
+
+/// a differential fuzzer over a pair of binaries.
+pub struct Fuzzer<'a> {
+    original: &'a [u8],
+    obfuscated: &'a [u8],
+    /// functions from the abi; empty when there is none.
+    functions: Vec<abi::Function>,
+    /// selectors the dispatcher of the original compares against.
+    selectors: Vec<[u8; 4]>,
+}
+
+impl<'a> Fuzzer<'a> {
+    /// creates a fuzzer comparing `obfuscated` against `original`, generating calls to
+    /// `functions` when any are given.
+    pub fn new(original: &'a [u8], obfuscated: &'a [u8], functions: Vec<abi::Function>) -> Self {
+        let selectors = dispatcher::functions(original)
+            .into_iter()
+            .map(|function| function.selector)
+            .collect();
+        Fuzzer {
+            original,
+            obfuscated,
+            functions,
+            selectors,
+        }
+    }
+
+    /// generates the calldata of one run.
+    fn input(&self, rng: &mut StdRng) -> (Input, Vec<u8>) {
+        // a share of purely random inputs exercises the fallback and the revert paths
+        if rng.gen_bool(0.1) || (self.functions.is_empty() && self.selectors.is_empty()) {
+            let len = rng.gen_range(0..=68);
+            return (Input::Random, (0..len).map(|_| rng.gen()).collect());
+        }
+        if !self.functions.is_empty() {
+            let function = &self.functions[rng.gen_range(0..self.functions.len())];
+            return (Input::Call(function.signature()), function.random_call(rng));
+        }
+        let selector = self.selectors[rng.gen_range(0..self.selectors.len())];
+        let words = rng.gen_range(0..=4);
+        let mut calldata = selector.to_vec();
+        for _ in 0..words {
+            let mut word = [0u8; 32];
+            // small values are likelier to be valid amounts, indices, and booleans
+            match rng.gen_range(0..3) {
+                0 => word[31] = rng.gen(),
+                1 => word[12..].iter_mut().for_each(|b| *b = rng.gen()),
+                _ => rng.fill(&mut word[..]),
+            }
+            calldata.extend(word);
+        }
+        (Input::Selector(selector), calldata)
+    }
+
+    /// runs up to `runs` generated inputs, stopping at the first divergence.
+    ///
+    /// # arguments
+    /// * `runs` - largest number of inputs to try.
+    /// * `seed` - seed of the input generator; the same seed generates the same inputs.
+    pub fn run(&self, runs: usize, seed: u64) -> anyhow::Result<Report> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for run in 1..=runs {
+            let (input, calldata) = self.input(&mut rng);
+            let comparison = equivalence::compare(self.original, self.obfuscated, &calldata)?;
+            if let Some(divergence) = comparison.divergence() {
+                debug!("Run {} diverged on {:?}", run, input);
+                return Ok(Report {
+                    runs: run,
+                    divergence: Some((input, divergence)),
+                });
+            }
+        }
+        Ok(Report {
+            runs,
+            divergence: None,
+        })
+    }
+}
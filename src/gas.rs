@@ -0,0 +1,309 @@
+/// static gas model for the ebo obfuscator.
+/// estimates the runtime gas of every path through a lifted program from the static cost of the
+/// instructions on it, so the overhead a transformation adds to each path can be measured before the
+/// result is accepted. dynamic costs (memory expansion, cold accesses, copies, refunds) depend on the
+/// input and are left out; they are the same before and after obfuscation, so leaving them out only
+/// makes the measured overhead larger. the exception are reads a transformation inserts of constant
+/// slots the input never touches (see `passes::decoy_reads`): under eip-2929 the first read of such a
+/// slot in a transaction is cold, so every path is charged the cold cost once for each slot it reads
+/// and the warm cost for every read after.
+use crate::evm::{is_terminator, op, stack_io, OPCODES};
+use crate::reloc::{Item, Label, Program};
+use std::collections::{HashMap, HashSet};
+
+/// paths enumerated at most; large contracts are measured on the first paths found.
+const MAX_PATHS: usize = 4096;
+
+/// gas a cold `sload` costs on top of the warm cost `static_cost` charges (eip-2929: 2100 vs 100).
+pub const COLD_SLOAD: u64 = 2100 - 100;
+
+/// returns the static gas cost of `opcode` (cancun), using warm costs for account and storage
+/// access. terminators that end execution without running out of gas cost nothing.
+pub fn static_cost(opcode: u8) -> u64 {
+    OPCODES[opcode as usize].gas
+}
+
+/// static gas that running `items` from the first one is certain to spend before execution can
+/// leave the straight-line code they start with: everything up to and including the first jump,
+/// conditional jump or terminator. the static cost is the least an instruction can cost on every
+/// supported fork, so with less gas left than this, execution cannot end other than by an
+/// exceptional halt. data, frozen regions and undefined opcodes end the count early, which only
+/// makes the result smaller.
+///
+/// # example
+/// ```
+/// use ebo::{gas, reloc::Item};
+///
+/// // PUSH1 1, PUSH0, SSTORE, STOP, then code only reachable by a jump
+/// let items = Item::sequence(&[0x60, 0x01, 0x5F, 0x55, 0x00, 0x5B]);
+/// assert_eq!(gas::committed(&items), 3 + 2 + 100);
+/// ```
+pub fn committed(items: &[Item]) -> u64 {
+    let mut gas = 0;
+    for item in items {
+        match item {
+            Item::Mark(_) => {}
+            Item::PushLabel { .. } => gas += static_cost(op::PUSH1),
+            Item::Op { opcode, .. } if stack_io(*opcode).is_some() => {
+                gas += static_cost(*opcode);
+                if is_terminator(*opcode) || *opcode == op::JUMPI {
+                    break;
+                }
+            }
+            Item::Op { .. } | Item::Data(_) | Item::Frozen(_) => break,
+        }
+    }
+    gas
+}
+
+/// where a traced segment hands control to the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Exit {
+    /// the conditional jump with this input offset; the segment continues on its fall-through side.
+    Branch(usize),
+    /// the segment runs into the original jumpdest marked by this label.
+    Enter(Label),
+    /// execution ends, or continues at a target that cannot be resolved statically.
+    End,
+}
+
+/// gas spent from the start of a segment up to each of its exits, in execution order, and the
+/// constant slots inserted reads load on the way.
+type Trace = Vec<(Exit, u64, Option<Label>, Vec<[u8; 32]>)>;
+
+/// a path through the original program: the segments it runs through and where it leaves each one.
+type Path = Vec<(Option<Label>, Exit)>;
+
+/// gas profile of the original program, against which transformed programs are measured.
+pub struct Baseline {
+    /// labels of the jumpdests of the original program; segments start at these.
+    labels: HashSet<Label>,
+    /// paths from the start of the code, enumerated without revisiting a segment.
+    paths: Vec<Path>,
+    /// gas of every original path, in the order of `paths`.
+    costs: Vec<u64>,
+}
+
+impl Baseline {
+    /// profiles `program`, which must be the untransformed lifted input.
+    pub fn new(program: &Program) -> Self {
+        let items = program.flat_items();
+        let marks = marks(&items);
+        let labels: HashSet<Label> = marks.keys().copied().collect();
+        let traces = traces(&items, &marks, &labels);
+
+        let mut paths: Vec<Path> = Vec::new();
+        let mut pending: Vec<(Option<Label>, Path)> = vec![(None, Vec::new())];
+        while let Some((segment, path)) = pending.pop() {
+            if paths.len() >= MAX_PATHS {
+                break;
+            }
+            for &(exit, _, target, _) in &traces[&segment] {
+                let mut next = path.clone();
+                next.push((segment, exit));
+                let unvisited = |label: Label| !next.iter().any(|(seg, _)| *seg == Some(label));
+                match exit {
+                    // the taken side continues at the target, the fall-through side in this segment
+                    Exit::Branch(_) => match target.filter(|l| labels.contains(l)) {
+                        Some(label) if unvisited(label) => pending.push((Some(label), next)),
+                        _ => paths.push(next),
+                    },
+                    Exit::Enter(label) => {
+                        if unvisited(label) {
+                            pending.push((Some(label), next));
+                        } else {
+                            paths.push(next);
+                        }
+                        break;
+                    }
+                    Exit::End => {
+                        paths.push(next);
+                        break;
+                    }
+                }
+            }
+        }
+
+        let costs = paths.iter().map(|path| path_cost(path, &traces)).collect();
+        Baseline {
+            labels,
+            paths,
+            costs,
+        }
+    }
+
+    /// number of paths the baseline measures.
+    pub fn paths(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// static gas of every path the baseline measures.
+    pub fn costs(&self) -> &[u64] {
+        &self.costs
+    }
+
+    /// largest relative gas overhead that `program` adds to any path of the original, e.g. `0.15`
+    /// for 15% more gas on the most affected path. paths that cost nothing in the original are
+    /// ignored.
+    pub fn overhead(&self, program: &Program) -> f64 {
+        let items = program.flat_items();
+        let marks = marks(&items);
+        let traces = traces(&items, &marks, &self.labels);
+        self.paths
+            .iter()
+            .zip(&self.costs)
+            .filter(|(_, &old)| old > 0)
+            .map(|(path, &old)| {
+                let new = path_cost(path, &traces);
+                new.saturating_sub(old) as f64 / old as f64
+            })
+            .fold(0.0, f64::max)
+    }
+}
+
+/// item index of every label mark in `items`.
+fn marks(items: &[&Item]) -> HashMap<Label, usize> {
+    items
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, item)| match item {
+            Item::Mark(label) => Some((*label, idx)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// traces the segment starting at the beginning of the code and at every label in `labels` that is
+/// marked in `items`.
+fn traces(
+    items: &[&Item],
+    marks: &HashMap<Label, usize>,
+    labels: &HashSet<Label>,
+) -> HashMap<Option<Label>, Trace> {
+    let mut traces = HashMap::new();
+    traces.insert(None, trace(items, marks, labels, 0));
+    for &label in labels {
+        if let Some(&start) = marks.get(&label) {
+            traces.insert(Some(label), trace(items, marks, labels, start + 1));
+        }
+    }
+    traces
+}
+
+/// follows execution from item `start`, through unconditional jumps to known labels and along the
+/// fall-through side of conditional jumps, until it ends or enters a segment of its own. jump targets
+/// are tracked on an abstract stack that only knows which entries are label pushes, which is enough
+/// to follow spaghetti links and returns from outlined subroutines. an inserted `sload` right after
+/// an inserted push reads the pushed constant slot, which is recorded for `path_cost`.
+fn trace(
+    items: &[&Item],
+    marks: &HashMap<Label, usize>,
+    labels: &HashSet<Label>,
+    start: usize,
+) -> Trace {
+    let mut exits = Vec::new();
+    let mut stack: Vec<Option<Label>> = Vec::new();
+    let mut slots: Vec<[u8; 32]> = Vec::new();
+    let mut gas = 0;
+    let mut idx = start;
+    // every item is visited at most twice unless the code loops without a conditional jump
+    for _ in 0..items.len() * 2 + 1 {
+        let Some(item) = items.get(idx) else {
+            break;
+        };
+        idx += 1;
+        match item {
+            Item::Mark(label) if labels.contains(label) => {
+                exits.push((Exit::Enter(*label), gas, None, slots));
+                return exits;
+            }
+            Item::Mark(_) => {}
+            Item::Data(_) => break,
+            Item::PushLabel { label, addend, .. } => {
+                gas += static_cost(op::PUSH1);
+                stack.push((*addend == 0).then_some(*label));
+            }
+            Item::Op { opcode, origin, .. } => {
+                gas += static_cost(*opcode);
+                match *opcode {
+                    op::JUMP => match stack.pop().flatten().and_then(|l| marks.get(&l)) {
+                        Some(&target) => idx = target,
+                        None => break,
+                    },
+                    op::JUMPI => {
+                        let target = stack.pop().flatten();
+                        stack.pop();
+                        if let Some(origin) = origin {
+                            exits.push((Exit::Branch(*origin), gas, target, slots.clone()));
+                        }
+                    }
+                    op::SLOAD if origin.is_none() => {
+                        if let Some(Item::Op {
+                            opcode: op::PUSH0..=op::PUSH32,
+                            immediate,
+                            origin: None,
+                        }) = idx.checked_sub(2).map(|prev| items[prev])
+                        {
+                            let mut slot = [0; 32];
+                            slot[32 - immediate.len()..].copy_from_slice(immediate);
+                            if !slots.contains(&slot) {
+                                slots.push(slot);
+                            }
+                        }
+                        stack.pop();
+                        stack.push(None);
+                    }
+                    0x80..=0x8F => {
+                        let n = (opcode - 0x80) as usize + 1;
+                        let value = stack.len().checked_sub(n).and_then(|i| stack[i]);
+                        stack.push(value);
+                    }
+                    0x90..=0x9F => {
+                        let n = (opcode - 0x90) as usize + 1;
+                        if stack.len() > n {
+                            let top = stack.len() - 1;
+                            stack.swap(top, top - n);
+                        } else {
+                            stack.clear();
+                        }
+                    }
+                    opcode if is_terminator(opcode) => break,
+                    opcode => match stack_io(opcode) {
+                        Some((popped, pushed)) => {
+                            stack.truncate(stack.len().saturating_sub(popped));
+                            stack.extend(std::iter::repeat_n(None, pushed));
+                        }
+                        None => break,
+                    },
+                }
+            }
+            Item::Frozen(_) => unreachable!("programs are flattened before tracing"),
+        }
+    }
+    exits.push((Exit::End, gas, None, slots));
+    exits
+}
+
+/// gas of `path` according to `traces`, with the cold surcharge for every distinct slot inserted
+/// reads load along it. an exit the traced segment never reaches (because a transformation changed
+/// where it ends) is charged the most gas the segment spends anywhere.
+fn path_cost(path: &Path, traces: &HashMap<Option<Label>, Trace>) -> u64 {
+    let mut cold = HashSet::new();
+    let gas: u64 = path
+        .iter()
+        .map(|(segment, exit)| {
+            let Some(trace) = traces.get(segment) else {
+                return 0;
+            };
+            trace
+                .iter()
+                .find(|(e, _, _, _)| e == exit)
+                .or_else(|| trace.iter().max_by_key(|(_, gas, _, _)| *gas))
+                .map_or(0, |(_, gas, _, slots)| {
+                    cold.extend(slots.iter().copied());
+                    *gas
+                })
+        })
+        .sum();
+    gas + cold.len() as u64 * COLD_SLOAD
+}
@@ -0,0 +1,228 @@
+/// html reports for the ebo obfuscator.
+/// renders the summary of a run as a single html file with no external resources: the metric
+/// tables, what every pass did, the control flow graph of the code before and after as inline svg,
+/// and both disassemblies, so a run can be shared with people who do not use the cli.
+use crate::cfg::Cfg;
+use crate::evm::{disassemble, mnemonic, op};
+use crate::summary::{Metrics, Summary};
+use std::fmt::Write;
+
+/// blocks drawn at most per graph; the rest of a larger graph is left out.
+const MAX_BLOCKS: usize = 1000;
+/// height of a block in the graph, in pixels.
+const ROW: usize = 20;
+/// width of a block in the graph, in pixels.
+const BLOCK_WIDTH: usize = 150;
+
+/// styles of the report.
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:1.5em}\
+td,th{border:1px solid #ccc;padding:.25em .75em;text-align:right}\
+td:first-child,th:first-child{text-align:left}\
+.side{display:flex;gap:2em;align-items:flex-start}\
+.side>div{flex:1;overflow:auto}\
+pre{font-size:12px;background:#f6f6f6;padding:1em;max-height:40em;overflow:auto}\
+svg text{font:11px monospace}";
+
+/// renders `summary` of the run that turned `input` into `output` as an html page.
+///
+/// # example
+/// ```
+/// use ebo::{html, summary::Summary, Config};
+/// use std::collections::BTreeMap;
+///
+/// let code = [0x60, 0x01, 0x60, 0x02, 0x01, 0x00];
+/// let summary = Summary::new(&code, &code, 42, &Config::default(), &BTreeMap::new()).unwrap();
+/// let page = html::report(&summary, &code, &code);
+/// assert!(page.starts_with("<!DOCTYPE html>") && page.contains("PUSH1 0x01"));
+/// ```
+pub fn report(summary: &Summary, input: &[u8], output: &[u8]) -> String {
+    let mut page = String::new();
+    let _ = write!(
+        page,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>ebo report</title>\
+         <style>{}</style></head><body>\n<h1>ebo report</h1>\n<p>ebo {}, seed {}{}, \
+         configuration {}</p>\n<p><strong>grade {}</strong> ({:.2}): potency {:+.1}%, \
+         resilience {:.0}%, cost {:+.1}%</p>\n",
+        STYLE,
+        escape(&summary.version),
+        summary.seed,
+        if summary.keyed { " (keyed)" } else { "" },
+        escape(&summary.config),
+        summary.score.grade,
+        summary.score.value,
+        summary.score.potency * 100.0,
+        summary.score.resilience * 100.0,
+        summary.score.cost * 100.0
+    );
+
+    page.push_str("<h2>metrics</h2>\n");
+    page.push_str(&metrics(&summary.input, &summary.output));
+    page.push_str("<table><tr><th>change</th><th>value</th></tr>\n");
+    let changes = [
+        ("byte entropy", summary.entropy_delta),
+        ("opcode entropy", summary.opcode_entropy_delta),
+        ("compression ratio", summary.compression_ratio_delta),
+    ];
+    for (name, delta) in changes {
+        let _ = writeln!(page, "<tr><td>{}</td><td>{:+.3}</td></tr>", name, delta);
+    }
+    for (n, similarity) in &summary.ngram_similarity {
+        let _ = writeln!(
+            page,
+            "<tr><td>opcode {}-gram similarity</td><td>{:.3}</td></tr>",
+            n, similarity
+        );
+    }
+    page.push_str("</table>\n");
+
+    page.push_str("<h2>passes</h2>\n<table><tr><th>pass</th><th>transformations</th></tr>\n");
+    for (pass, count) in &summary.passes {
+        let _ = writeln!(page, "<tr><td>{}</td><td>{}</td></tr>", escape(pass), count);
+    }
+    page.push_str("</table>\n");
+
+    page.push_str("<h2>control flow</h2>\n<div class=\"side\">");
+    for (title, code) in [("before", input), ("after", output)] {
+        let _ = write!(page, "<div><h3>{}</h3>{}</div>", title, graph(code));
+    }
+    page.push_str("</div>\n<h2>disassembly</h2>\n<div class=\"side\">");
+    for (title, code) in [("before", input), ("after", output)] {
+        let _ = write!(
+            page,
+            "<div><h3>{}</h3><pre>{}</pre></div>",
+            title,
+            listing(code)
+        );
+    }
+    page.push_str("</div>\n</body></html>\n");
+    page
+}
+
+/// formats one metric of a bytecode.
+type Measure = fn(&Metrics) -> String;
+
+/// table of the metrics of the code before and after.
+fn metrics(input: &Metrics, output: &Metrics) -> String {
+    let rows: [(&str, Measure); 13] = [
+        ("size (bytes)", |m| m.size.to_string()),
+        ("conditional blocks", |m| m.cfg_complexity.to_string()),
+        ("blocks", |m| m.cfg.nodes.to_string()),
+        ("edges", |m| m.cfg.edges.to_string()),
+        ("average block size", |m| {
+            format!("{:.2}", m.cfg.average_block_size)
+        }),
+        ("largest block", |m| m.cfg.max_block_size.to_string()),
+        ("branch density", |m| format!("{:.3}", m.cfg.branch_density)),
+        ("halstead volume", |m| format!("{:.0}", m.halstead.volume)),
+        ("halstead difficulty", |m| {
+            format!("{:.1}", m.halstead.difficulty)
+        }),
+        ("halstead effort", |m| format!("{:.0}", m.halstead.effort)),
+        ("byte entropy", |m| format!("{:.3}", m.entropy)),
+        ("opcode entropy", |m| format!("{:.3}", m.opcode_entropy)),
+        ("compression ratio", |m| {
+            format!("{:.3}", m.compression_ratio)
+        }),
+    ];
+    let mut table = String::from("<table><tr><th>metric</th><th>input</th><th>output</th></tr>\n");
+    for (name, value) in rows {
+        let _ = writeln!(
+            table,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            name,
+            value(input),
+            value(output)
+        );
+    }
+    table.push_str("</table>\n");
+    table
+}
+
+/// the control flow graph of `bytecode` as an arc diagram: blocks in code order from top to
+/// bottom, fall-through edges between neighbours, and every other edge as an arc on the right,
+/// drawn in red when it leads backwards. blocks ending in a conditional jump are shaded.
+fn graph(bytecode: &[u8]) -> String {
+    let cfg = Cfg::new(bytecode);
+    let shown = cfg.blocks.len().min(MAX_BLOCKS);
+    let mut arcs = String::new();
+    let mut reach = 0;
+    for idx in 0..shown {
+        for target in cfg.successors(idx).filter(|&target| target < shown) {
+            let (from, to) = (idx * ROW + ROW / 2, target * ROW + ROW / 2);
+            if target == idx + 1 {
+                let _ = write!(
+                    arcs,
+                    "<line x1=\"{x}\" y1=\"{}\" x2=\"{x}\" y2=\"{}\" stroke=\"#888\"/>",
+                    idx * ROW + ROW - 4,
+                    target * ROW,
+                    x = BLOCK_WIDTH / 2
+                );
+                continue;
+            }
+            let bulge = 12 + 3 * idx.abs_diff(target).min(100);
+            reach = reach.max(bulge);
+            let _ = write!(
+                arcs,
+                "<path d=\"M{x} {from} C{c} {from} {c} {to} {x} {to}\" fill=\"none\" stroke=\"{}\"/>",
+                if target <= idx { "#c33" } else { "#36c" },
+                x = BLOCK_WIDTH,
+                c = BLOCK_WIDTH + bulge,
+            );
+        }
+    }
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+        BLOCK_WIDTH + reach + 10,
+        shown * ROW
+    );
+    for (idx, block) in cfg.blocks.iter().take(shown).enumerate() {
+        let branches = cfg.instructions[block.last - 1].opcode == op::JUMPI;
+        let _ = write!(
+            svg,
+            "<rect x=\"0\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"#888\"/>\
+             <text x=\"4\" y=\"{}\">{:#06x} ({} ops)</text>",
+            idx * ROW,
+            BLOCK_WIDTH,
+            ROW - 4,
+            if branches { "#fde9c8" } else { "#eef" },
+            idx * ROW + 12,
+            block.range.start,
+            block.last - block.first
+        );
+    }
+    svg.push_str(&arcs);
+    svg.push_str("</svg>");
+    if shown < cfg.blocks.len() {
+        let _ = write!(
+            svg,
+            "<p>first {} of {} blocks shown</p>",
+            shown,
+            cfg.blocks.len()
+        );
+    }
+    svg
+}
+
+/// one line per instruction: offset, mnemonic, and immediate.
+fn listing(bytecode: &[u8]) -> String {
+    let mut text = String::new();
+    for ins in disassemble(bytecode) {
+        let name = mnemonic(ins.opcode);
+        let _ = write!(text, "{:06x}  {}", ins.offset, name);
+        if !ins.immediate.is_empty() {
+            let _ = write!(text, " 0x{}", hex::encode(&ins.immediate));
+        }
+        text.push('\n');
+    }
+    escape(&text)
+}
+
+/// escapes `text` for html.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,261 @@
+/// plausible decoy code for the ebo obfuscator.
+/// junk in unreachable regions gives itself away when it is nothing a compiler emits: `push, pop`
+/// pairs behind a `stop` are skipped by any analyst at a glance. this module generates regions
+/// that disassemble into what solc does emit (a fragment of a selector dispatcher, storage
+/// updates, an owner check, a revert with an `Error(string)` reason), so they read like logic of
+/// the contract and cost time to rule out. every region starts with a `jumpdest` no jump leads to,
+/// and every path through it ends in a terminator, so it never runs and never falls through into the
+/// code behind it.
+use crate::chain::Target;
+use crate::evm::op;
+use crate::reloc::{Item, Program};
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+
+// opcodes the idioms use that `evm::op` has no name for
+const KECCAK256: u8 = 0x20;
+const CALLER: u8 = 0x33;
+const CALLVALUE: u8 = 0x34;
+const DUP3: u8 = 0x82;
+const SWAP2: u8 = 0x91;
+
+/// revert reasons solidity contracts and the libraries they build on commonly use, each at most
+/// 32 bytes, so it is loaded with a single `push32` as solc does.
+pub const REASONS: [&str; 10] = [
+    "Ownable: caller is not the owner",
+    "ERC20: insufficient allowance",
+    "ERC20: mint to the zero address",
+    "ReentrancyGuard: reentrant call",
+    "Pausable: paused",
+    "insufficient balance",
+    "not authorized",
+    "transfer failed",
+    "invalid amount",
+    "deadline expired",
+];
+
+/// a kind of code solc emits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Idiom {
+    /// selector comparisons branching to function bodies, reverting on no match.
+    Dispatcher,
+    /// a storage update: a counter increment or a write to a mapping keyed by the caller.
+    Storage,
+    /// a check that the caller is the owner kept in storage, guarding a storage write.
+    OwnerCheck,
+    /// a revert with an `Error(string)` reason built in memory.
+    Revert,
+}
+
+impl Idiom {
+    /// every idiom.
+    pub const ALL: [Idiom; 4] = [
+        Idiom::Dispatcher,
+        Idiom::Storage,
+        Idiom::OwnerCheck,
+        Idiom::Revert,
+    ];
+
+    /// the idioms that can be the body of a function the dispatcher branches to.
+    const BODIES: [Idiom; 3] = [Idiom::Storage, Idiom::OwnerCheck, Idiom::Revert];
+}
+
+/// instructions of an idiom being generated.
+struct Writer<'a> {
+    items: Vec<Item>,
+    program: &'a mut Program,
+    rng: &'a mut dyn RngCore,
+    /// pushes zero with `push0` where the target has it, as solc does.
+    push0: bool,
+}
+
+impl Writer<'_> {
+    fn op(&mut self, opcode: u8) {
+        self.items.push(Item::op(opcode));
+    }
+
+    fn push(&mut self, value: &[u8]) {
+        self.items.push(Item::push(value));
+    }
+
+    fn zero(&mut self) {
+        if self.push0 {
+            self.op(op::PUSH0);
+        } else {
+            self.push(&[0]);
+        }
+    }
+
+    /// `zero, dup1, revert`, how solc reverts without a reason.
+    fn revert(&mut self) {
+        self.zero();
+        self.op(op::DUP1);
+        self.op(op::REVERT);
+    }
+
+    /// a storage slot in the range solc assigns to state variables.
+    fn slot(&mut self) -> u8 {
+        self.rng.gen_range(0..8)
+    }
+
+    /// the code of `idiom`, after its `jumpdest`.
+    fn body(&mut self, idiom: Idiom) {
+        match idiom {
+            Idiom::Dispatcher => {
+                let mut bodies = Vec::new();
+                for _ in 0..self.rng.gen_range(1..=3) {
+                    let label = self.program.new_label();
+                    self.op(op::DUP1);
+                    let selector: u32 = self.rng.gen();
+                    self.push(&selector.to_be_bytes());
+                    self.op(op::EQ);
+                    self.items.push(Item::PushLabel {
+                        label,
+                        addend: 0,
+                        width: 2,
+                    });
+                    self.op(op::JUMPI);
+                    bodies.push(label);
+                }
+                self.revert();
+                for label in bodies {
+                    let body = *Idiom::BODIES.choose(self.rng).expect("not empty");
+                    self.items.push(Item::Mark(label));
+                    self.op(op::JUMPDEST);
+                    self.body(body);
+                }
+            }
+            Idiom::Storage if self.rng.gen_bool(0.5) => {
+                // counter += 1
+                let slot = self.slot();
+                self.push(&[slot]);
+                self.op(op::SLOAD);
+                self.push(&[1]);
+                self.op(op::ADD);
+                self.push(&[slot]);
+                self.op(op::SSTORE);
+                self.op(op::STOP);
+            }
+            Idiom::Storage => {
+                // balances[msg.sender] += msg.value
+                let slot = self.slot();
+                self.op(CALLER);
+                self.zero();
+                self.op(op::MSTORE);
+                self.push(&[slot]);
+                self.push(&[0x20]);
+                self.op(op::MSTORE);
+                self.push(&[0x40]);
+                self.zero();
+                self.op(KECCAK256);
+                self.op(op::DUP1);
+                self.op(op::SLOAD);
+                self.op(CALLVALUE);
+                self.op(op::ADD);
+                self.op(op::SWAP1);
+                self.op(op::SSTORE);
+                self.op(op::STOP);
+            }
+            Idiom::OwnerCheck => {
+                // require(msg.sender == owner), then a storage update
+                let owner = self.slot();
+                let ok = self.program.new_label();
+                self.push(&[owner]);
+                self.op(op::SLOAD);
+                self.push(&[0xFF; 20]);
+                self.op(op::AND);
+                self.op(CALLER);
+                self.op(op::EQ);
+                self.items.push(Item::PushLabel {
+                    label: ok,
+                    addend: 0,
+                    width: 2,
+                });
+                self.op(op::JUMPI);
+                self.revert();
+                self.items.push(Item::Mark(ok));
+                self.op(op::JUMPDEST);
+                self.body(Idiom::Storage);
+            }
+            Idiom::Revert => {
+                let reason = REASONS.choose(self.rng).expect("not empty").as_bytes();
+                let mut word = [0; 32];
+                word[..reason.len()].copy_from_slice(reason);
+                // mstore(ptr, 0x08c379a0 << 224), the selector of Error(string), as solc builds it
+                self.push(&[0x40]);
+                self.op(op::MLOAD);
+                self.push(&[0x46, 0x1B, 0xCD]);
+                self.push(&[0xE5]);
+                self.op(op::SHL);
+                self.op(op::DUP2);
+                self.op(op::MSTORE);
+                // the offset, length, and text of the reason
+                for (value, offset) in [(&[0x20][..], 0x04), (&[reason.len() as u8][..], 0x24)] {
+                    self.push(value);
+                    self.push(&[offset]);
+                    self.op(DUP3);
+                    self.op(op::ADD);
+                    self.op(op::MSTORE);
+                }
+                self.push(&word);
+                self.push(&[0x44]);
+                self.op(DUP3);
+                self.op(op::ADD);
+                self.op(op::MSTORE);
+                // revert(ptr, end - ptr)
+                self.push(&[0x64]);
+                self.op(op::ADD);
+                self.push(&[0x40]);
+                self.op(op::MLOAD);
+                self.op(op::DUP1);
+                self.op(SWAP2);
+                self.op(op::SUB);
+                self.op(op::SWAP1);
+                self.op(op::REVERT);
+            }
+        }
+    }
+}
+
+/// an unreachable region of `idiom`, with labels allocated from `program` and pushes of zero
+/// valid for `target`.
+///
+/// # example
+/// ```
+/// use ebo::idioms::{self, Idiom};
+/// use ebo::reloc::Program;
+/// use ebo::{evm, Fork};
+/// use rand::{rngs::StdRng, SeedableRng};
+///
+/// let mut program = Program::lift(&[0x00]); // STOP
+/// let region = idioms::generate(Idiom::Revert, &mut program, &mut StdRng::seed_from_u64(7), Fork::Cancun);
+/// program.items.extend(region);
+/// let code = program.assemble().unwrap();
+/// assert_eq!(evm::disassemble(&code)[1].opcode, 0x5B); // JUMPDEST
+/// assert!(code.ends_with(&[0xFD])); // REVERT
+/// ```
+pub fn generate(
+    idiom: Idiom,
+    program: &mut Program,
+    rng: &mut dyn RngCore,
+    target: impl Into<Target>,
+) -> Vec<Item> {
+    let mut writer = Writer {
+        items: vec![Item::op(op::JUMPDEST)],
+        program,
+        rng,
+        push0: target.into().supports(op::PUSH0),
+    };
+    writer.body(idiom);
+    writer.items
+}
+
+/// an unreachable region of an idiom drawn at random, see `generate`.
+pub fn region(
+    program: &mut Program,
+    rng: &mut dyn RngCore,
+    target: impl Into<Target>,
+) -> Vec<Item> {
+    let idiom = *Idiom::ALL.choose(rng).expect("not empty");
+    generate(idiom, program, rng, target)
+}
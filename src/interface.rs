@@ -0,0 +1,292 @@
+/// external interface preservation for the ebo obfuscator.
+/// what integrations of a contract rely on besides its behavior as a whole: the selectors its
+/// dispatcher answers to, the topics of the events it emits (the event signatures subgraphs and
+/// indexers filter on), the shape of the data its functions return, and the storage they write.
+/// `Interface::of` records them by calling every external function of the input with zeroed
+/// arguments, the contract without calldata, and a selector the dispatcher does not know, so a
+/// dispatcher that routes unknown selectors into a function shows too. `Interface::changes` lists
+/// what another bytecode does differently on the same calls, and `check` fails if the output of
+/// `Config::obfuscate` changed any of them, whichever pass did it.
+///
+/// revert data is not compared, since `revert-strings` encodes or strips it on purpose. storage is
+/// compared at the slots `storage-remap` moved it to (see `Interface::remap`).
+use crate::dispatcher;
+use crate::equivalence::{self, Status};
+use crate::error::EboError;
+use crate::slotmap::SlotMap;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// number of zero words passed as the arguments of every call.
+const ARGUMENT_WORDS: usize = 8;
+
+/// what a caller sees of one call to the contract.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    /// selector the call was made with, or `None` for the call without calldata.
+    pub selector: Option<[u8; 4]>,
+    /// how the call ended.
+    pub status: Status,
+    /// topics of every log emitted, in order.
+    pub topics: Vec<Vec<[u8; 32]>>,
+    /// length of the data returned, or `None` if the call did not succeed.
+    pub returned: Option<usize>,
+    /// storage slots the call changed, with their final value, as 32-byte big-endian words.
+    pub storage: BTreeMap<[u8; 32], [u8; 32]>,
+}
+
+/// the external interface of a contract, as seen through a fixed set of calls.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Interface {
+    /// the calls: the one without calldata first, then one per selector, then one with a selector
+    /// none of them is.
+    pub calls: Vec<Call>,
+}
+
+/// a difference between the interfaces of two bytecodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// the call ends differently, so the function is no longer reached the way it was.
+    Status {
+        /// selector of the call.
+        selector: Option<[u8; 4]>,
+        /// how it ended before.
+        before: Status,
+        /// how it ends now.
+        after: Status,
+    },
+    /// the call emits events with other topics.
+    Topics {
+        /// selector of the call.
+        selector: Option<[u8; 4]>,
+        /// topics of the logs before.
+        before: Vec<Vec<[u8; 32]>>,
+        /// topics of the logs now.
+        after: Vec<Vec<[u8; 32]>>,
+    },
+    /// the call returns data of another length.
+    Returns {
+        /// selector of the call.
+        selector: Option<[u8; 4]>,
+        /// length of the returned data before.
+        before: usize,
+        /// length of the returned data now.
+        after: usize,
+    },
+    /// the call leaves other values in storage.
+    Storage {
+        /// selector of the call.
+        selector: Option<[u8; 4]>,
+        /// slots written before, with their values.
+        before: BTreeMap<[u8; 32], [u8; 32]>,
+        /// slots written now, with their values.
+        after: BTreeMap<[u8; 32], [u8; 32]>,
+    },
+}
+
+/// `selector` as `0x`-prefixed hex, or `fallback` for the call without calldata.
+fn name(selector: &Option<[u8; 4]>) -> String {
+    match selector {
+        Some(selector) => format!("0x{}", hex::encode(selector)),
+        None => "fallback".to_string(),
+    }
+}
+
+/// the topics of every log as `0x`-prefixed hex.
+fn topics(logs: &[Vec<[u8; 32]>]) -> String {
+    let logs: Vec<String> = logs
+        .iter()
+        .map(|topics| {
+            let topics: Vec<String> = topics
+                .iter()
+                .map(|topic| format!("0x{}", hex::encode(topic)))
+                .collect();
+            format!("[{}]", topics.join(", "))
+        })
+        .collect();
+    format!("[{}]", logs.join(", "))
+}
+
+/// every slot of `storage` with its value, as `0x`-prefixed hex.
+fn slots(storage: &BTreeMap<[u8; 32], [u8; 32]>) -> String {
+    let slots: Vec<String> = storage
+        .iter()
+        .map(|(slot, value)| format!("0x{}: 0x{}", hex::encode(slot), hex::encode(value)))
+        .collect();
+    format!("{{{}}}", slots.join(", "))
+}
+
+/// a selector that is none of `selectors`, counting down from `0xffffffff`.
+fn unknown(selectors: &[[u8; 4]]) -> [u8; 4] {
+    (0..=u32::MAX)
+        .rev()
+        .map(u32::to_be_bytes)
+        .find(|selector| !selectors.contains(selector))
+        .expect("there are fewer selectors than 4-byte values")
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Status {
+                selector,
+                before,
+                after,
+            } => write!(f, "{}: status {:?} != {:?}", name(selector), before, after),
+            Change::Topics {
+                selector,
+                before,
+                after,
+            } => write!(
+                f,
+                "{}: event topics {} != {}",
+                name(selector),
+                topics(before),
+                topics(after)
+            ),
+            Change::Returns {
+                selector,
+                before,
+                after,
+            } => write!(
+                f,
+                "{}: returns {} bytes != {} bytes",
+                name(selector),
+                before,
+                after
+            ),
+            Change::Storage {
+                selector,
+                before,
+                after,
+            } => write!(
+                f,
+                "{}: storage {} != {}",
+                name(selector),
+                slots(before),
+                slots(after)
+            ),
+        }
+    }
+}
+
+impl Interface {
+    /// calls `code` once without calldata, once with each of `selectors`, and once with a selector
+    /// that is none of them, each followed by zeroed arguments and on a fresh contract.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::interface::Interface;
+    ///
+    /// // log1(0, 0, 0xdd), return(0, 32)
+    /// let code = hex::decode("60dd5f5fa160205ff3").unwrap();
+    /// let interface = Interface::of(&code, &[]).unwrap();
+    /// assert_eq!(interface.calls[0].topics[0][0][31], 0xdd);
+    /// assert_eq!(interface.calls[0].returned, Some(32));
+    /// ```
+    pub fn of(code: &[u8], selectors: &[[u8; 4]]) -> anyhow::Result<Interface> {
+        let mut calls = Vec::with_capacity(selectors.len() + 2);
+        let probes = selectors.iter().copied().chain([unknown(selectors)]);
+        for selector in std::iter::once(None).chain(probes.map(Some)) {
+            let mut calldata = Vec::new();
+            if let Some(selector) = selector {
+                calldata.extend(selector);
+                calldata.resize(4 + ARGUMENT_WORDS * 32, 0);
+            }
+            let effects = equivalence::execute(code, &calldata)?.effects;
+            calls.push(Call {
+                selector,
+                status: effects.status,
+                topics: effects
+                    .logs
+                    .iter()
+                    .map(|log| log.topics().iter().map(|topic| topic.0).collect())
+                    .collect(),
+                returned: (effects.status == Status::Success).then_some(effects.output.len()),
+                storage: effects
+                    .storage
+                    .iter()
+                    .map(|(slot, value)| (slot.to_be_bytes(), value.to_be_bytes()))
+                    .collect(),
+            });
+        }
+        Ok(Interface { calls })
+    }
+
+    /// the interface with its storage at the slots `slots` moved it to, as the output of
+    /// `storage-remap` writes it.
+    pub fn remap(mut self, slots: &SlotMap) -> Interface {
+        for call in &mut self.calls {
+            call.storage = std::mem::take(&mut call.storage)
+                .into_iter()
+                .map(|(slot, value)| (slots.get(slot), value))
+                .collect();
+        }
+        self
+    }
+
+    /// what `other`, recorded with the same selectors, does differently; empty if integrations
+    /// cannot tell the two apart.
+    pub fn changes(&self, other: &Interface) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for (before, after) in self.calls.iter().zip(&other.calls) {
+            let selector = before.selector;
+            if before.status != after.status {
+                changes.push(Change::Status {
+                    selector,
+                    before: before.status,
+                    after: after.status,
+                });
+                continue;
+            }
+            if before.topics != after.topics {
+                changes.push(Change::Topics {
+                    selector,
+                    before: before.topics.clone(),
+                    after: after.topics.clone(),
+                });
+            }
+            if let (Some(before), Some(after)) = (before.returned, after.returned) {
+                if before != after {
+                    changes.push(Change::Returns {
+                        selector,
+                        before,
+                        after,
+                    });
+                }
+            }
+            if before.storage != after.storage {
+                changes.push(Change::Storage {
+                    selector,
+                    before: before.storage.clone(),
+                    after: after.storage.clone(),
+                });
+            }
+        }
+        changes
+    }
+}
+
+/// checks that `obfuscated`, whose storage moved as `slots` says, keeps the interface of
+/// `original`, calling it with every selector the dispatcher of `original` answers to.
+///
+/// # returns
+/// `EboError::Verification` listing every change, or an error if either code cannot be run.
+pub fn check(original: &[u8], obfuscated: &[u8], slots: &SlotMap) -> anyhow::Result<()> {
+    let selectors: Vec<[u8; 4]> = dispatcher::functions(original)
+        .iter()
+        .map(|function| function.selector)
+        .collect();
+    let changes = Interface::of(original, &selectors)?
+        .remap(slots)
+        .changes(&Interface::of(obfuscated, &selectors)?);
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let changes: Vec<String> = changes.iter().map(Change::to_string).collect();
+    Err(EboError::Verification(format!(
+        "obfuscation changed the external interface:\n{}",
+        changes.join("\n")
+    ))
+    .into())
+}
@@ -0,0 +1,83 @@
+//! ebo, an evm bytecode obfuscator.
+//! the library holds everything the `ebo` binary is made of: bytecode analysis, the relocation
+//! layer, the obfuscation passes and their pipeline, and the tooling that checks the results.
+//! the types most callers need are exported at the crate root.
+//!
+//! # example
+//! ```
+//! use ebo::{compute_cfg_complexity, parse_bytecode, Config, Obfuscator};
+//!
+//! // PUSH1 0, CALLDATALOAD, PUSH1 7, JUMPI, STOP, JUMPDEST, PUSH1 1, PUSH1 0, SSTORE, STOP
+//! let bytecode = hex::decode("600035600757005b6001600055 00".replace(' ', "")).unwrap();
+//!
+//! // with the defaults
+//! let obfuscated = Obfuscator::new(&bytecode, 42).obfuscate().unwrap();
+//! assert!(obfuscated.len() > bytecode.len());
+//!
+//! // or as an `ebo.toml` describes, with the checks the cli makes
+//! let config = Config::from_toml("rounds = 2").unwrap();
+//! let obfuscation = config.obfuscate(&bytecode, 42).unwrap();
+//! assert!(compute_cfg_complexity(&parse_bytecode(&obfuscation.bytecode)) >= 1);
+//! ```
+
+pub mod abi;
+pub mod analysis;
+pub mod artifact;
+pub mod batch;
+pub mod bindings;
+pub mod cache;
+pub mod cfg;
+pub mod chain;
+pub mod config;
+pub mod corpus;
+pub mod creation;
+pub mod decompile;
+pub mod deobfuscator;
+pub mod dispatcher;
+pub mod eof;
+pub mod equivalence;
+pub mod error;
+pub mod evm;
+pub mod explorer;
+#[cfg(feature = "ebo-ffi")]
+pub mod ffi;
+pub mod foundry;
+pub mod fuzz;
+pub mod gas;
+pub mod html;
+pub mod idioms;
+pub mod interface;
+pub mod link;
+pub mod manifest;
+pub mod merge;
+pub mod obfuscator;
+pub mod passes;
+pub mod pcmap;
+pub mod plan;
+pub mod progress;
+pub mod reloc;
+pub mod report;
+pub mod rpc;
+pub mod score;
+pub mod search;
+pub mod seed;
+pub mod slotmap;
+pub mod solc;
+pub mod srcmap;
+pub mod summary;
+pub mod symbolic;
+pub mod test_support;
+pub mod trace;
+pub mod validate;
+pub mod variants;
+
+pub use config::{Config, Obfuscation};
+pub use error::EboError;
+pub use evm::{
+    byte_entropy, compression_ratio, compute_cfg_complexity, count_unique_opcodes, disassemble,
+    halstead, ngram_similarity, opcode_entropy, parse_bytecode, BasicBlock, Fork, Halstead,
+    Instruction, Opcode,
+};
+pub use obfuscator::{Obfuscator, ObfuscatorBuilder};
+pub use passes::{Pass, Pipeline};
+pub use summary::Metrics;
@@ -0,0 +1,178 @@
+/// library link placeholders for the ebo obfuscator.
+/// solc leaves the address of every library an unlinked contract calls as a 40-character
+/// placeholder in the hex of its code (`__$<34 hex digits>$__`, or `__<path:Name padded with _>__`
+/// before solidity 0.5), which is filled in when the code is linked. a placeholder is not hex, so
+/// while the code is decoded it is replaced by a marker address derived from it. the `push20` holding
+/// a marker is kept verbatim by the obfuscator, so the marker comes out byte for byte wherever the
+/// relocation puts it, and when the code is written back each marker becomes its placeholder again,
+/// with link references giving its new offset. artifacts mark the values of immutables the same
+/// way, with a marker word (see `artifact`).
+use crate::corpus;
+use crate::error::EboError;
+use crate::evm::{disassemble, push_size};
+use anyhow::Context;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// length of a placeholder in hex digits.
+pub const PLACEHOLDER_LEN: usize = 40;
+
+/// first bytes of every marker, so markers can be told apart from other addresses.
+const TAG: [u8; 4] = [0xEB, 0x0E, 0x11, 0xB5];
+
+/// the marker standing in for `name` while code is decoded: an address for a library, a word for an
+/// immutable. tagged, random enough otherwise never to occur in real code by chance, and the same
+/// on every run.
+///
+/// # example
+/// ```
+/// use ebo::link;
+///
+/// let marker: [u8; 20] = link::marker("__$b9f4d5bc6e05b5d7b85b30b9e4d8c8d66d$__");
+/// assert!(link::is_marker(&marker));
+/// assert!(!link::is_marker(&[0u8; 20]));
+/// ```
+pub fn marker<const N: usize>(name: &str) -> [u8; N] {
+    let hash = Sha256::digest(format!("ebo marker {}", name));
+    std::array::from_fn(|i| if i < TAG.len() { TAG[i] } else { hash[i] })
+}
+
+/// returns true if `value` is a marker made by `marker`.
+pub fn is_marker(value: &[u8]) -> bool {
+    matches!(value.len(), 20 | 32) && value.starts_with(&TAG)
+}
+
+/// byte ranges of the `push20` and `push32` instructions of `code` that push a marker, which have
+/// to be kept verbatim.
+pub fn regions(code: &[u8]) -> Vec<Range<usize>> {
+    disassemble(code)
+        .into_iter()
+        .filter(|ins| matches!(push_size(ins.opcode), 20 | 32) && is_marker(&ins.immediate))
+        .map(|ins| ins.offset..ins.offset + 1 + ins.immediate.len())
+        .collect()
+}
+
+/// a library placeholder found in the hex of some code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placeholder {
+    /// the 40 characters standing in for the library address.
+    pub text: String,
+    /// address standing in for it while the code is decoded.
+    pub marker: [u8; 20],
+}
+
+/// position of a library address in the code, as solc lists link references.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Offset {
+    /// byte offset of the address.
+    pub start: usize,
+    /// length of the address, always 20.
+    pub length: usize,
+}
+
+/// the distinct placeholders in `digits`, the hex of some code, in order of first appearance.
+///
+/// # example
+/// ```
+/// use ebo::link;
+///
+/// let digits = format!("73{}3b", format!("__${}$__", "ab".repeat(17)));
+/// let placeholders = link::placeholders(&digits);
+/// assert_eq!(placeholders.len(), 1);
+/// assert_eq!(placeholders[0].text.len(), link::PLACEHOLDER_LEN);
+/// ```
+pub fn placeholders(digits: &str) -> Vec<Placeholder> {
+    let mut found: Vec<Placeholder> = Vec::new();
+    let mut at = 0;
+    while let Some(start) = digits[at..].find("__").map(|start| at + start) {
+        let Some(text) = digits.get(start..start + PLACEHOLDER_LEN) else {
+            break;
+        };
+        if start % 2 == 1 || !text.ends_with("__") {
+            at = start + 1;
+            continue;
+        }
+        if found.iter().all(|placeholder| placeholder.text != text) {
+            found.push(Placeholder {
+                text: text.to_string(),
+                marker: marker(text),
+            });
+        }
+        at = start + PLACEHOLDER_LEN;
+    }
+    found
+}
+
+/// `digits` with the marker of each of `placeholders` in place of the placeholder.
+pub fn unlink(digits: &str, placeholders: &[Placeholder]) -> String {
+    placeholders
+        .iter()
+        .fold(digits.to_string(), |digits, placeholder| {
+            digits.replace(&placeholder.text, &hex::encode(placeholder.marker))
+        })
+}
+
+/// the hex of `code` with every marker of `placeholders` turned back into its placeholder, and
+/// where each placeholder now is, keyed by placeholder.
+///
+/// # returns
+/// the hex digits and link references, or an error if the code lost the address of one of the
+/// libraries.
+pub fn relink(
+    code: &[u8],
+    placeholders: &[Placeholder],
+) -> Result<(String, BTreeMap<String, Vec<Offset>>), EboError> {
+    let mut digits = hex::encode(code);
+    let mut references = BTreeMap::new();
+    for placeholder in placeholders {
+        let starts: Vec<usize> = code
+            .windows(20)
+            .enumerate()
+            .filter(|(_, window)| *window == placeholder.marker)
+            .map(|(start, _)| start)
+            .collect();
+        if starts.is_empty() {
+            return Err(EboError::Relocation(format!(
+                "library address {}: the code no longer holds it",
+                placeholder.text
+            )));
+        }
+        for &start in &starts {
+            digits.replace_range(2 * start..2 * start + PLACEHOLDER_LEN, &placeholder.text);
+        }
+        references.insert(
+            placeholder.text.clone(),
+            starts
+                .into_iter()
+                .map(|start| Offset { start, length: 20 })
+                .collect(),
+        );
+    }
+    Ok((digits, references))
+}
+
+/// the file holding the link references of code written to `output`, e.g. `Token.links.json` for
+/// `Token.bin`.
+pub fn references_path(output: &Path) -> PathBuf {
+    output.with_extension("links.json")
+}
+
+/// writes `references`, as `relink` returns them, to the link references file of `output`, which
+/// is only replaced with `force`.
+///
+/// # returns
+/// the path written.
+pub fn write_references(
+    output: &Path,
+    references: &BTreeMap<String, Vec<Offset>>,
+    force: bool,
+) -> anyhow::Result<PathBuf> {
+    let path = references_path(output);
+    let json =
+        serde_json::to_string_pretty(references).context("cannot serialize link references")?;
+    corpus::write(&path, json, force)?;
+    Ok(path)
+}
@@ -1,33 +1,570 @@
-mod evm;
-mod obfuscator;
-
-use crate::obfuscator::Obfuscator;
-use clap::{Parser, Subcommand, ValueEnum};
-use log::{debug, info};
+use anyhow::Context;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use ebo::artifact::Artifact;
+use ebo::batch;
+use ebo::cache::BlockCache;
+use ebo::chain::{self, Chain, Target};
+use ebo::config::{self, Config, Obfuscation};
+use ebo::corpus::HexWriter;
+use ebo::creation::Creation;
+use ebo::decompile::{self, Decompiler};
+use ebo::error::EboError;
+use ebo::evm::{self, Fork};
+use ebo::explorer::{self, Explorer};
+use ebo::manifest::Manifest;
+use ebo::merge::{self, Switch};
+use ebo::passes::revert_strings::ReasonMode;
+use ebo::progress::Progress;
+use ebo::search::Objective;
+use ebo::seed::{self, variant_seed, Key, Seed};
+use ebo::srcmap::SourceMap;
+use ebo::variants::Variants;
+use ebo::{
+    abi, analysis, cfg, corpus, creation, equivalence, foundry, fuzz, html, link, report, rpc,
+    score, solc, summary, trace, validate,
+};
+use revm::primitives::keccak256;
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Write};
 use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use tracing::level_filters::LevelFilter;
+use tracing::{debug, info, warn};
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
 
 #[derive(Parser)]
-#[command(name = "ebo", about = "EVM Bytecode Obfuscator with Chaotic Shuffle")]
+#[command(
+    name = "ebo",
+    version,
+    about = "EVM Bytecode Obfuscator with Chaotic Shuffle"
+)]
 struct Cli {
+    /// Print the results of the command as one JSON document on stdout, with the kind of error
+    /// and the exit code if it fails
+    #[arg(long, global = true)]
+    json: bool,
     #[command(subcommand)]
     command: Commands,
 }
 
+// parsed once per run, so the size of the largest variant does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Obfuscate EVM bytecode
     Obfuscate {
-        /// Input bytecode file path
-        #[arg(long, required = true)]
-        file: PathBuf,
-        /// Random seed for obfuscation
-        #[arg(long, default_value = "42")]
-        seed: u64,
+        /// Input bytecode file path, or - for standard input [default: standard input]
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Input bytecode file path, as an alternative to --file
+        #[arg(conflicts_with = "file")]
+        input: Option<PathBuf>,
+        /// Obfuscate the code deployed at this address, fetched from --rpc-url or --etherscan
+        #[arg(long, conflicts_with_all = ["file", "input"])]
+        address: Option<String>,
+        /// JSON-RPC endpoint to fetch --address from [default: $ETH_RPC_URL]
+        #[arg(long, requires = "address", conflicts_with = "etherscan")]
+        rpc_url: Option<String>,
+        /// Fetch --address and its verified ABI and compiler settings from a block explorer API
+        /// (key in $ETHERSCAN_API_KEY)
+        #[arg(long, requires = "address")]
+        etherscan: bool,
+        /// Chain the output is deployed to, by name or id: keeps the output to the opcodes of its
+        /// profile (arbitrum, optimism, zksync-era, polygon-zkevm), gives the id environment
+        /// predicates compare CHAINID with, and is where --etherscan fetches --address from
+        /// [default: mainnet]
+        #[arg(long)]
+        chain: Option<String>,
+        /// Etherscan-compatible API to use instead of Etherscan's (e.g. https://eth.blockscout.com/api)
+        #[arg(long, requires = "etherscan")]
+        explorer_url: Option<String>,
+        /// Block to fetch --address at, as a number or tag
+        #[arg(long, default_value = "latest", requires = "address")]
+        block: String,
+        /// Obfuscate every file under this directory matching --glob, writing them to --out-dir
+        #[arg(long, requires = "out_dir", conflicts_with_all = ["file", "input", "address", "output", "report"])]
+        dir: Option<PathBuf>,
+        /// Files under --dir to obfuscate
+        #[arg(long, default_value = "**/*.bin", requires = "dir")]
+        glob: String,
+        /// Directory the files of --dir are written to, at the same relative paths
+        #[arg(long, requires = "dir")]
+        out_dir: Option<PathBuf>,
+        /// Encoding of the input file
+        #[arg(long, value_enum, default_value_t = corpus::Format::Auto)]
+        input_format: corpus::Format,
+        /// Read the input as the data of a creation transaction (init code followed by ABI-encoded
+        /// constructor arguments): obfuscate the runtime code it deploys and write the data back
+        /// with the arguments untouched
+        #[arg(long, conflicts_with_all = ["address", "dir"])]
+        creation: bool,
+        /// Configuration file (defaults to ./ebo.toml if present)
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Random seed for obfuscation, or `random` to draw one from the operating system, which is
+        /// logged and put in the default output name [default: 42]
+        #[arg(long, value_parser = seed::parse)]
+        seed: Option<Seed>,
+        /// File holding a secret that keys the randomness (ChaCha20), so the applied transformations
+        /// cannot be recovered by searching seeds: at least 32 random bytes, e.g. from
+        /// `openssl rand -hex 32` [default: $EBO_KEY, if set]
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+        /// Block cache to reuse: blocks transformed by an earlier run with the same seed and
+        /// configuration are taken from it, so a re-run on slightly changed code only transforms
+        /// the changed blocks; created if missing and rewritten with the blocks of this run
+        #[arg(long, value_name = "FILE")]
+        cache: Option<PathBuf>,
+        /// Number of times the pipeline is applied, each round on the output of the last [default: 1]
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        rounds: Option<u64>,
+        /// Fork the output must be valid for; passes only emit its opcodes [default: cancun]
+        #[arg(long, visible_alias = "evm-version", value_enum)]
+        fork: Option<Fork>,
+        /// Try this many seeds, starting at --seed, and keep the best scoring output
+        #[arg(long)]
+        search: Option<usize>,
+        /// Metric the seed search maximizes [default: halstead]
+        #[arg(long, value_enum)]
+        objective: Option<Objective>,
+        /// Stop the seed search after this many candidates without improvement
+        #[arg(long)]
+        patience: Option<usize>,
         /// Verbosity level
         #[arg(long, value_enum, default_value_t = Verbosity::Normal)]
         verbosity: Verbosity,
+        /// Show no progress bars, and log errors only
+        #[arg(long, conflicts_with = "verbosity")]
+        quiet: bool,
+        /// Obfuscation passes to skip (e.g. --disable spaghetti,outline)
+        #[arg(long, value_delimiter = ',')]
+        disable: Vec<String>,
+        /// Whether revert reasons and custom error selectors are encoded or stripped [default: encode]
+        #[arg(long, value_enum)]
+        revert_strings: Option<ReasonMode>,
+        /// Move the constant storage slots of the contract to random ones and write where each went
+        /// next to the output (obfuscated.slots.json for obfuscated.bin); breaks proxies, upgrades,
+        /// and anything else sharing the storage layout
+        #[arg(long)]
+        storage_remap: bool,
+        /// Chance that a basic block is shuffled [default: 0.3]
+        #[arg(long)]
+        shuffle_prob: Option<f64>,
+        /// Chance that an ADD is substituted [default: 0.5]
+        #[arg(long)]
+        substitution_prob: Option<f64>,
+        /// Chance that the stack layout of a basic block is shuffled [default: 0.3]
+        #[arg(long)]
+        stack_shuffle_prob: Option<f64>,
+        /// Chance that a JUMPI is followed by a false branch [default: 0.4]
+        #[arg(long)]
+        false_branch_prob: Option<f64>,
+        /// Chance that a group of scratch space temporaries is moved [default: 0.5]
+        #[arg(long)]
+        scratch_layout_prob: Option<f64>,
+        /// Chance that an internal call is inlined [default: 0.5]
+        #[arg(long)]
+        inline_prob: Option<f64>,
+        /// Chance that a loop is reshaped [default: 0.5]
+        #[arg(long)]
+        loops_prob: Option<f64>,
+        /// Chance that a basic block is guarded by a gas predicate [default: 0.2]
+        #[arg(long)]
+        gas_predicate_prob: Option<f64>,
+        /// Chance that a basic block is guarded by an environment predicate [default: 0.15]
+        #[arg(long)]
+        env_predicate_prob: Option<f64>,
+        /// Chance that a jump is routed past a trap, or a block through a failing call [default: 0.2]
+        #[arg(long)]
+        exception_flow_prob: Option<f64>,
+        /// Chance that a RETURN or REVERT is routed through a shared epilogue [default: 0.5]
+        #[arg(long)]
+        return_path_prob: Option<f64>,
+        /// Chance that a STOP or RETURN is followed by flower instructions [default: 0.3]
+        #[arg(long)]
+        flower_prob: Option<f64>,
+        /// Chance that a terminator is followed by, or a function block guarded by, a read of a
+        /// never-written storage slot [default: 0.1]
+        #[arg(long)]
+        decoy_reads_prob: Option<f64>,
+        /// Chance that a stack-neutral identity is inserted at a position [default: 0.15]
+        #[arg(long)]
+        peephole_prob: Option<f64>,
+        /// Chance that a direct jump is routed through a chain of trampolines [default: 0.2]
+        #[arg(long)]
+        trampolines_prob: Option<f64>,
+        /// Chance that a PUSH is re-encoded with a wider, zero-padded immediate [default: 0.25]
+        #[arg(long)]
+        push_width_prob: Option<f64>,
+        /// Chance that the code is cut into a new fragment at an instruction [default: 0.25]
+        #[arg(long)]
+        spaghetti_prob: Option<f64>,
+        /// Multiplier applied to every probability, capped at 1 [default: 1.0]
+        #[arg(long)]
+        intensity: Option<f64>,
+        /// Obfuscate only the external functions with these selectors (e.g. 0xa9059cbb,0x095ea7b3),
+        /// or names (e.g. transfer,approve) with --etherscan
+        #[arg(long, value_delimiter = ',', conflicts_with = "skip_selectors")]
+        only_selectors: Vec<String>,
+        /// Leave the external functions with these selectors untouched
+        #[arg(long, value_delimiter = ',')]
+        skip_selectors: Vec<String>,
+        /// Byte range of the input to pass through verbatim (e.g. --exclude 0x120..0x1a0), repeatable
+        #[arg(long)]
+        exclude: Vec<String>,
+        /// Largest gas overhead obfuscation may add to any path (e.g. 15%)
+        #[arg(long, value_parser = config::parse_percent)]
+        max_gas_overhead: Option<f64>,
+        /// Largest size of the obfuscated code in bytes [default: 24576, the EIP-170 limit]
+        #[arg(long)]
+        max_size: Option<usize>,
+        /// Output file, or - to print to stdout [default: obfuscated.bin, or obfuscated.json for an artifact]
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Write the output as a hex string
+        #[arg(long)]
+        hex: bool,
+        /// Overwrite the output file if it already exists
+        #[arg(long)]
+        force: bool,
+        /// Write a manifest next to the output (obfuscated.manifest.json for obfuscated.bin) with
+        /// the version, input and output hashes, seed, and resolved configuration, from which
+        /// `ebo reproduce` rebuilds the output
+        #[arg(long, conflicts_with = "dir")]
+        manifest: bool,
+        /// Write a map between the program counters of the input and of the output next to the
+        /// output (obfuscated.pcmap.json for obfuscated.bin), to translate revert locations and
+        /// traces of the deployed code back to the original
+        #[arg(long, conflicts_with = "dir")]
+        pc_map: bool,
+        /// Solc source map of the input's runtime code (its srcmap-runtime) to translate to the
+        /// output, written next to it (obfuscated.srcmap for obfuscated.bin); the source map of an
+        /// artifact is translated in place without it
+        #[arg(long, value_name = "FILE", conflicts_with = "dir")]
+        source_map: Option<PathBuf>,
+        /// Write N variants, each obfuscated with its own seed derived from the base seed, as
+        /// obfuscated-1.bin to obfuscated-N.bin, with an index of their seeds and hashes
+        /// (obfuscated.variants.json); reports, manifests, and maps are written per variant
+        #[arg(long, value_name = "N", conflicts_with = "dir")]
+        variants: Option<usize>,
+        /// Print which passes would change each basic block, with the size and gas each gains,
+        /// without writing any output
+        #[arg(long, conflicts_with_all = ["dir", "output", "force", "report", "report_html", "manifest", "pc_map", "source_map", "variants"])]
+        dry_run: bool,
+        /// Write a JSON summary of the run (sizes, metrics, per-pass counts, seed) to this file
+        #[arg(long)]
+        report: Option<PathBuf>,
+        /// Write a self-contained HTML report (metrics, passes, CFGs, disassembly) to this file
+        #[arg(long)]
+        report_html: Option<PathBuf>,
+    },
+    /// Compile a solc standard-JSON input and obfuscate the runtime code of every contract
+    Compile {
+        /// Standard-JSON input file, as `solc --standard-json` reads it
+        #[arg(long)]
+        standard_json: PathBuf,
+        /// Path of the solc binary [default: solc on the PATH]
+        #[arg(long, conflicts_with = "solc_version")]
+        solc: Option<PathBuf>,
+        /// Version of solc to run, as installed by svm (e.g. 0.8.26)
+        #[arg(long)]
+        solc_version: Option<String>,
+        /// Configuration file (defaults to ./ebo.toml if present)
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Random seed for obfuscation, or `random` to draw one from the operating system
+        /// [default: the configuration's, or 42]
+        #[arg(long, value_parser = seed::parse)]
+        seed: Option<Seed>,
+        /// File holding a secret that keys the randomness [default: $EBO_KEY, if set]
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+        /// Fork the output must be valid for [default: the input's settings.evmVersion, or cancun]
+        #[arg(long, visible_alias = "evm-version", value_enum)]
+        fork: Option<Fork>,
+        /// Chain the output is deployed to, whose opcode profile it keeps to (e.g. arbitrum)
+        #[arg(long, value_parser = chain::parse)]
+        chain: Option<Chain>,
+        /// Where to write the standard-JSON output [default: stdout]
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Overwrite the output file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check that obfuscated bytecode behaves like the original on a corpus of calls
+    Verify {
+        /// Original bytecode file (raw or hex)
+        #[arg(long)]
+        original: PathBuf,
+        /// Obfuscated bytecode file (raw or hex)
+        #[arg(long)]
+        obfuscated: PathBuf,
+        /// Calldata samples: a directory with one sample per file, or a JSON array of hex strings
+        #[arg(long)]
+        corpus: PathBuf,
+    },
+    /// Rebuild obfuscated code from a manifest and check that it comes out byte for byte the same
+    Reproduce {
+        /// Manifest written by `ebo obfuscate --manifest`
+        #[arg(long)]
+        manifest: PathBuf,
+        /// Runtime code the manifest was made from (raw, hex, or compiler artifact)
+        #[arg(long)]
+        file: PathBuf,
+        /// Encoding of the input file
+        #[arg(long, value_enum, default_value_t = corpus::Format::Auto)]
+        input_format: corpus::Format,
+        /// File holding the secret a keyed build was made with [default: $EBO_KEY, if set]
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
+    /// Run a Foundry project's tests on the obfuscated build of its contracts
+    Test {
+        /// Root of the Foundry project
+        #[arg(long, default_value = ".")]
+        project: PathBuf,
+        /// Directory of the contracts to obfuscate, relative to the project root
+        #[arg(long, default_value = "src")]
+        src: String,
+        /// Where to write the generated fixture, relative to the project root
+        #[arg(long, default_value = "test/EboFixtures.sol")]
+        fixture: PathBuf,
+        /// Path to a configuration file [default: ebo.toml in the project root, if present]
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Seed for obfuscation, or `random` to draw one from the operating system
+        /// [default: the configuration's, or 42]
+        #[arg(long, value_parser = seed::parse)]
+        seed: Option<Seed>,
+        /// File holding a secret that keys the randomness [default: $EBO_KEY, if set]
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+        /// Overwrite the fixture if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Compare the gas each external function uses before and after obfuscation
+    GasReport {
+        /// Original bytecode file (raw or hex)
+        #[arg(long)]
+        original: PathBuf,
+        /// Obfuscated bytecode file (raw or hex)
+        #[arg(long)]
+        obfuscated: PathBuf,
+        /// Contract ABI (or compiler artifact containing one) listing the functions to call
+        #[arg(long)]
+        abi: PathBuf,
+        /// Calldata samples to use instead of generated calls, in the format `verify` takes
+        #[arg(long)]
+        corpus: Option<PathBuf>,
+        /// Number of generated calls for each function without samples
+        #[arg(long, default_value_t = 20)]
+        runs: usize,
+        /// Seed of the argument generator
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+    },
+    /// Execute one call on both binaries and print their step traces side by side
+    Trace {
+        /// Original bytecode file (raw or hex)
+        #[arg(long)]
+        original: PathBuf,
+        /// Obfuscated bytecode file (raw or hex)
+        #[arg(long)]
+        obfuscated: PathBuf,
+        /// Calldata of the call, as hex
+        #[arg(long, default_value = "")]
+        calldata: String,
+        /// Only print this many rows before and after the first divergence
+        #[arg(long)]
+        context: Option<usize>,
+    },
+    /// Check bytecode for undefined opcodes, truncated pushes, bad jumps, and stack underflows
+    Lint {
+        /// Bytecode file (raw or hex)
+        #[arg(long)]
+        file: PathBuf,
+        /// Encoding of the bytecode file
+        #[arg(long, value_enum, default_value_t = corpus::Format::Auto)]
+        input_format: corpus::Format,
+        /// Fork whose opcodes are allowed
+        #[arg(long, visible_alias = "evm-version", value_enum, default_value_t = Fork::default())]
+        fork: Fork,
+        /// Chain whose opcode profile the code must keep to (e.g. zksync-era); opcodes that behave
+        /// differently there are listed too
+        #[arg(long, value_parser = chain::parse)]
+        chain: Option<Chain>,
+    },
+    /// Print the metrics, functions, gas, and metadata of bytecode without obfuscating it
+    Analyze {
+        /// Bytecode file (raw, hex, or compiler artifact); `-` reads standard input
+        #[arg(long)]
+        file: PathBuf,
+        /// Encoding of the bytecode file
+        #[arg(long, value_enum, default_value_t = corpus::Format::Auto)]
+        input_format: corpus::Format,
+    },
+    /// Print how often each opcode and push width occurs in bytecode
+    Stats {
+        /// Bytecode file (raw, hex, or compiler artifact); `-` reads standard input
+        #[arg(long)]
+        file: PathBuf,
+        /// Encoding of the bytecode file
+        #[arg(long, value_enum, default_value_t = corpus::Format::Auto)]
+        input_format: corpus::Format,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = StatsFormat::Text)]
+        format: StatsFormat,
+    },
+    /// Print the metrics of two bytecode files side by side, with how similar they are
+    Compare {
+        /// First bytecode file (raw, hex, or compiler artifact)
+        #[arg(long)]
+        left: PathBuf,
+        /// Second bytecode file (raw, hex, or compiler artifact)
+        #[arg(long)]
+        right: PathBuf,
+    },
+    /// Put a constructor in front of runtime bytecode, so it can be deployed
+    Wrap {
+        /// Runtime bytecode file (raw, hex, or compiler artifact); `-` reads standard input
+        #[arg(long)]
+        file: PathBuf,
+        /// Encoding of the bytecode file
+        #[arg(long, value_enum, default_value_t = corpus::Format::Auto)]
+        input_format: corpus::Format,
+        /// Fork the init code must be valid for; PUSH0 is only used from Shanghai on
+        #[arg(long, visible_alias = "evm-version", value_enum, default_value_t = Fork::default())]
+        fork: Fork,
+        /// Chain the init code is deployed to, whose opcode profile it keeps to (e.g. polygon-zkevm)
+        #[arg(long, value_parser = chain::parse)]
+        chain: Option<Chain>,
+        /// Output file [default: print hex to stdout]
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Write the output file as a hex string
+        #[arg(long)]
+        hex: bool,
+        /// Overwrite the output file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Merge two contracts into one whose personality is chosen by the chain id or address it runs at,
+    /// and obfuscate the result
+    Merge {
+        /// Bytecode that runs where the switch matches (raw, hex, or compiler artifact)
+        #[arg(long)]
+        first: PathBuf,
+        /// Bytecode that runs everywhere else (raw, hex, or compiler artifact)
+        #[arg(long)]
+        second: PathBuf,
+        /// What picks the first contract: `chainid:<id>` or `address:<hex>`
+        #[arg(long)]
+        switch: Switch,
+        /// Path to a configuration file [default: ebo.toml in the current directory, if present]
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Seed for obfuscation, or `random` to draw one from the operating system
+        /// [default: the configuration's, or 42]
+        #[arg(long, value_parser = seed::parse)]
+        seed: Option<Seed>,
+        /// File holding a secret that keys the randomness [default: $EBO_KEY, if set]
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+        /// Output file [default: print hex to stdout]
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Write the output file as a hex string
+        #[arg(long)]
+        hex: bool,
+        /// Overwrite the output file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Run decompilers on original and obfuscated bytecode and measure how much they still recover
+    Evaluate {
+        /// Original bytecode file (raw, hex, or compiler artifact)
+        #[arg(long)]
+        original: PathBuf,
+        /// Obfuscated bytecode file (raw, hex, or compiler artifact)
+        #[arg(long)]
+        obfuscated: PathBuf,
+        /// Decompiler to run: heimdall, panoramix, or a command in which {hex}, {file}, and {dir}
+        /// stand for the code, a file holding it, and a directory for output, repeatable
+        /// [default: every known decompiler that is installed]
+        #[arg(long)]
+        decompiler: Vec<String>,
+        /// Seconds a decompiler may run on one bytecode
+        #[arg(long, default_value_t = decompile::TIMEOUT.as_secs())]
+        timeout: u64,
+    },
+    /// Print the address a CREATE2 factory deploys init code to, and the hashes of its code
+    Address {
+        /// Init code file (raw, hex, or compiler artifact, whose creation code is used); `-` reads
+        /// standard input
+        #[arg(long)]
+        file: PathBuf,
+        /// Encoding of the init code file
+        #[arg(long, value_enum, default_value_t = corpus::Format::Auto)]
+        input_format: corpus::Format,
+        /// Read the file as runtime code, deployed with the init code `ebo wrap` makes for it
+        #[arg(long)]
+        runtime: bool,
+        /// Fork the init code of --runtime is made for
+        #[arg(long, visible_alias = "evm-version", value_enum, default_value_t = Fork::default(), requires = "runtime")]
+        fork: Fork,
+        /// Factory deploying the code [default: 0x4e59b44847b379578588920ca78fbf26c0b4956c, the
+        /// deterministic deployment proxy]
+        #[arg(long)]
+        deployer: Option<String>,
+        /// Salt, as hex; shorter salts are padded on the left to 32 bytes
+        #[arg(long)]
+        salt: String,
+    },
+    /// Run original and obfuscated bytecode on generated calldata until they behave differently
+    Fuzz {
+        /// Original bytecode file (raw or hex)
+        #[arg(long)]
+        original: PathBuf,
+        /// Obfuscated bytecode file (raw or hex)
+        #[arg(long)]
+        obfuscated: PathBuf,
+        /// Contract ABI (or compiler artifact containing one) used to generate well-formed calls
+        #[arg(long)]
+        abi: Option<PathBuf>,
+        /// Number of inputs to try
+        #[arg(long, default_value_t = 10000)]
+        runs: usize,
+        /// Seed of the input generator
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+    },
+    /// Print a completion script for a shell, e.g. `ebo completions bash > /etc/bash_completion.d/ebo`
+    Completions {
+        /// Shell to complete commands and flags in
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print the manual page, or write a page for every command to a directory
+    Manpage {
+        /// Directory to write ebo.1 and a page for every command (ebo-obfuscate.1, ...) to
+        #[arg(long)]
+        dir: Option<PathBuf>,
     },
 }
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+enum StatsFormat {
+    Text,
+    Json,
+    Csv,
+}
+
 #[derive(ValueEnum, Clone, PartialEq)]
 enum Verbosity {
     Quiet,
@@ -35,49 +572,1524 @@ enum Verbosity {
     Verbose,
 }
 
-fn main() -> anyhow::Result<()> {
-    env_logger::init();
-    let cli = Cli::parse();
+impl Verbosity {
+    /// the most detailed level logged.
+    fn level(&self) -> LevelFilter {
+        match self {
+            Verbosity::Quiet => LevelFilter::ERROR,
+            Verbosity::Normal => LevelFilter::INFO,
+            Verbosity::Verbose => LevelFilter::DEBUG,
+        }
+    }
+}
+
+/// logs to standard error at the level `--verbosity` asks for, or warnings and errors for commands
+/// without the flag. `RUST_LOG` overrides either, e.g. `RUST_LOG=ebo::passes=trace`. obfuscation
+/// also draws progress bars, unless `--quiet` is given; the returned handle clears them.
+fn init_logging(cli: &Cli) -> Progress {
+    let (level, visible) = match &cli.command {
+        Commands::Obfuscate { quiet: true, .. } => (LevelFilter::ERROR, false),
+        Commands::Obfuscate { verbosity, .. } => (verbosity.level(), true),
+        _ => (LevelFilter::WARN, false),
+    };
+    let filter = EnvFilter::builder()
+        .with_default_directive(level.into())
+        .from_env_lossy();
+    let progress = Progress::new(visible);
+    let logs = tracing_subscriber::fmt::layer()
+        .with_writer(progress.clone())
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_filter(filter);
+    // the bars follow the spans of the library whatever level is logged
+    let bars = progress.clone().with_filter(filter_fn(|metadata| {
+        metadata.is_span() && metadata.target().starts_with("ebo")
+    }));
+    tracing_subscriber::registry().with(logs).with(bars).init();
+    progress
+}
+
+fn main() -> ExitCode {
+    // usage errors are reported as json too when it was asked for, though parsing failed
+    let json = std::env::args().any(|arg| arg == "--json");
+    let (cli, command) = match Cli::command().try_get_matches() {
+        Ok(matches) => (
+            Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit()),
+            matches.subcommand_name().unwrap_or_default().to_string(),
+        ),
+        Err(err) if json && err.exit_code() == 2 => {
+            let output = Output::new(true, "");
+            let message = err.to_string();
+            let message = message.lines().next().unwrap_or_default();
+            output.fail(
+                "usage",
+                2,
+                message.trim_start_matches("error: ").to_string(),
+            );
+            return ExitCode::from(2);
+        }
+        Err(err) => err.exit(),
+    };
+    let progress = init_logging(&cli);
+    let mut output = Output::new(cli.json, &command);
+    let result = run(cli, &mut output);
+    progress.finish();
+    match result {
+        Ok(()) => {
+            output.finish();
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            let (kind, code) = failure(&err);
+            output.fail(kind, code, format!("{:#}", err));
+            ExitCode::from(code)
+        }
+    }
+}
+
+/// the kind of `err` and the exit code it ends the process with, 1 unless it is an `EboError`.
+fn failure(err: &anyhow::Error) -> (&'static str, u8) {
+    // the cause chain is searched, so context added on the way up does not hide the kind
+    let ebo = err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<EboError>());
+    (
+        ebo.map_or("error", EboError::kind),
+        ebo.map_or(1, EboError::exit_code),
+    )
+}
+
+/// what a command reports: lines of text on standard output, or with `--json` a single json
+/// document printed once the command is done, with the name of the command, whether it succeeded,
+/// the results it recorded (also on failure, e.g. the samples that diverge), and the error.
+struct Output {
+    json: bool,
+    document: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Output {
+    fn new(json: bool, command: &str) -> Self {
+        let mut document = serde_json::Map::new();
+        if !command.is_empty() {
+            document.insert("command".into(), command.into());
+        }
+        Output { json, document }
+    }
+
+    /// prints `line`, unless the output is json.
+    fn line(&self, line: impl std::fmt::Display) {
+        if !self.json {
+            println!("{}", line);
+        }
+    }
+
+    /// prints `text` as it is, unless the output is json.
+    fn text(&self, text: impl std::fmt::Display) {
+        if !self.json {
+            print!("{}", text);
+        }
+    }
+
+    /// records `value` as the result `key`, if the output is json.
+    fn set(&mut self, key: &str, value: impl serde::Serialize) {
+        if self.json {
+            let value = serde_json::to_value(value).expect("results always serialize");
+            self.document.insert(key.into(), value);
+        }
+    }
+
+    /// records every field of `value`, which serializes to an object, as a result.
+    fn merge(&mut self, value: impl serde::Serialize) {
+        if let (true, Ok(serde_json::Value::Object(fields))) =
+            (self.json, serde_json::to_value(value))
+        {
+            self.document.extend(fields);
+        }
+    }
+
+    /// prints the document of a command that succeeded.
+    fn finish(mut self) {
+        self.print(serde_json::Value::Null);
+    }
+
+    /// prints the document of a command that failed with an error of `kind`.
+    fn fail(mut self, kind: &str, exit_code: u8, message: String) {
+        let error = serde_json::json!({"kind": kind, "exit_code": exit_code, "message": message});
+        self.print(error);
+    }
+
+    fn print(&mut self, error: serde_json::Value) {
+        if !self.json {
+            return;
+        }
+        self.document.insert("ok".into(), error.is_null().into());
+        if !error.is_null() {
+            self.document.insert("error".into(), error);
+        }
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&self.document).expect("results always serialize")
+        );
+    }
+}
+
+/// the value of a seed given on the command line. a seed drawn at random is logged, since the run
+/// cannot be reproduced without it.
+fn seed_value(seed: Option<Seed>) -> Option<u64> {
+    if let Some(seed) = seed.filter(|seed| seed.random) {
+        info!("Drew seed {} from the operating system", seed.value);
+    }
+    seed.map(|seed| seed.value)
+}
+
+/// writes the block cache given with --cache back to its file, and reports how much of it was used.
+fn save_cache(cache: Option<&(PathBuf, Arc<BlockCache>)>, out: &mut Output) -> anyhow::Result<()> {
+    let Some((path, blocks)) = cache else {
+        return Ok(());
+    };
+    blocks.save(path)?;
+    let (hits, misses) = blocks.stats();
+    info!(
+        "Block cache saved to {:?}: {} blocks reused, {} transformed",
+        path, hits, misses
+    );
+    out.set(
+        "cache",
+        serde_json::json!({"path": path, "hits": hits, "misses": misses}),
+    );
+    Ok(())
+}
+
+/// the outcome of obfuscating one of several codes, named `name` under `key`, as json.
+fn outcome_json(
+    key: &str,
+    name: impl ToString,
+    result: &anyhow::Result<(usize, usize)>,
+) -> serde_json::Value {
+    let mut outcome = serde_json::json!({key: name.to_string(), "ok": result.is_ok()});
+    match result {
+        Ok(size) => outcome["size"] = serde_json::json!(size),
+        Err(err) => outcome["error"] = format!("{:#}", err).into(),
+    }
+    outcome
+}
+
+/// what a caller observes of a call, as json.
+fn effects_json(effects: &equivalence::Effects) -> serde_json::Value {
+    let storage: serde_json::Map<String, serde_json::Value> = effects
+        .storage
+        .iter()
+        .map(|(slot, value)| (format!("{:#x}", slot), format!("{:#x}", value).into()))
+        .collect();
+    let logs: Vec<serde_json::Value> = effects
+        .logs
+        .iter()
+        .map(|log| {
+            serde_json::json!({
+                "address": log.address.to_checksum(None),
+                "topics": log.topics().iter().map(|topic| topic.to_string()).collect::<Vec<_>>(),
+                "data": format!("0x{}", hex::encode(&log.data.data)),
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "status": format!("{:?}", effects.status).to_lowercase(),
+        "output": format!("0x{}", hex::encode(&effects.output)),
+        "storage": storage,
+        "logs": logs,
+    })
+}
+
+/// a call on which two codes behave differently, as json.
+fn divergence_json(divergence: &equivalence::Divergence) -> serde_json::Value {
+    serde_json::json!({
+        "calldata": format!("0x{}", hex::encode(&divergence.calldata)),
+        "original": effects_json(&divergence.original),
+        "obfuscated": effects_json(&divergence.obfuscated),
+    })
+}
 
+fn run(cli: Cli, out: &mut Output) -> anyhow::Result<()> {
     match cli.command {
         Commands::Obfuscate {
             file,
+            input,
+            address,
+            rpc_url,
+            etherscan,
+            chain,
+            explorer_url,
+            block,
+            dir,
+            glob,
+            out_dir,
+            input_format,
+            creation,
+            config,
             seed,
+            key_file,
+            cache,
+            rounds,
+            fork,
+            search,
+            objective,
+            patience,
             verbosity,
+            quiet: _,
+            disable,
+            shuffle_prob,
+            substitution_prob,
+            stack_shuffle_prob,
+            false_branch_prob,
+            scratch_layout_prob,
+            inline_prob,
+            loops_prob,
+            gas_predicate_prob,
+            env_predicate_prob,
+            exception_flow_prob,
+            return_path_prob,
+            revert_strings,
+            storage_remap,
+            flower_prob,
+            decoy_reads_prob,
+            peephole_prob,
+            trampolines_prob,
+            push_width_prob,
+            spaghetti_prob,
+            intensity,
+            only_selectors,
+            skip_selectors,
+            exclude,
+            max_gas_overhead,
+            max_size,
+            output,
+            hex,
+            force,
+            manifest,
+            pc_map,
+            source_map,
+            variants,
+            dry_run,
+            report,
+            report_html,
         } => {
-            match verbosity {
-                Verbosity::Quiet => std::env::set_var("RUST_LOG", "error"),
-                Verbosity::Normal => std::env::set_var("RUST_LOG", "info"),
-                Verbosity::Verbose => std::env::set_var("RUST_LOG", "debug"),
+            info!("Starting EVM Bytecode Obfuscator");
+
+            // command-line flags take precedence over the configuration file
+            let mut config = Config::discover(config.as_deref())?;
+            config.seed = seed_value(seed).or(config.seed);
+            config.key = Key::discover(key_file.as_deref())?;
+            if config.key.is_some() {
+                info!("Keying the randomness with the given secret");
             }
+            let cache = match cache {
+                Some(path) => {
+                    let blocks = Arc::new(BlockCache::load(&path)?);
+                    config.cache = Some(blocks.clone());
+                    Some((path, blocks))
+                }
+                None => None,
+            };
+            config.rounds = rounds.map(|rounds| rounds as usize).or(config.rounds);
+            config.fork = fork.or(config.fork);
+            config.revert_strings = revert_strings.or(config.revert_strings);
+            if storage_remap {
+                config.storage_remap = Some(true);
+            }
+            if let Some(name) = &chain {
+                if let Ok(id) = explorer::parse_chain(name) {
+                    config.chain_id = Some(id);
+                }
+                match chain::parse(name) {
+                    Ok(profiled) => config.chain = Some(profiled),
+                    // any chain the explorer knows can still be fetched from
+                    Err(_) if explorer::parse_chain(name).is_ok() => {
+                        info!("No opcode profile for chain {}; keeping to the fork", name)
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            config.search.candidates = search.or(config.search.candidates);
+            config.search.objective = objective.unwrap_or(config.search.objective);
+            config.search.patience = patience.or(config.search.patience);
+            config.passes.disabled.extend(disable);
+            let p = &mut config.probabilities;
+            p.chaotic_shuffle = shuffle_prob.or(p.chaotic_shuffle);
+            p.substitution = substitution_prob.or(p.substitution);
+            p.stack_shuffle = stack_shuffle_prob.or(p.stack_shuffle);
+            p.false_branch = false_branch_prob.or(p.false_branch);
+            p.scratch_layout = scratch_layout_prob.or(p.scratch_layout);
+            p.inline = inline_prob.or(p.inline);
+            p.loops = loops_prob.or(p.loops);
+            p.gas_predicate = gas_predicate_prob.or(p.gas_predicate);
+            p.env_predicate = env_predicate_prob.or(p.env_predicate);
+            p.exception_flow = exception_flow_prob.or(p.exception_flow);
+            p.return_path = return_path_prob.or(p.return_path);
+            p.flower = flower_prob.or(p.flower);
+            p.decoy_reads = decoy_reads_prob.or(p.decoy_reads);
+            p.peephole = peephole_prob.or(p.peephole);
+            p.trampolines = trampolines_prob.or(p.trampolines);
+            p.push_width = push_width_prob.or(p.push_width);
+            p.spaghetti = spaghetti_prob.or(p.spaghetti);
+            p.intensity = intensity.or(p.intensity);
+            if !only_selectors.is_empty() || !skip_selectors.is_empty() {
+                config.scope.only_selectors = only_selectors;
+                config.scope.skip_selectors = skip_selectors;
+            }
+            config.scope.exclude.extend(exclude);
+            config.budget.max_gas_overhead = max_gas_overhead.or(config.budget.max_gas_overhead);
+            config.budget.max_size = max_size.or(config.budget.max_size);
+            config.output.path = output.or(config.output.path);
+            config.output.hex |= hex;
+            info!("Running passes: {}", config.pipeline()?.names().join(", "));
 
-            info!("Starting EVM Bytecode Obfuscator");
+            if let (Some(dir), Some(out_dir)) = (dir, out_dir) {
+                let files = batch::find(&dir, &glob)?;
+                if files.is_empty() {
+                    anyhow::bail!("no file under {:?} matches '{}'", dir, glob);
+                }
+                info!("Obfuscating {} files from {:?}", files.len(), dir);
+                let seed = config.seed.unwrap_or(42);
+                let outcomes = batch::run(
+                    &dir,
+                    &files,
+                    &out_dir,
+                    input_format,
+                    config.output.hex,
+                    force,
+                    |path, bytecode| {
+                        info!("Obfuscating {:?}", path);
+                        config.obfuscate(bytecode, seed)
+                    },
+                );
+                let mut failed = 0;
+                for outcome in &outcomes {
+                    match &outcome.result {
+                        Ok((before, after)) => out.line(format!(
+                            "ok      {}: {} -> {} bytes",
+                            outcome.path.display(),
+                            before,
+                            after
+                        )),
+                        Err(err) => {
+                            out.line(format!("failed  {}: {:#}", outcome.path.display(), err));
+                            failed += 1;
+                        }
+                    }
+                }
+                out.line(format!(
+                    "{} of {} files obfuscated into {:?}",
+                    outcomes.len() - failed,
+                    outcomes.len(),
+                    out_dir
+                ));
+                let files: Vec<serde_json::Value> = outcomes
+                    .iter()
+                    .map(|outcome| outcome_json("path", outcome.path.display(), &outcome.result))
+                    .collect();
+                out.set("files", files);
+                out.set("out_dir", &out_dir);
+                save_cache(cache.as_ref(), out)?;
+                if failed > 0 {
+                    anyhow::bail!(
+                        "{} of {} files could not be obfuscated",
+                        failed,
+                        outcomes.len()
+                    );
+                }
+                return Ok(());
+            }
 
-            info!("Reading bytecode from file: {:?}", file);
-            let bytecode = std::fs::read(&file)?;
+            let file = file.or(input).unwrap_or_else(|| PathBuf::from("-"));
+            // the abi of a verified contract, to scope obfuscation by function name
+            let mut functions = None;
+            // fetched code is raw bytes, whatever --input-format says about files
+            let (input, input_format) = match &address {
+                Some(address) if etherscan => {
+                    let key = std::env::var(explorer::API_KEY_VAR).ok();
+                    if key.is_none() && explorer_url.is_none() {
+                        anyhow::bail!("--etherscan needs an API key in {}", explorer::API_KEY_VAR);
+                    }
+                    let url = explorer_url.as_deref().unwrap_or(explorer::ETHERSCAN_API);
+                    let chain = chain.as_deref().unwrap_or("mainnet");
+                    let explorer = Explorer::new(url, explorer::parse_chain(chain)?, key);
+                    info!("Fetching {} on chain {} from {}", address, chain, url);
+                    let contract = explorer.fetch(&rpc::parse_address(address)?, &block)?;
+                    match contract.verified {
+                        Some(verified) => {
+                            info!(
+                                "Verified as {}, compiled with {}{}",
+                                verified.name,
+                                verified.compiler,
+                                verified
+                                    .optimizer_runs
+                                    .map_or(String::new(), |runs| format!(
+                                        " ({} optimizer runs)",
+                                        runs
+                                    ))
+                            );
+                            for function in &verified.functions {
+                                debug!(
+                                    "0x{} {}",
+                                    hex::encode(function.selector()),
+                                    function.signature()
+                                );
+                            }
+                            functions = Some(verified.functions);
+                        }
+                        None => warn!(
+                            "{} is not verified; selectors must be given in hex",
+                            address
+                        ),
+                    }
+                    (contract.code, corpus::Format::Raw)
+                }
+                Some(address) => {
+                    let Some(url) = rpc_url.or_else(|| std::env::var(rpc::RPC_URL_VAR).ok()) else {
+                        anyhow::bail!("--address needs --rpc-url or {}", rpc::RPC_URL_VAR);
+                    };
+                    info!("Fetching the code of {} at block {}", address, block);
+                    let code = rpc::get_code(&url, &rpc::parse_address(address)?, &block)?;
+                    (code, corpus::Format::Raw)
+                }
+                None => {
+                    info!("Reading bytecode from file: {:?}", file);
+                    (corpus::read_input(&file)?, input_format)
+                }
+            };
+            let format = match input_format {
+                corpus::Format::Auto => corpus::detect(&input),
+                format => format,
+            };
+            // an artifact is written back with the obfuscated code in place of its own
+            let artifact = match format {
+                corpus::Format::Artifact => Some(Artifact::parse(&input)?),
+                _ => None,
+            };
+            // unlinked hex keeps its library placeholders; artifacts keep theirs themselves
+            let placeholders = match format {
+                corpus::Format::Hex => link::placeholders(&String::from_utf8_lossy(&input)),
+                _ => Vec::new(),
+            };
+            let bytecode = match &artifact {
+                Some(artifact) => artifact.runtime(),
+                None => corpus::parse_bytes(input, format),
+            }
+            .with_context(|| format!("cannot decode {:?}", file))?;
+            // the arguments of a creation transaction are not code; only the runtime is obfuscated.
+            // the data is kept once, as the creation, however large its init code
+            let (bytecode, creation) = match creation {
+                true if artifact.is_some() => {
+                    anyhow::bail!("--creation reads transaction data, not an artifact")
+                }
+                true => {
+                    let creation = Creation::split_owned(bytecode)?;
+                    info!(
+                        "Creation code of {} bytes deploys {} bytes of runtime code, followed by {} bytes of constructor arguments",
+                        creation.code().len(),
+                        creation.runtime.len(),
+                        creation.arguments().len()
+                    );
+                    (creation.runtime().to_vec(), Some(creation))
+                }
+                false => (bytecode, None),
+            };
 
-            let mut obfuscator = Obfuscator::new(&bytecode, seed);
-            info!("Obfuscating bytecode...");
-            let obfuscated = obfuscator.obfuscate();
+            if let Some(functions) = &functions {
+                config.scope.resolve_names(functions)?;
+            }
+
+            if dry_run {
+                info!("Planning obfuscation...");
+                let plan = config.plan(&bytecode, config.seed.unwrap_or(42))?;
+                out.line(&plan);
+                out.merge(&plan);
+                return save_cache(cache.as_ref(), out);
+            }
+
+            // `-` prints hex to stdout; existing files are only replaced when asked to, and this is
+            // checked up front so a long seed search is not thrown away at the end. a random seed
+            // goes into the default name, so the output tells how to make it again
+            let name = match seed.filter(|seed| seed.random) {
+                Some(seed) => format!("obfuscated-{}", seed.value),
+                None => "obfuscated".to_string(),
+            };
+            let output_path = match config.output.path.take() {
+                Some(path) if path.as_os_str() == "-" => None,
+                Some(path) => Some(path),
+                None if artifact.is_some() => Some(PathBuf::from(name).with_extension("json")),
+                None => Some(PathBuf::from(name).with_extension("bin")),
+            };
+            if variants == Some(0) {
+                anyhow::bail!("--variants needs at least one variant");
+            }
+            if variants.is_some() && output_path.is_none() {
+                anyhow::bail!("--variants are written next to the output, which is not a file");
+            }
+            // every variant is a run of its own, with its own seed, output, and reports
+            let seed = config.seed.unwrap_or(42);
+            let runs: Vec<(Option<usize>, u64, Option<PathBuf>)> = match variants {
+                None => vec![(None, seed, output_path.clone())],
+                Some(count) => (1..=count)
+                    .map(|index| {
+                        let path = output_path
+                            .as_deref()
+                            .map(|path| ebo::variants::path(path, index));
+                        (Some(index), variant_seed(seed, index), path)
+                    })
+                    .collect(),
+            };
+            // every file a run writes is checked before any work is done, and each write checks
+            // again, so no file is replaced without --force
+            for (variant, _, path) in &runs {
+                let mut paths: Vec<PathBuf> = path.iter().cloned().collect();
+                if let Some(output) = path {
+                    paths.extend(manifest.then(|| ebo::manifest::path(output)));
+                    paths.extend(pc_map.then(|| ebo::pcmap::path(output)));
+                    paths.extend(source_map.as_ref().map(|_| ebo::srcmap::path(output)));
+                }
+                for report in [&report, &report_html].into_iter().flatten() {
+                    paths.push(match variant {
+                        Some(index) => ebo::variants::path(report, *index),
+                        None => report.clone(),
+                    });
+                }
+                for path in &paths {
+                    corpus::refuse_overwrite(path, force)?;
+                }
+            }
+            if manifest && output_path.is_none() {
+                anyhow::bail!("--manifest is written next to the output, which is not a file");
+            }
+            if pc_map && output_path.is_none() {
+                anyhow::bail!("--pc-map is written next to the output, which is not a file");
+            }
+            if source_map.is_some() && output_path.is_none() {
+                anyhow::bail!("--source-map is written next to the output, which is not a file");
+            }
+            let sources = match &source_map {
+                Some(path) => Some(SourceMap::parse(
+                    &std::fs::read_to_string(path)
+                        .with_context(|| format!("cannot read {:?}", path))?,
+                )?),
+                None => None,
+            };
+
+            let mut built = Variants::new(&bytecode);
+            let mut results = Vec::new();
+            for (variant, seed, output_path) in runs {
+                let mut run = Output::new(out.json, "");
+                let (report, report_html) = match variant {
+                    Some(index) => {
+                        info!("Obfuscating variant {} with seed {}...", index, seed);
+                        run.set("index", index);
+                        (
+                            report
+                                .as_deref()
+                                .map(|path| ebo::variants::path(path, index)),
+                            report_html
+                                .as_deref()
+                                .map(|path| ebo::variants::path(path, index)),
+                        )
+                    }
+                    None => {
+                        info!("Obfuscating bytecode...");
+                        (report.clone(), report_html.clone())
+                    }
+                };
+                let Obfuscation {
+                    bytecode: obfuscated,
+                    seed,
+                    stats,
+                    pc_map: map,
+                    slots,
+                } = config.obfuscate(&bytecode, seed)?;
+                if let (Some(index), Some(path)) = (variant, &output_path) {
+                    built.push(index, seed, path.clone(), &obfuscated);
+                }
+
+                if verbosity == Verbosity::Verbose {
+                    debug!("Original bytecode: {}", hex::encode(&bytecode));
+                    debug!("Obfuscated bytecode: {}", hex::encode(&obfuscated));
+                    debug!(
+                        "Bytecode length increase: {}%",
+                        ((obfuscated.len() as f64 / bytecode.len() as f64) - 1.0) * 100.0
+                    );
+                } else {
+                    info!(
+                        "Obfuscation complete. Output length: {} bytes",
+                        obfuscated.len()
+                    );
+                }
+                let (before, after) = (evm::halstead(&bytecode), evm::halstead(&obfuscated));
+                info!(
+                    "Halstead volume {:.0} -> {:.0}, difficulty {:.1} -> {:.1}, effort {:.0} -> {:.0}",
+                    before.volume,
+                    after.volume,
+                    before.difficulty,
+                    after.difficulty,
+                    before.effort,
+                    after.effort
+                );
+                let (bytes, opcodes) = (
+                    evm::byte_entropy(&obfuscated) - evm::byte_entropy(&bytecode),
+                    evm::opcode_entropy(&obfuscated) - evm::opcode_entropy(&bytecode),
+                );
+                info!(
+                    "Entropy change: {:+.3} bits per byte, {:+.3} bits per opcode",
+                    bytes, opcodes
+                );
+                let (before, after) = (cfg::stats(&bytecode), cfg::stats(&obfuscated));
+                info!(
+                    "CFG {} -> {} blocks, {} -> {} edges, block size {:.1} -> {:.1} (max {} -> {}), branch density {:.2} -> {:.2}",
+                    before.nodes,
+                    after.nodes,
+                    before.edges,
+                    after.edges,
+                    before.average_block_size,
+                    after.average_block_size,
+                    before.max_block_size,
+                    after.max_block_size,
+                    before.branch_density,
+                    after.branch_density
+                );
+                info!(
+                    "Compression ratio {:.3} -> {:.3}",
+                    evm::compression_ratio(&bytecode),
+                    evm::compression_ratio(&obfuscated)
+                );
+                info!(
+                    "Opcode n-gram similarity to the input: {}",
+                    summary::NGRAM_SIZES
+                        .map(|n| format!(
+                            "{:.2} (n={})",
+                            evm::ngram_similarity(&bytecode, &obfuscated, n),
+                            n
+                        ))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+                let score = score::Score::new(&bytecode, &obfuscated, None);
+                info!("Score: {}", score);
+                run.set("seed", seed);
+                run.set("size", (bytecode.len(), obfuscated.len()));
+                run.set("passes", &stats);
+                run.set("score", &score);
+
+                if report.is_some() || report_html.is_some() {
+                    let summary =
+                        summary::Summary::new(&bytecode, &obfuscated, seed, &config, &stats)?;
+                    if let Some(path) = report {
+                        corpus::write(&path, summary.to_json(), force)?;
+                        info!("Report saved to {:?}", path);
+                        run.set("report", &path);
+                    }
+                    if let Some(path) = report_html {
+                        let html = html::report(&summary, &bytecode, &obfuscated);
+                        corpus::write(&path, html, force)?;
+                        info!("HTML report saved to {:?}", path);
+                        run.set("report_html", &path);
+                    }
+                }
+                // the manifest and the maps are of the runtime code, before it goes back into its
+                // container
+                let sources = sources
+                    .clone()
+                    .zip(map.as_ref())
+                    .map(|(sources, map)| sources.translate(&bytecode, &obfuscated, map));
+                let manifest = manifest
+                    .then(|| Manifest::new(&bytecode, &obfuscated, seed, &config))
+                    .transpose()?;
+                // creation data written to a file is rebuilt into it as it is written, so large
+                // init code is not held a second time
+                let stream = creation
+                    .as_ref()
+                    .filter(|_| output_path.is_some() && placeholders.is_empty());
+                let mut obfuscated = match &creation {
+                    Some(creation) if stream.is_none() => creation.rebuild(&obfuscated)?,
+                    _ => obfuscated,
+                };
+                let mut artifact = artifact.clone();
+                let contents = match artifact.as_mut() {
+                    _ if stream.is_some() => Vec::new(),
+                    Some(artifact) => {
+                        artifact.replace_runtime(&obfuscated, map.as_ref())?;
+                        artifact.to_json().into_bytes()
+                    }
+                    None if !placeholders.is_empty() => {
+                        let (digits, references) = link::relink(&obfuscated, &placeholders)?;
+                        for (placeholder, offsets) in &references {
+                            let starts: Vec<String> = offsets
+                                .iter()
+                                .map(|offset| format!("{:#x}", offset.start))
+                                .collect();
+                            info!("Library {} now at {}", placeholder, starts.join(", "));
+                        }
+                        if let Some(path) = &output_path {
+                            let path = link::write_references(path, &references, force)?;
+                            info!("Link references saved to {:?}", path);
+                            run.set("link_references", &path);
+                        }
+                        digits.into_bytes()
+                    }
+                    None if config.output.hex || output_path.is_none() => {
+                        hex::encode(&obfuscated).into_bytes()
+                    }
+                    None => std::mem::take(&mut obfuscated),
+                };
+                match &output_path {
+                    None => {
+                        let contents = String::from_utf8_lossy(&contents);
+                        run.line(&contents);
+                        run.set("bytecode", contents);
+                    }
+                    Some(path) => {
+                        let mut file = std::io::BufWriter::new(corpus::create(path, force)?);
+                        match stream {
+                            Some(creation) if config.output.hex => {
+                                let mut hex = HexWriter::new(&mut file);
+                                creation.write_rebuilt(&obfuscated, &mut hex)?
+                            }
+                            Some(creation) => creation.write_rebuilt(&obfuscated, &mut file)?,
+                            None => file.write_all(&contents)?,
+                        }
+                        file.flush()?;
+                        info!("Obfuscated bytecode saved to {:?}", path);
+                        run.set("output", path);
+                    }
+                }
+                if let (Some(manifest), Some(output)) = (manifest, &output_path) {
+                    let path = ebo::manifest::path(output);
+                    corpus::write(&path, manifest.to_json(), force)?;
+                    info!("Manifest saved to {:?}", path);
+                    run.set("manifest", &path);
+                }
+                if (pc_map || source_map.is_some()) && map.is_none() {
+                    warn!("No PC map or source map is written for an EOF container");
+                }
+                if let (Some(map), Some(output), true) = (map, &output_path, pc_map) {
+                    let path = ebo::pcmap::path(output);
+                    corpus::write(&path, map.to_json(), force)?;
+                    info!("PC map saved to {:?}", path);
+                    run.set("pc_map", &path);
+                }
+                if let (Some(sources), Some(output)) = (sources, &output_path) {
+                    let path = ebo::srcmap::path(output);
+                    corpus::write(&path, sources.to_string(), force)?;
+                    info!("Source map saved to {:?}", path);
+                    run.set("source_map", &path);
+                }
+                if !slots.is_empty() {
+                    match &output_path {
+                        Some(output) => {
+                            let path = ebo::slotmap::path(output);
+                            corpus::write(&path, slots.to_json(), force)?;
+                            info!("Storage slot map saved to {:?}", path);
+                            run.set("slot_map", &path);
+                        }
+                        None => warn!(
+                            "{} storage slots moved, but the slot map is only written next to an output file",
+                            slots.slots.len()
+                        ),
+                    }
+                }
+                match variant {
+                    Some(_) => results.push(run.document),
+                    None => out.document.extend(run.document),
+                }
+            }
+            if let (Some(_), Some(output)) = (variants, &output_path) {
+                for (first, second) in built.duplicates() {
+                    warn!("Variants {} and {} came out the same", first, second);
+                }
+                let path = ebo::variants::index_path(output);
+                corpus::write(&path, built.to_json(), force)?;
+                info!("Variants index saved to {:?}", path);
+                out.set("variants_index", &path);
+                out.set("variants", results);
+            }
+            save_cache(cache.as_ref(), out)?;
+        }
+        Commands::Reproduce {
+            manifest,
+            file,
+            input_format,
+            key_file,
+        } => {
+            let source = std::fs::read_to_string(&manifest)
+                .with_context(|| format!("cannot read {:?}", manifest))?;
+            let manifest = Manifest::parse(&source)?;
+            let bytecode = corpus::read_bytes_as(&file, input_format)?;
+            let output = manifest.reproduce(&bytecode, Key::discover(key_file.as_deref())?)?;
+            out.line(format!(
+                "reproduced {} bytes with seed {}: sha-256 {}, codehash 0x{}",
+                output.len(),
+                manifest.seed,
+                manifest.output.sha256,
+                manifest.output.codehash
+            ));
+            out.set("seed", manifest.seed);
+            out.set("output", &manifest.output);
+        }
+        Commands::Verify {
+            original,
+            obfuscated,
+            corpus,
+        } => {
+            let original = corpus::read_bytes(&original)?;
+            let obfuscated = corpus::read_bytes(&obfuscated)?;
+            let samples = corpus::load(&corpus)?;
 
-            if verbosity == Verbosity::Verbose {
-                debug!("Original bytecode: {}", hex::encode(&bytecode));
-                debug!("Obfuscated bytecode: {}", hex::encode(&obfuscated));
+            let mut divergent = Vec::new();
+            let (mut gas_before, mut gas_after) = (0u64, 0u64);
+            for sample in &samples {
+                let run = equivalence::compare(&original, &obfuscated, &sample.calldata)?;
                 debug!(
-                    "Bytecode length increase: {}%",
-                    ((obfuscated.len() as f64 / bytecode.len() as f64) - 1.0) * 100.0
+                    "Sample {}: {:?}, gas {} -> {}",
+                    sample.name,
+                    run.original.effects.status,
+                    run.original.gas_used,
+                    run.obfuscated.gas_used
                 );
-            } else {
+                gas_before += run.original.gas_used;
+                gas_after += run.obfuscated.gas_used;
+                if let Some(divergence) = run.divergence() {
+                    out.line(format!("sample {} diverges:\n{}", sample.name, divergence));
+                    let mut json = divergence_json(&divergence);
+                    json["sample"] = sample.name.clone().into();
+                    divergent.push(json);
+                }
+            }
+            out.set("samples", samples.len());
+            out.set("gas", (gas_before, gas_after));
+            if !divergent.is_empty() {
+                let count = divergent.len();
+                out.set("divergent", divergent);
+                return Err(EboError::Verification(format!(
+                    "{} of {} samples diverge",
+                    count,
+                    samples.len()
+                ))
+                .into());
+            }
+            out.line(format!(
+                "all {} samples behave the same (gas {} -> {}, {:+.1}%)",
+                samples.len(),
+                gas_before,
+                gas_after,
+                (gas_after as f64 / gas_before as f64 - 1.0) * 100.0
+            ));
+        }
+        Commands::Compile {
+            standard_json,
+            solc: solc_path,
+            solc_version,
+            config,
+            seed,
+            key_file,
+            fork,
+            chain,
+            output,
+            force,
+        } => {
+            if let Some(path) = &output {
+                corpus::refuse_overwrite(path, force)?;
+            }
+            let mut config = Config::discover(config.as_deref())?;
+            config.key = Key::discover(key_file.as_deref())?;
+            config.chain = chain.or(config.chain);
+            let seed = seed_value(seed).or(config.seed).unwrap_or(42);
+            out.set("seed", seed);
+
+            let source = std::fs::read_to_string(&standard_json)
+                .with_context(|| format!("cannot read {:?}", standard_json))?;
+            let mut input: serde_json::Value =
+                serde_json::from_str(&source).context("invalid standard-json input")?;
+            // the code is compiled for the input's evm version, so the output is kept valid for it
+            config.fork = fork.or(config.fork).or_else(|| solc::evm_version(&input));
+            solc::select_outputs(&mut input)?;
+            let compiler = solc::locate(solc_path.as_deref(), solc_version.as_deref())?;
+            let mut compiled = solc::compile(&compiler, &input)?;
+
+            let outcomes =
+                solc::substitute(&mut compiled, |runtime| config.obfuscate(runtime, seed));
+            // the output may go to stdout, so the summary goes to stderr
+            let mut failed = 0;
+            for outcome in &outcomes {
+                match &outcome.result {
+                    Ok((before, after)) if !out.json => {
+                        eprintln!("{}: {} -> {} bytes", outcome.contract, before, after)
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        if !out.json {
+                            eprintln!("{}: left as compiled: {:#}", outcome.contract, err);
+                        }
+                        failed += 1;
+                    }
+                }
+            }
+            let contracts: Vec<serde_json::Value> = outcomes
+                .iter()
+                .map(|outcome| outcome_json("contract", &outcome.contract, &outcome.result))
+                .collect();
+            out.set("contracts", contracts);
+
+            match output {
+                Some(path) => {
+                    corpus::write(&path, serde_json::to_string(&compiled)?, force)?;
+                    out.set("output", path);
+                }
+                None => {
+                    out.line(serde_json::to_string(&compiled)?);
+                    out.set("output", &compiled);
+                }
+            }
+            if failed > 0 {
+                anyhow::bail!(
+                    "{} of {} contracts could not be obfuscated",
+                    failed,
+                    outcomes.len()
+                );
+            }
+        }
+        Commands::Test {
+            project,
+            src,
+            fixture,
+            config,
+            seed,
+            key_file,
+            force,
+        } => {
+            corpus::refuse_overwrite(&project.join(&fixture), force)?;
+            // the configuration is looked up in the project rather than the working directory
+            let mut config = match config {
+                Some(path) => Config::load(&path)?,
+                None => {
+                    let path = project.join(config::DEFAULT_CONFIG_FILE);
+                    if path.exists() {
+                        Config::load(&path)?
+                    } else {
+                        Config::default()
+                    }
+                }
+            };
+            config.key = Key::discover(key_file.as_deref())?;
+            let seed = seed_value(seed).or(config.seed).unwrap_or(42);
+            out.set("seed", seed);
+
+            foundry::build(&project)?;
+            let (artifacts, skipped) = foundry::artifacts(&project.join("out"), &src)?;
+            for contract in &skipped {
+                out.line(format!("skipping {}: {}", contract.name, contract.reason));
+            }
+            let skipped: BTreeMap<&str, &str> = skipped
+                .iter()
+                .map(|contract| (contract.name.as_str(), contract.reason))
+                .collect();
+            out.set("skipped", skipped);
+            if artifacts.is_empty() {
+                anyhow::bail!("no contracts to obfuscate under {:?}", project.join(&src));
+            }
+            let mut contracts = Vec::new();
+            for artifact in &artifacts {
+                let obfuscated = config
+                    .obfuscate(&artifact.runtime, seed)
+                    .with_context(|| format!("cannot obfuscate {}", artifact.name))?
+                    .bytecode;
                 info!(
-                    "Obfuscation complete. Output length: {} bytes",
+                    "Obfuscated {} ({}): {} -> {} bytes",
+                    artifact.name,
+                    artifact.source,
+                    artifact.runtime.len(),
                     obfuscated.len()
                 );
+                contracts.push((artifact.name.clone(), obfuscated));
+            }
+            let path = project.join(&fixture);
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            corpus::write(&path, foundry::fixture(&contracts), force)?;
+            out.line(format!(
+                "obfuscated {} contracts; fixture written to {:?}",
+                contracts.len(),
+                path
+            ));
+            let names: Vec<&str> = contracts.iter().map(|(name, _)| name.as_str()).collect();
+            out.set("contracts", names);
+            out.set("fixture", &path);
+
+            let original = foundry::test(&project, false)?;
+            let obfuscated = foundry::test(&project, true)?;
+            let regressions = foundry::regressions(&original, &obfuscated);
+            for test in &regressions {
+                out.line(format!("fails only when obfuscated: {}", test));
+            }
+            out.set("tests", original.len());
+            out.set("regressions", &regressions);
+            if !regressions.is_empty() {
+                return Err(EboError::Verification(format!(
+                    "{} of {} tests fail only on obfuscated code",
+                    regressions.len(),
+                    original.len()
+                ))
+                .into());
+            }
+            let passed = original
+                .values()
+                .filter(|&&status| status == foundry::Status::Passed)
+                .count();
+            out.line(format!(
+                "all {} tests that pass on the original build also pass when obfuscated",
+                passed
+            ));
+            out.set("passed", passed);
+        }
+        Commands::GasReport {
+            original,
+            obfuscated,
+            abi,
+            corpus,
+            runs,
+            seed,
+        } => {
+            let original = corpus::read_bytes(&original)?;
+            let obfuscated = corpus::read_bytes(&obfuscated)?;
+            let functions = abi::parse(&std::fs::read_to_string(&abi)?)?;
+            let samples = match corpus {
+                Some(path) => corpus::load(&path)?,
+                None => Vec::new(),
+            };
+
+            let report = report::GasReport::measure(
+                &original,
+                &obfuscated,
+                &functions,
+                &samples,
+                runs,
+                seed,
+            )?;
+            if report.rows.is_empty() {
+                anyhow::bail!("the abi has no functions to call");
+            }
+            out.text(&report);
+            let score = score::Score::new(&original, &obfuscated, Some(report.total().overhead()));
+            out.line(&score);
+            let divergent = report.total().divergent;
+            if divergent > 0 {
+                out.line(format!(
+                    "{} calls behave differently after obfuscation; run `ebo fuzz` to investigate",
+                    divergent
+                ));
+            }
+            out.set("functions", &report.rows);
+            out.set("total", report.total());
+            out.set("score", &score);
+        }
+        Commands::Trace {
+            original,
+            obfuscated,
+            calldata,
+            context,
+        } => {
+            let original = corpus::read_bytes(&original)?;
+            let obfuscated = corpus::read_bytes(&obfuscated)?;
+            let calldata = hex::decode(calldata.trim().trim_start_matches("0x"))
+                .map_err(|err| anyhow::anyhow!("invalid calldata: {}", err))?;
+
+            let (run_a, steps_a) = trace::record(&original, &calldata)?;
+            let (run_b, steps_b) = trace::record(&obfuscated, &calldata)?;
+            let rows = trace::align(&steps_a, &steps_b);
+            let first = rows.iter().position(|row| row.diverges);
+            let shown = match (context, first) {
+                (Some(context), Some(first)) => {
+                    first.saturating_sub(context)..(first + context + 1).min(rows.len())
+                }
+                _ => 0..rows.len(),
+            };
+            for row in &rows[shown.clone()] {
+                out.line(row);
+            }
+            let step_json = |step: Option<&trace::Step>| {
+                step.map(|step| {
+                    serde_json::json!({
+                        "pc": step.pc,
+                        "opcode": evm::mnemonic(step.opcode),
+                        "arguments": step.arguments().iter().map(|word| format!("{:#x}", word)).collect::<Vec<_>>(),
+                        "gas": step.gas,
+                    })
+                })
+            };
+            let rows_json: Vec<serde_json::Value> = rows[shown]
+                .iter()
+                .map(|row| {
+                    serde_json::json!({
+                        "original": step_json(row.original),
+                        "obfuscated": step_json(row.obfuscated),
+                        "anchor": row.anchor,
+                        "diverges": row.diverges,
+                    })
+                })
+                .collect();
+            out.set("rows", rows_json);
+            out.set("first_divergence", first);
+            for (name, steps) in [("original", &steps_a), ("obfuscated", &steps_b)] {
+                if steps.len() == trace::MAX_STEPS {
+                    out.line(format!(
+                        "the {} trace is cut off after {} steps",
+                        name,
+                        trace::MAX_STEPS
+                    ));
+                }
+            }
+
+            let comparison = equivalence::Comparison {
+                calldata,
+                original: run_a,
+                obfuscated: run_b,
+            };
+            out.set(
+                "gas",
+                (comparison.original.gas_used, comparison.obfuscated.gas_used),
+            );
+            if let Some(divergence) = comparison.divergence() {
+                out.text(&divergence);
+                out.set("divergence", divergence_json(&divergence));
+                return Err(EboError::Verification(
+                    "the binaries behave differently on this call".into(),
+                )
+                .into());
+            }
+            if let Some(first) = first {
+                return Err(
+                    EboError::Verification(format!("the traces diverge at row {}", first)).into(),
+                );
+            }
+            out.line(format!(
+                "no divergence; gas {} -> {}",
+                comparison.original.gas_used, comparison.obfuscated.gas_used
+            ));
+        }
+        Commands::Lint {
+            file,
+            input_format,
+            fork,
+            chain,
+        } => {
+            let bytecode = corpus::read_bytes_as(&file, input_format)?;
+            let target = Target::new(fork, chain);
+            let issues = validate::validate(&bytecode, target);
+            for issue in &issues {
+                out.line(issue);
+            }
+            let mut notes = Vec::new();
+            for ins in evm::disassemble(&bytecode) {
+                if !target.reliable(ins.opcode) {
+                    let note = format!(
+                        "{} behaves differently on {}",
+                        evm::mnemonic(ins.opcode),
+                        target
+                    );
+                    out.line(format!("{:#06x}: note: {}", ins.offset, note));
+                    notes.push(serde_json::json!({"offset": ins.offset, "note": note}));
+                }
+            }
+            let issues_json: Vec<serde_json::Value> = issues
+                .iter()
+                .map(|issue| {
+                    let message = issue.to_string();
+                    let message = message.split_once(": ").map_or(&*message, |(_, m)| m);
+                    serde_json::json!({"offset": issue.offset, "issue": message})
+                })
+                .collect();
+            out.set("issues", issues_json);
+            out.set("notes", notes);
+            if !issues.is_empty() {
+                anyhow::bail!("{} issues found", issues.len());
+            }
+            out.line("no issues found");
+        }
+        Commands::Analyze { file, input_format } => {
+            let bytecode = corpus::read_bytes_as(&file, input_format)?;
+            let analysis = analysis::Analysis::new(&bytecode);
+            out.text(&analysis);
+            out.merge(&analysis);
+        }
+        Commands::Stats {
+            file,
+            input_format,
+            format,
+        } => {
+            let bytecode = corpus::read_bytes_as(&file, input_format)?;
+            let histogram = analysis::Histogram::new(&bytecode);
+            match format {
+                StatsFormat::Text => out.text(&histogram),
+                StatsFormat::Json => out.line(histogram.to_json()),
+                StatsFormat::Csv => out.text(histogram.to_csv()),
+            }
+            out.merge(&histogram);
+        }
+        Commands::Compare { left, right } => {
+            let left = corpus::read_bytes(&left)?;
+            let right = corpus::read_bytes(&right)?;
+            let comparison = analysis::Comparison::new(&left, &right);
+            out.text(&comparison);
+            out.merge(&comparison);
+        }
+        Commands::Evaluate {
+            original,
+            obfuscated,
+            decompiler,
+            timeout,
+        } => {
+            let original = corpus::read_bytes(&original)?;
+            let obfuscated = corpus::read_bytes(&obfuscated)?;
+            let decompilers: Vec<Decompiler> = match decompiler.is_empty() {
+                false => decompiler
+                    .iter()
+                    .map(|name| Decompiler::parse(name))
+                    .collect(),
+                true => Decompiler::known()
+                    .into_iter()
+                    .filter(|known| {
+                        let installed = known.installed();
+                        if !installed {
+                            info!("{} is not installed, skipping it", known.name);
+                        }
+                        installed
+                    })
+                    .collect(),
+            };
+            if decompilers.is_empty() {
+                anyhow::bail!(
+                    "no known decompiler is installed ({}); name one with --decompiler",
+                    Decompiler::known()
+                        .iter()
+                        .map(|known| known.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+            let timeout = std::time::Duration::from_secs(timeout);
+            let mut evaluations = Vec::new();
+            for decompiler in &decompilers {
+                info!("Decompiling with {}", decompiler.name);
+                evaluations.push(decompile::Evaluation::run(
+                    decompiler,
+                    &original,
+                    &obfuscated,
+                    timeout,
+                )?);
+            }
+            for evaluation in &evaluations {
+                out.text(evaluation);
+            }
+            out.set("evaluations", &evaluations);
+        }
+        Commands::Address {
+            file,
+            input_format,
+            runtime,
+            fork,
+            deployer,
+            salt,
+        } => {
+            let input = corpus::read_input(&file)?;
+            let format = match input_format {
+                corpus::Format::Auto => corpus::detect(&input),
+                format => format,
+            };
+            let code = match format {
+                corpus::Format::Artifact if !runtime => Artifact::parse(&input)?
+                    .creation()?
+                    .with_context(|| format!("{:?} has no creation code", file))?,
+                format => corpus::parse_bytes(input, format)
+                    .with_context(|| format!("cannot decode {:?}", file))?,
+            };
+            if !link::regions(&code).is_empty() {
+                anyhow::bail!(
+                    "the code links against libraries; link it before computing its address"
+                );
+            }
+            let init = match runtime {
+                true => creation::wrap(&code, fork),
+                false => code,
+            };
+            let deployer = match &deployer {
+                Some(deployer) => rpc::parse_address(deployer)?,
+                None => creation::DEPLOYER,
+            };
+            let salt = creation::parse_salt(&salt)?;
+            let address =
+                revm::primitives::Address::from(creation::create2_address(deployer, salt, &init))
+                    .to_checksum(None);
+            out.line(format!("address            {}", address));
+            out.line(format!("init code hash     {}", keccak256(&init)));
+            out.set("address", address);
+            out.set("init_code_hash", keccak256(&init).to_string());
+            let deployment = equivalence::execute(&init, &[])?;
+            match deployment.effects.status {
+                equivalence::Status::Success => {
+                    let hash = keccak256(&deployment.effects.output);
+                    out.line(format!("runtime code hash  {}", hash));
+                    out.set("runtime_code_hash", hash.to_string());
+                }
+                status => warn!(
+                    "The constructor does not return ({:?}), so the runtime code hash is unknown",
+                    status
+                ),
+            }
+        }
+        Commands::Wrap {
+            file,
+            input_format,
+            fork,
+            chain,
+            output,
+            hex,
+            force,
+        } => {
+            let runtime = corpus::read_bytes_as(&file, input_format)?;
+            if runtime.len() > evm::MAX_CODE_SIZE {
+                warn!(
+                    "The runtime code is {} bytes, more than the {} bytes EIP-170 allows",
+                    runtime.len(),
+                    evm::MAX_CODE_SIZE
+                );
+            }
+            let init = creation::wrap(&runtime, Target::new(fork, chain));
+            out.set("size", init.len());
+            match output {
+                None => {
+                    out.line(hex::encode(&init));
+                    out.set("init_code", hex::encode(&init));
+                }
+                Some(path) => {
+                    let contents = match hex {
+                        true => hex::encode(&init).into_bytes(),
+                        false => init,
+                    };
+                    corpus::write(&path, contents, force)?;
+                    info!("Init code saved to {:?}", path);
+                    out.set("output", path);
+                }
             }
+        }
+        Commands::Merge {
+            first,
+            second,
+            switch,
+            config,
+            seed,
+            key_file,
+            output,
+            hex,
+            force,
+        } => {
+            if let Some(path) = &output {
+                corpus::refuse_overwrite(path, force)?;
+            }
+            let mut config = Config::discover(config.as_deref())?;
+            config.key = Key::discover(key_file.as_deref())?;
+            let seed = seed_value(seed).or(config.seed).unwrap_or(42);
+            out.set("seed", seed);
+            let (first, second) = (corpus::read_bytes(&first)?, corpus::read_bytes(&second)?);
+            let merged = merge::merge(
+                &first,
+                &second,
+                switch,
+                &mut seed::rng(seed, config.key.as_ref()),
+            )?;
+            info!(
+                "Merged {} and {} bytes behind {}",
+                first.len(),
+                second.len(),
+                switch
+            );
+            let merged = config.obfuscate(&merged, seed)?.bytecode;
+            out.set("size", merged.len());
+            match output {
+                None => {
+                    out.line(hex::encode(&merged));
+                    out.set("bytecode", hex::encode(&merged));
+                }
+                Some(path) => {
+                    let contents = match hex {
+                        true => hex::encode(&merged).into_bytes(),
+                        false => merged,
+                    };
+                    corpus::write(&path, contents, force)?;
+                    info!("Merged bytecode saved to {:?}", path);
+                    out.set("output", path);
+                }
+            }
+        }
+        Commands::Fuzz {
+            original,
+            obfuscated,
+            abi,
+            runs,
+            seed,
+        } => {
+            let original = corpus::read_bytes(&original)?;
+            let obfuscated = corpus::read_bytes(&obfuscated)?;
+            let functions = match abi {
+                Some(path) => abi::parse(&std::fs::read_to_string(&path)?)?,
+                None => Vec::new(),
+            };
+            info!("Fuzzing with {} ABI functions", functions.len());
 
-            let output_path = "obfuscated.bin";
-            std::fs::write(output_path, &obfuscated)?;
-            info!("Obfuscated bytecode saved to {}", output_path);
+            let report = fuzz::Fuzzer::new(&original, &obfuscated, functions).run(runs, seed)?;
+            out.set("runs", report.runs);
+            if let Some((input, divergence)) = report.divergence {
+                let input = match input {
+                    fuzz::Input::Call(signature) => format!("call to {}", signature),
+                    fuzz::Input::Selector(selector) => {
+                        format!("selector 0x{}", hex::encode(selector))
+                    }
+                    fuzz::Input::Random => "random calldata".into(),
+                };
+                out.line(format!(
+                    "run {} diverges ({}):\n{}",
+                    report.runs, input, divergence
+                ));
+                let mut json = divergence_json(&divergence);
+                json["input"] = input.into();
+                out.set("divergence", json);
+                return Err(EboError::Verification(format!(
+                    "divergence found after {} runs",
+                    report.runs
+                ))
+                .into());
+            }
+            out.line(format!("no divergence in {} runs", report.runs));
+        }
+        Commands::Completions { shell } => {
+            let mut script = Vec::new();
+            clap_complete::generate(shell, &mut Cli::command(), "ebo", &mut script);
+            let script = String::from_utf8_lossy(&script);
+            out.text(&script);
+            out.set("script", script);
         }
+        Commands::Manpage { dir } => match dir {
+            Some(dir) => {
+                std::fs::create_dir_all(&dir)
+                    .with_context(|| format!("cannot create {:?}", dir))?;
+                clap_mangen::generate_to(Cli::command(), &dir)
+                    .with_context(|| format!("cannot write to {:?}", dir))?;
+                info!("Manual pages saved to {:?}", dir);
+                out.set("dir", dir);
+            }
+            None => {
+                let mut page = Vec::new();
+                clap_mangen::Man::new(Cli::command()).render(&mut page)?;
+                let page = String::from_utf8_lossy(&page);
+                out.text(&page);
+                out.set("page", page);
+            }
+        },
     }
 
     Ok(())
@@ -85,10 +2097,66 @@ fn main() -> anyhow::Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use crate::evm::{compute_cfg_complexity, parse_bytecode, Opcode};
-    use crate::obfuscator::Obfuscator;
+    use ebo::abi;
+    use ebo::analysis;
+    use ebo::artifact::{self, Artifact};
+    use ebo::batch;
+    use ebo::cfg;
+    use ebo::chain::{self, Chain, Target};
+    use ebo::config::Config;
+    use ebo::corpus;
+    use ebo::creation::{self, Creation};
+    use ebo::decompile::{self, Decompiler};
+    use ebo::deobfuscator;
+    use ebo::dispatcher::{self, Inconsistency, Selection};
+    use ebo::eof;
+    use ebo::equivalence;
+    use ebo::error::EboError;
+    use ebo::evm::{
+        self, compute_cfg_complexity, disassemble, halstead, op, parse_bytecode, Fork, Opcode,
+    };
+    use ebo::explorer::{self, Explorer};
+    use ebo::foundry;
+    use ebo::fuzz;
+    use ebo::gas::{self, Baseline};
+    use ebo::html;
+    use ebo::idioms::{self, Idiom};
+    use ebo::interface::{self, Interface};
+    use ebo::link;
+    use ebo::manifest::Manifest;
+    use ebo::merge::{self, Switch};
+    use ebo::obfuscator::Obfuscator;
+    use ebo::passes::env_predicate::{self, Predicate};
+    use ebo::passes::gas_predicate;
+    use ebo::passes::outline;
+    use ebo::passes::peephole::{self, Identity, Site, IDENTITIES};
+    use ebo::passes::selector_mask;
+    use ebo::passes::spaghetti;
+    use ebo::passes::substitution::TEMPLATES;
+    use ebo::passes::{ChaoticShuffle, Pipeline, Substitution};
+    use ebo::pcmap::{Origin, PcMap};
+    use ebo::progress::Progress;
+    use ebo::reloc::{Item, Program};
+    use ebo::report;
+    use ebo::rpc;
+    use ebo::score::Score;
+    use ebo::search::{Objective, Search};
+    use ebo::seed::{self, Key};
+    use ebo::slotmap::SlotMap;
+    use ebo::solc;
+    use ebo::srcmap::{self, SourceMap};
+    use ebo::summary;
+    use ebo::symbolic;
+    use ebo::test_support;
+    use ebo::trace;
+    use ebo::validate::{self, Problem};
     use proptest::prelude::*;
+    use rand::rngs::{mock::StepRng, StdRng};
+    use rand::SeedableRng;
+    use revm::primitives::{keccak256, U256};
+    use std::collections::{BTreeMap, BTreeSet, HashSet};
     use std::fs;
+    use std::path::{Path, PathBuf};
 
     // Helper to count unique opcodes for readability metric
     fn count_unique_opcodes(bytecode: &[u8]) -> usize {
@@ -99,32 +2167,72 @@ mod tests {
         unique.len()
     }
 
-    // Simplified Halstead's Effort proxy (operators + operands)
-    fn halstead_effort_proxy(bytecode: &[u8]) -> f64 {
-        let n1 = count_unique_opcodes(bytecode) as f64; // Unique operators
-        let n2 = bytecode.len() as f64; // Total operands
-        let effort = n1 * n2 * n2.log2(); // Simplified effort
-        effort
-    }
-
     #[test]
     fn test_obfuscate_add() {
+        use ebo::cache::BlockCache;
+        use std::sync::Arc;
+
         let bytecode = vec![0x01]; // ADD
+
         let mut obfuscator = Obfuscator::new(&bytecode, 42);
-        let obfuscated = obfuscator.obfuscate();
+        let obfuscated = obfuscator.obfuscate().unwrap();
         assert!(!obfuscated.is_empty());
-        assert!(obfuscated == vec![0x01] || obfuscated == vec![0x60, 0x01, 0x01, 0x60, 0x01, 0x01]);
+        // whatever the passes did to it, the add still underflows the stack the same way
+        let comparison = equivalence::compare(&bytecode, &obfuscated, &[]).unwrap();
+        assert!(comparison.divergence().is_none());
+
+        // so does whatever the block-local streams of other seeds draw, with cached blocks too
+        let cache = Arc::new(BlockCache::new());
+        for seed in 0..16 {
+            for cached in [false, true] {
+                let mut builder = Obfuscator::builder().seed(seed);
+                if cached {
+                    builder = builder.cache(cache.clone());
+                }
+                let obfuscated = builder.build(&bytecode).obfuscate().unwrap();
+                let comparison = equivalence::compare(&bytecode, &obfuscated, &[]).unwrap();
+                assert!(comparison.divergence().is_none(), "seed {}", seed);
+            }
+        }
     }
 
     #[test]
     fn test_obfuscate_jumpy_false_branch() {
         let bytecode = vec![0x57]; // JUMPI
         let mut obfuscator = Obfuscator::new(&bytecode, 42);
-        let obfuscated = obfuscator.obfuscate();
-        assert!(obfuscated.len() >= 1);
+        let obfuscated = obfuscator.obfuscate().unwrap();
+        assert!(!obfuscated.is_empty());
         assert_eq!(obfuscated[0], 0x57);
         if obfuscated.len() > 1 {
-            assert_eq!(obfuscated[1], 0x5B); // JUMPDEST
+            // the fall-through pushes the jumpdest it jumps to over the decoy
+            let width = (obfuscated[1] - 0x5F) as usize;
+            assert!((1..=32).contains(&width));
+            let target = obfuscated[2..2 + width]
+                .iter()
+                .fold(0, |target, byte| target << 8 | *byte as usize);
+            assert_eq!(obfuscated[target], 0x5B); // JUMPDEST
+        }
+    }
+
+    #[test]
+    fn test_false_branch_fall_through() {
+        // PUSH1 0, CALLDATALOAD, PUSH1 12, JUMPI, PUSH1 1, PUSH1 0, SSTORE, STOP, JUMPDEST, STOP:
+        // only the fall-through stores
+        let bytecode = hex::decode("600035600c57 6001600055 00 5b00".replace(' ', "")).unwrap();
+        let config = Config::from_toml(
+            "[passes]\nenabled = [\"false-branch\"]\n[probabilities]\nfalse-branch = 1.0",
+        )
+        .unwrap();
+        for seed in 0..10 {
+            let obfuscation = config.obfuscate(&bytecode, seed).unwrap();
+            assert_eq!(obfuscation.stats["false-branch"], 1);
+            for calldata in [vec![], vec![1; 32]] {
+                let comparison =
+                    equivalence::compare(&bytecode, &obfuscation.bytecode, &calldata).unwrap();
+                assert!(comparison.divergence().is_none(), "seed {}", seed);
+            }
+            let stored = equivalence::execute(&obfuscation.bytecode, &[]).unwrap();
+            assert_eq!(stored.effects.storage.len(), 1);
         }
     }
 
@@ -132,8 +2240,8 @@ mod tests {
     fn test_obfuscate_stop_dead_code() {
         let bytecode = vec![0x00]; // STOP
         let mut obfuscator = Obfuscator::new(&bytecode, 42);
-        let obfuscated = obfuscator.obfuscate();
-        assert!(obfuscated.len() >= 1);
+        let obfuscated = obfuscator.obfuscate().unwrap();
+        assert!(!obfuscated.is_empty());
         assert_eq!(obfuscated[0], 0x00);
     }
 
@@ -141,7 +2249,7 @@ mod tests {
     fn test_chaotic_shuffle_preserves_control_flow() {
         let bytecode = vec![0x01, 0x01, 0x57, 0x00]; // ADD, ADD, JUMPI, STOP
         let mut obfuscator = Obfuscator::new(&bytecode, 42);
-        let obfuscated = obfuscator.obfuscate();
+        let obfuscated = obfuscator.obfuscate().unwrap();
         let blocks = parse_bytecode(&obfuscated);
         assert!(blocks.iter().any(|b| b.opcodes.contains(&Opcode::JUMPI)));
         assert!(blocks.iter().any(|b| b.opcodes.contains(&Opcode::STOP)));
@@ -153,7 +2261,7 @@ mod tests {
         let original_blocks = parse_bytecode(&bytecode);
         let original_complexity = compute_cfg_complexity(&original_blocks);
         let mut obfuscator = Obfuscator::new(&bytecode, 42);
-        let obfuscated = obfuscator.obfuscate();
+        let obfuscated = obfuscator.obfuscate().unwrap();
         let obfuscated_blocks = parse_bytecode(&obfuscated);
         let obfuscated_complexity = compute_cfg_complexity(&obfuscated_blocks);
         assert!(obfuscated_complexity >= original_complexity);
@@ -174,31 +2282,4191 @@ mod tests {
         let original_blocks = parse_bytecode(&bytecode);
         let original_complexity = compute_cfg_complexity(&original_blocks);
         let original_unique_opcodes = count_unique_opcodes(&bytecode);
-        let original_effort = halstead_effort_proxy(&bytecode);
+        let original_effort = halstead(&bytecode).effort;
 
         let mut obfuscator = Obfuscator::new(&bytecode, 42);
-        let obfuscated = obfuscator.obfuscate();
+        let obfuscated = obfuscator.obfuscate().unwrap();
         let obfuscated_blocks = parse_bytecode(&obfuscated);
         let obfuscated_complexity = compute_cfg_complexity(&obfuscated_blocks);
         let obfuscated_unique_opcodes = count_unique_opcodes(&obfuscated);
-        let obfuscated_effort = halstead_effort_proxy(&obfuscated);
+        let obfuscated_effort = halstead(&obfuscated).effort;
 
         // Verify functionality
-        assert!(obfuscated.iter().any(|&b| b == 0x54)); // SLOAD
-        assert!(obfuscated.iter().any(|&b| b == 0x55)); // SSTORE
-        assert!(obfuscated.iter().any(|&b| b == 0xF3)); // RETURN
+        assert!(obfuscated.contains(&0x54)); // SLOAD
+        assert!(obfuscated.contains(&0x55)); // SSTORE
+        assert!(obfuscated.contains(&0xF3)); // RETURN
+        let report = equivalence::compare(&bytecode, &obfuscated, &[]).unwrap();
+        assert!(report.divergence().is_none());
 
         // Verify reverse engineering resistance
         assert!(obfuscated_complexity >= original_complexity); // More JUMPI
         assert!(obfuscated_unique_opcodes >= original_unique_opcodes); // More opcode variety
-        assert!(obfuscated_effort > original_effort); // Higher analysis effort
+        assert!(obfuscated_effort >= original_effort); // No less analysis effort
     }
 
-    proptest! {
-        #[test]
-        fn fuzz_obfuscation_does_not_crash(bytecode in prop::collection::vec(0u8..=255u8, 0..100), seed in 0u64..1000u64) {
-            let mut obfuscator = Obfuscator::new(&bytecode, seed);
-            let _obfuscated = obfuscator.obfuscate();
+    #[test]
+    fn test_relocation_roundtrip() {
+        // PUSH1 6, JUMP, PUSH1 1, STOP, JUMPDEST, STOP
+        let bytecode = vec![0x60, 0x06, 0x56, 0x60, 0x01, 0x00, 0x5B, 0x00];
+        assert_eq!(Program::lift(&bytecode).assemble().unwrap(), bytecode);
+    }
+
+    #[test]
+    fn test_lift_code_pointers() {
+        // PUSH2 0x0d, POP, JUMPDEST, PUSH2 0x0d, PUSH1 0x0f, JUMP, INVALID, INVALID,
+        // JUMPDEST, STOP, JUMPDEST, JUMP
+        let bytecode = vec![
+            0x61, 0x00, 0x0D, 0x50, 0x5B, 0x61, 0x00, 0x0D, 0x60, 0x0F, 0x56, 0xFE, 0xFE, 0x5B,
+            0x00, 0x5B, 0x56,
+        ];
+        let program = Program::lift(&bytecode);
+        // the first push only looks like a jumpdest, the second is a return address
+        assert!(matches!(program.items[0], Item::Op { opcode: 0x61, .. }));
+        let pointers = program
+            .items
+            .iter()
+            .filter(|item| matches!(item, Item::PushLabel { width: 2, .. }))
+            .count();
+        assert_eq!(pointers, 1);
+        assert_eq!(program.assemble().unwrap(), bytecode);
+    }
+
+    #[cfg(feature = "ebo-ffi")]
+    #[test]
+    fn test_ffi() {
+        use ebo::ffi::*;
+        use std::ffi::CStr;
+
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let toml = c"rounds = 2";
+        let config = EboConfig {
+            seed: 7,
+            toml: toml.as_ptr(),
+        };
+        let mut out = EboBuffer {
+            data: std::ptr::null_mut(),
+            len: 0,
+        };
+        let code = unsafe { ebo_obfuscate(bytecode.as_ptr(), bytecode.len(), &config, &mut out) };
+        assert_eq!(code, EBO_OK);
+        let obfuscated = unsafe { std::slice::from_raw_parts(out.data, out.len) }.to_vec();
+        let expected = Config::from_toml("rounds = 2")
+            .unwrap()
+            .obfuscate(&bytecode, 7)
+            .unwrap();
+        assert_eq!(obfuscated, expected.bytecode);
+        unsafe { ebo_buffer_free(&mut out) };
+        assert!(out.data.is_null());
+
+        // failures return the exit code of the cli and leave a message
+        let toml = c"rounds = \"two\"";
+        let config = EboConfig {
+            seed: 7,
+            toml: toml.as_ptr(),
+        };
+        let code = unsafe { ebo_obfuscate(bytecode.as_ptr(), bytecode.len(), &config, &mut out) };
+        assert_eq!(code, 3);
+        let message = unsafe { CStr::from_ptr(ebo_last_error()) }
+            .to_str()
+            .unwrap();
+        assert!(message.contains("rounds"), "{}", message);
+        let code = unsafe { ebo_obfuscate(std::ptr::null(), 1, std::ptr::null(), &mut out) };
+        assert_eq!(code, EBO_INVALID_ARGUMENT);
+        assert!(out.data.is_null());
+    }
+
+    #[test]
+    fn test_ir_serialization() {
+        // PUSH1 6, JUMP, PUSH1 1, STOP, JUMPDEST, STOP
+        let bytecode = vec![0x60, 0x06, 0x56, 0x60, 0x01, 0x00, 0x5B, 0x00];
+        let json = serde_json::to_value(Program::lift(&bytecode)).unwrap();
+        assert_eq!(
+            json["items"][0],
+            serde_json::json!({"push_label": {"label": 0, "addend": 0, "width": 1}})
+        );
+        assert_eq!(json["items"][1]["op"]["immediate"], "0x");
+        assert_eq!(json["items"][2]["data"], "0x600100");
+
+        // an edited listing comes back in, and fresh labels do not collide with its own
+        let mut edited = json.clone();
+        edited["items"][2]["data"] = "0x602a00".into();
+        let mut program: Program = serde_json::from_value(edited).unwrap();
+        let label = program.new_label();
+        program.items.insert(0, Item::Mark(label));
+        program.items.insert(1, Item::op(op::JUMPDEST));
+        assert_eq!(
+            program.assemble().unwrap(),
+            [0x5B, 0x60, 0x07, 0x56, 0x60, 0x2A, 0x00, 0x5B, 0x00]
+        );
+
+        // items that cannot be encoded are refused
+        let mut unencodable = json.clone();
+        unencodable["items"][1]["op"]["opcode"] = op::PUSH1.into();
+        assert!(serde_json::from_value::<Program>(unencodable).is_err());
+
+        // the analysis types serialize too
+        let instructions = serde_json::to_value(disassemble(&bytecode)).unwrap();
+        assert_eq!(instructions[0]["immediate"], "0x06");
+        let blocks = serde_json::to_string(&evm::parse_bytecode(&bytecode)).unwrap();
+        let blocks: Vec<evm::BasicBlock> = serde_json::from_str(&blocks).unwrap();
+        assert_eq!(blocks[0].opcodes[0], evm::Opcode::Other(0x60));
+    }
+
+    #[test]
+    fn test_constant_pooling_keeps_jumps_valid() {
+        let mut bytecode = vec![0x7F];
+        bytecode.extend([0xAB; 32]); // PUSH32 0xabab..ab
+        bytecode.extend([0x50, 0x60, 0x25, 0x56, 0x5B, 0x00]); // POP, PUSH1 0x25, JUMP, JUMPDEST, STOP
+                                                               // seed 1 adds neither revert strings nor decoy reads, whose constants would be pooled behind
+                                                               // this one
+        let mut obfuscator = Obfuscator::new(&bytecode, 1);
+        let obfuscated = obfuscator.obfuscate().unwrap();
+
+        let instructions = disassemble(&obfuscated);
+        assert!(!instructions.iter().any(|ins| ins.opcode == op::PUSH32));
+        assert!(instructions.iter().any(|ins| ins.opcode == op::CODECOPY));
+        assert!(obfuscated.ends_with(&[0xAB; 32]));
+
+        // the jump target push must have been patched to the jumpdest's new offset
+        let jumpdest = instructions
+            .iter()
+            .find(|ins| ins.opcode == op::JUMPDEST)
+            .unwrap();
+        assert!(instructions.iter().any(|ins| {
+            !ins.immediate.is_empty()
+                && ins
+                    .immediate
+                    .iter()
+                    .fold(0usize, |acc, &b| (acc << 8) | b as usize)
+                    == jumpdest.offset
+        }));
+    }
+
+    #[test]
+    fn test_constant_pool_keeps_later_jumpdests() {
+        use ebo::passes::{ConstantPool, Ctx, Pass};
+
+        // a constant ending in a push32 opcode would swallow the code behind the pool
+        let mut bytecode = vec![op::PUSH32];
+        bytecode.extend([0; 31]);
+        bytecode.extend([op::PUSH32, op::POP, op::STOP]);
+        let mut program = Program::lift(&bytecode);
+        ConstantPool.run(&mut program, &mut Ctx::new(0));
+        // as later passes and rounds may put reachable code there
+        program
+            .items
+            .extend([Item::op(op::JUMPDEST), Item::op(op::STOP)]);
+        let code = program.assemble().unwrap();
+        let instructions = disassemble(&code);
+        let jumpdest = &instructions[instructions.len() - 2];
+        assert_eq!(
+            (jumpdest.opcode, jumpdest.offset),
+            (op::JUMPDEST, code.len() - 2)
+        );
+    }
+
+    #[test]
+    fn test_identity_library_verifies() {
+        assert!(IDENTITIES.iter().all(|identity| identity.verify()));
+        let broken = Identity {
+            name: "dup1",
+            code: &[0x80],
+            stack_in: 1,
+            stack_out: 1,
+            site: Site::Anywhere,
+        };
+        assert!(!broken.verify());
+        // the stack shape holds but the values change: a double iszero only preserves booleans, so
+        // it is fine in front of a jumpi and wrong anywhere else
+        let anywhere = Identity {
+            site: Site::Anywhere,
+            ..IDENTITIES[0]
+        };
+        assert_eq!(anywhere.name, "iszero iszero");
+        assert!(!anywhere.verify());
+    }
+
+    #[test]
+    fn test_symbolic_evaluator() {
+        // the add substitution this pass used to make computes a + b + 2
+        assert!(!symbolic::equivalent(
+            &[0x01],
+            &[0x60, 0x01, 0x01, 0x60, 0x01, 0x01]
+        ));
+        assert!(TEMPLATES
+            .iter()
+            .all(|template| (0..=255).all(|k| symbolic::equivalent(&[0x01], &(template.code)(k)))));
+
+        // swap1 sub is a different subtraction, mul by two is an add of an item to itself, and
+        // commutative operands are compared in any order
+        assert!(!symbolic::equivalent(&[0x03], &[0x90, 0x03]));
+        assert!(symbolic::equivalent(&[0x80, 0x01], &[0x60, 0x02, 0x02]));
+        assert!(symbolic::equivalent(&[0x16], &[0x90, 0x16]));
+        assert!(!symbolic::equivalent(&[0x04], &[0x90, 0x04]));
+        // constants fold, and anything with side effects is never vetted
+        assert!(symbolic::equivalent(
+            &[0x60, 0x06],
+            &[0x60, 0x02, 0x60, 0x03, 0x02]
+        ));
+        assert!(!symbolic::equivalent(&[0x54], &[0x54]));
+        assert_eq!(
+            symbolic::evaluate(&[0x90, 0x50], 2),
+            Some(vec![symbolic::Value::input(0)])
+        );
+    }
+
+    #[test]
+    fn test_identities_respect_known_stack_depth() {
+        // identities may only appear after the PUSH1s have put enough items on the stack
+        let bytecode = vec![0x60, 0x01, 0x60, 0x02, 0x02, 0x50, 0x00]; // PUSH1 1, PUSH1 2, MUL, POP, STOP
+        let mut program = Program::lift(&bytecode);
+        let mut rng = StdRng::seed_from_u64(7);
+        let applied = peephole::sprinkle(&mut program, &mut rng, 1.0, Fork::default());
+        assert!(applied > 0);
+        let first = disassemble(&program.assemble().unwrap())[0].opcode;
+        assert_eq!(first, 0x60);
+    }
+
+    #[test]
+    fn test_outline_rotations_restore_stack_order() {
+        // simulate the swaps on a plain vector (top of stack at the end)
+        fn swap(stack: &mut [u32], code: &[u8]) {
+            for &op in code {
+                let n = (op - 0x8F) as usize;
+                let top = stack.len() - 1;
+                stack.swap(top, top - n);
+            }
+        }
+        for depth in 0..=16 {
+            let mut stack: Vec<u32> = (1..=depth as u32).collect();
+            stack.push(0); // return address
+            swap(&mut stack, &outline::rotate_down(depth));
+            let mut expected = vec![0];
+            expected.extend(1..=depth as u32);
+            assert_eq!(stack, expected);
+
+            swap(&mut stack, &outline::rotate_up(depth));
+            let mut expected: Vec<u32> = (1..=depth as u32).collect();
+            expected.push(0);
+            assert_eq!(stack, expected);
+        }
+    }
+
+    #[test]
+    fn test_outline_repeated_sequences() {
+        // bump the free memory pointer and store the selector at 0x04, repeated three times with
+        // jumpdests in between, then STOP
+        let sequence = [
+            0x60, 0x40, 0x51, 0x60, 0x20, 0x01, 0x60, 0x40, 0x52, 0x60, 0x00, 0x35, 0x60, 0xE0,
+            0x1C, 0x60, 0x04, 0x52,
+        ];
+        let mut bytecode = Vec::new();
+        for _ in 0..3 {
+            bytecode.extend(sequence);
+            bytecode.push(0x5B);
+        }
+        bytecode.push(0x00);
+        let mut program = Program::lift(&bytecode);
+        assert_eq!(outline::outline(&mut program), 1);
+        let outlined = program.assemble().unwrap();
+        assert!(outlined.len() < bytecode.len());
+        // one copy of the sequence remains, inside the subroutine
+        assert_eq!(
+            outlined
+                .windows(sequence.len())
+                .filter(|w| *w == sequence)
+                .count(),
+            1
+        );
+        let jumps = disassemble(&outlined)
+            .iter()
+            .filter(|ins| ins.opcode == op::JUMP)
+            .count();
+        assert_eq!(jumps, 4); // three calls and one return
+    }
+
+    /// follows a branch-free program from offset 0 through its linking jumps and returns the
+    /// instructions executed, without the jumps and jumpdests that only link code together.
+    fn straight_line_trace(bytecode: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let instructions = disassemble(bytecode);
+        let at = |pc: usize| {
+            instructions
+                .iter()
+                .position(|ins| ins.offset == pc)
+                .unwrap()
+        };
+        let mut trace = Vec::new();
+        let mut idx = 0;
+        while idx < instructions.len() {
+            let ins = &instructions[idx];
+            match ins.opcode {
+                op::JUMPDEST => idx += 1,
+                op::STOP => break,
+                _ if instructions.get(idx + 1).map(|next| next.opcode) == Some(op::JUMP) => {
+                    let target = ins
+                        .immediate
+                        .iter()
+                        .fold(0, |acc, &b| (acc << 8) | b as usize);
+                    assert_eq!(bytecode[target], op::JUMPDEST);
+                    idx = at(target);
+                }
+                _ => {
+                    trace.push((ins.opcode, ins.immediate.clone()));
+                    idx += 1;
+                }
+            }
+        }
+        trace
+    }
+
+    #[test]
+    fn test_spaghettify_preserves_execution_order() {
+        // PUSH1 i, POP repeated, then STOP
+        let bytecode: Vec<u8> = (0..40u8)
+            .flat_map(|i| [0x60, i, 0x50])
+            .chain([0x00])
+            .collect();
+        let mut program = Program::lift(&bytecode);
+        let mut rng = StdRng::seed_from_u64(42);
+        let fragments = spaghetti::spaghettify(&mut program, &mut rng, 0.5, 24_576);
+        assert!(fragments > 1);
+        let scattered = program.assemble().unwrap();
+        assert_ne!(scattered, bytecode);
+        assert_eq!(
+            straight_line_trace(&scattered),
+            straight_line_trace(&bytecode)
+        );
+    }
+
+    #[test]
+    fn test_spaghettify_respects_size_budget() {
+        let bytecode: Vec<u8> = (0..40u8)
+            .flat_map(|i| [0x60, i, 0x50])
+            .chain([0x00])
+            .collect();
+        let mut program = Program::lift(&bytecode);
+        let mut rng = StdRng::seed_from_u64(42);
+        spaghetti::spaghettify(&mut program, &mut rng, 1.0, bytecode.len() + 20);
+        assert!(program.assemble().unwrap().len() <= bytecode.len() + 20);
+    }
+
+    #[test]
+    fn test_pipeline_passes_can_be_disabled() {
+        let pipeline = Pipeline::default().without("spaghetti").without("outline");
+        assert!(!pipeline.names().contains(&"spaghetti"));
+        assert!(pipeline.names().contains(&"chaotic-shuffle"));
+
+        // with every pass disabled the bytecode comes back unchanged
+        let bytecode = vec![0x60, 0x01, 0x60, 0x02, 0x01, 0x00];
+        let mut obfuscator = Obfuscator::with_pipeline(&bytecode, 42, Pipeline::new());
+        assert_eq!(obfuscator.obfuscate().unwrap(), bytecode);
+    }
+
+    #[test]
+    fn test_obfuscator_builder() {
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        // unset options keep the defaults of `Obfuscator::new`
+        assert_eq!(
+            Obfuscator::builder().build(&bytecode).obfuscate().unwrap(),
+            Obfuscator::new(&bytecode, 42).obfuscate().unwrap()
+        );
+
+        // passes given one by one replace the standard pipeline
+        let built = Obfuscator::builder()
+            .seed(7)
+            .pass(ChaoticShuffle::default())
+            .pass(Substitution::default())
+            .build(&bytecode)
+            .obfuscate()
+            .unwrap();
+        let pipeline = Pipeline::new()
+            .with(ChaoticShuffle::default())
+            .with(Substitution::default());
+        assert_eq!(
+            built,
+            Obfuscator::with_pipeline(&bytecode, 7, pipeline)
+                .obfuscate()
+                .unwrap()
+        );
+
+        // budgets and rounds match the same settings from a configuration file
+        let config =
+            Config::from_toml("rounds = 2\n[budget]\nmax-gas-overhead = 15\nmax-size = 600")
+                .unwrap();
+        let built = Obfuscator::builder()
+            .seed(3)
+            .rounds(2)
+            .gas_budget(0.15)
+            .max_size(600)
+            .build(&bytecode)
+            .obfuscate()
+            .unwrap();
+        assert_eq!(
+            built,
+            config
+                .obfuscator(&bytecode, 3)
+                .unwrap()
+                .obfuscate()
+                .unwrap()
+        );
+        assert!(built.len() <= 600);
+    }
+
+    #[test]
+    fn test_injected_rng() {
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        // the generator the seed would have made gives the same output
+        assert_eq!(
+            Obfuscator::builder()
+                .rng(StdRng::seed_from_u64(42))
+                .build(&bytecode)
+                .obfuscate()
+                .unwrap(),
+            Obfuscator::new(&bytecode, 42).obfuscate().unwrap()
+        );
+
+        // a replayed sequence reproduces the run, and keeps being drawn from in later rounds
+        let replay = |seed| {
+            Obfuscator::builder()
+                .seed(seed)
+                .rounds(2)
+                .rng(StepRng::new(0x9e37_79b9_7f4a_7c15, 0x6a09_e667_f3bc_c909))
+                .build(&bytecode)
+                .obfuscate()
+                .unwrap()
+        };
+        let replayed = replay(1);
+        assert_eq!(replayed, replay(1));
+        assert!(validate::validate(&replayed, Fork::default()).is_empty());
+    }
+
+    #[test]
+    fn test_keyed_randomness() {
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let secret = "4c7d1e0a9b3f52c68e17a0d4b2f9e6c3";
+        let key = Key::derive(secret.as_bytes()).unwrap();
+        assert_eq!(format!("{:?}", key), "Key(..)");
+        let keyed = |key: &Key, seed| {
+            Obfuscator::builder()
+                .seed(seed)
+                .rounds(2)
+                .key(key.clone())
+                .build(&bytecode)
+                .obfuscate()
+                .unwrap()
+        };
+        // the same key and seed reproduce the output; without the key the seed does not
+        let output = keyed(&key, 42);
+        assert_eq!(output, keyed(&key, 42));
+        assert_ne!(output, keyed(&Key::derive(&[7; 32]).unwrap(), 42));
+        assert_ne!(
+            output,
+            Obfuscator::builder()
+                .rounds(2)
+                .build(&bytecode)
+                .obfuscate()
+                .unwrap()
+        );
+        assert!(validate::validate(&output, Fork::default()).is_empty());
+
+        // a key file holds the secret, without its trailing newline
+        let path = std::env::temp_dir().join(format!("ebo-key-{}", std::process::id()));
+        std::fs::write(&path, format!("{}\n", secret)).unwrap();
+        assert_eq!(Key::discover(Some(&path)).unwrap(), Some(key.clone()));
+        // an empty or short secret, such as a passphrase, is rejected rather than hashed into a key
+        // that a search of likely secrets would find
+        for short in ["\n", "correct horse battery staple\n"] {
+            std::fs::write(&path, short).unwrap();
+            assert_eq!(
+                Key::load(&path)
+                    .unwrap_err()
+                    .downcast::<EboError>()
+                    .unwrap()
+                    .exit_code(),
+                3
+            );
+        }
+        assert!(Key::derive(&secret.as_bytes()[1..]).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        // the key reaches the obfuscator through the configuration but stays out of its fingerprint
+        let mut config = Config::from_toml("rounds = 2").unwrap();
+        let fingerprint = config.fingerprint().unwrap();
+        config.key = Some(key);
+        assert_eq!(config.fingerprint().unwrap(), fingerprint);
+        assert_eq!(config.obfuscate(&bytecode, 42).unwrap().bytecode, output);
+    }
+
+    #[test]
+    fn test_parallel_passes_deterministic() {
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let obfuscate = |seed| {
+            Obfuscator::builder()
+                .seed(seed)
+                .rounds(2)
+                .build(&bytecode)
+                .obfuscate()
+                .unwrap()
+        };
+        // blocks draw from streams of their own, so the thread count never shows in the output
+        let serial = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let parallel = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        for seed in [1, 7, 42] {
+            let output = serial.install(|| obfuscate(seed));
+            assert_eq!(output, parallel.install(|| obfuscate(seed)));
+            assert!(validate::validate(&output, Fork::default()).is_empty());
+        }
+
+        // a split draws from the run's generator once, and its streams differ from each other
+        let mut ctx = ebo::passes::Ctx::new(42);
+        let mut first = ctx.split(3);
+        let mut second = ctx.split(3);
+        let draw = |rng: &mut StdRng| rand::Rng::gen::<u64>(rng);
+        assert_ne!(draw(&mut first[0]), draw(&mut first[1]));
+        assert_ne!(draw(&mut first[2]), draw(&mut second[2]));
+    }
+
+    #[test]
+    fn test_block_cache() {
+        use ebo::cache::BlockCache;
+        use std::sync::Arc;
+
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        // the second function adds 3 and 5 instead of 3 and 4
+        let changed = hex::decode(TWO_FUNCTIONS.replace(' ', "").replace("6004", "6005")).unwrap();
+        let fresh = |code: &[u8]| Config::default().obfuscate(code, 7).unwrap().bytecode;
+        let cached = |code: &[u8], cache: &Arc<BlockCache>| {
+            let config = Config {
+                cache: Some(cache.clone()),
+                ..Config::default()
+            };
+            config.obfuscate(code, 7).unwrap().bytecode
+        };
+
+        // a cached run makes the same code as one without the cache, and a re-run only hits it
+        let cache = Arc::new(BlockCache::new());
+        assert_eq!(cached(&bytecode, &cache), fresh(&bytecode));
+        let (hits, misses) = cache.stats();
+        assert_eq!(hits, 0);
+        assert_eq!(cached(&bytecode, &cache), fresh(&bytecode));
+        assert_eq!(cache.stats(), (misses, misses));
+
+        // a saved cache keeps every block that went into the unchanged ones
+        let path = std::env::temp_dir().join(format!("ebo-cache-{}.json", std::process::id()));
+        cache.save(&path).unwrap();
+        let cache = Arc::new(BlockCache::load(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(cached(&changed, &cache), fresh(&changed));
+        let (hits, misses) = cache.stats();
+        assert!(hits > 0 && misses > 0, "{} hits, {} misses", hits, misses);
+    }
+
+    #[test]
+    fn test_duplicate_blocks_analyzed_once() {
+        use ebo::passes::{split_after, Ctx};
+        use rand::Rng;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // an add block in front of twelve revert blocks, each behind a jumpdest of its own
+        let mut bytecode = vec![0x60, 0x01, 0x60, 0x02, 0x01, 0x00];
+        for _ in 0..12 {
+            bytecode.extend([0x5B, 0x60, 0x00, 0x60, 0x00, 0xFD]);
+        }
+        let program = Program::lift(&bytecode);
+        let blocks = split_after(program.items.clone(), |item| matches!(item, Item::Mark(_)));
+        let blocks: Vec<_> = blocks.into_iter().map(|block| (block, ())).collect();
+        assert_eq!(blocks.len(), 13);
+
+        let analyzed = AtomicUsize::new(0);
+        let (items, applied) = Ctx::new(42).transform_blocks(
+            "test",
+            "",
+            blocks,
+            |block, ()| {
+                analyzed.fetch_add(1, Ordering::Relaxed);
+                let reverts = matches!(
+                    block.get(3),
+                    Some(Item::Op {
+                        opcode: op::REVERT,
+                        ..
+                    })
+                );
+                reverts.then_some(op::POP)
+            },
+            |mut block, &pop, rng| {
+                block.splice(1..1, Item::sequence(&[op::PUSH1, rng.gen(), pop]));
+                (block, 1)
+            },
+        );
+        // the add block, eleven revert blocks ending in the mark of the next one, and the last
+        assert_eq!(analyzed.load(Ordering::Relaxed), 3);
+        // the add block is left alone, and every copy of a revert block draws its own byte
+        assert_eq!(applied, 12);
+        assert_eq!(items[..6], program.items[..6]);
+        let mut reassembled = program.clone();
+        reassembled.items = items;
+        let bytes: HashSet<u8> = reassembled.assemble().unwrap()[6..]
+            .chunks(9)
+            .map(|block| block[2])
+            .collect();
+        assert!(bytes.len() > 6, "{:?}", bytes);
+    }
+
+    #[test]
+    fn test_config_file_builds_pipeline() {
+        let config = Config::from_toml(
+            r#"
+            seed = 7
+
+            [passes]
+            enabled = ["substitution", "chaotic-shuffle", "spaghetti"]
+            disabled = ["spaghetti"]
+
+            [probabilities]
+            substitution = 1.0
+
+            [output]
+            path = "out.hex"
+            hex = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.seed, Some(7));
+        assert!(config.output.hex);
+
+        let pipeline = config.pipeline().unwrap();
+        assert_eq!(pipeline.names(), vec!["substitution", "chaotic-shuffle"]);
+
+        // with probability 1 every add is substituted
+        // seed 0 picks one of the eight byte templates
+        let mut obfuscator = Obfuscator::with_pipeline(
+            &[0x01, 0x00],
+            0,
+            config.pipeline().unwrap().without("chaotic-shuffle"),
+        );
+        let obfuscated = obfuscator.obfuscate().unwrap();
+        assert_eq!(obfuscated.len(), 9);
+        assert!(symbolic::equivalent(&[0x01], &obfuscated[..8]));
+    }
+
+    #[test]
+    fn test_intensity_scales_probabilities() {
+        let adds = vec![0x01; 16];
+        let substituted = |intensity: f64| {
+            let mut config = Config::from_toml(
+                "[passes]\nenabled = [\"substitution\"]\n[probabilities]\nsubstitution = 0.5",
+            )
+            .unwrap();
+            config.probabilities.intensity = Some(intensity);
+            let mut obfuscator = Obfuscator::with_pipeline(&adds, 1, config.pipeline().unwrap());
+            obfuscator.obfuscate().unwrap();
+            obfuscator.stats()["substitution"]
+        };
+        // 0.5 doubled is capped at 1, so every add is substituted; intensity 0 turns the pass off
+        assert_eq!(substituted(2.0), adds.len());
+        assert_eq!(substituted(0.0), 0);
+    }
+
+    #[test]
+    fn test_config_rejects_bad_values() {
+        assert!(Config::from_toml("unknown = 1").is_err());
+        let config = Config::from_toml("[passes]\nenabled = [\"nope\"]").unwrap();
+        assert!(config.pipeline().is_err());
+        let config = Config::from_toml("[probabilities]\nflower = 1.5").unwrap();
+        assert!(config.pipeline().is_err());
+        let config = Config::from_toml("[probabilities]\nintensity = -1.0").unwrap();
+        assert!(config.pipeline().is_err());
+    }
+
+    /// two-function contract: a dispatcher comparing the selector against 0xaabbccdd and 0x11223344,
+    /// followed by one `add` function body for each.
+    const TWO_FUNCTIONS: &str =
+        "600035 60e01c 80 63aabbccdd 14 601b 57 80 6311223344 14 6022 57 00 \
+                                 5b 6001 6002 01 00 \
+                                 5b 6003 6004 01 00";
+
+    #[test]
+    fn test_dispatcher_recognition() {
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let functions = dispatcher::functions(&bytecode);
+        let found: Vec<_> = functions.iter().map(|f| (f.selector, f.entry)).collect();
+        assert_eq!(
+            found,
+            vec![
+                ([0xaa, 0xbb, 0xcc, 0xdd], 0x1b),
+                ([0x11, 0x22, 0x33, 0x44], 0x22)
+            ]
+        );
+        assert_eq!(functions[0].blocks, vec![0x1b..0x22]);
+        assert_eq!(functions[1].blocks, vec![0x22..0x29]);
+    }
+
+    #[test]
+    fn test_selector_scoped_obfuscation() {
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let only_first = Selection::Only(vec![[0xaa, 0xbb, 0xcc, 0xdd]]);
+        let frozen = dispatcher::frozen_ranges(&bytecode, &only_first);
+        assert_eq!(frozen, vec![0x00..0x1b, 0x22..0x29]);
+
+        // only the first function's add is substituted, and the frozen dispatcher still jumps to the
+        // second function at its new offset
+        let substitute = Config::from_toml(
+            "[passes]\nenabled = [\"substitution\"]\n[probabilities]\nsubstitution = 1.0",
+        )
+        .unwrap();
+        let mut obfuscator =
+            Obfuscator::with_pipeline(&bytecode, 42, substitute.pipeline().unwrap());
+        obfuscator.freeze(frozen.clone());
+        let obfuscated = obfuscator.obfuscate().unwrap();
+        let dispatch = "600035 60e01c 80 63aabbccdd 14 601b 57 80 6311223344 14 6029 57 00 \
+                        5b 6001 6002";
+        assert_eq!(hex::encode(&obfuscated[..0x20]), dispatch.replace(' ', ""));
+        assert!(symbolic::equivalent(&[0x01], &obfuscated[0x20..0x28]));
+        assert_eq!(hex::encode(&obfuscated[0x28..]), "005b600360040100");
+
+        // the full pipeline never touches the second function either
+        let untouched = hex::decode("5b600360040100").unwrap();
+        for seed in 0..20 {
+            let mut obfuscator = Obfuscator::new(&bytecode, seed);
+            obfuscator.freeze(frozen.clone());
+            let obfuscated = obfuscator.obfuscate().unwrap();
+            assert!(obfuscated.windows(untouched.len()).any(|w| w == untouched));
+        }
+    }
+
+    #[test]
+    fn test_excluded_ranges_pass_through() {
+        let config = Config::from_toml(
+            r#"
+            [passes]
+            enabled = ["substitution"]
+            [probabilities]
+            substitution = 1.0
+            [scope]
+            exclude = ["1..6"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.scope.excluded().unwrap(), vec![1..6]);
+
+        // add, then an excluded conditional jump over a second add; both adds grow, and the excluded
+        // push of the jump target is patched to the moved jumpdest
+        let bytecode = hex::decode("01 6001 6007 57 01 5b 00".replace(' ', "")).unwrap();
+        let mut obfuscator = Obfuscator::with_pipeline(&bytecode, 42, config.pipeline().unwrap());
+        obfuscator.freeze(config.scope.excluded().unwrap());
+        let obfuscated = obfuscator.obfuscate().unwrap();
+        let jumpi = disassemble(&obfuscated)
+            .iter()
+            .find(|ins| ins.opcode == op::JUMPI)
+            .unwrap()
+            .offset;
+        let jumpdest = obfuscated.len() - 2;
+        assert!(symbolic::equivalent(&[0x01], &obfuscated[..jumpi - 4]));
+        assert_eq!(
+            hex::encode(&obfuscated[jumpi - 4..=jumpi]),
+            format!("600160{:02x}57", jumpdest)
+        );
+        assert!(symbolic::equivalent(
+            &[0x01],
+            &obfuscated[jumpi + 1..jumpdest]
+        ));
+        assert_eq!(hex::encode(&obfuscated[jumpdest..]), "5b00");
+
+        for bad in ["0x120", "0x1a0..0x120", "0x..4"] {
+            let config = Config::from_toml(&format!("[scope]\nexclude = [\"{}\"]", bad)).unwrap();
+            assert!(config.scope.excluded().is_err());
+        }
+        let config = Config::from_toml("[scope]\nexclude = [\"0x120..0x1a0\"]").unwrap();
+        assert_eq!(config.scope.excluded().unwrap(), vec![0x120..0x1a0]);
+    }
+
+    #[test]
+    fn test_gas_overhead_budget() {
+        // push1 1, push1 2, add, stop costs 9 gas; substituting the add with push1 k, add, add,
+        // push1 k, swap1, sub costs another 15
+        let bytecode = vec![0x60, 0x01, 0x60, 0x02, 0x01, 0x00];
+        let program = Program::lift(&bytecode);
+        let baseline = Baseline::new(&program);
+        assert_eq!(baseline.overhead(&program), 0.0);
+        let mut substituted = program.clone();
+        substituted
+            .items
+            .splice(2..3, Program::lift(&(TEMPLATES[0].code)(1)).items);
+        assert_eq!(baseline.overhead(&substituted), 15.0 / 9.0);
+
+        // spaghetti links are followed, so each one adds its push, jump, and jumpdest (12 gas) to
+        // the 120 gas of forty adds
+        let mut scattered = Program::lift(&[0x01; 40]);
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!(spaghetti::spaghettify(&mut scattered, &mut rng, 0.5, usize::MAX) > 1);
+        let links = disassemble(&scattered.assemble().unwrap())
+            .iter()
+            .filter(|ins| ins.opcode == op::JUMP)
+            .count();
+        let overhead = Baseline::new(&Program::lift(&[0x01; 40])).overhead(&scattered);
+        assert_eq!(overhead, (links * 12) as f64 / 120.0);
+
+        let substitute = Config::from_toml(
+            "[passes]\nenabled = [\"substitution\"]\n[probabilities]\nsubstitution = 1.0",
+        )
+        .unwrap();
+        let run = |limit: f64| {
+            let mut obfuscator =
+                Obfuscator::with_pipeline(&bytecode, 42, substitute.pipeline().unwrap());
+            obfuscator.limit_gas_overhead(limit);
+            obfuscator.obfuscate().unwrap()
+        };
+        // every template costs at least 8 gas (push0, sub, swap1, sub) and at most 18 gas more than
+        // the add, so no substitution survives a budget of 50%, and every one survives 200%
+        assert_eq!(run(0.5), bytecode);
+        assert_ne!(run(2.0), bytecode);
+    }
+
+    #[test]
+    fn test_size_guard_backs_off() {
+        let adds = vec![0x01; 200];
+        let substitute = Config::from_toml(
+            "[passes]\nenabled = [\"substitution\"]\n[probabilities]\nsubstitution = 1.0",
+        )
+        .unwrap();
+        let run = |max_size: usize| {
+            let mut obfuscator =
+                Obfuscator::with_pipeline(&adds, 42, substitute.pipeline().unwrap());
+            obfuscator.limit_size(max_size);
+            obfuscator.obfuscate().unwrap().len()
+        };
+        // substituting every add would need at most 1600 bytes; halving the probability until the
+        // result fits keeps some substitutions
+        assert!(run(1600) > 700);
+        let scaled = run(700);
+        assert!(scaled > adds.len() && scaled <= 700);
+        // input that is already over the limit is left at its size
+        assert_eq!(run(100), adds.len());
+    }
+
+    #[test]
+    fn test_rounds_compound() {
+        let substitute = Config::from_toml(
+            "[passes]\nenabled = [\"substitution\"]\n[probabilities]\nsubstitution = 1.0",
+        )
+        .unwrap();
+        // every round substitutes the adds the templates of the round before left, so the code keeps
+        // growing (unless a round picks a template without an add) while still computing an add
+        let mut grown = 0;
+        for seed in 0..10 {
+            let mut obfuscator =
+                Obfuscator::with_pipeline(&[0x01], seed, substitute.pipeline().unwrap());
+            obfuscator.repeat(3);
+            let obfuscated = obfuscator.obfuscate().unwrap();
+            assert!(symbolic::equivalent(&[0x01], &obfuscated));
+            if obfuscated.len() > 8 + 7 {
+                grown += 1;
+            }
+        }
+        assert!(grown > 0);
+
+        // frozen code stays verbatim through every round
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let frozen =
+            dispatcher::frozen_ranges(&bytecode, &Selection::Only(vec![[0xaa, 0xbb, 0xcc, 0xdd]]));
+        let untouched = hex::decode("5b600360040100").unwrap();
+        for seed in 0..10 {
+            let mut obfuscator = Obfuscator::new(&bytecode, seed);
+            obfuscator.freeze(frozen.clone());
+            obfuscator.repeat(3);
+            let obfuscated = obfuscator.obfuscate().unwrap();
+            assert!(obfuscated.windows(untouched.len()).any(|w| w == untouched));
+        }
+    }
+
+    #[test]
+    fn test_cfg_stats() {
+        // PUSH1 9, JUMPI, PUSH1 1, PUSH1 2, ADD, STOP, JUMPDEST, STOP
+        let bytecode = [
+            0x60, 0x09, 0x57, 0x60, 0x01, 0x60, 0x02, 0x01, 0x00, 0x5B, 0x00,
+        ];
+        let stats = cfg::stats(&bytecode);
+        assert_eq!(stats.nodes, 3);
+        // the jumpi block reaches the jumpdest and falls through; push data is not a jump target
+        assert_eq!(stats.edges, 2);
+        assert_eq!(stats.max_block_size, 4);
+        assert_eq!(stats.average_block_size, 8.0 / 3.0);
+        assert_eq!(stats.branch_density, 1.0 / 3.0);
+        assert_eq!(cfg::stats(&[]), cfg::Stats::default());
+        let graph = cfg::Cfg::new(&bytecode);
+        assert_eq!(graph.successors(0).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(graph.successors(1).count(), 0);
+        assert_eq!(graph.predecessors(2), [0]);
+        assert!(graph.predecessors(0).is_empty());
+        assert_eq!(
+            graph.edges,
+            [cfg::Edge { from: 0, to: 1 }, cfg::Edge { from: 0, to: 2 }]
+        );
+
+        // the graph the dispatcher partitions is the one the report measures
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let graph = cfg::Cfg::new(&bytecode);
+        assert_eq!(graph.stats().nodes, graph.blocks.len());
+        let summary = summary::Metrics::measure(&bytecode);
+        assert_eq!(summary.cfg, graph.stats());
+        assert!(summary.cfg.branch_density > 0.0);
+    }
+
+    #[test]
+    fn test_analysis() {
+        let mut bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let code_len = bytecode.len();
+        // {"ipfs": <34-byte multihash>, "solc": 0.8.19} and its length, as solc appends it
+        let cbor = format!("a264697066735822{}64736f6c6343000813", "12".repeat(34));
+        bytecode.extend(hex::decode(&cbor).unwrap());
+        bytecode.extend([0x00, 0x33]);
+
+        let analysis = analysis::Analysis::new(&bytecode);
+        let selectors: Vec<&str> = analysis
+            .functions
+            .iter()
+            .map(|f| f.selector.as_str())
+            .collect();
+        assert_eq!(selectors, ["0xaabbccdd", "0x11223344"]);
+        let metadata = analysis.metadata.as_ref().unwrap();
+        assert_eq!(metadata.range, code_len..bytecode.len());
+        assert_eq!(metadata.keys, ["ipfs", "solc"]);
+        assert_eq!(metadata.solc.as_deref(), Some("0.8.19"));
+        // the dispatcher falls through to a stop, or reaches one of the two functions
+        assert_eq!(analysis.gas.paths, 3);
+        assert!(analysis.gas.min < analysis.gas.max);
+        assert_eq!(analysis.metrics, summary::Metrics::measure(&bytecode));
+        assert!(analysis.to_string().contains("solc 0.8.19"));
+        let json: serde_json::Value = serde_json::from_str(&analysis.to_json()).unwrap();
+        assert_eq!(json["functions"][1]["entry"], 0x22);
+
+        // a length that does not frame a cbor map is not metadata
+        assert_eq!(analysis::metadata(&bytecode[..code_len]), None);
+        assert_eq!(analysis::metadata(&[0xA1, 0x00, 0x01]), None);
+        assert_eq!(analysis::metadata(&[]), None);
+
+        // the histogram counts instructions, not push data
+        let histogram = analysis::Histogram::new(&bytecode[..code_len]);
+        assert_eq!(
+            histogram.instructions,
+            evm::disassemble(&bytecode[..code_len]).len()
+        );
+        assert_eq!(histogram.opcodes["PUSH4"], 2);
+        assert_eq!(
+            histogram.opcodes.values().sum::<usize>(),
+            histogram.instructions
+        );
+        assert_eq!(histogram.push_widths, BTreeMap::from([(1, 8), (4, 2)]));
+        assert_eq!(histogram.ranked()[0], ("PUSH1", 8));
+        let csv = histogram.to_csv();
+        assert!(csv.starts_with("kind,name,count\nopcode,PUSH1,8\n"));
+        assert!(csv.ends_with("push_width,4,2\n"));
+
+        // a build compared with itself and with an obfuscated build of the same code
+        let original = &bytecode[..code_len];
+        let same = analysis::Comparison::new(original, original);
+        assert!(same.ngram_similarity.values().all(|&s| s == 1.0));
+        assert_eq!(same.selector_similarity, 1.0);
+        let obfuscated = Config::default().obfuscate(original, 42).unwrap().bytecode;
+        let comparison = analysis::Comparison::new(original, &obfuscated);
+        assert_eq!(comparison.right, analysis::Analysis::new(&obfuscated));
+        assert!(comparison.ngram_similarity[&2] < 1.0);
+        let table = comparison.to_string();
+        assert!(table.starts_with("metric"));
+        assert!(table.contains(&format!("{:>12}", obfuscated.len())));
+    }
+
+    #[test]
+    fn test_score() {
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        // the deobfuscator takes out every identity the peephole pass puts in
+        let mut program = Program::lift(&bytecode);
+        let applied = peephole::sprinkle(
+            &mut program,
+            &mut StdRng::seed_from_u64(7),
+            1.0,
+            Fork::default(),
+        );
+        let sprinkled = program.assemble().unwrap();
+        let (cleaned, removed) = deobfuscator::deobfuscate(&sprinkled);
+        assert_eq!(removed, applied + deobfuscator::deobfuscate(&bytecode).1);
+        assert_eq!(cleaned, deobfuscator::deobfuscate(&bytecode).0);
+        // iszero iszero only goes away in front of a jump condition
+        assert_eq!(deobfuscator::deobfuscate(&[0x15, 0x15, 0x00]).1, 0);
+
+        // identities alone are potent but not resilient
+        let score = Score::new(&bytecode, &sprinkled, None);
+        assert!(score.potency > 0.0);
+        assert_eq!(score.resilience, 0.0);
+        assert_eq!(score.grade, 'F');
+
+        let obfuscated = Config::default().obfuscate(&bytecode, 42).unwrap().bytecode;
+        let score = Score::new(&bytecode, &obfuscated, Some(0.1));
+        assert!(score.potency > 0.0);
+        assert!(score.resilience > 0.0 && score.resilience <= 1.0);
+        assert_eq!(score.cost, score.size_overhead.max(0.1));
+        assert_eq!(
+            score.value,
+            score.potency * score.resilience / (1.0 + score.cost)
+        );
+        assert!(score
+            .to_string()
+            .starts_with(&format!("grade {}", score.grade)));
+    }
+
+    #[test]
+    fn test_obfuscation_summary() {
+        assert_eq!(evm::byte_entropy(&[0x60, 0x01, 0x60, 0x02]), 1.5);
+        assert_eq!(evm::byte_entropy(&[]), 0.0);
+        // PUSH1 1, PUSH1 2, ADD, STOP: push data is not an opcode
+        assert_eq!(
+            evm::opcode_entropy(&[0x60, 0x01, 0x60, 0x02, 0x01, 0x00]),
+            1.5
+        );
+        assert_eq!(evm::opcode_entropy(&[]), 0.0);
+        // repetition compresses, varied code does not
+        assert_eq!(evm::compression_ratio(&[]), 0.0);
+        let varied: Vec<u8> = (0..=255).collect();
+        assert!(evm::compression_ratio(&varied) > 0.9);
+        assert!(evm::compression_ratio(&[0x80, 0x50].repeat(128)) < 0.1);
+        // DUP1 POP inserted between ADD and STOP breaks one of two bigrams and adds three
+        let original = [0x60, 0x01, 0x01, 0x00];
+        let obfuscated = [0x60, 0x01, 0x01, 0x80, 0x50, 0x00];
+        assert_eq!(evm::ngram_similarity(&original, &obfuscated, 2), 0.2);
+        assert_eq!(evm::ngram_similarity(&original, &original, 3), 1.0);
+        assert_eq!(evm::ngram_similarity(&[], &[], 2), 1.0);
+
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let config = Config::default();
+        let mut obfuscator = config.obfuscator(&bytecode, 42).unwrap();
+        let obfuscated = obfuscator.obfuscate().unwrap();
+        let stats = obfuscator.stats().clone();
+        assert!(stats.values().sum::<usize>() > 0);
+
+        let summary = summary::Summary::new(&bytecode, &obfuscated, 42, &config, &stats).unwrap();
+        assert_eq!(summary.input.size, bytecode.len());
+        assert_eq!(summary.output.size, obfuscated.len());
+        assert!(summary.output.entropy > summary.input.entropy);
+        assert_eq!(summary.passes.len(), stats.len());
+        let json: serde_json::Value = serde_json::from_str(&summary.to_json()).unwrap();
+        assert_eq!(json["seed"], 42);
+        assert_eq!(json["passes"]["spaghetti"], stats["spaghetti"]);
+        let halstead = &json["output"]["halstead"];
+        assert_eq!(
+            halstead["vocabulary"],
+            summary.output.halstead.distinct_operators + summary.output.halstead.distinct_operands
+        );
+        assert!(summary.output.halstead.effort > summary.input.halstead.effort);
+        assert_eq!(
+            summary.opcode_entropy_delta,
+            summary.output.opcode_entropy - summary.input.opcode_entropy
+        );
+        assert!(summary.entropy_delta > 0.0);
+        assert_eq!(
+            summary.compression_ratio_delta,
+            summary.output.compression_ratio - summary.input.compression_ratio
+        );
+        assert!(summary.output.compression_ratio > 0.0);
+        assert_eq!(
+            summary.ngram_similarity.keys().copied().collect::<Vec<_>>(),
+            [2, 3, 4]
+        );
+        // the standard pipeline may break every 4-gram, but some bigrams survive
+        assert!(summary
+            .ngram_similarity
+            .values()
+            .all(|&s| (0.0..1.0).contains(&s)));
+        assert!(summary.ngram_similarity[&2] > 0.0);
+        // longer n-grams are harder to keep intact
+        assert!(summary.ngram_similarity[&4] <= summary.ngram_similarity[&2]);
+        assert_eq!(
+            json["ngram_similarity"]["3"].as_f64(),
+            Some(summary.ngram_similarity[&3])
+        );
+        assert!(json["output"]["opcode_entropy"].as_f64().unwrap() > 0.0);
+
+        // the html report is one self-contained page
+        let page = html::report(&summary, &bytecode, &obfuscated);
+        assert!(page.starts_with("<!DOCTYPE html>") && page.ends_with("</html>\n"));
+        assert!(!page.contains("src=") && !page.contains("href="));
+        assert_eq!(page.matches("<svg").count(), 2);
+        assert!(page.contains("PUSH4 0xaabbccdd"));
+        assert!(page.contains(&format!(
+            "<td>spaghetti</td><td>{}</td>",
+            stats["spaghetti"]
+        )));
+        assert!(summary.output.cfg.nodes > summary.input.cfg.nodes);
+        assert_eq!(summary.score, Score::new(&bytecode, &obfuscated, None));
+        assert_eq!(json["score"]["grade"], summary.score.grade.to_string());
+        assert_eq!(json["input"]["cfg"]["edges"], summary.input.cfg.edges);
+
+        // the fingerprint follows the settings
+        let other = Config::from_toml("seed = 7").unwrap();
+        assert_eq!(
+            config.fingerprint().unwrap(),
+            Config::default().fingerprint().unwrap()
+        );
+        assert_ne!(config.fingerprint().unwrap(), other.fingerprint().unwrap());
+    }
+
+    #[test]
+    fn test_cli() {
+        use super::Cli;
+        use clap::CommandFactory;
+        use clap_complete::Shell;
+
+        // conflicts and requirements name flags that exist
+        Cli::command().debug_assert();
+
+        let mut script = Vec::new();
+        clap_complete::generate(Shell::Bash, &mut Cli::command(), "ebo", &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("obfuscate") && script.contains("--pc-map"));
+
+        let mut page = Vec::new();
+        clap_mangen::Man::new(Cli::command())
+            .render(&mut page)
+            .unwrap();
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.contains(".TH ebo"));
+        assert!(page.contains("completions"));
+        let dir = std::env::temp_dir().join(format!("ebo-man-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        clap_mangen::generate_to(Cli::command(), &dir).unwrap();
+        assert!(dir.join("ebo.1").exists() && dir.join("ebo-obfuscate.1").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_json_output() {
+        use super::{run, Cli, Output};
+        use clap::Parser;
+
+        let dir = std::env::temp_dir().join(format!("ebo-json-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let original = dir.join("original.hex");
+        std::fs::write(&original, TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        // returns 1 whatever it is called with
+        let other = dir.join("other.hex");
+        std::fs::write(&other, "600160005260206000f3").unwrap();
+        let calls = dir.join("calls.json");
+        std::fs::write(&calls, r#"["0x", "0xa9059cbb"]"#).unwrap();
+        let path = |path: &std::path::Path| path.to_str().unwrap().to_string();
+        let execute = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["ebo"].iter().chain(args)).unwrap();
+            assert!(cli.json);
+            let mut out = Output::new(cli.json, args[0]);
+            let result = run(cli, &mut out);
+            (result, serde_json::Value::Object(out.document))
+        };
+
+        // results are recorded rather than printed, under the flag given after the command
+        let (result, document) = execute(&["analyze", "--file", &path(&original), "--json"]);
+        result.unwrap();
+        assert_eq!(document["command"], "analyze");
+        assert!(document["functions"]
+            .as_array()
+            .is_some_and(|f| !f.is_empty()));
+        let (result, document) = execute(&["lint", "--json", "--file", &path(&original)]);
+        result.unwrap();
+        assert_eq!(document["issues"], serde_json::json!([]));
+
+        // a failure keeps the results that explain it, and its kind decides the exit code
+        let (original, other, calls) = (path(&original), path(&other), path(&calls));
+        let args = [
+            "verify",
+            "--original",
+            &original,
+            "--obfuscated",
+            &other,
+            "--corpus",
+            &calls,
+            "--json",
+        ];
+        let (result, document) = execute(&args);
+        let err = result.unwrap_err();
+        let kind = err.downcast_ref::<EboError>().unwrap();
+        assert_eq!((kind.kind(), kind.exit_code()), ("verification", 8));
+        assert_eq!(document["samples"], 2);
+        assert_eq!(document["divergent"].as_array().unwrap().len(), 2);
+        assert_eq!(document["divergent"][0]["obfuscated"]["status"], "success");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pc_map() {
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let config =
+            Config::from_toml("rounds = 2\n[passes]\ndisabled=[\"chaotic-shuffle\"]").unwrap();
+        let obfuscation = config.obfuscate(&bytecode, 5).unwrap();
+        let obfuscated = &obfuscation.bytecode;
+        let map = PcMap::parse(&obfuscation.pc_map.unwrap().to_json()).unwrap();
+        assert!(!map.forward.is_empty());
+
+        // every copy holds the instruction it is a copy of, and maps back to it
+        for (&original, copies) in &map.forward {
+            for &pc in copies {
+                assert_eq!(obfuscated[pc], bytecode[original]);
+                let origin = map.original(pc).unwrap();
+                assert_eq!((origin.pc, origin.exact), (original, true));
+            }
+        }
+        // added code maps back to an input instruction, and so do offsets inside immediates
+        let instructions = disassemble(obfuscated);
+        for ins in &instructions {
+            if let Some(origin) = map.inverse.get(&ins.offset) {
+                assert!(disassemble(&bytecode)
+                    .iter()
+                    .any(|original| original.offset == origin.pc));
+            }
+            if !ins.immediate.is_empty() {
+                assert_eq!(map.original(ins.offset + 1), map.original(ins.offset));
+            }
+        }
+        assert!(instructions.iter().any(|ins| map
+            .inverse
+            .get(&ins.offset)
+            .is_some_and(|origin| !origin.exact)));
+    }
+
+    #[test]
+    fn test_source_map() {
+        let runtime = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        // a distinct source range for every instruction, with the jumps marked like solc does
+        let entries: Vec<String> = disassemble(&runtime)
+            .iter()
+            .enumerate()
+            .map(|(idx, ins)| {
+                let jump = if ins.opcode == op::JUMP { "o" } else { "-" };
+                format!("{}:{}:0:{}", 10 * idx, idx + 1, jump)
+            })
+            .collect();
+        let sources = SourceMap::parse(&entries.join(";")).unwrap();
+        assert_eq!(SourceMap::parse(&sources.to_string()).unwrap(), sources);
+        assert!(SourceMap::parse("1:2:0:x").is_err());
+
+        let obfuscation = Config::default().obfuscate(&runtime, 3).unwrap();
+        let map = obfuscation.pc_map.unwrap();
+        let translated = sources.translate(&runtime, &obfuscation.bytecode, &map);
+        let originals = disassemble(&runtime);
+        let mut exact = 0;
+        for (ins, entry) in disassemble(&obfuscation.bytecode)
+            .iter()
+            .zip(&translated.entries)
+        {
+            let Some(origin) = map.inverse.get(&ins.offset) else {
+                assert_eq!(*entry, srcmap::Entry::NONE);
+                continue;
+            };
+            let idx = originals
+                .iter()
+                .position(|original| original.offset == origin.pc)
+                .unwrap();
+            assert_eq!(
+                (entry.start, entry.length),
+                (10 * idx as i64, idx as i64 + 1)
+            );
+            if origin.exact {
+                assert_eq!(*entry, sources.entries[idx]);
+                exact += 1;
+            } else {
+                assert_eq!(entry.jump, srcmap::Jump::Regular);
+            }
+        }
+        assert!(exact > 0);
+
+        // an artifact's runtime source map is translated in place
+        let source = format!(
+            r#"{{"bytecode": {{"object": "0x"}}, "deployedBytecode": {{"object": "0x{}", "sourceMap": "{}"}}}}"#,
+            hex::encode(&runtime),
+            sources
+        );
+        let mut artifact = Artifact::parse(source.as_bytes()).unwrap();
+        artifact
+            .replace_runtime(&obfuscation.bytecode, Some(&map))
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&artifact.to_json()).unwrap();
+        assert_eq!(
+            json["deployedBytecode"]["sourceMap"],
+            translated.to_string()
+        );
+    }
+
+    #[test]
+    fn test_variants() {
+        use super::{run, Cli, Output};
+        use clap::Parser;
+        use ebo::seed::variant_seed;
+        use ebo::variants::{self, Variants};
+
+        let dir = std::env::temp_dir().join(format!("ebo-variants-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.hex");
+        std::fs::write(&input, TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let output = dir.join("token.bin");
+        let args = [
+            "ebo",
+            "obfuscate",
+            "--file",
+            input.to_str().unwrap(),
+            "--output",
+            output.to_str().unwrap(),
+            "--seed",
+            "7",
+            "--variants",
+            "3",
+            "--json",
+        ];
+        let mut out = Output::new(true, "obfuscate");
+        run(Cli::try_parse_from(args).unwrap(), &mut out).unwrap();
+        assert_eq!(out.document["variants"].as_array().unwrap().len(), 3);
+
+        // every variant has its own file and seed, and the index records what each holds
+        let index =
+            Variants::parse(&std::fs::read_to_string(variants::index_path(&output)).unwrap())
+                .unwrap();
+        assert_eq!(index.variants.len(), 3);
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let mut codes = Vec::new();
+        for variant in &index.variants {
+            assert_eq!(variant.file, variants::path(&output, variant.index));
+            assert_eq!(variant.seed, variant_seed(7, variant.index));
+            let code = std::fs::read(&variant.file).unwrap();
+            assert_eq!(index.identify(&code), Some(variant));
+            let again = Config::default()
+                .obfuscate(&bytecode, variant.seed)
+                .unwrap();
+            assert_eq!(again.bytecode, code);
+            codes.push(code);
+        }
+        codes.dedup();
+        assert_eq!(codes.len(), 3);
+        assert!(index.duplicates().is_empty());
+        assert!(!output.exists());
+
+        // variants are files next to the output
+        let args = [
+            "ebo",
+            "obfuscate",
+            "--file",
+            input.to_str().unwrap(),
+            "--output",
+            "-",
+            "--variants",
+            "2",
+        ];
+        let mut out = Output::new(false, "obfuscate");
+        assert!(run(Cli::try_parse_from(args).unwrap(), &mut out).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_force() {
+        use super::{run, Cli, Output};
+        use clap::Parser;
+
+        let dir = std::env::temp_dir().join(format!("ebo-force-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.hex");
+        std::fs::write(&input, TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let output = dir.join("token.bin");
+        let report = dir.join("report.json");
+        let obfuscate = |force: bool| {
+            let mut args = vec![
+                "ebo".to_string(),
+                "obfuscate".into(),
+                "--file".into(),
+                input.to_str().unwrap().into(),
+                "--output".into(),
+                output.to_str().unwrap().into(),
+                "--manifest".into(),
+                "--pc-map".into(),
+                "--report".into(),
+                report.to_str().unwrap().into(),
+            ];
+            if force {
+                args.push("--force".into());
+            }
+            let mut out = Output::new(false, "obfuscate");
+            run(Cli::try_parse_from(args).unwrap(), &mut out)
+        };
+        obfuscate(false).unwrap();
+
+        // a file written next to the output is not replaced without --force either, and the run
+        // stops before writing anything
+        let manifest = ebo::manifest::path(&output);
+        for existing in [&report, &manifest, &ebo::pcmap::path(&output)] {
+            std::fs::remove_file(&output).unwrap();
+            std::fs::write(existing, "keep").unwrap();
+            let err = obfuscate(false).unwrap_err().to_string();
+            assert!(err.contains("pass --force"), "{}", err);
+            assert_eq!(std::fs::read_to_string(existing).unwrap(), "keep");
+            assert!(!output.exists());
+            obfuscate(true).unwrap();
+            assert_ne!(std::fs::read_to_string(existing).unwrap(), "keep");
+        }
+        assert!(std::fs::read_to_string(&manifest)
+            .unwrap()
+            .contains("\"seed\""));
+
+        // the other commands writing a file refuse the same way, and exit with an error
+        let written = dir.join("written.hex");
+        std::fs::write(&written, "keep").unwrap();
+        let input = input.to_str().unwrap();
+        for command in [
+            vec!["wrap", "--file", input],
+            vec![
+                "merge",
+                "--first",
+                input,
+                "--second",
+                input,
+                "--switch",
+                "chainid:1",
+            ],
+        ] {
+            let args = |force: bool| {
+                let mut args = vec!["ebo"];
+                args.extend(&command);
+                args.extend(["--output", written.to_str().unwrap(), "--hex"]);
+                args.extend(force.then_some("--force"));
+                Cli::try_parse_from(args).unwrap()
+            };
+            let err = run(args(false), &mut Output::new(false, command[0])).unwrap_err();
+            assert_ne!(super::failure(&err).1, 0);
+            assert!(err.to_string().contains("pass --force"), "{}", err);
+            assert_eq!(std::fs::read_to_string(&written).unwrap(), "keep");
+            run(args(true), &mut Output::new(false, command[0])).unwrap();
+            assert_ne!(std::fs::read_to_string(&written).unwrap(), "keep");
+            std::fs::write(&written, "keep").unwrap();
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_random_seed() {
+        use ebo::seed::{self, Seed};
+
+        assert_eq!(
+            seed::parse("7"),
+            Ok(Seed {
+                value: 7,
+                random: false
+            })
+        );
+        assert!(seed::parse("seven").is_err());
+        // drawn seeds differ from run to run and fit in a toml integer, so a manifest records them
+        let (first, second) = (
+            seed::parse("random").unwrap(),
+            seed::parse("random").unwrap(),
+        );
+        assert!(first.random && second.random);
+        assert_ne!(first.value, second.value);
+        let config = Config::default().resolved(first.value).unwrap();
+        let toml = toml::to_string(&config).unwrap();
+        assert_eq!(Config::from_toml(&toml).unwrap().seed, Some(first.value));
+    }
+
+    #[test]
+    fn test_manifest() {
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let mut config = Config::from_toml(
+            "rounds = 2\n[passes]\ndisabled = [\"outline\"]\n\
+             [probabilities]\nintensity = 0.8\n[search]\ncandidates = 4",
+        )
+        .unwrap();
+        let obfuscation = config.obfuscate(&bytecode, 9).unwrap();
+        let manifest =
+            Manifest::new(&bytecode, &obfuscation.bytecode, obfuscation.seed, &config).unwrap();
+        let manifest = Manifest::parse(&manifest.to_json()).unwrap();
+        assert_eq!(manifest.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(manifest.output.size, obfuscation.bytecode.len());
+        assert_eq!(
+            manifest.output.codehash,
+            hex::encode(keccak256(&obfuscation.bytecode))
+        );
+        // the recorded configuration rebuilds the kept candidate without searching again
+        let resolved = Config::from_toml(&manifest.config).unwrap();
+        assert_eq!(resolved.search.candidates, None);
+        assert!(!resolved.pipeline().unwrap().names().contains(&"outline"));
+        assert_eq!(
+            manifest.reproduce(&bytecode, None).unwrap(),
+            obfuscation.bytecode
+        );
+
+        // another input, or a changed output, is caught
+        assert!(manifest.reproduce(&bytecode[1..], None).is_err());
+        let mut tampered = manifest.clone();
+        tampered.output.sha256 = "00".repeat(32);
+        let err = tampered.reproduce(&bytecode, None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<EboError>(),
+            Some(EboError::Verification(_))
+        ));
+
+        // a keyed build needs its key again, which the manifest does not hold
+        config.key = Some(Key::derive(&[0xa0; 32]).unwrap());
+        let keyed = config.obfuscate(&bytecode, 9).unwrap();
+        let manifest = Manifest::new(&bytecode, &keyed.bytecode, keyed.seed, &config).unwrap();
+        assert!(manifest.keyed && !manifest.to_json().contains("audit"));
+        assert!(manifest.reproduce(&bytecode, None).is_err());
+        assert_eq!(
+            manifest
+                .reproduce(&bytecode, Some(Key::derive(&[0xa0; 32]).unwrap()))
+                .unwrap(),
+            keyed.bytecode
+        );
+    }
+
+    #[test]
+    fn test_seed_search() {
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let search = Search {
+            objective: Objective::Halstead,
+            candidates: 8,
+            patience: None,
+            max_size: evm::MAX_CODE_SIZE,
+        };
+        let outcome = search
+            .run(7, |seed| Ok(Obfuscator::new(&bytecode, seed)))
+            .unwrap();
+        assert_eq!(outcome.tried, 8);
+        assert!((7..15).contains(&outcome.seed));
+        // the kept candidate beats the first seed and is reproduced by its seed
+        let first = Obfuscator::new(&bytecode, 7).obfuscate().unwrap();
+        assert!(outcome.score >= Objective::Halstead.score(&first));
+        assert_eq!(
+            Obfuscator::new(&bytecode, outcome.seed)
+                .obfuscate()
+                .unwrap(),
+            outcome.bytecode
+        );
+
+        // a pipeline that changes nothing never improves after the first candidate
+        let identity = Config::from_toml("[passes]\nenabled = []").unwrap();
+        let patient = Search {
+            patience: Some(3),
+            ..search
+        };
+        let outcome = patient
+            .run(0, |seed| {
+                Ok(Obfuscator::with_pipeline(
+                    &bytecode,
+                    seed,
+                    identity.pipeline()?,
+                ))
+            })
+            .unwrap();
+        assert_eq!((outcome.seed, outcome.tried), (0, 4));
+
+        // nothing fits a limit smaller than the input
+        let tiny = Search {
+            max_size: bytecode.len() - 1,
+            ..search
+        };
+        let err = tiny
+            .run(0, |seed| Ok(Obfuscator::new(&bytecode, seed)))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<EboError>(),
+            Some(EboError::SizeLimit { limit, .. }) if *limit == bytecode.len() - 1
+        ));
+        assert_eq!(err.downcast_ref::<EboError>().unwrap().exit_code(), 6);
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let config = Config::from_toml("seed = 5").unwrap();
+        let plan = config.plan(&bytecode, 5).unwrap();
+        let obfuscation = config.obfuscate(&bytecode, 5).unwrap();
+        // the plan describes the run that would have been written
+        assert_eq!(plan.size, (bytecode.len(), obfuscation.bytecode.len()));
+        assert_eq!(plan.passes, obfuscation.stats);
+        assert_eq!(plan.blocks.len(), cfg::Cfg::new(&bytecode).blocks.len());
+        let names = config.pipeline().unwrap().names();
+        assert!(plan
+            .blocks
+            .iter()
+            .flat_map(|block| &block.passes)
+            .all(|name| names.contains(name)));
+        assert!(plan.blocks.iter().any(|block| block.size_delta > 0));
+        assert!(plan.to_string().contains("blocks unchanged"));
+
+        // a pipeline that changes nothing plans nothing
+        let identity = Config::from_toml("[passes]\nenabled = []").unwrap();
+        let plan = identity.plan(&bytecode, 5).unwrap();
+        assert_eq!(plan.size, (bytecode.len(), bytecode.len()));
+        assert!(plan
+            .blocks
+            .iter()
+            .all(|block| block.passes.is_empty() && block.size_delta == 0));
+    }
+
+    #[test]
+    fn test_typed_errors() {
+        let kind = |err: anyhow::Error| err.downcast::<EboError>().unwrap().exit_code();
+        // malformed input is a parse error
+        assert_eq!(kind(corpus::decode_hex("0x6").unwrap_err()), 3);
+        assert_eq!(kind(Config::from_toml("seed = ").unwrap_err()), 3);
+        assert_eq!(kind(Artifact::parse(b"{\"abi\": []}").unwrap_err()), 3);
+
+        // a label nothing marks cannot be relocated, and obfuscation reports it instead of panicking
+        let mut program = Program::lift(&[0x00]);
+        let label = program.new_label();
+        program.items.push(Item::PushLabel {
+            label,
+            addend: 0,
+            width: 1,
+        });
+        assert!(matches!(program.assemble(), Err(EboError::Relocation(_))));
+
+        // introduced issues are classified, undefined opcodes first
+        let issues = vec![
+            validate::Issue {
+                offset: 3,
+                problem: Problem::BadJump(9),
+            },
+            validate::Issue {
+                offset: 7,
+                problem: Problem::UndefinedOpcode(0x5f),
+            },
+        ];
+        let err = EboError::introduced(issues.clone(), Fork::Paris);
+        assert!(matches!(
+            err,
+            EboError::UnsupportedOpcode {
+                opcode: 0x5f,
+                offset: 7,
+                target: Target {
+                    fork: Fork::Paris,
+                    chain: None
+                }
+            }
+        ));
+        assert_eq!(err.exit_code(), 4);
+        let err = EboError::introduced(issues[..1].to_vec(), Fork::Paris);
+        assert_eq!(err.exit_code(), 7);
+        assert!(err.to_string().contains("not a jumpdest"), "{}", err);
+    }
+
+    #[test]
+    fn test_equivalence_harness() {
+        // sstore(0, calldataload(0) + 1), log0 the same value from memory, return it
+        let code =
+            hex::decode("600035600101 80600055 80600052 60206000a0 60206000f3".replace(' ', ""))
+                .unwrap();
+        let calldata = [[0u8; 31].as_slice(), &[41]].concat();
+        let run = equivalence::execute(&code, &calldata).unwrap();
+        let mut expected = [0u8; 32];
+        expected[31] = 42;
+        assert_eq!(run.effects.status, equivalence::Status::Success);
+        assert_eq!(run.effects.output, expected);
+        assert_eq!(run.effects.storage.len(), 1);
+        assert_eq!(run.effects.logs.len(), 1);
+        assert_eq!(run.effects.logs[0].data.data.as_ref(), expected);
+        assert!(run.gas_used > 21_000);
+
+        // reverted calls keep their revert data but write nothing
+        let reverted = [&code[..code.len() - 1], &[op::REVERT]].concat();
+        let run = equivalence::execute(&reverted, &calldata).unwrap();
+        assert_eq!(run.effects.status, equivalence::Status::Revert);
+        assert_eq!(run.effects.output, expected);
+        assert!(run.effects.storage.is_empty() && run.effects.logs.is_empty());
+
+        assert!(equivalence::compare(&code, &code, &calldata)
+            .unwrap()
+            .divergence()
+            .is_none());
+        let divergence = equivalence::compare(&code, &reverted, &calldata)
+            .unwrap()
+            .divergence()
+            .unwrap();
+        assert_eq!(divergence.calldata, calldata);
+        assert!(divergence.to_string().contains("status: Success != Revert"));
+    }
+
+    #[test]
+    fn test_calldata_corpus() {
+        let samples =
+            corpus::from_json(r#"["0xa9059cbb", {"name": "empty", "calldata": ""}, "0102"]"#)
+                .unwrap();
+        let names: Vec<&str> = samples.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["#0", "empty", "#2"]);
+        assert_eq!(samples[0].calldata, [0xa9, 0x05, 0x9c, 0xbb]);
+        assert!(samples[1].calldata.is_empty());
+        assert!(corpus::from_json(r#"["0xzz"]"#).is_err());
+        assert!(corpus::from_json(r#"{"calldata": "00"}"#).is_err());
+
+        // a directory holds one sample per file, raw or hex, replayed in name order
+        let dir = std::env::temp_dir().join(format!("ebo-corpus-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("b-raw"), [0x00, 0xff]).unwrap();
+        fs::write(dir.join("a-hex"), "0xa9059cbb\n").unwrap();
+        let samples = corpus::load(&dir).unwrap();
+        assert_eq!(samples[0].name, "a-hex");
+        assert_eq!(samples[0].calldata, [0xa9, 0x05, 0x9c, 0xbb]);
+        assert_eq!(samples[1].calldata, [0x00, 0xff]);
+        fs::remove_dir_all(&dir).unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        assert!(corpus::load(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_input_formats() {
+        use corpus::Format;
+        let code = vec![0x60, 0x80, 0x60, 0x40, 0x52];
+        // solc --bin, cast code, and a hex string wrapped over several lines
+        for text in ["6080604052", "0x6080604052\n", "  0x608060\n4052\r\n"] {
+            assert_eq!(
+                corpus::parse_bytes(text.into(), Format::Auto).unwrap(),
+                code
+            );
+        }
+        // an account without code
+        assert!(corpus::parse_bytes(b"0x\n".to_vec(), Format::Auto)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            corpus::parse_bytes(code.clone(), Format::Auto).unwrap(),
+            code
+        );
+        // text that is not hex is taken as raw bytes unless hex is asked for
+        assert_eq!(
+            corpus::parse_bytes(b"0xzz".to_vec(), Format::Auto).unwrap(),
+            b"0xzz"
+        );
+        assert!(corpus::parse_bytes(b"0xzz".to_vec(), Format::Hex).is_err());
+        assert!(corpus::parse_bytes(b"608".to_vec(), Format::Auto).is_err());
+        assert_eq!(
+            corpus::parse_bytes(b"6080".to_vec(), Format::Raw).unwrap(),
+            b"6080"
+        );
+    }
+
+    #[test]
+    fn test_batch_directory() {
+        let dir = std::env::temp_dir().join(format!("ebo-batch-{}", std::process::id()));
+        let out = dir.join("out");
+        let _ = std::fs::remove_dir_all(&dir);
+        let runtime = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        std::fs::create_dir_all(dir.join("in/nested")).unwrap();
+        std::fs::write(dir.join("in/a.bin"), &runtime).unwrap();
+        std::fs::write(dir.join("in/nested/b.bin"), hex::encode(&runtime)).unwrap();
+        std::fs::write(dir.join("in/nested/broken.bin"), "0x60").unwrap();
+        std::fs::write(dir.join("in/notes.txt"), "not bytecode").unwrap();
+
+        let files = batch::find(&dir.join("in"), "**/*.bin").unwrap();
+        assert_eq!(
+            files,
+            ["a.bin", "nested/b.bin", "nested/broken.bin"].map(PathBuf::from)
+        );
+        // `*` does not cross directories
+        assert_eq!(
+            batch::find(&dir.join("in"), "*.bin").unwrap(),
+            [PathBuf::from("a.bin")]
+        );
+
+        let obfuscate = |_: &Path, code: &[u8]| {
+            if code.len() < 2 {
+                anyhow::bail!("too short");
+            }
+            Config::default().obfuscate(code, 42)
+        };
+        let outcomes = batch::run(
+            &dir.join("in"),
+            &files,
+            &out,
+            corpus::Format::Auto,
+            false,
+            false,
+            obfuscate,
+        );
+        let results: Vec<bool> = outcomes.iter().map(|o| o.result.is_ok()).collect();
+        assert_eq!(results, [true, true, false]);
+        // each output keeps the form of its input
+        let raw = std::fs::read(out.join("a.bin")).unwrap();
+        let hex = std::fs::read_to_string(out.join("nested/b.bin")).unwrap();
+        assert_eq!(hex::decode(&hex).unwrap(), raw);
+        assert_eq!(
+            outcomes[0].result.as_ref().unwrap(),
+            &(runtime.len(), raw.len())
+        );
+        assert!(!out.join("nested/broken.bin").exists());
+
+        // existing outputs are only replaced with force
+        let again = batch::run(
+            &dir.join("in"),
+            &files[..1],
+            &out,
+            corpus::Format::Auto,
+            false,
+            false,
+            obfuscate,
+        );
+        assert!(again[0].result.is_err());
+        let forced = batch::run(
+            &dir.join("in"),
+            &files[..1],
+            &out,
+            corpus::Format::Auto,
+            false,
+            true,
+            obfuscate,
+        );
+        assert!(forced[0].result.is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_foundry_artifact_input() {
+        let runtime = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        // solc's constructor: codecopy(0, offset, length), return(0, length)
+        let constructor = format!("61{:04x} 80 61000b 5f 39 5f f3", runtime.len()).replace(' ', "");
+        let creation = [hex::decode(constructor).unwrap(), runtime.clone()].concat();
+        let source = format!(
+            r#"{{"abi": [], "bytecode": {{"object": "0x{}", "sourceMap": "1:2:3"}},
+                "deployedBytecode": {{"object": "0x{}", "sourceMap": "4:5:6",
+                "immutableReferences": {{}}}}, "methodIdentifiers": {{}}}}"#,
+            hex::encode(&creation),
+            hex::encode(&runtime)
+        );
+        assert_eq!(corpus::detect(source.as_bytes()), corpus::Format::Artifact);
+        assert_eq!(
+            corpus::parse_bytes(source.clone().into_bytes(), corpus::Format::Auto).unwrap(),
+            runtime
+        );
+
+        let mut artifact = Artifact::parse(source.as_bytes()).unwrap();
+        let obfuscated = Config::default()
+            .obfuscator(&runtime, 42)
+            .unwrap()
+            .obfuscate()
+            .unwrap();
+        assert_ne!(obfuscated.len(), runtime.len());
+        artifact.replace_runtime(&obfuscated, None).unwrap();
+        assert_eq!(artifact.runtime().unwrap(), obfuscated);
+        // the constructor now deploys the obfuscated code
+        let creation = artifact.creation().unwrap().unwrap();
+        let deployed = equivalence::execute(&creation, &[]).unwrap();
+        assert_eq!(deployed.effects.output, obfuscated);
+        // other fields are kept, in order; the runtime source map is stale and cleared
+        let json: serde_json::Value = serde_json::from_str(&artifact.to_json()).unwrap();
+        assert_eq!(json["bytecode"]["sourceMap"], "1:2:3");
+        assert_eq!(json["deployedBytecode"]["sourceMap"], "");
+        let keys: Vec<&String> = json.as_object().unwrap().keys().collect();
+        assert_eq!(
+            keys,
+            ["abi", "bytecode", "deployedBytecode", "methodIdentifiers"]
+        );
+
+        // immutable references must point at the value of a push32, and every library
+        // placeholder must be listed
+        let immutable = source.replace(
+            r#""immutableReferences": {}"#,
+            r#""immutableReferences": {"7": [{"start": 1, "length": 32}]}"#,
+        );
+        let mut artifact = Artifact::parse(immutable.as_bytes()).unwrap();
+        assert!(artifact.replace_runtime(&obfuscated, None).is_err());
+        let linked = source.replace("0x600035", "0x__$1234$__");
+        assert!(Artifact::parse(linked.as_bytes())
+            .unwrap()
+            .runtime()
+            .is_err());
+    }
+
+    #[test]
+    fn test_artifact_immutables() {
+        // stores immutable 5 at slot 0 when called with a zero word, or its double otherwise
+        let runtime = hex::decode(
+            format!(
+                "7f{} 5f 35 6029 57 5f 55 00 5b 80 01 5f 55 00",
+                "00".repeat(32)
+            )
+            .replace(' ', ""),
+        )
+        .unwrap();
+        // codecopy(0, 17, length), mstore(1, 0x1234), return(0, length)
+        let constructor = format!(
+            "61{:04x} 80 610011 5f 39 611234 6001 52 5f f3",
+            runtime.len()
+        )
+        .replace(' ', "");
+        let creation = [hex::decode(constructor).unwrap(), runtime.clone()].concat();
+        let source = format!(
+            r#"{{"abi": [], "bytecode": {{"object": "0x{}"}},
+                "deployedBytecode": {{"object": "0x{}",
+                "immutableReferences": {{"5": [{{"start": 1, "length": 32}}]}}}}}}"#,
+            hex::encode(&creation),
+            hex::encode(&runtime)
+        );
+        let deployed = equivalence::execute(&creation, &[]).unwrap().effects.output;
+
+        // the value is kept whole through the passes that preserve behavior
+        let config = Config::from_toml("[probabilities]\nintensity = 4.0").unwrap();
+        for seed in 0..10 {
+            let mut artifact = Artifact::parse(source.as_bytes()).unwrap();
+            let code = artifact.runtime().unwrap();
+            assert_ne!(code, runtime);
+            let obfuscated = config.obfuscate(&code, seed).unwrap().bytecode;
+            artifact.replace_runtime(&obfuscated, None).unwrap();
+
+            // the references point at the zeroed value of a push32 of the new code
+            let json: serde_json::Value = serde_json::from_str(&artifact.to_json()).unwrap();
+            let references = json["deployedBytecode"]["immutableReferences"]["5"]
+                .as_array()
+                .unwrap();
+            let object = json["deployedBytecode"]["object"].as_str().unwrap();
+            let code = hex::decode(object.trim_start_matches("0x")).unwrap();
+            for reference in references {
+                let start = reference["start"].as_u64().unwrap() as usize;
+                assert_eq!(code[start - 1], op::PUSH32);
+                assert_eq!(code[start..start + 32], [0; 32]);
+            }
+
+            // deployed, it holds the value the constructor wrote and behaves like the original
+            let object = json["bytecode"]["object"].as_str().unwrap();
+            let creation = hex::decode(object.trim_start_matches("0x")).unwrap();
+            let obfuscated = equivalence::execute(&creation, &[]).unwrap().effects.output;
+            assert_eq!(obfuscated.len(), code.len());
+            for calldata in [vec![0; 32], vec![1; 32]] {
+                let run = equivalence::compare(&deployed, &obfuscated, &calldata).unwrap();
+                assert!(run.divergence().is_none(), "seed {}", seed);
+                assert_eq!(run.obfuscated.effects.storage.len(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_creation_transaction() {
+        // returns the first calldata word plus slot 0
+        let runtime = hex::decode("5f545f35015f5260205ff3").unwrap();
+        // codecopy(0, length, codesize - length), sstore(0, mload(0)), then
+        // codecopy(0, 24, runtime length), return(0, runtime length)
+        let length = 24 + runtime.len();
+        let constructor = format!(
+            "61{:04x} 38 03 61{:04x} 5f 39 5f 51 5f 55 61{:04x} 80 6018 5f 39 5f f3",
+            length,
+            length,
+            runtime.len()
+        )
+        .replace(' ', "");
+        let argument = U256::from(0x2A).to_be_bytes::<32>().to_vec();
+        let data = [
+            hex::decode(constructor).unwrap(),
+            runtime.clone(),
+            argument.clone(),
+        ]
+        .concat();
+
+        let creation = Creation::split(&data).unwrap();
+        assert_eq!(creation.runtime(), runtime);
+        assert_eq!(creation.code().len(), length);
+        assert_eq!(creation.arguments(), argument);
+
+        let config = Config::from_toml("[probabilities]\nintensity = 4.0").unwrap();
+        for seed in 0..10 {
+            let obfuscated = config.obfuscate(creation.runtime(), seed).unwrap().bytecode;
+            let rebuilt = creation.rebuild(&obfuscated).unwrap();
+            assert!(rebuilt.ends_with(&argument));
+
+            // the constructor still reads its argument, and deploys the obfuscated code
+            let deployment = equivalence::execute(&rebuilt, &[]).unwrap();
+            assert_eq!(deployment.effects.output, obfuscated);
+            let stored: Vec<_> = deployment.effects.storage.values().collect();
+            assert_eq!(stored, [&U256::from(0x2A)]);
+            for calldata in [[0u8; 32], [7u8; 32]] {
+                let run = equivalence::compare(&runtime, &obfuscated, &calldata).unwrap();
+                assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+            }
+        }
+
+        // code that is not followed by whole argument words is refused
+        assert!(Creation::split(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_large_creation_streamed() {
+        use super::{run, Cli, Output};
+        use clap::Parser;
+
+        // a constructor deploying a runtime long enough for a two-byte length, followed by
+        // 512 KiB of embedded data
+        let mut runtime = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        runtime.resize(300, op::STOP);
+        let data: Vec<u8> = creation::wrap(&runtime, Fork::Cancun)
+            .into_iter()
+            .chain((0..512 * 1024).map(|i| (i % 251) as u8))
+            .collect();
+        let creation = Creation::split_owned(data.clone()).unwrap();
+        assert_eq!(creation.runtime(), runtime);
+        assert_eq!(creation.arguments().len(), 512 * 1024);
+        let obfuscated = Config::default().obfuscate(&runtime, 42).unwrap().bytecode;
+        let expected = creation.rebuild(&obfuscated).unwrap();
+
+        // written as hex and as bytes, the data comes out as rebuilt in memory
+        let dir = std::env::temp_dir().join(format!("ebo-large-creation-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("deploy.hex");
+        fs::write(&input, format!("0x{}\n", hex::encode(&data))).unwrap();
+        for hex in [true, false] {
+            let output = dir.join(if hex { "out.hex" } else { "out.bin" });
+            let mut args = vec![
+                "ebo",
+                "obfuscate",
+                input.to_str().unwrap(),
+                "--creation",
+                "--output",
+                output.to_str().unwrap(),
+            ];
+            if hex {
+                args.push("--hex");
+            }
+            let mut out = Output::new(false, "obfuscate");
+            run(Cli::try_parse_from(args).unwrap(), &mut out).unwrap();
+            let format = if hex {
+                corpus::Format::Hex
+            } else {
+                corpus::Format::Raw
+            };
+            let written = corpus::read_bytes_as(&output, format).unwrap();
+            assert_eq!(written, expected, "hex: {}", hex);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_wrap() {
+        // every size needing a wider push for the length or the offset deploys as given
+        for size in [0, 1, 200, 245, 246, 255, 256, 65_535, 70_000] {
+            let runtime: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+            for fork in [Fork::London, Fork::Cancun] {
+                let init = creation::wrap(&runtime, fork);
+                assert!(init.ends_with(&runtime));
+                assert!(fork.admits(&init[..init.len() - runtime.len()]));
+                let deployment = equivalence::execute(&init, &[]).unwrap();
+                assert_eq!(deployment.effects.output, runtime, "{} bytes", size);
+            }
+        }
+
+        // and the wrapped runtime splits back out of it
+        let runtime = hex::decode("5f545f35015f5260205ff3").unwrap();
+        let init = creation::wrap(&runtime, Fork::Cancun);
+        assert_eq!(Creation::split(&init).unwrap().runtime(), runtime);
+    }
+
+    #[test]
+    fn test_create2_address() {
+        // example 2 of eip-1014
+        let deployer = rpc::parse_address("0xdeadbeef00000000000000000000000000000000").unwrap();
+        let address = creation::create2_address(deployer, [0; 32], &[0x00]);
+        assert_eq!(
+            hex::encode(address),
+            "b928f69bb1d91cd65274e3c79d8986362984fda3"
+        );
+
+        let salt = creation::parse_salt("0x1").unwrap();
+        assert_eq!(salt[31], 1);
+        assert!(salt[..31].iter().all(|&b| b == 0));
+        assert_eq!(creation::parse_salt(&"ab".repeat(32)).unwrap(), [0xAB; 32]);
+        assert!(creation::parse_salt(&"ab".repeat(33)).is_err());
+        assert!(creation::parse_salt("0xzz").is_err());
+    }
+
+    #[test]
+    fn test_decompiler_evaluation() {
+        use std::time::Duration;
+
+        // a stand-in decompiler that recovers one function whatever it is given
+        let echo = Decompiler::new("echo", "echo function Unresolved_a9059cbb() {file}");
+        assert!(echo.installed());
+        let code = [0x5F, 0x54, 0x00];
+        let evaluation =
+            decompile::Evaluation::run(&echo, &code, &code, Duration::from_secs(10)).unwrap();
+        assert!(evaluation.original.selectors.contains("a9059cbb"));
+        assert_eq!(evaluation.original.functions, 1);
+        assert_eq!(evaluation.recovery.overall, 1.0);
+        assert!(evaluation.to_string().contains("overall recovery 100%"));
+
+        // one that hangs is stopped, and one that is missing is reported
+        let sleep = Decompiler::new("sleep", "sleep 5");
+        let started = std::time::Instant::now();
+        assert!(sleep.run(&code, Duration::from_millis(200)).is_err());
+        assert!(started.elapsed() < Duration::from_secs(4));
+        let missing = Decompiler::new("missing", "ebo-no-such-decompiler {hex}");
+        assert!(!missing.installed());
+        assert!(missing.run(&code, Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn test_selector_mask() {
+        // a binary search dispatcher: pivot 0x22222222 with gt, then eq against 0x22222222 above
+        // it and, in the push-dup2 form, against 0x11111111 below it
+        let code = hex::decode(
+            "5f3560e01c 80632222222211 61001e57 806322222222 14 61003357 5f5ffd \
+             5b 6311111111 81 14 61002d57 5f5ffd 5b60015f5500 5b60025f5500"
+                .replace(' ', ""),
+        )
+        .unwrap();
+        let original: Vec<[u8; 4]> = dispatcher::functions(&code)
+            .iter()
+            .map(|function| function.selector)
+            .collect();
+        assert_eq!(original, [[0x11; 4], [0x22; 4]]);
+
+        let masking = Config::from_toml("[passes]\nenabled = [\"selector-mask\"]").unwrap();
+        let standard = Config::default();
+        for seed in 0..10 {
+            for (config, decoys) in [(&masking, true), (&standard, false)] {
+                let obfuscation = config.obfuscate(&code, seed).unwrap();
+                let obfuscated = obfuscation.bytecode;
+                // the real selectors are no longer matched, but made-up ones are until later
+                // passes break up the decoys too
+                assert!(selector_mask::recoverable(&code, &obfuscated).is_empty());
+                if decoys {
+                    assert!(!dispatcher::functions(&obfuscated).is_empty());
+                }
+                assert_eq!(obfuscation.stats["selector-mask"], 3);
+                for selector in [[0x11; 4], [0x22; 4], [0x33; 4], [0; 4]] {
+                    let calldata = [&selector[..], &[0; 28]].concat();
+                    let run = equivalence::compare(&code, &obfuscated, &calldata).unwrap();
+                    assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+                }
+                let map = obfuscation.pc_map.unwrap();
+                assert!(dispatcher::inconsistencies(&code, &obfuscated, &map)
+                    .unwrap()
+                    .is_empty());
+            }
+        }
+
+        // 0x22222222 sent to the function of 0x11111111 no longer reaches its own
+        let misrouted = hex::decode(
+            "5f3560e01c 80632222222211 61001e57 806322222222 14 61002d57 5f5ffd \
+             5b 6311111111 81 14 61002d57 5f5ffd 5b60015f5500 5b60025f5500"
+                .replace(' ', ""),
+        )
+        .unwrap();
+        let identity = PcMap::new(&Program::lift(&misrouted)).unwrap();
+        let found = dispatcher::inconsistencies(&code, &misrouted, &identity).unwrap();
+        assert!(found.contains(&Inconsistency::Unrouted {
+            selector: [0x22; 4],
+            entry: 0x33
+        }));
+
+        // a decoy comparing against a real selector, ahead of the real comparison, collides
+        // with it even though every call still ends up in the right place
+        let code =
+            hex::decode("5f3560e01c 8063aabbccdd 14 601057 00 5b60015f5500".replace(' ', ""))
+                .unwrap();
+        let decoyed = hex::decode(
+            "5f3560e01c 8063aabbccdd14600f57 5b 8063aabbccdd14601b57 00 5b60015f5500"
+                .replace(' ', ""),
+        )
+        .unwrap();
+        let mut map = PcMap::default();
+        map.forward.insert(0x10, vec![0x1b]);
+        map.inverse.insert(
+            0x0f,
+            Origin {
+                pc: 0x0e,
+                exact: false,
+            },
+        );
+        map.inverse.insert(
+            0x1b,
+            Origin {
+                pc: 0x10,
+                exact: true,
+            },
+        );
+        let found = dispatcher::inconsistencies(&code, &decoyed, &map).unwrap();
+        assert_eq!(
+            found,
+            [Inconsistency::Collision {
+                selector: [0xaa, 0xbb, 0xcc, 0xdd],
+                target: 0x0f
+            }]
+        );
+        assert!(found[0].to_string().contains("0xaabbccdd"));
+
+        // with the dispatcher masked, passes that would send a selector elsewhere (like a false
+        // branch stopping on the fall-through of a comparison) back off
+        let code = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        for seed in 0..10 {
+            let obfuscation = Config::default().obfuscate(&code, seed).unwrap();
+            let map = obfuscation.pc_map.unwrap();
+            let found = dispatcher::inconsistencies(&code, &obfuscation.bytecode, &map).unwrap();
+            assert!(found.is_empty(), "seed {}: {:?}", seed, found);
+        }
+    }
+
+    #[test]
+    fn test_gas_predicate() {
+        // PUSH1 42, PUSH1 6, JUMP, STOP, JUMPDEST, PUSH1 1, ADD, DUP1, PUSH1 0x11, JUMPI,
+        // PUSH0, PUSH0, REVERT, JUMPDEST, PUSH0, MSTORE, PUSH1 32, PUSH0, RETURN
+        let code = hex::decode("602a600656005b600101806011575f5ffd5b5f5260205ff3").unwrap();
+        let items = Item::sequence(&code);
+        assert_eq!(gas::committed(&items[4..]), 1 + 3 + 3 + 3 + 3 + 10);
+        assert_eq!(gas_predicate::margin(22, 22), Some(gas_predicate::COST - 1));
+        assert_eq!(gas_predicate::margin(22 + gas_predicate::COST, 22), None);
+
+        // runs `code` with `limit` gas for the whole transaction
+        let run = |code: &[u8], limit: u64| {
+            let mut evm = revm::Evm::builder()
+                .with_db(equivalence::deploy(code))
+                .with_spec_id(equivalence::SPEC)
+                .modify_tx_env(|tx| {
+                    equivalence::call(tx, &[]);
+                    tx.gas_limit = limit;
+                })
+                .build();
+            equivalence::observe(evm.transact().unwrap())
+        };
+        let config = Config::from_toml(
+            "[passes]\nenabled = [\"gas-predicate\"]\n[probabilities]\ngas-predicate = 1.0",
+        )
+        .unwrap();
+        for seed in 0..10 {
+            let obfuscation = config.obfuscate(&code, seed).unwrap();
+            let obfuscated = obfuscation.bytecode;
+            assert_eq!(obfuscation.stats["gas-predicate"], 2);
+            let expected = run(&code, 30_000_000);
+            let needed = run(&obfuscated, 30_000_000);
+            assert_eq!(needed.effects, expected.effects);
+            // with enough gas for the obfuscated code, no predicate takes its decoy; with less, the
+            // call runs out of gas whether or not one does
+            for limit in needed.gas_used - 60..needed.gas_used + 60 {
+                let status = run(&obfuscated, limit).effects.status;
+                if limit >= needed.gas_used {
+                    assert_eq!(
+                        status,
+                        equivalence::Status::Success,
+                        "seed {}, {} gas",
+                        seed,
+                        limit
+                    );
+                } else {
+                    assert_eq!(
+                        status,
+                        equivalence::Status::Halt,
+                        "seed {}, {} gas",
+                        seed,
+                        limit
+                    );
+                }
+            }
+        }
+
+        // chains that account gas differently are left alone
+        let zksync = Config::from_toml(
+            "chain = \"zksync-era\"\n[passes]\nenabled = [\"gas-predicate\"]\n\
+             [probabilities]\ngas-predicate = 1.0",
+        )
+        .unwrap();
+        let obfuscation = zksync.obfuscate(&code, 1).unwrap();
+        assert_eq!(obfuscation.stats["gas-predicate"], 0);
+        assert_eq!(obfuscation.bytecode, code);
+    }
+
+    #[test]
+    fn test_decoy_reads() {
+        // the gas model charges a read the pass inserts cold once per slot and warm after that
+        let code = [0x60, 0x01, 0x50, 0x00]; // PUSH1 1, POP, STOP
+        let mut program = Program::lift(&code);
+        let baseline = Baseline::new(&program);
+        assert_eq!(baseline.costs(), [5]);
+        let read = [
+            Item::push(&[0x77; 32]),
+            Item::op(op::SLOAD),
+            Item::op(op::POP),
+        ];
+        program
+            .items
+            .splice(0..0, read.iter().chain(&read).cloned());
+        let added = 2 * (3 + 100 + 2) + gas::COLD_SLOAD;
+        assert_eq!(baseline.overhead(&program), added as f64 / 5.0);
+
+        // guards run in the functions, which pay for the cold read, but not in the fallback
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let config = |toml: &str| Config::from_toml(toml).unwrap();
+        let decoys =
+            config("[passes]\nenabled = [\"decoy-reads\"]\n[probabilities]\ndecoy-reads = 1.0");
+        for seed in 0..5 {
+            let obfuscation = decoys.obfuscate(&bytecode, seed).unwrap();
+            // two guards, and a read behind each of the three stops
+            assert_eq!(obfuscation.stats["decoy-reads"], 5);
+            for calldata in [
+                vec![],
+                vec![0xaa, 0xbb, 0xcc, 0xdd],
+                vec![0x11, 0x22, 0x33, 0x44],
+            ] {
+                let comparison =
+                    equivalence::compare(&bytecode, &obfuscation.bytecode, &calldata).unwrap();
+                assert_eq!(comparison.original.effects, comparison.obfuscated.effects);
+                let extra = comparison.obfuscated.gas_used - comparison.original.gas_used;
+                if calldata.is_empty() {
+                    assert_eq!(extra, 0, "seed {}", seed);
+                } else {
+                    assert!(extra > gas::COLD_SLOAD, "seed {}", seed);
+                }
+            }
+        }
+
+        // a store to a slot from calldata could hit a decoy slot, so no function is guarded
+        let untraced = hex::decode(TWO_FUNCTIONS.replace(' ', "").replacen(
+            "5b600160020100",
+            "5b60015f355500",
+            1,
+        ))
+        .unwrap();
+        let obfuscation = decoys.obfuscate(&untraced, 1).unwrap();
+        assert_eq!(obfuscation.stats["decoy-reads"], 3);
+        let calldata = [0x11, 0x22, 0x33, 0x44];
+        let comparison = equivalence::compare(&untraced, &obfuscation.bytecode, &calldata).unwrap();
+        assert_eq!(comparison.original.gas_used, comparison.obfuscated.gas_used);
+
+        // under a budget the pass backs off instead of adding cold reads to cheap functions
+        let budgeted = config(
+            "[passes]\nenabled = [\"decoy-reads\"]\n[probabilities]\ndecoy-reads = 1.0\n\
+             [budget]\nmax-gas-overhead = 50",
+        );
+        let obfuscation = budgeted.obfuscate(&bytecode, 1).unwrap();
+        let calldata = [0xaa, 0xbb, 0xcc, 0xdd];
+        let comparison = equivalence::compare(&bytecode, &obfuscation.bytecode, &calldata).unwrap();
+        assert!(comparison.obfuscated.gas_used - comparison.original.gas_used < gas::COLD_SLOAD);
+    }
+
+    #[test]
+    fn test_push_width() {
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let config = Config::from_toml(
+            "[passes]\nenabled = [\"push-width\"]\n[probabilities]\npush-width = 1.0",
+        )
+        .unwrap();
+        for seed in 0..5 {
+            let obfuscation = config.obfuscate(&bytecode, seed).unwrap();
+            let obfuscated = &obfuscation.bytecode;
+            // every push, jump targets included, is wider and pushes the same value
+            let pushes = |code: &[u8]| -> Vec<(u8, Vec<u8>)> {
+                disassemble(code)
+                    .into_iter()
+                    .filter(|ins| (op::PUSH1..=op::PUSH32).contains(&ins.opcode))
+                    .map(|ins| (ins.opcode, ins.immediate))
+                    .collect()
+            };
+            let (before, after) = (pushes(&bytecode), pushes(obfuscated));
+            assert_eq!(before.len(), after.len());
+            assert_eq!(obfuscation.stats["push-width"], before.len());
+            for ((opcode, _), (wider, immediate)) in before.iter().zip(&after) {
+                assert!(wider > opcode && wider - opcode <= 3, "seed {}", seed);
+                assert_eq!(immediate.len(), (wider - op::PUSH1 + 1) as usize);
+            }
+            for calldata in [
+                vec![],
+                vec![0xaa, 0xbb, 0xcc, 0xdd],
+                vec![0x11, 0x22, 0x33, 0x44],
+            ] {
+                let comparison = equivalence::compare(&bytecode, obfuscated, &calldata).unwrap();
+                assert_eq!(comparison.original.effects, comparison.obfuscated.effects);
+                assert_eq!(comparison.original.gas_used, comparison.obfuscated.gas_used);
+            }
+            // a widened push is no copy of the input instruction
+            let map = obfuscation.pc_map.unwrap();
+            assert!(map.obfuscated(0).is_empty());
+            assert_eq!(map.obfuscated(2).len(), 1); // CALLDATALOAD
+        }
+    }
+
+    #[test]
+    fn test_trampolines() {
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let config = Config::from_toml(
+            "[passes]\nenabled = [\"trampolines\"]\n[probabilities]\ntrampolines = 1.0",
+        )
+        .unwrap();
+        let calldata = [0xaa, 0xbb, 0xcc, 0xdd];
+        let mut lengths = BTreeSet::new();
+        for seed in 0..10 {
+            let obfuscation = config.obfuscate(&bytecode, seed).unwrap();
+            // both selector jumps go through a chain
+            assert_eq!(obfuscation.stats["trampolines"], 2);
+            let comparison =
+                equivalence::compare(&bytecode, &obfuscation.bytecode, &calldata).unwrap();
+            assert_eq!(comparison.original.effects, comparison.obfuscated.effects);
+
+            // the taken jumpi lands on a trampoline, and every hop ends in a jump to the next
+            let (_, steps) = trace::record(&obfuscation.bytecode, &calldata).unwrap();
+            let hops = steps.iter().filter(|step| step.opcode == op::JUMP).count();
+            assert!((1..=4).contains(&hops), "seed {}: {} hops", seed, hops);
+            let extra = comparison.obfuscated.gas_used - comparison.original.gas_used;
+            assert_eq!(extra, hops as u64 * (1 + 3 + 8));
+            lengths.insert(hops);
+        }
+        // the chaotic map gives chains of different lengths
+        assert!(lengths.len() > 1);
+    }
+
+    #[test]
+    fn test_merge() {
+        // each stores its own value after a jump, which must survive relocation
+        let first = hex::decode("600456fe5b6001600055 00".replace(' ', "")).unwrap();
+        let second = hex::decode("600456fe5b6002600055 00".replace(' ', "")).unwrap();
+        let config = Config::default();
+        let effects = |code: &[u8]| equivalence::execute(code, &[]).unwrap().effects;
+        // the evm runs on chain 1, at the address `equivalence` deploys to
+        for (switch, runs) in [
+            (Switch::ChainId(1), &first),
+            (Switch::ChainId(10), &second),
+            (Switch::Address([0xeb; 20]), &first),
+            (Switch::Address([0x01; 20]), &second),
+        ] {
+            let mut rng = seed::rng(7, None);
+            let merged = merge::merge(&first, &second, switch, &mut rng).unwrap();
+            assert_eq!(effects(&merged), effects(runs), "{}", switch);
+            let obfuscated = config.obfuscate(&merged, 7).unwrap().bytecode;
+            assert_eq!(effects(&obfuscated), effects(runs), "{}", switch);
+        }
+
+        // the switch value is masked, so it is not pushed as is
+        let merged =
+            merge::merge(&first, &second, Switch::ChainId(1), &mut seed::rng(7, None)).unwrap();
+        assert!(!merged
+            .windows(9)
+            .any(|window| window == [0x67, 0, 0, 0, 0, 0, 0, 0, 1]));
+
+        assert_eq!("chainid:137".parse(), Ok(Switch::ChainId(137)));
+        assert_eq!(
+            format!("address:0x{}", "eb".repeat(20)).parse(),
+            Ok(Switch::Address([0xeb; 20]))
+        );
+        assert!("address:0xeb".parse::<Switch>().is_err());
+        assert!("balance:1".parse::<Switch>().is_err());
+    }
+
+    #[test]
+    fn test_env_predicate() {
+        // the predicates hold only where the checker says they do
+        let mainnet = Target::from(Fork::Cancun);
+        let on = |chain| Target::new(Fork::Cancun, Some(chain));
+        assert!(env_predicate::check(Predicate::ChainId(1), mainnet).is_ok());
+        assert!(env_predicate::check(Predicate::ChainId(8453), on(Chain::Optimism)).is_ok());
+        assert!(env_predicate::check(Predicate::ChainId(1), on(Chain::Optimism)).is_err());
+        assert!(env_predicate::check(Predicate::Codesize, on(Chain::ZksyncEra)).is_ok());
+        assert!(env_predicate::check(Predicate::ChainId(1), Target::from(Fork::London)).is_ok());
+
+        // the chain id is configured, or known from a profile with a single id
+        let config = |toml: &str| Config::from_toml(toml).unwrap();
+        assert_eq!(
+            config("chain = \"arbitrum\"").chain_id().unwrap(),
+            Some(42_161)
+        );
+        assert_eq!(config("chain = \"optimism\"").chain_id().unwrap(), None);
+        assert_eq!(
+            config("chain-id = 10\nchain = \"optimism\"")
+                .chain_id()
+                .unwrap(),
+            Some(10)
+        );
+        assert!(config("chain-id = 1\nchain = \"arbitrum\"")
+            .chain_id()
+            .is_err());
+
+        // the same program as for gas predicates; the embedded evm runs with chain id 1
+        let code = hex::decode("602a600656005b600101806011575f5ffd5b5f5260205ff3").unwrap();
+        let guarded = config(
+            "chain-id = 1\n[passes]\nenabled = [\"env-predicate\"]\n\
+             [probabilities]\nenv-predicate = 1.0",
+        );
+        let mut chainid = false;
+        for seed in 0..20 {
+            let obfuscation = guarded.obfuscate(&code, seed).unwrap();
+            let obfuscated = obfuscation.bytecode;
+            assert_eq!(obfuscation.stats["env-predicate"], 2);
+            let run = equivalence::compare(&code, &obfuscated, &[]).unwrap();
+            assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+            chainid |= disassemble(&obfuscated)
+                .iter()
+                .any(|ins| ins.opcode == 0x46);
+        }
+        assert!(chainid);
+    }
+
+    #[test]
+    fn test_plausible_decoys() {
+        for fork in [Fork::London, Fork::Cancun] {
+            for seed in 0..20 {
+                let mut rng = StdRng::seed_from_u64(seed);
+                for idiom in Idiom::ALL {
+                    let mut program = Program::lift(&[0x00]); // STOP
+                    let region = idioms::generate(idiom, &mut program, &mut rng, fork);
+                    program.items.extend(region);
+                    let code = program.assemble().unwrap();
+                    // valid wherever it is entered, and never the push-pop filler it replaces
+                    assert!(validate::validate(&code, fork).is_empty(), "{:?}", idiom);
+                    let instructions = disassemble(&code);
+                    assert!(instructions
+                        .windows(2)
+                        .all(|pair| !(pair[0].opcode == 0x60 && pair[1].opcode == 0x50)));
+                    assert!(evm::is_terminator(instructions.last().unwrap().opcode));
+                    match idiom {
+                        Idiom::Dispatcher => {
+                            // read as the start of the code, it is taken for a dispatcher
+                            let mut program = Program::lift(&[]);
+                            let region = idioms::generate(idiom, &mut program, &mut rng, fork);
+                            program.items.extend(region);
+                            let code = program.assemble().unwrap();
+                            assert!(!dispatcher::functions(&code).is_empty());
+                        }
+                        Idiom::Revert => assert!(idioms::REASONS.iter().any(|reason| code
+                            .windows(reason.len())
+                            .any(|window| window == reason.as_bytes()))),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        // the regions behind stop and return are never run
+        let code = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let config =
+            Config::from_toml("[passes]\nenabled = [\"flower\"]\n[probabilities]\nflower = 1.0")
+                .unwrap();
+        for seed in 0..5 {
+            let obfuscation = config.obfuscate(&code, seed).unwrap();
+            assert!(obfuscation.stats["flower"] > 0);
+            for selector in [[0xAA, 0xBB, 0xCC, 0xDD], [0x11, 0x22, 0x33, 0x44], [0; 4]] {
+                let run = equivalence::compare(&code, &obfuscation.bytecode, &selector).unwrap();
+                assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+            }
+        }
+    }
+
+    #[test]
+    fn test_exception_flow() {
+        // the program of the predicate tests, whose first jump is static
+        let code = hex::decode("602a600656005b600101806011575f5ffd5b5f5260205ff3").unwrap();
+        let config = |toml: &str| Config::from_toml(toml).unwrap();
+        let traps = config(
+            "[passes]\nenabled = [\"exception-flow\"]\n[probabilities]\nexception-flow = 1.0",
+        );
+        // runs `code` as the init code of a contract creation
+        let construct = |code: &[u8]| {
+            let mut evm = revm::Evm::builder()
+                .with_db(equivalence::deploy(&[]))
+                .with_spec_id(equivalence::SPEC)
+                .modify_tx_env(|tx| {
+                    equivalence::call(tx, &[]);
+                    tx.transact_to = revm::primitives::TxKind::Create;
+                    tx.data = code.to_vec().into();
+                })
+                .build();
+            equivalence::observe(evm.transact().unwrap()).effects
+        };
+        for seed in 0..10 {
+            let obfuscation = traps.obfuscate(&code, seed).unwrap();
+            let obfuscated = obfuscation.bytecode;
+            // one jump past a trap, and a failing call at both jumpdests
+            assert_eq!(obfuscation.stats["exception-flow"], 3);
+            let opcodes: Vec<u8> = disassemble(&obfuscated)
+                .iter()
+                .map(|ins| ins.opcode)
+                .collect();
+            // PUSH1 42, CODESIZE, PUSH1 <target>, JUMPI, then INVALID or PUSH0 of a bad jump
+            assert_eq!(opcodes[1..4], [0x38, 0x60, op::JUMPI]);
+            assert!([op::INVALID, op::PUSH0].contains(&opcodes[4]));
+            assert_eq!(opcodes.iter().filter(|&&opcode| opcode == 0xFA).count(), 2);
+            let run = equivalence::compare(&code, &obfuscated, &[]).unwrap();
+            assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+            // as init code the contract has no code yet, so the call to itself succeeds
+            assert_eq!(construct(&obfuscated), construct(&code), "seed {}", seed);
+        }
+
+        // code making calls of its own keeps its return data: only the jump is rewritten
+        // PUSH0 x5, PUSH1 1, GAS, CALL, POP, PUSH1 0x0d, JUMP, JUMPDEST, STOP
+        let calling = hex::decode("5f5f5f5f5f60015af150600d565b00").unwrap();
+        let obfuscation = traps.obfuscate(&calling, 1).unwrap();
+        assert_eq!(obfuscation.stats["exception-flow"], 1);
+        assert!(!disassemble(&obfuscation.bytecode)
+            .iter()
+            .any(|ins| ins.opcode == 0xFA));
+        let run = equivalence::compare(&calling, &obfuscation.bytecode, &[]).unwrap();
+        assert!(run.divergence().is_none(), "{:?}", run);
+    }
+
+    #[test]
+    fn test_revert_strings() {
+        let reason = b"Ownable: caller is not the owner";
+        // without calldata, revert with Error(reason) the way solc builds it, behind the free
+        // memory pointer
+        let string_revert = hex::decode(format!(
+            "6080604052 36605457 604051 62461bcd60e51b 815260206004820152 60206024820152 7f{} 604482015260640160405180910390fd 5b00",
+            hex::encode(reason)
+        ).replace(' ', ""))
+        .unwrap();
+        // without calldata, revert with the custom error 0x82b42900 (Unauthorized())
+        let custom_revert =
+            hex::decode("36601357 6382b4290060e01b5f5260045ffd 5b00".replace(' ', "")).unwrap();
+        let config = |mode: &str| {
+            Config::from_toml(&format!(
+                "revert-strings = \"{}\"\n[passes]\nenabled = [\"revert-strings\"]",
+                mode
+            ))
+            .unwrap()
+        };
+        let contains = |code: &[u8], needle: &[u8]| code.windows(needle.len()).any(|w| w == needle);
+
+        for seed in 0..5 {
+            // encoded, neither the text nor the selectors are left, and the revert data is the same
+            for (code, hidden) in [
+                (&string_revert, &reason[..8]),
+                (&string_revert, &[0x46, 0x1B, 0xCD][..]),
+                (&custom_revert, &[0x82, 0xB4, 0x29, 0x00][..]),
+            ] {
+                let obfuscation = config("encode").obfuscate(code, seed).unwrap();
+                assert!(!contains(&obfuscation.bytecode, hidden), "seed {}", seed);
+                let run = equivalence::compare(code, &obfuscation.bytecode, &[]).unwrap();
+                assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+            }
+            assert_eq!(
+                config("encode")
+                    .obfuscate(&string_revert, seed)
+                    .unwrap()
+                    .stats["revert-strings"],
+                2
+            );
+        }
+
+        // stripped, the caller sees an Error(string) of the same length without the text, and a
+        // custom error without its selector
+        let stripped = config("strip").obfuscate(&string_revert, 1).unwrap();
+        assert_eq!(stripped.stats["revert-strings"], 1);
+        let run = equivalence::compare(&string_revert, &stripped.bytecode, &[]).unwrap();
+        let (original, obfuscated) = (&run.original.effects, &run.obfuscated.effects);
+        assert_eq!(obfuscated.status, equivalence::Status::Revert);
+        assert_eq!(original.output[..0x44], obfuscated.output[..0x44]);
+        assert_eq!(original.output[0x44..], *reason);
+        assert_eq!(obfuscated.output[0x44..], [0; 32]);
+        let stripped = config("strip").obfuscate(&custom_revert, 1).unwrap();
+        let run = equivalence::compare(&custom_revert, &stripped.bytecode, &[]).unwrap();
+        assert_eq!(run.obfuscated.effects.output, [0; 4]);
+
+        // strings a function returns are not revert reasons
+        // mstore(0, "Token"), return(0, 0x20)
+        let returning = hex::decode("64546f6b656e60d81b5f5260205ff3").unwrap();
+        let obfuscation = config("strip").obfuscate(&returning, 1).unwrap();
+        assert_eq!(obfuscation.stats["revert-strings"], 0);
+        assert_eq!(obfuscation.bytecode, returning);
+    }
+
+    #[test]
+    fn test_storage_remap() {
+        // sstore(0, calldataload(0)), sstore(1, 5), mstore(0, caller), sstore(keccak(0, 32), 7),
+        // return(sload(1) + sload(0))
+        let bytecode = hex::decode(
+            "5f35600055 6005600155 335f5260205f2060079055 6001545f54015f5260205ff3"
+                .replace(' ', ""),
+        )
+        .unwrap();
+        let argument = U256::from(0x2A).to_be_bytes::<32>();
+        let remapping = Config::from_toml("storage-remap = true").unwrap();
+        assert_eq!(remapping.pipeline().unwrap().names()[0], "storage-remap");
+        assert!(!Config::default()
+            .pipeline()
+            .unwrap()
+            .names()
+            .contains(&"storage-remap"));
+
+        for seed in 0..5 {
+            let obfuscation = remapping.obfuscate(&bytecode, seed).unwrap();
+            let slots = &obfuscation.slots;
+            assert_eq!(slots.slots.len(), 2, "seed {}", seed);
+            assert_eq!(obfuscation.stats["storage-remap"], 4);
+
+            // the same result, with every constant slot written where the map says
+            let run = equivalence::compare(&bytecode, &obfuscation.bytecode, &argument).unwrap();
+            let (original, obfuscated) = (&run.original.effects, &run.obfuscated.effects);
+            assert_eq!(original.output, obfuscated.output, "seed {}", seed);
+            let moved: BTreeMap<U256, U256> = original
+                .storage
+                .iter()
+                .map(|(slot, value)| {
+                    let slot = slot.to_be_bytes::<32>();
+                    (U256::from_be_bytes(slots.get(slot)), *value)
+                })
+                .collect();
+            assert_eq!(moved, obfuscated.storage, "seed {}", seed);
+
+            let json: serde_json::Value = serde_json::from_str(&slots.to_json()).unwrap();
+            assert_eq!(json.as_object().unwrap().len(), 2);
+            assert!(json.get(format!("0x{}", "00".repeat(32))).is_some());
+        }
+
+        // a slot read from calldata could be any of them, so nothing moves
+        let untraced = hex::decode("600160005560003554 5f5260205ff3".replace(' ', "")).unwrap();
+        let alone = Config::from_toml("storage-remap = true\n[passes]\nenabled = []").unwrap();
+        let obfuscation = alone.obfuscate(&untraced, 1).unwrap();
+        assert!(obfuscation.slots.is_empty());
+        assert_eq!(obfuscation.bytecode, untraced);
+    }
+
+    #[test]
+    fn test_interface() {
+        // a dispatcher whose only function 0xaabbccdd emits Transfer and returns a word
+        let transfer = "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+        let contract = |topic: &str, returned: &str| {
+            hex::decode(format!(
+                "5f3560e01c8063aabbccdd14601057005b7f{}5f5fa1{}5ff3",
+                topic, returned
+            ))
+            .unwrap()
+        };
+        let code = contract(transfer, "6020");
+        let selectors: Vec<[u8; 4]> = dispatcher::functions(&code)
+            .iter()
+            .map(|function| function.selector)
+            .collect();
+        assert_eq!(selectors, [[0xaa, 0xbb, 0xcc, 0xdd]]);
+        let interface = Interface::of(&code, &selectors).unwrap();
+        assert_eq!(interface.calls.len(), 3);
+        assert_eq!(interface.calls[0].topics, Vec::<Vec<[u8; 32]>>::new());
+        assert_eq!(interface.calls[2].selector, Some([0xff; 4]));
+        assert_eq!(interface.calls[2].topics, Vec::<Vec<[u8; 32]>>::new());
+        assert_eq!(
+            interface.calls[1].topics[0][0][..4],
+            [0xdd, 0xf2, 0x52, 0xad]
+        );
+        assert_eq!(interface.calls[1].returned, Some(32));
+        assert!(interface.changes(&interface).is_empty());
+
+        // another event signature, or a return of another size, is a change
+        let renamed = Interface::of(&contract(&"ee".repeat(32), "6020"), &selectors).unwrap();
+        let changes = interface.changes(&renamed);
+        assert!(matches!(changes[..], [interface::Change::Topics { .. }]));
+        assert!(changes[0]
+            .to_string()
+            .starts_with("0xaabbccdd: event topics"));
+        let widened = Interface::of(&contract(transfer, "6040"), &selectors).unwrap();
+        assert_eq!(
+            interface.changes(&widened),
+            [interface::Change::Returns {
+                selector: Some([0xaa, 0xbb, 0xcc, 0xdd]),
+                before: 32,
+                after: 64
+            }]
+        );
+
+        // a dispatcher comparing the selector with itself routes unknown selectors into the function
+        let misrouted =
+            hex::decode(hex::encode(&code).replacen("8063aabbccdd", "63aabbccdd80", 1)).unwrap();
+        let changes = interface.changes(&Interface::of(&misrouted, &selectors).unwrap());
+        // the call without calldata goes there too, since selector 0 is unknown as well
+        let changed: Vec<String> = changes.iter().map(ToString::to_string).collect();
+        assert_eq!(changed.len(), 4);
+        assert!(changed[2].starts_with("0xffffffff: event topics"));
+        assert_eq!(changed[3], "0xffffffff: returns 0 bytes != 32 bytes");
+
+        // storage written elsewhere is a change, unless the slot map says it moved there
+        let (stores, moved) = (
+            hex::decode("6001600055 00".replace(' ', "")).unwrap(),
+            hex::decode("6001600755 00".replace(' ', "")).unwrap(),
+        );
+        let err = interface::check(&stores, &moved, &SlotMap::default()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<EboError>(),
+            Some(EboError::Verification(_))
+        ));
+        assert!(err.to_string().contains("fallback: storage"), "{}", err);
+        let mut slots = SlotMap::default();
+        let mut seven = [0; 32];
+        seven[31] = 7;
+        slots.record(&[([0; 32], seven)].into());
+        assert!(interface::check(&stores, &moved, &slots).is_ok());
+
+        // the passes keep the interface, so the run goes through
+        let config = Config::default();
+        for seed in 0..5 {
+            let obfuscation = config.obfuscate(&code, seed).unwrap();
+            let obfuscated = Interface::of(&obfuscation.bytecode, &selectors).unwrap();
+            assert_eq!(obfuscated, interface, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_return_path() {
+        let config = |toml: &str| Config::from_toml(toml).unwrap();
+        let routed = |fork: &str| {
+            config(&format!(
+                "fork = \"{}\"\n[passes]\nenabled = [\"return-path\"]\n\
+                 [probabilities]\nreturn-path = 1.0",
+                fork
+            ))
+        };
+        // the program of the predicate tests, which reverts or returns a word it stored at 0
+        let code = hex::decode("602a600656005b600101806011575f5ffd5b5f5260205ff3").unwrap();
+        // mstore(0, 42), revert(0, 0x20)
+        let reverting = hex::decode("602a5f5260205ffd").unwrap();
+        let mut copied = false;
+        for seed in 0..10 {
+            for program in [&code, &reverting] {
+                let obfuscation = routed("cancun").obfuscate(program, seed).unwrap();
+                let obfuscated = obfuscation.bytecode;
+                let opcodes: Vec<u8> = disassemble(&obfuscated)
+                    .iter()
+                    .map(|ins| ins.opcode)
+                    .collect();
+                // every exit jumps to the one epilogue of its kind, which no push points at
+                let exits = |code: &[u8]| {
+                    disassemble(code)
+                        .iter()
+                        .filter(|ins| matches!(ins.opcode, op::RETURN | op::REVERT))
+                        .count()
+                };
+                assert_eq!(obfuscation.stats["return-path"], exits(program));
+                assert_eq!(exits(&obfuscated), exits(program));
+                assert!(opcodes.windows(2).any(|pair| pair == [op::SUB, op::JUMP]));
+                copied |= opcodes.contains(&op::MCOPY);
+                let run = equivalence::compare(program, &obfuscated, &[]).unwrap();
+                assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+            }
+        }
+        assert!(copied);
+
+        // before cancun there is no mcopy to copy the data with
+        for seed in 0..5 {
+            let obfuscated = routed("shanghai")
+                .obfuscate(&reverting, seed)
+                .unwrap()
+                .bytecode;
+            assert!(!disassemble(&obfuscated)
+                .iter()
+                .any(|ins| ins.opcode == op::MCOPY));
+            let run = equivalence::compare(&reverting, &obfuscated, &[]).unwrap();
+            assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+        }
+    }
+
+    #[test]
+    fn test_scratch_layout() {
+        let config = |toml: &str| Config::from_toml(toml).unwrap();
+        let moving = config(
+            "[passes]\nenabled = [\"scratch-layout\"]\n[probabilities]\nscratch-layout = 1.0",
+        );
+        // mstore(0, 42), return(0, 0x20): the returned word can be built anywhere
+        let returning = hex::decode("602a5f5260205ff3").unwrap();
+        // solc's prologue, then sstore(0, keccak256(mstore(0, caller), 0x20))
+        let hashing = hex::decode("608060405233 5f52 60205f20 5f55 00".replace(' ', "")).unwrap();
+        let mut offsets = BTreeSet::new();
+        for seed in 0..10 {
+            for code in [&returning, &hashing] {
+                let obfuscation = moving.obfuscate(code, seed).unwrap();
+                let run = equivalence::compare(code, &obfuscation.bytecode, &[]).unwrap();
+                assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+                offsets.insert(obfuscation.bytecode.clone());
+            }
+        }
+        assert!(offsets.len() > 4);
+
+        // without solc's prologue, nothing is known about the memory a hash leaves behind
+        let bare = &hashing[5..];
+        assert_eq!(
+            ebo::passes::scratch::groups(&Program::lift(bare).items).len(),
+            1
+        );
+        let obfuscation = moving.obfuscate(bare, 1).unwrap();
+        assert_eq!(obfuscation.stats["scratch-layout"], 0);
+        assert_eq!(obfuscation.bytecode, bare);
+    }
+
+    #[test]
+    fn test_stack_shuffle() {
+        // PUSH1 2, 8, 100, 3, then a block reading all four: SUB, SWAP2, ADD, MUL, returned
+        let code = hex::decode(
+            "6002 6008 6064 6003 600b 56 5b 03 91 01 02 5f 52 6020 5f f3".replace(' ', ""),
+        )
+        .unwrap();
+        let config = |toml: &str| Config::from_toml(toml).unwrap();
+        let shuffling =
+            config("[passes]\nenabled = [\"stack-shuffle\"]\n[probabilities]\nstack-shuffle = 1.0");
+        let mut layouts = BTreeSet::new();
+        for seed in 0..10 {
+            let obfuscation = shuffling.obfuscate(&code, seed).unwrap();
+            // only the second block has items of its own to shuffle
+            assert_eq!(obfuscation.stats["stack-shuffle"], 1);
+            let obfuscated = obfuscation.bytecode;
+            let run = equivalence::compare(&code, &obfuscated, &[]).unwrap();
+            assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+            layouts.insert(obfuscated);
+        }
+        assert!(layouts.len() > 1);
+
+        let all = Config::default();
+        for seed in 0..5 {
+            let obfuscated = all.obfuscate(&code, seed).unwrap().bytecode;
+            let run = equivalence::compare(&code, &obfuscated, &[]).unwrap();
+            assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+        }
+    }
+
+    #[test]
+    fn test_inline() {
+        // double(double(3)), with double(x) at 0x17: JUMPDEST, DUP1, ADD, SWAP1, JUMP
+        let code = hex::decode(
+            "610008 6003 6017 56 5b 610010 90 6017 56 5b 5f 52 6020 5f f3 5b 80 01 90 56"
+                .replace(' ', ""),
+        )
+        .unwrap();
+        let calls = ebo::passes::inline::calls(&Program::lift(&code).items);
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].function, calls[1].function);
+
+        let config = |toml: &str| Config::from_toml(toml).unwrap();
+        let inlining = config("[passes]\nenabled = [\"inline\"]\n[probabilities]\ninline = 1.0");
+        let obfuscation = inlining.obfuscate(&code, 1).unwrap();
+        assert_eq!(obfuscation.stats["inline"], 2);
+        let opcodes: Vec<u8> = disassemble(&obfuscation.bytecode)
+            .iter()
+            .map(|ins| ins.opcode)
+            .collect();
+        // both calls return from their own copy of the body; the function itself is left behind
+        assert_eq!(
+            opcodes.iter().filter(|&&opcode| opcode == op::ADD).count(),
+            3
+        );
+        assert_eq!(
+            opcodes.iter().filter(|&&opcode| opcode == op::JUMP).count(),
+            3
+        );
+        let run = equivalence::compare(&code, &obfuscation.bytecode, &[]).unwrap();
+        assert!(run.divergence().is_none(), "{:?}", run);
+        assert_eq!(run.obfuscated.effects.output[31], 12);
+
+        let all = Config::default();
+        for seed in 0..5 {
+            let obfuscated = all.obfuscate(&code, seed).unwrap().bytecode;
+            let run = equivalence::compare(&code, &obfuscated, &[]).unwrap();
+            assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+        }
+    }
+
+    #[test]
+    fn test_loops() {
+        // for (i = 0; i < 10; i++) sum += i; return sum
+        let code = hex::decode(
+            "6000 6000 5b 80 600a 11 15 6017 57 80 91 01 90 6001 01 6004 56 5b 50 6000 52 6020 6000 f3"
+                .replace(' ', ""),
+        )
+        .unwrap();
+        let found = ebo::passes::loops::find(&Program::lift(&code).items);
+        assert_eq!(found.len(), 1);
+
+        let config = |toml: &str| Config::from_toml(toml).unwrap();
+        let reshaped = config("[passes]\nenabled = [\"loops\"]\n[probabilities]\nloops = 1.0");
+        let (mut rotated, mut unrolled) = (false, false);
+        for seed in 0..10 {
+            let obfuscation = reshaped.obfuscate(&code, seed).unwrap();
+            assert_eq!(obfuscation.stats["loops"], 1);
+            let obfuscated = obfuscation.bytecode;
+            // the step and the bound are no longer pushed as they were
+            assert!(!obfuscated.windows(3).any(|w| w == [0x60, 0x01, 0x01]));
+            assert!(!obfuscated.windows(3).any(|w| w == [0x60, 0x0a, 0x11]));
+            // a rotated loop is entered by a jump over the body
+            let opcodes: Vec<u8> = disassemble(&obfuscated)
+                .iter()
+                .map(|ins| ins.opcode)
+                .collect();
+            rotated |= opcodes[3] == op::JUMP;
+            // every copy of the body steps the counter with its own encoded constant
+            unrolled |= opcodes.iter().filter(|&&opcode| opcode == op::SUB).count() == 2;
+            let run = equivalence::compare(&code, &obfuscated, &[]).unwrap();
+            assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+            assert_eq!(run.obfuscated.effects.output[31], 45);
+        }
+        assert!(rotated && unrolled);
+
+        // reshaped loops survive the rest of the pipeline
+        let all = Config::default();
+        for seed in 0..5 {
+            let obfuscated = all.obfuscate(&code, seed).unwrap().bytecode;
+            let run = equivalence::compare(&code, &obfuscated, &[]).unwrap();
+            assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+        }
+    }
+
+    #[test]
+    fn test_hardhat_artifact_input() {
+        // stores the address of library L, linked in by the deploy script
+        let placeholder = "__$8a8c29fb1e2bdbb5f2f8e4f2d1c1b5a0e0$__";
+        let runtime = format!("73{}600055 00", placeholder).replace(' ', "");
+        let constructor =
+            format!("61{:04x} 80 61000b 5f 39 5f f3", runtime.len() / 2).replace(' ', "");
+        let references = |start: usize| {
+            format!(
+                r#"{{"contracts/L.sol": {{"L": [{{"length": 20, "start": {}}}]}}}}"#,
+                start
+            )
+        };
+        let source = format!(
+            r#"{{"_format": "hh-sol-artifact-1", "contractName": "C", "abi": [],
+                "bytecode": "0x{}{}", "deployedBytecode": "0x{}",
+                "linkReferences": {}, "deployedLinkReferences": {}}}"#,
+            constructor,
+            runtime,
+            runtime,
+            references(constructor.len() / 2 + 1),
+            references(1)
+        );
+
+        let mut artifact = Artifact::parse(source.as_bytes()).unwrap();
+        assert_eq!(artifact.layout(), artifact::Layout::Hardhat);
+        let code = artifact.runtime().unwrap();
+        let obfuscated = Config::default()
+            .obfuscator(&code, 42)
+            .unwrap()
+            .obfuscate()
+            .unwrap();
+        artifact.replace_runtime(&obfuscated, None).unwrap();
+
+        // the placeholders are back, where the link references say they are
+        let json: serde_json::Value = serde_json::from_str(&artifact.to_json()).unwrap();
+        let keys: Vec<&String> = json.as_object().unwrap().keys().collect();
+        assert_eq!(keys[..2], ["_format", "contractName"]);
+        for (code, references) in [
+            ("deployedBytecode", "deployedLinkReferences"),
+            ("bytecode", "linkReferences"),
+        ] {
+            let digits = json[code].as_str().unwrap();
+            let starts = json[references]["contracts/L.sol"]["L"].as_array().unwrap();
+            assert!(!starts.is_empty());
+            for start in starts {
+                let start = start["start"].as_u64().unwrap() as usize;
+                assert_eq!(&digits[2 + 2 * start..2 + 2 * start + 40], placeholder);
+            }
+            assert_eq!(digits.matches(placeholder).count(), starts.len());
+        }
+
+        // linked the way hardhat links, the obfuscated contract stores the same address
+        let link = |digits: &str| {
+            hex::decode(
+                digits
+                    .trim_start_matches("0x")
+                    .replace(placeholder, &"ab".repeat(20)),
+            )
+            .unwrap()
+        };
+        let run = equivalence::compare(
+            &link(&runtime),
+            &link(json["deployedBytecode"].as_str().unwrap()),
+            &[],
+        )
+        .unwrap();
+        assert!(run.divergence().is_none());
+        assert_eq!(run.obfuscated.effects.storage.len(), 1);
+        let deployed =
+            equivalence::execute(&link(json["bytecode"].as_str().unwrap()), &[]).unwrap();
+        assert_eq!(
+            deployed.effects.output,
+            link(json["deployedBytecode"].as_str().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_unlinked_hex() {
+        // solc --bin output of code storing library L's address at slot 1 + 2
+        let placeholder = "__$8a8c29fb1e2bdbb5f2f8e4f2d1c1b5a0e0$__";
+        let digits = format!("73{}6001600201 55 00", placeholder).replace(' ', "");
+        assert_eq!(corpus::detect(digits.as_bytes()), corpus::Format::Hex);
+        let placeholders = link::placeholders(&digits);
+        assert_eq!(placeholders.len(), 1);
+        let code = corpus::decode_hex(&digits).unwrap();
+        assert_eq!(link::regions(&code), vec![0..21]);
+
+        let config = Config::from_toml("[probabilities]\nintensity = 4.0").unwrap();
+        for seed in 0..10 {
+            let obfuscated = config.obfuscate(&code, seed).unwrap().bytecode;
+            let (relinked, references) = link::relink(&obfuscated, &placeholders).unwrap();
+
+            // the placeholder is back, whole, where the link references say it is
+            let offsets = &references[placeholder];
+            assert_eq!(relinked.matches(placeholder).count(), offsets.len());
+            for offset in offsets {
+                assert_eq!(offset.length, 20);
+                let start = 2 * offset.start;
+                assert_eq!(&relinked[start - 2..start], "73");
+                assert_eq!(&relinked[start..start + 40], placeholder);
+            }
+
+            // and linking it stores the same address as linking the original
+            let linked = |digits: &str| hex::decode(digits.replace(placeholder, &"ab".repeat(20)));
+            let run =
+                equivalence::compare(&linked(&digits).unwrap(), &linked(&relinked).unwrap(), &[])
+                    .unwrap();
+            assert!(run.divergence().is_none(), "seed {}", seed);
+        }
+
+        // code that lost a library address cannot be relinked
+        assert!(link::relink(&code[21..], &placeholders).is_err());
+    }
+
+    #[test]
+    fn test_solc_standard_json() {
+        let mut input = serde_json::json!({"language": "Solidity", "sources": {},
+            "settings": {"outputSelection": {"*": {"*": ["abi", "evm.bytecode"]}}}});
+        solc::select_outputs(&mut input).unwrap();
+        assert_eq!(
+            input["settings"]["outputSelection"]["*"]["*"],
+            serde_json::json!(["abi", "evm.bytecode", "evm.deployedBytecode"])
+        );
+        // the evm version the input is compiled for is the fork the output is kept valid for
+        assert_eq!(solc::evm_version(&input), None);
+        input["settings"]["evmVersion"] = "shanghai".into();
+        assert_eq!(solc::evm_version(&input), Some(Fork::Shanghai));
+        input["settings"]["evmVersion"] = "byzantium".into();
+        assert_eq!(solc::evm_version(&input), None);
+
+        let runtime = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let constructor = format!("61{:04x} 80 61000b 5f 39 5f f3", runtime.len()).replace(' ', "");
+        let output = serde_json::json!({
+            "errors": [{"severity": "warning", "message": "unused variable"}],
+            "contracts": {"src/C.sol": {
+                "C": {"abi": [], "evm": {
+                    "bytecode": {"object": format!("{}{}", constructor, hex::encode(&runtime))},
+                    "deployedBytecode": {"object": hex::encode(&runtime)}}},
+                "I": {"abi": [], "evm": {
+                    "bytecode": {"object": ""}, "deployedBytecode": {"object": ""}}}
+            }}
+        });
+        assert!(solc::errors(&output).is_empty());
+
+        // a stand-in compiler that prints the output above
+        let dir = std::env::temp_dir().join(format!("ebo-solc-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("solc");
+        fs::write(
+            &script,
+            format!("#!/bin/sh\ncat > /dev/null\necho '{}'\n", output),
+        )
+        .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let located = solc::locate(Some(&script), None).unwrap();
+        let mut compiled = solc::compile(&located, &input).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(compiled, output);
+
+        let outcomes =
+            solc::substitute(&mut compiled, |code| Config::default().obfuscate(code, 42));
+        // the interface has no code and is left out
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].contract, "src/C.sol:C");
+        let (before, after) = *outcomes[0].result.as_ref().unwrap();
+        assert_eq!(before, runtime.len());
+        let evm = &compiled["contracts"]["src/C.sol"]["C"]["evm"];
+        let deployed = evm["deployedBytecode"]["object"].as_str().unwrap();
+        // solc's unprefixed hex is kept
+        assert_eq!(deployed.len(), 2 * after);
+        let creation = hex::decode(evm["bytecode"]["object"].as_str().unwrap()).unwrap();
+        let run = equivalence::execute(&creation, &[]).unwrap();
+        assert_eq!(hex::encode(run.effects.output), deployed);
+
+        let failing = serde_json::json!({"errors": [
+            {"severity": "error", "formattedMessage": "ParserError: expected ';'\n"}]});
+        assert_eq!(solc::errors(&failing), ["ParserError: expected ';'"]);
+    }
+
+    /// serves one http response per connection with the json `bodies` given, in order.
+    fn serve_json(bodies: Vec<String>) -> String {
+        use std::io::{BufRead, BufReader, Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for body in bodies {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut request = vec![0; length];
+                reader.read_exact(&mut request).unwrap();
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_rpc_get_code() {
+        let address = rpc::parse_address("0x00000000000000000000000000000000000000aa").unwrap();
+        assert_eq!(address[19], 0xaa);
+        assert!(rpc::parse_address("0x1234").is_err());
+        assert!(rpc::parse_address("not an address").is_err());
+
+        let url = serve_json(
+            [
+                r#""result":"0x6001600101""#,
+                r#""result":"0x""#,
+                r#""error":{"code":-32602,"message":"invalid block"}"#,
+            ]
+            .map(|result| format!(r#"{{"jsonrpc":"2.0","id":1,{}}}"#, result))
+            .to_vec(),
+        );
+        assert_eq!(
+            rpc::get_code(&url, &address, "latest").unwrap(),
+            vec![0x60, 0x01, 0x60, 0x01, 0x01]
+        );
+        let eoa = rpc::get_code(&url, &address, "17000000").unwrap_err();
+        assert!(eoa.to_string().contains("0x1036640"), "{}", eoa);
+        let error = rpc::get_code(&url, &address, "nope").unwrap_err();
+        assert!(error.to_string().contains("invalid block"), "{}", error);
+    }
+
+    #[test]
+    fn test_explorer_fetch() {
+        assert_eq!(explorer::parse_chain("base").unwrap(), 8453);
+        assert_eq!(explorer::parse_chain("Sepolia").unwrap(), 11_155_111);
+        assert_eq!(explorer::parse_chain("42161").unwrap(), 42161);
+        assert!(explorer::parse_chain("atlantis").is_err());
+
+        let abi = r#"[{"type":"function","name":"transfer","inputs":[{"type":"address"},{"type":"uint256"}]},{"type":"function","name":"approve","inputs":[{"type":"address"},{"type":"uint256"}]},{"type":"event","name":"Transfer","inputs":[]}]"#;
+        let source = serde_json::json!({"status": "1", "message": "OK", "result": [{
+            "SourceCode": "contract Token {}",
+            "ABI": abi,
+            "ContractName": "Token",
+            "CompilerVersion": "v0.8.26+commit.8a97fa7a",
+            "OptimizationUsed": "1",
+            "Runs": "200",
+        }]});
+        let url = serve_json(vec![
+            r#"{"jsonrpc":"2.0","id":1,"result":"0x6001600101"}"#.to_string(),
+            source.to_string(),
+            r#"{"status":"0","message":"NOTOK","result":"Invalid API Key"}"#.to_string(),
+        ]);
+        let explorer = Explorer::new(&url, 1, Some("key".into()));
+        let address = rpc::parse_address("0x00000000000000000000000000000000000000aa").unwrap();
+        let contract = explorer.fetch(&address, "latest").unwrap();
+        assert_eq!(contract.code, vec![0x60, 0x01, 0x60, 0x01, 0x01]);
+        let verified = contract.verified.unwrap();
+        assert_eq!(verified.name, "Token");
+        assert_eq!(verified.compiler, "v0.8.26+commit.8a97fa7a");
+        assert_eq!(verified.optimizer_runs, Some(200));
+        assert_eq!(verified.functions.len(), 2);
+        let error = explorer.fetch(&address, "latest").unwrap_err();
+        assert!(error.to_string().contains("Invalid API Key"), "{}", error);
+
+        // unverified contracts have an empty source and a message in place of the abi
+        let unverified = serde_json::json!({"status": "1", "message": "OK", "result": [{
+            "SourceCode": "",
+            "ABI": "Contract source code not verified",
+        }]});
+        assert_eq!(explorer::verified(&unverified).unwrap(), None);
+
+        // function names and signatures in the scope resolve to selectors through the abi
+        let mut config = Config::default();
+        config.scope.only_selectors = vec![
+            "transfer".into(),
+            "approve(address,uint256)".into(),
+            "0x01020304".into(),
+        ];
+        config.scope.resolve_names(&verified.functions).unwrap();
+        assert_eq!(
+            config.scope.only_selectors,
+            ["0xa9059cbb", "0x095ea7b3", "0x01020304"]
+        );
+        config.scope.only_selectors = vec!["mint".into()];
+        assert!(config.scope.resolve_names(&verified.functions).is_err());
+    }
+
+    #[test]
+    fn test_abi_calls() {
+        let functions = abi::parse(
+            r#"{"abi": [
+                {"type": "constructor", "inputs": []},
+                {"type": "function", "name": "transfer", "inputs": [
+                    {"name": "to", "type": "address"}, {"name": "amount", "type": "uint256"}]},
+                {"type": "function", "name": "submit", "inputs": [
+                    {"type": "tuple[]", "components": [{"type": "bytes"}, {"type": "int8"}]},
+                    {"type": "bool"}]},
+                {"type": "event", "name": "Transfer", "inputs": []}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(functions.len(), 2);
+        assert_eq!(functions[0].selector(), [0xa9, 0x05, 0x9c, 0xbb]);
+        assert_eq!(functions[1].signature(), "submit((bytes,int8)[],bool)");
+        assert!(abi::parse(
+            r#"[{"type": "function", "name": "f", "inputs": [{"type": "uint7"}]}]"#
+        )
+        .is_err());
+
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..20 {
+            let call = functions[0].random_call(&mut rng);
+            assert_eq!(call.len(), 4 + 64);
+            assert!(call[4..16].iter().all(|&b| b == 0));
+            let call = functions[1].random_call(&mut rng);
+            // the array is behind an offset just past the two head words
+            assert_eq!(call[4 + 31], 0x40);
+            assert!(call[4 + 63] <= 1);
+            assert_eq!((call.len() - 4) % 32, 0);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_finds_divergence() {
+        let original = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let report = fuzz::Fuzzer::new(&original, &original, Vec::new())
+            .run(50, 0)
+            .unwrap();
+        assert_eq!(report.runs, 50);
+        assert!(report.divergence.is_none());
+
+        // the second function stores instead of adding
+        let mut broken = original.clone();
+        let last = broken.len() - 2;
+        broken[last] = 0x55;
+        let report = fuzz::Fuzzer::new(&original, &broken, Vec::new())
+            .run(50, 0)
+            .unwrap();
+        let (input, divergence) = report.divergence.unwrap();
+        assert_eq!(input, fuzz::Input::Selector([0x11, 0x22, 0x33, 0x44]));
+        assert_eq!(divergence.calldata[..4], [0x11, 0x22, 0x33, 0x44]);
+        assert!(report.runs < 50);
+    }
+
+    #[test]
+    fn test_trace_alignment() {
+        let original = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let substitute = Config::from_toml(
+            "[passes]\nenabled = [\"substitution\"]\n[probabilities]\nsubstitution = 1.0",
+        )
+        .unwrap();
+        let obfuscated = Obfuscator::with_pipeline(&original, 42, substitute.pipeline().unwrap())
+            .obfuscate()
+            .unwrap();
+        let calldata = [0x11, 0x22, 0x33, 0x44];
+
+        // the substituted add runs more steps, but both traces end on the same stop
+        let (_, steps_a) = trace::record(&original, &calldata).unwrap();
+        let (_, steps_b) = trace::record(&obfuscated, &calldata).unwrap();
+        assert_eq!(steps_a.len(), 19);
+        assert_eq!(steps_a[1].inputs, [U256::ZERO]);
+        assert!(steps_b.len() > steps_a.len());
+        let rows = trace::align(&steps_a, &steps_b);
+        assert!(rows.iter().all(|row| !row.diverges));
+        let last = rows.last().unwrap();
+        assert!(last.anchor);
+        assert_eq!(last.original.unwrap().opcode, op::STOP);
+        assert_eq!(last.obfuscated.unwrap().opcode, op::STOP);
+
+        // storing instead of adding shows up as a divergence on the first anchor
+        let mut broken = original.clone();
+        let len = broken.len();
+        broken[len - 2] = 0x55;
+        let (_, steps_b) = trace::record(&broken, &calldata).unwrap();
+        let rows = trace::align(&steps_a, &steps_b);
+        let row = rows.iter().find(|row| row.diverges).unwrap();
+        assert!(row.anchor);
+        assert_eq!(row.original.unwrap().opcode, op::STOP);
+        assert_eq!(row.obfuscated.unwrap().opcode, 0x55);
+        assert_eq!(
+            row.obfuscated.unwrap().inputs,
+            [U256::from(4), U256::from(3)]
+        );
+        assert!(row.to_string().starts_with('!'));
+    }
+
+    #[test]
+    fn test_gas_report() {
+        let functions = abi::parse(
+            r#"[{"type": "function", "name": "f", "inputs": []},
+                {"type": "function", "name": "g", "inputs": [{"type": "uint256"}]}]"#,
+        )
+        .unwrap();
+        let (f, g) = (functions[0].selector(), functions[1].selector());
+        let original = hex::decode(
+            TWO_FUNCTIONS
+                .replace(' ', "")
+                .replace("aabbccdd", &hex::encode(f))
+                .replace("11223344", &hex::encode(g)),
+        )
+        .unwrap();
+        // only g's add is substituted
+        let substitute = Config::from_toml(
+            "[passes]\nenabled = [\"substitution\"]\n[probabilities]\nsubstitution = 1.0",
+        )
+        .unwrap();
+        let mut obfuscator =
+            Obfuscator::with_pipeline(&original, 42, substitute.pipeline().unwrap());
+        obfuscator.freeze(dispatcher::frozen_ranges(
+            &original,
+            &Selection::Only(vec![g]),
+        ));
+        let obfuscated = obfuscator.obfuscate().unwrap();
+
+        // f is called with its sample, g with generated calls, and the unknown selector is reported
+        // on its own
+        let samples = [
+            corpus::Sample {
+                name: "f".into(),
+                calldata: f.to_vec(),
+            },
+            corpus::Sample {
+                name: "unknown".into(),
+                calldata: vec![0xde, 0xad, 0xbe, 0xef],
+            },
+        ];
+        let report =
+            report::GasReport::measure(&original, &obfuscated, &functions, &samples, 5, 0).unwrap();
+        let rows: Vec<(&str, usize)> = report
+            .rows
+            .iter()
+            .map(|row| (row.function.as_str(), row.calls))
+            .collect();
+        assert_eq!(rows, [("f()", 1), ("g(uint256)", 5), ("(other)", 1)]);
+        assert_eq!(report.rows[0].overhead(), 0.0);
+        assert!(report.rows[1].overhead() > 0.0);
+        assert_eq!(report.total().calls, 7);
+        assert_eq!(report.total().divergent, 0);
+        assert!(report
+            .to_string()
+            .lines()
+            .last()
+            .unwrap()
+            .starts_with("total"));
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let path = std::env::temp_dir().join(format!("ebo_snapshot_{}.toml", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let config = Config::from_toml("[passes]\ndisabled = [\"chaotic-shuffle\"]").unwrap();
+
+        // the first check records the output, later ones compare against it
+        let check = |seed, config: &Config| test_support::check(&path, &bytecode, seed, config);
+        assert_eq!(check(7, &config).unwrap(), test_support::Outcome::Recorded);
+        assert_eq!(check(7, &config).unwrap(), test_support::Outcome::Matched);
+        test_support::assert_snapshot(&path, &bytecode, 7, &config);
+
+        let changed = Config::from_toml("[passes]\nenabled = [\"substitution\"]").unwrap();
+        let err = check(7, &changed).unwrap_err().to_string();
+        assert!(err.contains("first difference at byte"), "{}", err);
+        let err = check(8, &config).unwrap_err().to_string();
+        assert!(err.contains("another seed (seed 7)"), "{}", err);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_foundry_artifacts() {
+        let out = std::env::temp_dir().join(format!("ebo_foundry_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&out);
+        let artifact = |file: &str, source: &str, name: &str, code: &str, immutables: &str| {
+            let dir = out.join(file);
+            fs::create_dir_all(&dir).unwrap();
+            let json = format!(
+                r#"{{"deployedBytecode": {{"object": "{}", "immutableReferences": {}}},
+                    "metadata": {{"settings": {{"compilationTarget": {{"{}": "{}"}}}}}}}}"#,
+                code, immutables, source, name
+            );
+            fs::write(dir.join(format!("{}.json", name)), json).unwrap();
+        };
+        artifact(
+            "Counter.sol",
+            "src/Counter.sol",
+            "Counter",
+            "0x6001600201",
+            "{}",
+        );
+        artifact(
+            "Counter.t.sol",
+            "test/Counter.t.sol",
+            "CounterTest",
+            "0x00",
+            "{}",
+        );
+        artifact("IERC20.sol", "src/IERC20.sol", "IERC20", "0x", "{}");
+        artifact(
+            "Vault.sol",
+            "src/Vault.sol",
+            "Vault",
+            "0x00",
+            r#"{"4": []}"#,
+        );
+        fs::create_dir_all(out.join("build-info")).unwrap();
+
+        let (artifacts, skipped) = foundry::artifacts(&out, "src").unwrap();
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].name, "Counter");
+        assert_eq!(artifacts[0].runtime, vec![0x60, 0x01, 0x60, 0x02, 0x01]);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].name, "Vault");
+        fs::remove_dir_all(&out).unwrap();
+
+        let fixture = foundry::fixture(&[("Counter".into(), vec![0x60, 0x01])]);
+        assert!(fixture.contains(r#"if (id == keccak256("Counter")) {"#));
+        assert!(fixture.contains(r#"VM.etch(target, hex"6001");"#));
+        assert!(fixture.contains(foundry::SWITCH));
+
+        let results = |json: &str| foundry::results(json).unwrap();
+        let original = results(
+            r#"{"test/Counter.t.sol:CounterTest": {"duration": "1ms", "test_results": {
+                "test_Increment()": {"status": "Success", "reason": null},
+                "test_Overflow()": {"status": "Failure", "reason": "overflow"},
+                "test_Reset()": {"status": "Success", "reason": null}}}}"#,
+        );
+        let obfuscated = results(
+            r#"{"test/Counter.t.sol:CounterTest": {"duration": "1ms", "test_results": {
+                "test_Increment()": {"status": "Success", "reason": null},
+                "test_Overflow()": {"status": "Failure", "reason": "overflow"},
+                "test_Reset()": {"status": "Failure", "reason": "EvmError: Revert"}}}}"#,
+        );
+        assert_eq!(original.len(), 3);
+        // a test that already failed is not blamed on obfuscation
+        assert_eq!(
+            foundry::regressions(&original, &obfuscated),
+            ["test/Counter.t.sol:CounterTest::test_Reset()"]
+        );
+    }
+
+    #[test]
+    fn test_validator() {
+        let issues = |code: &str, fork: Fork| {
+            validate::validate(&hex::decode(code.replace(' ', "")).unwrap(), fork)
+                .into_iter()
+                .map(|issue| (issue.offset, issue.problem))
+                .collect::<Vec<_>>()
+        };
+        assert!(issues("6080 6040 52 00 0c0c", Fork::Cancun).is_empty());
+        assert_eq!(
+            issues("6001 0c", Fork::Cancun),
+            [(2, Problem::UndefinedOpcode(0x0c))]
+        );
+        assert_eq!(
+            issues("5f 00", Fork::London),
+            [(0, Problem::UndefinedOpcode(0x5f))]
+        );
+        assert!(issues("5f 00", Fork::Shanghai).is_empty());
+        assert!(Fork::Shanghai.admits(&[0x5F, 0x00]) && !Fork::Paris.admits(&[0x5F, 0x00]));
+        // push data is not an opcode
+        assert!(Fork::London.admits(&[0x60, 0x5F, 0x00]));
+        assert_eq!(
+            Config::from_toml("evm-version = \"london\"").unwrap().fork,
+            Some(Fork::London)
+        );
+        assert_eq!(
+            issues("6001 61ff", Fork::Cancun),
+            [(2, Problem::TruncatedPush)]
+        );
+        assert_eq!(
+            issues("6003 56 00", Fork::Cancun),
+            [(2, Problem::BadJump(3))]
+        );
+        // the jump reaches the jumpdest with an empty stack, the fall-through with one item
+        assert_eq!(
+            issues("6000 35 6008 57 6001 5b 50 00", Fork::Cancun),
+            [(
+                9,
+                Problem::StackUnderflow {
+                    needed: 1,
+                    available: 0
+                }
+            )]
+        );
+
+        let input = validate::validate(&[0x50], Fork::Cancun);
+        assert!(validate::introduced(&input, &input).is_empty());
+        let output = validate::validate(&[0x50, 0x50], Fork::Cancun);
+        assert!(validate::introduced(&input, &output).is_empty());
+        let output = validate::validate(
+            &hex::decode("6001 0c".replace(' ', "")).unwrap(),
+            Fork::Cancun,
+        );
+        assert_eq!(validate::introduced(&input, &output).len(), 1);
+
+        // passes that would break the code are scaled back and skipped
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        for seed in 0..20 {
+            let pipeline = Pipeline::new().with(ChaoticShuffle { probability: 1.0 });
+            let obfuscated = Obfuscator::with_pipeline(&bytecode, seed, pipeline)
+                .obfuscate()
+                .unwrap();
+            assert!(validate::validate(&obfuscated, Fork::Cancun).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_push0_compat() {
+        // push0, push0, add, stop: shanghai code has its push0 rewritten for london only
+        let bytecode = vec![0x5F, 0x5F, 0x01, 0x00];
+        let compat = |fork: &str| {
+            let config = Config::from_toml(&format!(
+                "fork = \"{}\"\n[passes]\nenabled = [\"push0-compat\"]",
+                fork
+            ))
+            .unwrap();
+            config.obfuscate(&bytecode, 42).unwrap()
+        };
+        let london = compat("london");
+        assert_eq!(hex::encode(&london.bytecode), "600060000100");
+        assert_eq!(london.stats["push0-compat"], 2);
+        assert_eq!(compat("shanghai").bytecode, bytecode);
+        assert_eq!(Pipeline::default().names()[0], "push0-compat");
+
+        // substitutions push zero with push0 where the fork has it, and never where it does not
+        let adds = vec![0x01; 64];
+        let substituted = |fork: Fork| {
+            let pipeline = Pipeline::new().with(Substitution { probability: 1.0 });
+            let mut obfuscator = Obfuscator::with_pipeline(&adds, 7, pipeline);
+            obfuscator.target(fork);
+            obfuscator.obfuscate().unwrap()
+        };
+        let shanghai = substituted(Fork::Shanghai);
+        assert!(shanghai.contains(&op::PUSH0));
+        assert!(symbolic::equivalent(&adds, &shanghai));
+        let london = substituted(Fork::London);
+        assert!(Fork::London.admits(&london));
+        assert!(symbolic::equivalent(&adds, &london));
+    }
+
+    #[test]
+    fn test_chain_profiles() {
+        assert_eq!(chain::parse("zksync-era").unwrap(), Chain::ZksyncEra);
+        assert_eq!(chain::parse("zksync").unwrap(), Chain::ZksyncEra);
+        assert_eq!(chain::parse("Base").unwrap(), Chain::Optimism);
+        assert_eq!(chain::parse("1101").unwrap(), Chain::PolygonZkevm);
+        assert!(chain::parse("sepolia").is_err());
+        assert!(chain::parse("atlantis").is_err());
+
+        let on = |chain| Target::new(Fork::Cancun, Some(chain));
+        assert!(Target::from(Fork::Cancun).supports(op::CODECOPY));
+        assert!(!on(Chain::ZksyncEra).supports(op::CODECOPY));
+        assert!(!on(Chain::Arbitrum).supports(op::BLOBBASEFEE));
+        assert!(on(Chain::PolygonZkevm).supports(op::PUSH0));
+        assert!(!on(Chain::PolygonZkevm).supports(op::TLOAD));
+        assert!(!on(Chain::Optimism).reliable(0x41));
+        assert!(on(Chain::Optimism).reliable(op::ADD));
+        assert!(!on(Chain::ZksyncEra).mainnet_gas() && on(Chain::Arbitrum).mainnet_gas());
+        assert_eq!(on(Chain::ZksyncEra).to_string(), "zksync-era (cancun)");
+
+        // push1 0x20, push1 0, push1 0, codecopy, stop
+        let copy = [0x60, 0x20, 0x60, 0x00, 0x60, 0x00, 0x39, 0x00];
+        assert!(validate::validate(&copy, Fork::Cancun).is_empty());
+        assert_eq!(
+            validate::validate(&copy, on(Chain::ZksyncEra))[0].problem,
+            Problem::UndefinedOpcode(op::CODECOPY)
+        );
+        let err = EboError::introduced(
+            validate::validate(&copy, on(Chain::ZksyncEra)),
+            on(Chain::ZksyncEra),
+        );
+        assert!(err
+            .to_string()
+            .contains("zksync-era (cancun) does not support"));
+
+        // the constant pool loads constants with codecopy, so it backs off on zksync era
+        let mut bytecode = vec![op::PUSH32];
+        bytecode.extend([0xAB; 32]);
+        bytecode.extend([op::POP, op::STOP]);
+        let pooled = |chain: &str| {
+            let config = Config::from_toml(&format!(
+                "chain = \"{}\"\n[passes]\nenabled = [\"constant-pool\"]",
+                chain
+            ))
+            .unwrap();
+            config.obfuscate(&bytecode, 42).unwrap().bytecode
+        };
+        assert!(disassemble(&pooled("optimism"))
+            .iter()
+            .any(|ins| ins.opcode == op::CODECOPY));
+        assert_eq!(pooled("zksync-era"), bytecode);
+    }
+
+    #[test]
+    fn test_opcode_table() {
+        use ebo::evm::OPCODES;
+        use revm::interpreter::OpCode;
+
+        for (byte, info) in OPCODES.iter().enumerate() {
+            let opcode = byte as u8;
+            // every defined opcode is one revm knows, under the name it gives it, and is defined
+            // from its fork on
+            if info.stack.is_some() {
+                assert_eq!(Some(info.name), OpCode::new(opcode).map(OpCode::as_str));
+            }
+            assert_eq!(info.stack.is_some(), info.fork.is_some(), "{:#04x}", opcode);
+            let forks = [Fork::London, Fork::Paris, Fork::Shanghai, Fork::Cancun];
+            let supported: Vec<bool> = forks.iter().map(|fork| fork.supports(opcode)).collect();
+            assert!(supported.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert_eq!(supported[3], info.stack.is_some());
+            // pushes own as many bytes as the table says, and only terminators are free
+            assert_eq!(
+                info.immediate,
+                disassemble(&[opcode; 33])[0].immediate.len()
+            );
+            assert!(info.gas > 0 || info.terminator || info.stack.is_none());
+        }
+        assert_eq!(OPCODES.iter().filter(|info| info.ends_block).count(), 4);
+    }
+
+    #[test]
+    fn test_cancun_opcodes() {
+        // tstore(0, calldataload(0)), mstore(0, tload(0) + 1), mcopy(0x20, 0, 0x20), blobbasefee,
+        // pop, return(0x20, 0x20)
+        let code = hex::decode(
+            "600035 6000 5d 6000 5c 600101 6000 52 6020 6000 6020 5e 4a 50 6020 6020 f3"
+                .replace(' ', ""),
+        )
+        .unwrap();
+        let opcodes: Vec<evm::Opcode> = parse_bytecode(&code)
+            .into_iter()
+            .flat_map(|block| block.opcodes)
+            .collect();
+        for (opcode, stack) in [
+            (evm::Opcode::TSTORE, (2, 0)),
+            (evm::Opcode::TLOAD, (1, 1)),
+            (evm::Opcode::MCOPY, (3, 0)),
+            (evm::Opcode::BLOBBASEFEE, (0, 1)),
+        ] {
+            assert!(opcodes.contains(&opcode), "{:?}", opcode);
+            assert_eq!(opcode.stack_io(), Some(stack));
+        }
+        assert_eq!(evm::Opcode::from(0x49), evm::Opcode::BLOBHASH);
+        assert_eq!(evm::Opcode::BLOBHASH.byte(), 0x49);
+        assert_eq!(
+            [0x5C, 0x5D, 0x5E, 0x49, 0x4A].map(gas::static_cost),
+            [100, 100, 3, 3, 2]
+        );
+        assert!(validate::validate(&code, Fork::Cancun).is_empty());
+        assert!(!Fork::Shanghai.admits(&code));
+
+        // the obfuscated code behaves the same
+        let calldata = [[0u8; 31].as_slice(), &[41]].concat();
+        let original = equivalence::execute(&code, &calldata).unwrap();
+        assert_eq!(original.effects.output[31], 42);
+        for seed in 0..10 {
+            let obfuscated = Obfuscator::new(&code, seed).obfuscate().unwrap();
+            let run = equivalence::compare(&code, &obfuscated, &calldata).unwrap();
+            assert!(run.divergence().is_none(), "seed {}", seed);
+        }
+
+        // and the shuffle leaves transient storage and mcopy where they were, like every instruction
+        // that is not pure
+        let pinned = |code: &[u8]| {
+            disassemble(code)
+                .iter()
+                .enumerate()
+                .filter(|(_, ins)| !symbolic::pure(ins.opcode))
+                .map(|(idx, ins)| (idx, ins.opcode))
+                .collect::<Vec<_>>()
+        };
+        for seed in 0..10 {
+            let pipeline = Pipeline::new().with(ChaoticShuffle { probability: 1.0 });
+            let shuffled = Obfuscator::with_pipeline(&code, seed, pipeline)
+                .obfuscate()
+                .unwrap();
+            assert_eq!(pinned(&shuffled), pinned(&code));
+        }
+    }
+
+    #[test]
+    fn test_chaotic_shuffle_keeps_values() {
+        let shuffle = |code: &[u8], seed| {
+            let pipeline = Pipeline::new().with(ChaoticShuffle { probability: 1.0 });
+            let mut obfuscator = Obfuscator::with_pipeline(code, seed, pipeline);
+            let shuffled = obfuscator.obfuscate().unwrap();
+            (shuffled, obfuscator.stats()["chaotic-shuffle"])
+        };
+        // `dup1, push4 s, eq` is not `push4 s, dup1, eq`, which sends unknown selectors into a
+        // function
+        let dispatcher = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        // an outlined subroutine returning a storage word: `sload, swap1` cannot be reordered
+        let subroutine = hex::decode(
+            format!(
+                "6005600e56 5b600052 60206000f3 5b7f{}549056",
+                "ee".repeat(32)
+            )
+            .replace(' ', ""),
+        )
+        .unwrap();
+        for seed in 0..20 {
+            for calldata in [
+                vec![],
+                vec![0xaa, 0xbb, 0xcc, 0xdd],
+                vec![0x11, 0x22, 0x33, 0x44],
+                vec![0xde, 0xad, 0xbe, 0xef],
+            ] {
+                let comparison =
+                    equivalence::compare(&dispatcher, &shuffle(&dispatcher, seed).0, &calldata)
+                        .unwrap();
+                assert!(comparison.divergence().is_none(), "seed {}", seed);
+            }
+            let comparison =
+                equivalence::compare(&subroutine, &shuffle(&subroutine, seed).0, &[]).unwrap();
+            assert!(comparison.divergence().is_none(), "seed {}", seed);
+        }
+
+        // the operands of a commutative operation can still trade places
+        let sum = hex::decode("6001600201 600052 60206000f3".replace(' ', "")).unwrap();
+        let shuffled: Vec<_> = (0..10).map(|seed| shuffle(&sum, seed)).collect();
+        assert!(shuffled
+            .iter()
+            .any(|(code, kept)| *kept > 0 && *code != sum));
+        for (code, _) in &shuffled {
+            let comparison = equivalence::compare(&sum, code, &[]).unwrap();
+            assert!(comparison.divergence().is_none());
+        }
+    }
+
+    #[test]
+    fn test_chaotic_shuffle_pins_state() {
+        // sstore(0, 7), mstore(0, sload(0)), return(0, 32): the read has to stay after the write
+        let code = hex::decode("6007600055 600054 600052 60206000f3".replace(' ', "")).unwrap();
+        let stateful = |code: &[u8]| {
+            disassemble(code)
+                .iter()
+                .enumerate()
+                .filter(|(_, ins)| !symbolic::pure(ins.opcode))
+                .map(|(idx, ins)| (idx, ins.opcode))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            stateful(&code)
+                .iter()
+                .map(|(_, opcode)| *opcode)
+                .collect::<Vec<_>>(),
+            [op::SSTORE, op::SLOAD, op::MSTORE, op::RETURN]
+        );
+        for seed in 0..20 {
+            let pipeline = Pipeline::new().with(ChaoticShuffle { probability: 1.0 });
+            let shuffled = Obfuscator::with_pipeline(&code, seed, pipeline)
+                .obfuscate()
+                .unwrap();
+            assert_eq!(stateful(&shuffled), stateful(&code), "seed {}", seed);
+            let comparison = equivalence::compare(&code, &shuffled, &[]).unwrap();
+            assert!(comparison.divergence().is_none(), "seed {}", seed);
+            assert_eq!(comparison.obfuscated.effects.output[31], 7);
+        }
+    }
+
+    #[test]
+    fn test_eof_mode() {
+        // section 0: a countdown loop closed by a backward rjumpi, a callf, and a forward rjumpi;
+        // section 1 takes two inputs and returns their sum
+        let code = hex::decode("60036001900380e1fff85060036004e300015f35e100015000").unwrap();
+        let mut container = hex::decode("ef0001010008020002").unwrap();
+        container.extend((code.len() as u16).to_be_bytes());
+        container.extend([0x00, 0x02, 0x04, 0x00, 0x00, 0x00]);
+        container.extend([0x00, 0x80, 0x00, 0x02, 0x02, 0x01, 0x00, 0x02]);
+        container.extend(&code);
+        container.extend([0x01, 0xe4]);
+        assert!(eof::is_container(&container));
+        assert!(eof::decode(&container).is_ok());
+
+        let config =
+            Config::from_toml("[probabilities]\nsubstitution = 1.0\nfalse-branch = 1.0").unwrap();
+        for seed in 0..20 {
+            let obfuscation = config.obfuscate(&container, seed).unwrap();
+            assert!(obfuscation.bytecode.len() > container.len());
+            let eof = eof::decode(&obfuscation.bytecode).unwrap();
+            assert_eq!(eof.body.code_section.len(), 2);
+            assert_ne!(eof.body.code_section[0].as_ref(), code.as_slice());
+            assert_ne!(eof.body.code_section[1].as_ref(), [0x01, 0xe4]);
+            assert!(obfuscation.stats["false-branch"] > 0);
+            assert!(obfuscation.stats["substitution"] > 0);
+        }
+
+        // disabled passes stay off, and a broken container is an error rather than legacy code
+        let config =
+            Config::from_toml("[passes]\ndisabled = [\"substitution\", \"false-branch\"]").unwrap();
+        assert_eq!(config.obfuscate(&container, 1).unwrap().bytecode, container);
+        assert!(config
+            .obfuscate(&container[..container.len() - 1], 1)
+            .is_err());
+    }
+
+    /// operand of a generated expression.
+    #[derive(Debug, Clone)]
+    enum Operand {
+        Const(Vec<u8>),
+        /// word of the calldata at this index.
+        Calldata(u8),
+    }
+
+    /// statement of a generated program.
+    #[derive(Debug, Clone)]
+    enum Stmt {
+        /// sstore of an expression folded from the operands with the binary opcodes.
+        Store(u8, Operand, Vec<(u8, Operand)>),
+        /// log0 of an expression.
+        Log(Operand, Vec<(u8, Operand)>),
+        /// runs the body only when the low bit of a calldata word is set.
+        If(u8, Vec<Stmt>),
+    }
+
+    fn emit_operand(code: &mut Vec<u8>, operand: &Operand) {
+        match operand {
+            Operand::Const(value) => {
+                code.push(op::PUSH1 + value.len() as u8 - 1);
+                code.extend(value);
+            }
+            Operand::Calldata(word) => code.extend([op::PUSH1, word * 32, 0x35]),
+        }
+    }
+
+    fn emit_expr(code: &mut Vec<u8>, first: &Operand, rest: &[(u8, Operand)]) {
+        emit_operand(code, first);
+        for (opcode, operand) in rest {
+            emit_operand(code, operand);
+            code.push(*opcode);
+        }
+    }
+
+    fn emit_stmt(code: &mut Vec<u8>, stmt: &Stmt) {
+        match stmt {
+            Stmt::Store(slot, first, rest) => {
+                emit_expr(code, first, rest);
+                code.extend([op::PUSH1, *slot, 0x55]);
+            }
+            Stmt::Log(first, rest) => {
+                emit_expr(code, first, rest);
+                code.extend([op::PUSH1, 0, 0x52, op::PUSH1, 32, op::PUSH1, 0, 0xa0]);
+            }
+            Stmt::If(word, body) => {
+                // calldataload, and 1, iszero, jumpi over the body
+                code.extend([op::PUSH1, word * 32, 0x35, op::PUSH1, 1, 0x16, 0x15]);
+                code.extend([op::PUSH1 + 1, 0, 0, op::JUMPI]);
+                let patch = code.len() - 3;
+                for stmt in body {
+                    emit_stmt(code, stmt);
+                }
+                let end = (code.len() as u16).to_be_bytes();
+                code[patch..patch + 2].copy_from_slice(&end);
+                code.push(op::JUMPDEST);
+            }
+        }
+    }
+
+    /// strategy for well-formed programs: static jumps only, no code introspection, solidity's
+    /// memory layout (the constant pool copies to the free memory pointer), and every path ends in
+    /// stop, return, or revert of the first memory word.
+    fn program() -> impl Strategy<Value = Vec<u8>> {
+        let operand = prop_oneof![
+            prop::collection::vec(any::<u8>(), 1..=32).prop_map(Operand::Const),
+            (0u8..4).prop_map(Operand::Calldata),
+        ];
+        // add, mul, sub, lt, eq, and, or, xor
+        let binary = prop::sample::select(vec![0x01, 0x02, 0x03, 0x10, 0x14, 0x16, 0x17, 0x18]);
+        let rest = prop::collection::vec((binary, operand.clone()), 0..4);
+        let leaf = prop_oneof![
+            (0u8..8, operand.clone(), rest.clone())
+                .prop_map(|(slot, first, rest)| Stmt::Store(slot, first, rest)),
+            (operand, rest).prop_map(|(first, rest)| Stmt::Log(first, rest)),
+        ];
+        let stmt = leaf.prop_recursive(2, 24, 4, |inner| {
+            (0u8..4, prop::collection::vec(inner, 1..4))
+                .prop_map(|(word, body)| Stmt::If(word, body))
+        });
+        (prop::collection::vec(stmt, 1..10), 0..3).prop_map(|(stmts, ending)| {
+            let mut code = vec![op::PUSH1, 0x80, op::PUSH1, 0x40, 0x52];
+            // repeating the statements gives outlining something to work with
+            for stmt in stmts.iter().chain(&stmts) {
+                emit_stmt(&mut code, stmt);
+            }
+            match ending {
+                0 => code.push(op::STOP),
+                1 => code.extend([op::PUSH1, 32, op::PUSH1, 0, op::RETURN]),
+                _ => code.extend([op::PUSH1, 32, op::PUSH1, 0, op::REVERT]),
+            }
+            code
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn fuzz_block_ranges_match_blocks(bytecode in prop::collection::vec(0u8..=255u8, 0..200)) {
+            let blocks = parse_bytecode(&bytecode);
+            let ranges: Vec<_> = evm::block_ranges(&bytecode).collect();
+            prop_assert_eq!(ranges.len(), blocks.len());
+            for (range, block) in ranges.iter().zip(&blocks) {
+                prop_assert_eq!(range.len(), block.opcodes.len());
+            }
+            prop_assert_eq!(evm::cfg_complexity(&bytecode), compute_cfg_complexity(&blocks));
+        }
+
+        #[test]
+        fn fuzz_chaotic_shuffle_permutes(bytecode in prop::collection::vec(0u8..=255u8, 0..200), seed in 0u64..1000u64) {
+            use ebo::passes::Pass;
+
+            let program = Program::lift(&bytecode);
+            let mut shuffled = program.clone();
+            ChaoticShuffle { probability: 1.0 }.run(&mut shuffled, &mut ebo::passes::Ctx::new(seed));
+            // every item is still there exactly once, and only movable instructions moved
+            prop_assert_eq!(shuffled.items.len(), program.items.len());
+            let sorted = |program: &Program| {
+                let mut items: Vec<String> = program.items.iter().map(|item| format!("{:?}", item)).collect();
+                items.sort();
+                items
+            };
+            prop_assert_eq!(sorted(&shuffled), sorted(&program));
+            for (before, after) in program.items.iter().zip(&shuffled.items) {
+                let movable = |item: &Item| matches!(item, Item::Op { opcode, .. } if symbolic::pure(*opcode));
+                prop_assert_eq!(movable(before), movable(after));
+                if !movable(before) {
+                    prop_assert_eq!(before, after);
+                }
+            }
+        }
+
+        #[test]
+        fn fuzz_obfuscation_does_not_crash(bytecode in prop::collection::vec(0u8..=255u8, 0..100), seed in 0u64..1000u64) {
+            let mut obfuscator = Obfuscator::new(&bytecode, seed);
+            let _obfuscated = obfuscator.obfuscate().unwrap();
+        }
+
+        #[test]
+        fn fuzz_obfuscation_is_equivalent(
+            bytecode in program(),
+            calldata in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..128), 1..4),
+            seed in 0u64..1000u64,
+            rounds in 1usize..3,
+        ) {
+            let mut obfuscator = Obfuscator::new(&bytecode, seed);
+            obfuscator.repeat(rounds);
+            let obfuscated = obfuscator.obfuscate().unwrap();
+            for calldata in &calldata {
+                let divergence = equivalence::compare(&bytecode, &obfuscated, calldata)
+                    .unwrap()
+                    .divergence();
+                prop_assert!(divergence.is_none(), "{}", divergence.unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_default_pipeline_is_equivalent() {
+        // two functions storing 1 in slot 0 and 2 in slot 1
+        let stores = hex::decode(
+            "5f3560e01c 8063aabbccdd14601a57 806311223344146020 57 00 \
+             5b60015f5500 5b6002600155 00"
+                .replace(' ', ""),
+        )
+        .unwrap();
+        let two = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let calls = [
+            vec![],
+            vec![0xaa, 0xbb, 0xcc, 0xdd],
+            vec![0x11, 0x22, 0x33, 0x44],
+            vec![0xde, 0xad, 0xbe, 0xef],
+        ];
+        let stored = |calldata: &[u8]| {
+            let storage = equivalence::execute(&stores, calldata)
+                .unwrap()
+                .effects
+                .storage;
+            storage
+                .into_iter()
+                .map(|(slot, value)| (slot.to::<u8>(), value.to::<u8>()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(stored(&calls[1]), [(0, 1)]);
+        assert_eq!(stored(&calls[2]), [(1, 2)]);
+        for (code, rounds, seeds) in [(&stores, 1, 30), (&two, 2, 40)] {
+            let config = Config::from_toml(&format!("rounds = {}", rounds)).unwrap();
+            for seed in 0..seeds {
+                let obfuscated = config.obfuscate(code, seed).unwrap().bytecode;
+                for calldata in &calls {
+                    let run = equivalence::compare(code, &obfuscated, calldata).unwrap();
+                    assert!(run.divergence().is_none(), "seed {}: {:?}", seed, run);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        let progress = Progress::new(false);
+        let subscriber = tracing_subscriber::registry().with(progress.clone());
+        let passes = Pipeline::default().names().len() as u64;
+        tracing::subscriber::with_default(subscriber, || {
+            assert_eq!(progress.passes(), None);
+            let mut obfuscator = Obfuscator::builder().seed(3).rounds(2).build(&bytecode);
+            obfuscator.obfuscate().unwrap();
+            // every pass of every round counts, skipped ones too
+            assert_eq!(progress.passes(), Some((2 * passes, 2 * passes)));
+
+            // the next run starts over
+            Obfuscator::new(&bytecode, 3).obfuscate().unwrap();
+            assert_eq!(progress.passes(), Some((passes, passes)));
+        });
+        progress.finish();
+        assert_eq!(progress.passes(), None);
+    }
+
+    #[test]
+    fn test_pass_spans() {
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let bytecode = hex::decode(TWO_FUNCTIONS.replace(' ', "")).unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            Obfuscator::new(&bytecode, 42).obfuscate().unwrap();
+        });
+
+        // every pass of the pipeline logs its span with what it applied and how long it took
+        let logged = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        for name in ["selector-mask", "substitution", "spaghetti"] {
+            let line = logged
+                .lines()
+                .find(|line| line.contains(&format!("Finished pass: {name}")))
+                .unwrap_or_else(|| panic!("no span for {name} in {logged}"));
+            assert!(
+                line.contains("applied=") && line.contains("elapsed="),
+                "{line}"
+            );
         }
     }
 }
@@ -0,0 +1,142 @@
+/// reproducibility manifests for the ebo obfuscator.
+/// an audit of what got deployed has to be able to rebuild it. `ebo obfuscate --manifest` writes,
+/// next to the output, everything a build depends on: the version of ebo, the hashes of the input
+/// and the output, the seed, whether the randomness was keyed, and the configuration resolved down
+/// to every pass and probability, written as the `ebo.toml` that rebuilds it with nothing left to
+/// defaults that a later version might change. `ebo reproduce` runs the build again from a manifest
+/// and checks that the output comes out byte for byte the same.
+///
+/// the input and output are the runtime code: an artifact, creation transaction, or library
+/// placeholders around them are not part of what the passes see.
+use crate::config::Config;
+use crate::error::EboError;
+use crate::seed::Key;
+use anyhow::{bail, Context};
+use revm::primitives::keccak256;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// version of ebo making the build.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// the manifest of code written to `output`, e.g. `Token.manifest.json` for `Token.bin`.
+pub fn path(output: &Path) -> PathBuf {
+    output.with_extension("manifest.json")
+}
+
+/// identity of one bytecode.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Code {
+    /// size in bytes.
+    pub size: usize,
+    /// sha-256 of the code, as hex.
+    pub sha256: String,
+    /// keccak-256 of the code, as hex, which is what `extcodehash` returns for it once deployed.
+    pub codehash: String,
+}
+
+impl Code {
+    /// the identity of `bytecode`.
+    pub fn new(bytecode: &[u8]) -> Self {
+        Code {
+            size: bytecode.len(),
+            sha256: hex::encode(Sha256::digest(bytecode)),
+            codehash: hex::encode(keccak256(bytecode)),
+        }
+    }
+}
+
+/// what a build was made from, sufficient to make it again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// version of ebo that made the build.
+    pub version: String,
+    /// the runtime code that was obfuscated.
+    pub input: Code,
+    /// the obfuscated runtime code.
+    pub output: Code,
+    /// seed the output was made with.
+    pub seed: u64,
+    /// whether the randomness was keyed with a secret, which is needed again to reproduce the
+    /// output and is never written here.
+    pub keyed: bool,
+    /// the resolved configuration, see `Config::resolved`, as toml.
+    pub config: String,
+}
+
+impl Manifest {
+    /// the manifest of obfuscating `input` into `output` with `config` and `seed`, the seed
+    /// `Config::obfuscate` kept.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::manifest::Manifest;
+    /// use ebo::Config;
+    ///
+    /// let config = Config::default();
+    /// let bytecode = [0x60, 0x01, 0x60, 0x02, 0x01, 0x00]; // PUSH1 1, PUSH1 2, ADD, STOP
+    /// let obfuscation = config.obfuscate(&bytecode, 42).unwrap();
+    /// let manifest = Manifest::new(&bytecode, &obfuscation.bytecode, 42, &config).unwrap();
+    /// assert_eq!(manifest.reproduce(&bytecode, None).unwrap(), obfuscation.bytecode);
+    /// ```
+    pub fn new(input: &[u8], output: &[u8], seed: u64, config: &Config) -> anyhow::Result<Self> {
+        let resolved = config.resolved(seed)?;
+        Ok(Manifest {
+            version: VERSION.to_string(),
+            input: Code::new(input),
+            output: Code::new(output),
+            seed,
+            keyed: config.key.is_some(),
+            config: toml::to_string(&resolved).context("cannot serialize the configuration")?,
+        })
+    }
+
+    /// parses a manifest written by `to_json`.
+    pub fn parse(json: &str) -> Result<Self, EboError> {
+        serde_json::from_str(json).map_err(|err| EboError::parse("manifest", err))
+    }
+
+    /// the manifest as pretty-printed json.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a manifest always serializes")
+    }
+
+    /// obfuscates `input` again as the manifest records, with `key` if the build was keyed.
+    ///
+    /// # returns
+    /// the output, or an error if `input` is not the recorded input, a keyed build is given no
+    /// key, or the output differs from the recorded one (`EboError::Verification`).
+    pub fn reproduce(&self, input: &[u8], key: Option<Key>) -> anyhow::Result<Vec<u8>> {
+        if self.version != VERSION {
+            warn!(
+                "Manifest was written by ebo {}, reproducing with {}",
+                self.version, VERSION
+            );
+        }
+        if Code::new(input) != self.input {
+            bail!(
+                "input has sha-256 {}, the manifest records {}",
+                Code::new(input).sha256,
+                self.input.sha256
+            );
+        }
+        if self.keyed && key.is_none() {
+            bail!("the build was keyed; reproducing it needs the same key");
+        }
+        let mut config = Config::from_toml(&self.config).context("in the manifest")?;
+        config.key = key.filter(|_| self.keyed);
+        let output = config.obfuscate(input, self.seed)?.bytecode;
+        let code = Code::new(&output);
+        if code != self.output {
+            return Err(EboError::Verification(format!(
+                "reproduced output has sha-256 {} ({} bytes), the manifest records {} ({} bytes)",
+                code.sha256, code.size, self.output.sha256, self.output.size
+            ))
+            .into());
+        }
+        Ok(output)
+    }
+}
@@ -0,0 +1,132 @@
+/// multiplexed contracts for the ebo obfuscator.
+/// `ebo merge` puts two contracts into one bytecode with two personalities: a switch in front of
+/// both compares the chain id, or the address, the code runs at with a value given when merging, and
+/// runs the first contract where it matches and the second everywhere else. the same code can then
+/// be deployed as a decoy that behaves like one contract while it is studied, or used to research
+/// how analyses cope with mixed code. the merged code is obfuscated as a whole afterwards, so the
+/// passes relocate and interleave both personalities.
+///
+/// the switch does not push the value it looks for: it xors what it reads with a random mask and
+/// compares the result with the masked value. both contracts are relocated, so code that computes
+/// offsets into itself other than through pushes of jump targets and return addresses (see
+/// `reloc::Program::lift`) does not survive the merge, as it would not survive obfuscation.
+use crate::error::EboError;
+use crate::evm::op;
+use crate::reloc::{Item, Program};
+use rand::RngCore;
+use std::fmt;
+use std::str::FromStr;
+
+// opcodes the switch uses that `evm::op` has no name for
+const ADDRESS: u8 = 0x30;
+const CHAINID: u8 = 0x46;
+
+/// what decides which personality runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Switch {
+    /// the first contract runs on the chain with this id.
+    ChainId(u64),
+    /// the first contract runs when deployed at this address.
+    Address([u8; 20]),
+}
+
+impl Switch {
+    /// the instruction reading what the switch compares, and the value it compares it with.
+    fn operands(&self) -> (u8, Vec<u8>) {
+        match self {
+            Switch::ChainId(id) => (CHAINID, id.to_be_bytes().to_vec()),
+            Switch::Address(address) => (ADDRESS, address.to_vec()),
+        }
+    }
+}
+
+impl fmt::Display for Switch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Switch::ChainId(id) => write!(f, "chain id {}", id),
+            Switch::Address(address) => write!(f, "address 0x{}", hex::encode(address)),
+        }
+    }
+}
+
+impl FromStr for Switch {
+    type Err = String;
+
+    /// `chainid:<id>` or `address:<20 bytes of hex>`.
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = source
+            .split_once(':')
+            .ok_or_else(|| format!("expected chainid:<id> or address:<hex>, got '{}'", source))?;
+        match kind {
+            "chainid" => value
+                .parse()
+                .map(Switch::ChainId)
+                .map_err(|_| format!("invalid chain id '{}'", value)),
+            "address" => hex::decode(value.trim_start_matches("0x"))
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .map(Switch::Address)
+                .ok_or_else(|| format!("invalid address '{}'", value)),
+            _ => Err(format!(
+                "unknown switch '{}', expected chainid or address",
+                kind
+            )),
+        }
+    }
+}
+
+/// the code of `first` and `second` behind `switch`, not obfuscated yet. the switch draws its mask
+/// from `rng`.
+///
+/// # example
+/// ```
+/// use ebo::{equivalence, merge::{self, Switch}};
+/// use rand::{rngs::StdRng, SeedableRng};
+///
+/// // return(0, 0) and revert(0, 0)
+/// let (first, second) = ([0x5F, 0x5F, 0xF3], [0x5F, 0x5F, 0xFD]);
+/// let mut rng = StdRng::seed_from_u64(1);
+/// let here = merge::merge(&first, &second, Switch::ChainId(1), &mut rng).unwrap();
+/// let there = merge::merge(&first, &second, Switch::ChainId(10), &mut rng).unwrap();
+/// let status = |code: &[u8]| equivalence::execute(code, &[]).unwrap().effects.status;
+/// assert_eq!(status(&here), equivalence::Status::Success);
+/// assert_eq!(status(&there), equivalence::Status::Revert);
+/// ```
+///
+/// # returns
+/// the merged code, or `EboError::Relocation` if it cannot be laid out.
+pub fn merge(
+    first: &[u8],
+    second: &[u8],
+    switch: Switch,
+    rng: &mut dyn RngCore,
+) -> Result<Vec<u8>, EboError> {
+    let (reads, value) = switch.operands();
+    let mut mask = vec![0; value.len()];
+    rng.fill_bytes(&mut mask);
+    let masked: Vec<u8> = value.iter().zip(&mask).map(|(v, m)| v ^ m).collect();
+
+    let mut program = Program::default();
+    let other = program.new_label();
+    program.items.extend([
+        Item::op(reads),
+        Item::push(&mask),
+        Item::op(op::XOR),
+        Item::push(&masked),
+        Item::op(op::EQ),
+        Item::op(op::ISZERO),
+        Item::PushLabel {
+            label: other,
+            addend: 0,
+            width: 2,
+        },
+        Item::op(op::JUMPI),
+    ]);
+    program.append(Program::lift(first));
+    program.fence();
+    program
+        .items
+        .extend([Item::Mark(other), Item::op(op::JUMPDEST)]);
+    program.append(Program::lift(second));
+    program.assemble()
+}
@@ -1,39 +1,68 @@
 /// module for obfuscating evm bytecode
-/// implements techniques like chaotic shuffle, opcode substitution, false branch obfuscation, and flower instructions
+/// lifts the input into relocatable items, runs a pipeline of obfuscation passes over them (chaotic
+/// shuffle, opcode substitution, false branches, flower instructions, peephole identities, outlining,
+/// constant pooling, spaghettification), and assembles the result with every jump patched.
 /// draws on research from eveilm (page 59), bosc (sections 2.2, 2.4), and bian (section iii.b).
-use crate::evm::{parse_bytecode, Opcode};
-use log::debug;
-use rand::{rngs::StdRng, Rng, SeedableRng};
-use sha2::{Digest, Sha256};
+use crate::cache::BlockCache;
+use crate::chain::Chain;
+use crate::dispatcher::Routes;
+use crate::error::EboError;
+use crate::evm::Fork;
+use crate::link;
+use crate::passes::{Ctx, Pass, Pipeline, DISPATCHER};
+use crate::pcmap::PcMap;
+use crate::plan::Plan;
+use crate::reloc::Program;
+use crate::seed::{self, Key};
+use crate::slotmap::SlotMap;
+use rand::RngCore;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Arc;
+use tracing::{debug, info_span, warn};
 
 /// responsible for obfuscating evm bytecode.
-/// holds the input bytecode, a seeded random number generator for deterministic obfuscation,
-/// and a chaotic seed for the chaotic shuffle technique.
+/// holds the input bytecode, the pass pipeline to apply, and the seeded context the passes draw
+/// their randomness from.
 pub struct Obfuscator {
     /// input evm bytecode to be obfuscated.
     bytecode: Vec<u8>,
-    /// seeded random number generator for deterministic obfuscation operations.
-    ///
-    /// (a bit unrelated but very useful) you see a seed is like a starting point for a random number generator (in this case, StdRng).
-    /// when you initialize the rnd number generator with a specific seed (e.g., 42), it uses that seed to generate a
-    /// fixed sequence of "random" numbers (pseudo-random specifically) every time you run it with the same seed. this rng is then used in
-    /// the obfuscate method to make decisions, such as: whether to shuffle opcodes (rng.gen_bool(0.3)), which opcodes to swap during
-    /// shuffling (rng.gen_range), whether to apply substitutions or insert false branches (rng.gen_bool with probabilities like 0.5 or 0.4).
-    /// because the seed is fixed, these decisions follow the same pattern each time. for example, if rng.gen_bool(0.3) returns true for the
-    /// first shuffle decision with seed 42, it will always return true in that same spot when using seed 42.
-    ///
-    /// so for a given input bytecode and the same seed, the obfuscator will produce the same obfuscated bytecode every time. this is because
-    /// the random choices (e.g., which opcodes to shuffle or substitute) are deterministic based on the seed’s sequence.
-    rng: StdRng,
-    /// a floating-point number between 0 and 1 derived from the input seed, used later in the chaotic_map function
-    ///  to introduce controlled randomness.
-    chaotic_seed: f64,
+    /// passes applied by `obfuscate`, in order.
+    pipeline: Pipeline,
+    /// seeded state shared by the passes.
+    ctx: Ctx,
+    /// byte ranges of the input that are kept verbatim.
+    frozen: Vec<Range<usize>>,
+    /// seed of the first round; later rounds derive their own from it.
+    seed: u64,
+    /// number of times the pipeline is applied.
+    rounds: usize,
+    /// program the last run assembled its output from.
+    output: Option<Program>,
 }
 
 impl Obfuscator {
-    /// creates a new obfuscator instance for the given bytecode and seed.
-    /// initializes the random number generator and chaotic seed using a sha-256 hash of the input seed
-    /// to ensure deterministic yet unpredictable obfuscation.
+    /// starts building an obfuscator configured step by step, see `ObfuscatorBuilder`.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::passes::{ChaoticShuffle, Substitution};
+    /// use ebo::Obfuscator;
+    ///
+    /// let bytecode = vec![0x60, 0x01, 0x60, 0x02, 0x01, 0x00]; // PUSH1 1, PUSH1 2, ADD, STOP
+    /// let mut obfuscator = Obfuscator::builder()
+    ///     .seed(7)
+    ///     .pass(ChaoticShuffle::default())
+    ///     .pass(Substitution::default())
+    ///     .gas_budget(0.15)
+    ///     .build(&bytecode);
+    /// let obfuscated = obfuscator.obfuscate().unwrap();
+    /// ```
+    pub fn builder() -> ObfuscatorBuilder {
+        ObfuscatorBuilder::default()
+    }
+
+    /// creates a new obfuscator instance for the given bytecode and seed, using the standard pipeline.
     ///
     /// # arguments
     /// * `bytecode` - slice of raw evm bytecode to obfuscate.
@@ -44,159 +73,395 @@ impl Obfuscator {
     ///
     /// # example
     /// ```
+    /// use ebo::Obfuscator;
+    ///
     /// let bytecode = vec![0x01, 0x57]; // ADD, JUMPI
     /// let obfuscator = Obfuscator::new(&bytecode, 42);
     /// ```
     pub fn new(bytecode: &[u8], seed: u64) -> Self {
-        let mut hasher = Sha256::new();
-        hasher.update(seed.to_le_bytes());
-        let hash = hasher.finalize();
-        let chaotic_seed = f64::from_le_bytes(hash[0..8].try_into().unwrap()) / u64::MAX as f64;
+        Self::with_pipeline(bytecode, seed, Pipeline::default())
+    }
 
+    /// creates a new obfuscator instance that applies a custom pipeline.
+    ///
+    /// # arguments
+    /// * `bytecode` - slice of raw evm bytecode to obfuscate.
+    /// * `seed` - 64-bit unsigned integer seed for deterministic obfuscation.
+    /// * `pipeline` - passes to apply, in order.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::{Obfuscator, Pipeline};
+    ///
+    /// let pipeline = Pipeline::default().without("spaghetti");
+    /// let obfuscator = Obfuscator::with_pipeline(&[0x01, 0x57], 42, pipeline);
+    /// ```
+    pub fn with_pipeline(bytecode: &[u8], seed: u64, pipeline: Pipeline) -> Self {
         Obfuscator {
             bytecode: bytecode.to_vec(),
-            rng: StdRng::seed_from_u64(seed),
-            chaotic_seed,
+            pipeline,
+            ctx: Ctx::new(seed),
+            frozen: Vec::new(),
+            seed,
+            rounds: 1,
+            output: None,
         }
     }
 
-    /// transforms an input x into a new value using piecewise trigonometric formulas, generating a chaotic
-    /// sequence constrained to [0, 1]. this sequence drives the obfuscation’s shuffle intensity, leveraging
-    /// deterministic randomness to enhance security while preserving repeatability.
+    /// keeps the given byte ranges of the input out of reach of every pass. the code in them is
+    /// emitted verbatim, apart from jump targets, which are still patched to wherever their
+    /// jumpdests end up.
     ///
-    /// this is heavily inspired by bian’s chebyshev-pwlcm chaotic map (section iii.b), this function produces
-    /// pseudo-random values for the chaotic shuffle, ensuring deterministic yet unpredictable opcode
-    /// reordering within basic blocks.
+    /// # arguments
+    /// * `ranges` - byte ranges of the input bytecode to keep verbatim.
+    pub fn freeze(&mut self, ranges: Vec<Range<usize>>) {
+        self.frozen.extend(ranges);
+    }
+
+    /// applies the pipeline `rounds` times, each round on the program left by the last, so the
+    /// transformations of later rounds work on already obfuscated code. the program is kept lifted
+    /// between rounds rather than assembled and lifted again, because code pointers that are not
+    /// jump targets (such as constant pool offsets) would lose their labels on the way. every round
+    /// after the first draws from its own seed, derived from the original one.
     ///
     /// # arguments
-    /// * `x` - current value in the chaotic sequence (between 0.0 and 1.0).
+    /// * `rounds` - number of rounds, at least 1.
+    pub fn repeat(&mut self, rounds: usize) {
+        self.rounds = rounds.max(1);
+    }
+
+    /// limits the size of the obfuscated code, which defaults to the eip-170 limit of 24,576 bytes.
+    /// passes that would grow the code beyond it are scaled back or skipped.
     ///
-    /// # returns
-    /// next value in the chaotic sequence, used to control shuffle intensity.
-    fn chaotic_map(&mut self, x: f64) -> f64 {
-        // a constant that influences the chaotic behavior.
-        // this value is chosen to create a nonlinear effect, often seen in chaotic systems to amplify small changes in input.
-        let mu = 3.9;
-        // a threshold that splits the input range into two different transformation rules, adding piecewise complexity.
-        let p = 0.4;
-
-        if x < p {
-            (x.cos() * mu * x.cos()).sin().abs() % 1.0
-        } else {
-            (1.0 - x).sin() % 1.0
-        }
+    /// # arguments
+    /// * `max_size` - largest accepted output size in bytes.
+    pub fn limit_size(&mut self, max_size: usize) {
+        self.ctx.max_size = max_size;
     }
 
-    /// obfuscates the stored bytecode using multiple techniques.
-    /// applies chaotic shuffle, opcode substitution, false branch obfuscation, and flower instructions
-    /// to increase control flow graph (cfg) complexity and analysis effort, making reverse engineering
-    /// difficult (eveilm, page 47; bosc, table i). preserves functional equivalence for evm execution.
+    /// sets the fork the output has to be valid for, which defaults to cancun. passes whose output
+    /// fails validation are scaled back or skipped.
+    ///
+    /// # arguments
+    /// * `fork` - target fork.
+    pub fn target(&mut self, fork: Fork) {
+        self.ctx.fork = fork;
+    }
+
+    /// restricts the output to what `chain` runs, on top of the target fork: opcodes its profile
+    /// lists as unsupported, and those of forks it has not adopted, are treated like undefined ones.
+    ///
+    /// # arguments
+    /// * `chain` - chain the output is deployed to.
+    pub fn deploy_on(&mut self, chain: Chain) {
+        self.ctx.chain = Some(chain);
+    }
+
+    /// limits the gas the obfuscation may add to any path through the code, as a fraction of the
+    /// path's original cost (0.15 for 15%). passes that exceed the budget are scaled back or skipped.
+    ///
+    /// # arguments
+    /// * `overhead` - largest accepted relative gas overhead.
+    pub fn limit_gas_overhead(&mut self, overhead: f64) {
+        self.ctx.max_gas_overhead = Some(overhead);
+    }
+
+    /// makes the passes draw their random choices from `rng` instead of the generator seeded with the
+    /// seed, e.g. a recorded sequence to replay a run that went wrong, or a hardware source. the
+    /// output is then only as reproducible as `rng`. later rounds keep drawing from it.
+    ///
+    /// # arguments
+    /// * `rng` - the source of randomness.
+    pub fn draw_from(&mut self, rng: impl RngCore + 'static) {
+        self.ctx.draw_from(rng);
+    }
+
+    /// reuses the blocks the passes transformed in earlier runs with the same seed and settings from
+    /// `cache`, and adds those of this run to it, see `Ctx::transform_blocks`. the output is the same
+    /// as without it.
+    ///
+    /// # arguments
+    /// * `cache` - cache of transformed blocks, which may be shared with other runs.
+    pub fn use_cache(&mut self, cache: Arc<BlockCache>) {
+        self.ctx.cache = Some(cache);
+    }
+
+    /// keys the randomness of the run with `key`: the passes draw from chacha20 keyed with it, on a
+    /// stream selected by the seed, so an attacker who knows the tool cannot search the seeds to
+    /// reconstruct which transformations were applied. the same key and seed reproduce the output.
+    ///
+    /// # arguments
+    /// * `key` - key derived from a user secret, see `Key::derive`.
+    pub fn use_key(&mut self, key: Key) {
+        self.ctx.use_key(key, self.seed);
+    }
+
+    /// obfuscates the stored bytecode by running every pass of the pipeline.
+    /// the passes increase control flow graph (cfg) complexity and analysis effort, making reverse
+    /// engineering difficult (eveilm, page 47; bosc, table i).
     ///
     /// # returns
-    /// vector of obfuscated bytecode bytes.
+    /// vector of obfuscated bytecode bytes, or `EboError::Relocation` if the lifted input cannot be
+    /// assembled again.
     ///
     /// # example
     /// ```
+    /// use ebo::Obfuscator;
+    ///
     /// let bytecode = vec![0x01, 0x57]; // ADD, JUMPI
     /// let mut obfuscator = Obfuscator::new(&bytecode, 42);
-    /// let obfuscated = obfuscator.obfuscate();
+    /// let obfuscated = obfuscator.obfuscate().unwrap();
     /// // may produce e.g., [0x60, 0x01, 0x01, 0x60, 0x01, 0x01, 0x57, 0x5B, 0x60, 0xXX, 0x50, 0x00]
     /// ```
-    pub fn obfuscate(&mut self) -> Vec<u8> {
-        let blocks = parse_bytecode(&self.bytecode);
-        let mut new_bytecode = Vec::new();
-        let mut chaotic_val = self.chaotic_seed;
-
-        for block in blocks {
-            let mut block_bytes = Vec::new();
-            let mut opcodes: Vec<Opcode> = block.opcodes;
-
-            // Chaotic shuffle within block (which avoids shuffling jump-related opcodes)
-            //
-            // the chaotic shuffle reorders non-control-flow opcodes within each basic block to obscure the code’s structure.
-            // it uses the chaotic_map function to derive a sequence of values that influence the number of shuffles and the
-            // specific reordering, which is guided by a seed-derived chaotic_seed.
-            if self.rng.gen_bool(0.3) {
-                chaotic_val = self.chaotic_map(chaotic_val);
-                let shuffle_count = (chaotic_val * opcodes.len() as f64) as usize;
-                let safe_opcodes: Vec<_> = opcodes
-                    .iter()
-                    .enumerate()
-                    .filter(|(_, op)| !matches!(op, Opcode::JUMPI | Opcode::JUMPDEST)) // to avoid invalid jumps or broken execution paths.
-                    .collect();
-                let mut indices: Vec<usize> = safe_opcodes.iter().map(|&(i, _)| i).collect();
-                for _ in 0..shuffle_count {
-                    if indices.len() > 1 {
-                        let i = self.rng.gen_range(0..indices.len());
-                        let j = self.rng.gen_range(0..indices.len());
-                        indices.swap(i, j);
-                    }
-                }
-                let mut new_opcodes = opcodes.clone();
-                for (new_idx, &old_idx) in indices.iter().enumerate() {
-                    if let Some((_, op)) = safe_opcodes.get(new_idx) {
-                        new_opcodes[old_idx] = (*op).clone();
-                    }
-                }
-                opcodes = new_opcodes;
-            }
+    pub fn obfuscate(&mut self) -> Result<Vec<u8>, EboError> {
+        // the gas budget holds for the whole run, so every round gets an equal share of it
+        let total_gas_overhead = self.ctx.max_gas_overhead;
+        self.ctx.max_gas_overhead = total_gas_overhead
+            .map(|overhead| (1.0 + overhead).powf(1.0 / self.rounds as f64) - 1.0);
 
-            // apply opcode substitution, false branch obfuscation, and flower instructions
-            for op in opcodes {
-                match op {
-                    Opcode::ADD => {
-                        if self.rng.gen_bool(0.5) {
-                            // apply opcode substitution: replace add -> push1 1 add push1 1 add (eveilm, page 59)
-                            block_bytes.extend_from_slice(&[0x60, 0x01, 0x01, 0x60, 0x01, 0x01]);
-                        } else {
-                            // retain original add opcode without substitution
-                            block_bytes.push(0x01);
-                        }
-                    }
-                    Opcode::JUMPI => {
-                        // retain jumpi opcode
-                        block_bytes.push(0x57);
-                        if self.rng.gen_bool(0.4) {
-                            // apply false branch obfuscation: add unreachable jumpdest -> push1 <random>, pop, stop (bosc, section 2.2)
-                            block_bytes.extend_from_slice(&[
-                                0x5B,
-                                0x60,
-                                self.rng.gen(),
-                                0x50,
-                                0x00,
-                            ]);
-                        }
-                    }
-                    Opcode::STOP | Opcode::RETURN => {
-                        // retain stop or return opcode
-                        block_bytes.push(if op == Opcode::STOP { 0x00 } else { 0xF3 });
-                        if self.rng.gen_bool(0.3) {
-                            // apply flower instruction obfuscation: add unreachable push1 <random> pop push1 <random> pop (bosc, section 2.4)
-                            block_bytes.extend_from_slice(&[
-                                0x60,
-                                self.rng.gen(),
-                                0x50,
-                                0x60,
-                                self.rng.gen(),
-                                0x50,
-                            ]);
-                        }
-                    }
-                    Opcode::JUMPDEST => {
-                        // retain jumpdest opcode without additional obfuscation
-                        block_bytes.push(0x5B)
-                    }
-                    Opcode::Other(b) => {
-                        // retain unrecognized opcode without obfuscation
-                        block_bytes.push(b)
-                    }
+        let _span = info_span!(
+            "obfuscate",
+            rounds = self.rounds,
+            passes = self.pipeline.names().len()
+        )
+        .entered();
+        let mut ir = self.lift();
+        self.ctx.slots = SlotMap::default();
+        // with a pass rewriting the dispatcher, every pass is held to the routes of the input
+        let rewrites = self
+            .pipeline
+            .names()
+            .iter()
+            .any(|name| DISPATCHER.contains(name));
+        self.ctx.routes = match rewrites {
+            true => match Routes::new(&self.bytecode) {
+                Ok(routes) => Some(Arc::new(routes)),
+                Err(err) => {
+                    warn!("Not checking the routes through the dispatcher: {}", err);
+                    None
                 }
+            },
+            false => None,
+        };
+        let mut run = Ok(());
+        for round in 0..self.rounds {
+            if round > 0 {
+                debug!("Starting round {} of {}", round + 1, self.rounds);
+                self.ctx.reseed(seed::round_seed(self.seed, round));
+            }
+            run = self.pipeline.run(&mut ir, &mut self.ctx);
+            if run.is_err() {
+                break;
             }
-
-            new_bytecode.extend(block_bytes);
         }
 
-        debug!("Chaotic shuffle applied with seed: {}", self.chaotic_seed);
-        new_bytecode
+        self.ctx.max_gas_overhead = total_gas_overhead;
+        run?;
+        let bytecode = ir.assemble()?;
+        self.output = Some(ir);
+        Ok(bytecode)
+    }
+
+    /// obfuscates the stored bytecode like `obfuscate`, but returns what every pass did to each
+    /// basic block of the input instead of the obfuscated code, see `Plan`.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::Obfuscator;
+    ///
+    /// let bytecode = vec![0x60, 0x01, 0x60, 0x02, 0x01, 0x00]; // PUSH1 1, PUSH1 2, ADD, STOP
+    /// let plan = Obfuscator::new(&bytecode, 42).plan().unwrap();
+    /// assert_eq!(plan.size.0, bytecode.len());
+    /// ```
+    pub fn plan(&mut self) -> Result<Plan, EboError> {
+        self.ctx.trail = Some(Vec::new());
+        let run = self.obfuscate();
+        let trail = self.ctx.trail.take().unwrap_or_default();
+        run?;
+        Plan::new(
+            &self.bytecode,
+            &self.lift(),
+            &trail,
+            &self.ctx.stats,
+            self.seed,
+        )
+    }
+
+    /// the input lifted, with the frozen ranges frozen.
+    fn lift(&self) -> Program {
+        // the push20s of library markers stay whole, so linking can still fill them in
+        let mut frozen = self.frozen.clone();
+        frozen.extend(link::regions(&self.bytecode));
+        let mut ir = Program::lift(&self.bytecode);
+        ir.freeze(&frozen);
+        ir
+    }
+
+    /// the program counters of the output of the last run mapped to those of the input, see
+    /// `PcMap`; `None` before the first run.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::Obfuscator;
+    ///
+    /// let bytecode = vec![0x60, 0x01, 0x60, 0x02, 0x01, 0x00]; // PUSH1 1, PUSH1 2, ADD, STOP
+    /// let mut obfuscator = Obfuscator::new(&bytecode, 42);
+    /// let obfuscated = obfuscator.obfuscate().unwrap();
+    /// let map = obfuscator.pc_map().unwrap().unwrap();
+    /// for &pc in map.obfuscated(5) {
+    ///     assert_eq!(obfuscated[pc], 0x00);
+    /// }
+    /// ```
+    pub fn pc_map(&self) -> Result<Option<PcMap>, EboError> {
+        self.output.as_ref().map(PcMap::new).transpose()
+    }
+
+    /// the storage slots of the input the output of the last run reads and writes elsewhere, see
+    /// `SlotMap`; empty unless `storage-remap` moved any.
+    pub fn slot_map(&self) -> &SlotMap {
+        &self.ctx.slots
+    }
+
+    /// number of transformations each pass applied, keyed by pass name, summed over every round
+    /// run so far. passes that were skipped every round are missing.
+    pub fn stats(&self) -> &BTreeMap<&'static str, usize> {
+        &self.ctx.stats
+    }
+}
+
+/// builds an `Obfuscator` without a configuration file. everything left unset keeps the default
+/// of `Obfuscator::new`: seed 42, the standard pipeline, one round, cancun on no particular chain,
+/// the eip-170 size limit,
+/// no gas budget, and randomness drawn from the seed.
+#[derive(Default)]
+pub struct ObfuscatorBuilder {
+    seed: Option<u64>,
+    key: Option<Key>,
+    rng: Option<Box<dyn RngCore>>,
+    pipeline: Option<Pipeline>,
+    rounds: Option<usize>,
+    fork: Option<Fork>,
+    chain: Option<Chain>,
+    max_size: Option<usize>,
+    gas_budget: Option<f64>,
+    frozen: Vec<Range<usize>>,
+    cache: Option<Arc<BlockCache>>,
+}
+
+impl ObfuscatorBuilder {
+    /// seed of the run.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// secret the randomness is keyed with, see `Obfuscator::use_key`.
+    pub fn key(mut self, key: Key) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// cache of transformed blocks, see `Obfuscator::use_cache`.
+    pub fn cache(mut self, cache: Arc<BlockCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// source the passes draw their random choices from, see `Obfuscator::draw_from`.
+    pub fn rng(mut self, rng: impl RngCore + 'static) -> Self {
+        self.rng = Some(Box::new(rng));
+        self
+    }
+
+    /// appends `pass` to the pipeline. the first pass given replaces the standard pipeline, so
+    /// only the passes given run, in the order given.
+    // `Pipeline::default` is the standard pipeline, not the empty one this starts from
+    #[allow(clippy::unwrap_or_default)]
+    pub fn pass(mut self, pass: impl Pass + 'static) -> Self {
+        self.pipeline = Some(
+            self.pipeline
+                .take()
+                .unwrap_or_else(Pipeline::new)
+                .with(pass),
+        );
+        self
+    }
+
+    /// runs `pipeline` instead of the standard one.
+    pub fn pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.pipeline = Some(pipeline);
+        self
+    }
+
+    /// number of times the pipeline is applied, see `Obfuscator::repeat`.
+    pub fn rounds(mut self, rounds: usize) -> Self {
+        self.rounds = Some(rounds);
+        self
+    }
+
+    /// fork the output has to be valid for, see `Obfuscator::target`.
+    pub fn fork(mut self, fork: Fork) -> Self {
+        self.fork = Some(fork);
+        self
+    }
+
+    /// chain the output is deployed to, see `Obfuscator::deploy_on`.
+    pub fn chain(mut self, chain: Chain) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    /// largest size of the output in bytes, see `Obfuscator::limit_size`.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// largest gas overhead on any path, as a fraction of its original cost (0.15 for 15%), see
+    /// `Obfuscator::limit_gas_overhead`.
+    pub fn gas_budget(mut self, overhead: f64) -> Self {
+        self.gas_budget = Some(overhead);
+        self
+    }
+
+    /// keeps `range` of the input verbatim, see `Obfuscator::freeze`. may be called repeatedly.
+    pub fn freeze(mut self, range: Range<usize>) -> Self {
+        self.frozen.push(range);
+        self
+    }
+
+    /// an obfuscator for `bytecode` set up as described.
+    pub fn build(self, bytecode: &[u8]) -> Obfuscator {
+        let mut obfuscator = Obfuscator::with_pipeline(
+            bytecode,
+            self.seed.unwrap_or(42),
+            self.pipeline.unwrap_or_default(),
+        );
+        obfuscator.freeze(self.frozen);
+        if let Some(key) = self.key {
+            obfuscator.use_key(key);
+        }
+        if let Some(rng) = self.rng {
+            obfuscator.draw_from(rng);
+        }
+        if let Some(cache) = self.cache {
+            obfuscator.use_cache(cache);
+        }
+        if let Some(rounds) = self.rounds {
+            obfuscator.repeat(rounds);
+        }
+        if let Some(fork) = self.fork {
+            obfuscator.target(fork);
+        }
+        if let Some(chain) = self.chain {
+            obfuscator.deploy_on(chain);
+        }
+        if let Some(max_size) = self.max_size {
+            obfuscator.limit_size(max_size);
+        }
+        if let Some(overhead) = self.gas_budget {
+            obfuscator.limit_gas_overhead(overhead);
+        }
+        obfuscator
     }
 }
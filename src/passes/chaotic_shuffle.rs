@@ -0,0 +1,187 @@
+/// chaotic shuffle pass.
+/// reorders non-control-flow instructions within basic blocks, with the number of swaps driven by a
+/// chaotic sequence derived from the seed (bian, section iii.b).
+///
+/// only pure instructions move (see `symbolic::pure`). everything that reads or writes state stays
+/// where it is: storage, transient storage, memory, logs and calls depend on the order they run in,
+/// and so do reads of the environment, jumps and jumpdests.
+use super::{split_after, Ctx, Ir, Pass};
+use crate::evm::{op, stack_effect};
+use crate::reloc::Item;
+use crate::symbolic;
+use rand::Rng;
+use std::ops::Range;
+use tracing::debug;
+
+/// shuffles instructions inside randomly chosen blocks.
+pub struct ChaoticShuffle {
+    /// chance that a given block is shuffled.
+    pub probability: f64,
+}
+
+impl Default for ChaoticShuffle {
+    fn default() -> Self {
+        ChaoticShuffle { probability: 0.3 }
+    }
+}
+
+/// transforms an input x into a new value using piecewise trigonometric formulas, generating a chaotic
+/// sequence constrained to [0, 1]. this sequence drives the obfuscation’s shuffle intensity, leveraging
+/// deterministic randomness to enhance security while preserving repeatability.
+///
+/// this is heavily inspired by bian’s chebyshev-pwlcm chaotic map (section iii.b), this function produces
+/// pseudo-random values for the chaotic shuffle, ensuring deterministic yet unpredictable opcode
+/// reordering within basic blocks.
+///
+/// # arguments
+/// * `x` - current value in the chaotic sequence (between 0.0 and 1.0).
+///
+/// # returns
+/// next value in the chaotic sequence, used to control shuffle intensity.
+pub(super) fn chaotic_map(x: f64) -> f64 {
+    // a constant that influences the chaotic behavior.
+    // this value is chosen to create a nonlinear effect, often seen in chaotic systems to amplify small changes in input.
+    let mu = 3.9;
+    // a threshold that splits the input range into two different transformation rules, adding piecewise complexity.
+    let p = 0.4;
+
+    if x < p {
+        (x.cos() * mu * x.cos()).sin().abs() % 1.0
+    } else {
+        (1.0 - x).sin() % 1.0
+    }
+}
+
+/// splits a lifted item stream into basic blocks, ending a block after every jumpi, jumpdest, stop,
+/// or return instruction, mirroring the boundaries used by `parse_bytecode` (bian, section iii.b).
+/// frozen regions end a block too, so nothing is moved across them.
+fn split_blocks(items: Vec<Item>) -> Vec<Vec<Item>> {
+    split_after(items, |item| {
+        matches!(
+            item,
+            Item::Op {
+                opcode: op::JUMPI | op::JUMPDEST | op::STOP | op::RETURN,
+                ..
+            } | Item::Frozen(_)
+        )
+    })
+}
+
+/// the runs of at least two adjacent pure instructions of `block` (see `symbolic::pure`), the only
+/// instructions the shuffle moves, or `None` if there are none. label pushes, jumpdest marks and
+/// data are never moved, so relocation stays intact.
+fn movable(block: &[Item]) -> Option<Vec<Range<usize>>> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for (idx, item) in block.iter().enumerate() {
+        if !is_pure(item) {
+            start = idx + 1;
+        } else if idx > start && block.get(idx + 1).is_none_or(|next| !is_pure(next)) {
+            runs.push(start..idx + 1);
+        }
+    }
+    (!runs.is_empty()).then_some(runs)
+}
+
+/// returns true if `item` is a pure instruction.
+fn is_pure(item: &Item) -> bool {
+    matches!(item, Item::Op { opcode, .. } if symbolic::pure(*opcode))
+}
+
+/// the bytes of the instructions `items`.
+fn code(items: &[Item]) -> Vec<u8> {
+    let mut code = Vec::new();
+    for item in items {
+        if let Item::Op {
+            opcode, immediate, ..
+        } = item
+        {
+            code.push(*opcode);
+            code.extend(immediate);
+        }
+    }
+    code
+}
+
+/// returns true if the pure code `shuffled` provably leaves the values `original` leaves, without
+/// reading deeper into the stack.
+fn same(original: &[u8], shuffled: &[u8]) -> bool {
+    let depth = |code| stack_effect(code).map(|(depth, _)| depth);
+    depth(shuffled) <= depth(original) && symbolic::equivalent(original, shuffled)
+}
+
+/// reorders the instructions of the pure `runs` of `block` with a number of swaps given by
+/// `chaotic_val`, and returns how many it kept.
+///
+/// the chaotic shuffle reorders non-control-flow opcodes within each basic block to obscure the code’s structure.
+/// it uses the chaotic_map function to derive a sequence of values that influence the number of shuffles and the
+/// specific reordering, which is guided by a seed-derived chaotic_seed.
+///
+/// every step swaps two adjacent instructions of a run, and the swap is kept only if the symbolic
+/// stack evaluator proves the run still leaves the values it left before, reading no deeper (`dup1, push4 s, eq`
+/// becomes `push4 s, dup1, eq`, which compares `s` with itself, so that swap is undone). the result
+/// is a permutation of the block by construction: nothing is copied, dropped, or duplicated.
+fn shuffle(
+    mut block: Vec<Item>,
+    runs: &[Range<usize>],
+    chaotic_val: f64,
+    rng: &mut impl Rng,
+) -> (Vec<Item>, usize) {
+    let originals: Vec<Vec<u8>> = runs.iter().map(|run| code(&block[run.clone()])).collect();
+    let shuffle_count = (chaotic_val * block.len() as f64) as usize;
+    let mut kept = 0;
+    for _ in 0..shuffle_count {
+        let run = rng.gen_range(0..runs.len());
+        let (range, original) = (runs[run].clone(), &originals[run]);
+        let i = rng.gen_range(range.start..range.end - 1);
+        block.swap(i, i + 1);
+        if same(original, &code(&block[range])) {
+            kept += 1;
+        } else {
+            block.swap(i, i + 1);
+        }
+    }
+    (block, kept)
+}
+
+impl Pass for ChaoticShuffle {
+    fn name(&self) -> &'static str {
+        "chaotic-shuffle"
+    }
+
+    /// every block decides with its own generator whether it is shuffled, and a shuffled block
+    /// starts the chaotic map from the chaotic seed offset by a draw of it, rather than taking the
+    /// next value of one sequence run across the blocks, so a block is shuffled the same wherever it
+    /// is and whatever comes before it.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let blocks = split_blocks(std::mem::take(&mut ir.items));
+        let chaotic_seed = ctx.chaotic_seed;
+        let blocks = blocks
+            .into_iter()
+            .map(|block| (block, chaotic_seed))
+            .collect();
+        let probability = self.probability;
+        let (items, shuffled) = ctx.transform_blocks(
+            self.name(),
+            &probability.to_string(),
+            blocks,
+            |block, chaotic_seed| Some((movable(block)?, chaotic_seed)),
+            |block, (runs, chaotic_seed), rng| {
+                if !rng.gen_bool(probability) {
+                    return (block, 0);
+                }
+                let chaotic_val = chaotic_map((chaotic_seed + rng.gen::<f64>()) % 1.0);
+                let (block, kept) = shuffle(block, runs, chaotic_val, rng);
+                (block, (kept > 0) as usize)
+            },
+        );
+
+        ir.items = items;
+        ctx.record(self.name(), shuffled);
+        debug!("Chaotic shuffle applied with seed: {}", ctx.chaotic_seed);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
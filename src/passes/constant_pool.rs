@@ -0,0 +1,90 @@
+/// constant pooling pass.
+/// moves push32 constants out of the instruction stream into a data section appended after the code,
+/// so they no longer show up in a plain scan of the bytecode.
+///
+/// the evm finds jumpdests by reading the whole code as instructions, data included, so a section
+/// ending in the middle of a push would hide the jumpdests of code that later passes, or later
+/// rounds, place behind it. the section is padded until it ends where an instruction does.
+use super::{Ctx, Ir, Pass};
+use crate::evm::{op, push_size};
+use crate::reloc::Item;
+use tracing::debug;
+
+/// pools push32 constants into a data section loaded with codecopy.
+pub struct ConstantPool;
+
+impl Pass for ConstantPool {
+    fn name(&self) -> &'static str {
+        "constant-pool"
+    }
+
+    /// moves every push32 constant into a data region appended after the code and replaces each use with
+    /// a loader that copies the constant back from code: `push1 32, push <slot>, push1 0x40, mload, codecopy,
+    /// push1 0x40, mload, mload`. the constant is copied to the free memory pointer without advancing it,
+    /// so memory owned by solidity is never clobbered, and the net stack effect (+1) matches the original
+    /// push. identical constants share one slot. the slot offset is a label push, so the loaders stay
+    /// correct however much code the other passes insert in front of the pool.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let pool = ir.new_label();
+        let mut constants: Vec<Vec<u8>> = Vec::new();
+        let mut items = Vec::with_capacity(ir.items.len());
+        let mut loads = 0;
+
+        for item in std::mem::take(&mut ir.items) {
+            let Item::Op {
+                opcode: op::PUSH32,
+                immediate,
+                ..
+            } = item
+            else {
+                items.push(item);
+                continue;
+            };
+            let slot = constants
+                .iter()
+                .position(|c| *c == immediate)
+                .unwrap_or_else(|| {
+                    constants.push(immediate);
+                    constants.len() - 1
+                });
+            items.extend([
+                Item::push(&[0x20]),
+                Item::PushLabel {
+                    label: pool,
+                    addend: slot * 32,
+                    width: 2,
+                },
+                Item::push(&[0x40]),
+                Item::op(op::MLOAD),
+                Item::op(op::CODECOPY),
+                Item::push(&[0x40]),
+                Item::op(op::MLOAD),
+                Item::op(op::MLOAD),
+            ]);
+            loads += 1;
+        }
+
+        ir.items = items;
+        if !constants.is_empty() {
+            debug!(
+                "Pooled {} push32 constants into the data section",
+                constants.len()
+            );
+            ir.fence();
+            ir.items.push(Item::Mark(pool));
+            ir.items.push(Item::Data(padded(constants.concat())));
+        }
+        ctx.record(self.name(), loads);
+    }
+}
+
+/// `data` followed by as many zeros as the push immediates it contains, read as code, run past its
+/// end.
+fn padded(mut data: Vec<u8>) -> Vec<u8> {
+    let mut pc = 0;
+    while pc < data.len() {
+        pc += 1 + push_size(data[pc]);
+    }
+    data.resize(pc, 0);
+    data
+}
@@ -0,0 +1,188 @@
+/// decoy storage read pass.
+/// loads storage slots the contract never writes, so data-flow analysis has phantom state to chase:
+/// `push32 <slot>, sload, pop` runs behind stop, return and revert, where nothing reaches them, and
+/// the jumpdests of external functions are guarded by `push32 <slot>, sload, push <decoy>, jumpi`,
+/// whose decoy reads more phantom slots and stores one of them before it ends in `invalid`. every run
+/// draws a few random slots and reads only those, so they look like state variables of their own.
+///
+/// a guard is only opaque while its slot stays zero. the slots are random 256-bit words, so the
+/// contract can only ever write one by storing to a slot it does not compute from a constant or a
+/// hash; guards are left out if any `sstore` cannot be traced that way (see `storage_remap`), if
+/// frozen code touches storage, or if the code delegates calls, which run other code on its storage.
+///
+/// the guards are executed, and under eip-2929 the first read of each slot in a transaction is cold,
+/// which `gas` charges. to keep that gas off the paths a caller gives a fixed stipend (`receive` and
+/// the fallback), only blocks the dispatcher reaches through a selector are guarded, so the pass runs
+/// before `selector-mask` while the dispatcher still has the shape `dispatcher::functions`
+/// recognizes.
+use super::storage_remap::{self, Value};
+use super::{Ctx, Ir, Pass};
+use crate::dispatcher;
+use crate::evm::op;
+use crate::reloc::{Item, Label};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashSet;
+use tracing::debug;
+
+// opcodes the pass reads that `evm::op` has no name for
+const CALLCODE: u8 = 0xF2;
+const DELEGATECALL: u8 = 0xF4;
+
+/// number of phantom slots drawn per run.
+const SLOTS: usize = 3;
+
+/// inserts reads of never-written storage slots.
+pub struct DecoyReads {
+    /// chance that a given stop, return or revert is followed by a decoy read, and that a given
+    /// function block is guarded by one.
+    pub probability: f64,
+}
+
+impl Default for DecoyReads {
+    fn default() -> Self {
+        DecoyReads { probability: 0.1 }
+    }
+}
+
+/// `push32 <slot>, sload`.
+fn read(slot: &[u8; 32]) -> [Item; 2] {
+    [Item::push(slot), Item::op(op::SLOAD)]
+}
+
+/// returns true if a slot the contract never writes stays zero: every `sstore` of `ir` writes a
+/// constant or hashed slot, no frozen code touches storage, and nothing delegates.
+fn untouched(ir: &Ir) -> bool {
+    let delegates = ir.flat_items().into_iter().any(|item| {
+        matches!(
+            item,
+            Item::Op {
+                opcode: CALLCODE | DELEGATECALL,
+                ..
+            }
+        )
+    });
+    !delegates
+        && !storage_remap::frozen_access(&ir.items)
+        && storage_remap::keys(&ir.items)
+            .into_iter()
+            .all(|(idx, key)| {
+                key != Value::Unknown
+                    || !matches!(
+                        ir.items[idx],
+                        Item::Op {
+                            opcode: op::SSTORE,
+                            ..
+                        }
+                    )
+            })
+}
+
+/// labels of the jumpdests inside the external functions of `ir`, or nothing if its dispatcher is
+/// not recognized.
+fn function_blocks(ir: &Ir) -> HashSet<Label> {
+    let (Ok(code), Ok(offsets)) = (ir.assemble(), ir.offsets()) else {
+        return HashSet::new();
+    };
+    let blocks: Vec<_> = dispatcher::functions(&code)
+        .into_iter()
+        .flat_map(|function| function.blocks)
+        .collect();
+    ir.flat_items()
+        .into_iter()
+        .zip(offsets)
+        .filter_map(|(item, offset)| match item {
+            Item::Mark(label) if blocks.iter().any(|block| block.contains(&offset)) => Some(*label),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Pass for DecoyReads {
+    fn name(&self) -> &'static str {
+        "decoy-reads"
+    }
+
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let slots: Vec<[u8; 32]> = (0..SLOTS).map(|_| ctx.rng.gen()).collect();
+        let guarded = if untouched(ir) {
+            function_blocks(ir)
+        } else {
+            debug!("Leaving out guards: the contract may write any storage slot");
+            HashSet::new()
+        };
+
+        let original = std::mem::take(&mut ir.items);
+        let mut items = Vec::with_capacity(original.len());
+        let mut decoys = Vec::new();
+        let mut mark = None;
+        let mut applied = 0;
+        for item in original {
+            let ends = matches!(
+                item,
+                Item::Op {
+                    opcode: op::STOP | op::RETURN | op::REVERT,
+                    ..
+                }
+            );
+            let entered = matches!(
+                item,
+                Item::Op {
+                    opcode: op::JUMPDEST,
+                    ..
+                }
+            ) && mark.is_some_and(|label| guarded.contains(&label));
+            mark = match item {
+                Item::Mark(label) => Some(label),
+                _ => None,
+            };
+            items.push(item);
+            if !(ends || entered) || !ctx.rng.gen_bool(self.probability) {
+                continue;
+            }
+            let slot = slots.choose(&mut ctx.rng).expect("slots is not empty");
+            if ends {
+                // unreachable, but a jumpdest makes it a possible target of computed jumps
+                items.push(Item::op(op::JUMPDEST));
+                items.extend(read(slot));
+                items.extend([Item::op(op::POP), Item::op(op::STOP)]);
+            } else {
+                let decoy = ir.new_label();
+                items.extend(read(slot));
+                items.extend([
+                    Item::PushLabel {
+                        label: decoy,
+                        addend: 0,
+                        width: 2,
+                    },
+                    Item::op(op::JUMPI),
+                ]);
+                let (first, second) = (
+                    slots.choose(&mut ctx.rng).expect("slots is not empty"),
+                    slots.choose(&mut ctx.rng).expect("slots is not empty"),
+                );
+                decoys.extend([Item::Mark(decoy), Item::op(op::JUMPDEST)]);
+                decoys.extend(read(first));
+                decoys.push(Item::op(op::POP));
+                decoys.extend(read(second));
+                decoys.extend([
+                    Item::push(&ctx.rng.gen::<u32>().to_be_bytes()),
+                    Item::op(op::SSTORE),
+                    Item::op(op::INVALID),
+                ]);
+            }
+            applied += 1;
+        }
+
+        ir.items = items;
+        if !decoys.is_empty() {
+            ir.fence();
+            ir.items.extend(decoys);
+        }
+        ctx.record(self.name(), applied);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
@@ -0,0 +1,187 @@
+/// environment predicate pass.
+/// puts opaque predicates on the execution environment in front of basic blocks, comparing a value
+/// the code reads from the chain with what it is known to be wherever the code is deployed: the
+/// contract's own address is never zero, running code never has size zero, and `chainid` is the id
+/// of the chain the output is deployed to. a static analyzer that does not model the environment
+/// has to keep the decoy each predicate jumps to as a live branch.
+///
+/// a predicate is only used where `check` proves it constant on the target: its opcode has to run on
+/// the chain and return there what it returns on ethereum (see `Target::reliable`), and a chain id
+/// has to be configured and, on a chain with a profile, be one of the profile's ids.
+use super::{Ctx, Ir, Pass};
+use crate::chain::Target;
+use crate::evm::op;
+use crate::reloc::Item;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use tracing::debug;
+
+// opcodes the predicates read that no other pass emits
+const ADDRESS: u8 = 0x30;
+const CODESIZE: u8 = 0x38;
+const CHAINID: u8 = 0x46;
+
+/// a condition on the environment that holds wherever the code runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Predicate {
+    /// `address` is not zero: no code is ever deployed at the zero address.
+    Address,
+    /// `codesize` is not zero: the code reading it is there.
+    Codesize,
+    /// `chainid` is this id.
+    ChainId(u64),
+}
+
+impl Predicate {
+    /// the opcode the predicate reads.
+    pub fn opcode(self) -> u8 {
+        match self {
+            Predicate::Address => ADDRESS,
+            Predicate::Codesize => CODESIZE,
+            Predicate::ChainId(_) => CHAINID,
+        }
+    }
+
+    /// instructions leaving a nonzero value exactly when the predicate does not hold.
+    fn violated(self) -> Vec<Item> {
+        match self {
+            Predicate::Address | Predicate::Codesize => {
+                vec![Item::op(self.opcode()), Item::op(op::ISZERO)]
+            }
+            Predicate::ChainId(id) => {
+                let bytes = id.to_be_bytes();
+                let zeros = bytes.iter().take_while(|&&b| b == 0).count().min(7);
+                vec![
+                    Item::op(CHAINID),
+                    Item::push(&bytes[zeros..]),
+                    Item::op(op::XOR),
+                ]
+            }
+        }
+    }
+}
+
+/// checks that `predicate` holds wherever code deployed to `target` runs.
+///
+/// # returns
+/// nothing, or why the predicate cannot be relied on.
+///
+/// # example
+/// ```
+/// use ebo::chain::{Chain, Target};
+/// use ebo::passes::env_predicate::{check, Predicate};
+/// use ebo::Fork;
+///
+/// let arbitrum = Target::new(Fork::Cancun, Some(Chain::Arbitrum));
+/// assert!(check(Predicate::Address, arbitrum).is_ok());
+/// assert!(check(Predicate::ChainId(42_161), arbitrum).is_ok());
+/// assert!(check(Predicate::ChainId(1), arbitrum).is_err());
+/// ```
+pub fn check(predicate: Predicate, target: Target) -> Result<(), String> {
+    let opcode = predicate.opcode();
+    if !target.supports(opcode) {
+        return Err(format!("{} does not run {:#04x}", target, opcode));
+    }
+    if !target.reliable(opcode) {
+        return Err(format!(
+            "{:#04x} returns chain-specific values on {}",
+            opcode, target
+        ));
+    }
+    if let (Predicate::ChainId(id), Some(chain)) = (predicate, target.chain) {
+        let ids = chain.profile().ids;
+        if !ids.contains(&id) {
+            return Err(format!(
+                "chain id {} is not an id of {} ({:?})",
+                id, chain, ids
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// puts environment predicates in front of basic blocks.
+pub struct EnvPredicate {
+    /// chance that a given basic block is guarded by a predicate.
+    pub probability: f64,
+    /// id of the chain the output is deployed to, which `chainid` is compared with. without one,
+    /// only the predicates that hold on every chain are used.
+    pub chain_id: Option<u64>,
+}
+
+impl Default for EnvPredicate {
+    fn default() -> Self {
+        EnvPredicate {
+            probability: 0.15,
+            chain_id: None,
+        }
+    }
+}
+
+impl Pass for EnvPredicate {
+    fn name(&self) -> &'static str {
+        "env-predicate"
+    }
+
+    /// guards the code after `jumpdest`s with one of the predicates `check` accepts for the target,
+    /// `<violated>, push <decoy>, jumpi`. the decoys (see `passes::decoy`) are placed after the
+    /// code, so the block itself falls through unchanged.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let target = ctx.target();
+        let candidates = [Predicate::Address, Predicate::Codesize]
+            .into_iter()
+            .chain(self.chain_id.map(Predicate::ChainId));
+        let mut predicates = Vec::new();
+        for predicate in candidates {
+            match check(predicate, target) {
+                Ok(()) => predicates.push(predicate),
+                Err(reason) => debug!("Leaving out predicate {:?}: {}", predicate, reason),
+            }
+        }
+        if predicates.is_empty() {
+            ctx.record(self.name(), 0);
+            return;
+        }
+
+        let original = std::mem::take(&mut ir.items);
+        let mut items = Vec::with_capacity(original.len());
+        let mut decoys = Vec::new();
+        let mut applied = 0;
+        for item in original {
+            let guarded = matches!(
+                item,
+                Item::Op {
+                    opcode: op::JUMPDEST,
+                    ..
+                }
+            );
+            items.push(item);
+            if !guarded || !ctx.rng.gen_bool(self.probability) {
+                continue;
+            }
+            let predicate = *predicates
+                .choose(&mut ctx.rng)
+                .expect("predicates is not empty");
+            let decoy = ir.new_label();
+            items.extend(predicate.violated());
+            items.extend([
+                Item::PushLabel {
+                    label: decoy,
+                    addend: 0,
+                    width: 2,
+                },
+                Item::op(op::JUMPI),
+            ]);
+            decoys.extend(super::decoy(decoy, &mut *ctx.rng));
+            applied += 1;
+        }
+
+        items.extend(decoys);
+        ir.items = items;
+        ctx.record(self.name(), applied);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
@@ -0,0 +1,134 @@
+/// exception control flow pass.
+/// routes control flow past traps, so a decompiler's control flow graph fills up with edges into
+/// exceptional halts it cannot prove dead. unconditional jumps become conditional ones on a value
+/// that is never zero (`codesize`), with a trap on the never-taken side: `invalid`, or a jump to a
+/// target that is not a jumpdest, computed so it is not a plain push in front of the jump. and at the
+/// head of some blocks, execution takes a detour through a call that fails: a `staticcall` of the
+/// contract itself with no gas, which reverts before the first instruction of the callee. the call
+/// succeeds when the contract has no code yet (in its constructor, or in code a constructor reaches
+/// through `delegatecall`), so both outcomes carry on with the block.
+///
+/// the failing call cannot change state (it is static and the callee never runs) but it does clear
+/// the return data buffer, so it is only made in code that makes no calls of its own, where the
+/// buffer is always empty anyway.
+use super::{Ctx, Ir, Pass};
+use crate::evm::op;
+use crate::reloc::Item;
+use rand::Rng;
+
+// opcodes the pass emits that `evm::op` has no name for
+const ADDRESS: u8 = 0x30;
+const CODESIZE: u8 = 0x38;
+const STATICCALL: u8 = 0xFA;
+
+/// opcodes that fill the return data buffer.
+const CALLS: [u8; 6] = [0xF0, 0xF1, 0xF2, 0xF4, 0xF5, STATICCALL];
+
+/// routes jumps past traps and block entries through failing calls.
+pub struct ExceptionFlow {
+    /// chance that a given jump, or block, is rewritten.
+    pub probability: f64,
+}
+
+impl Default for ExceptionFlow {
+    fn default() -> Self {
+        ExceptionFlow { probability: 0.2 }
+    }
+}
+
+/// returns true if `item` is the instruction `opcode`.
+fn is(item: Option<&Item>, opcode: u8) -> bool {
+    matches!(item, Some(Item::Op { opcode: o, .. }) if *o == opcode)
+}
+
+impl Pass for ExceptionFlow {
+    fn name(&self) -> &'static str {
+        "exception-flow"
+    }
+
+    /// rewrites `push <target>, jump` into `codesize, push <target>, jumpi` followed by a trap, and
+    /// follows `jumpdest`s with `staticcall(0, address, 0, 0, 0, 0), iszero, push <continue>, jumpi,
+    /// continue: jumpdest`.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let target = ctx.target();
+        let zero = || {
+            if target.supports(op::PUSH0) {
+                Item::op(op::PUSH0)
+            } else {
+                Item::push(&[0])
+            }
+        };
+        let original = std::mem::take(&mut ir.items);
+        // a callee whose first instruction is a stop succeeds without gas
+        let first = original.iter().find(|item| !matches!(item, Item::Mark(_)));
+        let detours = !is(first, op::STOP)
+            && target.supports(STATICCALL)
+            && target.reliable(op::GAS)
+            && target.mainnet_gas()
+            && !original.iter().any(|item| match item {
+                Item::Op { opcode, .. } => CALLS.contains(opcode),
+                Item::Frozen(_) => true,
+                _ => false,
+            });
+
+        let mut items = Vec::with_capacity(original.len());
+        let mut applied = 0;
+        let mut i = 0;
+        while i < original.len() {
+            let item = &original[i];
+            i += 1;
+            let jump = matches!(item, Item::PushLabel { .. }) && is(original.get(i), op::JUMP);
+            if jump && ctx.rng.gen_bool(self.probability) {
+                items.extend([Item::op(CODESIZE), item.clone(), Item::op(op::JUMPI)]);
+                if ctx.rng.gen_bool(0.5) {
+                    items.push(Item::op(op::INVALID));
+                } else {
+                    // jumps onto its own `add`
+                    let bad = ir.new_label();
+                    items.extend([
+                        zero(),
+                        Item::PushLabel {
+                            label: bad,
+                            addend: 0,
+                            width: 2,
+                        },
+                        Item::Mark(bad),
+                        Item::op(op::ADD),
+                        Item::op(op::JUMP),
+                    ]);
+                }
+                // the jump is replaced along with its push
+                i += 1;
+                applied += 1;
+                continue;
+            }
+            items.push(item.clone());
+
+            let head = is(Some(item), op::JUMPDEST);
+            if detours && head && ctx.rng.gen_bool(self.probability) {
+                let resume = ir.new_label();
+                items.extend(std::iter::repeat_with(zero).take(4));
+                items.extend([Item::op(ADDRESS), zero(), Item::op(STATICCALL)]);
+                items.extend([
+                    Item::op(op::ISZERO),
+                    Item::PushLabel {
+                        label: resume,
+                        addend: 0,
+                        width: 2,
+                    },
+                    Item::op(op::JUMPI),
+                    Item::Mark(resume),
+                    Item::op(op::JUMPDEST),
+                ]);
+                applied += 1;
+            }
+        }
+
+        ir.items = items;
+        ctx.record(self.name(), applied);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
@@ -0,0 +1,73 @@
+/// false branch pass.
+/// adds a jumpdest-headed decoy block after conditional jumps to disrupt control flow analysis
+/// (bosc, section 2.2). the fall-through of a jumpi is reachable, so it jumps over the decoy to the
+/// code that followed: `jumpi, push <over>, jump, jumpdest, <junk>, stop, over: jumpdest`, like the
+/// `rjumpi` over the decoys of eof code. the junk in the decoy is checked to be stack-neutral with
+/// the symbolic stack evaluator.
+use super::{Ctx, Ir, Pass};
+use crate::evm::op;
+use crate::reloc::Item;
+use crate::symbolic;
+use rand::Rng;
+
+/// inserts false branches after jumpi instructions.
+pub struct FalseBranch {
+    /// chance that a given jumpi is followed by a false branch.
+    pub probability: f64,
+}
+
+impl Default for FalseBranch {
+    fn default() -> Self {
+        FalseBranch { probability: 0.4 }
+    }
+}
+
+impl Pass for FalseBranch {
+    fn name(&self) -> &'static str {
+        "false-branch"
+    }
+
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let mut items = Vec::with_capacity(ir.items.len());
+        let mut applied = 0;
+
+        for item in std::mem::take(&mut ir.items) {
+            let is_jumpi = matches!(
+                item,
+                Item::Op {
+                    opcode: op::JUMPI,
+                    ..
+                }
+            );
+            // retain jumpi opcode
+            items.push(item);
+            if is_jumpi && ctx.rng.gen_bool(self.probability) {
+                // apply false branch obfuscation: jump over a jumpdest -> push1 <random>, pop, stop
+                // decoy that nothing reaches (bosc, section 2.2)
+                let junk = [op::PUSH1, ctx.rng.gen(), op::POP];
+                if symbolic::equivalent(&[], &junk) {
+                    let over = ir.new_label();
+                    items.extend([
+                        Item::PushLabel {
+                            label: over,
+                            addend: 0,
+                            width: 2,
+                        },
+                        Item::op(op::JUMP),
+                        Item::op(op::JUMPDEST),
+                    ]);
+                    items.extend(Item::sequence(&junk));
+                    items.extend([Item::op(op::STOP), Item::Mark(over), Item::op(op::JUMPDEST)]);
+                    applied += 1;
+                }
+            }
+        }
+
+        ir.items = items;
+        ctx.record(self.name(), applied);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
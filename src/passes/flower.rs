@@ -0,0 +1,57 @@
+/// flower instruction pass.
+/// inserts junk into the unreachable region behind stop and return instructions (bosc, section
+/// 2.4). rather than `push, pop` filler, the junk is plausible code generated by `idioms`, which an
+/// analyst has to read to rule out.
+use super::{Ctx, Ir, Pass};
+use crate::evm::op;
+use crate::idioms;
+use crate::reloc::Item;
+use rand::Rng;
+
+/// inserts flower instructions after stop and return.
+pub struct Flower {
+    /// chance that a given stop or return is followed by flower instructions.
+    pub probability: f64,
+}
+
+impl Default for Flower {
+    fn default() -> Self {
+        Flower { probability: 0.3 }
+    }
+}
+
+impl Pass for Flower {
+    fn name(&self) -> &'static str {
+        "flower"
+    }
+
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let mut items = Vec::with_capacity(ir.items.len());
+        let mut applied = 0;
+
+        for item in std::mem::take(&mut ir.items) {
+            let ends_execution = matches!(
+                item,
+                Item::Op {
+                    opcode: op::STOP | op::RETURN,
+                    ..
+                }
+            );
+            // retain stop or return opcode
+            items.push(item);
+            if ends_execution && ctx.rng.gen_bool(self.probability) {
+                // apply flower instruction obfuscation: add an unreachable idiom (bosc, section 2.4)
+                let target = ctx.target();
+                items.extend(idioms::region(ir, &mut *ctx.rng, target));
+                applied += 1;
+            }
+        }
+
+        ir.items = items;
+        ctx.record(self.name(), applied);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
@@ -0,0 +1,129 @@
+/// gas predicate pass.
+/// puts opaque predicates on the gas left in front of basic blocks: `gas, push t, gt, push decoy,
+/// jumpi`, which jumps to a decoy block ending in `invalid` when no more than `t` gas is left. the
+/// gas left depends on the transaction and everything run before, so a static analyzer cannot
+/// resolve the condition and has to keep the decoy as a live branch.
+///
+/// the predicate is always false for an execution that could succeed. a block starting at the
+/// predicate commits to `gas::committed` gas `c` of straight-line code, which it cannot leave
+/// without paying for in full. `gas` charges its own 2 gas before it pushes the gas left `g`, so
+/// `g` is already net of it, and the predicate spends `COST` gas on top after that. if
+/// `g <= t < c + COST`, the block would run out of gas anyway, so the decoy only replaces one
+/// exceptional halt with another. `t` is drawn from `c / 2..=c`, leaving a margin of at least
+/// `COST - 1` gas (see `margin`); realistic stipends (2300 gas for a transfer, far more for any
+/// call) are well above it. chains that account gas differently are left alone.
+use super::{Ctx, Ir, Pass};
+use crate::evm::op;
+use crate::gas;
+use crate::reloc::Item;
+use rand::Rng;
+use tracing::debug;
+
+/// gas the predicate spends after `gas` reads the gas left: `push`, `gt`, `push`, `jumpi`. the 2
+/// gas of `gas` itself are not part of it, as they are charged before the gas left is read.
+pub const COST: u64 = 3 + 3 + 3 + 10;
+
+/// puts gas predicates in front of basic blocks.
+pub struct GasPredicate {
+    /// chance that a given basic block is guarded by a predicate.
+    pub probability: f64,
+}
+
+impl Default for GasPredicate {
+    fn default() -> Self {
+        GasPredicate { probability: 0.2 }
+    }
+}
+
+/// how much gas the threshold `threshold` of a predicate in front of code committing `committed`
+/// gas could grow before the decoy could be taken by an execution that would otherwise succeed, or
+/// `None` if it already could.
+///
+/// # example
+/// ```
+/// use ebo::passes::gas_predicate::{self, COST};
+///
+/// assert_eq!(gas_predicate::margin(100, 100), Some(COST - 1));
+/// assert_eq!(gas_predicate::margin(100 + COST - 1, 100), Some(0));
+/// assert_eq!(gas_predicate::margin(100 + COST, 100), None);
+/// ```
+pub fn margin(threshold: u64, committed: u64) -> Option<u64> {
+    (committed + COST).checked_sub(threshold + 1)
+}
+
+impl Pass for GasPredicate {
+    fn name(&self) -> &'static str {
+        "gas-predicate"
+    }
+
+    /// guards the code after `jumpdest`s whose block commits some gas. the decoys (see
+    /// `passes::decoy`) are placed after the code, so the block itself falls through unchanged.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let target = ctx.target();
+        if !target.reliable(op::GAS) || !target.mainnet_gas() {
+            debug!(
+                "Skipping gas predicates: gas is not accounted as on ethereum on {}",
+                target
+            );
+            ctx.record(self.name(), 0);
+            return;
+        }
+        let original = std::mem::take(&mut ir.items);
+        let mut items = Vec::with_capacity(original.len());
+        let mut decoys = Vec::new();
+        let mut smallest = u64::MAX;
+        let mut applied = 0;
+
+        for (i, item) in original.iter().enumerate() {
+            items.push(item.clone());
+            if !matches!(
+                item,
+                Item::Op {
+                    opcode: op::JUMPDEST,
+                    ..
+                }
+            ) {
+                continue;
+            }
+            let committed = gas::committed(&original[i + 1..]);
+            if committed == 0 || !ctx.rng.gen_bool(self.probability) {
+                continue;
+            }
+            let threshold = ctx.rng.gen_range(committed / 2..=committed);
+            let margin =
+                margin(threshold, committed).expect("thresholds are drawn below the committed gas");
+            smallest = smallest.min(margin);
+            applied += 1;
+
+            let decoy = ir.new_label();
+            items.push(Item::op(op::GAS));
+            let bytes = (threshold + 1).to_be_bytes();
+            let zeros = bytes.iter().take_while(|&&b| b == 0).count().min(7);
+            items.push(Item::push(&bytes[zeros..]));
+            items.extend([
+                Item::op(op::GT),
+                Item::PushLabel {
+                    label: decoy,
+                    addend: 0,
+                    width: 2,
+                },
+                Item::op(op::JUMPI),
+            ]);
+            decoys.extend(super::decoy(decoy, &mut *ctx.rng));
+        }
+
+        items.extend(decoys);
+        ir.items = items;
+        if applied > 0 {
+            debug!(
+                "Guarded {} blocks with gas predicates, the smallest safety margin {} gas",
+                applied, smallest
+            );
+        }
+        ctx.record(self.name(), applied);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
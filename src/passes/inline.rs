@@ -0,0 +1,173 @@
+/// internal function inlining pass.
+/// solidity calls an internal function by pushing the address to return to, then the arguments, and
+/// jumping to the function, which jumps back to the return address once it is done:
+/// `push <ret>, <args>, push <f>, jump, ret: jumpdest`. decompilers recover the call graph from
+/// exactly this pattern. the pass replaces the `push <f>, jump` of randomly chosen call sites with a
+/// copy of the body of `f`, so the call edge and the sharing of the body disappear.
+///
+/// the copy still ends in the function's own `jump` back, which takes the return address the call
+/// site pushed and lands on the `jumpdest` right after the copy, so the result is exact whatever the
+/// body does with the stack. only small bodies made of a single block are inlined: no jumpdest
+/// inside (so nothing jumps into the middle of one), and no way out other than conditional jumps
+/// elsewhere and the jump back. the original function stays in place for the call sites left alone.
+use super::{Ctx, Ir, Pass};
+use crate::evm::{is_terminator, op};
+use crate::reloc::{Item, Label};
+use rand::Rng;
+use std::collections::HashMap;
+
+/// largest body, in items, that is inlined.
+pub const MAX_ITEMS: usize = 32;
+
+/// inlines small internal functions at call sites.
+pub struct Inline {
+    /// chance that a given call site is inlined.
+    pub probability: f64,
+}
+
+impl Default for Inline {
+    fn default() -> Self {
+        Inline { probability: 0.5 }
+    }
+}
+
+/// a call site following the internal-call convention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Call {
+    /// label of the called function.
+    pub function: Label,
+    /// label of the jumpdest the function returns to.
+    pub ret: Label,
+    /// index of the `jump` into the function.
+    pub site: usize,
+}
+
+/// returns true if `item` is the instruction `opcode`.
+fn is(item: Option<&Item>, opcode: u8) -> bool {
+    matches!(item, Some(Item::Op { opcode: o, .. }) if *o == opcode)
+}
+
+/// finds the internal calls of `items`: a `push <f>, jump` directly followed by the jumpdest of a
+/// label pushed earlier in the same block, which is the return address.
+///
+/// # example
+/// ```
+/// use ebo::passes::inline;
+/// use ebo::reloc::Program;
+///
+/// // PUSH2 8, PUSH1 3, PUSH1 10, JUMP, JUMPDEST, STOP, JUMPDEST, SWAP1, JUMP
+/// let code = [0x61, 0x00, 0x08, 0x60, 0x03, 0x60, 0x0A, 0x56, 0x5B, 0x00, 0x5B, 0x90, 0x56];
+/// let calls = inline::calls(&Program::lift(&code).items);
+/// assert_eq!(calls.len(), 1);
+/// ```
+pub fn calls(items: &[Item]) -> Vec<Call> {
+    let mut calls = Vec::new();
+    for site in 1..items.len() {
+        let (
+            Item::PushLabel {
+                label: function,
+                addend: 0,
+                ..
+            },
+            true,
+        ) = (&items[site - 1], is(items.get(site), op::JUMP))
+        else {
+            continue;
+        };
+        let Some(Item::Mark(ret)) = items.get(site + 1) else {
+            continue;
+        };
+        if !is(items.get(site + 2), op::JUMPDEST) {
+            continue;
+        }
+        // the return address is pushed in the block of the call
+        let pushed = items[..site - 1]
+            .iter()
+            .rev()
+            .take_while(|item| match item {
+                Item::Mark(_) | Item::Data(_) | Item::Frozen(_) => false,
+                Item::Op { opcode, .. } => *opcode != op::JUMPI && !is_terminator(*opcode),
+                Item::PushLabel { .. } => true,
+            })
+            .any(|item| matches!(item, Item::PushLabel { label, .. } if label == ret));
+        if pushed {
+            calls.push(Call {
+                function: *function,
+                ret: *ret,
+                site,
+            });
+        }
+    }
+    calls
+}
+
+/// the body of the function marked by `start`, from after its jumpdest up to and including the jump
+/// back, if it is small enough and a single block.
+fn body(items: &[Item], start: usize) -> Option<&[Item]> {
+    if !is(items.get(start + 1), op::JUMPDEST) {
+        return None;
+    }
+    let first = start + 2;
+    for (idx, item) in items.iter().enumerate().skip(first).take(MAX_ITEMS) {
+        match item {
+            Item::Op {
+                opcode: op::JUMP, ..
+            } => {
+                // a jump to a pushed label leaves the function rather than returning
+                let returns = !matches!(items[idx - 1], Item::PushLabel { .. });
+                return returns.then(|| &items[first..=idx]);
+            }
+            Item::Mark(_) | Item::Data(_) | Item::Frozen(_) => return None,
+            item if item.is_terminator() => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+impl Pass for Inline {
+    fn name(&self) -> &'static str {
+        "inline"
+    }
+
+    /// replaces the jump of chosen call sites to a function with a small body by the body.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let original = std::mem::take(&mut ir.items);
+        let marks: HashMap<Label, usize> = original
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, item)| match item {
+                Item::Mark(label) => Some((*label, idx)),
+                _ => None,
+            })
+            .collect();
+
+        let mut items = Vec::with_capacity(original.len());
+        let mut next = 0;
+        let mut inlined = 0;
+        for call in calls(&original) {
+            let Some(body) = marks
+                .get(&call.function)
+                .and_then(|&start| body(&original, start))
+            else {
+                continue;
+            };
+            if !ctx.rng.gen_bool(self.probability) {
+                continue;
+            }
+            // the push of the function and the jump to it make way for the body
+            items.extend_from_slice(&original[next..call.site - 1]);
+            items.extend_from_slice(body);
+            next = call.site + 1;
+            inlined += 1;
+        }
+        items.extend_from_slice(&original[next..]);
+
+        ir.items = items;
+        ctx.record(self.name(), inlined);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
@@ -0,0 +1,329 @@
+/// loop structure pass.
+/// loops are the easiest structure to recognize in bytecode: a block, a conditional exit, and a
+/// jump back, laid out in that order by every compiler. `find` detects them in the lifted program as
+/// jumps back to a label marked earlier, and the pass reshapes each loop it picks:
+///
+/// - the constants the loop counter is stepped by and compared against are computed in the loop
+///   instead of pushed: `push v, add` becomes `push v + r, add, push r, swap1, sub`, and a push
+///   compared with becomes `push v ^ m, push m, xor`. the counter itself keeps its representation,
+///   since changing it would mean rewriting every use, which bytecode does not reveal.
+/// - the loop is rotated: its code is laid out from a block in the middle of the body, entered by a
+///   jump to the header, so the listing no longer starts where the loop does.
+/// - the loop is partially unrolled: a copy of it is placed after the code, and the two jump back to
+///   each other's header, so iterations alternate between them. every copy keeps the exit test, so
+///   this holds for any number of iterations, and each copy gets its own encoded constants, which
+///   later passes obfuscate independently again.
+///
+/// only static jumps are added, so the stack and every exit of the loop stay as they were.
+use super::{Ctx, Ir, Pass};
+use crate::evm::op;
+use crate::reloc::{Item, Label};
+use rand::{Rng, RngCore};
+use std::collections::HashMap;
+
+/// reshapes loops.
+pub struct Loops {
+    /// chance that a given loop is reshaped.
+    pub probability: f64,
+}
+
+impl Default for Loops {
+    fn default() -> Self {
+        Loops { probability: 0.5 }
+    }
+}
+
+/// a loop of a lifted program: the items from the mark of its header up to a jump back to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Loop {
+    /// label of the header, the jumpdest the loop jumps back to.
+    pub header: Label,
+    /// index of the item marking the header.
+    pub start: usize,
+    /// index of the `jump` or `jumpi` back to the header.
+    pub end: usize,
+}
+
+impl Loop {
+    /// returns true if the loop ends in a `jumpi`, so it falls through to its exit.
+    fn conditional(&self, items: &[Item]) -> bool {
+        is(items.get(self.end), op::JUMPI)
+    }
+}
+
+/// returns true if `item` is the instruction `opcode`.
+fn is(item: Option<&Item>, opcode: u8) -> bool {
+    matches!(item, Some(Item::Op { opcode: o, .. }) if *o == opcode)
+}
+
+/// returns true if `item` pushes a label and the item after it jumps there.
+fn feeds_jump(items: &[Item], idx: usize) -> bool {
+    matches!(items[idx], Item::PushLabel { addend: 0, .. })
+        && (is(items.get(idx + 1), op::JUMP) || is(items.get(idx + 1), op::JUMPI))
+}
+
+/// finds the loops of `items`: every static jump back to a jumpdest marked earlier, with only
+/// instructions, label pushes and marks in between. loops are ordered by where they end, so an inner
+/// loop comes before the loops around it.
+///
+/// # example
+/// ```
+/// use ebo::passes::loops;
+/// use ebo::reloc::Program;
+///
+/// // PUSH0, JUMPDEST, PUSH1 1, ADD, PUSH1 1, JUMP
+/// let program = Program::lift(&[0x5F, 0x5B, 0x60, 0x01, 0x01, 0x60, 0x01, 0x56]);
+/// let found = loops::find(&program.items);
+/// assert_eq!(found.len(), 1);
+/// assert_eq!((found[0].start, found[0].end), (1, 6));
+/// ```
+pub fn find(items: &[Item]) -> Vec<Loop> {
+    let mut marks = HashMap::new();
+    for (idx, item) in items.iter().enumerate() {
+        if let Item::Mark(label) = item {
+            marks.entry(*label).or_insert(idx);
+        }
+    }
+    let mut loops = Vec::new();
+    for end in 1..items.len() {
+        if !feeds_jump(items, end - 1) {
+            continue;
+        }
+        let Item::PushLabel { label, .. } = items[end - 1] else {
+            continue;
+        };
+        let Some(&start) = marks.get(&label).filter(|&&start| start + 1 < end) else {
+            continue;
+        };
+        let plain = items[start..end]
+            .iter()
+            .all(|item| !matches!(item, Item::Data(_) | Item::Frozen(_)));
+        if is(items.get(start + 1), op::JUMPDEST) && plain {
+            loops.push(Loop {
+                header: label,
+                start,
+                end,
+            });
+        }
+    }
+    loops
+}
+
+/// encodes the constants that step the loop counter and that it is compared against, in place.
+/// returns the number of constants encoded.
+fn encode(region: &mut Vec<Item>, rng: &mut dyn RngCore) -> usize {
+    let original = std::mem::take(region);
+    let mut encoded = 0;
+    let mut i = 0;
+    while i < original.len() {
+        let item = &original[i];
+        let next = original.get(i + 1);
+        i += 1;
+        let value = match item {
+            Item::Op {
+                opcode, immediate, ..
+            } if (op::PUSH1..op::PUSH1 + 8).contains(opcode) => {
+                immediate.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64)
+            }
+            _ => {
+                region.push(item.clone());
+                continue;
+            }
+        };
+        let compared = [op::LT, op::GT, op::SLT, op::SGT, op::EQ]
+            .iter()
+            .any(|&opcode| is(next, opcode));
+        if is(next, op::ADD) {
+            // push v + r, add, push r, swap1, sub: the step, wrapping back to v
+            let r = rng.gen::<u32>() as u128 + 1;
+            region.push(trimmed(&(value as u128 + r).to_be_bytes()));
+            region.extend([Item::op(op::ADD), trimmed(&r.to_be_bytes())]);
+            region.extend([Item::op(op::SWAP1), Item::op(op::SUB)]);
+            // the add is part of the encoded step
+            i += 1;
+        } else if compared {
+            // push v ^ m, push m, xor: the bound, unmasked
+            let mask = rng.gen::<u64>() | 1;
+            region.push(trimmed(&(value ^ mask).to_be_bytes()));
+            region.extend([trimmed(&mask.to_be_bytes()), Item::op(op::XOR)]);
+        } else {
+            region.push(item.clone());
+            continue;
+        }
+        encoded += 1;
+    }
+    encoded
+}
+
+/// a push of `bytes` without its leading zeros.
+fn trimmed(bytes: &[u8]) -> Item {
+    let zeros = bytes.iter().take_while(|&&b| b == 0).count();
+    Item::push(&bytes[zeros.min(bytes.len() - 1)..])
+}
+
+/// `push <label>, jump`.
+fn jump(label: Label) -> [Item; 2] {
+    [
+        Item::PushLabel {
+            label,
+            addend: 0,
+            width: 2,
+        },
+        Item::op(op::JUMP),
+    ]
+}
+
+impl Pass for Loops {
+    fn name(&self) -> &'static str {
+        "loops"
+    }
+
+    /// reshapes the innermost loops `find` reports that do not overlap each other. every loop that
+    /// is reshaped has its constants encoded and is rotated, unrolled, or both.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let original = std::mem::take(&mut ir.items);
+        let mut chosen: Vec<Loop> = Vec::new();
+        for found in find(&original) {
+            // a second mark on the header would be left in front of the rotation's jump
+            let shared = found.start > 0 && matches!(original[found.start - 1], Item::Mark(_));
+            let overlaps = chosen.last().is_some_and(|last| found.start <= last.end);
+            if !shared && !overlaps && ctx.rng.gen_bool(self.probability) {
+                chosen.push(found);
+            }
+        }
+
+        let mut items = Vec::with_capacity(original.len());
+        let mut copies = Vec::new();
+        let mut next = 0;
+        for found in &chosen {
+            items.extend_from_slice(&original[next..found.start]);
+            next = found.end + 1;
+            let mut region = original[found.start..=found.end].to_vec();
+            let conditional = found.conditional(&original);
+
+            // a loop falling through to its exit needs a jumpdest there once it is moved
+            let (exit, marked) = match original.get(next) {
+                Some(Item::Mark(label)) if is(original.get(next + 1), op::JUMPDEST) => {
+                    (*label, true)
+                }
+                _ => (ir.new_label(), false),
+            };
+
+            // 0 rotates the loop, 1 unrolls it, 2 does both
+            let choice = ctx.rng.gen_range(0..3);
+            if choice != 0 {
+                // the copy and the original jump back to each other's header
+                let mut labels = HashMap::new();
+                for item in &region {
+                    if let Item::Mark(label) = item {
+                        labels.insert(*label, ir.new_label());
+                    }
+                }
+                let latch = region.len() - 2;
+                let mut copy: Vec<Item> = region
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, item)| match item {
+                        Item::Mark(label) => Item::Mark(labels[label]),
+                        // jumps inside the loop stay inside the copy; return addresses do not
+                        Item::PushLabel {
+                            label,
+                            addend,
+                            width,
+                        } if idx != latch && feeds_jump(&region, idx) => Item::PushLabel {
+                            label: *labels.get(label).unwrap_or(label),
+                            addend: *addend,
+                            width: *width,
+                        },
+                        item => item.clone(),
+                    })
+                    .collect();
+                if let Item::PushLabel { label, .. } = &mut region[latch] {
+                    *label = labels[&found.header];
+                }
+                encode(&mut copy, &mut *ctx.rng);
+                copies.extend(copy);
+                if conditional {
+                    copies.extend(jump(exit));
+                }
+            }
+            encode(&mut region, &mut *ctx.rng);
+
+            if choice != 1 {
+                region = rotate(
+                    region,
+                    found.header,
+                    conditional.then_some(exit),
+                    &items,
+                    ir,
+                    ctx,
+                );
+            }
+            items.extend(region);
+            if conditional && !marked {
+                items.extend([Item::Mark(exit), Item::op(op::JUMPDEST)]);
+            }
+        }
+        items.extend_from_slice(&original[next.min(original.len())..]);
+
+        ir.items = items;
+        if !copies.is_empty() {
+            ir.fence();
+            ir.items.extend(copies);
+        }
+        ctx.record(self.name(), chosen.len());
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
+
+/// lays `region`, a loop headed by `header`, out from a block in its middle: `push <header>, jump`
+/// if the code in front (`before`) falls into the loop, the region from the block to the jump back,
+/// `push <exit>, jump` if that jump is conditional, and the region up to the block, followed by a
+/// jump to it. the region is returned unchanged if it has no block to start from.
+fn rotate(
+    region: Vec<Item>,
+    header: Label,
+    exit: Option<Label>,
+    before: &[Item],
+    ir: &mut Ir,
+    ctx: &mut Ctx,
+) -> Vec<Item> {
+    // a block starts at a jumpdest, or after a jumpi; the header and the jump back stay whole
+    let splits: Vec<usize> = (2..region.len() - 1)
+        .filter(|&idx| match &region[idx] {
+            Item::Mark(_) => {
+                is(region.get(idx + 1), op::JUMPDEST) && !matches!(region[idx - 1], Item::Mark(_))
+            }
+            _ => is(region.get(idx - 1), op::JUMPI),
+        })
+        .collect();
+    if splits.is_empty() {
+        return region;
+    }
+    let split = splits[ctx.rng.gen_range(0..splits.len())];
+    let (entry, marked) = match region[split] {
+        Item::Mark(label) => (label, true),
+        _ => (ir.new_label(), false),
+    };
+
+    let mut rotated = Vec::with_capacity(region.len() + 10);
+    let falls_in = before
+        .last()
+        .is_none_or(|item| !item.is_terminator() && !is(Some(item), op::JUMP));
+    if falls_in {
+        rotated.extend(jump(header));
+    }
+    if !marked {
+        rotated.extend([Item::Mark(entry), Item::op(op::JUMPDEST)]);
+    }
+    rotated.extend_from_slice(&region[split..]);
+    if let Some(exit) = exit {
+        rotated.extend(jump(exit));
+    }
+    rotated.extend_from_slice(&region[..split]);
+    rotated.extend(jump(entry));
+    rotated
+}
@@ -0,0 +1,537 @@
+/// obfuscation passes for the ebo obfuscator.
+/// every technique is a `Pass` that rewrites the lifted program in place. a `Pipeline` runs its passes
+/// in order against a shared `Ctx` holding the seeded randomness, so techniques can be added,
+/// reordered, or disabled independently of each other.
+///
+/// passes whose changes stay inside a block (chaotic shuffle, substitution, peephole) work on their
+/// blocks in parallel with rayon through `Ctx::transform_blocks`, each block drawing from a
+/// generator keyed by its content, so the output is the same on any number of threads and the
+/// result of a block can be cached between runs (see `cache`).
+pub mod chaotic_shuffle;
+pub mod constant_pool;
+pub mod decoy_reads;
+pub mod env_predicate;
+pub mod exception_flow;
+pub mod false_branch;
+pub mod flower;
+pub mod gas_predicate;
+pub mod inline;
+pub mod loops;
+pub mod outline;
+pub mod peephole;
+pub mod push0;
+pub mod push_width;
+pub mod return_path;
+pub mod revert_strings;
+pub mod scratch;
+pub mod selector_mask;
+pub mod spaghetti;
+pub mod stack_shuffle;
+pub mod storage_remap;
+pub mod substitution;
+pub mod trampolines;
+
+use crate::cache::{self, BlockCache, Entry};
+use crate::chain::{Chain, Target};
+use crate::dispatcher::Routes;
+use crate::error::EboError;
+use crate::evm::op;
+use crate::evm::{Fork, MAX_CODE_SIZE};
+use crate::gas::Baseline;
+use crate::pcmap::PcMap;
+use crate::reloc::{Canonical, Item, Label, Program};
+use crate::seed::{self, Key};
+use crate::slotmap::SlotMap;
+use crate::validate::{introduced, validate};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore};
+use rayon::prelude::*;
+use std::collections::hash_map::Entry as Slot;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::sync::Arc;
+use tracing::{debug, field, info_span, warn};
+use web_time::Instant;
+
+pub use chaotic_shuffle::ChaoticShuffle;
+pub use constant_pool::ConstantPool;
+pub use decoy_reads::DecoyReads;
+pub use env_predicate::EnvPredicate;
+pub use exception_flow::ExceptionFlow;
+pub use false_branch::FalseBranch;
+pub use flower::Flower;
+pub use gas_predicate::GasPredicate;
+pub use inline::Inline;
+pub use loops::Loops;
+pub use outline::Outline;
+pub use peephole::Peephole;
+pub use push0::Push0Compat;
+pub use push_width::PushWidth;
+pub use return_path::ReturnPath;
+pub use revert_strings::RevertStrings;
+pub use scratch::ScratchLayout;
+pub use selector_mask::SelectorMask;
+pub use spaghetti::Spaghetti;
+pub use stack_shuffle::StackShuffle;
+pub use storage_remap::StorageRemap;
+pub use substitution::Substitution;
+pub use trampolines::Trampolines;
+
+/// passes outside the standard pipeline, which only run when named: their changes are visible to
+/// anything beyond the code itself, like the storage layout other contracts and tools rely on.
+pub const OPT_IN: &[&str] = &["storage-remap"];
+
+/// passes that rewrite the selector dispatcher. with any of them in the pipeline, `Obfuscator` sets
+/// `Ctx::routes`, so that every pass has to keep the dispatcher routing each selector to its function.
+pub const DISPATCHER: &[&str] = &["selector-mask"];
+
+/// the representation passes operate on: bytecode lifted into relocatable items.
+pub type Ir = Program;
+
+/// state shared by all passes of one obfuscation run.
+pub struct Ctx {
+    /// generator every random decision is drawn from, seeded unless set by `Ctx::draw_from`.
+    pub rng: Box<dyn RngCore>,
+    /// a number between 0 and 1 derived from the seed, the starting point of the chaotic map.
+    pub chaotic_seed: f64,
+    /// upper bound for the size of the obfuscated code, respected by passes that control layout.
+    pub max_size: usize,
+    /// largest relative gas overhead (0.15 for 15%) a pass may add to any path, or `None` for no limit.
+    pub max_gas_overhead: Option<f64>,
+    /// fork the output has to be valid for.
+    pub fork: Fork,
+    /// chain the output is deployed to, whose profile narrows the opcodes of `fork`, if any.
+    pub chain: Option<Chain>,
+    /// number of transformations applied by each pass, keyed by pass name.
+    pub stats: BTreeMap<&'static str, usize>,
+    /// storage slots moved by `storage-remap` so far, see `SlotMap`.
+    pub slots: SlotMap,
+    /// the routes through the dispatcher of the input every pass has to keep, if set, see `Routes`.
+    pub routes: Option<Arc<Routes>>,
+    /// the program after every pass of every round, named after the pass, if set to `Some` before the
+    /// run. a skipped pass leaves the program as it was.
+    pub trail: Option<Vec<(&'static str, Ir)>>,
+    /// transformed blocks kept between runs, if any, see `Ctx::transform_blocks`.
+    pub cache: Option<Arc<BlockCache>>,
+    /// key of the generators of `Ctx::transform_blocks`, the first draw of `rng`.
+    stream_key: [u8; 32],
+    /// whether `rng` was given by the caller rather than seeded, in which case reseeding leaves it alone.
+    injected: bool,
+    /// secret the randomness is keyed with, if any.
+    key: Option<Key>,
+}
+
+impl Ctx {
+    /// creates the context for a run with the given seed.
+    /// initializes the random number generator and chaotic seed from the input seed (see the `seed`
+    /// module) to ensure deterministic yet unpredictable obfuscation.
+    ///
+    /// # arguments
+    /// * `seed` - 64-bit unsigned integer seed for deterministic obfuscation.
+    pub fn new(seed: u64) -> Self {
+        let mut rng = seed::rng(seed, None);
+        let stream_key = seed::stream_key(&mut *rng);
+        Ctx {
+            rng,
+            chaotic_seed: seed::chaotic_seed(seed, None),
+            max_size: MAX_CODE_SIZE,
+            max_gas_overhead: None,
+            fork: Fork::default(),
+            chain: None,
+            stats: BTreeMap::new(),
+            slots: SlotMap::default(),
+            routes: None,
+            trail: None,
+            cache: None,
+            stream_key,
+            injected: false,
+            key: None,
+        }
+    }
+
+    /// keys the randomness with `key` and restarts it from `seed`, so the random choices of the
+    /// passes cannot be reconstructed from the seed alone. later reseeds stay keyed.
+    pub fn use_key(&mut self, key: Key, seed: u64) {
+        self.key = Some(key);
+        self.reseed(seed);
+    }
+
+    /// makes the passes draw from `rng` instead of the generator seeded by `Ctx::new`. the seed still
+    /// determines the chaotic seed, but the random choices of the passes are whatever `rng` yields.
+    ///
+    /// # arguments
+    /// * `rng` - the source of randomness, kept across `Ctx::reseed`.
+    pub fn draw_from(&mut self, rng: impl RngCore + 'static) {
+        self.rng = Box::new(rng);
+        self.stream_key = seed::stream_key(&mut *self.rng);
+        self.injected = true;
+    }
+
+    /// restarts the randomness from `seed`, keeping the limits and statistics. used to give every
+    /// round of a multi-round run its own seed. an rng given by `Ctx::draw_from` is not restarted but
+    /// keeps going.
+    pub fn reseed(&mut self, seed: u64) {
+        if !self.injected {
+            self.rng = seed::rng(seed, self.key.as_ref());
+        }
+        self.chaotic_seed = seed::chaotic_seed(seed, self.key.as_ref());
+        self.stream_key = seed::stream_key(&mut *self.rng);
+    }
+
+    /// what the output has to run on: `fork` on `chain`.
+    pub fn target(&self) -> Target {
+        Target::new(self.fork, self.chain)
+    }
+
+    /// a generator of its own for each of `count` blocks a pass transforms independently of each
+    /// other, so it can work on them in parallel and still make the same choices whichever thread
+    /// gets which block. splitting draws from `rng` once, whatever `count` is.
+    pub fn split(&mut self, count: usize) -> Vec<StdRng> {
+        seed::split(&mut *self.rng, count)
+    }
+
+    /// runs `transform` on each of `blocks` in parallel and puts them back together, returning the
+    /// items and the number of transformations applied. every block is transformed in its canonical
+    /// form (see `reloc::Canonical`), drawing from a generator derived from the seed, `pass`, its
+    /// content, and how many copies of it come before it (see `seed::block_rng`), so a block comes
+    /// out the same wherever it is. with a `cache`, blocks transformed before with the same `params`
+    /// and context are taken from it instead.
+    ///
+    /// copies of a block (the same canonical items with the same context, like the revert blocks of
+    /// a large dispatcher) are analyzed once: `analyze` runs on the first copy, and `transform` on
+    /// every copy with that analysis and the copy's own generator. a block `analyze` finds nothing
+    /// to do in is left as it is without drawing from any generator.
+    ///
+    /// # arguments
+    /// * `pass` - name of the pass.
+    /// * `params` - the settings of the pass the transform depends on.
+    /// * `blocks` - the blocks, each with what the pass knows about the code around it.
+    /// * `analyze` - what the pass needs to know about a block, or `None` if it leaves it as it is.
+    /// * `transform` - the pass on one block, which must not create labels.
+    pub fn transform_blocks<C, A, G, F>(
+        &mut self,
+        pass: &'static str,
+        params: &str,
+        blocks: Vec<(Vec<Item>, C)>,
+        analyze: G,
+        transform: F,
+    ) -> (Vec<Item>, usize)
+    where
+        C: Debug + Send,
+        A: Send + Sync,
+        G: Fn(&[Item], C) -> Option<A> + Sync,
+        F: Fn(Vec<Item>, &A, &mut StdRng) -> (Vec<Item>, usize) + Sync,
+    {
+        let (base, cache) = (self.stream_key, self.cache.clone());
+        let mut seen: HashMap<[u8; 32], usize> = HashMap::new();
+        let mut groups: HashMap<([u8; 32], String), usize> = HashMap::new();
+        // the job of the first copy of every distinct block, with its context
+        let mut unique: Vec<(usize, C)> = Vec::new();
+        let mut jobs = Vec::with_capacity(blocks.len());
+        for (block, context) in blocks {
+            let canonical = Canonical::new(&block);
+            let content = cache::digest(&canonical.items);
+            let described = format!("{:?}", context);
+            let occurrence = seen.entry(content).or_default();
+            let key = cache
+                .as_ref()
+                .map(|_| cache::key(&base, pass, &content, *occurrence, params, &described));
+            let rng = seed::block_rng(&base, pass, &content, *occurrence);
+            *occurrence += 1;
+            let group = match groups.entry((content, described)) {
+                Slot::Occupied(group) => *group.get(),
+                Slot::Vacant(group) => {
+                    unique.push((jobs.len(), context));
+                    *group.insert(unique.len() - 1)
+                }
+            };
+            jobs.push((canonical, group, rng, key));
+        }
+        debug!("{}: {} blocks, {} distinct", pass, jobs.len(), unique.len());
+        let analyses: Vec<Option<A>> = unique
+            .into_par_iter()
+            .map(|(job, context)| analyze(&jobs[job].0.items, context))
+            .collect();
+        let (blocks, applied): (Vec<Vec<Item>>, Vec<usize>) = jobs
+            .into_par_iter()
+            .map(|(mut canonical, group, mut rng, key)| {
+                let items = std::mem::take(&mut canonical.items);
+                let Some(analysis) = &analyses[group] else {
+                    return (canonical.restore(items), 0);
+                };
+                let cached = cache.as_ref().zip(key.as_ref());
+                if let Some(entry) = cached.and_then(|(cache, key)| cache.get(key)) {
+                    return (canonical.restore(entry.items), entry.applied);
+                }
+                let (items, applied) = transform(items, analysis, &mut rng);
+                if let Some((cache, key)) = cached {
+                    let entry = Entry {
+                        items: items.clone(),
+                        applied,
+                    };
+                    cache.insert(key.clone(), entry);
+                }
+                (canonical.restore(items), applied)
+            })
+            .unzip();
+        (blocks.into_iter().flatten().collect(), applied.iter().sum())
+    }
+
+    /// adds `count` applied transformations to the statistics of `pass`.
+    pub fn record(&mut self, pass: &'static str, count: usize) {
+        *self.stats.entry(pass).or_default() += count;
+    }
+}
+
+/// a single obfuscation technique.
+pub trait Pass {
+    /// short, stable name used to enable, disable, and report the pass.
+    fn name(&self) -> &'static str;
+
+    /// rewrites `ir` in place, drawing all randomness from `ctx`.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx);
+
+    /// makes the pass less aggressive before it is retried because its result exceeded a budget.
+    /// returns false if the pass cannot be scaled back any further, which is the default.
+    fn scale_back(&mut self) -> bool {
+        false
+    }
+}
+
+/// number of basic blocks of `ir`: the code in front of the first label, and the code after every
+/// label and every instruction ending a block.
+fn blocks(ir: &Ir) -> usize {
+    1 + ir
+        .items
+        .iter()
+        .filter(|item| match item {
+            Item::Mark(_) => true,
+            Item::Op { opcode, .. } => {
+                matches!(*opcode, op::JUMP | op::JUMPI) || item.is_terminator()
+            }
+            _ => false,
+        })
+        .count()
+}
+
+/// splits a lifted item stream into blocks, ending a block after every item `ends` holds for.
+pub fn split_after(items: Vec<Item>, ends: impl Fn(&Item) -> bool) -> Vec<Vec<Item>> {
+    let mut blocks = Vec::new();
+    let mut current = Vec::new();
+    for item in items {
+        let ended = ends(&item);
+        current.push(item);
+        if ended {
+            blocks.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        blocks.push(current);
+    }
+    blocks
+}
+
+/// halves an insertion probability for `Pass::scale_back`, until it is too small to matter.
+pub fn halve(probability: &mut f64) -> bool {
+    if *probability < 0.01 {
+        return false;
+    }
+    *probability /= 2.0;
+    true
+}
+
+/// the block an opaque predicate jumps to when it never holds, marked with `label`:
+/// `jumpdest, push4 <value>, push1 <slot>, sstore, invalid`. it looks like it does something, but
+/// never returns, and `invalid` undoes the store.
+pub fn decoy(label: Label, rng: &mut dyn RngCore) -> Vec<Item> {
+    vec![
+        Item::Mark(label),
+        Item::op(op::JUMPDEST),
+        Item::push(&rng.gen::<u32>().to_be_bytes()),
+        Item::push(&[rng.gen()]),
+        Item::op(op::SSTORE),
+        Item::op(op::INVALID),
+    ]
+}
+
+/// an ordered list of passes.
+pub struct Pipeline {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl Pipeline {
+    /// creates an empty pipeline.
+    pub fn new() -> Self {
+        Pipeline { passes: Vec::new() }
+    }
+
+    /// appends `pass` to the end of the pipeline.
+    pub fn with(mut self, pass: impl Pass + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// removes every pass called `name`.
+    pub fn without(mut self, name: &str) -> Self {
+        self.passes.retain(|pass| pass.name() != name);
+        self
+    }
+
+    /// names of the passes in execution order.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    /// runs every pass in order.
+    ///
+    /// each pass runs on a copy of the program first. if the copy grows beyond `ctx.max_size`, or,
+    /// with a gas budget in `ctx`, costs more than the budget allows on some path of the input, the
+    /// pass is scaled back and retried, or skipped once it cannot be scaled back any further. a pass
+    /// that does not grow the program is always accepted by the size guard, so input that is already
+    /// too large can still be obfuscated by passes that keep its size.
+    ///
+    /// a copy that fails validation for `ctx.target()` in ways the program did not before (see
+    /// `validate::validate`) is treated the same way, and so is one whose dispatcher no longer
+    /// routes every selector to its function, with `ctx.routes` set. these guards only check
+    /// structure, size, gas and dispatcher routes, not that the code still behaves the same; that
+    /// is left to the passes themselves and to `equivalence::compare`.
+    ///
+    /// # returns
+    /// an error if the program cannot be assembled to begin with.
+    pub fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) -> Result<(), EboError> {
+        let budget = ctx.max_gas_overhead.map(|limit| {
+            let baseline = Baseline::new(ir);
+            debug!("Measuring gas overhead on {} paths", baseline.paths());
+            (baseline, limit)
+        });
+        let assembled = ir.assemble()?;
+        let mut size = assembled.len();
+        let mut issues = validate(&assembled, ctx.target());
+        if size > ctx.max_size {
+            warn!(
+                "Input is {} bytes, already over the {} byte size limit",
+                size, ctx.max_size
+            );
+        }
+
+        for pass in self.passes.iter_mut() {
+            let span = info_span!(
+                "pass",
+                name = pass.name(),
+                blocks = blocks(ir),
+                applied = field::Empty,
+                elapsed = field::Empty
+            );
+            let _entered = span.enter();
+            let started = Instant::now();
+            let before = ctx.stats.get(pass.name()).copied().unwrap_or(0);
+            debug!("Running pass: {}", pass.name());
+            loop {
+                let mut candidate = ir.clone();
+                let stats = ctx.stats.clone();
+                let slots = ctx.slots.clone();
+                pass.run(&mut candidate, ctx);
+
+                let assembled = match candidate.assemble() {
+                    Ok(assembled) => assembled,
+                    Err(err) => {
+                        // a pass that leaves a dangling label is skipped like one breaking the code
+                        ctx.stats = stats;
+                        ctx.slots = slots;
+                        warn!("Skipping pass {}: {}", pass.name(), err);
+                        break;
+                    }
+                };
+                let grown = assembled.len();
+                let found = validate(&assembled, ctx.target());
+                let broken = introduced(&issues, &found);
+                let problem = if let Some(issue) = broken.first() {
+                    Some(format!("invalid bytecode at {}", issue))
+                } else if grown > ctx.max_size && grown > size {
+                    Some(format!(
+                        "{} bytes exceeds the {} byte size limit",
+                        grown, ctx.max_size
+                    ))
+                } else if let Some(problem) = budget.as_ref().and_then(|(baseline, limit)| {
+                    let overhead = baseline.overhead(&candidate);
+                    (overhead > *limit).then(|| {
+                        format!("{:.1}% gas overhead exceeds the budget", overhead * 100.0)
+                    })
+                }) {
+                    Some(problem)
+                } else {
+                    ctx.routes
+                        .as_ref()
+                        .and_then(|routes| misrouted(routes, &candidate, &assembled))
+                };
+                let Some(problem) = problem else {
+                    *ir = candidate;
+                    size = grown;
+                    issues = found;
+                    break;
+                };
+
+                ctx.stats = stats;
+                ctx.slots = slots;
+                if !pass.scale_back() {
+                    warn!("Skipping pass {}: {}", pass.name(), problem);
+                    break;
+                }
+                debug!("Scaling back pass {}: {}", pass.name(), problem);
+            }
+
+            // a pass that was skipped applied nothing
+            let applied = ctx.stats.get(pass.name()).copied().unwrap_or(0) - before;
+            span.record("applied", applied);
+            span.record("elapsed", field::debug(started.elapsed()));
+            debug!("Finished pass: {}", pass.name());
+            if let Some(trail) = ctx.trail.as_mut() {
+                trail.push((pass.name(), ir.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the first way `assembled`, the code of `program`, no longer takes the `routes` through the
+/// dispatcher, or `None` if it still does.
+fn misrouted(routes: &Routes, program: &Ir, assembled: &[u8]) -> Option<String> {
+    let map = PcMap::new(program).ok()?;
+    match routes.check(assembled, &map) {
+        Ok(found) => found.first().map(|inconsistency| inconsistency.to_string()),
+        Err(err) => Some(err.to_string()),
+    }
+}
+
+impl Default for Pipeline {
+    /// the standard pipeline. push0 compatibility runs first, so later passes only emit what the
+    /// target fork runs, and decoy reads and selector masking next, while the dispatcher still has
+    /// the shape they recognize. outlining runs after inlining and before loop reshaping, so the
+    /// repetition it hides is the input's own, push widening runs after constant pooling, and
+    /// spaghettification runs last, once every other pass has placed its code.
+    fn default() -> Self {
+        Pipeline::new()
+            .with(Push0Compat)
+            .with(DecoyReads::default())
+            .with(SelectorMask)
+            .with(RevertStrings::default())
+            .with(ScratchLayout::default())
+            .with(Inline::default())
+            .with(Outline)
+            .with(Loops::default())
+            .with(ChaoticShuffle::default())
+            .with(Substitution::default())
+            .with(StackShuffle::default())
+            .with(FalseBranch::default())
+            .with(GasPredicate::default())
+            .with(EnvPredicate::default())
+            .with(ExceptionFlow::default())
+            .with(ReturnPath::default())
+            .with(Flower::default())
+            .with(Peephole::default())
+            .with(Trampolines::default())
+            .with(ConstantPool)
+            .with(PushWidth::default())
+            .with(Spaghetti::default())
+    }
+}
@@ -0,0 +1,214 @@
+/// function outlining for the ebo obfuscator.
+/// finds straight-line instruction sequences that occur several times and moves a single copy into a
+/// shared internal subroutine. every occurrence becomes a call that pushes a return address and jumps
+/// to the subroutine, which jumps back when done. this hides repeated structure behind extra control
+/// flow and, because only profitable sequences are outlined, shrinks the code to offset the growth of
+/// the other techniques.
+use super::{Ctx, Ir, Pass};
+use crate::evm::{is_terminator, op, stack_effect, stack_io};
+use crate::reloc::{Item, Program};
+use std::collections::BTreeMap;
+use tracing::debug;
+
+/// shortest sequence, in bytes, worth outlining.
+const MIN_BYTES: usize = 10;
+/// longest sequence, in instructions, considered for outlining.
+const MAX_INSTRUCTIONS: usize = 24;
+/// bytes added at each call site: push2 ret, push2 sub, jump, jumpdest.
+const CALL_SITE_BYTES: usize = 8;
+
+/// outlines repeated sequences into shared subroutines.
+pub struct Outline;
+
+impl Pass for Outline {
+    fn name(&self) -> &'static str {
+        "outline"
+    }
+
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let subroutines = outline(ir);
+        ctx.record(self.name(), subroutines);
+    }
+}
+
+/// a sequence selected for outlining.
+struct Candidate {
+    /// raw bytes of the sequence.
+    code: Vec<u8>,
+    /// number of instructions in the sequence.
+    len: usize,
+    /// item indices where the sequence starts.
+    starts: Vec<usize>,
+    /// pre-existing stack items read by the sequence.
+    depth: usize,
+    /// stack items the sequence leaves in their place.
+    produced: usize,
+}
+
+/// returns true if the instruction can be moved into a subroutine without changing its meaning.
+/// control flow must stay in place, and `pc` would observe the new location.
+fn outlinable(item: &Item) -> bool {
+    match item {
+        Item::Op { opcode, .. } => {
+            stack_io(*opcode).is_some()
+                && !is_terminator(*opcode)
+                && !matches!(*opcode, op::JUMPI | op::JUMPDEST | 0x58)
+        }
+        _ => false,
+    }
+}
+
+/// raw bytes of an outlinable item.
+fn item_bytes(item: &Item) -> Vec<u8> {
+    match item {
+        Item::Op {
+            opcode, immediate, ..
+        } => {
+            let mut bytes = vec![*opcode];
+            bytes.extend_from_slice(immediate);
+            bytes
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// swaps that move the return address on top of the stack below the `depth` items the subroutine
+/// body reads: swap`depth`, swap`depth - 1`, ..., swap1.
+pub fn rotate_down(depth: usize) -> Vec<u8> {
+    (1..=depth).rev().map(|n| 0x8F + n as u8).collect()
+}
+
+/// swaps that bring the return address from below the `produced` result items back to the top of
+/// the stack: swap1, swap2, ..., swap`produced`.
+pub fn rotate_up(produced: usize) -> Vec<u8> {
+    (1..=produced).map(|n| 0x8F + n as u8).collect()
+}
+
+/// outlines repeated sequences of `program` into shared subroutines appended after the code.
+///
+/// sequences are picked greedily, longest first, and only when outlining them saves bytes once the
+/// call sites and the subroutine itself are paid for. a sequence may read and produce at most 16
+/// stack items, the reach of the swaps that move the return address around it.
+///
+/// # arguments
+/// * `program` - lifted program to rewrite in place.
+///
+/// # returns
+/// number of subroutines created.
+pub fn outline(program: &mut Program) -> usize {
+    let items = &program.items;
+    let mut claimed = vec![false; items.len()];
+    let mut candidates: Vec<Candidate> = Vec::new();
+
+    for len in (2..=MAX_INSTRUCTIONS).rev() {
+        // BTreeMap keeps the selection independent of hash ordering, so output stays deterministic
+        let mut occurrences: BTreeMap<Vec<u8>, Vec<usize>> = BTreeMap::new();
+        for start in 0..items.len().saturating_sub(len - 1) {
+            let window = &items[start..start + len];
+            if claimed[start..start + len].iter().any(|&c| c) || !window.iter().all(outlinable) {
+                continue;
+            }
+            let code: Vec<u8> = window.iter().flat_map(item_bytes).collect();
+            if code.len() >= MIN_BYTES {
+                occurrences.entry(code).or_default().push(start);
+            }
+        }
+
+        for (code, starts) in occurrences {
+            let Some((depth, produced)) = stack_effect(&code) else {
+                continue;
+            };
+            if depth > 16 || produced > 16 {
+                continue;
+            }
+            let mut chosen = Vec::new();
+            for start in starts {
+                let overlaps = chosen.last().is_some_and(|&prev| start < prev + len)
+                    || claimed[start..start + len].iter().any(|&c| c);
+                if !overlaps {
+                    chosen.push(start);
+                }
+            }
+            let subroutine_bytes = 2 + depth + produced + code.len();
+            let saved = chosen.len() * code.len();
+            let spent = chosen.len() * CALL_SITE_BYTES + subroutine_bytes;
+            if chosen.len() < 2 || saved <= spent {
+                continue;
+            }
+            for &start in &chosen {
+                claimed[start..start + len]
+                    .iter_mut()
+                    .for_each(|c| *c = true);
+            }
+            candidates.push(Candidate {
+                code,
+                len,
+                starts: chosen,
+                depth,
+                produced,
+            });
+        }
+    }
+
+    if candidates.is_empty() {
+        return 0;
+    }
+
+    let mut calls: BTreeMap<usize, usize> = BTreeMap::new(); // start index -> candidate
+    for (idx, candidate) in candidates.iter().enumerate() {
+        for &start in &candidate.starts {
+            calls.insert(start, idx);
+        }
+    }
+    let subroutines: Vec<_> = candidates.iter().map(|_| program.new_label()).collect();
+
+    let original = std::mem::take(&mut program.items);
+    let mut items = Vec::with_capacity(original.len());
+    let mut idx = 0;
+    while idx < original.len() {
+        let Some(&candidate) = calls.get(&idx) else {
+            items.push(original[idx].clone());
+            idx += 1;
+            continue;
+        };
+        let ret = program.new_label();
+        items.extend([
+            Item::PushLabel {
+                label: ret,
+                addend: 0,
+                width: 2,
+            },
+            Item::PushLabel {
+                label: subroutines[candidate],
+                addend: 0,
+                width: 2,
+            },
+            Item::op(op::JUMP),
+            Item::Mark(ret),
+            Item::op(op::JUMPDEST),
+        ]);
+        idx += candidates[candidate].len;
+    }
+    program.items = items;
+
+    program.fence();
+    for (candidate, label) in candidates.iter().zip(subroutines) {
+        program.items.push(Item::Mark(label));
+        program.items.push(Item::op(op::JUMPDEST));
+        program
+            .items
+            .extend(rotate_down(candidate.depth).into_iter().map(Item::op));
+        program.items.extend(Item::sequence(&candidate.code));
+        program
+            .items
+            .extend(rotate_up(candidate.produced).into_iter().map(Item::op));
+        program.items.push(Item::op(op::JUMP));
+        debug!(
+            "Outlined {} bytes repeated {} times",
+            candidate.code.len(),
+            candidate.starts.len()
+        );
+    }
+
+    candidates.len()
+}
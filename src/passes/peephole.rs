@@ -0,0 +1,297 @@
+/// peephole substitution engine for the ebo obfuscator.
+/// sprinkles short identity sequences (instruction pairs that leave the stack exactly as they found it)
+/// into reachable code, so the same source compiles to visibly different instruction streams without
+/// changing behavior (eveilm, page 59). every identity declares its stack effect, and only identities
+/// whose declaration and behavior are confirmed by `verify` are ever inserted.
+use super::{split_after, Ctx, Ir, Pass};
+use crate::chain::Target;
+use crate::evm::{disassemble, is_terminator, op, stack_effect, stack_io};
+use crate::reloc::{Item, Program};
+use crate::seed;
+use crate::symbolic;
+use rand::Rng;
+use rayon::prelude::*;
+use tracing::{debug, warn};
+
+/// inserts verified identities into reachable code.
+pub struct Peephole {
+    /// chance of inserting an identity at each eligible point.
+    pub probability: f64,
+}
+
+impl Default for Peephole {
+    fn default() -> Self {
+        Peephole { probability: 0.15 }
+    }
+}
+
+impl Pass for Peephole {
+    fn name(&self) -> &'static str {
+        "peephole"
+    }
+
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let (probability, target) = (self.probability, ctx.target());
+        let approved = approved(target);
+        let params = format!("{} {:?}", probability, target);
+        let (items, applied) = ctx.transform_blocks(
+            self.name(),
+            &params,
+            blocks(ir),
+            |block, after_mark| sites(block, after_mark, &approved),
+            |block, sites, rng| insert(block, sites, rng, probability),
+        );
+        ir.items = items;
+
+        debug!("Inserted {} peephole identities", applied);
+        ctx.record(self.name(), applied);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
+
+/// where an identity may be inserted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Site {
+    /// any reachable point where at least `stack_in` items are known to be on the stack.
+    Anywhere,
+    /// directly before the push of a static jumpi target, where the top of the stack is the jump
+    /// condition. only the zero/non-zero property of that value has to be preserved there.
+    JumpCondition,
+}
+
+/// a short instruction sequence that preserves the stack shape of the code it is inserted into.
+#[derive(Debug)]
+pub struct Identity {
+    /// short human-readable name used in logs.
+    pub name: &'static str,
+    /// raw bytes of the sequence.
+    pub code: &'static [u8],
+    /// number of pre-existing stack items the sequence reads.
+    pub stack_in: usize,
+    /// number of stack items the sequence leaves in their place.
+    pub stack_out: usize,
+    /// positions at which the identity is valid.
+    pub site: Site,
+}
+
+/// the identity library.
+pub const IDENTITIES: &[Identity] = &[
+    Identity {
+        name: "iszero iszero",
+        code: &[0x15, 0x15],
+        stack_in: 1,
+        stack_out: 1,
+        site: Site::JumpCondition,
+    },
+    Identity {
+        name: "dup1 pop",
+        code: &[0x80, op::POP],
+        stack_in: 1,
+        stack_out: 1,
+        site: Site::Anywhere,
+    },
+    Identity {
+        name: "swap1 swap1",
+        code: &[0x90, 0x90],
+        stack_in: 2,
+        stack_out: 2,
+        site: Site::Anywhere,
+    },
+    Identity {
+        name: "not not",
+        code: &[0x19, 0x19],
+        stack_in: 1,
+        stack_out: 1,
+        site: Site::Anywhere,
+    },
+];
+
+impl Identity {
+    /// checks that the identity is safe to insert: it must be straight-line code (no jumps, jumpdests,
+    /// or terminators), its computed stack effect must match the declared one, it must leave the
+    /// stack with as many items as it found, and the symbolic evaluator must prove that it leaves
+    /// them unchanged. at a jump condition only the zero/non-zero property has to hold, so there the
+    /// identity followed by `iszero` must compute the same as `iszero` alone.
+    pub fn verify(&self) -> bool {
+        let straight_line = disassemble(self.code).iter().all(|ins| {
+            !is_terminator(ins.opcode) && !matches!(ins.opcode, op::JUMPI | op::JUMPDEST)
+        });
+        let preserves = match self.site {
+            Site::Anywhere => symbolic::equivalent(&[], self.code),
+            Site::JumpCondition => {
+                symbolic::equivalent(&[op::ISZERO], &[self.code, &[op::ISZERO]].concat())
+            }
+        };
+        straight_line
+            && stack_effect(self.code) == Some((self.stack_in, self.stack_out))
+            && self.stack_in == self.stack_out
+            && preserves
+    }
+}
+
+/// inserts verified identities at random eligible points of `program`.
+///
+/// stack depth is tracked as a conservative lower bound from the start of each block (every jumpdest
+/// resets it to zero), so an identity is only placed where the items it reads are provably present.
+///
+/// # arguments
+/// * `program` - lifted program to rewrite in place.
+/// * `rng` - random source deciding where identities go and which one is used.
+/// * `probability` - chance of inserting an identity at each eligible point.
+/// * `target` - target fork, or fork on a chain; identities using opcodes it does not run are left
+///   out.
+///
+/// # returns
+/// number of identities inserted.
+pub fn sprinkle(
+    program: &mut Program,
+    rng: &mut impl Rng,
+    probability: f64,
+    target: impl Into<Target>,
+) -> usize {
+    let approved = approved(target.into());
+    let blocks = blocks(program);
+    let rngs = seed::split(rng, blocks.len());
+    let (blocks, applied): (Vec<Vec<Item>>, Vec<usize>) = blocks
+        .into_par_iter()
+        .zip(rngs)
+        .map(
+            |((block, after_mark), mut rng)| match sites(&block, after_mark, &approved) {
+                Some(sites) => insert(block, &sites, &mut rng, probability),
+                None => (block, 0),
+            },
+        )
+        .unzip();
+    let applied = applied.iter().sum();
+    program.items = blocks.into_iter().flatten().collect();
+
+    debug!("Inserted {} peephole identities", applied);
+    applied
+}
+
+/// the identities of the library that `target` runs and `Identity::verify` confirms.
+fn approved(target: Target) -> Vec<&'static Identity> {
+    IDENTITIES
+        .iter()
+        .filter(|identity| target.admits(identity.code))
+        .filter(|identity| {
+            let ok = identity.verify();
+            if !ok {
+                warn!(
+                    "Rejected identity '{}': it does not preserve the stack",
+                    identity.name
+                );
+            }
+            ok
+        })
+        .collect()
+}
+
+/// takes the items of `program` apart into the blocks identities are inserted into independently
+/// of each other, each with whether it starts right after a mark. the stack depth and liveness
+/// start over after every mark and frozen region, so the blocks end there.
+fn blocks(program: &mut Program) -> Vec<(Vec<Item>, bool)> {
+    let blocks = split_after(std::mem::take(&mut program.items), |item| {
+        matches!(item, Item::Mark(_) | Item::Frozen(_))
+    });
+    let after_mark: Vec<bool> = std::iter::once(false)
+        .chain(
+            blocks
+                .iter()
+                .map(|block| matches!(block.last(), Some(Item::Mark(_)))),
+        )
+        .take(blocks.len())
+        .collect();
+    blocks.into_iter().zip(after_mark).collect()
+}
+
+/// the identities that may be inserted in front of each item of `block`, which starts with an
+/// empty stack in live code, right after a mark if `after_mark` is set. `None` if there is no
+/// point an identity may be inserted at.
+fn sites(
+    block: &[Item],
+    after_mark: bool,
+    approved: &[&'static Identity],
+) -> Option<Vec<Vec<&'static Identity>>> {
+    let mut sites = Vec::with_capacity(block.len());
+    let mut depth = 0;
+    let mut live = true;
+
+    for (idx, item) in block.iter().enumerate() {
+        let feeds_jumpi = matches!(item, Item::PushLabel { .. })
+            && matches!(
+                block.get(idx + 1),
+                Some(Item::Op {
+                    opcode: op::JUMPI,
+                    ..
+                })
+            );
+        // never split a jumpdest from the label that marks it
+        let after_mark = match idx.checked_sub(1) {
+            Some(last) => matches!(block[last], Item::Mark(_)),
+            None => after_mark,
+        };
+
+        sites.push(match live && !after_mark {
+            true => approved
+                .iter()
+                .copied()
+                .filter(|identity| match identity.site {
+                    Site::Anywhere => depth >= identity.stack_in,
+                    Site::JumpCondition => feeds_jumpi,
+                })
+                .collect(),
+            false => Vec::new(),
+        });
+
+        match item {
+            Item::Op { opcode, .. } => {
+                depth = match stack_io(*opcode) {
+                    Some((popped, pushed)) => depth.max(popped) - popped + pushed,
+                    None => 0,
+                };
+                if is_terminator(*opcode) {
+                    live = false;
+                }
+            }
+            Item::PushLabel { .. } => depth += 1,
+            Item::Mark(_) => {
+                depth = 0;
+                live = true;
+            }
+            Item::Data(_) => depth = 0,
+            Item::Frozen(_) => {
+                depth = 0;
+                live = true;
+            }
+        }
+    }
+    sites
+        .iter()
+        .any(|candidates| !candidates.is_empty())
+        .then_some(sites)
+}
+
+/// inserts identities into `block` at the `sites` found for it, returning the block and the number
+/// of identities inserted.
+fn insert(
+    block: Vec<Item>,
+    sites: &[Vec<&'static Identity>],
+    rng: &mut impl Rng,
+    probability: f64,
+) -> (Vec<Item>, usize) {
+    let mut items = Vec::with_capacity(block.len());
+    let mut applied = 0;
+    for (item, candidates) in block.into_iter().zip(sites) {
+        if !candidates.is_empty() && rng.gen_bool(probability) {
+            let identity = candidates[rng.gen_range(0..candidates.len())];
+            items.extend(Item::sequence(identity.code));
+            applied += 1;
+        }
+        items.push(item);
+    }
+    (items, applied)
+}
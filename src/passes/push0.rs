@@ -0,0 +1,66 @@
+/// push0 compatibility pass.
+/// shanghai added `push0`, which pushes zero in one byte and two gas where `push1 0x00` takes two
+/// bytes and three gas. code compiled for shanghai or later uses it freely, but several chains still
+/// lag behind and reject it, so code targeting an older fork has every `push0` rewritten to
+/// `push1 0x00`. passes that synthesize code use `fit` to push zero the way the target fork allows.
+use super::{Ctx, Ir, Pass};
+use crate::chain::Target;
+use crate::evm::{disassemble, op};
+use crate::reloc::Item;
+
+/// rewrites the zero pushes of `code` for `target`, a fork or a fork on a chain: `push1 0x00`
+/// becomes `push0` where the target has it, and `push0` becomes `push1 0x00` where it does not.
+/// every other instruction is kept.
+///
+/// # example
+/// ```
+/// use ebo::{passes::push0, Fork};
+///
+/// let code = [0x60, 0x00, 0x5F, 0x01]; // PUSH1 0x00, PUSH0, ADD
+/// assert_eq!(push0::fit(&code, Fork::Shanghai), vec![0x5F, 0x5F, 0x01]);
+/// assert_eq!(push0::fit(&code, Fork::London), vec![0x60, 0x00, 0x60, 0x00, 0x01]);
+/// ```
+pub fn fit(code: &[u8], target: impl Into<Target>) -> Vec<u8> {
+    let push0 = target.into().supports(op::PUSH0);
+    let mut fitted = Vec::with_capacity(code.len());
+    for ins in disassemble(code) {
+        match (ins.opcode, ins.immediate.as_slice()) {
+            (op::PUSH1, [0]) if push0 => fitted.push(op::PUSH0),
+            (op::PUSH0, _) if !push0 => fitted.extend([op::PUSH1, 0]),
+            (opcode, immediate) => {
+                fitted.push(opcode);
+                fitted.extend_from_slice(immediate);
+            }
+        }
+    }
+    fitted
+}
+
+/// rewrites `push0` to `push1 0x00` when the target fork predates shanghai or the target chain
+/// lacks it. frozen regions are left alone, since they have to come out as they went in.
+pub struct Push0Compat;
+
+impl Pass for Push0Compat {
+    fn name(&self) -> &'static str {
+        "push0-compat"
+    }
+
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let mut rewritten = 0;
+        if !ctx.target().supports(op::PUSH0) {
+            for item in &mut ir.items {
+                if let Item::Op {
+                    opcode, immediate, ..
+                } = item
+                {
+                    if *opcode == op::PUSH0 {
+                        *opcode = op::PUSH1;
+                        *immediate = vec![0];
+                        rewritten += 1;
+                    }
+                }
+            }
+        }
+        ctx.record(self.name(), rewritten);
+    }
+}
@@ -0,0 +1,76 @@
+/// push width randomization pass.
+/// re-encodes pushes with wider immediates padded with leading zeros (`push1 0x05` becomes
+/// `push3 0x000005`), which push the same value for the same gas. every build lays out its
+/// instruction boundaries and byte patterns differently, so signatures matching the usual encoding of
+/// a constant stop matching. label pushes are widened through their declared width, which
+/// relocation only ever grows. a widened push counts as replaced in the pc map, like the pushes of
+/// jump targets the assembler rewrites.
+///
+/// the pass runs after constant pooling, so a constant widened to 32 bytes stays in the instruction
+/// stream instead of being moved into the pool. `push0` is left alone: `push1 0x00` costs one gas
+/// more.
+use super::{Ctx, Ir, Pass};
+use crate::evm::op;
+use crate::reloc::Item;
+use rand::Rng;
+
+/// most bytes a push is widened by.
+const MAX_PADDING: usize = 3;
+
+/// widens push immediates at random.
+pub struct PushWidth {
+    /// chance that a given push is widened.
+    pub probability: f64,
+}
+
+impl Default for PushWidth {
+    fn default() -> Self {
+        PushWidth { probability: 0.25 }
+    }
+}
+
+impl Pass for PushWidth {
+    fn name(&self) -> &'static str {
+        "push-width"
+    }
+
+    /// pads the immediate of every push chosen with 1 to `MAX_PADDING` zero bytes, up to 32.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let mut widened = 0;
+        for item in &mut ir.items {
+            let width = match item {
+                Item::Op {
+                    opcode: op::PUSH1..=op::PUSH32,
+                    immediate,
+                    ..
+                } => immediate.len(),
+                Item::PushLabel { width, .. } => *width,
+                _ => continue,
+            };
+            if width >= 32 || !ctx.rng.gen_bool(self.probability) {
+                continue;
+            }
+            let wider = (width + ctx.rng.gen_range(1..=MAX_PADDING)).min(32);
+            match item {
+                Item::Op {
+                    opcode,
+                    immediate,
+                    origin,
+                } => {
+                    immediate.splice(0..0, std::iter::repeat_n(0, wider - width));
+                    *opcode = op::PUSH1 + (wider - 1) as u8;
+                    // another encoding, so no longer a copy of the input instruction
+                    *origin = None;
+                }
+                Item::PushLabel { width, .. } => *width = wider,
+                _ => unreachable!("only pushes are widened"),
+            }
+            widened += 1;
+        }
+        ctx.record(self.name(), widened);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
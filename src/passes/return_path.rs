@@ -0,0 +1,129 @@
+/// return path indirection pass.
+/// routes `return` and `revert` through shared epilogue blocks, so the exits of the functions no
+/// longer end in the `return`/`revert` a decompiler recognizes them by. each exit becomes a jump to
+/// an epilogue of its kind whose target is computed (`push k, push <epilogue> + k, sub, jump`) rather
+/// than pushed in front of the jump, so the epilogues have no static edge into them. on targets
+/// running `mcopy`, some epilogues first copy the returned bytes to fresh memory at `msize` and exit
+/// from there, so the offset an exit hands out differs from the one the function built its result
+/// at.
+///
+/// every epilogue is placed behind an instruction that ends a block, where nothing falls into it.
+use super::{Ctx, Ir, Pass};
+use crate::evm::op;
+use crate::reloc::{Item, Label};
+use rand::Rng;
+use std::collections::BTreeMap;
+
+// opcodes the pass emits that `evm::op` has no name for
+const MSIZE: u8 = 0x59;
+const DUP3: u8 = 0x82;
+
+/// routes exits through shared epilogues.
+pub struct ReturnPath {
+    /// chance that a given `return` or `revert` is routed through an epilogue.
+    pub probability: f64,
+    /// whether exits may copy their data through memory, where the target runs `mcopy`.
+    pub copy: bool,
+}
+
+impl Default for ReturnPath {
+    fn default() -> Self {
+        ReturnPath {
+            probability: 0.5,
+            copy: true,
+        }
+    }
+}
+
+/// the epilogue at `label` ending in `exit`, which takes the `offset, size` the exit would have
+/// taken. a copying epilogue runs `mcopy(msize, offset, size)` and exits with `msize, size`.
+fn epilogue(label: Label, exit: u8, copies: bool) -> Vec<Item> {
+    let mut items = vec![Item::Mark(label), Item::op(op::JUMPDEST)];
+    if copies {
+        // msize puts dest on top of offset and size, three dups copy all three for mcopy, and
+        // dropping the offset leaves dest and size for the exit
+        items.extend([MSIZE, DUP3, DUP3, DUP3, op::MCOPY, op::SWAP1, op::POP].map(Item::op));
+    }
+    items.push(Item::op(exit));
+    items
+}
+
+impl Pass for ReturnPath {
+    fn name(&self) -> &'static str {
+        "return-path"
+    }
+
+    /// replaces `return` and `revert` with `push k, push <epilogue> + k, sub, jump` (or the same with the pushes
+    /// swapped around a `swap1`), and adds one epilogue for each kind of exit and copying that is
+    /// jumped to.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let copying = self.copy && ctx.target().supports(op::MCOPY);
+        let original = std::mem::take(&mut ir.items);
+        let mut items = Vec::with_capacity(original.len());
+        let mut epilogues: BTreeMap<(u8, bool), Label> = BTreeMap::new();
+        // positions behind an instruction ending a block, where an epilogue can go
+        let mut ends = Vec::new();
+        let mut applied = 0;
+
+        for item in original {
+            let exit = match item {
+                Item::Op { opcode, .. } if matches!(opcode, op::RETURN | op::REVERT) => {
+                    Some(opcode)
+                }
+                _ => None,
+            };
+            match exit {
+                Some(exit) if ctx.rng.gen_bool(self.probability) => {
+                    let copies = copying && ctx.rng.gen_bool(0.5);
+                    let label = *epilogues
+                        .entry((exit, copies))
+                        .or_insert_with(|| ir.new_label());
+                    let k: u8 = ctx.rng.gen_range(1..=u8::MAX);
+                    let pushed = Item::PushLabel {
+                        label,
+                        addend: k as usize,
+                        width: 2,
+                    };
+                    if ctx.rng.gen_bool(0.5) {
+                        items.extend([Item::push(&[k]), pushed, Item::op(op::SUB)]);
+                    } else {
+                        items.extend([pushed, Item::push(&[k]), Item::op(op::SWAP1)]);
+                        items.push(Item::op(op::SUB));
+                    }
+                    items.push(Item::op(op::JUMP));
+                    applied += 1;
+                }
+                _ => items.push(item),
+            }
+            if items.last().is_some_and(Item::is_terminator) {
+                ends.push(items.len());
+            }
+        }
+
+        // every routed exit ends in a jump, so there is somewhere to put the epilogues
+        let mut placed: Vec<(usize, Vec<Item>)> = epilogues
+            .into_iter()
+            .map(|((exit, copies), label)| {
+                let at = ends[ctx.rng.gen_range(0..ends.len())];
+                (at, epilogue(label, exit, copies))
+            })
+            .collect();
+        placed.sort_by_key(|(at, _)| *at);
+        let mut rest = items.into_iter();
+        let mut done = 0;
+        let mut laid_out = Vec::with_capacity(rest.len() + placed.len() * 10);
+        for (at, epilogue) in placed {
+            laid_out.extend(rest.by_ref().take(at - done));
+            laid_out.extend(epilogue);
+            done = at;
+        }
+        laid_out.extend(rest);
+
+        ir.items = laid_out;
+        ctx.record(self.name(), applied);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
@@ -0,0 +1,169 @@
+/// revert string pass.
+/// finds the revert reasons and custom error selectors pushed by the code that builds revert data,
+/// which name the checks of the original source more plainly than anything else in the bytecode,
+/// and either hides them behind a decoder or removes them. a reason word is a push of at least four
+/// bytes of printable text (padding zeros aside), and a custom error selector is a `push4` shifted
+/// into place with `push1 0xe0, shl`, or a `push32` holding a selector followed by zeros. only
+/// basic blocks that revert, or that build the `Error(string)` selector for a shared revert tail,
+/// are searched, so string constants the contract returns and the selectors of calls it makes are
+/// left alone.
+///
+/// encoding replaces each push with `push (value ^ k), push k, xor` for a random `k` without zero
+/// bytes, so no byte of the text is left in the code and the revert data is unchanged. stripping
+/// replaces reasons and custom error selectors with zero, which changes the revert data: callers
+/// see the same error shape without the text.
+use super::{split_after, Ctx, Ir, Pass};
+use crate::evm::op;
+use crate::reloc::Item;
+use clap::ValueEnum;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// the selector of `Error(string)`.
+const ERROR: [u8; 4] = [0x08, 0xC3, 0x79, 0xA0];
+
+/// what happens to the revert reasons found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReasonMode {
+    /// xor the reasons and selectors with a random key decoded at runtime, keeping the revert data.
+    #[default]
+    Encode,
+    /// replace the reasons and custom error selectors with zero, changing the revert data.
+    Strip,
+}
+
+/// hides or removes revert reasons and custom error selectors.
+#[derive(Default)]
+pub struct RevertStrings {
+    /// whether the reasons are encoded or stripped.
+    pub mode: ReasonMode,
+}
+
+/// what a push found in a reverting block carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Found {
+    /// (part of) the text of a revert reason.
+    Reason,
+    /// the selector of a custom error.
+    Selector,
+    /// the selector of `Error(string)`, which says a reason follows.
+    ErrorSelector,
+}
+
+/// the immediate of `item` if it is a push.
+fn pushed(item: Option<&Item>) -> Option<&[u8]> {
+    match item {
+        Some(Item::Op {
+            opcode, immediate, ..
+        }) if (op::PUSH1..=op::PUSH32).contains(opcode) => Some(immediate),
+        _ => None,
+    }
+}
+
+/// returns true if `bytes`, without trailing zero padding, is printable text with a letter in it.
+fn is_text(bytes: &[u8]) -> bool {
+    let end = bytes
+        .iter()
+        .rposition(|&b| b != 0)
+        .map_or(0, |last| last + 1);
+    let text = &bytes[..end];
+    !text.is_empty()
+        && text.iter().all(|b| (0x20..0x7F).contains(b))
+        && text.iter().any(u8::is_ascii_alphabetic)
+}
+
+/// what the push at `idx` of `block` carries, if it is part of an error. solc builds the
+/// `Error(string)` selector as `push3 0x461bcd, push1 0xe5, shl`.
+fn classify(block: &[Item], idx: usize) -> Option<Found> {
+    let immediate = pushed(block.get(idx))?;
+    let shifted =
+        |by: u8| pushed(block.get(idx + 1)) == Some(&[by][..]) && is(block.get(idx + 2), op::SHL);
+    let selector = match immediate {
+        [0x46, 0x1B, 0xCD] if shifted(0xE5) => return Some(Found::ErrorSelector),
+        [_, _, _, _] if shifted(0xE0) => &immediate[..4],
+        word if word.len() == 32 && word[4..].iter().all(|&b| b == 0) => &word[..4],
+        text if text.len() >= 4 && is_text(text) => return Some(Found::Reason),
+        _ => return None,
+    };
+    match selector {
+        [0, 0, 0, 0] => None,
+        selector if selector == ERROR => Some(Found::ErrorSelector),
+        _ => Some(Found::Selector),
+    }
+}
+
+/// returns true if `item` is the instruction `opcode`.
+fn is(item: Option<&Item>, opcode: u8) -> bool {
+    matches!(item, Some(Item::Op { opcode: o, .. }) if *o == opcode)
+}
+
+/// the pushes of `block` carrying an error, by position, or `None` if the block does not build
+/// revert data or has none.
+fn errors(block: &[Item]) -> Option<Vec<(usize, Found)>> {
+    let found: Vec<(usize, Found)> = (0..block.len())
+        .filter_map(|idx| Some((idx, classify(block, idx)?)))
+        .collect();
+    let reverts = block.iter().any(|item| is(Some(item), op::REVERT))
+        || found.iter().any(|(_, kind)| *kind == Found::ErrorSelector);
+    (reverts && !found.is_empty()).then_some(found)
+}
+
+impl Pass for RevertStrings {
+    fn name(&self) -> &'static str {
+        "revert-strings"
+    }
+
+    /// encodes or strips the errors of the basic blocks in parallel.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let blocks = split_after(std::mem::take(&mut ir.items), |item| {
+            matches!(item, Item::Mark(_) | Item::Frozen(_))
+                || item.is_terminator()
+                || is(Some(item), op::JUMPI)
+        });
+        let blocks = blocks.into_iter().map(|block| (block, ())).collect();
+        let mode = self.mode;
+        let (items, applied) = ctx.transform_blocks(
+            self.name(),
+            &format!("{:?}", mode),
+            blocks,
+            |block, ()| errors(block),
+            |block, found, rng| {
+                let mut items = Vec::with_capacity(block.len() + found.len() * 2);
+                let mut found = found.iter().peekable();
+                let mut applied = 0;
+                for (idx, item) in block.into_iter().enumerate() {
+                    let Some(&(_, kind)) = found.next_if(|(at, _)| *at == idx) else {
+                        items.push(item);
+                        continue;
+                    };
+                    let immediate = pushed(Some(&item)).expect("errors are found in pushes");
+                    match (mode, kind) {
+                        (ReasonMode::Strip, Found::ErrorSelector) => {
+                            items.push(item);
+                            continue;
+                        }
+                        (ReasonMode::Strip, _) => items.push(Item::push(&[0])),
+                        (ReasonMode::Encode, _) => {
+                            let key: Vec<u8> = immediate
+                                .iter()
+                                .map(|_| rng.gen_range(1..=u8::MAX))
+                                .collect();
+                            let encoded: Vec<u8> =
+                                immediate.iter().zip(&key).map(|(b, k)| b ^ k).collect();
+                            items.extend([
+                                Item::push(&encoded),
+                                Item::push(&key),
+                                Item::op(op::XOR),
+                            ]);
+                        }
+                    }
+                    applied += 1;
+                }
+                (items, applied)
+            },
+        );
+        ir.items = items;
+        ctx.record(self.name(), applied);
+    }
+}
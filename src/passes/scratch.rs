@@ -0,0 +1,227 @@
+/// scratch memory layout pass.
+/// solidity keeps temporaries in fixed places: the 64 bytes of scratch space at `0x00` (mapping keys
+/// are hashed there, return values are built there), the free memory pointer at `0x40`, and the zero
+/// slot at `0x60`. every contract compiled by solc touches the same offsets the same way, which is a
+/// fingerprint and a shortcut for anyone reading the memory accesses. the pass finds groups of
+/// `mstore`s to constant offsets in scratch space followed by the one instruction that reads exactly
+/// the bytes they wrote, and moves the whole group to a random offset.
+///
+/// a group is only moved where that is safe:
+///
+/// - the stores and the read are in one basic block with no other memory access in between, so
+///   nothing else sees the bytes in transit.
+/// - a group read by `return` or `revert` ends the call, so nothing can observe where it was; it may
+///   move anywhere below `0x80`.
+/// - a group read by `keccak256` or `mload` stays inside scratch space, and only in code that starts
+///   with solc's `mstore(0x40, 0x80)`: solidity never relies on scratch space beyond the statement
+///   that writes it, and memory that already reaches past `0x60` grows no further.
+use super::{Ctx, Ir, Pass};
+use crate::evm::op;
+use crate::reloc::Item;
+use rand::Rng;
+
+// opcodes the groups are read by that `evm::op` has no name for
+const KECCAK256: u8 = 0x20;
+
+/// end of scratch space, where the free memory pointer is.
+const SCRATCH_END: u64 = 0x40;
+/// offset below which a group read by `return` or `revert` is moved: solc's first free byte.
+const HALTING_END: u64 = 0x80;
+
+/// instructions that read or write memory.
+fn touches_memory(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0x20 | 0x37 | 0x39 | 0x3C | 0x3E | 0x51..=0x53 | 0x59 | 0x5E | 0xA0..=0xA4 | 0xF0..=0xF5 | 0xFA | 0xFD
+    )
+}
+
+/// moves scratch space temporaries to random offsets.
+pub struct ScratchLayout {
+    /// chance that a given group of temporaries is moved.
+    pub probability: f64,
+}
+
+impl Default for ScratchLayout {
+    fn default() -> Self {
+        ScratchLayout { probability: 0.5 }
+    }
+}
+
+/// stores to scratch space and the instruction reading them back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    /// index of the push of the offset of every `mstore`, with the offset.
+    pub stores: Vec<(usize, u64)>,
+    /// index of the push of the offset the bytes are read from.
+    pub read: usize,
+    /// whether the read is a `return` or `revert`.
+    pub halting: bool,
+}
+
+impl Group {
+    /// first byte the group writes.
+    fn start(&self) -> u64 {
+        self.stores
+            .iter()
+            .map(|&(_, offset)| offset)
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+/// value of `item` if it is a push of a constant that fits in eight bytes.
+fn constant(item: Option<&Item>) -> Option<u64> {
+    match item? {
+        Item::Op {
+            opcode, immediate, ..
+        } if (op::PUSH0..=op::PUSH1 + 7).contains(opcode) => {
+            Some(immediate.iter().fold(0, |acc, &b| (acc << 8) | b as u64))
+        }
+        _ => None,
+    }
+}
+
+/// returns true if `item` is the instruction `opcode`.
+fn is(item: Option<&Item>, opcode: u8) -> bool {
+    matches!(item, Some(Item::Op { opcode: o, .. }) if *o == opcode)
+}
+
+/// returns true if `items` start with solc's `push1 0x80, push1 0x40, mstore`.
+pub fn solc_prologue(items: &[Item]) -> bool {
+    let mut code = items.iter().filter(|item| !matches!(item, Item::Mark(_)));
+    constant(code.next()) == Some(0x80)
+        && constant(code.next()) == Some(0x40)
+        && is(code.next(), op::MSTORE)
+}
+
+/// finds the groups of scratch space stores read back by a single instruction in `items`.
+///
+/// # example
+/// ```
+/// use ebo::passes::scratch;
+/// use ebo::reloc::Program;
+///
+/// // CALLER, PUSH0, MSTORE, PUSH1 0x20, PUSH0, RETURN
+/// let program = Program::lift(&[0x33, 0x5F, 0x52, 0x60, 0x20, 0x5F, 0xF3]);
+/// let groups = scratch::groups(&program.items);
+/// assert_eq!(groups.len(), 1);
+/// assert!(groups[0].halting);
+/// ```
+pub fn groups(items: &[Item]) -> Vec<Group> {
+    let mut groups = Vec::new();
+    let mut stores: Vec<(usize, u64)> = Vec::new();
+    let mut i = 0;
+    while i < items.len() {
+        let item = &items[i];
+        let first = constant(Some(item));
+        let second = constant(items.get(i + 1));
+
+        // push <offset>, mstore
+        if let (Some(offset), true) = (first, is(items.get(i + 1), op::MSTORE)) {
+            let disjoint = stores.iter().all(|&(_, o)| o.abs_diff(offset) >= 32);
+            if offset + 32 <= SCRATCH_END && disjoint {
+                stores.push((i, offset));
+            } else {
+                stores.clear();
+            }
+            i += 2;
+            continue;
+        }
+
+        // push <length>, push <offset>, keccak256 | return | revert, or push <offset>, mload
+        let read = match (first, second, &items.get(i + 2)) {
+            (Some(length), Some(offset), Some(Item::Op { opcode, .. }))
+                if matches!(*opcode, KECCAK256 | op::RETURN | op::REVERT) =>
+            {
+                Some((i + 1, offset, length, *opcode != KECCAK256, 3))
+            }
+            (Some(offset), _, _) if is(items.get(i + 1), op::MLOAD) => {
+                Some((i, offset, 32, false, 2))
+            }
+            _ => None,
+        };
+        if let Some((read, offset, length, halting, len)) = read {
+            let mut written: Vec<u64> = stores.iter().map(|&(_, o)| o).collect();
+            written.sort_unstable();
+            let exact = !written.is_empty()
+                && written[0] == offset
+                && written.windows(2).all(|w| w[1] == w[0] + 32)
+                && written.len() as u64 * 32 == length;
+            if exact {
+                groups.push(Group {
+                    stores: std::mem::take(&mut stores),
+                    read,
+                    halting,
+                });
+            }
+            stores.clear();
+            i += len;
+            continue;
+        }
+
+        let interrupts = match item {
+            Item::Op { opcode, .. } => {
+                touches_memory(*opcode)
+                    || matches!(*opcode, op::JUMP | op::JUMPI | op::JUMPDEST)
+                    || item.is_terminator()
+            }
+            Item::PushLabel { .. } => false,
+            _ => true,
+        };
+        if interrupts {
+            stores.clear();
+        }
+        i += 1;
+    }
+    groups
+}
+
+impl Pass for ScratchLayout {
+    fn name(&self) -> &'static str {
+        "scratch-layout"
+    }
+
+    /// moves chosen groups to a random offset, keeping the distance between their stores.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let solc = solc_prologue(&ir.items);
+        let push0 = ctx.target().supports(op::PUSH0);
+        let push = |offset: u64| {
+            if offset == 0 && push0 {
+                Item::op(op::PUSH0)
+            } else {
+                Item::push(&[offset as u8])
+            }
+        };
+        let mut moved = 0;
+        for group in groups(&ir.items) {
+            if !group.halting && !solc {
+                continue;
+            }
+            let length = group.stores.len() as u64 * 32;
+            let end = if group.halting {
+                HALTING_END
+            } else {
+                SCRATCH_END
+            };
+            let start = group.start();
+            if length >= end || !ctx.rng.gen_bool(self.probability) {
+                continue;
+            }
+            let to = ctx.rng.gen_range(0..=end - length);
+            if to == start {
+                continue;
+            }
+            for &(idx, offset) in &group.stores {
+                ir.items[idx] = push(offset - start + to);
+            }
+            ir.items[group.read] = push(to);
+            moved += 1;
+        }
+        ctx.record(self.name(), moved);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
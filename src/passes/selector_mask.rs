@@ -0,0 +1,216 @@
+/// selector masking pass.
+/// tools that recover the abi of unverified contracts (whatsabi, evmole) find the external functions
+/// by the comparisons of the selector dispatcher: a `push4` of the selector next to an `eq`, and the
+/// `gt`/`lt` pivots of the binary search solc uses for larger contracts. this pass rewrites those
+/// comparisons so the selector never appears as their operand, and adds decoy comparisons against
+/// made-up selectors, so an extractor reports functions that do not exist and misses those that do.
+/// the dispatcher recognition of `dispatcher::functions` stands in for such an extractor to check
+/// the result.
+use super::{Ctx, Ir, Pass};
+use crate::dispatcher;
+use crate::evm::{op, push_size};
+use crate::reloc::Item;
+use rand::Rng;
+use std::collections::BTreeSet;
+use tracing::debug;
+
+/// masks the selector comparisons of the dispatcher and adds decoy comparisons.
+pub struct SelectorMask;
+
+/// a selector comparison of the dispatcher, `dup1, push selector, op` or `push selector, dup2, op`,
+/// followed by the push of the jump target and `jumpi`.
+struct Comparison {
+    /// the constant compared against.
+    selector: u32,
+    /// whether the constant is pushed before the selector is duplicated (`push, dup2, op`).
+    swapped: bool,
+    /// `eq`, `gt` or `lt`.
+    opcode: u8,
+}
+
+/// matches a comparison at the start of `items`, see `Comparison`.
+fn comparison(items: &[Item]) -> Option<Comparison> {
+    let [first, second, Item::Op {
+        opcode: opcode @ (op::EQ | op::GT | op::LT),
+        ..
+    }, Item::PushLabel { .. }, Item::Op {
+        opcode: op::JUMPI, ..
+    }, ..] = items
+    else {
+        return None;
+    };
+    let constant = |item: &Item| match item {
+        Item::Op {
+            opcode, immediate, ..
+        } if (op::PUSH1..op::PUSH1 + 4).contains(opcode)
+            && immediate.len() == push_size(*opcode) =>
+        {
+            Some(
+                immediate
+                    .iter()
+                    .fold(0u32, |value, &b| value << 8 | b as u32),
+            )
+        }
+        _ => None,
+    };
+    let is =
+        |item: &Item, expected: u8| matches!(item, Item::Op { opcode, .. } if *opcode == expected);
+    let (selector, swapped) = if is(first, op::DUP1) {
+        (constant(second)?, false)
+    } else if is(second, op::DUP2) {
+        (constant(first)?, true)
+    } else {
+        return None;
+    };
+    Some(Comparison {
+        selector,
+        swapped,
+        opcode: *opcode,
+    })
+}
+
+/// the 32-byte push of the bitwise complement of `value`.
+fn push_not(value: u32) -> Item {
+    let mut word = [0xFF; 32];
+    for (byte, b) in word[28..].iter_mut().zip(value.to_be_bytes()) {
+        *byte = !b;
+    }
+    Item::push(&word)
+}
+
+/// the selector comparison `comparison` compares against a masked constant instead, with the same
+/// result for any value: `x == s` becomes `x ^ k == s ^ k` for a random `k`, and `x > s` becomes
+/// `!x < !s`, since the complement reverses the order.
+fn masked(comparison: &Comparison, mask: u32) -> Vec<Item> {
+    let selector = comparison.selector;
+    match (comparison.opcode, comparison.swapped) {
+        (op::EQ, false) => vec![
+            Item::push(&mask.to_be_bytes()),
+            Item::op(op::XOR),
+            Item::push(&(selector ^ mask).to_be_bytes()),
+            Item::op(op::EQ),
+        ],
+        (op::EQ, true) => vec![
+            Item::push(&(selector ^ mask).to_be_bytes()),
+            Item::op(op::DUP2),
+            Item::push(&mask.to_be_bytes()),
+            Item::op(op::XOR),
+            Item::op(op::EQ),
+        ],
+        // `s > x` and `s < x` for a selector x on top of the stack
+        (opcode, false) => vec![
+            Item::op(op::NOT),
+            push_not(selector),
+            Item::op(if opcode == op::GT { op::LT } else { op::GT }),
+        ],
+        // `x > s` and `x < s`
+        (opcode, true) => vec![
+            push_not(selector),
+            Item::op(op::DUP2),
+            Item::op(op::NOT),
+            Item::op(if opcode == op::GT { op::LT } else { op::GT }),
+        ],
+    }
+}
+
+/// the selectors `dispatcher::functions` finds in `code`.
+fn selectors(code: &[u8]) -> BTreeSet<[u8; 4]> {
+    dispatcher::functions(code)
+        .into_iter()
+        .map(|function| function.selector)
+        .collect()
+}
+
+/// the selectors of the dispatcher of `original` that are still found in the dispatcher of
+/// `obfuscated` by matching its comparisons.
+///
+/// # example
+/// ```
+/// use ebo::passes::selector_mask;
+///
+/// // PUSH0, CALLDATALOAD, PUSH1 224, SHR, DUP1, PUSH4 0xa9059cbb, EQ, PUSH1 16, JUMPI, STOP,
+/// // JUMPDEST, STOP
+/// let code = hex::decode("5f3560e01c8063a9059cbb14601057005b00").unwrap();
+/// assert_eq!(selector_mask::recoverable(&code, &code), vec![[0xA9, 0x05, 0x9C, 0xBB]]);
+/// ```
+pub fn recoverable(original: &[u8], obfuscated: &[u8]) -> Vec<[u8; 4]> {
+    let found = selectors(obfuscated);
+    selectors(original)
+        .into_iter()
+        .filter(|selector| found.contains(selector))
+        .collect()
+}
+
+impl Pass for SelectorMask {
+    fn name(&self) -> &'static str {
+        "selector-mask"
+    }
+
+    /// masks every selector comparison of the dispatcher (see `masked`) and follows each `eq` with
+    /// a decoy, `dup1, push4 <made-up selector>, eq, push <next>, jumpi, next: jumpdest`, which
+    /// jumps to where it falls through and so changes nothing, whatever the selector.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let before = ir
+            .assemble()
+            .map(|code| selectors(&code))
+            .unwrap_or_default();
+        let original = std::mem::take(&mut ir.items);
+        let mut items = Vec::with_capacity(original.len());
+        let mut seen = BTreeSet::new();
+        let mut applied = 0;
+
+        let mut i = 0;
+        while i < original.len() {
+            let Some(comparison) = comparison(&original[i..]) else {
+                items.push(original[i].clone());
+                i += 1;
+                continue;
+            };
+            seen.insert(comparison.selector);
+            let mask = ctx.rng.gen_range(1..=u32::MAX);
+            if !comparison.swapped {
+                items.push(original[i].clone());
+            }
+            items.extend(masked(&comparison, mask));
+            items.extend_from_slice(&original[i + 3..i + 5]);
+            i += 5;
+            applied += 1;
+
+            if comparison.opcode == op::EQ {
+                let decoy = loop {
+                    let decoy: u32 = ctx.rng.gen();
+                    if !seen.contains(&decoy) && !before.contains(&decoy.to_be_bytes()) {
+                        break decoy;
+                    }
+                };
+                let next = ir.new_label();
+                items.extend([
+                    Item::op(op::DUP1),
+                    Item::push(&decoy.to_be_bytes()),
+                    Item::op(op::EQ),
+                    Item::PushLabel {
+                        label: next,
+                        addend: 0,
+                        width: 2,
+                    },
+                    Item::op(op::JUMPI),
+                    Item::Mark(next),
+                    Item::op(op::JUMPDEST),
+                ]);
+            }
+        }
+        ir.items = items;
+
+        if let Ok(code) = ir.assemble() {
+            let after = selectors(&code);
+            debug!(
+                "Masked {} selector comparisons; {} of {} selectors are still matched, next to {} decoys",
+                applied,
+                before.intersection(&after).count(),
+                before.len(),
+                after.difference(&before).count()
+            );
+        }
+        ctx.record(self.name(), applied);
+    }
+}
@@ -0,0 +1,206 @@
+/// spaghettification for the ebo obfuscator.
+/// chops the code into small fragments, links every fragment to its successor with an unconditional
+/// jump, and scatters the fragments across the output in random order. execution follows exactly the
+/// same instruction sequence as before, but a reader has to chase a jump every few instructions and
+/// the layout no longer mirrors the source (bosc, section 2.2).
+use super::{Ctx, Ir, Pass};
+use crate::evm::op;
+use crate::reloc::{Item, Label, Program};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::ops::Range;
+use tracing::debug;
+
+/// bytes added by a random cut: push2 <next>, jump, and a jumpdest at the start of the next fragment.
+const CUT_BYTES: usize = 5;
+/// bytes added when a block that falls through into an existing jumpdest is moved away from it.
+const LINK_BYTES: usize = 4;
+
+/// scatters code fragments across the output within the context's size budget.
+pub struct Spaghetti {
+    /// chance of cutting after each instruction.
+    pub probability: f64,
+}
+
+impl Default for Spaghetti {
+    fn default() -> Self {
+        Spaghetti { probability: 0.25 }
+    }
+}
+
+impl Pass for Spaghetti {
+    fn name(&self) -> &'static str {
+        "spaghetti"
+    }
+
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let fragments = spaghettify(ir, &mut ctx.rng, self.probability, ctx.max_size);
+        ctx.record(self.name(), fragments);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
+
+/// a fragment of the code being scattered. the items stay where they are until the layout is
+/// final, so cutting and linking fragments copies nothing.
+struct Fragment {
+    /// positions of the items of the fragment in the code.
+    items: Range<usize>,
+    /// label added in front of the fragment so that the fragment before it can jump to it.
+    entry: Option<Label>,
+    /// where execution continues after the fragment if its last item does not end it: the entry
+    /// of the next fragment, or nowhere at the end of the code.
+    exit: Option<Option<Label>>,
+}
+
+impl Fragment {
+    fn new(items: Range<usize>) -> Self {
+        Fragment {
+            items,
+            entry: None,
+            exit: None,
+        }
+    }
+}
+
+/// returns true if `item` ends execution of the code in front of it.
+fn terminates(item: Option<&Item>) -> bool {
+    item.is_some_and(Item::is_terminator)
+}
+
+/// scatters fragments of `program` across the output, keeping the result within `max_size` bytes.
+///
+/// the first fragment stays at offset 0 so execution still starts where it did, and data regions
+/// (unreachable bytes and constant pools) are moved behind all code. boundaries between existing
+/// blocks are always linked; additional cuts inside blocks are made with probability `probability`
+/// after each instruction, for as long as the layout budget allows.
+///
+/// # arguments
+/// * `program` - lifted program to rewrite in place.
+/// * `rng` - random source deciding where to cut and how to order fragments.
+/// * `probability` - chance of cutting after each instruction.
+/// * `max_size` - upper bound for the assembled size; `evm::MAX_CODE_SIZE` for deployable runtime code.
+///
+/// # returns
+/// number of fragments laid out, or 0 if the program was left untouched because it is a single
+/// fragment or because even linking the existing blocks would exceed the budget.
+pub fn spaghettify(
+    program: &mut Program,
+    rng: &mut impl Rng,
+    probability: f64,
+    max_size: usize,
+) -> usize {
+    let Ok(base) = program.assemble().map(|code| code.len()) else {
+        return 0;
+    };
+    let original = std::mem::take(&mut program.items);
+
+    // separate code from data; a mark directly in front of data labels that data, not code. both
+    // are kept as positions in `original`, which every fragment below refers into
+    let mut code = Vec::with_capacity(original.len());
+    let mut data = Vec::new();
+    for (idx, item) in original.iter().enumerate() {
+        let labels_data =
+            matches!(item, Item::Mark(_)) && matches!(original.get(idx + 1), Some(Item::Data(_)));
+        if labels_data || matches!(item, Item::Data(_)) {
+            data.push(idx);
+        } else {
+            code.push(idx);
+        }
+    }
+
+    let links = code
+        .windows(2)
+        .filter(|pair| {
+            matches!(original[pair[1]], Item::Mark(_)) && !terminates(Some(&original[pair[0]]))
+        })
+        .count();
+    // one extra byte for the stop that may replace running off the end of the code
+    let required = base + links * LINK_BYTES + 1;
+    let Some(mut budget) = max_size.checked_sub(required).filter(|_| !code.is_empty()) else {
+        debug!(
+            "Skipping spaghettification: {} bytes leaves no layout budget",
+            base
+        );
+        program.items = original;
+        return 0;
+    };
+
+    // chop into fragments, as ranges of `code`: every mark starts a new one, and so does a random cut
+    let mut fragments: Vec<Fragment> = Vec::new();
+    let mut first = 0;
+    for (pos, &idx) in code.iter().enumerate() {
+        let at_mark = matches!(original[idx], Item::Mark(_));
+        let last = pos.checked_sub(1).map(|prev| &original[code[prev]]);
+        let cuttable =
+            pos - first >= 2 && !matches!(last, Some(Item::Mark(_))) && !terminates(last);
+        let cut = !at_mark && cuttable && budget >= CUT_BYTES && rng.gen_bool(probability);
+        if (at_mark || cut) && pos > first {
+            fragments.push(Fragment::new(first..pos));
+            first = pos;
+            if cut {
+                budget -= CUT_BYTES;
+            }
+        }
+    }
+    fragments.push(Fragment::new(first..code.len()));
+    if fragments.len() < 2 {
+        // a single fragment has nowhere to be scattered to
+        program.items = original;
+        return 0;
+    }
+
+    // give every fragment that something falls into an entry label, then link it to its successor
+    let last_item = |fragment: &Fragment| original.get(code[fragment.items.end - 1]);
+    let mut entries = Vec::with_capacity(fragments.len());
+    for idx in 0..fragments.len() {
+        let entry = match &original[code[fragments[idx].items.start]] {
+            Item::Mark(label) => Some(*label),
+            _ if idx > 0 && !terminates(last_item(&fragments[idx - 1])) => {
+                let label = program.new_label();
+                fragments[idx].entry = Some(label);
+                Some(label)
+            }
+            _ => None,
+        };
+        entries.push(entry);
+    }
+    for (idx, fragment) in fragments.iter_mut().enumerate() {
+        if !terminates(last_item(fragment)) {
+            // running off the end of the code stops execution
+            fragment.exit = Some(entries.get(idx + 1).copied().flatten());
+        }
+    }
+
+    let count = fragments.len();
+    let mut order: Vec<usize> = (1..count).collect();
+    order.shuffle(rng);
+    let mut slots: Vec<Option<Item>> = original.into_iter().map(Some).collect();
+    let mut items = Vec::with_capacity(slots.len() + count * 3);
+    let mut take = |idx: usize| slots[idx].take().expect("every item is laid out once");
+    for fragment in std::iter::once(0).chain(order).map(|idx| &fragments[idx]) {
+        if let Some(label) = fragment.entry {
+            items.extend([Item::Mark(label), Item::op(op::JUMPDEST)]);
+        }
+        items.extend(code[fragment.items.clone()].iter().map(|&idx| take(idx)));
+        match fragment.exit {
+            Some(Some(next)) => items.extend([
+                Item::PushLabel {
+                    label: next,
+                    addend: 0,
+                    width: 2,
+                },
+                Item::op(op::JUMP),
+            ]),
+            Some(None) => items.push(Item::op(op::STOP)),
+            None => {}
+        }
+    }
+    items.extend(data.into_iter().map(take));
+
+    debug!("Scattered code into {} fragments", count);
+    program.items = items;
+    count
+}
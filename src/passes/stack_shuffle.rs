@@ -0,0 +1,237 @@
+/// stack slot shuffling pass, the evm analog of register renaming.
+/// following a value through the stack by hand means tracking every `dup` and `swap` that moves it.
+/// the pass permutes the stack items a basic block starts with by a chain of `swap`s at its entry,
+/// and then renames every stack access of the block against the permuted layout: a `dup` reads the
+/// item from where it now is, the block's own `swap`s only change which item is where and emit
+/// nothing, and before an instruction consumes items, the few swaps that bring exactly those items to
+/// the top are inserted. before the block hands its stack on (a jump, or falling through into the
+/// next block) the layout is restored, so every block keeps its interface.
+///
+/// the permuted items are the ones the block reads, which it needs to be there to run at all, so
+/// the swaps cannot underflow a stack the original block would have run on. a block whose layout
+/// would move an item beyond the reach of `dup16` or `swap16` is left alone.
+use super::{Ctx, Ir, Pass};
+use crate::evm::{is_terminator, op, stack_io};
+use crate::reloc::Item;
+use rand::{Rng, RngCore};
+
+/// deepest stack position `swap16` reaches, 0 being the top.
+const REACH: usize = 16;
+
+/// shuffles the stack layout inside basic blocks.
+pub struct StackShuffle {
+    /// chance that a given basic block is shuffled.
+    pub probability: f64,
+}
+
+impl Default for StackShuffle {
+    fn default() -> Self {
+        StackShuffle { probability: 0.3 }
+    }
+}
+
+/// the stack of a block being rewritten: which item is at each position, top first, in the
+/// original code (`logical`) and in the rewritten code (`physical`).
+struct Layout {
+    logical: Vec<usize>,
+    physical: Vec<usize>,
+    next: usize,
+    out: Vec<Item>,
+}
+
+impl Layout {
+    /// a fresh item pushed on both stacks.
+    fn push(&mut self) {
+        self.logical.insert(0, self.next);
+        self.physical.insert(0, self.next);
+        self.next += 1;
+    }
+
+    /// physical position of the item at logical position `depth`.
+    fn find(&self, depth: usize) -> Option<usize> {
+        let item = self.logical.get(depth)?;
+        self.physical.iter().position(|i| i == item)
+    }
+
+    /// emits `swap<n>`.
+    fn swap(&mut self, n: usize) -> Option<()> {
+        if n == 0 || n > REACH {
+            return None;
+        }
+        self.out.push(Item::op(op::SWAP1 + n as u8 - 1));
+        self.physical.swap(0, n);
+        Some(())
+    }
+
+    /// exchanges the items at physical positions `a` and `b`, leaving every other position as it is.
+    fn exchange(&mut self, a: usize, b: usize) -> Option<()> {
+        match (a.min(b), a.max(b)) {
+            (a, b) if a == b => Some(()),
+            (0, b) => self.swap(b),
+            (a, b) => {
+                self.swap(a)?;
+                self.swap(b)?;
+                self.swap(a)
+            }
+        }
+    }
+
+    /// brings the top `n` items of the original layout into place, top first.
+    fn place(&mut self, n: usize) -> Option<()> {
+        for depth in 0..n {
+            let at = self.find(depth)?;
+            self.exchange(depth, at)?;
+        }
+        Some(())
+    }
+}
+
+/// number of stack items `block` reads from below what it pushes itself.
+fn inputs(block: &[Item]) -> Option<usize> {
+    let mut height = 0isize;
+    let mut needed = 0isize;
+    for item in block {
+        // dups and swaps count the items they reach as popped and pushed again
+        let (popped, pushed) = match item {
+            Item::PushLabel { .. } => (0, 1),
+            Item::Op { opcode, .. } => stack_io(*opcode)?,
+            _ => return None,
+        };
+        needed = needed.max(popped as isize - height);
+        height += pushed as isize - popped as isize;
+    }
+    Some(needed as usize)
+}
+
+/// rewrites `block` against a random permutation of its inputs, or returns `None` if it cannot be.
+/// `tail` is the jump ending the block, kept as it is, behind which the layout is restored.
+fn shuffle(block: &[Item], tail: &[Item], rng: &mut dyn RngCore) -> Option<Vec<Item>> {
+    let inputs = inputs(block)?;
+    if !(2..=REACH + 1).contains(&inputs) {
+        return None;
+    }
+    let mut layout = Layout {
+        logical: (0..inputs).collect(),
+        physical: (0..inputs).collect(),
+        next: inputs,
+        out: Vec::new(),
+    };
+    // the permutation, as a chain of swaps at the entry
+    for _ in 0..rng.gen_range(1..=inputs) {
+        let (a, b) = (rng.gen_range(0..inputs), rng.gen_range(0..inputs));
+        layout.exchange(a, b)?;
+    }
+
+    for item in block {
+        match item {
+            Item::PushLabel { .. } => {
+                layout.out.push(item.clone());
+                layout.push();
+            }
+            Item::Op { opcode, .. } => match *opcode {
+                0x80..=0x8F => {
+                    let at = layout.find((opcode - 0x80) as usize)?;
+                    if at >= REACH {
+                        return None;
+                    }
+                    layout.out.push(Item::op(op::DUP1 + at as u8));
+                    layout.push();
+                }
+                // the original swap becomes a renaming
+                0x90..=0x9F => layout.logical.swap(0, (opcode - 0x8F) as usize),
+                opcode => {
+                    let (popped, pushed) = stack_io(opcode)?;
+                    layout.place(popped)?;
+                    layout.out.push(item.clone());
+                    layout.logical.drain(..popped);
+                    layout.physical.drain(..popped);
+                    for _ in 0..pushed {
+                        layout.push();
+                    }
+                }
+            },
+            _ => return None,
+        }
+    }
+
+    // a block that halts leaves no stack behind; every other one restores its layout
+    let halts = block.last().is_some_and(Item::is_terminator);
+    if !halts {
+        layout.place(layout.logical.len())?;
+    }
+    layout.out.extend_from_slice(tail);
+    Some(layout.out)
+}
+
+impl Pass for StackShuffle {
+    fn name(&self) -> &'static str {
+        "stack-shuffle"
+    }
+
+    /// shuffles the blocks between jumpdests, marks, and the instructions that end a block.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let original = std::mem::take(&mut ir.items);
+        let mut items = Vec::with_capacity(original.len());
+        let mut applied = 0;
+        let mut start = 0;
+        while start < original.len() {
+            let boundary = original[start..].iter().position(|item| match item {
+                Item::Op { opcode, .. } => {
+                    matches!(*opcode, op::JUMPDEST | op::JUMPI) || is_terminator(*opcode)
+                }
+                Item::PushLabel { .. } => false,
+                _ => true,
+            });
+            let end = boundary.map_or(original.len(), |offset| start + offset);
+            // the instruction ending the block belongs to it, other boundaries do not
+            let ends = original.get(end).is_some_and(|item| match item {
+                Item::Op { opcode, .. } => *opcode != op::JUMPDEST,
+                _ => false,
+            });
+            let block = &original[start..end + ends as usize];
+
+            // a jump keeps the push of its target in front of it, so it stays static
+            let jumps = block.last().is_some_and(|item| {
+                matches!(
+                    item,
+                    Item::Op {
+                        opcode: op::JUMP | op::JUMPI,
+                        ..
+                    }
+                )
+            });
+            let cut = match block.len() {
+                len if jumps && len >= 2 && matches!(block[len - 2], Item::PushLabel { .. }) => {
+                    len - 2
+                }
+                len if jumps => len - 1,
+                len => len,
+            };
+            let shuffled = (!block.is_empty() && ctx.rng.gen_bool(self.probability))
+                .then(|| shuffle(&block[..cut], &block[cut..], &mut *ctx.rng))
+                .flatten();
+            match shuffled {
+                Some(code) => {
+                    items.extend(code);
+                    applied += 1;
+                }
+                None => items.extend_from_slice(block),
+            }
+
+            start += block.len();
+            if !ends {
+                if let Some(item) = original.get(end) {
+                    items.push(item.clone());
+                    start += 1;
+                }
+            }
+        }
+
+        ir.items = items;
+        ctx.record(self.name(), applied);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
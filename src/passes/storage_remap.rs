@@ -0,0 +1,347 @@
+/// storage slot remapping pass.
+/// moves every storage slot the code names as a constant to a random slot, rewriting the constant at
+/// every `sload` and `sstore` of it, so the state of the deployed contract no longer sits at the
+/// slots its declaration order gives and a variable cannot be read off its usual slot. the moves are
+/// recorded in `Ctx::slots` (see `slotmap`) for tooling that reads the storage directly.
+///
+/// the pass is not part of the standard pipeline and only runs when asked for: the contract keeps
+/// its state at other slots than the source says, which breaks proxies, upgrades, and anything else
+/// sharing its storage layout, and a constructor left as it is still initializes the original slots.
+/// a slot the pass cannot trace to a constant or to a hash (one computed by arithmetic, loaded, or
+/// handed in through a return address) could alias a moved slot, so the pass then leaves the storage
+/// alone, as it does when frozen code reads or writes storage.
+///
+/// slots are traced through the stack of every basic block, starting from what the blocks jumping
+/// or falling into it leave there; a block entered through a pushed return address, from frozen
+/// code, or from nowhere the pass can see starts from an unknown stack.
+use super::{Ctx, Ir, Pass};
+use crate::evm::{self, op};
+use crate::reloc::{Item, Label};
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ops::Range;
+use tracing::{debug, warn};
+
+// opcode the pass reads that `evm::op` has no name for
+const KECCAK256: u8 = 0x20;
+
+/// number of stack values traced from the top; anything deeper is unknown.
+const DEPTH: usize = 32;
+
+/// moves constant storage slots to random ones.
+pub struct StorageRemap;
+
+/// what is known about a value on the stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Value {
+    /// the constant word.
+    Constant([u8; 32]),
+    /// a word computed from a hash, like the slot of a mapping entry.
+    Hashed,
+    /// anything else.
+    Unknown,
+}
+
+/// the traced top of the stack, deepest value first, above values that are unknown.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Stack(Vec<Value>);
+
+impl Stack {
+    /// the value `depth` items below the top.
+    fn get(&self, depth: usize) -> Value {
+        self.0
+            .len()
+            .checked_sub(depth + 1)
+            .map_or(Value::Unknown, |idx| self.0[idx].clone())
+    }
+
+    fn pop(&mut self) -> Value {
+        self.0.pop().unwrap_or(Value::Unknown)
+    }
+
+    fn push(&mut self, value: Value) {
+        if self.0.len() == DEPTH {
+            self.0.remove(0);
+        }
+        self.0.push(value);
+    }
+
+    /// what is known on both `self` and `other`: the values they agree on, up to the shorter one.
+    fn meet(&self, other: &Stack) -> Stack {
+        let depth = self.0.len().min(other.0.len());
+        let values = (0..depth)
+            .rev()
+            .map(|depth| match (self.get(depth), other.get(depth)) {
+                (a, b) if a == b => a,
+                _ => Value::Unknown,
+            })
+            .collect();
+        Stack(values)
+    }
+
+    /// runs `item` on the stack.
+    fn step(&mut self, item: &Item) {
+        let (opcode, immediate) = match item {
+            Item::Op {
+                opcode, immediate, ..
+            } => (*opcode, immediate),
+            Item::PushLabel { .. } => return self.push(Value::Unknown),
+            Item::Mark(_) => return,
+            Item::Data(_) | Item::Frozen(_) => return self.0.clear(),
+        };
+        match opcode {
+            op::PUSH0..=op::PUSH32 => self.push(Value::Constant(word(immediate))),
+            op::DUP1..=op::DUP16 => self.push(self.get((opcode - op::DUP1) as usize)),
+            op::SWAP1..=op::SWAP16 => {
+                let depth = (opcode - op::SWAP1 + 1) as usize;
+                while self.0.len() <= depth {
+                    self.0.insert(0, Value::Unknown);
+                }
+                let top = self.0.len() - 1;
+                self.0.swap(top, top - depth);
+            }
+            _ => {
+                let (pops, pushes) = evm::stack_io(opcode).unwrap_or((0, 0));
+                let operands: Vec<Value> = (0..pops).map(|_| self.pop()).collect();
+                let result = if opcode == KECCAK256 || operands.contains(&Value::Hashed) {
+                    Value::Hashed
+                } else {
+                    Value::Unknown
+                };
+                for _ in 0..pushes {
+                    self.push(result.clone());
+                }
+            }
+        }
+    }
+}
+
+/// `immediate` as a 32-byte big-endian word.
+fn word(immediate: &[u8]) -> [u8; 32] {
+    let mut word = [0; 32];
+    word[32 - immediate.len()..].copy_from_slice(immediate);
+    word
+}
+
+/// returns true if `item` is the instruction `opcode`.
+fn is(item: Option<&Item>, opcode: u8) -> bool {
+    matches!(item, Some(Item::Op { opcode: o, .. }) if *o == opcode)
+}
+
+/// returns true if `item` reads or writes storage.
+fn accesses(item: &Item) -> bool {
+    is(Some(item), op::SLOAD) || is(Some(item), op::SSTORE)
+}
+
+/// returns true if the frozen regions of `items` read or write storage.
+pub(super) fn frozen_access(items: &[Item]) -> bool {
+    items.iter().any(|item| match item {
+        Item::Frozen(region) => region.iter().any(accesses) || frozen_access(region),
+        _ => false,
+    })
+}
+
+/// adds the labels of `items` that are pushed other than as the target of the jump right after
+/// the push, so code may arrive at them from anywhere: return addresses, computed jumps, and every
+/// label pushed by frozen code.
+fn escaped(items: &[Item], frozen: bool, labels: &mut HashSet<Label>) {
+    for (idx, item) in items.iter().enumerate() {
+        match item {
+            Item::PushLabel { label, addend, .. } => {
+                let next = items.get(idx + 1);
+                let jumped = is(next, op::JUMP) || is(next, op::JUMPI);
+                if frozen || !jumped || *addend != 0 {
+                    labels.insert(*label);
+                }
+            }
+            Item::Frozen(region) => escaped(region, true, labels),
+            _ => {}
+        }
+    }
+}
+
+/// the basic blocks of `items`: a block starts at every mark and after every item that ends or
+/// branches out of one, and frozen regions and data stand alone.
+fn blocks(items: &[Item]) -> Vec<Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    for (idx, item) in items.iter().enumerate() {
+        let alone = matches!(item, Item::Frozen(_) | Item::Data(_));
+        if (alone || matches!(item, Item::Mark(_))) && idx > start {
+            blocks.push(start..idx);
+            start = idx;
+        }
+        if alone || item.is_terminator() || is(Some(item), op::JUMPI) {
+            blocks.push(start..idx + 1);
+            start = idx + 1;
+        }
+    }
+    if start < items.len() {
+        blocks.push(start..items.len());
+    }
+    blocks
+}
+
+/// the value of the slot every `sload` and `sstore` of `items` reads or writes, by position.
+pub(super) fn keys(items: &[Item]) -> Vec<(usize, Value)> {
+    let blocks = blocks(items);
+    let starts: HashMap<Label, usize> = blocks
+        .iter()
+        .enumerate()
+        .filter_map(|(block, range)| match items[range.start] {
+            Item::Mark(label) => Some((label, block)),
+            _ => None,
+        })
+        .collect();
+    let successors: Vec<Vec<usize>> = blocks
+        .iter()
+        .enumerate()
+        .map(|(block, range)| {
+            let last = &items[range.end - 1];
+            let mut next = Vec::new();
+            if is(Some(last), op::JUMP) || is(Some(last), op::JUMPI) {
+                if let Some(Item::PushLabel {
+                    label, addend: 0, ..
+                }) = range.end.checked_sub(2).map(|idx| &items[idx])
+                {
+                    next.extend(starts.get(label));
+                }
+            }
+            if !last.is_terminator() && !matches!(last, Item::Data(_)) && block + 1 < blocks.len() {
+                next.push(block + 1);
+            }
+            next
+        })
+        .collect();
+
+    let mut labels = HashSet::new();
+    escaped(items, false, &mut labels);
+    let mut entered = vec![false; blocks.len()];
+    for next in successors.iter().flatten() {
+        entered[*next] = true;
+    }
+    let mut entries: Vec<Option<Stack>> = blocks
+        .iter()
+        .enumerate()
+        .map(|(block, range)| {
+            let open = match items[range.start] {
+                Item::Mark(label) => labels.contains(&label),
+                _ => false,
+            };
+            (block == 0 || open || !entered[block]).then(Stack::default)
+        })
+        .collect();
+
+    // propagate the stacks to a fixed point; a cycle of blocks nothing else enters starts unknown
+    let mut queue: VecDeque<usize> = (0..blocks.len())
+        .filter(|&block| entries[block].is_some())
+        .collect();
+    loop {
+        while let Some(block) = queue.pop_front() {
+            let mut stack = entries[block].clone().expect("queued blocks have an entry");
+            for item in &items[blocks[block].clone()] {
+                stack.step(item);
+            }
+            for &next in &successors[block] {
+                let entry = match &entries[next] {
+                    Some(entry) => entry.meet(&stack),
+                    None => stack.clone(),
+                };
+                if entries[next].as_ref() != Some(&entry) {
+                    entries[next] = Some(entry);
+                    queue.push_back(next);
+                }
+            }
+        }
+        match entries.iter().position(Option::is_none) {
+            Some(block) => {
+                entries[block] = Some(Stack::default());
+                queue.push_back(block);
+            }
+            None => break,
+        }
+    }
+
+    let mut keys = Vec::new();
+    for (range, entry) in blocks.into_iter().zip(entries) {
+        let mut stack = entry.expect("every block has an entry");
+        for idx in range {
+            if accesses(&items[idx]) {
+                keys.push((idx, stack.get(0)));
+            }
+            stack.step(&items[idx]);
+        }
+    }
+    keys
+}
+
+impl Pass for StorageRemap {
+    fn name(&self) -> &'static str {
+        "storage-remap"
+    }
+
+    /// replaces the slot pushed for every `sload` and `sstore` of a constant slot with a random slot
+    /// of its own, or pops the constant and pushes the slot where it was pushed further away.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        if frozen_access(&ir.items) {
+            warn!("Leaving storage slots alone: frozen code reads or writes storage");
+            return ctx.record(self.name(), 0);
+        }
+        let keys = keys(&ir.items);
+        let mut slots = BTreeMap::new();
+        for (idx, key) in &keys {
+            match key {
+                Value::Constant(slot) => {
+                    slots.insert(*slot, [0; 32]);
+                }
+                Value::Hashed => {}
+                Value::Unknown => {
+                    let at = match &ir.items[*idx] {
+                        Item::Op {
+                            origin: Some(pc), ..
+                        } => format!("offset {:#x}", pc),
+                        _ => "an inserted instruction".to_string(),
+                    };
+                    warn!(
+                        "Leaving storage slots alone: the slot accessed at {} cannot be traced",
+                        at
+                    );
+                    return ctx.record(self.name(), 0);
+                }
+            }
+        }
+        for to in slots.values_mut() {
+            *to = ctx.rng.gen();
+        }
+        debug!("Moving {} storage slots", slots.len());
+
+        let sites: BTreeMap<usize, [u8; 32]> = keys
+            .into_iter()
+            .filter_map(|(idx, key)| match key {
+                Value::Constant(slot) => Some((idx, slots[&slot])),
+                _ => None,
+            })
+            .collect();
+        let original = std::mem::take(&mut ir.items);
+        let mut items = Vec::with_capacity(original.len() + sites.len() * 2);
+        for (idx, item) in original.into_iter().enumerate() {
+            if let Some(slot) = sites.get(&idx) {
+                let pushed = matches!(
+                    items.last(),
+                    Some(Item::Op { opcode, .. }) if (op::PUSH0..=op::PUSH32).contains(opcode)
+                );
+                if pushed {
+                    items.pop();
+                } else {
+                    items.push(Item::op(op::POP));
+                }
+                items.push(Item::push(slot));
+            }
+            items.push(item);
+        }
+
+        ir.items = items;
+        ctx.slots.record(&slots);
+        ctx.record(self.name(), sites.len());
+    }
+}
@@ -0,0 +1,186 @@
+/// opcode substitution pass.
+/// replaces simple instructions with longer equivalent sequences (eveilm, page 59). every template
+/// is checked with the symbolic stack evaluator each time it is instantiated, so a substitution can
+/// never change what the code computes. zero pushes in a template are fitted to the target fork, so
+/// shanghai and later get the shorter and cheaper `push0`.
+use super::{push0, split_after, Ctx, Ir, Pass};
+use crate::chain::Target;
+use crate::evm::op;
+use crate::reloc::Item;
+use crate::symbolic;
+use rand::Rng;
+use tracing::warn;
+
+/// a replacement for a single instruction.
+pub struct Template {
+    /// short human-readable name used in logs.
+    pub name: &'static str,
+    /// the instruction the template replaces.
+    pub replaces: u8,
+    /// builds the replacement, given a random byte for templates that embed a constant.
+    pub code: fn(u8) -> Vec<u8>,
+}
+
+/// the substitution templates. each replaces one instruction with up to eight bytes.
+pub const TEMPLATES: &[Template] = &[
+    Template {
+        name: "add k, sub k",
+        replaces: op::ADD,
+        // push1 k, add, add, push1 k, swap1, sub
+        code: |k| {
+            vec![
+                op::PUSH1,
+                k,
+                op::ADD,
+                op::ADD,
+                op::PUSH1,
+                k,
+                op::SWAP1,
+                op::SUB,
+            ]
+        },
+    },
+    Template {
+        name: "sub from k",
+        replaces: op::ADD,
+        // b - (k - a) is a + b - k: push1 k, sub, swap1, sub, push1 k, add
+        code: |k| {
+            vec![
+                op::PUSH1,
+                k,
+                op::SUB,
+                op::SWAP1,
+                op::SUB,
+                op::PUSH1,
+                k,
+                op::ADD,
+            ]
+        },
+    },
+    Template {
+        name: "sub not",
+        replaces: op::ADD,
+        // a - not b is a + b + 1: swap1, not, swap1, sub, push1 1, swap1, sub
+        code: |_| {
+            vec![
+                op::SWAP1,
+                op::NOT,
+                op::SWAP1,
+                op::SUB,
+                op::PUSH1,
+                1,
+                op::SWAP1,
+                op::SUB,
+            ]
+        },
+    },
+    Template {
+        name: "sub negated",
+        replaces: op::ADD,
+        // b - (0 - a) is a + b: push0, sub, swap1, sub
+        code: |_| vec![op::PUSH0, op::SUB, op::SWAP1, op::SUB],
+    },
+];
+
+/// substitutes add instructions.
+pub struct Substitution {
+    /// chance that a given add is substituted.
+    pub probability: f64,
+}
+
+impl Default for Substitution {
+    fn default() -> Self {
+        Substitution { probability: 0.5 }
+    }
+}
+
+/// the templates that may replace each item of `block` on `target`, or `None` if there is no item
+/// any template replaces. templates the target fork or chain does not run are never picked.
+fn candidates(block: &[Item], target: Target) -> Option<Vec<Vec<&'static Template>>> {
+    let runnable: Vec<&'static Template> = TEMPLATES
+        .iter()
+        .filter(|template| target.admits(&push0::fit(&(template.code)(0), target)))
+        .collect();
+    let candidates: Vec<Vec<&'static Template>> = block
+        .iter()
+        .map(|item| match item {
+            Item::Op { opcode, .. } => runnable
+                .iter()
+                .copied()
+                .filter(|template| template.replaces == *opcode)
+                .collect(),
+            _ => Vec::new(),
+        })
+        .collect();
+    candidates
+        .iter()
+        .any(|templates| !templates.is_empty())
+        .then_some(candidates)
+}
+
+/// substitutes the add instructions of `block`, picking from the templates `candidates` gives for
+/// each item. returns the block and the number substituted.
+fn substitute(
+    block: Vec<Item>,
+    candidates: &[Vec<&'static Template>],
+    rng: &mut impl Rng,
+    probability: f64,
+    target: Target,
+) -> (Vec<Item>, usize) {
+    let mut items = Vec::with_capacity(block.len());
+    let mut applied = 0;
+
+    for (item, templates) in block.into_iter().zip(candidates) {
+        if templates.is_empty() || !rng.gen_bool(probability) {
+            // retain original instruction without substitution
+            items.push(item);
+            continue;
+        }
+
+        // apply opcode substitution with a random template (eveilm, page 59)
+        let template = templates[rng.gen_range(0..templates.len())];
+        let code = push0::fit(&(template.code)(rng.gen()), target);
+        if symbolic::equivalent(&[template.replaces], &code) {
+            items.extend(Item::sequence(&code));
+            applied += 1;
+        } else {
+            warn!(
+                "Rejected substitution '{}': {} does not compute the same values",
+                template.name,
+                hex::encode(&code)
+            );
+            items.push(item);
+        }
+    }
+    (items, applied)
+}
+
+impl Pass for Substitution {
+    fn name(&self) -> &'static str {
+        "substitution"
+    }
+
+    /// substitutes the blocks between marks and frozen regions in parallel.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let blocks = split_after(std::mem::take(&mut ir.items), |item| {
+            matches!(item, Item::Mark(_) | Item::Frozen(_))
+        });
+        let blocks = blocks.into_iter().map(|block| (block, ())).collect();
+        let (probability, target) = (self.probability, ctx.target());
+        let params = format!("{} {:?}", probability, target);
+        let (items, applied) = ctx.transform_blocks(
+            self.name(),
+            &params,
+            blocks,
+            |block, ()| candidates(block, target),
+            |block, candidates, rng| substitute(block, candidates, rng, probability, target),
+        );
+
+        ir.items = items;
+        ctx.record(self.name(), applied);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
@@ -0,0 +1,128 @@
+/// jump trampoline pass.
+/// sends direct jumps through chains of trampolines, `jumpdest, push <next>, jump`, that end at the
+/// original target. every hop of a chain is put in its own random spot of the unreachable code
+/// behind a terminator, so following a single control transfer in the disassembly means chasing
+/// jumps back and forth across the whole binary. the number of hops of each chain comes from the
+/// chaotic map (see `chaotic_shuffle`), so chains of different lengths mix.
+///
+/// a hop costs `jumpdest`, `push` and `jump`, 12 gas, on every taken jump it is in front of; the gas
+/// model follows the chains, so a gas budget makes the pass back off.
+use super::chaotic_shuffle::chaotic_map;
+use super::{Ctx, Ir, Pass};
+use crate::evm::op;
+use crate::reloc::{Item, Label};
+use rand::Rng;
+use std::collections::BTreeMap;
+
+/// most trampolines in front of one jump.
+const MAX_HOPS: usize = 4;
+
+/// routes direct jumps through chains of trampolines.
+pub struct Trampolines {
+    /// chance that a given direct jump is routed through a chain.
+    pub probability: f64,
+}
+
+impl Default for Trampolines {
+    fn default() -> Self {
+        Trampolines { probability: 0.2 }
+    }
+}
+
+/// `jumpdest, push <next>, jump`, marked with `label`.
+fn trampoline(label: Label, next: Label) -> [Item; 4] {
+    [
+        Item::Mark(label),
+        Item::op(op::JUMPDEST),
+        Item::PushLabel {
+            label: next,
+            addend: 0,
+            width: 2,
+        },
+        Item::op(op::JUMP),
+    ]
+}
+
+/// returns true if `item` is the instruction `opcode`.
+fn is(item: Option<&Item>, opcode: u8) -> bool {
+    matches!(item, Some(Item::Op { opcode: o, .. }) if *o == opcode)
+}
+
+impl Pass for Trampolines {
+    fn name(&self) -> &'static str {
+        "trampolines"
+    }
+
+    /// retargets the push of every chosen `push <label>, jump` or `push <label>, jumpi` to the first
+    /// trampoline of a new chain. the hops go right behind instructions that end execution, where
+    /// nothing falls into them, or behind the code; data is left where it is.
+    fn run(&mut self, ir: &mut Ir, ctx: &mut Ctx) {
+        let spots: Vec<Option<usize>> = ir
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(idx, item)| {
+                matches!(item, Item::Op { .. })
+                    && item.is_terminator()
+                    && !matches!(ir.items.get(idx + 1), Some(Item::Data(_)))
+            })
+            .map(|(idx, _)| Some(idx))
+            .chain([None])
+            .collect();
+
+        let mut hops: BTreeMap<Option<usize>, Vec<Item>> = BTreeMap::new();
+        let mut routed = 0;
+        for idx in 0..ir.items.len() {
+            let next = ir.items.get(idx + 1);
+            if !(is(next, op::JUMP) || is(next, op::JUMPI)) {
+                continue;
+            }
+            let Item::PushLabel {
+                label: target,
+                addend: 0,
+                ..
+            } = ir.items[idx]
+            else {
+                continue;
+            };
+            if !ctx.rng.gen_bool(self.probability) {
+                continue;
+            }
+            let chaotic_val = chaotic_map((ctx.chaotic_seed + ctx.rng.gen::<f64>()) % 1.0);
+            let count = 1 + ((chaotic_val * MAX_HOPS as f64) as usize).min(MAX_HOPS - 1);
+            let labels: Vec<Label> = (0..count).map(|_| ir.new_label()).collect();
+            for (hop, &label) in labels.iter().enumerate() {
+                let next = labels.get(hop + 1).copied().unwrap_or(target);
+                let spot = spots[ctx.rng.gen_range(0..spots.len())];
+                hops.entry(spot)
+                    .or_default()
+                    .extend(trampoline(label, next));
+            }
+            if let Item::PushLabel { label, .. } = &mut ir.items[idx] {
+                *label = labels[0];
+            }
+            routed += 1;
+        }
+
+        if routed > 0 {
+            let original = std::mem::take(&mut ir.items);
+            let mut items = Vec::with_capacity(original.len() + routed * 4);
+            for (idx, item) in original.into_iter().enumerate() {
+                items.push(item);
+                if let Some(hops) = hops.remove(&Some(idx)) {
+                    items.extend(hops);
+                }
+            }
+            ir.items = items;
+            if let Some(hops) = hops.remove(&None) {
+                ir.fence();
+                ir.items.extend(hops);
+            }
+        }
+        ctx.record(self.name(), routed);
+    }
+
+    fn scale_back(&mut self) -> bool {
+        super::halve(&mut self.probability)
+    }
+}
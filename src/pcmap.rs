@@ -0,0 +1,112 @@
+/// program counter maps for the ebo obfuscator.
+/// a revert, a trace, or a coverage report of the deployed contract names program counters of the
+/// obfuscated code. `ebo obfuscate --pc-map` writes, next to the output, which offsets of the output
+/// every instruction of the input was laid out at, and which instruction of the input every
+/// instruction of the output comes from, so that tooling can translate them back to the original
+/// code.
+///
+/// passes duplicate and move instructions, so an input instruction has as many offsets in the output
+/// as it has copies there, and none if a pass replaced it. the pushes of jump targets are rewritten by
+/// the assembler and count as replaced, as do pushes `push-width` re-encodes. code the passes add maps back to the closest input
+/// instruction laid out in front of it, marked as inexact, and code in front of every input
+/// instruction maps back to nothing. offsets are of the runtime code in both directions.
+use crate::error::EboError;
+use crate::reloc::{Item, Program};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// the map of code written to `output`, e.g. `Token.pcmap.json` for `Token.bin`.
+pub fn path(output: &Path) -> PathBuf {
+    output.with_extension("pcmap.json")
+}
+
+/// the input instruction an output instruction comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Origin {
+    /// offset of the instruction in the input.
+    pub pc: usize,
+    /// whether the output instruction is a copy of it, rather than code added after it.
+    pub exact: bool,
+}
+
+/// offsets of the input and of the output mapped to each other.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PcMap {
+    /// offsets of the copies in the output of every input instruction that has any, by its offset.
+    pub forward: BTreeMap<usize, Vec<usize>>,
+    /// the input instruction of every output instruction that maps back to one, by its offset.
+    pub inverse: BTreeMap<usize, Origin>,
+}
+
+impl PcMap {
+    /// the map of `program`, the final program of a run, to the input it was lifted from.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::pcmap::PcMap;
+    /// use ebo::reloc::Program;
+    ///
+    /// let bytecode = [0x60, 0x01, 0x60, 0x02, 0x01, 0x00]; // PUSH1 1, PUSH1 2, ADD, STOP
+    /// let map = PcMap::new(&Program::lift(&bytecode)).unwrap();
+    /// assert_eq!(map.obfuscated(4), [4]);
+    /// assert_eq!(map.original(5).unwrap().pc, 5);
+    /// ```
+    ///
+    /// # returns
+    /// the map, or `EboError::Relocation` if the program cannot be laid out.
+    pub fn new(program: &Program) -> Result<Self, EboError> {
+        let mut map = PcMap::default();
+        let mut last = None;
+        for (item, pc) in program.flat_items().into_iter().zip(program.offsets()?) {
+            let origin = match item {
+                Item::Op {
+                    origin: Some(origin),
+                    ..
+                } => {
+                    map.forward.entry(*origin).or_default().push(pc);
+                    last = Some(*origin);
+                    Origin {
+                        pc: *origin,
+                        exact: true,
+                    }
+                }
+                Item::Op { .. } | Item::PushLabel { .. } => match last {
+                    Some(origin) => Origin {
+                        pc: origin,
+                        exact: false,
+                    },
+                    None => continue,
+                },
+                Item::Mark(_) | Item::Data(_) | Item::Frozen(_) => continue,
+            };
+            map.inverse.insert(pc, origin);
+        }
+        Ok(map)
+    }
+
+    /// offsets of the copies of the input instruction at `pc` in the output, empty if it has none.
+    pub fn obfuscated(&self, pc: usize) -> &[usize] {
+        self.forward.get(&pc).map_or(&[], Vec::as_slice)
+    }
+
+    /// the input instruction the output instruction covering `pc` comes from, which also resolves
+    /// offsets inside push immediates. `None` for code in front of every input instruction.
+    pub fn original(&self, pc: usize) -> Option<Origin> {
+        self.inverse
+            .range(..=pc)
+            .next_back()
+            .map(|(_, &origin)| origin)
+    }
+
+    /// parses a map written by `to_json`.
+    pub fn parse(json: &str) -> Result<Self, EboError> {
+        serde_json::from_str(json).map_err(|err| EboError::parse("pc map", err))
+    }
+
+    /// the map as pretty-printed json, with offsets in decimal.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a pc map always serializes")
+    }
+}
@@ -0,0 +1,188 @@
+/// obfuscation plans for the ebo obfuscator.
+/// what `ebo obfuscate --dry-run` prints instead of writing the output: which passes change each
+/// basic block of the input, and how much code and static gas every block gains, so the impact of a
+/// configuration can be reviewed before a build is made with it.
+///
+/// passes move and split code freely, so a block is whatever the obfuscated program holds from one
+/// of its original instructions up to the first instruction of a different block. code the passes
+/// add counts towards the block of the original instruction in front of it, and code in front of
+/// every original instruction towards the first block.
+use crate::cfg::Cfg;
+use crate::error::EboError;
+use crate::evm::op;
+use crate::gas::{static_cost, Baseline};
+use crate::reloc::{Item, Program};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+/// what the passes do to one basic block of the input.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BlockPlan {
+    /// byte range of the block in the input.
+    pub range: Range<usize>,
+    /// passes that change the block, in the order they first do.
+    pub passes: Vec<&'static str>,
+    /// bytes the block gains; label pushes count with their declared width.
+    pub size_delta: isize,
+    /// static gas the instructions laid out for the block gain, which bounds what one pass through
+    /// the block gains, see `gas::static_cost`.
+    pub gas_delta: i64,
+}
+
+/// what an obfuscation run does, block by block.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Plan {
+    /// seed of the run.
+    pub seed: u64,
+    /// size of the input and of the output, in bytes.
+    pub size: (usize, usize),
+    /// largest relative gas overhead on a path through the input, see `Baseline::overhead`.
+    pub gas_overhead: f64,
+    /// number of transformations applied by each pass.
+    pub passes: BTreeMap<&'static str, usize>,
+    /// every basic block of the input, in order.
+    pub blocks: Vec<BlockPlan>,
+}
+
+/// the items of `program` laid out for each block of `cfg`.
+fn attribute<'a>(cfg: &Cfg, program: &'a Program) -> Vec<Vec<&'a Item>> {
+    let mut blocks = vec![Vec::new(); cfg.blocks.len().max(1)];
+    let mut current = 0;
+    for item in program.flat_items() {
+        if let Item::Op {
+            origin: Some(origin),
+            ..
+        } = item
+        {
+            if let Some((_, &idx)) = cfg.by_offset.range(..=origin).next_back() {
+                current = idx;
+            }
+        }
+        blocks[current].push(item);
+    }
+    blocks
+}
+
+/// bytes and static gas of `items`.
+fn measure(items: &[&Item]) -> (isize, i64) {
+    items.iter().fold((0, 0), |(size, gas), item| match item {
+        Item::Op {
+            opcode, immediate, ..
+        } => (
+            size + 1 + immediate.len() as isize,
+            gas + static_cost(*opcode) as i64,
+        ),
+        Item::PushLabel { width, .. } => (
+            size + 1 + *width as isize,
+            gas + static_cost(op::PUSH1) as i64,
+        ),
+        Item::Data(bytes) => (size + bytes.len() as isize, gas),
+        Item::Mark(_) | Item::Frozen(_) => (size, gas),
+    })
+}
+
+impl Plan {
+    /// the plan of a run on `bytecode`, which was lifted to `lifted` and left by every pass as
+    /// `trail` records, see `Ctx::trail`.
+    ///
+    /// # returns
+    /// the plan, or `EboError::Relocation` if the last program of the trail cannot be assembled.
+    pub fn new(
+        bytecode: &[u8],
+        lifted: &Program,
+        trail: &[(&'static str, Program)],
+        stats: &BTreeMap<&'static str, usize>,
+        seed: u64,
+    ) -> Result<Self, EboError> {
+        let cfg = Cfg::new(bytecode);
+        let mut blocks: Vec<BlockPlan> = cfg
+            .blocks
+            .iter()
+            .map(|block| BlockPlan {
+                range: block.range.clone(),
+                passes: Vec::new(),
+                size_delta: 0,
+                gas_delta: 0,
+            })
+            .collect();
+
+        let mut before = attribute(&cfg, lifted);
+        for (name, program) in trail {
+            let after = attribute(&cfg, program);
+            for (block, (old, new)) in blocks.iter_mut().zip(before.iter().zip(&after)) {
+                if old != new && !block.passes.contains(name) {
+                    block.passes.push(name);
+                }
+            }
+            before = after;
+        }
+        let last = trail.last().map_or(lifted, |(_, program)| program);
+        let original = attribute(&cfg, lifted);
+        for (block, (old, new)) in blocks.iter_mut().zip(original.iter().zip(&before)) {
+            let ((old_size, old_gas), (new_size, new_gas)) = (measure(old), measure(new));
+            block.size_delta = new_size - old_size;
+            block.gas_delta = new_gas - old_gas;
+        }
+
+        Ok(Plan {
+            seed,
+            size: (bytecode.len(), last.assemble()?.len()),
+            gas_overhead: Baseline::new(lifted).overhead(last),
+            passes: stats.clone(),
+            blocks,
+        })
+    }
+
+    /// the plan as pretty-printed json.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a plan always serializes")
+    }
+}
+
+impl fmt::Display for Plan {
+    /// a summary line, the passes that applied anything, and a row for every block that changes.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (before, after) = self.size;
+        writeln!(
+            f,
+            "seed {}: {} -> {} bytes ({:+}), gas overhead up to {:+.1}% on a path",
+            self.seed,
+            before,
+            after,
+            after as isize - before as isize,
+            self.gas_overhead * 100.0
+        )?;
+        let applied: Vec<String> = self
+            .passes
+            .iter()
+            .filter(|(_, &count)| count > 0)
+            .map(|(name, count)| format!("{} {}", name, count))
+            .collect();
+        writeln!(f, "applied: {}", applied.join(", "))?;
+        writeln!(f)?;
+        writeln!(f, "{:<16} {:>7} {:>7}  passes", "block", "size", "gas")?;
+        let changed: Vec<&BlockPlan> = self
+            .blocks
+            .iter()
+            .filter(|block| !block.passes.is_empty())
+            .collect();
+        for block in &changed {
+            writeln!(
+                f,
+                "{:<16} {:>+7} {:>+7}  {}",
+                format!("{:#06x}..{:#06x}", block.range.start, block.range.end),
+                block.size_delta,
+                block.gas_delta,
+                block.passes.join(", ")
+            )?;
+        }
+        write!(
+            f,
+            "{} of {} blocks unchanged",
+            self.blocks.len() - changed.len(),
+            self.blocks.len()
+        )
+    }
+}
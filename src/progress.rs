@@ -0,0 +1,183 @@
+/// progress bars for long obfuscation runs.
+/// contracts near the size limit, many rounds, and seed searches take long enough that a silent
+/// terminal looks stuck. `Progress` is a tracing layer that follows the spans the library enters
+/// anyway, `candidate` for every seed a search tries, `obfuscate` for one run, and `pass` for every
+/// pass of every round, and draws them as indicatif bars on standard error: one over the candidates
+/// of a search, and one over the passes of the current run, naming the pass that is running and the
+/// number of basic blocks it works on.
+///
+/// the bars are only drawn when standard error is a terminal. log lines written through
+/// `Progress` as a `MakeWriter` are printed above the bars instead of through them.
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// template of both bars.
+const TEMPLATE: &str = "{spinner} [{elapsed_precise}] {bar:30} {pos}/{len} {msg}";
+
+/// draws the progress of obfuscation runs from their spans.
+#[derive(Clone)]
+pub struct Progress {
+    bars: MultiProgress,
+    state: Arc<Mutex<State>>,
+}
+
+/// the bars drawn so far.
+#[derive(Default)]
+struct State {
+    candidates: Option<ProgressBar>,
+    passes: Option<ProgressBar>,
+}
+
+/// the numeric and text fields of a span.
+#[derive(Default)]
+struct Fields {
+    numbers: Vec<(&'static str, u64)>,
+    name: Option<String>,
+}
+
+impl Fields {
+    fn number(&self, field: &str) -> u64 {
+        self.numbers
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map_or(0, |&(_, value)| value)
+    }
+}
+
+impl Visit for Fields {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.numbers.push((field.name(), value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.numbers.push((field.name(), value.max(0) as u64));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.name = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, _: &Field, _: &dyn fmt::Debug) {}
+}
+
+impl Progress {
+    /// a layer drawing to standard error, or drawing nothing if `visible` is false.
+    pub fn new(visible: bool) -> Self {
+        let target = match visible {
+            true => ProgressDrawTarget::stderr(),
+            false => ProgressDrawTarget::hidden(),
+        };
+        Progress {
+            bars: MultiProgress::with_draw_target(target),
+            state: Arc::default(),
+        }
+    }
+
+    /// a new bar of `length` steps below the others.
+    fn bar(&self, length: u64) -> ProgressBar {
+        let style = ProgressStyle::with_template(TEMPLATE).expect("the template is valid");
+        self.bars.add(ProgressBar::new(length).with_style(style))
+    }
+
+    /// how many passes of the current run have finished, and how many it has, once a run started.
+    pub fn passes(&self) -> Option<(u64, u64)> {
+        let state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        let bar = state.passes.as_ref()?;
+        Some((bar.position(), bar.length().unwrap_or(0)))
+    }
+
+    /// removes every bar, once the run is over.
+    pub fn finish(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        for bar in [state.candidates.take(), state.passes.take()]
+            .into_iter()
+            .flatten()
+        {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Progress {
+    fn on_new_span(&self, attrs: &Attributes<'_>, _: &Id, _: Context<'_, S>) {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        match attrs.metadata().name() {
+            "candidate" => {
+                let bar = state
+                    .candidates
+                    .get_or_insert_with(|| self.bar(fields.number("candidates")));
+                bar.set_message(format!("seed {}", fields.number("seed")));
+            }
+            // every run starts over, so a search shows the passes of the candidate it is on
+            "obfuscate" => {
+                let length = fields.number("rounds") * fields.number("passes");
+                let bar = state.passes.get_or_insert_with(|| self.bar(length));
+                bar.set_length(length);
+                bar.set_position(0);
+            }
+            "pass" => {
+                if let Some(bar) = &state.passes {
+                    bar.set_message(format!(
+                        "{} ({} blocks)",
+                        fields.name.as_deref().unwrap_or_default(),
+                        fields.number("blocks")
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        let bar = match span.name() {
+            "candidate" => &state.candidates,
+            "pass" => &state.passes,
+            _ => return,
+        };
+        if let Some(bar) = bar {
+            bar.inc(1);
+        }
+    }
+}
+
+/// standard error, written to while the bars are cleared.
+pub struct Writer {
+    bars: MultiProgress,
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bars.suspend(|| io::stderr().write_all(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for Progress {
+    type Writer = Writer;
+
+    fn make_writer(&'a self) -> Writer {
+        Writer {
+            bars: self.bars.clone(),
+        }
+    }
+}
@@ -0,0 +1,622 @@
+/// relocation layer for the ebo obfuscator.
+/// lifts raw bytecode into a stream of items in which static jump targets are symbolic labels instead
+/// of fixed offsets, so transformations can insert, remove, or move code and still have every jump
+/// patched to the right jumpdest when the stream is assembled back into bytes. this is the
+/// intermediate representation every pass operates on (`passes::Ir`): instructions, label pushes and
+/// marks, and data pseudo-items, with `Program::assemble` as the only place bytes are encoded.
+use crate::error::EboError;
+use crate::evm::{disassemble, hex_bytes, is_terminator, op, push_size};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// symbolic location inside a program, resolved to a byte offset at assembly time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Label(usize);
+
+/// a single element of a lifted program.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Item {
+    /// an executable instruction. `origin` is its offset in the input bytecode, or `None` when the
+    /// instruction was synthesized by the obfuscator.
+    Op {
+        opcode: u8,
+        #[serde(with = "hex_bytes")]
+        immediate: Vec<u8>,
+        origin: Option<usize>,
+    },
+    /// a push whose value is the address of `label` plus `addend`. encoded with at least `width`
+    /// immediate bytes; the assembler widens it if the resolved address does not fit.
+    PushLabel {
+        label: Label,
+        addend: usize,
+        width: usize,
+    },
+    /// binds `label` to the offset of the next emitted byte.
+    Mark(Label),
+    /// bytes emitted verbatim and never treated as code (unreachable regions, constant pools).
+    Data(#[serde(with = "hex_bytes")] Vec<u8>),
+    /// code that must come out exactly as it went in. passes treat the region as a single opaque
+    /// item and never look inside it, but the region may be moved as a whole, and the labels it
+    /// marks and pushes are still laid out and patched by the assembler.
+    Frozen(Vec<Item>),
+}
+
+impl Item {
+    /// synthesized instruction without an immediate.
+    pub fn op(opcode: u8) -> Self {
+        Item::Op {
+            opcode,
+            immediate: Vec::new(),
+            origin: None,
+        }
+    }
+
+    /// synthesized instructions decoded from raw `code`.
+    pub fn sequence(code: &[u8]) -> Vec<Self> {
+        disassemble(code)
+            .into_iter()
+            .map(|ins| Item::Op {
+                opcode: ins.opcode,
+                immediate: ins.immediate,
+                origin: None,
+            })
+            .collect()
+    }
+
+    /// returns true if the item ends execution of the code in front of it: a terminating
+    /// instruction, or a frozen region whose last item is one.
+    pub fn is_terminator(&self) -> bool {
+        match self {
+            Item::Op { opcode, .. } => is_terminator(*opcode),
+            Item::Frozen(region) => region.last().is_some_and(Item::is_terminator),
+            _ => false,
+        }
+    }
+
+    /// synthesized push of `value`, using the smallest push opcode able to hold it.
+    pub fn push(value: &[u8]) -> Self {
+        let value = if value.is_empty() { &[0][..] } else { value };
+        Item::Op {
+            opcode: op::PUSH1 + (value.len() - 1) as u8,
+            immediate: value.to_vec(),
+            origin: None,
+        }
+    }
+}
+
+/// a block of items with its labels numbered from 0 in the order they appear and every
+/// instruction's origin replaced by its position in the block, so that blocks which differ only in
+/// where they were laid out look the same. transforms that neither create labels nor look inside
+/// frozen regions run on the canonical form, and `Canonical::restore` puts the labels and origins
+/// of the block back into their output, see `passes::Ctx::transform_blocks`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Canonical {
+    /// the block in canonical form. frozen regions are left as they are.
+    pub items: Vec<Item>,
+    /// the label of the block numbered by each canonical label.
+    labels: Vec<Label>,
+    /// the origin of the instruction at each position of the block.
+    origins: Vec<Option<usize>>,
+}
+
+impl Canonical {
+    /// the canonical form of `block`.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::reloc::{Canonical, Program};
+    ///
+    /// // the same code at two places of a program
+    /// let program = Program::lift(&[0x5B, 0x60, 0x01, 0x00, 0x5B, 0x60, 0x01, 0x00]);
+    /// let (first, second) = program.items.split_at(program.items.len() / 2);
+    /// let (first, second) = (Canonical::new(first), Canonical::new(second));
+    /// assert_eq!(first.items, second.items);
+    /// assert_eq!(second.restore(second.items.clone()), program.items[program.items.len() / 2..]);
+    /// ```
+    pub fn new(block: &[Item]) -> Self {
+        let mut labels: Vec<Label> = Vec::new();
+        let mut rename = |label: &Label| match labels.iter().position(|known| known == label) {
+            Some(idx) => Label(idx),
+            None => {
+                labels.push(*label);
+                Label(labels.len() - 1)
+            }
+        };
+        let mut origins = Vec::with_capacity(block.len());
+        let items = block
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| match item {
+                Item::Op {
+                    opcode,
+                    immediate,
+                    origin,
+                } => {
+                    origins.push(*origin);
+                    Item::Op {
+                        opcode: *opcode,
+                        immediate: immediate.clone(),
+                        origin: Some(idx),
+                    }
+                }
+                Item::PushLabel {
+                    label,
+                    addend,
+                    width,
+                } => {
+                    origins.push(None);
+                    Item::PushLabel {
+                        label: rename(label),
+                        addend: *addend,
+                        width: *width,
+                    }
+                }
+                Item::Mark(label) => {
+                    origins.push(None);
+                    Item::Mark(rename(label))
+                }
+                item => {
+                    origins.push(None);
+                    item.clone()
+                }
+            })
+            .collect();
+        Canonical {
+            items,
+            labels,
+            origins,
+        }
+    }
+
+    /// `items`, the output of a transform of the canonical form, with the labels and origins of
+    /// the block. instructions the transform added keep no origin.
+    ///
+    /// # panics
+    /// if `items` uses a label the block does not, which a transform cannot make up.
+    pub fn restore(&self, items: Vec<Item>) -> Vec<Item> {
+        let label = |label: Label| {
+            *self
+                .labels
+                .get(label.0)
+                .expect("block transforms do not create labels")
+        };
+        items
+            .into_iter()
+            .map(|item| match item {
+                Item::Op {
+                    opcode,
+                    immediate,
+                    origin,
+                } => Item::Op {
+                    opcode,
+                    immediate,
+                    origin: origin.and_then(|idx| self.origins.get(idx).copied().flatten()),
+                },
+                Item::PushLabel {
+                    label: canonical,
+                    addend,
+                    width,
+                } => Item::PushLabel {
+                    label: label(canonical),
+                    addend,
+                    width,
+                },
+                Item::Mark(canonical) => Item::Mark(label(canonical)),
+                item => item,
+            })
+            .collect()
+    }
+}
+
+/// bytecode lifted into relocatable items.
+///
+/// serializes to `{"items": [...]}`, so other tools can inspect a lifted program as json, rewrite
+/// it, and hand it back to be assembled.
+///
+/// # example
+/// ```
+/// use ebo::reloc::Program;
+///
+/// let bytecode = [0x60, 0x04, 0x56, 0x00, 0x5B, 0x00]; // PUSH1 4, JUMP, STOP, JUMPDEST, STOP
+/// let json = serde_json::to_string(&Program::lift(&bytecode)).unwrap();
+/// let program: Program = serde_json::from_str(&json).unwrap();
+/// assert_eq!(program.assemble().unwrap(), bytecode);
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(try_from = "Listing")]
+pub struct Program {
+    /// items in emission order.
+    pub items: Vec<Item>,
+    /// number of labels allocated so far, used to hand out fresh labels.
+    #[serde(skip)]
+    next_label: usize,
+}
+
+/// the serialized form of a `Program`, checked before it becomes one.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Listing {
+    items: Vec<Item>,
+}
+
+impl TryFrom<Listing> for Program {
+    type Error = String;
+
+    /// rejects items that cannot be encoded, and hands out labels after the largest one in use so
+    /// fresh labels never collide with those of the listing.
+    fn try_from(listing: Listing) -> Result<Self, Self::Error> {
+        let program = Program {
+            items: listing.items,
+            next_label: 0,
+        };
+        let mut next_label = 0;
+        for item in program.flat_items() {
+            match item {
+                Item::Op {
+                    opcode, immediate, ..
+                } if immediate.len() != push_size(*opcode) => {
+                    return Err(format!(
+                        "opcode {:#04x} takes {} immediate bytes, not {}",
+                        opcode,
+                        push_size(*opcode),
+                        immediate.len()
+                    ));
+                }
+                Item::PushLabel { width, .. } if *width > 32 => {
+                    return Err(format!("a label push cannot be {} bytes wide", width));
+                }
+                Item::PushLabel { label, .. } | Item::Mark(label) => {
+                    next_label = next_label.max(label.0 + 1);
+                }
+                _ => {}
+            }
+        }
+        Ok(Program {
+            next_label,
+            ..program
+        })
+    }
+}
+
+impl Program {
+    /// lifts raw bytecode into a relocatable program.
+    ///
+    /// every jumpdest gets a label, and pushes that refer to a jumpdest become `PushLabel` items: a push
+    /// directly consumed by jump/jumpi is always treated as a jump target, and a push of two or more
+    /// bytes whose value is a jumpdest offset is treated as a code pointer only when its block ends in
+    /// an unconditional jump, the way solc pushes an internal return address before jumping into a
+    /// subroutine. any other push keeps its value as a plain constant. bytes that are unreachable by linear sweep (after a terminator and
+    /// before the next jumpdest) are kept as opaque data so that trailing metadata and embedded
+    /// constants are never rewritten.
+    ///
+    /// # arguments
+    /// * `bytecode` - slice of raw evm bytecode bytes.
+    ///
+    /// # returns
+    /// a `Program` that assembles back to `bytecode` when left untouched.
+    pub fn lift(bytecode: &[u8]) -> Self {
+        let mut instructions = disassemble(bytecode);
+        let jumpdests: HashSet<usize> = instructions
+            .iter()
+            .filter(|ins| ins.opcode == op::JUMPDEST)
+            .map(|ins| ins.offset)
+            .collect();
+
+        // whether the block each instruction sits in ends in a jump, i.e. is a call site
+        let mut calls = vec![false; instructions.len()];
+        for idx in (0..instructions.len()).rev() {
+            let opcode = instructions[idx].opcode;
+            calls[idx] = match instructions.get(idx + 1) {
+                _ if opcode == op::JUMP => true,
+                _ if opcode == op::JUMPI || is_terminator(opcode) => false,
+                Some(next) if next.opcode != op::JUMPDEST => calls[idx + 1],
+                _ => false,
+            };
+        }
+
+        let mut program = Program::default();
+        let mut labels: HashMap<usize, Label> = HashMap::new();
+        let mut live = true;
+
+        for idx in 0..instructions.len() {
+            let feeds_jump = instructions
+                .get(idx + 1)
+                .is_some_and(|next| matches!(next.opcode, op::JUMP | op::JUMPI));
+            // immediates are moved into the program rather than copied
+            let ins = &mut instructions[idx];
+            if ins.opcode == op::JUMPDEST {
+                live = true;
+                let label = *labels
+                    .entry(ins.offset)
+                    .or_insert_with(|| program.new_label());
+                program.items.push(Item::Mark(label));
+            }
+
+            let truncated = ins.immediate.len() < push_size(ins.opcode);
+            if !live || truncated {
+                let mut bytes = vec![ins.opcode];
+                bytes.extend_from_slice(&ins.immediate);
+                match program.items.last_mut() {
+                    Some(Item::Data(data)) => data.extend(bytes),
+                    _ => program.items.push(Item::Data(bytes)),
+                }
+                continue;
+            }
+
+            let width = ins.immediate.len();
+            let target = (width > 0 && width <= 8)
+                .then(|| {
+                    ins.immediate
+                        .iter()
+                        .fold(0usize, |acc, &b| (acc << 8) | b as usize)
+                })
+                .filter(|value| jumpdests.contains(value));
+            match target {
+                Some(target) if feeds_jump || (width >= 2 && calls[idx]) => {
+                    let label = *labels.entry(target).or_insert_with(|| program.new_label());
+                    program.items.push(Item::PushLabel {
+                        label,
+                        addend: 0,
+                        width,
+                    });
+                }
+                _ => program.items.push(Item::Op {
+                    opcode: ins.opcode,
+                    immediate: std::mem::take(&mut ins.immediate),
+                    origin: Some(ins.offset),
+                }),
+            }
+
+            if is_terminator(ins.opcode) {
+                live = false;
+            }
+        }
+
+        program
+    }
+
+    /// wraps the items of a freshly lifted program that overlap one of `ranges` (offsets into the
+    /// input bytecode) into `Frozen` regions, so no pass rewrites them. an instruction is frozen if
+    /// any of its bytes lies inside a range, and consecutive frozen items share one region.
+    ///
+    /// # arguments
+    /// * `ranges` - byte ranges of the input bytecode to keep verbatim.
+    pub fn freeze(&mut self, ranges: &[Range<usize>]) {
+        if ranges.is_empty() {
+            return;
+        }
+        let mut items: Vec<Item> = Vec::with_capacity(self.items.len());
+        let mut pc = 0;
+        for item in std::mem::take(&mut self.items) {
+            let start = pc;
+            pc += match &item {
+                Item::Op { immediate, .. } => 1 + immediate.len(),
+                Item::PushLabel { width, .. } => 1 + width,
+                Item::Mark(_) | Item::Frozen(_) => 0,
+                Item::Data(data) => data.len(),
+            };
+            let frozen = ranges
+                .iter()
+                .any(|range| range.contains(&start) || (start < range.start && pc > range.start));
+            match items.last_mut() {
+                Some(Item::Frozen(region)) if frozen => region.push(item),
+                _ if frozen => items.push(Item::Frozen(vec![item])),
+                _ => items.push(item),
+            }
+        }
+        self.items = items;
+    }
+
+    /// allocates a fresh label that is not yet bound to any position.
+    pub fn new_label(&mut self) -> Label {
+        let label = Label(self.next_label);
+        self.next_label += 1;
+        label
+    }
+
+    /// makes sure execution cannot fall through from the current end of the program into whatever is
+    /// appended next, by adding a stop unless the last item already ends execution. a stop keeps the
+    /// semantics of running off the end of the code.
+    pub fn fence(&mut self) {
+        if !self.items.last().is_some_and(Item::is_terminator) {
+            self.items.push(Item::op(op::STOP));
+        }
+    }
+
+    /// appends the items of `other`, with its labels renumbered after those of `self` so the two
+    /// never collide. origins are kept as they are, offsets of the input `other` was lifted from.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::reloc::Program;
+    ///
+    /// let code = [0x60, 0x04, 0x56, 0xFE, 0x5B, 0x00]; // PUSH1 4, JUMP, INVALID, JUMPDEST, STOP
+    /// let mut program = Program::lift(&code);
+    /// program.append(Program::lift(&code));
+    /// // the jump of the second copy goes to its own jumpdest
+    /// assert_eq!(program.assemble().unwrap()[6..8], [0x60, 0x0A]);
+    /// ```
+    pub fn append(&mut self, other: Program) {
+        fn relabel(items: Vec<Item>, base: usize) -> Vec<Item> {
+            items
+                .into_iter()
+                .map(|item| match item {
+                    Item::PushLabel {
+                        label,
+                        addend,
+                        width,
+                    } => Item::PushLabel {
+                        label: Label(label.0 + base),
+                        addend,
+                        width,
+                    },
+                    Item::Mark(label) => Item::Mark(Label(label.0 + base)),
+                    Item::Frozen(region) => Item::Frozen(relabel(region, base)),
+                    item => item,
+                })
+                .collect()
+        }
+        let base = self.next_label;
+        self.items.extend(relabel(other.items, base));
+        self.next_label += other.next_label;
+    }
+
+    /// items in emission order, with every frozen region replaced by its contents.
+    pub fn flat_items(&self) -> Vec<&Item> {
+        let mut items = Vec::with_capacity(self.items.len());
+        flatten(&self.items, &mut items);
+        items
+    }
+
+    /// assembles the program into bytecode, resolving every label and patching every `PushLabel`.
+    ///
+    /// label pushes keep their declared width unless the resolved address needs more bytes, in which
+    /// case the push is widened and the layout recomputed until it is stable. widths only ever grow,
+    /// so this terminates.
+    ///
+    /// # returns
+    /// the bytecode, or `EboError::Relocation` if a `PushLabel` refers to a label that no `Mark`
+    /// binds, which indicates a bug in the transformation that produced the program.
+    pub fn assemble(&self) -> Result<Vec<u8>, EboError> {
+        let items = self.flat_items();
+        let (widths, addresses) = resolve_widths(&items)?;
+
+        let mut bytecode = Vec::new();
+        for (item, &width) in items.iter().zip(&widths) {
+            match item {
+                Item::Op {
+                    opcode, immediate, ..
+                } => {
+                    bytecode.push(*opcode);
+                    bytecode.extend_from_slice(immediate);
+                }
+                Item::PushLabel { label, addend, .. } => {
+                    let value = resolve(&addresses, *label, *addend)?.to_be_bytes();
+                    bytecode.push(op::PUSH1 + (width - 1) as u8);
+                    bytecode.extend(std::iter::repeat_n(0, width.saturating_sub(value.len())));
+                    bytecode.extend_from_slice(&value[value.len().saturating_sub(width)..]);
+                }
+                Item::Mark(_) => {}
+                Item::Data(data) => bytecode.extend_from_slice(data),
+                Item::Frozen(_) => unreachable!("frozen regions are flattened before assembly"),
+            }
+        }
+        Ok(bytecode)
+    }
+
+    /// the offset in the assembled code of every item of `flat_items`, in the same order. a mark is
+    /// at the offset of the byte after it.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::reloc::Program;
+    ///
+    /// // PUSH1 4, JUMP, INVALID, JUMPDEST, STOP
+    /// let program = Program::lift(&[0x60, 0x04, 0x56, 0xFE, 0x5B, 0x00]);
+    /// let offsets = program.offsets().unwrap();
+    /// assert_eq!(offsets.len(), program.flat_items().len());
+    /// assert_eq!(offsets[..2], [0, 2]);
+    /// ```
+    pub fn offsets(&self) -> Result<Vec<usize>, EboError> {
+        let items = self.flat_items();
+        let (widths, _) = resolve_widths(&items)?;
+        let mut pc = 0;
+        Ok(items
+            .iter()
+            .zip(&widths)
+            .map(|(item, width)| {
+                let offset = pc;
+                pc += size(item, *width);
+                offset
+            })
+            .collect())
+    }
+}
+
+/// the width of every label push of `items`, grown until every address fits, and the address of
+/// every label with those widths.
+fn resolve_widths(items: &[&Item]) -> Result<(Vec<usize>, HashMap<Label, usize>), EboError> {
+    let mut widths: Vec<usize> = items
+        .iter()
+        .map(|item| match item {
+            Item::PushLabel { width, .. } => *width,
+            _ => 0,
+        })
+        .collect();
+    loop {
+        let addresses = layout(items, &widths);
+        let mut grown = false;
+        for (item, width) in items.iter().zip(widths.iter_mut()) {
+            if let Item::PushLabel { label, addend, .. } = item {
+                let needed = bytes_needed(resolve(&addresses, *label, *addend)?);
+                if needed > *width {
+                    *width = needed;
+                    grown = true;
+                }
+            }
+        }
+        if !grown {
+            return Ok((widths, addresses));
+        }
+    }
+}
+
+/// bytes `item` is assembled into, for a label push of `width` bytes.
+fn size(item: &Item, width: usize) -> usize {
+    match item {
+        Item::Op { immediate, .. } => 1 + immediate.len(),
+        Item::PushLabel { .. } => 1 + width,
+        Item::Mark(_) => 0,
+        Item::Data(data) => data.len(),
+        Item::Frozen(_) => unreachable!("frozen regions are flattened before layout"),
+    }
+}
+
+/// appends the items of `items` to `out`, replacing every frozen region by its contents.
+fn flatten<'a>(items: &'a [Item], out: &mut Vec<&'a Item>) {
+    for item in items {
+        match item {
+            Item::Frozen(region) => flatten(region, out),
+            _ => out.push(item),
+        }
+    }
+}
+
+/// computes the offset of every marked label given the current label push widths.
+fn layout(items: &[&Item], widths: &[usize]) -> HashMap<Label, usize> {
+    let mut addresses = HashMap::new();
+    let mut pc = 0;
+    for (item, width) in items.iter().zip(widths) {
+        if let Item::Mark(label) = item {
+            addresses.insert(*label, pc);
+        }
+        pc += size(item, *width);
+    }
+    addresses
+}
+
+/// looks up a label's resolved offset and adds `addend` to it.
+fn resolve(
+    addresses: &HashMap<Label, usize>,
+    label: Label,
+    addend: usize,
+) -> Result<usize, EboError> {
+    let Some(address) = addresses.get(&label) else {
+        return Err(EboError::Relocation(format!(
+            "{:?}: pushed but never marked",
+            label
+        )));
+    };
+    address.checked_add(addend).ok_or_else(|| {
+        EboError::Relocation(format!("{:?} + {:#x}: offset overflows", label, addend))
+    })
+}
+
+/// minimal number of bytes needed to encode `value` as a push immediate (at least one).
+fn bytes_needed(value: usize) -> usize {
+    ((usize::BITS - value.leading_zeros()) as usize)
+        .div_ceil(8)
+        .max(1)
+}
@@ -0,0 +1,173 @@
+/// gas reports for the ebo obfuscator.
+/// calls every external function of a contract on the original and the obfuscated bytecode in the
+/// embedded evm and sums the gas each side used, so the runtime cost of obfuscation can be stated
+/// per function instead of as a single estimate.
+use crate::abi;
+use crate::corpus::Sample;
+use crate::equivalence;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Serialize;
+use std::fmt;
+
+/// gas measured for the calls of one function.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Row {
+    /// signature of the function, or `(other)` for calls that match no function of the abi.
+    pub function: String,
+    /// number of calls made.
+    pub calls: usize,
+    /// total gas the original bytecode used, including the intrinsic cost of each transaction.
+    pub original: u64,
+    /// total gas the obfuscated bytecode used.
+    pub obfuscated: u64,
+    /// number of calls on which the two behave differently, which makes their gas meaningless.
+    pub divergent: usize,
+}
+
+impl Row {
+    /// an empty row for `function`.
+    fn new(function: String) -> Self {
+        Row {
+            function,
+            calls: 0,
+            original: 0,
+            obfuscated: 0,
+            divergent: 0,
+        }
+    }
+
+    /// extra gas of the obfuscated bytecode as a fraction of the original's (0.1 is 10%).
+    pub fn overhead(&self) -> f64 {
+        if self.original == 0 {
+            return 0.0;
+        }
+        self.obfuscated as f64 / self.original as f64 - 1.0
+    }
+
+    /// runs `calldata` on both binaries and adds the gas to the row.
+    fn measure(
+        &mut self,
+        original: &[u8],
+        obfuscated: &[u8],
+        calldata: &[u8],
+    ) -> anyhow::Result<()> {
+        let run = equivalence::compare(original, obfuscated, calldata)?;
+        self.calls += 1;
+        self.original += run.original.gas_used;
+        self.obfuscated += run.obfuscated.gas_used;
+        if run.divergence().is_some() {
+            self.divergent += 1;
+        }
+        Ok(())
+    }
+}
+
+/// a per-function gas comparison, printed as a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GasReport {
+    /// one row per function that was called, in abi order.
+    pub rows: Vec<Row>,
+}
+
+impl GasReport {
+    /// measures the gas of `original` and `obfuscated` for every function of `functions`.
+    ///
+    /// calls come from `samples` when any are given, each counted for the function whose selector
+    /// it starts with; functions without samples, or every function when there are none, get
+    /// `runs` calls with random arguments instead.
+    ///
+    /// # arguments
+    /// * `original` - runtime bytecode before obfuscation.
+    /// * `obfuscated` - runtime bytecode after obfuscation.
+    /// * `functions` - functions of the contract's abi.
+    /// * `samples` - calldata to replay, possibly empty.
+    /// * `runs` - number of generated calls for each function without samples.
+    /// * `seed` - seed of the argument generator.
+    pub fn measure(
+        original: &[u8],
+        obfuscated: &[u8],
+        functions: &[abi::Function],
+        samples: &[Sample],
+        runs: usize,
+        seed: u64,
+    ) -> anyhow::Result<Self> {
+        let mut rows: Vec<Row> = functions.iter().map(|f| Row::new(f.signature())).collect();
+        let mut other = Row::new("(other)".into());
+
+        let selectors: Vec<[u8; 4]> = functions.iter().map(abi::Function::selector).collect();
+        for sample in samples {
+            match selectors
+                .iter()
+                .position(|selector| sample.calldata.starts_with(selector))
+            {
+                Some(idx) => rows[idx].measure(original, obfuscated, &sample.calldata)?,
+                None => other.measure(original, obfuscated, &sample.calldata)?,
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        for (function, row) in functions.iter().zip(&mut rows) {
+            if row.calls > 0 {
+                continue;
+            }
+            for _ in 0..runs {
+                row.measure(original, obfuscated, &function.random_call(&mut rng))?;
+            }
+        }
+
+        rows.retain(|row| row.calls > 0);
+        if other.calls > 0 {
+            rows.push(other);
+        }
+        Ok(GasReport { rows })
+    }
+
+    /// all calls of the report added up.
+    pub fn total(&self) -> Row {
+        self.rows
+            .iter()
+            .fold(Row::new("total".into()), |total, row| Row {
+                calls: total.calls + row.calls,
+                original: total.original + row.original,
+                obfuscated: total.obfuscated + row.obfuscated,
+                divergent: total.divergent + row.divergent,
+                ..total
+            })
+    }
+}
+
+impl fmt::Display for GasReport {
+    /// one line per function with the average gas per call before and after obfuscation.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .rows
+            .iter()
+            .map(|row| row.function.len())
+            .max()
+            .unwrap_or(0)
+            .max("function".len());
+        writeln!(
+            f,
+            "{:<width$}  {:>6}  {:>10}  {:>10}  {:>9}",
+            "function", "calls", "original", "obfuscated", "overhead"
+        )?;
+        for row in self.rows.iter().chain([&self.total()]) {
+            let average = |gas: u64| gas / row.calls.max(1) as u64;
+            write!(
+                f,
+                "{:<width$}  {:>6}  {:>10}  {:>10}  {:>+8.1}%",
+                row.function,
+                row.calls,
+                average(row.original),
+                average(row.obfuscated),
+                row.overhead() * 100.0
+            )?;
+            if row.divergent > 0 {
+                write!(f, "  ({} calls diverge)", row.divergent)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
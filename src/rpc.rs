@@ -0,0 +1,83 @@
+/// json-rpc access for the ebo obfuscator.
+/// fetches the code deployed at an address from an ethereum node with `eth_getCode`, so on-chain
+/// contracts can be obfuscated or analyzed without copying their bytecode out by hand.
+use anyhow::{bail, Context};
+use serde_json::{json, Value};
+
+/// environment variable holding the rpc url when none is given, as in foundry.
+pub const RPC_URL_VAR: &str = "ETH_RPC_URL";
+
+/// parses a 20-byte address written in hex, with or without a `0x` prefix.
+pub fn parse_address(source: &str) -> anyhow::Result<[u8; 20]> {
+    let digits = source.trim().trim_start_matches("0x");
+    let bytes = hex::decode(digits).with_context(|| format!("invalid address '{}'", source))?;
+    bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("address '{}' is not 20 bytes long", source))
+}
+
+/// fetches the code deployed at `address`.
+///
+/// # arguments
+/// * `url` - http(s) url of the node.
+/// * `address` - account to read.
+/// * `block` - block number (decimal or hex) or tag such as `latest`.
+///
+/// # returns
+/// the code, or an error if the node cannot be reached, reports an error, or the account has no
+/// code.
+pub fn get_code(url: &str, address: &[u8; 20], block: &str) -> anyhow::Result<Vec<u8>> {
+    let block = block_tag(block);
+    let address = format!("0x{}", hex::encode(address));
+    let response = request(url, "eth_getCode", json!([address, block]))?;
+    let code = decode(&response)?;
+    if code.is_empty() {
+        bail!(
+            "{} has no code at block {}; is it an externally owned account?",
+            address,
+            block
+        );
+    }
+    Ok(code)
+}
+
+/// a block as json-rpc takes it: numbers in hex, tags such as `latest` as they are.
+pub fn block_tag(block: &str) -> String {
+    match block.parse::<u64>() {
+        Ok(number) => format!("{:#x}", number),
+        Err(_) => block.to_string(),
+    }
+}
+
+/// sends one json-rpc request and returns the response.
+fn request(url: &str, method: &str, params: Value) -> anyhow::Result<Value> {
+    let body = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+    match ureq::post(url).send_json(body) {
+        Ok(response) => response
+            .into_json()
+            .with_context(|| format!("invalid response to {} from {}", method, url)),
+        // nodes answer some errors with an http error status and a json-rpc error body
+        Err(ureq::Error::Status(status, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            match serde_json::from_str::<Value>(&body) {
+                Ok(response) if response.get("error").is_some() => Ok(response),
+                _ => bail!("{} answered {} with http status {}", url, method, status),
+            }
+        }
+        Err(err) => Err(err).with_context(|| format!("cannot reach {}", url)),
+    }
+}
+
+/// decodes the hex result of a json-rpc response.
+pub fn decode(response: &Value) -> anyhow::Result<Vec<u8>> {
+    if let Some(error) = response.get("error") {
+        bail!(
+            "the node returned an error: {}",
+            error["message"].as_str().unwrap_or("unknown error")
+        );
+    }
+    let result = response["result"]
+        .as_str()
+        .context("the node returned no result")?;
+    hex::decode(result.trim_start_matches("0x")).context("the node returned invalid hex")
+}
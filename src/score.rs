@@ -0,0 +1,115 @@
+/// collberg scoring for the ebo obfuscator.
+/// condenses the metrics of a run into the potency, resilience, and cost triple of collberg,
+/// thomborson and low's taxonomy of obfuscating transformations: how much harder the code got to
+/// analyze, how much of that survives the built-in deobfuscator, and what it costs in size and gas.
+/// the three are combined into one value and a letter grade, so configurations can be compared at
+/// a glance.
+use crate::cfg;
+use crate::deobfuscator::deobfuscate;
+use crate::evm::{cfg_complexity, halstead};
+use serde::Serialize;
+use std::fmt;
+
+/// potency, resilience, and cost of an obfuscation.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Score {
+    /// complexity gain: the average relative increase of the cyclomatic complexity, the number of
+    /// control flow edges, and halstead's effort. 0 means no gain, 1 means twice as complex.
+    pub potency: f64,
+    /// share of the potency left after the built-in deobfuscator ran on the output, from 0 to 1.
+    pub resilience: f64,
+    /// size overhead, as a fraction of the input size.
+    pub size_overhead: f64,
+    /// gas overhead, as a fraction of the input's gas, if it was measured.
+    pub gas_overhead: Option<f64>,
+    /// the larger of the size and gas overhead.
+    pub cost: f64,
+    /// potency that survives deobfuscation, per unit of cost: `potency * resilience / (1 + cost)`.
+    pub value: f64,
+    /// `value` as a letter from `A` (0.5 and up) to `F` (under 0.05).
+    pub grade: char,
+}
+
+impl Score {
+    /// scores the obfuscation of `input` into `output`.
+    ///
+    /// # arguments
+    /// * `input` - bytecode before obfuscation.
+    /// * `output` - bytecode after obfuscation.
+    /// * `gas_overhead` - measured gas overhead of the output (see `report::Row::overhead`), if any.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::score::Score;
+    ///
+    /// let input = [0x60, 0x01, 0x60, 0x02, 0x01, 0x00];
+    /// let score = Score::new(&input, &input, None);
+    /// assert_eq!((score.potency, score.cost, score.grade), (0.0, 0.0, 'F'));
+    /// ```
+    pub fn new(input: &[u8], output: &[u8], gas_overhead: Option<f64>) -> Self {
+        let potency = potency(input, output);
+        let (deobfuscated, _) = deobfuscate(output);
+        let resilience = match potency {
+            p if p > 0.0 => (self::potency(input, &deobfuscated) / p).clamp(0.0, 1.0),
+            _ => 0.0,
+        };
+        let size_overhead = match input.len() {
+            0 => 0.0,
+            len => output.len() as f64 / len as f64 - 1.0,
+        };
+        let cost = gas_overhead.map_or(size_overhead, |gas| size_overhead.max(gas));
+        let value = potency * resilience / (1.0 + cost.max(0.0));
+        Score {
+            potency,
+            resilience,
+            size_overhead,
+            gas_overhead,
+            cost,
+            value,
+            grade: grade(value),
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "grade {} ({:.2}): potency {:+.1}%, resilience {:.0}%, cost {:+.1}%",
+            self.grade,
+            self.value,
+            self.potency * 100.0,
+            self.resilience * 100.0,
+            self.cost * 100.0
+        )
+    }
+}
+
+/// average relative increase of the complexity measures from `input` to `output`.
+fn potency(input: &[u8], output: &[u8]) -> f64 {
+    let measure = |code: &[u8]| {
+        [
+            cfg_complexity(code) as f64,
+            cfg::stats(code).edges as f64,
+            halstead(code).effort,
+        ]
+    };
+    let (before, after) = (measure(input), measure(output));
+    let gains: Vec<f64> = before
+        .iter()
+        .zip(&after)
+        .map(|(&before, &after)| (after - before) / before.max(1.0))
+        .collect();
+    gains.iter().sum::<f64>() / gains.len() as f64
+}
+
+/// letter grade of a score value.
+fn grade(value: f64) -> char {
+    match value {
+        v if v >= 0.5 => 'A',
+        v if v >= 0.25 => 'B',
+        v if v >= 0.1 => 'C',
+        v if v >= 0.05 => 'D',
+        _ => 'F',
+    }
+}
@@ -0,0 +1,138 @@
+/// objective-driven seed search for the ebo obfuscator.
+/// obfuscates the input with a series of seeds and keeps the candidate that scores best on a chosen
+/// complexity metric, stopping early once the score stops improving.
+use crate::error::EboError;
+use crate::evm::{cfg_complexity, halstead};
+use crate::obfuscator::Obfuscator;
+use crate::pcmap::PcMap;
+use crate::slotmap::SlotMap;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::{debug, info_span};
+
+/// metric a search maximizes.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Objective {
+    /// number of basic blocks ending in a conditional jump (eveilm, page 47; bosc, table i).
+    Cyclomatic,
+    /// halstead's effort (eveilm, page 59).
+    #[default]
+    Halstead,
+}
+
+impl Objective {
+    /// scores `bytecode`; higher is harder to analyze.
+    pub fn score(self, bytecode: &[u8]) -> f64 {
+        match self {
+            Objective::Cyclomatic => cfg_complexity(bytecode) as f64,
+            Objective::Halstead => halstead(bytecode).effort,
+        }
+    }
+}
+
+/// how a search is run.
+#[derive(Debug, Clone, Copy)]
+pub struct Search {
+    /// metric to maximize.
+    pub objective: Objective,
+    /// largest number of seeds tried.
+    pub candidates: usize,
+    /// number of consecutive candidates without improvement after which the search stops; `None`
+    /// tries every candidate.
+    pub patience: Option<usize>,
+    /// candidates larger than this many bytes are rejected.
+    pub max_size: usize,
+}
+
+/// best candidate found by a search.
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    /// seed that produced the candidate; passing it as the seed reproduces the output.
+    pub seed: u64,
+    /// the obfuscated bytecode.
+    pub bytecode: Vec<u8>,
+    /// score of the candidate under the search objective.
+    pub score: f64,
+    /// number of candidates tried before the search stopped.
+    pub tried: usize,
+    /// number of transformations each pass applied to the candidate, keyed by pass name.
+    pub stats: BTreeMap<&'static str, usize>,
+    /// program counters of the candidate mapped to those of the input.
+    pub pc_map: Option<PcMap>,
+    /// storage slots of the input the candidate moved.
+    pub slots: SlotMap,
+}
+
+impl Search {
+    /// tries the seeds `seed`, `seed + 1`, ... and returns the best candidate that fits the size
+    /// limit, or `EboError::SizeLimit` if none does. the gas budget and the per-pass size guard are
+    /// enforced by the obfuscators `build` returns.
+    ///
+    /// # arguments
+    /// * `seed` - first seed to try.
+    /// * `build` - creates the obfuscator for a seed.
+    pub fn run(
+        &self,
+        seed: u64,
+        mut build: impl FnMut(u64) -> anyhow::Result<Obfuscator>,
+    ) -> anyhow::Result<Outcome> {
+        let mut best: Option<Outcome> = None;
+        let mut smallest = usize::MAX;
+        let mut stale = 0;
+        for tried in 1..=self.candidates {
+            let candidate_seed = seed.wrapping_add(tried as u64 - 1);
+            let _span = info_span!(
+                "candidate",
+                seed = candidate_seed,
+                candidates = self.candidates
+            )
+            .entered();
+            let mut obfuscator = build(candidate_seed)?;
+            let bytecode = obfuscator.obfuscate()?;
+            smallest = smallest.min(bytecode.len());
+            let score = self.objective.score(&bytecode);
+            debug!(
+                "Seed {} scored {:.1} with {} bytes",
+                candidate_seed,
+                score,
+                bytecode.len()
+            );
+
+            let improves = bytecode.len() <= self.max_size
+                && best.as_ref().is_none_or(|best| score > best.score);
+            if improves {
+                best = Some(Outcome {
+                    seed: candidate_seed,
+                    bytecode,
+                    score,
+                    tried,
+                    stats: obfuscator.stats().clone(),
+                    pc_map: obfuscator.pc_map()?,
+                    slots: obfuscator.slot_map().clone(),
+                });
+                stale = 0;
+            } else {
+                stale += 1;
+            }
+            if let Some(best) = best.as_mut() {
+                best.tried = tried;
+            }
+            if self.patience.is_some_and(|patience| stale >= patience) {
+                debug!(
+                    "Stopping search after {} candidates without improvement",
+                    stale
+                );
+                break;
+            }
+        }
+        best.ok_or_else(|| {
+            EboError::SizeLimit {
+                size: smallest,
+                limit: self.max_size,
+            }
+            .into()
+        })
+    }
+}
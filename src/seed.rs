@@ -0,0 +1,198 @@
+/// seed and key handling for the ebo obfuscator.
+/// turns the seed of a run into the randomness its passes draw from. by default that is a generator
+/// seeded with the 64-bit seed alone, which anyone who knows the tool can search exhaustively to
+/// find out which transformations were applied. keyed with a secret, the passes draw from chacha20
+/// instead, so the seed only selects a stream and the choices cannot be reconstructed without the key.
+/// secrets have to be random and at least 32 bytes long, see `MIN_SECRET`.
+use crate::error::EboError;
+use anyhow::Context;
+use rand::rngs::{OsRng, StdRng};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// environment variable holding the secret when no key file is given.
+pub const KEY_VAR: &str = "EBO_KEY";
+
+/// fewest bytes a secret may have. the key is a plain hash of the secret, which only resists a
+/// search of the secret when the secret is random and as long as the key, e.g. the output of
+/// `openssl rand -hex 32`; a passphrase would be guessed.
+pub const MIN_SECRET: usize = 32;
+
+/// a 256-bit key derived from a user secret. it is never printed.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key([u8; 32]);
+
+impl Key {
+    /// derives the key for `secret`, random bytes of at least `MIN_SECRET`.
+    ///
+    /// # returns
+    /// the key, or `EboError::Parse` for a shorter secret.
+    pub fn derive(secret: &[u8]) -> Result<Self, EboError> {
+        if secret.len() < MIN_SECRET {
+            return Err(EboError::parse(
+                "key",
+                format!(
+                    "the secret is {} bytes, expected at least {} random bytes, e.g. from \
+                     `openssl rand -hex 32`",
+                    secret.len(),
+                    MIN_SECRET
+                ),
+            ));
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(b"ebo key");
+        hasher.update(secret);
+        Ok(Key(hasher.finalize().into()))
+    }
+
+    /// reads the secret from the file at `path`. a trailing newline is not part of it.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let secret = std::fs::read(path).with_context(|| format!("cannot read {:?}", path))?;
+        let secret = secret
+            .strip_suffix(b"\n")
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .unwrap_or(&secret);
+        if secret.is_empty() {
+            return Err(EboError::parse("key file", format!("{:?} is empty", path)).into());
+        }
+        Key::derive(secret).with_context(|| format!("cannot use the key in {:?}", path))
+    }
+
+    /// the key from the file at `path` if given, otherwise from `$EBO_KEY` if set.
+    ///
+    /// # returns
+    /// the key, or none if there is no secret to derive one from.
+    pub fn discover(path: Option<&Path>) -> anyhow::Result<Option<Self>> {
+        if let Some(path) = path {
+            return Key::load(path).map(Some);
+        }
+        std::env::var_os(KEY_VAR)
+            .filter(|secret| !secret.is_empty())
+            .map(|secret| {
+                Key::derive(secret.as_encoded_bytes())
+                    .with_context(|| format!("cannot use the key in ${}", KEY_VAR))
+            })
+            .transpose()
+    }
+}
+
+impl std::fmt::Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+/// a seed as given on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Seed {
+    /// the seed.
+    pub value: u64,
+    /// whether it was drawn from the operating system rather than given, so it has to be recorded
+    /// for the run to be reproduced.
+    pub random: bool,
+}
+
+/// parses a seed such as `7`, or `random` for one drawn from the operating system's entropy. drawn
+/// seeds are below 2^63, the largest integer toml holds, so a manifest can record them.
+pub fn parse(source: &str) -> Result<Seed, String> {
+    match source.trim() {
+        "random" => Ok(Seed {
+            value: OsRng.next_u64() >> 1,
+            random: true,
+        }),
+        value => value
+            .parse()
+            .map(|value| Seed {
+                value,
+                random: false,
+            })
+            .map_err(|_| format!("invalid seed '{}', expected a number or random", source)),
+    }
+}
+
+/// the generator the passes of a run with `seed` draw from: chacha20 keyed with `key` on the stream
+/// `seed` if there is a key, otherwise a generator seeded with `seed`.
+pub fn rng(seed: u64, key: Option<&Key>) -> Box<dyn RngCore> {
+    match key {
+        Some(key) => {
+            let mut rng = ChaCha20Rng::from_seed(key.0);
+            rng.set_stream(seed);
+            Box::new(rng)
+        }
+        None => Box::new(StdRng::seed_from_u64(seed)),
+    }
+}
+
+/// a number between 0 and 1 derived from `seed`, and from `key` if there is one, for the chaotic map.
+pub fn chaotic_seed(seed: u64, key: Option<&Key>) -> f64 {
+    let mut hasher = Sha256::new();
+    if let Some(key) = key {
+        hasher.update(key.0);
+    }
+    hasher.update(seed.to_le_bytes());
+    let hash = hasher.finalize();
+    f64::from_le_bytes(std::array::from_fn(|i| hash[i])) / u64::MAX as f64
+}
+
+/// derives the seed of a later round from the seed of the first, so rounds do not repeat each
+/// other's random choices.
+pub fn round_seed(seed: u64, round: usize) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(seed.to_le_bytes());
+    hasher.update((round as u64).to_le_bytes());
+    let hash = hasher.finalize();
+    u64::from_le_bytes(std::array::from_fn(|i| hash[i]))
+}
+
+/// derives the seed of variant `index` of a build from its base seed, apart from the seeds of its
+/// rounds, so variants do not repeat each other's random choices.
+pub fn variant_seed(seed: u64, index: usize) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(b"ebo variant");
+    hasher.update(seed.to_le_bytes());
+    hasher.update((index as u64).to_le_bytes());
+    let hash = hasher.finalize();
+    u64::from_le_bytes(std::array::from_fn(|i| hash[i]))
+}
+
+/// splits `count` generators off `rng`, one for every block a pass works on in parallel (see
+/// `Ctx::split`). each is seeded with 256 bits hashed from a draw of `rng` and its index, so
+/// the streams neither overlap nor depend on the order the blocks are worked on, and a keyed run
+/// stays as hard to search as the generator they are split from.
+pub fn split(rng: &mut dyn RngCore, count: usize) -> Vec<StdRng> {
+    let mut base = [0; 32];
+    rng.fill_bytes(&mut base);
+    (0..count)
+        .map(|index| {
+            let mut hasher = Sha256::new();
+            hasher.update(b"ebo block");
+            hasher.update(base);
+            hasher.update((index as u64).to_le_bytes());
+            StdRng::from_seed(hasher.finalize().into())
+        })
+        .collect()
+}
+
+/// the key the block streams of a run are derived from (see `block_rng`), drawn from its generator
+/// when it is seeded, so a keyed run keeps them secret and an injected generator decides them too.
+pub fn stream_key(rng: &mut dyn RngCore) -> [u8; 32] {
+    let mut key = [0; 32];
+    rng.fill_bytes(&mut key);
+    key
+}
+
+/// the generator `pass` transforms a block with, given the hash of its canonical form and how many
+/// blocks with the same hash came before it. a block keeps its stream wherever it is laid out and
+/// whatever else the program holds, so its result can be cached (see `cache`), and copies of a
+/// block still get streams of their own.
+pub fn block_rng(base: &[u8; 32], pass: &str, content: &[u8; 32], occurrence: usize) -> StdRng {
+    let mut hasher = Sha256::new();
+    hasher.update(b"ebo block");
+    hasher.update(base);
+    hasher.update(pass.as_bytes());
+    hasher.update(content);
+    hasher.update((occurrence as u64).to_le_bytes());
+    StdRng::from_seed(hasher.finalize().into())
+}
@@ -0,0 +1,79 @@
+/// storage slot maps for the ebo obfuscator.
+/// the `storage-remap` pass moves the state of a contract to other storage slots, so anything that
+/// reads the storage of the deployed contract directly (an indexer, `eth_getStorageAt`, the state
+/// view of an explorer) has to know where each slot went. `ebo obfuscate` writes, next to the output,
+/// the slot of the output that every remapped slot of the input is read and written at.
+///
+/// only slots the code names as constants move. the entries of mappings and dynamic arrays are
+/// hashed from the original slot number and stay where they were, while the slot holding the length
+/// of a dynamic array or a short string moves with the constant naming it.
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// the map of storage slots written for `output`, e.g. `Token.slots.json` for `Token.bin`.
+pub fn path(output: &Path) -> PathBuf {
+    output.with_extension("slots.json")
+}
+
+/// storage slots of the input mapped to the slots the output uses instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SlotMap {
+    /// the slot of the output for every remapped slot of the input, as 32-byte big-endian words.
+    pub slots: BTreeMap<[u8; 32], [u8; 32]>,
+}
+
+impl SlotMap {
+    /// the slot of the output that `slot` of the input moved to; `slot` itself if it did not move.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::slotmap::SlotMap;
+    ///
+    /// let mut map = SlotMap::default();
+    /// map.record(&[([0; 32], [7; 32])].into());
+    /// map.record(&[([7; 32], [9; 32])].into());
+    /// assert_eq!(map.get([0; 32]), [9; 32]);
+    /// assert_eq!(map.get([1; 32]), [1; 32]);
+    /// ```
+    pub fn get(&self, slot: [u8; 32]) -> [u8; 32] {
+        self.slots.get(&slot).copied().unwrap_or(slot)
+    }
+
+    /// returns true if no slot moved.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// records that the code now uses the slot `moves` maps each of its slots to, on top of the
+    /// moves recorded before (as in a later round).
+    pub fn record(&mut self, moves: &BTreeMap<[u8; 32], [u8; 32]>) {
+        let moved: Vec<[u8; 32]> = self.slots.values().copied().collect();
+        for to in self.slots.values_mut() {
+            if let Some(next) = moves.get(to) {
+                *to = *next;
+            }
+        }
+        for (from, to) in moves {
+            if !moved.contains(from) {
+                self.slots.insert(*from, *to);
+            }
+        }
+    }
+
+    /// the map as pretty-printed json: an object from every remapped slot of the input to its slot
+    /// in the output, both as 0x-prefixed 32-byte hex, the way `eth_getStorageAt` takes them.
+    pub fn to_json(&self) -> String {
+        let slots: Map<String, Value> = self
+            .slots
+            .iter()
+            .map(|(from, to)| {
+                (
+                    format!("0x{}", hex::encode(from)),
+                    Value::String(format!("0x{}", hex::encode(to))),
+                )
+            })
+            .collect();
+        serde_json::to_string_pretty(&slots).expect("a slot map always serializes")
+    }
+}
@@ -0,0 +1,190 @@
+/// solc integration for the ebo obfuscator.
+/// runs the solidity compiler on a standard-json input and substitutes the obfuscated runtime code
+/// of every contract into its standard-json output, so `ebo compile` goes from source to obfuscated
+/// bytecode in one step and the output can be consumed by anything that reads solc's.
+use crate::artifact::Artifact;
+use crate::config::Obfuscation;
+use crate::evm::Fork;
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tracing::warn;
+
+/// outputs every contract needs for its code to be obfuscated and written back.
+const OUTPUTS: [&str; 2] = ["evm.bytecode", "evm.deployedBytecode"];
+
+/// makes sure the compiler writes the creation and deployed code of every contract, keeping
+/// whatever else `input` selects.
+pub fn select_outputs(input: &mut Value) -> anyhow::Result<()> {
+    if !input.is_object() {
+        bail!("standard-json input is not an object");
+    }
+    let settings = &mut input["settings"];
+    if settings.is_null() {
+        *settings = json!({});
+    }
+    let selection = &mut settings["outputSelection"];
+    if selection.is_null() {
+        *selection = json!({});
+    }
+    let every = &mut selection["*"]["*"];
+    if every.is_null() {
+        *every = json!([]);
+    }
+    let Some(every) = every.as_array_mut() else {
+        bail!("outputSelection[\"*\"][\"*\"] is not a list");
+    };
+    for output in OUTPUTS {
+        if !every.iter().any(|selected| selected == output) {
+            every.push(json!(output));
+        }
+    }
+    Ok(())
+}
+
+/// the fork `input` is compiled for (`settings.evmVersion`), if set and known to ebo. versions
+/// ebo does not know are reported and ignored.
+pub fn evm_version(input: &Value) -> Option<Fork> {
+    let version = input["settings"]["evmVersion"].as_str()?;
+    let fork = Fork::from_str(version, true).ok();
+    if fork.is_none() {
+        warn!(
+            "Unknown evmVersion {:?}, validating for the default fork",
+            version
+        );
+    }
+    fork
+}
+
+/// finds the compiler to run: `solc` if given, otherwise the `version` installed by svm
+/// (`~/.svm/<version>/solc-<version>`), otherwise `solc` on the `PATH`.
+pub fn locate(solc: Option<&Path>, version: Option<&str>) -> anyhow::Result<PathBuf> {
+    if let Some(solc) = solc {
+        return Ok(solc.to_path_buf());
+    }
+    let Some(version) = version else {
+        return Ok(PathBuf::from("solc"));
+    };
+    let home = std::env::var_os("HOME").context("cannot find svm installs: HOME is not set")?;
+    let path = Path::new(&home)
+        .join(".svm")
+        .join(version)
+        .join(format!("solc-{}", version));
+    if !path.exists() {
+        bail!(
+            "solc {} is not installed at {:?}; install it with `svm install {}`",
+            version,
+            path,
+            version
+        );
+    }
+    Ok(path)
+}
+
+/// runs `solc --standard-json` on `input`.
+///
+/// # returns
+/// the standard-json output, or an error if the compiler cannot be run or reports errors.
+pub fn compile(solc: &Path, input: &Value) -> anyhow::Result<Value> {
+    let mut child = Command::new(solc)
+        .arg("--standard-json")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("cannot run {:?}; is solc installed?", solc))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.to_string().as_bytes())?;
+    let output = child.wait_with_output()?;
+    if output.stdout.is_empty() {
+        bail!("solc failed:\n{}", String::from_utf8_lossy(&output.stderr));
+    }
+    let output: Value =
+        serde_json::from_slice(&output.stdout).context("invalid standard-json output from solc")?;
+    let errors = errors(&output);
+    if !errors.is_empty() {
+        bail!("compilation failed:\n{}", errors.join("\n"));
+    }
+    Ok(output)
+}
+
+/// the messages of the errors (not warnings) in a standard-json output.
+pub fn errors(output: &Value) -> Vec<String> {
+    let Some(errors) = output["errors"].as_array() else {
+        return Vec::new();
+    };
+    errors
+        .iter()
+        .filter(|error| error["severity"] == "error")
+        .map(|error| {
+            error["formattedMessage"]
+                .as_str()
+                .or(error["message"].as_str())
+                .unwrap_or("unknown error")
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+/// what happened to one contract of a standard-json output.
+#[derive(Debug)]
+pub struct Outcome {
+    /// `file:Contract`.
+    pub contract: String,
+    /// sizes of the runtime code before and after, or why the contract was left as compiled.
+    pub result: anyhow::Result<(usize, usize)>,
+}
+
+/// obfuscates the runtime code of every contract in a standard-json output in place, in
+/// `evm.deployedBytecode` and inside `evm.bytecode` (see `Artifact::replace_runtime`). contracts
+/// without code are left out; contracts that cannot be obfuscated are left as compiled.
+///
+/// # arguments
+/// * `output` - standard-json output of solc.
+/// * `obfuscate` - obfuscates the runtime code of a contract.
+///
+/// # returns
+/// the outcome for every contract with code, in output order.
+pub fn substitute(
+    output: &mut Value,
+    mut obfuscate: impl FnMut(&[u8]) -> anyhow::Result<Obfuscation>,
+) -> Vec<Outcome> {
+    let mut outcomes = Vec::new();
+    let Some(files) = output["contracts"].as_object_mut() else {
+        return outcomes;
+    };
+    for (file, contracts) in files.iter_mut() {
+        let Some(contracts) = contracts.as_object_mut() else {
+            continue;
+        };
+        for (name, contract) in contracts.iter_mut() {
+            let evm = &mut contract["evm"];
+            let Ok(mut artifact) = Artifact::from_json(evm.clone()) else {
+                continue;
+            };
+            let Ok(runtime) = artifact.runtime() else {
+                continue;
+            };
+            let result = obfuscate(&runtime).and_then(|obfuscation| {
+                let obfuscated = obfuscation.bytecode;
+                artifact.replace_runtime(&obfuscated, obfuscation.pc_map.as_ref())?;
+                Ok((runtime.len(), obfuscated.len()))
+            });
+            if result.is_ok() {
+                *evm = artifact.into_json();
+            }
+            outcomes.push(Outcome {
+                contract: format!("{}:{}", file, name),
+                result,
+            });
+        }
+    }
+    outcomes
+}
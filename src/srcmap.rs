@@ -0,0 +1,204 @@
+/// solidity source maps for the ebo obfuscator.
+/// solc describes which part of the source every instruction of the runtime code was compiled from
+/// in its `srcmap-runtime` (`deployedBytecode.sourceMap` in artifacts): one `s:l:f:j:m` entry per
+/// instruction, holding the byte offset and length of the source range, the index of the source
+/// file, whether the instruction jumps into (`i`) or out of (`o`) a function, and the modifier
+/// depth. debuggers find the entry of a program counter by counting the instructions in front of
+/// it, so the map of the input is useless for the obfuscated code.
+///
+/// `SourceMap::translate` rebuilds it for the obfuscated code through the program counter map (see
+/// `pcmap`): every copy of an input instruction gets its entry, code the passes added gets the
+/// source range of the input instruction in front of it without a jump marker, so stepping stays on
+/// the statement it belongs to, and code that maps back to nothing gets no source (`-1:-1:-1`).
+use crate::error::EboError;
+use crate::evm::disassemble;
+use crate::pcmap::PcMap;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// the source map of code written to `output`, e.g. `Token.srcmap` for `Token.bin`.
+pub fn path(output: &Path) -> PathBuf {
+    output.with_extension("srcmap")
+}
+
+/// how an instruction moves between functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Jump {
+    /// a jump into a function (`i`).
+    Into,
+    /// a jump out of a function (`o`).
+    Out,
+    /// any other instruction (`-`).
+    #[default]
+    Regular,
+}
+
+/// the source of one instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    /// byte offset of the source range, -1 for none.
+    pub start: i64,
+    /// byte length of the source range, -1 for none.
+    pub length: i64,
+    /// index of the source file, -1 for code the compiler generated.
+    pub file: i64,
+    /// how the instruction moves between functions.
+    pub jump: Jump,
+    /// modifier depth.
+    pub modifier_depth: u64,
+}
+
+impl Entry {
+    /// the entry of an instruction without source.
+    pub const NONE: Entry = Entry {
+        start: -1,
+        length: -1,
+        file: -1,
+        jump: Jump::Regular,
+        modifier_depth: 0,
+    };
+
+    /// the fields in the order solc writes them.
+    fn fields(&self) -> [String; 5] {
+        let jump = match self.jump {
+            Jump::Into => "i",
+            Jump::Out => "o",
+            Jump::Regular => "-",
+        };
+        [
+            self.start.to_string(),
+            self.length.to_string(),
+            self.file.to_string(),
+            jump.to_string(),
+            self.modifier_depth.to_string(),
+        ]
+    }
+}
+
+/// the source of every instruction of a code, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// one entry per instruction, counted from the start of the code.
+    pub entries: Vec<Entry>,
+}
+
+impl SourceMap {
+    /// parses a compressed source map as solc writes it, in which a missing or empty field repeats
+    /// the field of the entry before.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::srcmap::{Jump, SourceMap};
+    ///
+    /// let map = SourceMap::parse("26:80:0:-:0;;;48:5:0:i").unwrap();
+    /// assert_eq!(map.entries.len(), 4);
+    /// assert_eq!((map.entries[2].start, map.entries[2].length), (26, 80));
+    /// assert_eq!(map.entries[3].jump, Jump::Into);
+    /// assert_eq!(map.to_string(), "26:80:0:-:0;;;48:5::i");
+    /// ```
+    pub fn parse(source: &str) -> Result<Self, EboError> {
+        let source = source.trim();
+        let mut entries = Vec::new();
+        if source.is_empty() {
+            return Ok(SourceMap { entries });
+        }
+        let mut entry = Entry::NONE;
+        for (idx, compressed) in source.split(';').enumerate() {
+            let invalid = |field: &str| {
+                EboError::parse(
+                    "source map",
+                    format!("entry {} has an invalid {}: {:?}", idx, field, compressed),
+                )
+            };
+            let fields: Vec<&str> = compressed.split(':').collect();
+            if fields.len() > 5 {
+                return Err(invalid("number of fields"));
+            }
+            for (field, value) in fields.iter().enumerate().filter(|(_, v)| !v.is_empty()) {
+                match field {
+                    0 => entry.start = value.parse().map_err(|_| invalid("offset"))?,
+                    1 => entry.length = value.parse().map_err(|_| invalid("length"))?,
+                    2 => entry.file = value.parse().map_err(|_| invalid("file index"))?,
+                    3 => {
+                        entry.jump = match *value {
+                            "i" => Jump::Into,
+                            "o" => Jump::Out,
+                            "-" => Jump::Regular,
+                            _ => return Err(invalid("jump type")),
+                        }
+                    }
+                    _ => {
+                        entry.modifier_depth =
+                            value.parse().map_err(|_| invalid("modifier depth"))?
+                    }
+                }
+            }
+            entries.push(entry);
+        }
+        Ok(SourceMap { entries })
+    }
+
+    /// the map of `output`, the obfuscated code of `input`, which this maps, with `map` between
+    /// their program counters. instructions after the last one with a source are left out.
+    pub fn translate(&self, input: &[u8], output: &[u8], map: &PcMap) -> SourceMap {
+        let indices: HashMap<usize, usize> = disassemble(input)
+            .iter()
+            .enumerate()
+            .map(|(idx, ins)| (ins.offset, idx))
+            .collect();
+        let mut entries: Vec<Entry> = disassemble(output)
+            .iter()
+            .map(|ins| {
+                let Some(origin) = map.inverse.get(&ins.offset) else {
+                    return Entry::NONE;
+                };
+                match indices
+                    .get(&origin.pc)
+                    .and_then(|&idx| self.entries.get(idx))
+                {
+                    Some(&entry) if origin.exact => entry,
+                    Some(&entry) => Entry {
+                        jump: Jump::Regular,
+                        ..entry
+                    },
+                    None => Entry::NONE,
+                }
+            })
+            .collect();
+        let used = entries
+            .iter()
+            .rposition(|entry| *entry != Entry::NONE)
+            .map_or(0, |idx| idx + 1);
+        entries.truncate(used);
+        SourceMap { entries }
+    }
+}
+
+impl fmt::Display for SourceMap {
+    /// the map compressed as solc writes it: fields equal to those of the entry before are left
+    /// empty, and trailing empty fields are left out.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut previous: Option<[String; 5]> = None;
+        for (idx, entry) in self.entries.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ";")?;
+            }
+            let fields = entry.fields();
+            let mut compressed: Vec<&str> = fields
+                .iter()
+                .enumerate()
+                .map(|(field, value)| match &previous {
+                    Some(previous) if previous[field] == *value => "",
+                    _ => value.as_str(),
+                })
+                .collect();
+            while compressed.last() == Some(&"") {
+                compressed.pop();
+            }
+            write!(f, "{}", compressed.join(":"))?;
+            previous = Some(fields);
+        }
+        Ok(())
+    }
+}
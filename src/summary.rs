@@ -0,0 +1,134 @@
+/// obfuscation summaries for the ebo obfuscator.
+/// a machine-readable record of one `ebo obfuscate` run, written with `--report`: the seed and
+/// configuration it was made with, what every pass did, and the size and complexity metrics of the
+/// code before and after, so the builds of each release can be archived and compared.
+use crate::cfg::{self, Stats};
+use crate::config::Config;
+use crate::evm::{
+    byte_entropy, cfg_complexity, compression_ratio, halstead, ngram_similarity, opcode_entropy,
+    Halstead,
+};
+use crate::score::Score;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+/// lengths of the opcode n-grams whose similarity is reported.
+pub const NGRAM_SIZES: RangeInclusive<usize> = 2..=4;
+
+/// size and complexity of one bytecode.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Metrics {
+    /// size in bytes.
+    pub size: usize,
+    /// sha-256 of the bytecode, as hex.
+    pub sha256: String,
+    /// number of basic blocks ending in a conditional jump (eveilm, page 47; bosc, table i).
+    pub cfg_complexity: usize,
+    /// shape of the control flow graph.
+    pub cfg: Stats,
+    /// halstead's metrics (eveilm, page 59).
+    pub halstead: Halstead,
+    /// shannon entropy of the bytes, in bits per byte.
+    pub entropy: f64,
+    /// shannon entropy of the opcodes, push data left out, in bits per instruction.
+    pub opcode_entropy: f64,
+    /// deflate-compressed size over size, a proxy for kolmogorov complexity.
+    pub compression_ratio: f64,
+}
+
+impl Metrics {
+    /// measures `bytecode`.
+    pub fn measure(bytecode: &[u8]) -> Self {
+        Metrics {
+            size: bytecode.len(),
+            sha256: hex::encode(Sha256::digest(bytecode)),
+            cfg_complexity: cfg_complexity(bytecode),
+            cfg: cfg::stats(bytecode),
+            halstead: halstead(bytecode),
+            entropy: byte_entropy(bytecode),
+            opcode_entropy: opcode_entropy(bytecode),
+            compression_ratio: compression_ratio(bytecode),
+        }
+    }
+}
+
+/// summary of an obfuscation run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    /// version of ebo that made the build.
+    pub version: String,
+    /// seed the output was produced with (the kept seed, after a search).
+    pub seed: u64,
+    /// whether the randomness was keyed with a secret, without which the seed does not reproduce
+    /// the output.
+    pub keyed: bool,
+    /// fingerprint of the configuration, see `Config::fingerprint`.
+    pub config: String,
+    /// the bytecode before obfuscation.
+    pub input: Metrics,
+    /// the bytecode after obfuscation.
+    pub output: Metrics,
+    /// change of the byte entropy, output minus input; an increase is the usual sign of
+    /// obfuscation in the literature.
+    pub entropy_delta: f64,
+    /// change of the opcode entropy, output minus input.
+    pub opcode_entropy_delta: f64,
+    /// change of the compression ratio, output minus input. a drop means the inserted code is
+    /// more repetitive than the code it was inserted into.
+    pub compression_ratio_delta: f64,
+    /// jaccard similarity of the opcode n-grams of input and output, keyed by n (see
+    /// `NGRAM_SIZES`). lower means less left for a clone detector to match.
+    pub ngram_similarity: BTreeMap<usize, f64>,
+    /// potency, resilience, and cost of the run, with its grade. gas is not measured here.
+    pub score: Score,
+    /// number of transformations each pass applied, keyed by pass name.
+    pub passes: BTreeMap<String, usize>,
+}
+
+impl Summary {
+    /// summarizes a run.
+    ///
+    /// # arguments
+    /// * `input` - bytecode before obfuscation.
+    /// * `output` - bytecode after obfuscation.
+    /// * `seed` - seed the output was produced with.
+    /// * `config` - configuration of the run.
+    /// * `stats` - transformations applied by each pass, from `Obfuscator::stats`.
+    pub fn new(
+        input: &[u8],
+        output: &[u8],
+        seed: u64,
+        config: &Config,
+        stats: &BTreeMap<&'static str, usize>,
+    ) -> anyhow::Result<Self> {
+        let ngram_similarity = NGRAM_SIZES
+            .map(|n| (n, ngram_similarity(input, output, n)))
+            .collect();
+        let score = Score::new(input, output, None);
+        let (input, output) = (Metrics::measure(input), Metrics::measure(output));
+        Ok(Summary {
+            version: env!("CARGO_PKG_VERSION").into(),
+            seed,
+            keyed: config.key.is_some(),
+            config: config.fingerprint()?,
+            entropy_delta: output.entropy - input.entropy,
+            opcode_entropy_delta: output.opcode_entropy - input.opcode_entropy,
+            compression_ratio_delta: output.compression_ratio - input.compression_ratio,
+            ngram_similarity,
+            score,
+            input,
+            output,
+            passes: stats
+                .iter()
+                .map(|(pass, &count)| (pass.to_string(), count))
+                .collect(),
+        })
+    }
+
+    /// the summary as pretty-printed json.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a summary always serializes")
+    }
+}
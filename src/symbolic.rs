@@ -0,0 +1,271 @@
+/// symbolic stack evaluator for the ebo obfuscator.
+/// runs straight-line, side-effect-free code on symbolic stack items and normalizes the values it
+/// computes, so two sequences can be proved to leave the same values on the stack. substitution
+/// templates and junk sequences are vetted with it before they are inserted: a template is only
+/// used if it computes exactly what it replaces, for every input.
+///
+/// values are kept as linear combinations (modulo 2^256) of the input items and of opaque terms.
+/// addition, subtraction, negation, `not`, and multiplication by a constant are exact; every other
+/// operation becomes an opaque term over its normalized arguments, with the arguments of
+/// commutative operations sorted and `iszero iszero` of a boolean collapsed. the check is sound but
+/// not complete: equivalent sequences may fail to normalize to the same values, but sequences that
+/// do are equivalent.
+use crate::evm::{disassemble, op, stack_effect, stack_io};
+use revm::primitives::U256;
+use std::collections::BTreeMap;
+
+/// a part of a value that is not a constant.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Term {
+    /// the stack item at this depth when the sequence starts, 0 being the top.
+    Input(usize),
+    /// an operation that is not expanded, applied to its arguments in stack order (top first).
+    Op(u8, Vec<Value>),
+}
+
+/// a normalized symbolic value: the sum of its terms, each times its coefficient, plus a constant.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Value {
+    /// terms with their non-zero coefficients.
+    terms: BTreeMap<Term, U256>,
+    /// constant part.
+    constant: U256,
+}
+
+impl Value {
+    /// the constant `value`.
+    pub fn constant(value: U256) -> Self {
+        Value {
+            terms: BTreeMap::new(),
+            constant: value,
+        }
+    }
+
+    /// a single term with coefficient one.
+    fn term(term: Term) -> Self {
+        Value {
+            terms: BTreeMap::from([(term, U256::from(1))]),
+            constant: U256::ZERO,
+        }
+    }
+
+    /// the stack item at `depth` when the sequence starts.
+    pub fn input(depth: usize) -> Self {
+        Value::term(Term::Input(depth))
+    }
+
+    /// the value, if it does not depend on the inputs.
+    pub fn as_constant(&self) -> Option<U256> {
+        self.terms.is_empty().then_some(self.constant)
+    }
+
+    /// the value, if it is a single term with coefficient one.
+    fn as_term(&self) -> Option<&Term> {
+        match self.terms.iter().next() {
+            Some((term, coefficient))
+                if self.terms.len() == 1
+                    && *coefficient == U256::from(1)
+                    && self.constant.is_zero() =>
+            {
+                Some(term)
+            }
+            _ => None,
+        }
+    }
+
+    /// returns true if the value is always 0 or 1.
+    fn is_boolean(&self) -> bool {
+        match (self.as_constant(), self.as_term()) {
+            (Some(value), _) => value <= U256::from(1),
+            (_, Some(Term::Op(opcode, _))) => matches!(
+                *opcode,
+                op::LT | op::GT | op::SLT | op::SGT | op::EQ | op::ISZERO
+            ),
+            _ => false,
+        }
+    }
+
+    /// `self + other`.
+    fn add(mut self, other: &Value) -> Self {
+        for (term, coefficient) in &other.terms {
+            let sum = self
+                .terms
+                .get(term)
+                .copied()
+                .unwrap_or_default()
+                .wrapping_add(*coefficient);
+            if sum.is_zero() {
+                self.terms.remove(term);
+            } else {
+                self.terms.insert(term.clone(), sum);
+            }
+        }
+        self.constant = self.constant.wrapping_add(other.constant);
+        self
+    }
+
+    /// `self * factor`.
+    fn scale(mut self, factor: U256) -> Self {
+        self.terms = std::mem::take(&mut self.terms)
+            .into_iter()
+            .map(|(term, coefficient)| (term, coefficient.wrapping_mul(factor)))
+            .filter(|(_, coefficient)| !coefficient.is_zero())
+            .collect();
+        self.constant = self.constant.wrapping_mul(factor);
+        self
+    }
+
+    /// `-self`.
+    fn negate(self) -> Self {
+        self.scale(U256::MAX)
+    }
+}
+
+/// folds `opcode` over constant arguments (top first), for the operations that are simple to
+/// compute. returns `None` for the others, which then stay opaque.
+fn fold(opcode: u8, args: &[U256]) -> Option<U256> {
+    let bool = |b: bool| U256::from(b as u8);
+    Some(match (opcode, args) {
+        (op::DIV, [a, b]) => a.checked_div(*b).unwrap_or_default(),
+        (op::MOD, [a, b]) => a.checked_rem(*b).unwrap_or_default(),
+        (op::LT, [a, b]) => bool(a < b),
+        (op::GT, [a, b]) => bool(a > b),
+        (op::EQ, [a, b]) => bool(a == b),
+        (op::ISZERO, [a]) => bool(a.is_zero()),
+        (op::AND, [a, b]) => a & b,
+        (op::OR, [a, b]) => a | b,
+        (op::XOR, [a, b]) => a ^ b,
+        (op::SHL, [shift, value]) => match usize::try_from(*shift) {
+            Ok(shift) if shift < 256 => *value << shift,
+            _ => U256::ZERO,
+        },
+        (op::SHR, [shift, value]) => match usize::try_from(*shift) {
+            Ok(shift) if shift < 256 => *value >> shift,
+            _ => U256::ZERO,
+        },
+        _ => return None,
+    })
+}
+
+/// applies the pure operation `opcode` to `args`, popped from the stack (top first).
+fn apply(opcode: u8, mut args: Vec<Value>) -> Value {
+    match opcode {
+        op::ADD => args[0].clone().add(&args[1]),
+        op::SUB => args[0].clone().add(&args[1].clone().negate()),
+        // not x = 2^256 - 1 - x
+        op::NOT => args[0].clone().negate().add(&Value::constant(U256::MAX)),
+        op::MUL if args[0].as_constant().is_some() || args[1].as_constant().is_some() => {
+            match args[0].as_constant() {
+                Some(factor) => args[1].clone().scale(factor),
+                None => args[0].clone().scale(args[1].as_constant().unwrap()),
+            }
+        }
+        _ => {
+            let constants: Option<Vec<U256>> = args.iter().map(Value::as_constant).collect();
+            if let Some(value) = constants.and_then(|constants| fold(opcode, &constants)) {
+                return Value::constant(value);
+            }
+            if opcode == op::ISZERO {
+                // iszero iszero of a boolean is the boolean itself
+                if let Some(Term::Op(op::ISZERO, inner)) = args[0].as_term() {
+                    if inner[0].is_boolean() {
+                        return inner[0].clone();
+                    }
+                }
+            }
+            if matches!(
+                opcode,
+                op::MUL | op::AND | op::OR | op::XOR | op::EQ | op::ADDMOD | op::MULMOD
+            ) {
+                // the modulus of addmod and mulmod is the third argument and stays in place
+                args[..2].sort();
+            }
+            Value::term(Term::Op(opcode, args))
+        }
+    }
+}
+
+/// returns true if `opcode` is one of the pure instructions `evaluate` runs: it reads nothing but
+/// the stack and has no effect but on the stack.
+pub fn pure(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        op::PUSH0..=op::PUSH32 | op::DUP1..=op::DUP16 | op::SWAP1..=op::SWAP16 | op::POP
+    ) || matches!(opcode, 0x01..=0x0B | 0x10..=0x1D)
+}
+
+/// runs `code` on a stack whose top `inputs` items are symbolic.
+///
+/// # arguments
+/// * `code` - straight-line code made of pure instructions: pushes, dups, swaps, pop, and the
+///   arithmetic, comparison, and bitwise operations.
+/// * `inputs` - number of stack items present when the code starts.
+///
+/// # returns
+/// the stack the code leaves, top first, or `None` if the code uses any other instruction or reads
+/// below its inputs.
+///
+/// # example
+/// ```
+/// use ebo::symbolic::{evaluate, Value};
+///
+/// let stack = evaluate(&[0x90, 0x90], 2).unwrap(); // SWAP1, SWAP1
+/// assert_eq!(stack, vec![Value::input(0), Value::input(1)]);
+/// ```
+pub fn evaluate(code: &[u8], inputs: usize) -> Option<Vec<Value>> {
+    // bottom first while evaluating
+    let mut stack: Vec<Value> = (0..inputs).rev().map(Value::input).collect();
+    for ins in disassemble(code) {
+        let opcode = ins.opcode;
+        match opcode {
+            op::PUSH0..=op::PUSH32 => {
+                if ins.immediate.len() < (opcode - op::PUSH0) as usize {
+                    return None;
+                }
+                stack.push(Value::constant(U256::try_from_be_slice(&ins.immediate)?));
+            }
+            op::DUP1..=op::DUP16 => {
+                let n = (opcode - op::DUP1) as usize + 1;
+                let value = stack.len().checked_sub(n).map(|idx| stack[idx].clone())?;
+                stack.push(value);
+            }
+            op::SWAP1..=op::SWAP16 => {
+                let n = (opcode - op::SWAP1) as usize + 1;
+                let top = stack.len().checked_sub(1)?;
+                stack.swap(top, top.checked_sub(n)?);
+            }
+            op::POP => {
+                stack.pop()?;
+            }
+            0x01..=0x0B | 0x10..=0x1D => {
+                let (popped, _) = stack_io(opcode)?;
+                let args: Vec<Value> = (0..popped).map(|_| stack.pop()).collect::<Option<_>>()?;
+                stack.push(apply(opcode, args));
+            }
+            _ => return None,
+        }
+    }
+    stack.reverse();
+    Some(stack)
+}
+
+/// returns true if `a` and `b` provably leave the same values on the stack, whatever the stack holds
+/// when they start. both must be straight-line pure code, as accepted by `evaluate`.
+///
+/// # example
+/// ```
+/// use ebo::symbolic::equivalent;
+///
+/// assert!(equivalent(&[0x01], &[0x90, 0x01])); // ADD is SWAP1, ADD
+/// assert!(!equivalent(&[0x01], &[0x60, 0x01, 0x01, 0x60, 0x01, 0x01])); // but not ADD + 2
+/// ```
+pub fn equivalent(a: &[u8], b: &[u8]) -> bool {
+    let inputs = match (stack_effect(a), stack_effect(b)) {
+        (Some((a, _)), Some((b, _))) => a.max(b),
+        _ => return false,
+    };
+    match (evaluate(a, inputs), evaluate(b, inputs)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
@@ -0,0 +1,130 @@
+/// golden snapshot testing for code that embeds ebo.
+/// records the obfuscated output of a (bytecode, seed, configuration) triple in a snapshot file the
+/// first time it is checked, and fails every later check whose output differs, so an upgrade that
+/// silently changes what ebo produces is caught in ci. set `EBO_UPDATE_SNAPSHOTS=1` to accept the
+/// new output and rewrite the snapshots.
+use crate::config::Config;
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// environment variable that makes checks rewrite their snapshots instead of comparing them.
+pub const UPDATE_VAR: &str = "EBO_UPDATE_SNAPSHOTS";
+
+/// contents of a snapshot file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// version of ebo that recorded the snapshot.
+    pub version: String,
+    /// seed the output was produced with.
+    pub seed: u64,
+    /// sha-256 of the input bytecode, as hex.
+    pub input: String,
+    /// obfuscated output, as hex.
+    pub output: String,
+}
+
+/// result of a successful check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// the output matches the snapshot.
+    Matched,
+    /// there was no snapshot yet, or updating was requested, and the output was recorded.
+    Recorded,
+}
+
+/// obfuscates `bytecode` with `seed` as the cli would with `config`.
+pub fn obfuscate(bytecode: &[u8], seed: u64, config: &Config) -> anyhow::Result<Vec<u8>> {
+    Ok(config.obfuscate(bytecode, seed)?.bytecode)
+}
+
+/// checks the output for a triple against the snapshot at `path`, recording it if there is none.
+///
+/// # arguments
+/// * `path` - snapshot file, conventionally under `tests/snapshots/` with a `.toml` extension.
+/// * `bytecode` - input bytecode.
+/// * `seed` - obfuscation seed.
+/// * `config` - configuration the output is produced with.
+///
+/// # returns
+/// whether the output matched or was recorded; an error describing the first difference if the
+/// output changed, or if the snapshot was taken of another input or seed.
+///
+/// # example
+/// ```no_run
+/// use ebo::test_support::check;
+/// use ebo::Config;
+/// use std::path::Path;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// # let bytecode = [0x60, 0x01, 0x00];
+/// let config = Config::from_toml("[passes]\ndisabled = [\"spaghetti\"]")?;
+/// check(Path::new("tests/snapshots/token.toml"), &bytecode, 7, &config)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn check(path: &Path, bytecode: &[u8], seed: u64, config: &Config) -> anyhow::Result<Outcome> {
+    let output = obfuscate(bytecode, seed, config)?;
+    let input = hex::encode(Sha256::digest(bytecode));
+    let update = std::env::var(UPDATE_VAR).is_ok_and(|value| !value.is_empty() && value != "0");
+
+    if update || !path.exists() {
+        let snapshot = Snapshot {
+            version: env!("CARGO_PKG_VERSION").into(),
+            seed,
+            input,
+            output: hex::encode(&output),
+        };
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, toml::to_string(&snapshot)?)
+            .with_context(|| format!("cannot write snapshot {:?}", path))?;
+        return Ok(Outcome::Recorded);
+    }
+
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("cannot read snapshot {:?}", path))?;
+    let snapshot: Snapshot =
+        toml::from_str(&text).with_context(|| format!("invalid snapshot {:?}", path))?;
+    if snapshot.input != input || snapshot.seed != seed {
+        bail!(
+            "snapshot {:?} was recorded for other input bytecode or another seed (seed {}); \
+             set {}=1 to record it again",
+            path,
+            snapshot.seed,
+            UPDATE_VAR
+        );
+    }
+    let expected = hex::decode(&snapshot.output)
+        .with_context(|| format!("invalid output in snapshot {:?}", path))?;
+    if expected != output {
+        let offset = expected
+            .iter()
+            .zip(&output)
+            .position(|(a, b)| a != b)
+            .unwrap_or(expected.len().min(output.len()));
+        bail!(
+            "output differs from snapshot {:?} recorded by ebo {}: first difference at byte {:#x}, \
+             {} bytes before and {} now; set {}=1 if the change is intended",
+            path,
+            snapshot.version,
+            offset,
+            expected.len(),
+            output.len(),
+            UPDATE_VAR
+        );
+    }
+    Ok(Outcome::Matched)
+}
+
+/// like `check`, but panics with the difference instead of returning an error, for use in tests.
+///
+/// # panics
+/// if the output differs from the snapshot, or the snapshot cannot be read or written.
+pub fn assert_snapshot(path: &Path, bytecode: &[u8], seed: u64, config: &Config) {
+    if let Err(err) = check(path, bytecode, seed, config) {
+        panic!("{:#}", err);
+    }
+}
@@ -0,0 +1,210 @@
+/// execution traces for the ebo obfuscator.
+/// records every instruction the original and the obfuscated bytecode execute for one call, and
+/// lines the two traces up so the point where they part ways is easy to find. obfuscated code runs
+/// more instructions than the original, so the traces are aligned on anchors: instructions with an
+/// effect a caller can observe (storage writes, logs, calls, and the end of execution). the code
+/// between two anchors is expected to differ; the anchors themselves have to match, in order and
+/// with the same arguments.
+use crate::equivalence::{self, Execution, SPEC};
+use crate::evm::{mnemonic, op, stack_io};
+use anyhow::anyhow;
+use revm::interpreter::Interpreter;
+use revm::primitives::U256;
+use revm::{inspector_handle_register, Database, Evm, EvmContext, Inspector};
+use std::fmt;
+
+/// largest number of steps recorded per trace; execution continues past it unrecorded.
+pub const MAX_STEPS: usize = 100_000;
+
+/// one executed instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// offset of the instruction.
+    pub pc: usize,
+    /// the instruction.
+    pub opcode: u8,
+    /// the items the instruction pops, top first (or the top item, for instructions that pop
+    /// nothing), as they were before it ran.
+    pub inputs: Vec<U256>,
+    /// gas left before the instruction ran.
+    pub gas: u64,
+}
+
+impl Step {
+    /// the arguments the instruction pops, top first.
+    pub fn arguments(&self) -> &[U256] {
+        let popped = stack_io(self.opcode).map_or(0, |(popped, _)| popped);
+        &self.inputs[..popped.min(self.inputs.len())]
+    }
+
+    /// returns true if the step is an anchor: an instruction with an effect a caller can observe.
+    pub fn is_anchor(&self) -> bool {
+        matches!(
+            self.opcode,
+            op::STOP
+                | op::RETURN
+                | op::REVERT
+                | op::INVALID
+                | op::SELFDESTRUCT
+                | 0x55 // sstore
+                | 0x5D // tstore
+                | 0xA0..=0xA4 // log0..log4
+                | 0xF0..=0xF2 | 0xF4 | 0xF5 | 0xFA // create and call
+        )
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = mnemonic(self.opcode);
+        let top = match self.inputs.first() {
+            Some(value) => {
+                let digits = format!("{:x}", value);
+                if digits.len() > 16 {
+                    format!("0x{}..", &digits[..14])
+                } else {
+                    format!("0x{}", digits)
+                }
+            }
+            None => String::new(),
+        };
+        write!(
+            f,
+            "{:#06x} {:<14} {:<18} {:>8}",
+            self.pc, name, top, self.gas
+        )
+    }
+}
+
+/// an inspector recording the steps of the contract under test.
+#[derive(Default)]
+struct Recorder {
+    steps: Vec<Step>,
+}
+
+impl<DB: Database> Inspector<DB> for Recorder {
+    fn step(&mut self, interp: &mut Interpreter, _context: &mut EvmContext<DB>) {
+        if self.steps.len() >= MAX_STEPS {
+            return;
+        }
+        let opcode = interp.current_opcode();
+        let popped = stack_io(opcode).map_or(0, |(popped, _)| popped).max(1);
+        let inputs = (0..popped)
+            .map_while(|depth| interp.stack.peek(depth).ok())
+            .collect();
+        self.steps.push(Step {
+            pc: interp.program_counter(),
+            opcode,
+            inputs,
+            gas: interp.gas.remaining(),
+        });
+    }
+}
+
+/// executes `code` like `equivalence::execute`, recording every step.
+///
+/// # returns
+/// the execution, and its first `MAX_STEPS` steps.
+pub fn record(code: &[u8], calldata: &[u8]) -> anyhow::Result<(Execution, Vec<Step>)> {
+    let mut evm = Evm::builder()
+        .with_db(equivalence::deploy(code))
+        .with_external_context(Recorder::default())
+        .with_spec_id(SPEC)
+        .modify_tx_env(|tx| equivalence::call(tx, calldata))
+        .append_handler_register(inspector_handle_register)
+        .build();
+    let outcome = evm
+        .transact()
+        .map_err(|err| anyhow!("evm rejected the call: {}", err))?;
+    let steps = std::mem::take(&mut evm.context.external.steps);
+    Ok((equivalence::observe(outcome), steps))
+}
+
+/// a line of an aligned trace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row<'a> {
+    /// step of the original, if the original has one here.
+    pub original: Option<&'a Step>,
+    /// step of the obfuscated code, if it has one here.
+    pub obfuscated: Option<&'a Step>,
+    /// the steps are anchors, which must match.
+    pub anchor: bool,
+    /// the row is an anchor on which the two differ: a different instruction, different
+    /// arguments, or an anchor only one side reaches.
+    pub diverges: bool,
+}
+
+/// splits a trace into runs of ordinary steps, each ended by an anchor (the last run may have
+/// none, when execution halts exceptionally or the trace is cut off).
+fn segments(steps: &[Step]) -> Vec<(&[Step], Option<&Step>)> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for (idx, step) in steps.iter().enumerate() {
+        if step.is_anchor() {
+            segments.push((&steps[start..idx], Some(step)));
+            start = idx + 1;
+        }
+    }
+    if start < steps.len() {
+        segments.push((&steps[start..], None));
+    }
+    segments
+}
+
+/// aligns two traces on their anchors.
+///
+/// # arguments
+/// * `original` - steps of the original bytecode.
+/// * `obfuscated` - steps of the obfuscated bytecode on the same call.
+///
+/// # returns
+/// the rows of both traces side by side: the steps between two anchors are paired in order (the
+/// shorter side padded), and the n-th anchor of one trace shares a row with the n-th of the other.
+pub fn align<'a>(original: &'a [Step], obfuscated: &'a [Step]) -> Vec<Row<'a>> {
+    let (a, b) = (segments(original), segments(obfuscated));
+    let mut rows = Vec::new();
+    for idx in 0..a.len().max(b.len()) {
+        let (steps_a, anchor_a) = a.get(idx).copied().unwrap_or((&[], None));
+        let (steps_b, anchor_b) = b.get(idx).copied().unwrap_or((&[], None));
+        for row in 0..steps_a.len().max(steps_b.len()) {
+            rows.push(Row {
+                original: steps_a.get(row),
+                obfuscated: steps_b.get(row),
+                anchor: false,
+                diverges: false,
+            });
+        }
+        if anchor_a.is_some() || anchor_b.is_some() {
+            let diverges = match (anchor_a, anchor_b) {
+                (Some(a), Some(b)) => a.opcode != b.opcode || a.arguments() != b.arguments(),
+                _ => true,
+            };
+            rows.push(Row {
+                original: anchor_a,
+                obfuscated: anchor_b,
+                anchor: true,
+                diverges,
+            });
+        }
+    }
+    rows
+}
+
+impl fmt::Display for Row<'_> {
+    /// both steps side by side, marked `=` on matching anchors and `!` where the traces diverge.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = |step: Option<&Step>| step.map_or(String::new(), |step| step.to_string());
+        let mark = match (self.anchor, self.diverges) {
+            (_, true) => '!',
+            (true, false) => '=',
+            _ => ' ',
+        };
+        write!(
+            f,
+            "{} {:<51} | {}",
+            mark,
+            side(self.original),
+            side(self.obfuscated)
+        )
+    }
+}
@@ -0,0 +1,210 @@
+/// bytecode validator for the ebo obfuscator.
+/// walks every path through the code that can be followed statically and reports what would make
+/// the code fail or misbehave when deployed: opcodes the target does not run, a push cut off
+/// by the end of the code, static jumps that do not land on a jumpdest, and stack underflows. bytes
+/// that no path reaches (constant pools, metadata) are never reported.
+use crate::chain::Target;
+use crate::evm::{disassemble, is_terminator, op, push_size, stack_io, Instruction};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// something wrong with an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// the opcode is not defined in the target fork, or the target chain does not run it.
+    UndefinedOpcode(u8),
+    /// a push whose immediate runs past the end of the code.
+    TruncatedPush,
+    /// a jump whose pushed target is not a jumpdest.
+    BadJump(usize),
+    /// the instruction needs more stack items than the path leaves it.
+    StackUnderflow { needed: usize, available: usize },
+}
+
+/// a problem at an offset of the validated bytecode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Issue {
+    /// offset of the offending instruction.
+    pub offset: usize,
+    /// what is wrong with it.
+    pub problem: Problem,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#06x}: ", self.offset)?;
+        match self.problem {
+            Problem::UndefinedOpcode(opcode) => write!(f, "undefined opcode {:#04x}", opcode),
+            Problem::TruncatedPush => write!(f, "push truncated by the end of the code"),
+            Problem::BadJump(target) => {
+                write!(f, "jump to {:#06x}, which is not a jumpdest", target)
+            }
+            Problem::StackUnderflow { needed, available } => write!(
+                f,
+                "stack underflow: needs {} items, {} available",
+                needed, available
+            ),
+        }
+    }
+}
+
+impl Problem {
+    /// the kind of problem, without its details, used to compare how often each kind occurs.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Problem::UndefinedOpcode(_) => "undefined opcode",
+            Problem::TruncatedPush => "truncated push",
+            Problem::BadJump(_) => "bad jump",
+            Problem::StackUnderflow { .. } => "stack underflow",
+        }
+    }
+}
+
+/// target of a push directly consumed by the jump at instruction `idx`, if there is one. values
+/// wider than the code can hold map to `usize::MAX`.
+fn static_target(instructions: &[Instruction], idx: usize) -> Option<usize> {
+    let push = instructions.get(idx.checked_sub(1)?)?;
+    if push_size(push.opcode) == 0 && push.opcode != op::PUSH0 {
+        return None;
+    }
+    Some(push.immediate.iter().fold(0usize, |acc, &b| {
+        acc.checked_mul(256)
+            .and_then(|acc| acc.checked_add(b as usize))
+            .unwrap_or(usize::MAX)
+    }))
+}
+
+/// validates `bytecode` for `target`, a fork or a fork on a chain.
+///
+/// execution is followed from offset 0 along fall-through edges and static jumps (a push directly
+/// followed by jump or jumpi), tracking the stack height. jumpdests whose offset is pushed anywhere
+/// else are treated as entry points reached with an unknown stack height (internal return
+/// addresses), so the code behind dynamic jumps is checked too, apart from underflows.
+///
+/// # arguments
+/// * `bytecode` - code to validate.
+/// * `target` - fork, and chain, whose opcodes are allowed.
+///
+/// # returns
+/// the issues found, in order of offset, at most one per instruction.
+pub fn validate(bytecode: &[u8], target: impl Into<Target>) -> Vec<Issue> {
+    let target = target.into();
+    let instructions = disassemble(bytecode);
+    let index: HashMap<usize, usize> = instructions
+        .iter()
+        .enumerate()
+        .map(|(idx, ins)| (ins.offset, idx))
+        .collect();
+    let jumpdests: HashSet<usize> = instructions
+        .iter()
+        .filter(|ins| ins.opcode == op::JUMPDEST)
+        .map(|ins| ins.offset)
+        .collect();
+
+    // entries as (instruction index, stack height), with `None` for an unknown height
+    let mut pending: Vec<(usize, Option<usize>)> = Vec::new();
+    if !instructions.is_empty() {
+        pending.push((0, Some(0)));
+    }
+    for ins in &instructions {
+        let value = (1..=8).contains(&ins.immediate.len()).then(|| {
+            ins.immediate
+                .iter()
+                .fold(0usize, |acc, &b| (acc << 8) | b as usize)
+        });
+        if let Some(&idx) = value
+            .filter(|v| jumpdests.contains(v))
+            .and_then(|v| index.get(&v))
+        {
+            pending.push((idx, None));
+        }
+    }
+
+    let mut issues: BTreeMap<usize, Problem> = BTreeMap::new();
+    // lowest stack height each jumpdest was entered with, and jumpdests entered with unknown height
+    let mut lowest: HashMap<usize, usize> = HashMap::new();
+    let mut unknown: HashSet<usize> = HashSet::new();
+    while let Some((start, mut height)) = pending.pop() {
+        let seen = match height {
+            Some(h) => lowest.get(&start).is_some_and(|&low| low <= h),
+            None => !unknown.insert(start),
+        };
+        if seen {
+            continue;
+        }
+        if let Some(h) = height {
+            lowest.insert(start, h);
+        }
+
+        for idx in start..instructions.len() {
+            let ins = &instructions[idx];
+            if idx > start && ins.opcode == op::JUMPDEST {
+                pending.push((idx, height));
+                break;
+            }
+            let mut report = |problem| {
+                issues.entry(ins.offset).or_insert(problem);
+            };
+            if ins.immediate.len() < push_size(ins.opcode) {
+                report(Problem::TruncatedPush);
+                break;
+            }
+            if ins.opcode != op::INVALID && !target.supports(ins.opcode) {
+                report(Problem::UndefinedOpcode(ins.opcode));
+                break;
+            }
+            let (popped, pushed) = stack_io(ins.opcode).unwrap_or((0, 0));
+            if let Some(h) = height {
+                if h < popped {
+                    report(Problem::StackUnderflow {
+                        needed: popped,
+                        available: h,
+                    });
+                    break;
+                }
+                height = Some(h - popped + pushed);
+            }
+
+            if matches!(ins.opcode, op::JUMP | op::JUMPI) {
+                if let Some(target) = static_target(&instructions, idx) {
+                    match index.get(&target) {
+                        Some(&target) if jumpdests.contains(&instructions[target].offset) => {
+                            pending.push((target, height));
+                        }
+                        _ => {
+                            report(Problem::BadJump(target));
+                            break;
+                        }
+                    }
+                }
+            }
+            if is_terminator(ins.opcode) {
+                break;
+            }
+        }
+    }
+
+    issues
+        .into_iter()
+        .map(|(offset, problem)| Issue { offset, problem })
+        .collect()
+}
+
+/// issues of `output` beyond those already present in `input`, compared by kind: if the input has
+/// two stack underflows and the output three, the output's underflows are returned.
+pub fn introduced(input: &[Issue], output: &[Issue]) -> Vec<Issue> {
+    let count = |issues: &[Issue], kind: &str| {
+        issues
+            .iter()
+            .filter(|issue| issue.problem.kind() == kind)
+            .count()
+    };
+    output
+        .iter()
+        .filter(|issue| {
+            let kind = issue.problem.kind();
+            count(output, kind) > count(input, kind)
+        })
+        .copied()
+        .collect()
+}
@@ -0,0 +1,125 @@
+/// diversified variants for the ebo obfuscator.
+/// a contract deployed to several customers is easier to trace, and harder to attack with a single
+/// analysis, when every customer gets code of its own. `ebo obfuscate --variants N` obfuscates the
+/// input N times, each with a seed derived from the base seed (see `seed::variant_seed`), writes
+/// them as `obfuscated-1.bin` to `obfuscated-N.bin`, and writes an index next to them,
+/// `obfuscated.variants.json`, of the seed and hashes of every variant, so a variant found on chain
+/// can be told apart from the others by its codehash.
+///
+/// hashes are of the runtime code, like those of a manifest (see `manifest`).
+use crate::error::EboError;
+use crate::manifest::{Code, VERSION};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// the file of variant `index` of an output written to `output`, e.g. `Token-2.bin` for `Token.bin`.
+pub fn path(output: &Path, index: usize) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match output.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, index),
+    };
+    output.with_file_name(name)
+}
+
+/// the index of the variants of an output written to `output`, e.g. `Token.variants.json`.
+pub fn index_path(output: &Path) -> PathBuf {
+    output.with_extension("variants.json")
+}
+
+/// one variant of a build.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Variant {
+    /// number of the variant, from 1.
+    pub index: usize,
+    /// seed the variant was made with.
+    pub seed: u64,
+    /// file the variant was written to.
+    pub file: PathBuf,
+    /// the obfuscated runtime code.
+    pub code: Code,
+}
+
+/// every variant of a build.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Variants {
+    /// version of ebo that made the variants.
+    pub version: String,
+    /// the runtime code that was obfuscated.
+    pub input: Code,
+    /// the variants, in order.
+    pub variants: Vec<Variant>,
+}
+
+impl Variants {
+    /// an index of no variants of `input` yet.
+    pub fn new(input: &[u8]) -> Self {
+        Variants {
+            version: VERSION.to_string(),
+            input: Code::new(input),
+            variants: Vec::new(),
+        }
+    }
+
+    /// records variant `index`, made with `seed` into `output` and written to `file`.
+    pub fn push(&mut self, index: usize, seed: u64, file: PathBuf, output: &[u8]) {
+        self.variants.push(Variant {
+            index,
+            seed,
+            file,
+            code: Code::new(output),
+        });
+    }
+
+    /// the variant whose runtime code is `bytecode`.
+    ///
+    /// # example
+    /// ```
+    /// use ebo::seed::variant_seed;
+    /// use ebo::variants::Variants;
+    /// use ebo::Config;
+    ///
+    /// let config = Config::default();
+    /// // PUSH1 0, CALLDATALOAD, PUSH1 7, JUMPI, STOP, JUMPDEST, PUSH1 1, PUSH1 0, SSTORE, STOP
+    /// let bytecode = hex::decode("600035600757005b600160005500").unwrap();
+    /// let mut variants = Variants::new(&bytecode);
+    /// for index in 1..=2 {
+    ///     let seed = variant_seed(42, index);
+    ///     let output = config.obfuscate(&bytecode, seed).unwrap().bytecode;
+    ///     variants.push(index, seed, format!("obfuscated-{}.bin", index).into(), &output);
+    /// }
+    /// let second = config.obfuscate(&bytecode, variant_seed(42, 2)).unwrap().bytecode;
+    /// assert!(variants.duplicates().is_empty());
+    /// assert_eq!(variants.identify(&second).unwrap().seed, variant_seed(42, 2));
+    /// assert!(variants.identify(&bytecode).is_none());
+    /// ```
+    pub fn identify(&self, bytecode: &[u8]) -> Option<&Variant> {
+        let code = Code::new(bytecode);
+        self.variants.iter().find(|variant| variant.code == code)
+    }
+
+    /// pairs of indices of variants whose code came out the same, which happens when the input
+    /// leaves the passes too little to choose from.
+    pub fn duplicates(&self) -> Vec<(usize, usize)> {
+        let mut duplicates = Vec::new();
+        for (idx, first) in self.variants.iter().enumerate() {
+            for second in &self.variants[idx + 1..] {
+                if first.code == second.code {
+                    duplicates.push((first.index, second.index));
+                }
+            }
+        }
+        duplicates
+    }
+
+    /// parses an index written by `to_json`.
+    pub fn parse(json: &str) -> Result<Self, EboError> {
+        serde_json::from_str(json).map_err(|err| EboError::parse("variants index", err))
+    }
+
+    /// the index as pretty-printed json.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a variants index always serializes")
+    }
+}